@@ -1,5 +1,6 @@
 // bridge/src/index.ts
-import { spawn } from 'child_process';
+import { ChildProcess, spawn } from 'child_process';
+import { randomBytes } from 'crypto';
 import express from 'express';
 import pino from 'pino';
 import stripAnsi from 'strip-ansi';
@@ -14,14 +15,56 @@ const logger = pino({
   }),
 });
 
+type ContentBlock =
+  | { type: 'text'; text: string }
+  | { type: 'tool_use'; id: string; name: string; input: unknown }
+  | { type: 'tool_result'; tool_use_id: string; content: string };
+
 interface ChatMessage {
   role: string;
-  content: string;
+  content: string | ContentBlock[];
+}
+
+interface Tool {
+  name: string;
+  description?: string;
+  input_schema: unknown;
 }
 
+type ToolChoice =
+  | { type: 'auto' }
+  | { type: 'none' }
+  | { type: 'any' }
+  | { type: 'tool'; name: string };
+
 interface AnthropicRequest {
   messages: ChatMessage[];
   model: string;
+  system?: string;
+  temperature?: number;
+  top_p?: number;
+  top_k?: number;
+  max_tokens?: number;
+  stop?: string | string[];
+  stream?: boolean;
+  tools?: Tool[];
+  tool_choice?: ToolChoice;
+}
+
+interface ParsedRequest {
+  prompt: string;
+  system?: string;
+  model?: string;
+  stop: string[];
+  maxTokens?: number;
+  // Output must be buffered and scanned for tool calls
+  usesTools: boolean;
+}
+
+interface ToolUse {
+  id: string;
+  name: string;
+  input: unknown;
 }
 
 interface OpenAIChunk {
@@ -31,7 +74,7 @@ interface OpenAIChunk {
   model: string;
   choices: Array<{
     index: number;
-    delta: { content?: string };
+    delta: { content?: string; tool_use?: ToolUse };
     finish_reason?: string | null;
   }>;
 }
@@ -43,6 +86,11 @@ const MAX_ROLE_LENGTH = 20;
 const MAX_CONTENT_LENGTH = 50000;
 const ALLOWED_ROLES = new Set(['user', 'assistant', 'system', 'human', 'ai']);
 const RESPONSE_DETECTION_THRESHOLD = 50;
+const MAX_STOP_SEQUENCES = 4;
+const MAX_TOOLS = 128;
+const TOOL_USE_PATTERN = /<tool_use>([\s\S]*?)<\/tool_use>/g;
+// The CLI reports no token counts; approximate max_tokens by characters
+const CHARS_PER_TOKEN = 4;
 
 // Validation helpers
 function isValidRole(role: unknown): role is string {
@@ -100,45 +148,155 @@ function detectAssistantResponse(
   return { hasStarted: false, content: '' };
 }
 
-const app = express();
-const PORT = parseInt(process.env.PORT || '4001', 10);
-const HOST = process.env.HOST || '0.0.0.0';
+class RequestError extends Error {}
 
-// Security: Limit body size
-app.use(express.json({ limit: '10mb' }));
+// The CLI has no native tool support, so calls and results are rendered into
+// the transcript in the same tagged form the model is asked to answer in.
+function renderContent(content: string | ContentBlock[]): string {
+  if (typeof content === 'string') {
+    if (!isValidContent(content)) {
+      throw new Error(`must be non-empty string under ${MAX_CONTENT_LENGTH} chars`);
+    }
+    return content;
+  }
+  if (!Array.isArray(content) || content.length === 0) {
+    throw new Error('must be a string or a non-empty array of content blocks');
+  }
+  const rendered = content
+    .map((block) => {
+      switch (block?.type) {
+        case 'text':
+          return block.text;
+        case 'tool_use':
+          return `<tool_use>${JSON.stringify({ id: block.id, name: block.name, input: block.input })}</tool_use>`;
+        case 'tool_result':
+          return `<tool_result id="${block.tool_use_id}">${block.content}</tool_result>`;
+        default:
+          throw new Error('has an unknown content block type');
+      }
+    })
+    .join('\n');
+  if (!isValidContent(rendered)) {
+    throw new Error(`must render to under ${MAX_CONTENT_LENGTH} chars`);
+  }
+  return rendered;
+}
 
-// Security: Disable x-powered-by header
-app.disable('x-powered-by');
+// Describes the tools and the expected call format in the system prompt.
+function renderTools(tools: Tool[], choice?: ToolChoice): string {
+  const definitions = tools
+    .map((tool) =>
+      JSON.stringify({
+        name: tool.name,
+        description: tool.description,
+        input_schema: tool.input_schema,
+      })
+    )
+    .join('\n');
+  const requirement =
+    choice?.type === 'any'
+      ? 'You must call at least one tool.'
+      : choice?.type === 'tool'
+        ? `You must call the ${choice.name} tool.`
+        : 'Call a tool only when it helps answer the user.';
+  return [
+    'You can call these tools:',
+    definitions,
+    'To call a tool, reply with one block per call in exactly this form and stop:',
+    '<tool_use>{"name": "tool_name", "input": {...}}</tool_use>',
+    'Tool results arrive as <tool_result id="...">...</tool_result> blocks.',
+    requirement,
+  ].join('\n');
+}
 
-app.get('/health', (_req, res) => {
-  res.json({ status: 'ok', service: 'anthropic-bridge' });
-});
+// Splits CLI output into plain text and the tool calls it contains.
+function extractToolUses(text: string): { content: string; toolUses: ToolUse[] } {
+  const toolUses: ToolUse[] = [];
+  const content = text.replace(TOOL_USE_PATTERN, (block, json: string) => {
+    try {
+      const call = JSON.parse(json);
+      if (typeof call?.name !== 'string' || call.name.length === 0) return block;
+      toolUses.push({
+        id: `toolu_${randomBytes(12).toString('hex')}`,
+        name: call.name,
+        input: call.input ?? {},
+      });
+      return '';
+    } catch {
+      // Not a well-formed call; leave it in the text
+      return block;
+    }
+  });
+  return { content: content.trim(), toolUses };
+}
 
-app.post('/anthropic/chat', async (req, res) => {
-  const { messages, model }: AnthropicRequest = req.body;
+// Validates the request body and builds the CLI prompt. Throws RequestError on bad input.
+function parseRequest(body: AnthropicRequest): ParsedRequest {
+  const { messages, model, system, temperature, top_p, top_k, max_tokens, stop, tools, tool_choice } =
+    body;
 
-  // Validate messages array
   if (!messages || !Array.isArray(messages)) {
-    logger.warn('Invalid request: messages is not an array');
-    return res.status(400).json({ error: 'Invalid messages format' });
+    throw new RequestError('Invalid messages format');
   }
-
   if (messages.length === 0) {
-    logger.warn('Invalid request: empty messages array');
-    return res.status(400).json({ error: 'Messages array cannot be empty' });
+    throw new RequestError('Messages array cannot be empty');
   }
-
   if (messages.length > MAX_MESSAGE_COUNT) {
-    logger.warn({ count: messages.length }, 'Invalid request: too many messages');
-    return res
-      .status(400)
-      .json({ error: `Too many messages. Maximum ${MAX_MESSAGE_COUNT} allowed` });
+    throw new RequestError(`Too many messages. Maximum ${MAX_MESSAGE_COUNT} allowed`);
   }
-
-  // Validate model if provided
   if (model !== undefined && typeof model !== 'string') {
-    logger.warn('Invalid request: model is not a string');
-    return res.status(400).json({ error: 'Model must be a string' });
+    throw new RequestError('Model must be a string');
+  }
+  if (system !== undefined && !isValidContent(system)) {
+    throw new RequestError(
+      `System prompt must be a non-empty string under ${MAX_CONTENT_LENGTH} chars`
+    );
+  }
+  for (const [name, value, max] of [
+    ['temperature', temperature, 2],
+    ['top_p', top_p, 1],
+  ] as const) {
+    if (value !== undefined && (typeof value !== 'number' || value < 0 || value > max)) {
+      throw new RequestError(`${name} must be a number between 0 and ${max}`);
+    }
+  }
+  if (
+    top_k !== undefined &&
+    (typeof top_k !== 'number' || !Number.isInteger(top_k) || top_k < 1)
+  ) {
+    throw new RequestError('top_k must be a positive integer');
+  }
+  if (
+    max_tokens !== undefined &&
+    (typeof max_tokens !== 'number' || !Number.isInteger(max_tokens) || max_tokens < 1)
+  ) {
+    throw new RequestError('max_tokens must be a positive integer');
+  }
+  const stopList = stop === undefined ? [] : Array.isArray(stop) ? stop : [stop];
+  if (
+    stopList.length > MAX_STOP_SEQUENCES ||
+    stopList.some((s) => typeof s !== 'string' || s.length === 0)
+  ) {
+    throw new RequestError(`stop must be up to ${MAX_STOP_SEQUENCES} non-empty strings`);
+  }
+
+  if (
+    tools !== undefined &&
+    (!Array.isArray(tools) ||
+      tools.length > MAX_TOOLS ||
+      tools.some((t) => typeof t?.name !== 'string' || t.name.length === 0))
+  ) {
+    throw new RequestError(`tools must be up to ${MAX_TOOLS} tools, each with a name`);
+  }
+  const requiredTool = tool_choice?.type === 'tool' ? tool_choice.name : undefined;
+  if (requiredTool !== undefined && !tools?.some((tool) => tool.name === requiredTool)) {
+    throw new RequestError(`tool_choice names unknown tool ${requiredTool}`);
+  }
+  const usesTools = (tools?.length ?? 0) > 0 && tool_choice?.type !== 'none';
+
+  // The claude CLI exposes no sampling flags, so these are accepted but not applied
+  if (temperature !== undefined || top_p !== undefined || top_k !== undefined) {
+    logger.debug({ temperature, top_p, top_k }, 'Sampling parameters are not supported by the CLI');
   }
 
   let prompt: string;
@@ -151,12 +309,14 @@ app.post('/anthropic/chat', async (req, res) => {
               `Invalid role at message ${idx}: must be one of ${Array.from(ALLOWED_ROLES).join(', ')}`
             );
           }
-          if (!isValidContent(msg.content)) {
-            throw new Error(
-              `Invalid content at message ${idx}: must be non-empty string under ${MAX_CONTENT_LENGTH} chars`
-            );
+          let content: string;
+          try {
+            content = renderContent(msg.content);
+          } catch (error) {
+            const reason = error instanceof Error ? error.message : String(error);
+            throw new Error(`Invalid content at message ${idx}: ${reason}`);
           }
-          const sanitizedContent = sanitizePrompt(msg.content);
+          const sanitizedContent = sanitizePrompt(content);
           return `${msg.role}: ${sanitizedContent}`;
         })
         .join('\n\n') + '\n\nAssistant:';
@@ -164,49 +324,192 @@ app.post('/anthropic/chat', async (req, res) => {
     prompt = sanitizePrompt(rawPrompt);
   } catch (error) {
     const errorMessage = error instanceof Error ? error.message : String(error);
-    logger.warn({ err: error }, 'Input validation failed');
-    return res.status(400).json({ error: `Invalid input: ${errorMessage}` });
+    throw new RequestError(`Invalid input: ${errorMessage}`);
   }
 
-  res.setHeader('Content-Type', 'text/event-stream');
-  res.setHeader('Cache-Control', 'no-cache, no-store, must-revalidate');
-  res.setHeader('Connection', 'keep-alive');
-  res.setHeader('X-Content-Type-Options', 'nosniff');
+  const systemPrompt = [system, usesTools ? renderTools(tools ?? [], tool_choice) : undefined]
+    .filter((part): part is string => part !== undefined)
+    .join('\n\n');
+
+  return {
+    prompt,
+    system: systemPrompt ? sanitizePrompt(systemPrompt) : undefined,
+    model,
+    stop: stopList,
+    maxTokens: max_tokens,
+    usesTools,
+  };
+}
 
-  const claude = spawn('claude', ['-p', prompt], {
+function spawnClaude(request: ParsedRequest): ChildProcess {
+  const args = ['-p', request.prompt];
+  if (request.system) {
+    args.push('--system-prompt', request.system);
+  }
+  return spawn('claude', args, {
     env: { ...process.env, CI: 'true' },
     stdio: ['ignore', 'pipe', 'pipe'],
     timeout: 300000, // 5 minute timeout
   });
+}
+
+// Applies stop sequences and the max_tokens budget to CLI output as it arrives.
+// Text that could be the start of a stop sequence is held back until it is
+// disambiguated, so a stop sequence split across chunks is never emitted.
+class OutputLimiter {
+  private pending = '';
+  private emittedChars = 0;
+  finishReason: 'stop' | 'length' | null = null;
+
+  constructor(
+    private readonly stop: string[],
+    private readonly maxChars?: number
+  ) {}
+
+  get done(): boolean {
+    return this.finishReason !== null;
+  }
+
+  push(text: string): string {
+    if (this.done) return '';
+    this.pending += text;
+
+    let out: string;
+    const stopAt = this.findStop();
+    if (stopAt >= 0) {
+      out = this.pending.slice(0, stopAt);
+      this.pending = '';
+      this.finishReason = 'stop';
+    } else {
+      const holdBack = Math.max(0, ...this.stop.map((s) => s.length - 1));
+      out = this.pending.slice(0, Math.max(0, this.pending.length - holdBack));
+      this.pending = this.pending.slice(out.length);
+    }
+    return this.applyBudget(out);
+  }
+
+  finish(): string {
+    const out = this.done ? '' : this.applyBudget(this.pending);
+    this.pending = '';
+    if (!this.done) this.finishReason = 'stop';
+    return out;
+  }
+
+  private findStop(): number {
+    const hits = this.stop.map((s) => this.pending.indexOf(s)).filter((i) => i >= 0);
+    return hits.length > 0 ? Math.min(...hits) : -1;
+  }
+
+  private applyBudget(text: string): string {
+    if (this.maxChars === undefined) return text;
+    const remaining = this.maxChars - this.emittedChars;
+    if (text.length >= remaining) {
+      this.finishReason = 'length';
+      this.emittedChars = this.maxChars;
+      return text.slice(0, remaining);
+    }
+    this.emittedChars += text.length;
+    return text;
+  }
+}
 
-  let buffer = '';
+function createLimiter(request: ParsedRequest): OutputLimiter {
+  return new OutputLimiter(
+    request.stop,
+    request.maxTokens === undefined ? undefined : request.maxTokens * CHARS_PER_TOKEN
+  );
+}
+
+// Feeds CLI stdout through response detection and the limiter, calling `emit`
+// with each piece of assistant text. Kills the process once a limit is reached.
+// Returns a flush callback for process exit: output too short to pass response
+// detection is still the answer and is emitted then.
+function pipeAssistantOutput(
+  claude: ChildProcess,
+  limiter: OutputLimiter,
+  emit: (content: string) => void
+): () => void {
   let hasStarted = false;
   let preStartBuffer = '';
 
-  claude.stdout.on('data', (chunk: Buffer) => {
-    const text = chunk.toString();
-    const cleanText = stripAnsi(text);
+  claude.stdout?.on('data', (chunk: Buffer) => {
+    const cleanText = stripAnsi(chunk.toString());
 
+    let text = cleanText;
     if (!hasStarted) {
       const detection = detectAssistantResponse(cleanText, preStartBuffer);
-      if (detection.hasStarted) {
-        hasStarted = true;
-        if (detection.content) {
-          buffer += detection.content;
-          sendChunk(detection.content);
-        }
-        preStartBuffer = '';
+      if (!detection.hasStarted) {
+        preStartBuffer += cleanText;
         return;
       }
-      preStartBuffer += cleanText;
-      return;
+      hasStarted = true;
+      preStartBuffer = '';
+      text = detection.content;
     }
 
-    buffer += cleanText;
-    sendChunk(cleanText);
+    const content = limiter.push(text);
+    if (content) emit(content);
+    if (limiter.done) claude.kill();
+  });
+
+  return () => {
+    if (!hasStarted) emit(limiter.push(preStartBuffer.trim()));
+    emit(limiter.finish());
+  };
+}
+
+const app = express();
+const PORT = parseInt(process.env.PORT || '4001', 10);
+const HOST = process.env.HOST || '0.0.0.0';
+
+// Security: Limit body size
+app.use(express.json({ limit: '10mb' }));
+
+// Security: Disable x-powered-by header
+app.disable('x-powered-by');
+
+// Reported on /health so the proxy can refuse to route to an incompatible bridge.
+// Bump BRIDGE_API_VERSION on breaking changes to the request or response format.
+const BRIDGE_VERSION = '1.1.0';
+const BRIDGE_API_VERSION = 1;
+const BRIDGE_CAPABILITIES = ['chat', 'complete', 'tools'];
+
+app.get('/health', (_req, res) => {
+  res.json({
+    status: 'ok',
+    service: 'anthropic-bridge',
+    version: BRIDGE_VERSION,
+    api_version: BRIDGE_API_VERSION,
+    capabilities: BRIDGE_CAPABILITIES,
   });
+});
+
+app.post('/anthropic/chat', async (req, res) => {
+  let request: ParsedRequest;
+  try {
+    request = parseRequest(req.body as AnthropicRequest);
+  } catch (error) {
+    const errorMessage = error instanceof Error ? error.message : String(error);
+    logger.warn({ err: error }, 'Input validation failed');
+    return res.status(400).json({ error: errorMessage });
+  }
+  const model = request.model;
 
-  claude.stderr.on('data', (data: Buffer) => {
+  res.setHeader('Content-Type', 'text/event-stream');
+  res.setHeader('Cache-Control', 'no-cache, no-store, must-revalidate');
+  res.setHeader('Connection', 'keep-alive');
+  res.setHeader('X-Content-Type-Options', 'nosniff');
+
+  const claude = spawnClaude(request);
+  const limiter = createLimiter(request);
+  // Tool calls can only be recognised in the complete output, so it is held back
+  let buffered = '';
+  const flush = pipeAssistantOutput(claude, limiter, (content) => {
+    if (request.usesTools) buffered += content;
+    else sendChunk(content);
+  });
+
+  claude.stderr?.on('data', (data: Buffer) => {
     const errorText = data.toString();
     logger.error({ stderr: errorText }, 'CLI stderr output');
 
@@ -228,7 +531,8 @@ app.post('/anthropic/chat', async (req, res) => {
   });
 
   claude.on('close', (code: number | null) => {
-    if (code !== 0 || code === null) {
+    // A process we killed after hitting a stop sequence or max_tokens finished normally
+    if (!limiter.done && (code !== 0 || code === null)) {
       logger.warn({ exitCode: code }, 'Claude process exited with non-zero code');
       const errorChunk: OpenAIChunk = {
         id: 'chatcmpl-bridge-error',
@@ -239,12 +543,20 @@ app.post('/anthropic/chat', async (req, res) => {
       };
       res.write(`data: ${JSON.stringify(errorChunk)}\n\n`);
     } else {
+      flush();
+      let finishReason: string | null = limiter.finishReason;
+      if (request.usesTools) {
+        const { content, toolUses } = extractToolUses(buffered);
+        sendChunk(content);
+        toolUses.forEach(sendToolUse);
+        if (toolUses.length > 0) finishReason = 'tool_use';
+      }
       const finishChunk: OpenAIChunk = {
         id: 'chatcmpl-bridge-done',
         object: 'chat.completion.chunk',
         created: Math.floor(Date.now() / 1000),
         model: model || 'claude-3-5-sonnet',
-        choices: [{ index: 0, delta: {}, finish_reason: 'stop' }],
+        choices: [{ index: 0, delta: {}, finish_reason: finishReason }],
       };
       res.write(`data: ${JSON.stringify(finishChunk)}\n\n`);
     }
@@ -288,6 +600,74 @@ app.post('/anthropic/chat', async (req, res) => {
 
     res.write(`data: ${JSON.stringify(chunk)}\n\n`);
   }
+
+  function sendToolUse(toolUse: ToolUse) {
+    const chunk: OpenAIChunk = {
+      id: 'chatcmpl-bridge-stream',
+      object: 'chat.completion.chunk',
+      created: Math.floor(Date.now() / 1000),
+      model: model || 'claude-3-5-sonnet',
+      choices: [{ index: 0, delta: { tool_use: toolUse }, finish_reason: null }],
+    };
+
+    res.write(`data: ${JSON.stringify(chunk)}\n\n`);
+  }
+});
+
+// Non-streaming variant: runs the CLI to completion and returns
+// `{ content, finish_reason, tool_use? }` so the proxy need not reassemble SSE.
+app.post('/anthropic/complete', async (req, res) => {
+  let request: ParsedRequest;
+  try {
+    request = parseRequest(req.body as AnthropicRequest);
+  } catch (error) {
+    const errorMessage = error instanceof Error ? error.message : String(error);
+    logger.warn({ err: error }, 'Input validation failed');
+    return res.status(400).json({ error: errorMessage });
+  }
+
+  const claude = spawnClaude(request);
+  const limiter = createLimiter(request);
+  let content = '';
+  let stderr = '';
+  let settled = false;
+  const flush = pipeAssistantOutput(claude, limiter, (text) => {
+    content += text;
+  });
+
+  claude.stderr?.on('data', (data: Buffer) => {
+    stderr += data.toString();
+  });
+
+  claude.on('close', (code: number | null) => {
+    if (settled) return;
+    settled = true;
+    if (!limiter.done && (code !== 0 || code === null)) {
+      logger.warn({ exitCode: code, stderr }, 'Claude process exited with non-zero code');
+      return res.status(502).json({
+        error: `Claude CLI exited with code ${code}${stderr ? `: ${stderr.trim()}` : ''}`,
+      });
+    }
+    flush();
+    if (request.usesTools) {
+      const extracted = extractToolUses(content);
+      if (extracted.toolUses.length > 0) {
+        return res.json({
+          content: extracted.content,
+          finish_reason: 'tool_use',
+          tool_use: extracted.toolUses,
+        });
+      }
+    }
+    res.json({ content: content.trim(), finish_reason: limiter.finishReason });
+  });
+
+  claude.on('error', (error: Error) => {
+    logger.error({ err: error }, 'Failed to spawn claude process');
+    if (settled) return;
+    settled = true;
+    res.status(502).json({ error: `Spawn Error: ${error.message}` });
+  });
 });
 
 app.listen(PORT, HOST, () => {
@@ -48,6 +48,8 @@ export interface ChatCompletionResponse {
         prompt_tokens: number;
         completion_tokens: number;
         total_tokens: number;
+        prompt_tokens_details?: { cached_tokens: number };
+        completion_tokens_details?: { reasoning_tokens: number };
     };
 }
 
@@ -0,0 +1,25 @@
+// Arbitrary ChatGPT backend event streams, split at an arbitrary point,
+// through the SSE parser and the chunk transformer.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vertex_bridge::openai::sse_parser::SSEParser;
+use vertex_bridge::openai::transformer::transform_sse_to_openai_chunk;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, stream)) = data.split_first() else {
+        return;
+    };
+    let split = usize::from(split).min(stream.len());
+
+    let mut parser = SSEParser::new();
+    let mut events = parser.parse_chunk(&stream[..split]);
+    events.extend(parser.parse_chunk(&stream[split..]));
+
+    let mut whole = SSEParser::new();
+    assert_eq!(whole.parse_chunk(stream).len(), events.len());
+
+    for event in &events {
+        let _ = transform_sse_to_openai_chunk(event, "gpt-4", "req-1");
+    }
+});
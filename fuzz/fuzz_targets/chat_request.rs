@@ -0,0 +1,27 @@
+// Arbitrary client requests through both request transformers.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vertex_bridge::config::VertexGenerationConfig;
+use vertex_bridge::models::openai::{ChatCompletionRequest, Role};
+use vertex_bridge::openai::transformer::transform_to_backend;
+use vertex_bridge::services::transformer::transform_request;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(req) = serde_json::from_slice::<ChatCompletionRequest>(data) else {
+        return;
+    };
+
+    let backend_req = transform_to_backend(&req.model, &req.messages, req.temperature, req.max_tokens)
+        .expect("backend transform never fails");
+    assert_eq!(backend_req.messages.len(), req.messages.len());
+
+    let turns = req
+        .messages
+        .iter()
+        .filter(|m| !matches!(m.role, Role::System))
+        .count();
+    let vertex_req = transform_request(req, &VertexGenerationConfig::default())
+        .expect("Vertex transform never fails");
+    assert_eq!(vertex_req.contents.len(), turns);
+});
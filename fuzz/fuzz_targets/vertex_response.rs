@@ -0,0 +1,23 @@
+// Arbitrary Vertex response bodies through the response transformers.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vertex_bridge::models::vertex::GenerateContentResponse;
+use vertex_bridge::services::transformer::{transform_response, transform_stream_chunk};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(vertex_res) = serde_json::from_slice::<GenerateContentResponse>(data) else {
+        return;
+    };
+    let text: Option<String> = vertex_res
+        .candidates
+        .as_ref()
+        .and_then(|c| c.first())
+        .and_then(|c| c.content.as_ref())
+        .map(|c| c.parts.iter().filter_map(|p| p.text.as_deref()).collect());
+
+    if let Ok(response) = transform_response(&vertex_res, "model".to_string(), "id".to_string()) {
+        assert_eq!(Some(&response.choices[0].message.content), text.as_ref());
+    }
+    let _ = transform_stream_chunk(&vertex_res, "model".to_string(), "id".to_string());
+});
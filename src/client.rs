@@ -0,0 +1,730 @@
+// Typed async client for the proxy's HTTP API.
+//
+// Requests and responses use the same structs as the server, so Rust services
+// calling the proxy stay in step with it instead of hand-rolling reqwest calls
+// and JSON. Enabled with the `client` cargo feature.
+
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::{Method, RequestBuilder, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::Pin;
+
+use crate::handlers::admin::{
+    AliasTarget, BudgetStatus, CredentialRefresh, MaintenanceStatus, ModelRefresh,
+    ProviderValidationReport,
+};
+use crate::handlers::tokens::{TokenCount, TokenCountRequest};
+use crate::handlers::usage::UsageQuery;
+use crate::middleware::admin_signature;
+use crate::middleware::rate_limit::RateLimitStats;
+use crate::models::openai::{ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse};
+use crate::openai::errors::OpenAIError;
+use crate::services::budgets::BudgetLimits;
+use crate::services::maintenance_mode::MaintenanceRequest;
+use crate::services::model_registry::ModelInfo;
+use crate::services::prompt_templates::PromptTemplate;
+use crate::services::usage::UsageSummary;
+
+/// Chunks of a streamed chat completion, ending after the proxy's `[DONE]`.
+pub type ChunkStream = Pin<Box<dyn Stream<Item = Result<ChatCompletionChunk, ClientError>> + Send>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("Invalid proxy URL: {0}")]
+    InvalidUrl(String),
+    #[error("Request to proxy failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// An error response from the proxy. `status` is 200 for errors reported
+    /// in-band after a stream has started.
+    #[error("Proxy returned {status}: {message}")]
+    Api {
+        status: u16,
+        message: String,
+        code: Option<String>,
+    },
+    #[error("Unexpected response from proxy: {0}")]
+    Decode(String),
+}
+
+#[derive(Deserialize)]
+struct ListResponse<T> {
+    data: Vec<T>,
+}
+
+/// Client for one proxy instance.
+///
+/// Cheap to clone; clones share the underlying connection pool.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+    api_key: Option<String>,
+    sign_admin: bool,
+}
+
+impl Client {
+    /// # Errors
+    ///
+    /// Returns [`ClientError::InvalidUrl`] if `base_url` is not an absolute
+    /// http(s) URL.
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        let base_url = Url::parse(base_url).map_err(|e| ClientError::InvalidUrl(e.to_string()))?;
+        if base_url.cannot_be_a_base() || !matches!(base_url.scheme(), "http" | "https") {
+            return Err(ClientError::InvalidUrl(format!(
+                "'{base_url}' is not an http(s) URL"
+            )));
+        }
+        Ok(Self {
+            http: reqwest::Client::new(),
+            base_url,
+            api_key: None,
+            sign_admin: false,
+        })
+    }
+
+    /// Sends `key` as a bearer token with every request.
+    #[must_use]
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Signs mutating admin requests with the API key, for proxies that set
+    /// `admin_signature.required`.
+    #[must_use]
+    pub fn with_signed_admin_requests(mut self) -> Self {
+        self.sign_admin = true;
+        self
+    }
+
+    /// Uses a preconfigured HTTP client (timeouts, proxies, TLS settings).
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// `POST /v1/chat/completions` without streaming.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the proxy rejects it.
+    pub async fn chat(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse, ClientError> {
+        let mut request = request.clone();
+        request.stream = false;
+        let builder = self
+            .request(Method::POST, &["v1", "chat", "completions"])?
+            .json(&request);
+        self.send_json(builder).await
+    }
+
+    /// `POST /v1/chat/completions` with streaming.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the proxy rejects it
+    /// before streaming starts; later failures are yielded by the stream.
+    pub async fn chat_stream(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChunkStream, ClientError> {
+        let mut request = request.clone();
+        request.stream = true;
+        let builder = self
+            .request(Method::POST, &["v1", "chat", "completions"])?
+            .json(&request);
+        let response = check_status(builder.send().await?).await?;
+        Ok(sse_chunks(response.bytes_stream()))
+    }
+
+    /// `GET /v1/models`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, ClientError> {
+        let builder = self.request(Method::GET, &["v1", "models"])?;
+        let list: ListResponse<ModelInfo> = self.send_json(builder).await?;
+        Ok(list.data)
+    }
+
+    /// `GET /v1/models/:id`; aliases resolve to their target model.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Api`] with status 404 for unknown models.
+    pub async fn get_model(&self, id: &str) -> Result<ModelInfo, ClientError> {
+        let builder = self.request(Method::GET, &["v1", "models", id])?;
+        self.send_json(builder).await
+    }
+
+    /// `POST /v1/tokens/count`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or is malformed.
+    pub async fn count_tokens(
+        &self,
+        request: &TokenCountRequest,
+    ) -> Result<TokenCount, ClientError> {
+        let builder = self
+            .request(Method::POST, &["v1", "tokens", "count"])?
+            .json(request);
+        self.send_json(builder).await
+    }
+
+    /// `GET /usage`. Non-admin keys only see their own usage.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the range is invalid.
+    pub async fn usage(&self, query: &UsageQuery) -> Result<Vec<UsageSummary>, ClientError> {
+        let builder = self.request(Method::GET, &["usage"])?.query(query);
+        let list: ListResponse<UsageSummary> = self.send_json(builder).await?;
+        Ok(list.data)
+    }
+
+    /// `GET /admin/budgets`: limits and current spend per key.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the key is not an admin.
+    pub async fn list_budgets(&self) -> Result<BTreeMap<String, BudgetStatus>, ClientError> {
+        let builder = self.request(Method::GET, &["admin", "budgets"])?;
+        self.send_json(builder).await
+    }
+
+    /// `PUT /admin/budgets/:key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the limits are invalid.
+    pub async fn set_budget(
+        &self,
+        key: &str,
+        limits: BudgetLimits,
+    ) -> Result<BudgetStatus, ClientError> {
+        let builder = self
+            .request(Method::PUT, &["admin", "budgets", key])?
+            .json(&limits);
+        self.send_json(builder).await
+    }
+
+    /// `GET /admin/models/aliases`: alias to target model.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the key is not an admin.
+    pub async fn list_model_aliases(&self) -> Result<BTreeMap<String, String>, ClientError> {
+        let builder = self.request(Method::GET, &["admin", "models", "aliases"])?;
+        self.send_json(builder).await
+    }
+
+    /// `PUT /admin/models/aliases/:alias`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the target is unknown.
+    pub async fn set_model_alias(&self, alias: &str, target: &str) -> Result<(), ClientError> {
+        let builder = self
+            .request(Method::PUT, &["admin", "models", "aliases", alias])?
+            .json(&AliasTarget {
+                target: target.to_string(),
+            });
+        self.send_empty(builder).await
+    }
+
+    /// `DELETE /admin/models/aliases/:alias`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Api`] with status 404 if there is no such alias.
+    pub async fn delete_model_alias(&self, alias: &str) -> Result<(), ClientError> {
+        let builder = self.request(Method::DELETE, &["admin", "models", "aliases", alias])?;
+        self.send_empty(builder).await
+    }
+
+    /// `GET /admin/prompts`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the key is not an admin.
+    pub async fn list_prompt_templates(
+        &self,
+    ) -> Result<BTreeMap<String, PromptTemplate>, ClientError> {
+        let builder = self.request(Method::GET, &["admin", "prompts"])?;
+        self.send_json(builder).await
+    }
+
+    /// `PUT /admin/prompts/:name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the template is invalid.
+    pub async fn set_prompt_template(
+        &self,
+        name: &str,
+        template: &PromptTemplate,
+    ) -> Result<(), ClientError> {
+        let builder = self
+            .request(Method::PUT, &["admin", "prompts", name])?
+            .json(template);
+        self.send_empty(builder).await
+    }
+
+    /// `DELETE /admin/prompts/:name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Api`] with status 404 if there is no such template.
+    pub async fn delete_prompt_template(&self, name: &str) -> Result<(), ClientError> {
+        let builder = self.request(Method::DELETE, &["admin", "prompts", name])?;
+        self.send_empty(builder).await
+    }
+
+    /// `GET /admin/rate-limit?top=N`: limiter settings and the busiest keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the key is not an admin.
+    pub async fn rate_limit_stats(&self, top: usize) -> Result<RateLimitStats, ClientError> {
+        let builder = self
+            .request(Method::GET, &["admin", "rate-limit"])?
+            .query(&[("top", top)]);
+        self.send_json(builder).await
+    }
+
+    /// `POST /admin/providers/validate`: sends a one-token completion to each
+    /// provider, or only to `provider`, and reports the outcome.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or `provider` is unknown.
+    pub async fn validate_providers(
+        &self,
+        provider: Option<&str>,
+    ) -> Result<ProviderValidationReport, ClientError> {
+        let mut builder = self.request(Method::POST, &["admin", "providers", "validate"])?;
+        if let Some(provider) = provider {
+            builder = builder.query(&[("provider", provider)]);
+        }
+        self.send_json(builder).await
+    }
+
+    /// `GET /admin/maintenance`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the key is not an admin.
+    pub async fn maintenance_status(&self) -> Result<MaintenanceStatus, ClientError> {
+        let builder = self.request(Method::GET, &["admin", "maintenance"])?;
+        self.send_json(builder).await
+    }
+
+    /// `PUT /admin/maintenance`: puts the `/v1/*` endpoints into maintenance.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or `until` is in the past.
+    pub async fn start_maintenance(
+        &self,
+        settings: &MaintenanceRequest,
+    ) -> Result<MaintenanceStatus, ClientError> {
+        let builder = self
+            .request(Method::PUT, &["admin", "maintenance"])?
+            .json(settings);
+        self.send_json(builder).await
+    }
+
+    /// `DELETE /admin/maintenance`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Api`] with status 404 if maintenance is not active.
+    pub async fn end_maintenance(&self) -> Result<(), ClientError> {
+        let builder = self.request(Method::DELETE, &["admin", "maintenance"])?;
+        self.send_empty(builder).await
+    }
+
+    /// `POST /admin/credentials/refresh`: drops cached provider credentials
+    /// so the next request acquires new ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the key is not an admin.
+    pub async fn refresh_credentials(&self) -> Result<CredentialRefresh, ClientError> {
+        let builder = self.request(Method::POST, &["admin", "credentials", "refresh"])?;
+        self.send_json(builder).await
+    }
+
+    /// `POST /admin/models/refresh`: fetches the upstream model lists behind
+    /// `/v1/models` now.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Api`] with status 400 if model discovery is
+    /// disabled, or 502 if the upstream did not answer.
+    pub async fn refresh_models(&self) -> Result<ModelRefresh, ClientError> {
+        let builder = self.request(Method::POST, &["admin", "models", "refresh"])?;
+        self.send_json(builder).await
+    }
+
+    fn request(&self, method: Method, segments: &[&str]) -> Result<RequestBuilder, ClientError> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|()| ClientError::InvalidUrl(self.base_url.to_string()))?
+            .pop_if_empty()
+            .extend(segments);
+        let builder = self.http.request(method, url);
+        Ok(match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        })
+    }
+
+    /// Sends `builder`, signing it first if it is a mutating admin request
+    /// and signing is enabled.
+    async fn send(&self, builder: RequestBuilder) -> Result<reqwest::Response, ClientError> {
+        let mut request = builder.build()?;
+        let is_admin = request
+            .url()
+            .path()
+            .strip_prefix(self.base_url.path().trim_end_matches('/'))
+            .is_some_and(|path| path.starts_with("/admin/"));
+        let mutating = !matches!(*request.method(), Method::GET | Method::HEAD);
+        if let (true, true, true, Some(key)) = (self.sign_admin, is_admin, mutating, &self.api_key)
+        {
+            let timestamp = chrono::Utc::now().timestamp();
+            let nonce = uuid::Uuid::new_v4().to_string();
+            let path_and_query = match request.url().query() {
+                Some(query) => format!("{}?{query}", request.url().path()),
+                None => request.url().path().to_string(),
+            };
+            let body = request
+                .body()
+                .and_then(reqwest::Body::as_bytes)
+                .unwrap_or_default();
+            let canonical = admin_signature::canonical_request(
+                request.method(),
+                &path_and_query,
+                timestamp,
+                &nonce,
+                body,
+            );
+            let signature = admin_signature::sign(key, &canonical);
+            let headers = request.headers_mut();
+            for (name, value) in [
+                (admin_signature::TIMESTAMP_HEADER, timestamp.to_string()),
+                (admin_signature::NONCE_HEADER, nonce),
+                (admin_signature::SIGNATURE_HEADER, signature),
+            ] {
+                // Digits, a UUID and hex are always valid header values
+                if let Ok(value) = value.parse() {
+                    headers.insert(name, value);
+                }
+            }
+        }
+        Ok(self.http.execute(request).await?)
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        builder: RequestBuilder,
+    ) -> Result<T, ClientError> {
+        let response = check_status(self.send(builder).await?).await?;
+        let bytes = response.bytes().await?;
+        serde_json::from_slice(&bytes).map_err(|e| ClientError::Decode(e.to_string()))
+    }
+
+    async fn send_empty(&self, builder: RequestBuilder) -> Result<(), ClientError> {
+        check_status(self.send(builder).await?).await.map(|_| ())
+    }
+}
+
+/// Turns a non-2xx response into [`ClientError::Api`], using the OpenAI error
+/// envelope when the body has one.
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(api_error(status, &body))
+}
+
+fn api_error(status: StatusCode, body: &str) -> ClientError {
+    match serde_json::from_str::<OpenAIError>(body) {
+        Ok(envelope) => ClientError::Api {
+            status: status.as_u16(),
+            message: envelope.error.message,
+            code: envelope.error.code,
+        },
+        Err(_) => ClientError::Api {
+            status: status.as_u16(),
+            message: if body.is_empty() {
+                status.to_string()
+            } else {
+                body.to_string()
+            },
+            code: None,
+        },
+    }
+}
+
+/// What one server-sent event carried.
+enum SseEvent {
+    Chunk(Result<ChatCompletionChunk, ClientError>),
+    Done,
+    Ignored,
+}
+
+fn parse_event(event: &[u8]) -> SseEvent {
+    let text = String::from_utf8_lossy(event);
+    let data: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect();
+    if data.is_empty() {
+        return SseEvent::Ignored;
+    }
+    let data = data.join("\n");
+    if data == "[DONE]" {
+        return SseEvent::Done;
+    }
+    // The proxy reports failures after the stream started as an error event
+    if data.contains("\"error\"") {
+        if let Ok(envelope) = serde_json::from_str::<OpenAIError>(&data) {
+            return SseEvent::Chunk(Err(ClientError::Api {
+                status: StatusCode::OK.as_u16(),
+                message: envelope.error.message,
+                code: envelope.error.code,
+            }));
+        }
+    }
+    SseEvent::Chunk(serde_json::from_str(&data).map_err(|e| ClientError::Decode(e.to_string())))
+}
+
+struct SseState<S> {
+    bytes: Pin<Box<S>>,
+    buffer: Vec<u8>,
+    pending: VecDeque<Result<ChatCompletionChunk, ClientError>>,
+    done: bool,
+}
+
+/// Splits an SSE body into chat completion chunks.
+fn sse_chunks<S>(bytes: S) -> ChunkStream
+where
+    S: Stream<Item = reqwest::Result<axum::body::Bytes>> + Send + 'static,
+{
+    let state = SseState {
+        bytes: Box::pin(bytes),
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        done: false,
+    };
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+            if state.done {
+                return None;
+            }
+            match state.bytes.next().await {
+                Some(Ok(chunk)) => {
+                    state.buffer.extend_from_slice(&chunk);
+                    while let Some(end) = state.buffer.windows(2).position(|w| w == b"\n\n") {
+                        let event: Vec<u8> = state.buffer.drain(..end + 2).collect();
+                        match parse_event(&event) {
+                            SseEvent::Chunk(item) => state.pending.push_back(item),
+                            SseEvent::Done => {
+                                state.done = true;
+                                break;
+                            }
+                            SseEvent::Ignored => {}
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    state.pending.push_back(Err(e.into()));
+                    state.done = true;
+                }
+                None => state.done = true,
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn request() -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .expect("request should parse")
+    }
+
+    #[tokio::test]
+    async fn test_chat_round_trip() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("authorization", "Bearer sk-test"))
+            .and(body_partial_json(serde_json::json!({"stream": false})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gemini-2.5-flash",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "hello"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+            })))
+            .mount(&server)
+            .await;
+
+        let client = Client::new(&server.uri())
+            .expect("valid url")
+            .with_api_key("sk-test");
+        let response = client.chat(&request()).await.expect("chat should succeed");
+        assert_eq!(response.choices[0].message.content, "hello");
+        assert_eq!(response.usage.map(|u| u.total_tokens), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_mutating_admin_requests_are_signed() {
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/admin/models/aliases/stable"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "target": "gemini-2.5-flash"
+            })))
+            .mount(&server)
+            .await;
+
+        let client = Client::new(&server.uri())
+            .expect("valid url")
+            .with_api_key("sk-admin")
+            .with_signed_admin_requests();
+        client
+            .set_model_alias("stable", "gemini-2.5-flash")
+            .await
+            .expect("alias should be set");
+
+        let sent = server.received_requests().await.expect("requests recorded");
+        let headers = &sent[0].headers;
+        let header = |name: &str| {
+            headers[name]
+                .to_str()
+                .expect("header should be text")
+                .to_string()
+        };
+        let canonical = admin_signature::canonical_request(
+            &Method::PUT,
+            "/admin/models/aliases/stable",
+            header(admin_signature::TIMESTAMP_HEADER)
+                .parse()
+                .expect("timestamp should be a number"),
+            &header(admin_signature::NONCE_HEADER),
+            &sent[0].body,
+        );
+        assert_eq!(
+            header(admin_signature::SIGNATURE_HEADER),
+            admin_signature::sign("sk-admin", &canonical)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_envelope_mapped() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/models/no%20such"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "error": {
+                    "message": "The model 'no such' does not exist",
+                    "type": "invalid_request_error",
+                    "param": "model",
+                    "code": "model_not_found"
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = Client::new(&format!("{}/", server.uri())).expect("valid url");
+        match client.get_model("no such").await {
+            Err(ClientError::Api { status, code, .. }) => {
+                assert_eq!(status, 404);
+                assert_eq!(code.as_deref(), Some("model_not_found"));
+            }
+            other => panic!("expected an API error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_chunks_and_in_band_error() {
+        let chunk = |content: &str| {
+            serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "gemini-2.5-flash",
+                "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": null}]
+            })
+        };
+        let body = format!(
+            "data: {}\n\n: keep-alive\n\ndata: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Hel"),
+            chunk("lo"),
+            serde_json::json!({"error": {"message": "boom", "type": "server_error", "param": null, "code": "stream_error"}}),
+        );
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"stream": true})))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "text/event-stream")
+                    .set_body_string(body),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Client::new(&server.uri()).expect("valid url");
+        let items: Vec<_> = client
+            .chat_stream(&request())
+            .await
+            .expect("stream should start")
+            .collect()
+            .await;
+        assert_eq!(items.len(), 3);
+        let text: String = items
+            .iter()
+            .filter_map(|item| item.as_ref().ok())
+            .filter_map(|chunk| chunk.choices[0].delta.content.clone())
+            .collect();
+        assert_eq!(text, "Hello");
+        assert!(matches!(
+            &items[2],
+            Err(ClientError::Api { code: Some(code), .. }) if code == "stream_error"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_base_url_rejected() {
+        assert!(Client::new("localhost:4000").is_err());
+        assert!(Client::new("ftp://example.com").is_err());
+    }
+}
@@ -4,10 +4,36 @@ use std::env;
 use std::fs;
 use validator::Validate;
 
+use crate::middleware::body_limit::BodyLimits;
+use crate::services::{
+    mirror::MirrorSinkKind,
+    post_processor::PostProcessFailurePolicy,
+    providers::gemini_cli,
+    quota::QuotaOverflow,
+    routing::{
+        RoutingStrategy, DEFAULT_LATENCY_WINDOW_SECS, DEFAULT_SESSION_HEADER,
+        DEFAULT_SESSION_TTL_SECS,
+    },
+    slo,
+    upstream_clients::{self, TlsVersion},
+    upstream_headers, watermark,
+};
+
 const DEFAULT_MAX_REQUEST_SIZE: usize = 10 * 1024 * 1024;
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 30;
 const DEFAULT_ACCESS_TOKEN_TTL_SECS: u64 = 3600;
 const DEFAULT_ARKOSE_TOKEN_TTL_SECS: u64 = 120;
 const DEFAULT_CACHE_TTL_SECS: u64 = 3600;
+const DEFAULT_MAINTENANCE_INTERVAL_SECS: u64 = 60;
+const DEFAULT_MAINTENANCE_RETRY_AFTER_SECS: u64 = 300;
+const DEFAULT_MAINTENANCE_MESSAGE: &str =
+    "The service is undergoing scheduled maintenance. Please retry later.";
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+const DEFAULT_MAX_MESSAGES: usize = 1_000;
+const DEFAULT_MAX_MESSAGE_CHARS: usize = 1_000_000;
+const DEFAULT_MAX_TOTAL_CHARS: usize = 4_000_000;
+const DEFAULT_MAX_PROMPTS: usize = 32;
+const DEFAULT_MAX_CONCURRENT_PROMPTS: usize = 4;
 
 #[derive(Debug, Deserialize, Clone, Validate)]
 pub struct ServerConfig {
@@ -17,12 +43,31 @@ pub struct ServerConfig {
     pub port: u16,
     #[serde(default = "default_max_request_size")]
     pub max_request_size: usize,
+    /// Wall-clock limit for a chat completion, streams included; 0 disables it.
+    #[serde(default)]
+    pub request_timeout_secs: u64,
+    /// How long shutdown waits for in-flight requests before cancelling them.
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+    /// Per-route body size budgets as `path=bytes`; a trailing `*` on the path
+    /// matches by prefix. Routes without one use `max_request_size`.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub body_limits: Vec<String>,
+    /// Bind with `SO_REUSEPORT` so a replacement process can listen on the
+    /// same port while this one drains. Ignored under systemd socket
+    /// activation, which hands over the listener instead.
+    #[serde(default)]
+    pub reuse_port: bool,
 }
 
 fn default_max_request_size() -> usize {
     DEFAULT_MAX_REQUEST_SIZE
 }
 
+fn default_shutdown_grace_secs() -> u64 {
+    DEFAULT_SHUTDOWN_GRACE_SECS
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct AuthConfig {
     pub require_auth: bool,
@@ -39,6 +84,27 @@ pub struct VertexConfig {
     pub api_key_base_url: Option<String>,
     #[validate(length(min = 1))]
     pub oauth_base_url: Option<String>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub generation: VertexGenerationConfig,
+}
+
+/// Generation settings sent to Vertex when the client leaves them out.
+///
+/// Unset values are omitted from the Vertex request, so Gemini applies its
+/// own per-model defaults rather than OpenAI's.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct VertexGenerationConfig {
+    #[validate(range(min = 0.0, max = 2.0))]
+    pub temperature: Option<f32>,
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub top_p: Option<f32>,
+    #[validate(range(min = 1))]
+    pub top_k: Option<u32>,
+    #[validate(range(min = 1))]
+    pub max_output_tokens: Option<u32>,
+    #[validate(range(min = 1, max = 8))]
+    pub candidate_count: Option<u32>,
 }
 
 #[derive(Debug, Deserialize, Clone, Validate)]
@@ -55,16 +121,70 @@ fn default_log_format() -> String {
 #[derive(Debug, Deserialize, Clone, Validate)]
 pub struct OpenAIConfig {
     pub harvester_url: String,
+    /// ChatGPT backend conversation endpoint; defaults to chatgpt.com.
+    #[serde(default)]
+    #[validate(length(min = 1))]
+    pub backend_url: Option<String>,
     #[validate(range(min = 1))]
     pub access_token_ttl_secs: u64,
     #[validate(range(min = 1))]
     pub arkose_token_ttl_secs: u64,
+    #[serde(default)]
+    #[validate(nested)]
+    pub waf_cooldown: WafCooldownConfig,
+}
+
+/// Cooldown for harvester sessions the ChatGPT backend's WAF keeps blocking.
+///
+/// After `threshold` blocks within `window_secs`, requests using the session
+/// are refused for `cooldown_secs` while the harvester is asked for a fresh
+/// one, and an alert is sent. A `threshold` of 0 disables the cooldown.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct WafCooldownConfig {
+    #[serde(default = "default_waf_cooldown_threshold")]
+    pub threshold: usize,
+    #[serde(default = "default_waf_cooldown_window_secs")]
+    #[validate(range(min = 1))]
+    pub window_secs: u64,
+    #[serde(default = "default_waf_cooldown_secs")]
+    #[validate(range(min = 1))]
+    pub cooldown_secs: u64,
+}
+
+impl Default for WafCooldownConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_waf_cooldown_threshold(),
+            window_secs: default_waf_cooldown_window_secs(),
+            cooldown_secs: default_waf_cooldown_secs(),
+        }
+    }
+}
+
+fn default_waf_cooldown_threshold() -> usize {
+    3
+}
+
+fn default_waf_cooldown_window_secs() -> u64 {
+    300
+}
+
+fn default_waf_cooldown_secs() -> u64 {
+    600
 }
 
 #[derive(Debug, Deserialize, Clone, Validate)]
 pub struct AnthropicConfig {
     #[validate(length(min = 1))]
     pub bridge_url: String,
+    /// Seconds between bridge health and version checks; the first runs at startup.
+    #[serde(default = "default_anthropic_health_check_interval")]
+    #[validate(range(min = 1))]
+    pub health_check_interval_secs: u64,
+}
+
+fn default_anthropic_health_check_interval() -> u64 {
+    30
 }
 
 /// Configuration for the Gemini CLI provider.
@@ -82,6 +202,43 @@ pub struct GeminiCliConfig {
     #[serde(default = "default_gemini_cli_max_concurrency")]
     #[validate(range(min = 1))]
     pub max_concurrency: usize,
+    /// Directory the CLI runs in; file-reading tools resolve paths against it.
+    pub working_dir: Option<String>,
+    /// Pass `--sandbox` so tool execution happens inside the CLI's sandbox.
+    #[serde(default)]
+    pub sandbox: bool,
+    /// Tools the CLI may run without confirmation (`--allowed-tools`).
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub allowed_tools: Vec<String>,
+    /// Extensions to load (`--extensions`); empty loads the CLI's defaults.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub extensions: Vec<String>,
+    /// Additional arguments appended verbatim to every invocation.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub extra_args: Vec<String>,
+    /// Models the CLI serves; a trailing `*` matches by prefix. Other `gemini-*`
+    /// models fall through to Vertex.
+    #[serde(
+        default = "default_gemini_cli_models",
+        deserialize_with = "deserialize_string_list"
+    )]
+    pub models: Vec<String>,
+    /// Flag the CLI uses to load a saved chat checkpoint. When set, prior turns
+    /// are written to a checkpoint file instead of a `User:`/`Assistant:` transcript.
+    pub checkpoint_flag: Option<String>,
+    /// Seconds between CLI auth probes reported on `/health`; the first runs at startup.
+    #[serde(default = "default_gemini_cli_health_check_interval")]
+    #[validate(range(min = 1))]
+    pub health_check_interval_secs: u64,
+    /// Requests the CLI's account may make per minute; unset is unlimited.
+    #[validate(range(min = 1))]
+    pub requests_per_minute: Option<u32>,
+    /// Requests the CLI's account may make per day, reset at midnight Pacific.
+    #[validate(range(min = 1))]
+    pub requests_per_day: Option<u32>,
+    /// Where requests go once a quota is used up.
+    #[serde(default)]
+    pub quota_overflow: QuotaOverflow,
 }
 
 impl Default for GeminiCliConfig {
@@ -91,6 +248,17 @@ impl Default for GeminiCliConfig {
             cli_path: None,
             timeout_secs: default_gemini_cli_timeout(),
             max_concurrency: default_gemini_cli_max_concurrency(),
+            working_dir: None,
+            sandbox: false,
+            allowed_tools: Vec::new(),
+            extensions: Vec::new(),
+            extra_args: Vec::new(),
+            models: default_gemini_cli_models(),
+            checkpoint_flag: None,
+            health_check_interval_secs: default_gemini_cli_health_check_interval(),
+            requests_per_minute: None,
+            requests_per_day: None,
+            quota_overflow: QuotaOverflow::default(),
         }
     }
 }
@@ -107,6 +275,194 @@ fn default_gemini_cli_max_concurrency() -> usize {
     4
 }
 
+fn default_gemini_cli_health_check_interval() -> u64 {
+    300
+}
+
+fn default_gemini_cli_models() -> Vec<String> {
+    [
+        "gemini-2.5-pro",
+        "gemini-2.5-flash",
+        "gemini-2.5-flash-lite",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+/// Configuration for the native Ollama provider.
+///
+/// Talks to a local Ollama server's `/api/chat`. Requests for `ollama/<name>`
+/// always go to Ollama with the prefix removed; `models` lists further names
+/// served under their own names.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct OllamaConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_ollama_base_url")]
+    #[validate(length(min = 1))]
+    pub base_url: String,
+    /// Models served without the `ollama/` prefix; a trailing `*` matches by prefix.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub models: Vec<String>,
+    /// Seconds a request may take, including loading the model.
+    #[serde(default = "default_ollama_timeout")]
+    #[validate(range(min = 1))]
+    pub timeout_secs: u64,
+    /// Seconds between server health checks; the first runs at startup.
+    #[serde(default = "default_ollama_health_check_interval")]
+    #[validate(range(min = 1))]
+    pub health_check_interval_secs: u64,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            base_url: default_ollama_base_url(),
+            models: Vec::new(),
+            timeout_secs: default_ollama_timeout(),
+            health_check_interval_secs: default_ollama_health_check_interval(),
+        }
+    }
+}
+
+fn default_ollama_base_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+fn default_ollama_timeout() -> u64 {
+    300
+}
+
+fn default_ollama_health_check_interval() -> u64 {
+    60
+}
+
+/// Configuration for DeepSeek's OpenAI-compatible API.
+///
+/// The provider is registered, and serves `deepseek-*` models, only when
+/// `api_key` is set.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct DeepSeekConfig {
+    pub api_key: Option<String>,
+    #[serde(default = "default_deepseek_base_url")]
+    #[validate(length(min = 1))]
+    pub base_url: String,
+    /// Seconds a request may take, including the whole of a stream.
+    #[serde(default = "default_deepseek_timeout")]
+    #[validate(range(min = 1))]
+    pub timeout_secs: u64,
+}
+
+impl Default for DeepSeekConfig {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            base_url: default_deepseek_base_url(),
+            timeout_secs: default_deepseek_timeout(),
+        }
+    }
+}
+
+fn default_deepseek_base_url() -> String {
+    "https://api.deepseek.com".to_string()
+}
+
+fn default_deepseek_timeout() -> u64 {
+    600
+}
+
+/// Configuration for a generic OpenAI-compatible upstream such as
+/// OpenRouter, Together or Groq.
+///
+/// The provider is registered only when `base_url` is set and `models` is
+/// not empty, and serves exactly the listed models.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct OpenAICompatibleConfig {
+    /// API root the `/chat/completions` path is appended to, e.g.
+    /// `https://openrouter.ai/api/v1`.
+    #[validate(length(min = 1))]
+    pub base_url: Option<String>,
+    /// Sent as a bearer token; local servers often need none.
+    pub api_key: Option<String>,
+    /// Models forwarded to the upstream under their own names; a trailing `*`
+    /// matches by prefix. They take precedence over the prefix routing of
+    /// the built-in providers.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub models: Vec<String>,
+    /// Seconds a request may take, including the whole of a stream.
+    #[serde(default = "default_openai_compatible_timeout")]
+    #[validate(range(min = 1))]
+    pub timeout_secs: u64,
+}
+
+impl Default for OpenAICompatibleConfig {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            api_key: None,
+            models: Vec::new(),
+            timeout_secs: default_openai_compatible_timeout(),
+        }
+    }
+}
+
+fn default_openai_compatible_timeout() -> u64 {
+    600
+}
+
+/// Configuration for the AWS Bedrock provider.
+///
+/// Serves `anthropic.claude-*` and `amazon.titan-*` model IDs through the
+/// Converse API when enabled. Without `access_key_id` and
+/// `secret_access_key`, credentials come from the `AWS_*` environment
+/// variables or the EC2 instance profile.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct BedrockConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// AWS region; falls back to `AWS_REGION`, `AWS_DEFAULT_REGION`, then `us-east-1`.
+    pub region: Option<String>,
+    /// Overrides the `https://bedrock-runtime.<region>.amazonaws.com` endpoint,
+    /// e.g. for a VPC endpoint.
+    pub endpoint_url: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    /// Instance metadata service queried for instance profile credentials.
+    #[serde(default = "default_bedrock_metadata_url")]
+    #[validate(length(min = 1))]
+    pub metadata_url: String,
+    /// Seconds a request may take, including the whole of a stream.
+    #[serde(default = "default_bedrock_timeout")]
+    #[validate(range(min = 1))]
+    pub timeout_secs: u64,
+}
+
+impl Default for BedrockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: None,
+            endpoint_url: None,
+            access_key_id: None,
+            secret_access_key: None,
+            session_token: None,
+            metadata_url: default_bedrock_metadata_url(),
+            timeout_secs: default_bedrock_timeout(),
+        }
+    }
+}
+
+fn default_bedrock_metadata_url() -> String {
+    "http://169.254.169.254".to_string()
+}
+
+fn default_bedrock_timeout() -> u64 {
+    600
+}
+
 #[derive(Debug, Deserialize, Clone, Validate)]
 pub struct RateLimitConfig {
     #[validate(range(min = 1))]
@@ -123,6 +479,12 @@ pub struct CircuitBreakerConfig {
     pub timeout_secs: u64,
     #[validate(range(min = 1))]
     pub success_threshold: u32,
+    /// Save breaker state across restarts, to `state_file` if set and
+    /// otherwise to the configured storage backend.
+    #[serde(default)]
+    pub persist: bool,
+    #[serde(default)]
+    pub state_file: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone, Validate)]
@@ -132,6 +494,14 @@ pub struct CacheConfig {
     #[validate(range(min = 1))]
     #[serde(default = "default_cache_ttl")]
     pub default_ttl_secs: u64,
+    /// Share one upstream call among identical concurrent non-streaming requests.
+    #[serde(default = "default_coalesce_requests")]
+    pub coalesce_requests: bool,
+    /// Key cached and coalesced responses on the caller's API key as well as
+    /// the request. Disabling it shares hits across keys, which can leak
+    /// prompt-derived content between tenants.
+    #[serde(default = "default_vary_on_key")]
+    pub vary_on_key: bool,
 }
 
 fn default_cache_enabled() -> bool {
@@ -142,28 +512,974 @@ fn default_cache_ttl() -> u64 {
     DEFAULT_CACHE_TTL_SECS
 }
 
+fn default_coalesce_requests() -> bool {
+    true
+}
+
+fn default_vary_on_key() -> bool {
+    true
+}
+
+/// Configuration for the background maintenance task.
+///
+/// The task sweeps expired cache entries and idle rate-limiter buckets so that
+/// no client request pays for a full-store scan.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct MaintenanceConfig {
+    #[serde(default = "default_maintenance_interval")]
+    #[validate(range(min = 1))]
+    pub interval_secs: u64,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_maintenance_interval(),
+        }
+    }
+}
+
+fn default_maintenance_interval() -> u64 {
+    DEFAULT_MAINTENANCE_INTERVAL_SECS
+}
+
+/// Defaults for maintenance windows opened through `PUT /admin/maintenance`.
+///
+/// While a window is open, `/v1/*` requests get a 503 with `message` and a
+/// `Retry-After` of `retry_after_secs` (or the time left in the window).
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct MaintenanceModeConfig {
+    #[serde(default = "default_maintenance_message")]
+    #[validate(length(min = 1))]
+    pub message: String,
+    #[serde(default = "default_maintenance_retry_after")]
+    #[validate(range(min = 1))]
+    pub retry_after_secs: u64,
+}
+
+impl Default for MaintenanceModeConfig {
+    fn default() -> Self {
+        Self {
+            message: default_maintenance_message(),
+            retry_after_secs: default_maintenance_retry_after(),
+        }
+    }
+}
+
+fn default_maintenance_message() -> String {
+    DEFAULT_MAINTENANCE_MESSAGE.to_string()
+}
+
+fn default_maintenance_retry_after() -> u64 {
+    DEFAULT_MAINTENANCE_RETRY_AFTER_SECS
+}
+
+/// Startup warm-up: fetches the Vertex access token, harvester tokens and
+/// the Anthropic bridge's health in parallel once the server starts, so the
+/// first request does not pay for them. `/health/ready` reports 503 until it
+/// finishes.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct WarmupConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Seconds each warm-up step may take before it is reported as failed.
+    #[serde(default = "default_warmup_timeout")]
+    #[validate(range(min = 1))]
+    pub timeout_secs: u64,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_secs: default_warmup_timeout(),
+        }
+    }
+}
+
+fn default_warmup_timeout() -> u64 {
+    10
+}
+
+/// Configuration for the model metadata registry.
+///
+/// `overrides_file` points to a JSON array of model definitions that extend or
+/// replace the built-in table.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct ModelsConfig {
+    #[validate(length(min = 1))]
+    pub overrides_file: Option<String>,
+    /// `alias=target` entries added to (or replacing) the built-in aliases.
+    /// `alias=a|b` defines a group of equivalent models, primary first.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub aliases: Vec<String>,
+    /// How a model is picked from an alias group unless `X-Routing-Strategy` overrides it.
+    #[serde(default)]
+    pub routing_strategy: RoutingStrategy,
+    /// How long latency samples count towards the `fastest` strategy.
+    #[validate(range(min = 1))]
+    #[serde(default = "default_latency_window_secs")]
+    pub latency_window_secs: u64,
+    /// Pin each conversation session to the alias group model first chosen for it.
+    #[serde(default)]
+    pub sticky_sessions: bool,
+    /// Request header carrying the session id; the body's `user` field is used without it.
+    #[validate(length(min = 1))]
+    #[serde(default = "default_session_header")]
+    pub session_header: String,
+    /// How long an idle session stays pinned.
+    #[validate(range(min = 1))]
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+    /// JSON array of content-based routing rules, checked in order.
+    #[validate(length(min = 1))]
+    pub rules_file: Option<String>,
+    /// `model=fallback` entries: retry prompts too long for `model` on `fallback`.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub context_fallbacks: Vec<String>,
+    /// `model[=replacement][@YYYY-MM-DD]` entries: serve `model` with a
+    /// deprecation warning, and reject it after the date.
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub deprecations: Vec<String>,
+}
+
+impl Default for ModelsConfig {
+    fn default() -> Self {
+        Self {
+            overrides_file: None,
+            aliases: Vec::new(),
+            routing_strategy: RoutingStrategy::default(),
+            latency_window_secs: default_latency_window_secs(),
+            sticky_sessions: false,
+            session_header: default_session_header(),
+            session_ttl_secs: default_session_ttl_secs(),
+            rules_file: None,
+            context_fallbacks: Vec::new(),
+            deprecations: Vec::new(),
+        }
+    }
+}
+
+fn default_latency_window_secs() -> u64 {
+    DEFAULT_LATENCY_WINDOW_SECS
+}
+
+fn default_session_header() -> String {
+    DEFAULT_SESSION_HEADER.to_string()
+}
+
+fn default_session_ttl_secs() -> u64 {
+    DEFAULT_SESSION_TTL_SECS
+}
+
+/// Configuration for per-client API keys.
+///
+/// `file` points to a JSON array of `{ "name", "key", "max_priority" }` objects
+/// accepted alongside the master key.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct KeysConfig {
+    #[validate(length(min = 1))]
+    pub file: Option<String>,
+}
+
+/// Configuration for optional persistent storage.
+///
+/// When `sqlite_path` is set, usage records, API keys and audit events are
+/// written to that SQLite database so accounting survives restarts. With
+/// `redis_url` (and the `redis` feature) usage records, API keys, circuit
+/// breaker state, and in cluster mode rate limits and cached and idempotent
+/// responses, live in Redis instead; only audit events stay in SQLite.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct StorageConfig {
+    #[validate(length(min = 1))]
+    pub sqlite_path: Option<String>,
+    #[validate(length(min = 1))]
+    pub redis_url: Option<String>,
+}
+
+/// Configuration for running several replicas as one proxy.
+///
+/// With `enabled`, rate limit buckets, cached and idempotent responses and
+/// budget spend live in the SQLite database at `storage.sqlite_path`, or in
+/// Redis at `storage.redis_url`, instead of process memory, so every replica
+/// pointing at the same backend enforces the same limits.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct ClusterConfig {
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Configuration for the upstream concurrency scheduler.
+///
+/// At most `max_in_flight` chat completions run at once; excess requests queue
+/// by `X-Priority`.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct SchedulerConfig {
+    #[serde(default = "default_max_in_flight")]
+    #[validate(range(min = 1))]
+    pub max_in_flight: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: default_max_in_flight(),
+        }
+    }
+}
+
+fn default_max_in_flight() -> usize {
+    DEFAULT_MAX_IN_FLIGHT
+}
+
+/// Per-request input limits enforced before a request is routed to a provider.
+///
+/// These bound pathological inputs beyond the raw `server.max_request_size` body
+/// limit. The estimated token count is additionally checked against the model's
+/// `context_window` from the model registry. A `prompts` request may hold at
+/// most `max_prompts` prompts, of which `max_concurrent_prompts` are sent
+/// upstream at a time.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct LimitsConfig {
+    #[serde(default = "default_max_messages")]
+    #[validate(range(min = 1))]
+    pub max_messages: usize,
+    #[serde(default = "default_max_message_chars")]
+    #[validate(range(min = 1))]
+    pub max_message_chars: usize,
+    #[serde(default = "default_max_total_chars")]
+    #[validate(range(min = 1))]
+    pub max_total_chars: usize,
+    /// Proxy-wide ceiling for `max_tokens`, on top of each model's own
+    /// `max_output_tokens`.
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default = "default_max_prompts")]
+    #[validate(range(min = 1))]
+    pub max_prompts: usize,
+    #[serde(default = "default_max_concurrent_prompts")]
+    #[validate(range(min = 1))]
+    pub max_concurrent_prompts: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_messages: default_max_messages(),
+            max_message_chars: default_max_message_chars(),
+            max_total_chars: default_max_total_chars(),
+            max_output_tokens: None,
+            max_prompts: default_max_prompts(),
+            max_concurrent_prompts: default_max_concurrent_prompts(),
+        }
+    }
+}
+
+fn default_max_messages() -> usize {
+    DEFAULT_MAX_MESSAGES
+}
+
+fn default_max_message_chars() -> usize {
+    DEFAULT_MAX_MESSAGE_CHARS
+}
+
+fn default_max_total_chars() -> usize {
+    DEFAULT_MAX_TOTAL_CHARS
+}
+
+fn default_max_prompts() -> usize {
+    DEFAULT_MAX_PROMPTS
+}
+
+fn default_max_concurrent_prompts() -> usize {
+    DEFAULT_MAX_CONCURRENT_PROMPTS
+}
+
+/// Extra headers attached to upstream requests, per provider.
+///
+/// Each entry is `Name: value`; values may use `{project_id}` (the resolved
+/// Vertex project) and `${VAR}` (an environment variable), e.g.
+/// `x-goog-user-project: {project_id}` for quota-project billing.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct UpstreamHeadersConfig {
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub vertex: Vec<String>,
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub anthropic: Vec<String>,
+    /// Upstream response headers passed on to clients as `x-upstream-<name>`;
+    /// a trailing `*` matches a prefix (e.g. `x-ratelimit-*`).
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub forward: Vec<String>,
+}
+
+/// HTTP client settings for one upstream provider.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct ClientProfileConfig {
+    /// `User-Agent` sent upstream; each provider has its own default.
+    #[validate(length(min = 1))]
+    pub user_agent: Option<String>,
+    /// Speak HTTP/1.1 only, for upstreams or gateways that mishandle HTTP/2.
+    #[serde(default)]
+    pub http1_only: bool,
+    /// Oldest TLS version offered: `tls1.2` or `tls1.3`.
+    pub min_tls_version: Option<TlsVersion>,
+    /// Newest TLS version offered: `tls1.2` or `tls1.3`.
+    pub max_tls_version: Option<TlsVersion>,
+    /// curl-impersonate wrapper (e.g. `curl_chrome116`) to send requests
+    /// through instead; OpenAI only, needs the `impersonate` feature.
+    #[validate(length(min = 1))]
+    pub impersonate_command: Option<String>,
+}
+
+/// Upstream HTTP client settings, per provider.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct UpstreamClientsConfig {
+    #[serde(default)]
+    #[validate(nested)]
+    pub vertex: ClientProfileConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub anthropic: ClientProfileConfig,
+    /// The ChatGPT backend; defaults to a desktop Chrome user agent.
+    #[serde(default)]
+    #[validate(nested)]
+    pub openai: ClientProfileConfig,
+}
+
+/// Proxy-level model allow/deny lists, checked before routing.
+///
+/// Entries are exact model names or prefixes ending in `*`. A model matching
+/// `deny`, or missing from a non-empty `allow`, is rejected with a 403 unless
+/// the caller's key name is in `exempt_keys`.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct ModelPolicyConfig {
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub allow: Vec<String>,
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub deny: Vec<String>,
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub exempt_keys: Vec<String>,
+}
+
+/// Configuration for named prompt templates.
+///
+/// `file` points to a JSON object mapping template names to
+/// `{ "messages": [...] }`; requests select one with `prompt_template`.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct PromptsConfig {
+    #[validate(length(min = 1))]
+    pub file: Option<String>,
+}
+
+/// Configuration for A/B experiments.
+///
+/// `file` points to a JSON array of experiments, each splitting traffic for
+/// one requested `model` across weighted `variants`.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct ExperimentsConfig {
+    #[validate(length(min = 1))]
+    pub file: Option<String>,
+}
+
+/// Configuration for canned replies served while providers are down.
+///
+/// `file` points to a JSON array of `{ "models": [...], "message": "..." }`
+/// entries; the first covering the requested model is used.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct FallbackResponsesConfig {
+    #[validate(length(min = 1))]
+    pub file: Option<String>,
+}
+
+/// Configuration for the response post-processing webhook.
+///
+/// When `webhook_url` is set, completed responses are sent there before being
+/// returned; `on_failure` decides what happens if it errors or exceeds
+/// `timeout_ms`. Streams are buffered and post-processed only with `streams`.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct PostProcessConfig {
+    #[validate(length(min = 1))]
+    pub webhook_url: Option<String>,
+    #[validate(range(min = 1))]
+    #[serde(default = "default_post_process_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default)]
+    pub on_failure: PostProcessFailurePolicy,
+    #[serde(default)]
+    pub streams: bool,
+}
+
+impl Default for PostProcessConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            timeout_ms: default_post_process_timeout_ms(),
+            on_failure: PostProcessFailurePolicy::default(),
+            streams: false,
+        }
+    }
+}
+
+fn default_post_process_timeout_ms() -> u64 {
+    2000
+}
+
+/// Configuration for mirroring request/response pairs to an evaluation sink.
+///
+/// `sink` selects where records go: a JSON Lines `path`, an HTTP `url`, or
+/// `kafka_topic` on `kafka_brokers` (with the `kafka` feature). A
+/// `sample_rate` share of completed requests is mirrored; `redact_patterns`
+/// are regexes whose matches are masked in every string of the pair.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct MirrorConfig {
+    pub sink: Option<MirrorSinkKind>,
+    #[validate(length(min = 1))]
+    pub path: Option<String>,
+    #[validate(length(min = 1))]
+    pub url: Option<String>,
+    #[validate(length(min = 1))]
+    pub kafka_brokers: Option<String>,
+    #[validate(length(min = 1))]
+    pub kafka_topic: Option<String>,
+    #[serde(default = "default_mirror_sample_rate")]
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub sample_rate: f64,
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub redact_patterns: Vec<String>,
+    #[serde(default = "default_mirror_queue_size")]
+    #[validate(range(min = 1))]
+    pub queue_size: usize,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            sink: None,
+            path: None,
+            url: None,
+            kafka_brokers: None,
+            kafka_topic: None,
+            sample_rate: default_mirror_sample_rate(),
+            redact_patterns: Vec::new(),
+            queue_size: default_mirror_queue_size(),
+        }
+    }
+}
+
+fn default_mirror_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_mirror_queue_size() -> usize {
+    1024
+}
+
+/// Configuration for `Idempotency-Key` handling on chat completions.
+///
+/// Successful responses are kept for `ttl_secs` and replayed for retries with
+/// the same key; at most `max_entries` are held, the oldest evicted first. A
+/// `max_entries` of 0 ignores the header.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct IdempotencyConfig {
+    #[serde(default = "default_idempotency_ttl_secs")]
+    #[validate(range(min = 1))]
+    pub ttl_secs: u64,
+    #[serde(default = "default_idempotency_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: default_idempotency_ttl_secs(),
+            max_entries: default_idempotency_max_entries(),
+        }
+    }
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+    86_400
+}
+
+fn default_idempotency_max_entries() -> usize {
+    1000
+}
+
+/// Configuration for watermarking completions with the path that served them.
+///
+/// With `header`, responses carry that header set to
+/// `instance=<instance>; provider=<provider>; model=<model>`; with
+/// `body_field`, the same values are added as an object under that field of
+/// JSON completions and of a stream's final chunk. `instance` defaults to the
+/// `HOSTNAME` environment variable. Only responses to the API keys named in
+/// `keys` are marked, or every key's when the list is empty.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct WatermarkConfig {
+    #[validate(length(min = 1))]
+    pub header: Option<String>,
+    #[validate(length(min = 1))]
+    pub body_field: Option<String>,
+    #[validate(length(min = 1))]
+    pub instance: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub keys: Vec<String>,
+}
+
+/// Configuration for keeping slow non-streaming completions alive.
+///
+/// A non-streaming completion still running after `after_secs` is answered
+/// with `200 OK` straight away and a body of JSON whitespace, one space every
+/// `interval_secs`, followed by the completion once it is ready. Intermediaries
+/// that cut idle connections then see traffic. An `after_secs` of 0 disables
+/// it.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct KeepaliveConfig {
+    #[serde(default)]
+    pub after_secs: u64,
+    #[serde(default = "default_keepalive_interval_secs")]
+    #[validate(range(min = 1))]
+    pub interval_secs: u64,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            after_secs: 0,
+            interval_secs: default_keepalive_interval_secs(),
+        }
+    }
+}
+
+fn default_keepalive_interval_secs() -> u64 {
+    15
+}
+
+/// Configuration for server-sent event streams.
+///
+/// With `strict`, streamed completions only carry `chat.completion.chunk`
+/// frames and `data: [DONE]`, for SDKs that fail on anything else. Idle
+/// heartbeats are then `: ping` comments, and a stream that fails midway
+/// ends with a chunk whose `finish_reason` is `error`.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct SseConfig {
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// The range a provider accepts for one sampling parameter set.
+///
+/// Clients send `temperature` on OpenAI's 0–2 scale and `top_p` on 0–1. A
+/// configured maximum maps the client's value linearly onto `0..=max`, so
+/// `max_temperature = 1` sends half the requested temperature.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct SamplingScaleConfig {
+    #[validate(range(exclusive_min = 0.0))]
+    pub max_temperature: Option<f32>,
+    #[validate(range(exclusive_min = 0.0))]
+    pub max_top_p: Option<f32>,
+}
+
+/// Sampling parameter ranges, per provider; unset ranges pass values through.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct SamplingConfig {
+    #[serde(default)]
+    #[validate(nested)]
+    pub vertex: SamplingScaleConfig,
+    /// Anthropic's `temperature` spans 0–1; set `max_temperature = 1` to
+    /// map OpenAI's range onto it.
+    #[serde(default)]
+    #[validate(nested)]
+    pub anthropic: SamplingScaleConfig,
+}
+
+/// Per-model service level objectives.
+///
+/// Each `objectives` entry is `model=<target>` or `model=<target>@<ms>`: at
+/// least `target` percent of requests for `model` must succeed and, with a
+/// latency, be answered within `ms` milliseconds. Compliance and burn rate
+/// (the share of bad requests divided by the error budget, `1 - target`) are
+/// computed over the trailing `window_secs`, and an alert is raised when the
+/// burn rate exceeds `burn_rate_threshold` with at least `min_requests`
+/// requests in the window.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct SloConfig {
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub objectives: Vec<String>,
+    #[serde(default = "default_slo_window_secs")]
+    #[validate(range(min = 60))]
+    pub window_secs: u64,
+    #[serde(default = "default_slo_burn_rate_threshold")]
+    #[validate(range(exclusive_min = 0.0))]
+    pub burn_rate_threshold: f64,
+    #[serde(default = "default_slo_min_requests")]
+    #[validate(range(min = 1))]
+    pub min_requests: u64,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            objectives: Vec::new(),
+            window_secs: default_slo_window_secs(),
+            burn_rate_threshold: default_slo_burn_rate_threshold(),
+            min_requests: default_slo_min_requests(),
+        }
+    }
+}
+
+fn default_slo_window_secs() -> u64 {
+    3600
+}
+
+// Spends 2% of a 30-day error budget within the default one-hour window
+fn default_slo_burn_rate_threshold() -> f64 {
+    14.4
+}
+
+fn default_slo_min_requests() -> u64 {
+    20
+}
+
+/// Configuration for signed admin requests.
+///
+/// With `required`, mutating `/admin/*` requests must carry
+/// `X-Admin-Timestamp`, `X-Admin-Nonce` and `X-Admin-Signature`, an
+/// HMAC-SHA256 keyed with the caller's admin key. Timestamps more than
+/// `max_skew_secs` away from the proxy's clock, and nonces seen within that
+/// window, are rejected.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct AdminSignatureConfig {
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default = "default_admin_signature_max_skew_secs")]
+    #[validate(range(min = 1))]
+    pub max_skew_secs: u64,
+}
+
+impl Default for AdminSignatureConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            max_skew_secs: default_admin_signature_max_skew_secs(),
+        }
+    }
+}
+
+fn default_admin_signature_max_skew_secs() -> u64 {
+    300
+}
+
+/// Configuration for listing upstream models.
+///
+/// With `enabled`, `/v1/models` also lists the Gemini models Vertex reports,
+/// beyond those in the model registry. The upstream list is fetched at most
+/// once per `ttl_secs` and can be refreshed early through
+/// `POST /admin/models/refresh`.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct ModelDiscoveryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_model_discovery_ttl_secs")]
+    #[validate(range(min = 1))]
+    pub ttl_secs: u64,
+}
+
+impl Default for ModelDiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_model_discovery_ttl_secs(),
+        }
+    }
+}
+
+fn default_model_discovery_ttl_secs() -> u64 {
+    3600
+}
+
+/// Configuration for server-side conversation transcripts.
+///
+/// With `enabled`, chat completions carrying the session header
+/// (`models.session_header`) have their messages and the assistant's reply
+/// appended to a transcript under that session ID, readable and deletable
+/// through `/v1/conversations/{id}`. Transcripts are kept in the configured
+/// storage, or in memory without one, and expire `ttl_secs` after their last
+/// update; 0 keeps them until deleted.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct TranscriptsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_transcripts_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for TranscriptsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_transcripts_ttl_secs(),
+        }
+    }
+}
+
+fn default_transcripts_ttl_secs() -> u64 {
+    30 * 86_400
+}
+
+/// Configuration for error responses.
+///
+/// With `provider_detail`, errors from a provider that answered with a
+/// structured error body carry a sanitized copy of it as
+/// `error.provider_detail`. Off by default, as the body describes upstream
+/// internals.
+#[derive(Debug, Deserialize, Clone, Default, Validate)]
+pub struct ErrorsConfig {
+    #[serde(default)]
+    pub provider_detail: bool,
+}
+
+/// Redaction of prompt-bearing text in logs, error messages and audit events.
+///
+/// Provider error bodies often echo the prompt, and the Gemini CLI is logged
+/// with its prompt; `mode` decides what is kept of such text: `none` (as is),
+/// `full` (only its length), `hashed` (a short SHA-256 prefix, so repeats can
+/// still be correlated) or `truncated` (the first `max_chars` characters).
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct RedactionConfig {
+    #[serde(default)]
+    pub mode: RedactionMode,
+    #[serde(default = "default_redaction_max_chars")]
+    #[validate(range(min = 1))]
+    pub max_chars: usize,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            mode: RedactionMode::default(),
+            max_chars: default_redaction_max_chars(),
+        }
+    }
+}
+
+/// What [`RedactionConfig`] keeps of redacted text.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionMode {
+    #[default]
+    None,
+    Full,
+    Hashed,
+    Truncated,
+}
+
+fn default_redaction_max_chars() -> usize {
+    200
+}
+
+/// Configuration for webhook alerting.
+///
+/// Alerts are POSTed as Slack-compatible JSON (`{"text": ...}`) to every URL in
+/// `webhook_urls`. Identical alerts are suppressed for `cooldown_secs`.
+#[derive(Debug, Deserialize, Clone, Validate)]
+pub struct AlertsConfig {
+    #[serde(default, deserialize_with = "deserialize_string_list")]
+    pub webhook_urls: Vec<String>,
+    #[serde(default = "default_alert_cooldown")]
+    #[validate(range(min = 1))]
+    pub cooldown_secs: u64,
+    #[serde(default = "default_alert_check_interval")]
+    #[validate(range(min = 1))]
+    pub check_interval_secs: u64,
+    #[serde(default = "default_provider_down_minutes")]
+    #[validate(range(min = 1))]
+    pub provider_down_minutes: u64,
+    #[serde(default = "default_error_rate_threshold")]
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub error_rate_threshold: f64,
+    #[serde(default = "default_error_rate_min_requests")]
+    #[validate(range(min = 1))]
+    pub error_rate_min_requests: u64,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            webhook_urls: Vec::new(),
+            cooldown_secs: default_alert_cooldown(),
+            check_interval_secs: default_alert_check_interval(),
+            provider_down_minutes: default_provider_down_minutes(),
+            error_rate_threshold: default_error_rate_threshold(),
+            error_rate_min_requests: default_error_rate_min_requests(),
+        }
+    }
+}
+
+fn default_alert_cooldown() -> u64 {
+    300
+}
+
+fn default_alert_check_interval() -> u64 {
+    30
+}
+
+fn default_provider_down_minutes() -> u64 {
+    5
+}
+
+fn default_error_rate_threshold() -> f64 {
+    0.5
+}
+
+fn default_error_rate_min_requests() -> u64 {
+    20
+}
+
+/// Accepts either a list or a comma-separated string (as supplied via env vars).
+fn deserialize_string_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringList {
+        String(String),
+        List(Vec<String>),
+    }
+
+    let items = match StringList::deserialize(deserializer)? {
+        StringList::String(s) => s.split(',').map(str::to_string).collect(),
+        StringList::List(v) => v,
+    };
+    Ok(items
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect())
+}
+
 #[derive(Debug, Deserialize, Clone, Validate)]
 pub struct AppConfig {
     #[validate(nested)]
-    pub server: ServerConfig,
-    pub auth: AuthConfig,
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    #[validate(nested)]
+    pub vertex: VertexConfig,
+    #[validate(nested)]
+    pub log: LogConfig,
+    #[validate(nested)]
+    pub openai: OpenAIConfig,
+    #[validate(nested)]
+    pub anthropic: AnthropicConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub gemini_cli: GeminiCliConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub ollama: OllamaConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub deepseek: DeepSeekConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub bedrock: BedrockConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub openai_compatible: OpenAICompatibleConfig,
+    #[validate(nested)]
+    pub rate_limit: RateLimitConfig,
+    #[validate(nested)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[validate(nested)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub maintenance_mode: MaintenanceModeConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub warmup: WarmupConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub models: ModelsConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub keys: KeysConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
     #[validate(nested)]
-    pub vertex: VertexConfig,
+    pub alerts: AlertsConfig,
+    #[serde(default)]
     #[validate(nested)]
-    pub log: LogConfig,
+    pub storage: StorageConfig,
+    #[serde(default)]
     #[validate(nested)]
-    pub openai: OpenAIConfig,
+    pub cluster: ClusterConfig,
+    #[serde(default)]
     #[validate(nested)]
-    pub anthropic: AnthropicConfig,
+    pub limits: LimitsConfig,
     #[serde(default)]
     #[validate(nested)]
-    pub gemini_cli: GeminiCliConfig,
+    pub upstream_headers: UpstreamHeadersConfig,
+    #[serde(default)]
     #[validate(nested)]
-    pub rate_limit: RateLimitConfig,
+    pub upstream_clients: UpstreamClientsConfig,
+    #[serde(default)]
     #[validate(nested)]
-    pub circuit_breaker: CircuitBreakerConfig,
+    pub model_policy: ModelPolicyConfig,
+    #[serde(default)]
     #[validate(nested)]
-    pub cache: CacheConfig,
+    pub prompts: PromptsConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub experiments: ExperimentsConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub fallback_responses: FallbackResponsesConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub post_process: PostProcessConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub mirror: MirrorConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub errors: ErrorsConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub redaction: RedactionConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub idempotency: IdempotencyConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub watermark: WatermarkConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub keepalive: KeepaliveConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub sse: SseConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub admin_signature: AdminSignatureConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub model_discovery: ModelDiscoveryConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub transcripts: TranscriptsConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub sampling: SamplingConfig,
+    #[serde(default)]
+    #[validate(nested)]
+    pub slo: SloConfig,
 }
 
 fn parse_bool(value: &str) -> bool {
@@ -264,8 +1580,10 @@ fn build_config_from_sources() -> Result<AppConfig, ConfigError> {
         .set_default("circuit_breaker.success_threshold", 3)?
         .set_default("cache.enabled", false)?
         .set_default("cache.default_ttl_secs", DEFAULT_CACHE_TTL_SECS)?
+        .set_default("cache.coalesce_requests", true)?
         .add_source(
             config::Environment::with_prefix("APP")
+                .prefix_separator("_")
                 .separator("__")
                 .try_parsing(true),
         )
@@ -341,6 +1659,156 @@ fn validate_auth_config(config: &AppConfig) -> Result<(), ConfigError> {
     Ok(())
 }
 
+fn validate_upstream_headers(config: &AppConfig) -> Result<(), ConfigError> {
+    for (provider, specs) in [
+        ("vertex", &config.upstream_headers.vertex),
+        ("anthropic", &config.upstream_headers.anthropic),
+    ] {
+        upstream_headers::validate(specs).map_err(|e| {
+            ConfigError::Message(format!("Invalid upstream_headers.{provider}: {e}"))
+        })?;
+    }
+    upstream_headers::validate_forward(&config.upstream_headers.forward)
+        .map_err(|e| ConfigError::Message(format!("Invalid upstream_headers.forward: {e}")))?;
+    Ok(())
+}
+
+fn validate_upstream_clients(config: &AppConfig) -> Result<(), ConfigError> {
+    for (provider, profile) in [
+        ("vertex", &config.upstream_clients.vertex),
+        ("anthropic", &config.upstream_clients.anthropic),
+        ("openai", &config.upstream_clients.openai),
+    ] {
+        upstream_clients::validate(profile).map_err(|e| {
+            ConfigError::Message(format!("Invalid upstream_clients.{provider}: {e}"))
+        })?;
+        if profile.impersonate_command.is_some() && provider != "openai" {
+            return Err(ConfigError::Message(format!(
+                "upstream_clients.{provider}.impersonate_command is only supported for openai"
+            )));
+        }
+    }
+    if cfg!(not(feature = "impersonate"))
+        && config.upstream_clients.openai.impersonate_command.is_some()
+    {
+        return Err(ConfigError::Message(
+            "upstream_clients.openai.impersonate_command needs the proxy built with the `impersonate` feature"
+                .into(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_body_limits(config: &AppConfig) -> Result<(), ConfigError> {
+    BodyLimits::from_config(&config.server)
+        .map(|_| ())
+        .map_err(|e| ConfigError::Message(format!("Invalid server.body_limits: {e}")))
+}
+
+fn validate_cluster(config: &AppConfig) -> Result<(), ConfigError> {
+    if !config.cluster.enabled || config.storage.redis_url.is_some() {
+        return Ok(());
+    }
+    match config.storage.sqlite_path.as_deref() {
+        None => Err(ConfigError::Message(
+            "APP_STORAGE__SQLITE_PATH or APP_STORAGE__REDIS_URL is required when APP_CLUSTER__ENABLED=true"
+                .into(),
+        )),
+        Some(":memory:") => Err(ConfigError::Message(
+            "APP_CLUSTER__ENABLED=true needs a database file that all replicas share, not :memory:"
+                .into(),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+fn validate_circuit_breaker(config: &AppConfig) -> Result<(), ConfigError> {
+    let breaker = &config.circuit_breaker;
+    if breaker.persist
+        && breaker.state_file.is_none()
+        && config.storage.sqlite_path.is_none()
+        && config.storage.redis_url.is_none()
+    {
+        return Err(ConfigError::Message(
+            "APP_CIRCUIT_BREAKER__PERSIST=true needs APP_CIRCUIT_BREAKER__STATE_FILE, APP_STORAGE__SQLITE_PATH or APP_STORAGE__REDIS_URL"
+                .into(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_slo(config: &AppConfig) -> Result<(), ConfigError> {
+    slo::parse_objectives(&config.slo.objectives)
+        .map(|_| ())
+        .map_err(|e| ConfigError::Message(format!("Invalid slo.objectives: {e}")))
+}
+
+fn validate_watermark(config: &AppConfig) -> Result<(), ConfigError> {
+    watermark::validate(&config.watermark)
+        .map_err(|e| ConfigError::Message(format!("Invalid watermark: {e}")))
+}
+
+fn validate_mirror(config: &AppConfig) -> Result<(), ConfigError> {
+    let mirror = &config.mirror;
+    let missing = match mirror.sink {
+        None => None,
+        Some(MirrorSinkKind::File) => mirror.path.is_none().then_some("APP_MIRROR__PATH"),
+        Some(MirrorSinkKind::Http) => mirror.url.is_none().then_some("APP_MIRROR__URL"),
+        Some(MirrorSinkKind::Kafka) => {
+            if mirror.kafka_brokers.is_none() {
+                Some("APP_MIRROR__KAFKA_BROKERS")
+            } else {
+                mirror
+                    .kafka_topic
+                    .is_none()
+                    .then_some("APP_MIRROR__KAFKA_TOPIC")
+            }
+        }
+    };
+    if let Some(setting) = missing {
+        return Err(ConfigError::Message(format!(
+            "APP_MIRROR__SINK={} needs {setting}",
+            mirror.sink.map_or("", MirrorSinkKind::as_str)
+        )));
+    }
+    for pattern in &mirror.redact_patterns {
+        regex::Regex::new(pattern).map_err(|e| {
+            ConfigError::Message(format!(
+                "Invalid mirror.redact_patterns entry '{pattern}': {e}"
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+fn validate_gemini_cli(config: &AppConfig) -> Result<(), ConfigError> {
+    let cli = &config.gemini_cli;
+    if !cli.enabled {
+        return Ok(());
+    }
+    if cli.models.is_empty() {
+        return Err(ConfigError::Message(
+            "APP_GEMINI_CLI__MODELS must list at least one model when the Gemini CLI is enabled"
+                .into(),
+        ));
+    }
+    if let Some(dir) = &cli.working_dir {
+        if !std::path::Path::new(dir).is_dir() {
+            return Err(ConfigError::Message(format!(
+                "APP_GEMINI_CLI__WORKING_DIR '{dir}' is not a directory"
+            )));
+        }
+    }
+    gemini_cli::validate_extra_args(&cli.extra_args)
+        .map_err(|e| ConfigError::Message(format!("Invalid gemini_cli.extra_args: {e}")))?;
+    if let Some(flag) = &cli.checkpoint_flag {
+        gemini_cli::validate_extra_args(std::slice::from_ref(flag)).map_err(|e| {
+            ConfigError::Message(format!("Invalid gemini_cli.checkpoint_flag: {e}"))
+        })?;
+    }
+    Ok(())
+}
+
 fn ensure_vertex_credentials(
     config: &AppConfig,
     credentials_path_env: Option<&str>,
@@ -397,12 +1865,58 @@ impl AppConfig {
         normalize_vertex_config(&mut config);
         validate_config_values(&config)?;
         validate_auth_config(&config)?;
+        validate_upstream_headers(&config)?;
+        validate_upstream_clients(&config)?;
+        validate_body_limits(&config)?;
+        validate_gemini_cli(&config)?;
+        validate_cluster(&config)?;
+        validate_circuit_breaker(&config)?;
+        validate_mirror(&config)?;
+        validate_watermark(&config)?;
+        validate_slo(&config)?;
 
         let credentials_path_env = env::var("GOOGLE_APPLICATION_CREDENTIALS").ok();
         ensure_vertex_credentials(&config, credentials_path_env.as_deref())?;
 
         Ok(config)
     }
+
+    /// Settings that split state between backends, worth a warning at
+    /// startup because part of it would not be where operators expect.
+    #[must_use]
+    pub fn cluster_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.storage.redis_url.is_some() && self.storage.sqlite_path.is_some() {
+            warnings.push(
+                "Both Redis and SQLite storage are configured; everything but the audit log \
+                 lives in Redis, and audit events stay in the SQLite database"
+                    .to_string(),
+            );
+        }
+        if !self.cluster.enabled {
+            return warnings;
+        }
+        if let (Some(path), None) = (
+            self.storage.sqlite_path.as_deref(),
+            self.storage.redis_url.as_deref(),
+        ) {
+            if std::path::Path::new(path).is_relative() {
+                warnings.push(format!(
+                    "Cluster mode uses the relative SQLite path '{path}'; replicas started \
+                     from different directories will not share state"
+                ));
+            }
+        }
+        if self.circuit_breaker.persist {
+            if let Some(path) = self.circuit_breaker.state_file.as_deref() {
+                warnings.push(format!(
+                    "Circuit breaker state is saved to the local file '{path}' while cluster mode \
+                     shares everything else; unset APP_CIRCUIT_BREAKER__STATE_FILE to share it too"
+                ));
+            }
+        }
+        warnings
+    }
 }
 
 #[cfg(test)]
@@ -486,4 +2000,266 @@ mod tests {
 
         let _ = std::fs::remove_file(&creds_path);
     }
+
+    #[test]
+    fn app_config_parses_comma_separated_webhook_urls() {
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                (
+                    "APP_ALERTS__WEBHOOK_URLS",
+                    Some("https://hooks.example.com/a, https://hooks.example.com/b"),
+                ),
+            ],
+            || {
+                let config = AppConfig::new().expect("config should load");
+                assert_eq!(
+                    config.alerts.webhook_urls,
+                    vec![
+                        "https://hooks.example.com/a".to_string(),
+                        "https://hooks.example.com/b".to_string()
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn app_config_cluster_mode_requires_shared_storage() {
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_CLUSTER__ENABLED", Some("true")),
+                ("APP_STORAGE__SQLITE_PATH", None),
+            ],
+            || {
+                let err = AppConfig::new().expect_err("cluster mode needs storage");
+                assert!(err.to_string().contains("APP_STORAGE__SQLITE_PATH"));
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_CLUSTER__ENABLED", Some("true")),
+                ("APP_STORAGE__SQLITE_PATH", None),
+                ("APP_STORAGE__REDIS_URL", Some("redis://127.0.0.1:6379")),
+            ],
+            || {
+                let config = AppConfig::new().expect("Redis alone backs cluster mode");
+                assert!(config.cluster.enabled);
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_CLUSTER__ENABLED", Some("false")),
+                ("APP_STORAGE__SQLITE_PATH", Some("/var/lib/proxy/state.db")),
+            ],
+            || {
+                let config = AppConfig::new().expect("config should load");
+                assert!(config.cluster_warnings().is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn app_config_warns_about_split_backends() {
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_CLUSTER__ENABLED", Some("true")),
+                ("APP_STORAGE__SQLITE_PATH", Some("state.db")),
+                ("APP_STORAGE__REDIS_URL", Some("redis://127.0.0.1:6379")),
+                ("APP_CIRCUIT_BREAKER__PERSIST", Some("true")),
+                (
+                    "APP_CIRCUIT_BREAKER__STATE_FILE",
+                    Some("/var/lib/proxy/breaker.json"),
+                ),
+            ],
+            || {
+                let warnings = AppConfig::new()
+                    .expect("config should load")
+                    .cluster_warnings();
+                // The relative SQLite path only holds audit events, so it is not flagged
+                assert_eq!(warnings.len(), 2, "{warnings:?}");
+                assert!(warnings[0].contains("Redis and SQLite"));
+                assert!(warnings[1].contains("breaker.json"));
+            },
+        );
+    }
+
+    #[test]
+    fn app_config_circuit_breaker_persistence_needs_a_target() {
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_CIRCUIT_BREAKER__PERSIST", Some("true")),
+                ("APP_CIRCUIT_BREAKER__STATE_FILE", None),
+                ("APP_STORAGE__SQLITE_PATH", None),
+            ],
+            || {
+                let err = AppConfig::new().expect_err("persistence needs a target");
+                assert!(err.to_string().contains("APP_CIRCUIT_BREAKER__STATE_FILE"));
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_CIRCUIT_BREAKER__PERSIST", Some("true")),
+                (
+                    "APP_CIRCUIT_BREAKER__STATE_FILE",
+                    Some("/var/lib/proxy/breaker.json"),
+                ),
+            ],
+            || {
+                let config = AppConfig::new().expect("config should load");
+                assert!(config.circuit_breaker.persist);
+            },
+        );
+    }
+
+    #[test]
+    fn app_config_mirror_sink_needs_its_target() {
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_MIRROR__SINK", Some("http")),
+                ("APP_MIRROR__URL", None),
+            ],
+            || {
+                let err = AppConfig::new().expect_err("http sink needs a URL");
+                assert!(err.to_string().contains("APP_MIRROR__URL"));
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_MIRROR__SINK", Some("file")),
+                ("APP_MIRROR__PATH", Some("/var/log/proxy/mirror.jsonl")),
+                ("APP_MIRROR__SAMPLE_RATE", Some("0.1")),
+                ("APP_MIRROR__REDACT_PATTERNS", Some(r"sk-\w+")),
+            ],
+            || {
+                let config = AppConfig::new().expect("config should load");
+                assert_eq!(config.mirror.sink, Some(MirrorSinkKind::File));
+                assert!((config.mirror.sample_rate - 0.1).abs() < f64::EPSILON);
+                assert_eq!(config.mirror.redact_patterns, vec![r"sk-\w+".to_string()]);
+            },
+        );
+    }
+
+    #[test]
+    fn app_config_upstream_client_profiles() {
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                (
+                    "APP_UPSTREAM_CLIENTS__OPENAI__USER_AGENT",
+                    Some("Mozilla/5.0"),
+                ),
+                ("APP_UPSTREAM_CLIENTS__OPENAI__HTTP1_ONLY", Some("true")),
+                (
+                    "APP_UPSTREAM_CLIENTS__VERTEX__MIN_TLS_VERSION",
+                    Some("tls1.3"),
+                ),
+            ],
+            || {
+                let config = AppConfig::new().expect("config should load");
+                let openai = &config.upstream_clients.openai;
+                assert_eq!(openai.user_agent.as_deref(), Some("Mozilla/5.0"));
+                assert!(openai.http1_only);
+                assert_eq!(
+                    config.upstream_clients.vertex.min_tls_version,
+                    Some(TlsVersion::Tls13)
+                );
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                (
+                    "APP_UPSTREAM_CLIENTS__VERTEX__MIN_TLS_VERSION",
+                    Some("tls1.3"),
+                ),
+                (
+                    "APP_UPSTREAM_CLIENTS__VERTEX__MAX_TLS_VERSION",
+                    Some("tls1.2"),
+                ),
+            ],
+            || {
+                let err = AppConfig::new().expect_err("empty TLS range should be rejected");
+                assert!(err.to_string().contains("upstream_clients.vertex"));
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                (
+                    "APP_UPSTREAM_CLIENTS__VERTEX__IMPERSONATE_COMMAND",
+                    Some("curl_chrome116"),
+                ),
+            ],
+            || {
+                let err = AppConfig::new().expect_err("impersonation is OpenAI-only");
+                assert!(err.to_string().contains("only supported for openai"));
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                (
+                    "APP_UPSTREAM_CLIENTS__OPENAI__IMPERSONATE_COMMAND",
+                    Some("curl_chrome116"),
+                ),
+            ],
+            || {
+                let result = AppConfig::new();
+                assert_eq!(result.is_ok(), cfg!(feature = "impersonate"));
+            },
+        );
+    }
+
+    #[test]
+    fn app_config_vertex_generation_defaults() {
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_VERTEX__GENERATION__TEMPERATURE", Some("0.4")),
+                ("APP_VERTEX__GENERATION__TOP_K", Some("40")),
+            ],
+            || {
+                let config = AppConfig::new().expect("config should load");
+                let generation = &config.vertex.generation;
+                assert!(generation
+                    .temperature
+                    .is_some_and(|t| (t - 0.4).abs() < f32::EPSILON));
+                assert_eq!(generation.top_k, Some(40));
+                assert_eq!(generation.top_p, None);
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("GOOGLE_API_KEY", Some("test-api-key")),
+                ("APP_AUTH__REQUIRE_AUTH", Some("false")),
+                ("APP_VERTEX__GENERATION__TOP_P", Some("1.5")),
+            ],
+            || {
+                assert!(AppConfig::new().is_err());
+            },
+        );
+    }
 }
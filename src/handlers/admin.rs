@@ -0,0 +1,663 @@
+use axum::{
+    extract::{Extension, Path, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::middleware::rate_limit::RateLimitStats;
+use crate::openai::errors::{map_error_with_code, map_error_with_status, OpenAIError};
+use crate::openai::harvester::HarvesterClient;
+use crate::services::budgets::BudgetLimits;
+use crate::services::keys::{self, AuthenticatedKey};
+use crate::services::log_control::{self, LogSettings, LogUpdate};
+use crate::services::maintenance_mode::{MaintenanceRequest, MaintenanceWindow};
+use crate::services::prompt_templates::PromptTemplate;
+use crate::services::providers::ProviderValidation;
+use crate::services::redaction;
+use crate::services::status_page::{Incident, IncidentRequest};
+use crate::state::AppState;
+
+const DEFAULT_RATE_LIMIT_TOP: usize = 20;
+const MAX_RATE_LIMIT_TOP: usize = 1_000;
+const DEFAULT_VALIDATION_TIMEOUT_SECS: u64 = 30;
+const MAX_VALIDATION_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BudgetStatus {
+    #[serde(flatten)]
+    pub limits: BudgetLimits,
+    pub spent_today_usd: f64,
+    pub spent_this_month_usd: f64,
+}
+
+async fn budget_status(state: &AppState, key: &str, limits: BudgetLimits) -> BudgetStatus {
+    let (daily, monthly) = state.usage.current_spend(key).await;
+    BudgetStatus {
+        limits,
+        spent_today_usd: daily,
+        spent_this_month_usd: monthly,
+    }
+}
+
+/// `GET /admin/budgets`: configured limits and current spend for every limited key.
+#[utoipa::path(
+    get,
+    path = "/admin/budgets",
+    tag = "admin",
+    responses((status = 200, description = "Budget status by key", body = BTreeMap<String, BudgetStatus>))
+)]
+pub async fn list_budgets(State(state): State<AppState>) -> Response {
+    let mut budgets = BTreeMap::new();
+    for (key, limits) in state.budgets.all().await {
+        let status = budget_status(&state, &key, limits).await;
+        budgets.insert(key, status);
+    }
+    Json(budgets).into_response()
+}
+
+/// `PUT /admin/budgets/:key`: replaces the limits for one key at runtime.
+///
+/// Sending both limits as `null` removes the budget. With persistent storage
+/// the change is audited, and saved for keys that live in the database.
+#[utoipa::path(
+    put,
+    path = "/admin/budgets/{key}",
+    tag = "admin",
+    params(("key" = String, Path, description = "API key name")),
+    request_body = BudgetLimits,
+    responses(
+        (status = 200, description = "The key's updated budget", body = BudgetStatus),
+        (status = 400, description = "Invalid limits", body = OpenAIError)
+    )
+)]
+pub async fn set_budget(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Path(key): Path<String>,
+    Json(limits): Json<BudgetLimits>,
+) -> Response {
+    if [limits.daily_usd, limits.monthly_usd]
+        .iter()
+        .flatten()
+        .any(|v| !v.is_finite() || *v < 0.0)
+    {
+        return map_error_with_status(400, "Budget limits must be non-negative numbers");
+    }
+    state.budgets.set(&key, limits).await;
+
+    if let Some(storage) = &state.storage {
+        if let Err(e) = keys::update_stored_budget(storage.as_ref(), &key, limits).await {
+            warn!("Failed to persist budget for '{}': {e:#}", key);
+        }
+    }
+    let detail = serde_json::json!({ "key": key, "limits": limits }).to_string();
+    audit(&state, caller, "budget.set", &detail).await;
+    Json(budget_status(&state, &key, limits).await).into_response()
+}
+
+async fn audit(
+    state: &AppState,
+    caller: Option<Extension<AuthenticatedKey>>,
+    action: &str,
+    detail: &str,
+) {
+    if let Some(store) = &state.store {
+        let actor = caller.map_or_else(AuthenticatedKey::anonymous, |Extension(k)| k);
+        let detail = redaction::redact(&state.config.redaction, detail);
+        if let Err(e) = store.record_audit(&actor.name, action, &detail).await {
+            warn!("Failed to record audit event: {e:#}");
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AliasTarget {
+    pub target: String,
+}
+
+/// `GET /admin/models/aliases`: every model alias and its current target.
+#[utoipa::path(
+    get,
+    path = "/admin/models/aliases",
+    tag = "admin",
+    responses((status = 200, description = "Alias targets by alias", body = BTreeMap<String, String>))
+)]
+pub async fn list_model_aliases(State(state): State<AppState>) -> Response {
+    Json(state.model_registry.aliases()).into_response()
+}
+
+/// `PUT /admin/models/aliases/:alias`: points an alias at a concrete model.
+///
+/// Takes effect for the next request. Runtime changes are not persisted; the
+/// configured aliases apply again after a restart.
+#[utoipa::path(
+    put,
+    path = "/admin/models/aliases/{alias}",
+    tag = "admin",
+    params(("alias" = String, Path, description = "Alias name")),
+    request_body = AliasTarget,
+    responses(
+        (status = 200, description = "The alias and its new target", body = Object),
+        (status = 400, description = "Invalid alias or target", body = OpenAIError)
+    )
+)]
+pub async fn set_model_alias(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Path(alias): Path<String>,
+    Json(body): Json<AliasTarget>,
+) -> Response {
+    if let Err(e) = state.model_registry.set_alias(&alias, &body.target) {
+        return map_error_with_code(400, &e.to_string(), "invalid_request", Some("target"));
+    }
+    let detail = serde_json::json!({ "alias": alias, "target": body.target }).to_string();
+    audit(&state, caller, "model_alias.set", &detail).await;
+    Json(serde_json::json!({ "alias": alias, "target": body.target })).into_response()
+}
+
+/// `DELETE /admin/models/aliases/:alias`: removes an alias.
+#[utoipa::path(
+    delete,
+    path = "/admin/models/aliases/{alias}",
+    tag = "admin",
+    params(("alias" = String, Path, description = "Alias name")),
+    responses(
+        (status = 204, description = "Alias removed"),
+        (status = 404, description = "Unknown alias", body = OpenAIError)
+    )
+)]
+pub async fn delete_model_alias(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Path(alias): Path<String>,
+) -> Response {
+    let Some(target) = state.model_registry.remove_alias(&alias) else {
+        return map_error_with_status(404, &format!("No model alias named '{alias}'"));
+    };
+    let detail = serde_json::json!({ "alias": alias, "target": target }).to_string();
+    audit(&state, caller, "model_alias.delete", &detail).await;
+    axum::http::StatusCode::NO_CONTENT.into_response()
+}
+
+/// `GET /admin/prompts`: every prompt template, by name.
+#[utoipa::path(
+    get,
+    path = "/admin/prompts",
+    tag = "admin",
+    responses((status = 200, description = "Prompt templates by name", body = BTreeMap<String, PromptTemplate>))
+)]
+pub async fn list_prompt_templates(State(state): State<AppState>) -> Response {
+    Json(state.prompts.list()).into_response()
+}
+
+/// `PUT /admin/prompts/:name`: adds or replaces a prompt template.
+///
+/// Takes effect for the next request. Runtime changes are not persisted; the
+/// templates file applies again after a restart.
+#[utoipa::path(
+    put,
+    path = "/admin/prompts/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Template name")),
+    request_body = PromptTemplate,
+    responses(
+        (status = 200, description = "The stored template", body = PromptTemplate),
+        (status = 400, description = "Invalid template", body = OpenAIError)
+    )
+)]
+pub async fn set_prompt_template(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Path(name): Path<String>,
+    Json(template): Json<PromptTemplate>,
+) -> Response {
+    if let Err(e) = state.prompts.set(&name, template.clone()) {
+        return map_error_with_code(400, &e.to_string(), "invalid_request", Some("messages"));
+    }
+    let detail = serde_json::json!({ "name": name }).to_string();
+    audit(&state, caller, "prompt_template.set", &detail).await;
+    Json(template).into_response()
+}
+
+/// `DELETE /admin/prompts/:name`: removes a prompt template.
+#[utoipa::path(
+    delete,
+    path = "/admin/prompts/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Template name")),
+    responses(
+        (status = 204, description = "Template removed"),
+        (status = 404, description = "Unknown template", body = OpenAIError)
+    )
+)]
+pub async fn delete_prompt_template(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Path(name): Path<String>,
+) -> Response {
+    if state.prompts.remove(&name).is_none() {
+        return map_error_with_status(404, &format!("No prompt template named '{name}'"));
+    }
+    let detail = serde_json::json!({ "name": name }).to_string();
+    audit(&state, caller, "prompt_template.delete", &detail).await;
+    axum::http::StatusCode::NO_CONTENT.into_response()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RateLimitQuery {
+    /// Number of keys to list (default 20, at most 1000).
+    pub top: Option<usize>,
+}
+
+/// `GET /admin/rate-limit`: limiter settings and the busiest keys with their
+/// remaining tokens, allowed and rejected requests, and recent rejections.
+#[utoipa::path(
+    get,
+    path = "/admin/rate-limit",
+    tag = "admin",
+    params(RateLimitQuery),
+    responses((status = 200, description = "Limiter settings and busiest keys", body = RateLimitStats))
+)]
+pub async fn rate_limit_stats(
+    State(state): State<AppState>,
+    Query(query): Query<RateLimitQuery>,
+) -> Response {
+    let top = query
+        .top
+        .unwrap_or(DEFAULT_RATE_LIMIT_TOP)
+        .min(MAX_RATE_LIMIT_TOP);
+    Json(state.rate_limiter.stats(top).await).into_response()
+}
+
+/// Whether a maintenance window is open, and its settings if one is open or
+/// scheduled.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceStatus {
+    /// The window is open now; `false` while it is only scheduled.
+    pub active: bool,
+    #[serde(flatten)]
+    pub window: Option<MaintenanceWindow>,
+}
+
+/// `GET /admin/maintenance`: the current or scheduled maintenance window, if
+/// any.
+#[utoipa::path(
+    get,
+    path = "/admin/maintenance",
+    tag = "admin",
+    responses((status = 200, description = "Current maintenance state", body = MaintenanceStatus))
+)]
+pub async fn maintenance_status(State(state): State<AppState>) -> Response {
+    let now = Utc::now();
+    let window = state.maintenance_mode.window_at(now).await;
+    Json(MaintenanceStatus {
+        active: window.as_ref().is_some_and(|w| w.is_open_at(now)),
+        window,
+    })
+    .into_response()
+}
+
+/// `PUT /admin/maintenance`: starts maintenance for the `/v1/*` endpoints, or
+/// schedules it with `from`, replacing any other window.
+#[utoipa::path(
+    put,
+    path = "/admin/maintenance",
+    tag = "admin",
+    request_body = MaintenanceRequest,
+    responses(
+        (status = 200, description = "The opened or scheduled window", body = MaintenanceStatus),
+        (status = 400, description = "Invalid window settings", body = OpenAIError)
+    )
+)]
+pub async fn start_maintenance(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Json(settings): Json<MaintenanceRequest>,
+) -> Response {
+    let now = Utc::now();
+    if settings.until.is_some_and(|until| until <= now) {
+        return map_error_with_status(400, "Maintenance 'until' must be in the future");
+    }
+    if let (Some(from), Some(until)) = (settings.from, settings.until) {
+        if from >= until {
+            return map_error_with_status(400, "Maintenance 'from' must be before 'until'");
+        }
+    }
+    if settings.retry_after_secs == Some(0) {
+        return map_error_with_status(400, "Maintenance 'retry_after_secs' must be at least 1");
+    }
+    let detail = serde_json::to_string(&settings).unwrap_or_default();
+    let window = state.maintenance_mode.enable(settings).await;
+    let active = window.is_open_at(now);
+    if active {
+        warn!("Maintenance mode started: {detail}");
+    } else {
+        warn!("Maintenance mode scheduled: {detail}");
+    }
+    audit(&state, caller, "maintenance.start", &detail).await;
+    Json(MaintenanceStatus {
+        active,
+        window: Some(window),
+    })
+    .into_response()
+}
+
+/// `DELETE /admin/maintenance`: ends or cancels the maintenance window.
+#[utoipa::path(
+    delete,
+    path = "/admin/maintenance",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Maintenance ended", body = MaintenanceStatus),
+        (status = 404, description = "Maintenance is not active", body = OpenAIError)
+    )
+)]
+pub async fn end_maintenance(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+) -> Response {
+    if !state.maintenance_mode.disable().await {
+        return map_error_with_status(404, "Maintenance mode is not active");
+    }
+    info!("Maintenance mode ended");
+    audit(&state, caller, "maintenance.end", "").await;
+    Json(MaintenanceStatus {
+        active: false,
+        window: None,
+    })
+    .into_response()
+}
+
+/// Whether the public status page reports an incident, and its details if so.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct IncidentStatus {
+    pub active: bool,
+    #[serde(flatten)]
+    pub incident: Option<Incident>,
+}
+
+/// `GET /admin/incident`: the incident shown on `/status`, if any.
+#[utoipa::path(
+    get,
+    path = "/admin/incident",
+    tag = "admin",
+    responses((status = 200, description = "Current incident state", body = IncidentStatus))
+)]
+pub async fn incident_status(State(state): State<AppState>) -> Response {
+    let incident = state.status_page.incident().await;
+    Json(IncidentStatus {
+        active: incident.is_some(),
+        incident,
+    })
+    .into_response()
+}
+
+/// `PUT /admin/incident`: flags an incident on the public status page,
+/// replacing any open one. Unlike maintenance, requests are still served.
+#[utoipa::path(
+    put,
+    path = "/admin/incident",
+    tag = "admin",
+    request_body = IncidentRequest,
+    responses((status = 200, description = "The raised incident", body = IncidentStatus))
+)]
+pub async fn raise_incident(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Json(settings): Json<IncidentRequest>,
+) -> Response {
+    let detail = serde_json::to_string(&settings).unwrap_or_default();
+    let incident = state.status_page.raise(settings).await;
+    warn!("Incident raised: {detail}");
+    audit(&state, caller, "incident.raise", &detail).await;
+    Json(IncidentStatus {
+        active: true,
+        incident: Some(incident),
+    })
+    .into_response()
+}
+
+/// `DELETE /admin/incident`: clears the incident flag.
+#[utoipa::path(
+    delete,
+    path = "/admin/incident",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Incident resolved", body = IncidentStatus),
+        (status = 404, description = "No incident is open", body = OpenAIError)
+    )
+)]
+pub async fn resolve_incident(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+) -> Response {
+    if !state.status_page.resolve().await {
+        return map_error_with_status(404, "No incident is open");
+    }
+    info!("Incident resolved");
+    audit(&state, caller, "incident.resolve", "").await;
+    Json(IncidentStatus {
+        active: false,
+        incident: None,
+    })
+    .into_response()
+}
+
+/// Outcome of `POST /admin/credentials/refresh`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CredentialRefresh {
+    /// Cached Google access tokens dropped, global and per-key.
+    pub vertex_tokens_cleared: usize,
+    /// Whether the harvester handed out a fresh ChatGPT session.
+    pub harvester_refreshed: bool,
+    /// Why the harvester refresh failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub harvester_error: Option<String>,
+    /// Harvester sessions taken out of WAF cooldown.
+    pub waf_cooldowns_cleared: usize,
+    /// Conversation sessions unbound from their harvester tokens.
+    pub token_bindings_cleared: usize,
+}
+
+/// `POST /admin/credentials/refresh`: drops cached provider credentials so
+/// the next request acquires new ones, without a restart.
+///
+/// Clears the cached Vertex access tokens, the WAF cooldowns and the
+/// sessions' token bindings, and asks the harvester for a new session. A harvester failure is reported in the
+/// body rather than failing the call, as the other credentials are already
+/// flushed by then.
+#[utoipa::path(
+    post,
+    path = "/admin/credentials/refresh",
+    tag = "admin",
+    responses((status = 200, description = "What was flushed", body = CredentialRefresh))
+)]
+pub async fn refresh_credentials(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+) -> Response {
+    let vertex_tokens_cleared = state.token_manager.clear_cached_tokens().await;
+    let waf_cooldowns_cleared = state.waf_cooldown.clear();
+    let token_bindings_cleared = state.token_bindings.clear();
+    let harvester_error = match HarvesterClient::new(&state.config) {
+        Ok(harvester) => harvester.refresh_tokens(true).await.err(),
+        Err(e) => Some(e),
+    }
+    .map(|e| format!("{e:#}"));
+    if let Some(e) = &harvester_error {
+        warn!("Harvester refresh failed during credential refresh: {e}");
+    }
+    info!(
+        "Credentials refreshed: {vertex_tokens_cleared} Vertex token(s), {waf_cooldowns_cleared} WAF cooldown(s), {token_bindings_cleared} token binding(s) cleared"
+    );
+    let refresh = CredentialRefresh {
+        vertex_tokens_cleared,
+        harvester_refreshed: harvester_error.is_none(),
+        harvester_error,
+        waf_cooldowns_cleared,
+        token_bindings_cleared,
+    };
+    let detail = serde_json::to_string(&refresh).unwrap_or_default();
+    audit(&state, caller, "credentials.refresh", &detail).await;
+    Json(refresh).into_response()
+}
+
+/// Outcome of `POST /admin/models/refresh`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ModelRefresh {
+    /// Gemini models Vertex now reports.
+    pub vertex_models: usize,
+}
+
+/// `POST /admin/models/refresh`: fetches the upstream model lists behind
+/// `/v1/models` now, instead of when their TTL runs out.
+#[utoipa::path(
+    post,
+    path = "/admin/models/refresh",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Models listed after the refresh", body = ModelRefresh),
+        (status = 400, description = "Model discovery is disabled", body = OpenAIError),
+        (status = 502, description = "The upstream did not return a model list", body = OpenAIError)
+    )
+)]
+pub async fn refresh_models(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+) -> Response {
+    if !state.model_catalog.enabled() {
+        return map_error_with_status(
+            400,
+            "Model discovery is disabled; set APP_MODEL_DISCOVERY__ENABLED=true",
+        );
+    }
+    match state.model_catalog.refresh_vertex(&state).await {
+        Ok(vertex_models) => {
+            let refresh = ModelRefresh { vertex_models };
+            let detail = serde_json::to_string(&refresh).unwrap_or_default();
+            audit(&state, caller, "models.refresh", &detail).await;
+            Json(refresh).into_response()
+        }
+        Err(e) => {
+            warn!("Model list refresh failed: {e}");
+            map_error_with_status(
+                502,
+                &format!("Failed to refresh the Vertex model list: {e}"),
+            )
+        }
+    }
+}
+
+const LOG_CONTROL_UNAVAILABLE: &str =
+    "Log output is not managed by this process and cannot be changed at runtime";
+
+/// `GET /admin/logs`: the log level, format and target directives in effect.
+#[utoipa::path(
+    get,
+    path = "/admin/logs",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Current log settings", body = LogSettings),
+        (status = 503, description = "The process did not install the log subscriber", body = OpenAIError)
+    )
+)]
+pub async fn log_settings() -> Response {
+    match log_control::current() {
+        Some(control) => Json(control.settings()).into_response(),
+        None => map_error_with_status(503, LOG_CONTROL_UNAVAILABLE),
+    }
+}
+
+/// `PUT /admin/logs`: changes the log level, format or target directives
+/// until the next restart, e.g. JSON lines with `debug` for one module while
+/// chasing a production issue.
+#[utoipa::path(
+    put,
+    path = "/admin/logs",
+    tag = "admin",
+    request_body = LogUpdate,
+    responses(
+        (status = 200, description = "The log settings now in effect", body = LogSettings),
+        (status = 400, description = "Invalid level or target directives", body = OpenAIError),
+        (status = 503, description = "The process did not install the log subscriber", body = OpenAIError)
+    )
+)]
+pub async fn update_log_settings(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Json(update): Json<LogUpdate>,
+) -> Response {
+    let Some(control) = log_control::current() else {
+        return map_error_with_status(503, LOG_CONTROL_UNAVAILABLE);
+    };
+    match control.update(update) {
+        Ok(settings) => {
+            let detail = serde_json::to_string(&settings).unwrap_or_default();
+            audit(&state, caller, "logs.update", &detail).await;
+            Json(settings).into_response()
+        }
+        Err(e) => map_error_with_status(400, &e),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ValidateProvidersQuery {
+    /// Validate only this provider (e.g. `vertex`, `anthropic_cli`, `gemini_cli`).
+    pub provider: Option<String>,
+    /// Per-provider timeout (default 30, at most 120).
+    pub timeout_secs: Option<u64>,
+}
+
+/// Per-provider outcome of `POST /admin/providers/validate`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProviderValidationReport {
+    /// Whether every validated provider passed.
+    pub ok: bool,
+    pub providers: Vec<ProviderValidation>,
+}
+
+/// `POST /admin/providers/validate`: sends a one-token completion to each
+/// provider and reports pass/fail with latency.
+#[utoipa::path(
+    post,
+    path = "/admin/providers/validate",
+    tag = "admin",
+    params(ValidateProvidersQuery),
+    responses(
+        (status = 200, description = "Outcome per provider", body = ProviderValidationReport),
+        (status = 404, description = "Unknown provider", body = OpenAIError)
+    )
+)]
+pub async fn validate_providers(
+    State(state): State<AppState>,
+    Query(query): Query<ValidateProvidersQuery>,
+) -> Response {
+    let timeout = Duration::from_secs(
+        query
+            .timeout_secs
+            .unwrap_or(DEFAULT_VALIDATION_TIMEOUT_SECS)
+            .clamp(1, MAX_VALIDATION_TIMEOUT_SECS),
+    );
+    let providers = state
+        .provider_registry
+        .validate(&state, query.provider.as_deref(), timeout)
+        .await;
+    if providers.is_empty() {
+        if let Some(name) = &query.provider {
+            return map_error_with_status(404, &format!("Provider '{name}' is not configured"));
+        }
+    }
+    Json(ProviderValidationReport {
+        ok: providers.iter().all(|p| p.ok),
+        providers,
+    })
+    .into_response()
+}
@@ -1,29 +1,186 @@
+use axum::http::{header, HeaderMap, HeaderValue};
 use axum::{
-    extract::State,
+    extract::{rejection::JsonRejection, Extension, Query, State},
     response::{sse::Event, IntoResponse, Sse},
     Json,
 };
 use futures::stream::StreamExt;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::convert::Infallible;
-use tracing::{error, info, warn};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+use utoipa::IntoParams;
 use uuid::Uuid;
 
 use crate::{
-    handlers::openai_chat,
-    models::openai::{ChatCompletionChunk, ChatCompletionRequest},
-    openai::errors::map_error_with_status,
-    services::providers::ProviderError,
+    config::AppConfig,
+    handlers::{keepalive, openai_chat, sse},
+    middleware::access_log::RequestModel,
+    models::openai::{ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, Usage},
+    openai::errors::{
+        is_context_length_error, map_error_with_code, map_error_with_detail, map_error_with_status,
+        map_json_rejection, OpenAIError, CODE_CONTEXT_LENGTH_EXCEEDED, CODE_IDEMPOTENCY_KEY_IN_USE,
+        CODE_IDEMPOTENCY_KEY_REUSED, CODE_MODEL_NOT_ALLOWED, CODE_MODEL_NOT_FOUND,
+        CODE_MODEL_RETIRED, CODE_POST_PROCESSING_FAILED, CODE_PROMPT_TEMPLATE_NOT_FOUND,
+        CODE_UNSUPPORTED_CAPABILITY,
+    },
+    services::{
+        capabilities,
+        debug_trace::{self, CacheResult},
+        fallback_responses, finish_reason,
+        idempotency::{self, Claim, Reservation, IDEMPOTENCY_KEY_HEADER},
+        keys::AuthenticatedKey,
+        log_context, model_policy,
+        notifier::AlertEvent,
+        param_policy,
+        post_processor::{self, PostProcessError},
+        prompt_templates::TemplateError,
+        providers::{self, LLMProvider, Provider, ProviderError, RouteReason, StreamingResponse},
+        redaction,
+        request_limits::{self, LimitExceeded},
+        routing::{self, RoutingStrategy, ROUTING_STRATEGY_HEADER},
+        tokenizer,
+        trace_context::TraceContext,
+        transcripts::Transcripts,
+        upstream_headers,
+    },
     state::AppState,
 };
 
+/// Response header naming the provider that served (or failed) the request.
+pub const X_ROUTED_PROVIDER: &str = "x-routed-provider";
+
+/// Response header listing parameters clamped by the key's policy.
+pub const X_PARAMETER_ADJUSTMENTS: &str = "x-parameter-adjustments";
+
+/// Response header naming the experiment and variant, as `experiment=variant`.
+pub const X_EXPERIMENT: &str = "x-experiment";
+
+/// Response header naming the requested model when a context fallback answered instead.
+pub const X_CONTEXT_FALLBACK: &str = "x-context-fallback";
+
+/// Response header set when a configured fallback reply stands in for an upstream outage.
+pub const X_FALLBACK_RESPONSE: &str = "x-fallback-response";
+
+// Bounds fallback chains so a misconfigured cycle cannot loop forever
+const MAX_CONTEXT_FALLBACKS: usize = 3;
+
+/// Marks responses rejecting a prompt as too long for the model, so the
+/// request can be retried on a context fallback.
+#[derive(Clone, Copy)]
+struct ContextOverflow;
+
+/// Query parameters of `POST /v1/chat/completions`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChatCompletionQuery {
+    /// Run validation, aliasing, routing and limit checks, then return the
+    /// plan instead of calling the provider.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// What a chat completion would do, returned for `?dry_run=true`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatCompletionPlan {
+    /// Always `chat.completion.plan`.
+    pub object: String,
+    /// Model the request resolves to after experiments, rules, aliases and
+    /// context fallbacks.
+    pub model: String,
+    pub provider: String,
+    pub route_reason: String,
+    pub stream: bool,
+    /// Tokenizer the prompt was counted with, e.g. `o200k_base`.
+    pub tokenizer: String,
+    /// Estimated from the prompt text by the model family's tokenizer.
+    pub prompt_tokens: u32,
+    /// `max_tokens` after parameter policies and clamping.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Upper bound in USD: the estimated prompt plus `max_tokens` (or the
+    /// model's output limit) of completion. Absent for models without
+    /// registry metadata.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cost_usd: Option<f64>,
+}
+
+/// How `route_chat_completion` resolved a request, for dispatching it.
+#[derive(Clone, Copy)]
+struct Dispatch<'a> {
+    route_reason: RouteReason,
+    avoid_providers: &'a [String],
+    session: Option<&'a str>,
+    /// Answer with the plan instead of calling the provider.
+    dry_run: bool,
+}
+
+/// Marks responses for upstream outages, so a configured fallback reply can
+/// replace them.
+#[derive(Clone, Copy)]
+struct ProviderOutage;
+
+// `gpt-*` models bypass the provider registry and go to the harvester backend,
+// unless listed for the OpenAI-compatible upstream
+const OPENAI_PROVIDER_NAME: &str = openai_chat::PROVIDER_NAME;
+
 #[must_use]
 pub fn is_openai_model(model: &str) -> bool {
     // gpt-3.5 and gpt-4 are already covered by starts_with("gpt-")
     model.starts_with("gpt-")
 }
 
+type EventStream = std::pin::Pin<
+    Box<dyn futures::Stream<Item = Result<Event, Box<dyn std::error::Error + Send + Sync>>> + Send>,
+>;
+
+/// The events of a strictly framed stream: completion chunks, ending with
+/// `[DONE]` even when the provider sends none.
+fn strict_events(provider_stream: StreamingResponse) -> EventStream {
+    let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let seen = done.clone();
+    let events = provider_stream.filter_map(move |chunk_result| {
+        futures::future::ready(match chunk_result {
+            Ok(chunk) => {
+                let event = parse_strict_sse_chunk(&chunk);
+                if chunk.trim() == "data: [DONE]" {
+                    seen.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                event.map(Ok)
+            }
+            Err(e) => Some(Err(e)),
+        })
+    });
+    let terminator = futures::stream::once(async move {
+        (!done.load(std::sync::atomic::Ordering::Relaxed)).then(|| Ok(sse::done_event()))
+    })
+    .filter_map(futures::future::ready);
+    Box::pin(events.chain(terminator))
+}
+
+/// Like [`parse_sse_chunk`], but yields only completion chunks and `[DONE]`,
+/// dropping comments and data that does not parse as a chunk.
+fn parse_strict_sse_chunk(chunk_data: &str) -> Option<Event> {
+    let json_data = chunk_data.strip_prefix("data: ")?.trim();
+    if json_data == "[DONE]" {
+        return Some(sse::done_event());
+    }
+    match serde_json::from_str::<ChatCompletionChunk>(json_data) {
+        Ok(chunk) => Event::default().json_data(chunk).ok(),
+        Err(e) => {
+            warn!("Dropping SSE data that is not a completion chunk: {e}");
+            None
+        }
+    }
+}
+
 fn parse_sse_chunk(chunk_data: &str) -> Event {
+    // Providers send SSE comment lines (": keep-alive") for chunks with no payload
+    if let Some(comment) = chunk_data.strip_prefix(':') {
+        return Event::default().comment(comment.trim());
+    }
+
     // Validate SSE format: should start with "data: "
     if !chunk_data.starts_with("data: ") {
         if !chunk_data.trim().is_empty() {
@@ -44,7 +201,7 @@ fn parse_sse_chunk(chunk_data: &str) -> Event {
     };
     let json_data = json_data.trim();
     if json_data == "[DONE]" {
-        return Event::default().comment("[DONE]");
+        return sse::done_event();
     }
 
     // Try to parse as ChatCompletionChunk first
@@ -89,28 +246,611 @@ fn parse_sse_chunk(chunk_data: &str) -> Event {
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/chat/completions",
+    tag = "openai",
+    request_body = ChatCompletionRequest,
+    params(
+        ChatCompletionQuery,
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays the stored response for retries with the same key instead of running the request again"),
+        ("X-Session-Id" = Option<String>, Header, description = "Session ID (header name set by `models.session_header`) for sticky alias routing and session-bound OpenAI tokens and, when transcripts are enabled, the conversation the turn is recorded under"),
+        ("X-Debug" = Option<bool>, Header, description = "With an admin key, adds a `debug` object (an `X-Debug` header on streams) with the routing decision, attempts, cache result and stage timings")
+    ),
+    responses(
+        (status = 200, description = "Completion, or a stream of chunks when `stream` is set; a configured fallback reply with `finish_reason: \"error\"` during provider outages; a `ChatCompletionPlan` for `dry_run`", content(
+            (ChatCompletionResponse = "application/json"),
+            (ChatCompletionChunk = "text/event-stream")
+        )),
+        (status = 400, description = "Invalid request", body = OpenAIError),
+        (status = 401, description = "Missing or invalid API key", body = OpenAIError),
+        (status = 404, description = "Unknown model or prompt template", body = OpenAIError),
+        (status = 409, description = "A request with the same Idempotency-Key is still running", body = OpenAIError),
+        (status = 422, description = "The Idempotency-Key was used for a different request", body = OpenAIError),
+        (status = 429, description = "Rate limit or budget exceeded", body = OpenAIError),
+        (status = 503, description = "No provider available, or maintenance", body = OpenAIError)
+    )
+)]
 pub async fn chat_completions(
     State(state): State<AppState>,
-    Json(req): Json<ChatCompletionRequest>,
+    key: Option<Extension<AuthenticatedKey>>,
+    Query(query): Query<ChatCompletionQuery>,
+    headers: HeaderMap,
+    payload: Result<Json<ChatCompletionRequest>, JsonRejection>,
+) -> axum::response::Response {
+    let Json(req) = match payload {
+        Ok(payload) => payload,
+        Err(rejection) => return map_json_rejection(&rejection),
+    };
+    let strategy = match headers
+        .get(ROUTING_STRATEGY_HEADER)
+        .map(|v| v.to_str().map_err(|e| e.to_string()))
+    {
+        None => state.config.models.routing_strategy,
+        Some(Ok(value)) => match value.parse::<RoutingStrategy>() {
+            Ok(strategy) => strategy,
+            Err(e) => return map_error_with_status(400, &e),
+        },
+        Some(Err(e)) => {
+            return map_error_with_status(400, &format!("Invalid X-Routing-Strategy: {e}"))
+        }
+    };
+    let session = headers
+        .get(state.config.models.session_header.as_str())
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| req.user.clone());
+    let conversation = if state.transcripts.is_enabled() {
+        match Transcripts::session_id(&headers, &state.config.models.session_header) {
+            Ok(conversation) => conversation,
+            Err(e) => return map_error_with_status(400, &e),
+        }
+    } else {
+        None
+    };
+    let model = req.model.clone();
+    log_context::record("model", &model);
+    let key = key.map_or_else(AuthenticatedKey::anonymous, |Extension(k)| k);
+    if query.dry_run {
+        let mut response = plan_chat_completion(state, key, strategy, session, req).await;
+        response.extensions_mut().insert(RequestModel(model));
+        return response;
+    }
+    let traced = debug_trace::requested(&headers) && key.admin;
+    if debug_trace::requested(&headers) && !key.admin {
+        debug!("Ignoring X-Debug from non-admin key '{}'", key.name);
+    }
+    let keepalive = state.config.keepalive.clone();
+    let streaming = req.stream;
+    let requested_model = model.clone();
+    let forward = state.config.upstream_headers.forward.clone();
+    // Boxed so the wrapped pipeline future does not sit on the stack
+    let completion = Box::pin(complete_chat(
+        state,
+        key,
+        headers,
+        strategy,
+        session,
+        conversation,
+        req,
+    ));
+    let completion = upstream_headers::forward(forward, completion);
+    let completion = async move {
+        if traced {
+            debug_trace::capture(requested_model, completion).await
+        } else {
+            completion.await
+        }
+    };
+    // Streams keep themselves alive with SSE comments
+    let mut response = if streaming {
+        completion.await
+    } else {
+        keepalive::respond(&keepalive, completion).await
+    };
+    response.extensions_mut().insert(RequestModel(model));
+    response
+}
+
+/// Runs a parsed chat completion request through idempotency, routing,
+/// response marking and, under a `conversation` ID, transcript recording.
+async fn complete_chat(
+    state: AppState,
+    key: AuthenticatedKey,
+    headers: HeaderMap,
+    strategy: RoutingStrategy,
+    session: Option<String>,
+    conversation: Option<String>,
+    mut req: ChatCompletionRequest,
+) -> axum::response::Response {
+    let model = req.model.clone();
+    let reservation = match claim_idempotency_key(&state, &key, &headers, &req).await {
+        Ok(reservation) => reservation,
+        Err(response) => return *response,
+    };
+    let experiment = assign_experiment(&state, &key, &mut req);
+    let metrics = state.metrics.clone();
+    let slos = state.slos.clone();
+    let request_start = std::time::Instant::now();
+    let streaming = req.stream;
+    let watermark = state.watermark.clone();
+    let transcript = conversation.map(|id| (state.transcripts.clone(), id, req.messages.clone()));
+    let cancel = request_cancellation(&state);
+    // Fires if the client disconnects before the response is ready
+    let disconnect = cancel.clone().drop_guard();
+    let mut response =
+        route_chat_completion(state, &key, strategy, session, req, &cancel, false).await;
+    if streaming && response.status().is_success() {
+        // The stream owns the token from here on
+        disconnect.disarm();
+    }
+    slos.record(&model, response.status(), request_start.elapsed());
+    if let Some((experiment, variant)) = experiment {
+        let duration_ms = u64::try_from(request_start.elapsed().as_millis()).unwrap_or(u64::MAX);
+        metrics
+            .record_experiment_result(
+                &experiment,
+                &variant,
+                response.status().is_success(),
+                duration_ms,
+            )
+            .await;
+        if let Ok(value) = HeaderValue::from_str(&format!("{experiment}={variant}")) {
+            response.headers_mut().insert(X_EXPERIMENT, value);
+        }
+    }
+    let provider = response
+        .headers()
+        .get(X_ROUTED_PROVIDER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    response = watermark
+        .apply(&key.name, provider.as_deref(), &model, response)
+        .await;
+    if let Some((transcripts, id, messages)) = transcript {
+        response = transcripts.record(&key.name, &id, messages, response);
+    }
+    if let Some(reservation) = reservation {
+        response = reservation.capture(response);
+    }
+    response
+}
+
+/// Claims the request's `Idempotency-Key`, if it sent one. `Err` carries the
+/// response to send instead of running the request: the stored response of
+/// an earlier attempt, or a conflict.
+async fn claim_idempotency_key(
+    state: &AppState,
+    key: &AuthenticatedKey,
+    headers: &HeaderMap,
+    req: &ChatCompletionRequest,
+) -> Result<Option<Reservation>, Box<axum::response::Response>> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    if !state.idempotency.is_enabled() {
+        return Ok(None);
+    }
+    let idempotency_key = match value.to_str() {
+        Ok(value) if !value.is_empty() && value.len() <= idempotency::MAX_KEY_LEN => value,
+        _ => {
+            return Err(Box::new(map_error_with_status(
+                400,
+                &format!(
+                    "Idempotency-Key must be 1 to {} visible ASCII characters",
+                    idempotency::MAX_KEY_LEN
+                ),
+            )))
+        }
+    };
+    let body = serde_json::to_vec(req)
+        .map_err(|e| map_error_with_status(500, &format!("Failed to fingerprint request: {e}")))?;
+    match state
+        .idempotency
+        .claim(&key.name, idempotency_key, idempotency::fingerprint(&body))
+        .await
+    {
+        Claim::Acquired(reservation) => Ok(Some(reservation)),
+        Claim::Replay(response) => {
+            info!(
+                "Replaying stored response for key '{}' (Idempotency-Key {idempotency_key})",
+                key.name
+            );
+            Err(Box::new(response))
+        }
+        Claim::InProgress => Err(Box::new(map_error_with_code(
+            409,
+            "A request with this Idempotency-Key is still in progress; retry once it completes",
+            CODE_IDEMPOTENCY_KEY_IN_USE,
+            None,
+        ))),
+        Claim::Mismatch => Err(Box::new(map_error_with_code(
+            422,
+            "This Idempotency-Key was already used for a different request",
+            CODE_IDEMPOTENCY_KEY_REUSED,
+            None,
+        ))),
+    }
+}
+
+/// Token cancelled on shutdown, when the configured request timeout passes, or
+/// when the request is abandoned, whichever comes first.
+fn request_cancellation(state: &AppState) -> CancellationToken {
+    let cancel = state.shutdown.child_token();
+    let timeout_secs = state.config.server.request_timeout_secs;
+    if timeout_secs > 0 {
+        let deadline = cancel.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                () = tokio::time::sleep(std::time::Duration::from_secs(timeout_secs)) => {
+                    warn!("Request exceeded {timeout_secs}s timeout; cancelling");
+                    deadline.cancel();
+                }
+                () = deadline.cancelled() => {}
+            }
+        });
+    }
+    cancel
+}
+
+/// Runs `req` through experiments, routing and the checks before dispatch
+/// without calling a provider, answering with the plan or the error the
+/// request would get.
+async fn plan_chat_completion(
+    state: AppState,
+    key: AuthenticatedKey,
+    strategy: RoutingStrategy,
+    session: Option<String>,
+    mut req: ChatCompletionRequest,
 ) -> axum::response::Response {
+    assign_experiment(&state, &key, &mut req);
+    let cancel = CancellationToken::new();
+    route_chat_completion(state, &key, strategy, session, req, &cancel, true).await
+}
+
+/// Applies the variant of the experiment running on the requested model, if
+/// any, returning the experiment and variant names.
+fn assign_experiment(
+    state: &AppState,
+    key: &AuthenticatedKey,
+    req: &mut ChatCompletionRequest,
+) -> Option<(String, String)> {
+    let experiment = state.experiments.for_model(&req.model)?;
+    // End users keep their variant across keys; otherwise the key is the subject
+    let subject = req.user.clone().unwrap_or_else(|| key.name.clone());
+    let variant = experiment.assign(&subject);
+    if let Some(model) = &variant.model {
+        req.model.clone_from(model);
+    }
+    if let Some(template) = &variant.prompt_template {
+        req.prompt_template = Some(template.clone());
+    }
+    info!(
+        "Assigned request for {} to experiment {} variant {}",
+        experiment.model, experiment.name, variant.name
+    );
+    Some((experiment.name.clone(), variant.name.clone()))
+}
+
+async fn route_chat_completion(
+    state: AppState,
+    key: &AuthenticatedKey,
+    strategy: RoutingStrategy,
+    session: Option<String>,
+    mut req: ChatCompletionRequest,
+    cancel: &CancellationToken,
+    dry_run: bool,
+) -> axum::response::Response {
+    let client_model = req.model.clone();
+    let stream = req.stream;
+    if let Some(name) = req.prompt_template.take() {
+        match state.prompts.render(&name, &req.variables) {
+            Ok(mut messages) => {
+                messages.append(&mut req.messages);
+                req.messages = messages;
+                req.variables.clear();
+            }
+            Err(e @ TemplateError::Unknown(_)) => {
+                return map_error_with_code(
+                    404,
+                    &e.to_string(),
+                    CODE_PROMPT_TEMPLATE_NOT_FOUND,
+                    Some("prompt_template"),
+                );
+            }
+            Err(e) => {
+                return map_error_with_code(
+                    400,
+                    &e.to_string(),
+                    "invalid_request",
+                    Some("variables"),
+                );
+            }
+        }
+    }
+
     // Validate request
     if let Err(e) = req.validate() {
         error!("Invalid request: {e}");
         return map_error_with_status(400, &format!("Invalid request: {e}"));
     }
 
-    if is_openai_model(&req.model) {
-        return openai_chat::openai_chat_completions(State(state), Json(req)).await;
+    let mut route_reason = RouteReason::PrefixMatch;
+    let mut avoid_providers = Vec::new();
+    if let Some(rule) = state.routing_rules.evaluate(&req) {
+        info!(
+            "Routing rule {} matched request for {}",
+            rule.name, req.model
+        );
+        if let Some(model) = &rule.model {
+            req.model.clone_from(model);
+        }
+        avoid_providers.clone_from(&rule.avoid_providers);
+        route_reason = RouteReason::Rule;
+    }
+    if let Some(mut targets) = state.model_registry.alias_targets(&req.model) {
+        // Sessions are scoped to the calling key so clients cannot share pins
+        let session = session
+            .as_deref()
+            .filter(|_| state.config.models.sticky_sessions)
+            .map(|s| format!("{}:{s}", key.name));
+        let pinned = match &session {
+            Some(session) => state
+                .affinity
+                .get(session, &req.model)
+                .filter(|target| targets.contains(target)),
+            None => None,
+        };
+        let target = match pinned {
+            Some(target) if routing::is_servable(&target, &state.provider_registry).await => {
+                route_reason = RouteReason::Sticky;
+                target
+            }
+            _ => match routing::select(
+                strategy,
+                &targets,
+                &state.model_registry,
+                &state.provider_registry,
+                &state.latency,
+            )
+            .await
+            {
+                Some(selected) => {
+                    route_reason = strategy.route_reason();
+                    selected
+                }
+                None => targets.swap_remove(0),
+            },
+        };
+        if let Some(session) = session.as_ref().filter(|_| !dry_run) {
+            state.affinity.bind(session, &req.model, &target);
+        }
+        let via = if route_reason == RouteReason::Sticky {
+            "sticky session"
+        } else {
+            strategy.as_str()
+        };
+        info!("Resolved model alias {} to {target} ({via})", req.model);
+        req.model = target;
+    }
+
+    let mut adjustments = state
+        .key_store
+        .param_policy(&key.name)
+        .map(|policy| param_policy::apply(policy, &mut req))
+        .unwrap_or_default();
+    let model_max = state
+        .model_registry
+        .get(&req.model)
+        .map(|model| model.max_output_tokens);
+    adjustments.extend(param_policy::clamp_max_tokens(
+        &mut req,
+        model_max,
+        state.config.limits.max_output_tokens,
+    ));
+    let adjusted = (!adjustments.is_empty()).then(|| {
+        adjustments
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    });
+    if let Some(adjusted) = &adjusted {
+        info!("Clamped parameters for key '{}': {adjusted}", key.name);
+    }
+
+    let requested = req.model.clone();
+    let mut fallbacks = 0;
+    let mut warning = None;
+    let mut response = loop {
+        if let Some(deprecation) = state
+            .model_registry
+            .get(&req.model)
+            .and_then(|model| model.deprecated.as_ref())
+        {
+            let today = chrono::Utc::now().date_naive();
+            let notice = deprecation.notice(&req.model, today);
+            if deprecation.is_retired(today) {
+                warn!("Rejecting request for retired model {}", req.model);
+                break map_error_with_code(410, &notice, CODE_MODEL_RETIRED, Some("model"));
+            }
+            warn!(
+                "Request for deprecated model {} from key '{}'",
+                req.model, key.name
+            );
+            state
+                .metrics
+                .record_deprecated_model_request(&req.model)
+                .await;
+            warning = HeaderValue::from_str(&format!("299 - \"{notice}\"")).ok();
+        }
+
+        let fallback = state
+            .model_registry
+            .context_fallback(&req.model)
+            .filter(|_| fallbacks < MAX_CONTEXT_FALLBACKS)
+            .map(str::to_string);
+        let retry = fallback.as_ref().map(|_| req.clone());
+        let attempt_model = req.model.clone();
+        let attempt_start = std::time::Instant::now();
+        let dispatch = Dispatch {
+            route_reason,
+            avoid_providers: &avoid_providers,
+            session: session.as_deref(),
+            dry_run,
+        };
+        let response = dispatch_chat_completion(state.clone(), key, dispatch, req, cancel).await;
+        debug_trace::record_attempt(
+            &attempt_model,
+            response
+                .headers()
+                .get(X_ROUTED_PROVIDER)
+                .and_then(|v| v.to_str().ok()),
+            response.status().as_u16(),
+            attempt_start,
+        );
+        match (fallback, retry) {
+            (Some(fallback), Some(mut retry))
+                if response.extensions().get::<ContextOverflow>().is_some() =>
+            {
+                info!(
+                    "Prompt too long for {}; retrying on {fallback}",
+                    retry.model
+                );
+                retry.model = fallback;
+                req = retry;
+                fallbacks += 1;
+            }
+            _ => break response,
+        }
+    };
+    if response.extensions().get::<ProviderOutage>().is_some() {
+        let message = state
+            .fallback_responses
+            .for_model(&client_model)
+            .or_else(|| state.fallback_responses.for_model(&requested));
+        if let Some(message) = message {
+            warn!(
+                "Providers unavailable for {client_model} ({}); serving the fallback response",
+                response.status()
+            );
+            response = fallback_response(&client_model, message, stream);
+        }
+    }
+    if let Some(warning) = warning {
+        response.headers_mut().insert(header::WARNING, warning);
+    }
+    if fallbacks > 0 {
+        if let Ok(value) = HeaderValue::from_str(&requested) {
+            response.headers_mut().insert(X_CONTEXT_FALLBACK, value);
+        }
+    }
+    if let Some(value) = adjusted.and_then(|a| HeaderValue::from_str(&a).ok()) {
+        response
+            .headers_mut()
+            .insert(X_PARAMETER_ADJUSTMENTS, value);
+    }
+    response
+}
+
+async fn dispatch_chat_completion(
+    mut state: AppState,
+    key: &AuthenticatedKey,
+    dispatch: Dispatch<'_>,
+    req: ChatCompletionRequest,
+    cancel: &CancellationToken,
+) -> axum::response::Response {
+    let Dispatch {
+        route_reason,
+        avoid_providers,
+        session,
+        dry_run,
+    } = dispatch;
+    let routing_start = std::time::Instant::now();
+    if let Err(e) = model_policy::check(&state.config.model_policy, &key.name, &req.model) {
+        warn!("Rejecting request for key '{}': {e}", key.name);
+        return map_error_with_code(403, &e.to_string(), CODE_MODEL_NOT_ALLOWED, Some("model"));
+    }
+
+    let model_info = state.model_registry.get(&req.model);
+    if let Err(e) = request_limits::check(&state.config.limits, &req, model_info) {
+        warn!("Rejecting request over limits: {e}");
+        let code = match e {
+            LimitExceeded::ContextLength { .. } => CODE_CONTEXT_LENGTH_EXCEEDED,
+            _ => "invalid_request",
+        };
+        let param = match e {
+            LimitExceeded::TooManyPrompts { .. } | LimitExceeded::PromptTooLarge { .. } => {
+                "prompts"
+            }
+            _ => "messages",
+        };
+        let mut response = map_error_with_code(400, &e.to_string(), code, Some(param));
+        if code == CODE_CONTEXT_LENGTH_EXCEEDED {
+            response.extensions_mut().insert(ContextOverflow);
+        }
+        return response;
+    }
+    if let Err(e) = capabilities::check_model(&req, model_info) {
+        warn!("Rejecting request: {e}");
+        return map_error_with_code(
+            400,
+            &e.to_string(),
+            CODE_UNSUPPORTED_CAPABILITY,
+            Some(e.param()),
+        );
+    }
+
+    if key.exempt {
+        debug!("Key '{}' is exempt from spend limits", key.name);
+    } else if let Err(e) = state.budgets.check(&key.name, &state.usage).await {
+        warn!("Rejecting request: {e}");
+        state.notifier.notify(&AlertEvent::BudgetExceeded {
+            key: e.key.clone(),
+            period: e.period.to_string(),
+        });
+        return map_error_with_status(402, &e.to_string());
+    }
+
+    // Vertex calls use the caller's own credentials and project when it has them
+    state.token_manager = state.token_manager.for_key(&key.name);
+
+    let listed_upstream = state
+        .provider_registry
+        .route_by_model(&req.model)
+        .is_some_and(|provider| provider.provider_type() == Provider::OpenAICompatible);
+    if is_openai_model(&req.model) && !listed_upstream {
+        if !req.prompts.is_empty() {
+            return map_error_with_code(
+                400,
+                "prompts are not supported for OpenAI models",
+                "invalid_request",
+                Some("prompts"),
+            );
+        }
+        if dry_run {
+            return plan_response(&state, &req, OPENAI_PROVIDER_NAME, route_reason);
+        }
+        record_routing_decision(&state, OPENAI_PROVIDER_NAME, route_reason).await;
+        debug_trace::record_stage("routing", routing_start);
+        let upstream_start = std::time::Instant::now();
+        let response =
+            openai_chat::openai_chat_completions(State(state), &key.name, session, Json(req)).await;
+        debug_trace::record_stage("upstream", upstream_start);
+        return with_routed_provider(response, OPENAI_PROVIDER_NAME);
     }
 
     let request_start = std::time::Instant::now();
-    let request_id = Uuid::new_v4().to_string();
+    let request_id =
+        TraceContext::current().map_or_else(|| Uuid::new_v4().to_string(), |ctx| ctx.request_id);
     let span = tracing::span!(
         tracing::Level::INFO,
         "chat_completions",
         request_id = %request_id,
         model = %req.model,
-        stream = req.stream
+        stream = req.stream,
+        provider = tracing::field::Empty,
+        route_reason = tracing::field::Empty
     );
     let _guard = span.enter();
     info!(
@@ -118,51 +858,197 @@ pub async fn chat_completions(
         request_id, req.model, req.stream
     );
 
-    let Some(provider) = state.provider_registry.route_by_model(&req.model) else {
+    let decision = match state
+        .provider_registry
+        .route_avoiding(&req.model, avoid_providers)
+    {
+        Some(decision) => Some(decision),
+        None if !avoid_providers.is_empty() => {
+            warn!(
+                "Only avoided providers serve {}; ignoring the routing rule",
+                req.model
+            );
+            state.provider_registry.route(&req.model)
+        }
+        None => None,
+    };
+    let Some(decision) = decision else {
         error!("No provider found for model: {}", req.model);
-        return map_error_with_status(400, &format!("Unsupported model: {}", req.model));
+        return map_error_with_code(
+            404,
+            &format!("The model '{}' does not exist", req.model),
+            CODE_MODEL_NOT_FOUND,
+            Some("model"),
+        );
+    };
+    let provider = decision.provider;
+    let provider_name = provider.provider_type().name();
+    if let Err(e) = capabilities::check_provider(&req, provider_name, provider.supports_tools()) {
+        warn!("Rejecting request: {e}");
+        return map_error_with_code(
+            400,
+            &e.to_string(),
+            CODE_UNSUPPORTED_CAPABILITY,
+            Some(e.param()),
+        );
+    }
+    let reason = match route_reason {
+        RouteReason::PrefixMatch => decision.reason,
+        chosen => chosen,
+    };
+    if dry_run {
+        return plan_response(&state, &req, provider_name, reason);
+    }
+    span.record("provider", provider_name);
+    span.record("route_reason", reason.as_str());
+    record_routing_decision(&state, provider_name, reason).await;
+    debug_trace::record_stage("routing", routing_start);
+
+    let response = execute_routed(&state, key, provider, req, request_start, cancel).await;
+    with_routed_provider(response, provider_name)
+}
+
+/// The plan for a dry run of `req` on `provider`.
+fn plan_response(
+    state: &AppState,
+    req: &ChatCompletionRequest,
+    provider: &'static str,
+    reason: RouteReason,
+) -> axum::response::Response {
+    let prompt_tokens = u32::try_from(tokenizer::prompt_tokens(req)).unwrap_or(u32::MAX);
+    let max_cost_usd = state.model_registry.get(&req.model).map(|model| {
+        model.pricing.cost(
+            prompt_tokens,
+            req.max_tokens.unwrap_or(model.max_output_tokens),
+        )
+    });
+    info!("Dry run of {} planned on {provider}", req.model);
+    let plan = ChatCompletionPlan {
+        object: "chat.completion.plan".to_string(),
+        model: req.model.clone(),
+        provider: provider.to_string(),
+        route_reason: reason.as_str().to_string(),
+        stream: req.stream,
+        tokenizer: tokenizer::for_model(&req.model).name().to_string(),
+        prompt_tokens,
+        max_tokens: req.max_tokens,
+        max_cost_usd,
     };
+    with_routed_provider(Json(plan).into_response(), provider)
+}
+
+async fn record_routing_decision(state: &AppState, provider: &str, reason: RouteReason) {
+    log_context::record("provider", provider);
+    debug_trace::record_route(provider, reason.as_str());
+    info!("Routed to provider {provider} ({})", reason.as_str());
+    state
+        .metrics
+        .record_routing_decision(provider, reason.as_str())
+        .await;
+}
 
+fn with_routed_provider(
+    mut response: axum::response::Response,
+    provider: &'static str,
+) -> axum::response::Response {
+    response
+        .headers_mut()
+        .insert(X_ROUTED_PROVIDER, HeaderValue::from_static(provider));
+    response
+}
+
+async fn execute_routed(
+    state: &AppState,
+    key: &AuthenticatedKey,
+    provider: &dyn LLMProvider,
+    req: ChatCompletionRequest,
+    request_start: std::time::Instant,
+    cancel: &CancellationToken,
+) -> axum::response::Response {
+    let original = state.post_processor.is_enabled().then(|| req.clone());
+    let mirrored = state.mirror.sample().then(|| req.clone());
     if req.stream {
-        let stream_result = provider.execute_stream(req, &state).await;
-
-        let stream = match stream_result {
-            Ok(provider_stream) => provider_stream.map(move |chunk_result| match chunk_result {
-                Ok(chunk_data) => Ok::<Event, Infallible>(parse_sse_chunk(&chunk_data)),
-                Err(e) => {
-                    error!("Provider stream error: {}", e);
-                    let error_chunk = serde_json::json!({
-                        "error": {
-                            "message": format!("Stream error: {}", e),
-                            "type": "stream_error",
-                            "code": "stream_failed"
-                        }
-                    });
-                    match Event::default().json_data(error_chunk) {
-                        Ok(event) => Ok::<Event, Infallible>(event),
-                        Err(_) => Ok::<Event, Infallible>(
-                            Event::default().comment(format!("error: stream failed: {e}")),
-                        ),
-                    }
-                }
-            }),
+        let include_usage = req.include_usage();
+        let metered = MeteredRequest {
+            key_name: key.name.clone(),
+            model: req.model.clone(),
+            prompt_tokens: tokenizer::prompt_tokens(&req),
+            include_usage,
+        };
+        let framing = sse::Framing::new(&state.config.sse, &req.model);
+        debug_trace::record_cache(CacheResult::Bypass);
+        let upstream_start = std::time::Instant::now();
+        let provider_stream = provider.execute_stream(req, state, cancel).await;
+        debug_trace::record_stage("upstream", upstream_start);
+        let provider_stream = match provider_stream {
+            Ok(provider_stream) => {
+                // Cancels the token once the stream ends or the client goes away
+                let done = cancel.clone().drop_guard();
+                let provider_stream =
+                    providers::cancellable_stream(provider_stream, cancel.clone());
+                Box::pin(provider_stream.map(move |chunk| {
+                    let _ = &done;
+                    chunk
+                }))
+            }
             Err(e) => {
                 error!("Provider execution error: {}", e);
-                let status = map_provider_error_to_status(&e);
                 state.metrics.record_request(false).await;
-                return map_error_with_status(status, &e.to_string());
+                return provider_error_response(&e, &state.config);
             }
         };
+        let provider_stream = meter_stream(state.clone(), metered, provider_stream);
+        let provider_stream = match mirrored {
+            Some(request) => mirror_stream(
+                state.clone(),
+                key.name.clone(),
+                provider.provider_type().name(),
+                request,
+                request_start,
+                provider_stream,
+            ),
+            None => provider_stream,
+        };
 
-        // Note: Metrics for streaming requests are recorded when stream is created
-        // Full stream completion metrics would require consuming the stream, which isn't feasible
-        // For accurate metrics, consider using a wrapper stream that records on completion
-        return Sse::new(stream)
-            .keep_alive(axum::response::sse::KeepAlive::default())
+        let keep_alive = framing.keep_alive();
+        if let Some(original) = original.filter(|_| state.post_processor.applies_to_streams()) {
+            let events = post_process_stream(state.clone(), original, provider_stream);
+            return Sse::new(sse::with_error_contract(
+                events,
+                state.metrics.clone(),
+                request_start,
+                framing,
+            ))
+            .keep_alive(keep_alive)
             .into_response();
+        }
+        let events: EventStream = if framing.is_strict() {
+            strict_events(provider_stream)
+        } else {
+            Box::pin(
+                provider_stream
+                    .map(|chunk_result| chunk_result.map(|chunk| parse_sse_chunk(&chunk))),
+            )
+        };
+        return Sse::new(sse::with_error_contract(
+            events,
+            state.metrics.clone(),
+            request_start,
+            framing,
+        ))
+        .keep_alive(keep_alive)
+        .into_response();
     }
 
-    match provider.execute(req, &state).await {
+    let model = req.model.clone();
+    let upstream_start = std::time::Instant::now();
+    let result = if req.prompts.is_empty() {
+        execute_coalesced(state, key, provider, req, cancel).await
+    } else {
+        execute_fan_out(state, key, provider, &req, cancel).await
+    };
+    debug_trace::record_stage("upstream", upstream_start);
+    match result {
         Ok(response) => {
             // Fix: Prevent overflow when converting duration to milliseconds
             let duration_ms = u64::try_from(
@@ -174,25 +1060,402 @@ pub async fn chat_completions(
             .unwrap_or(u64::MAX);
             state.metrics.record_request(true).await;
             state.metrics.record_request_duration(duration_ms).await;
-            Json(response).into_response()
+            state
+                .latency
+                .record(provider.provider_type().name(), &model, duration_ms);
+            if let Some(usage) = &response.usage {
+                state
+                    .usage
+                    .record(&key.name, &response.model, usage, &state.model_registry)
+                    .await;
+            }
+            if let Some(request) = mirrored {
+                match serde_json::to_value(&response) {
+                    Ok(body) => state.mirror.publish(
+                        &key.name,
+                        provider.provider_type().name(),
+                        duration_ms,
+                        &request,
+                        body,
+                    ),
+                    Err(e) => warn!("Failed to serialize mirrored response: {e}"),
+                }
+            }
+            let Some(original) = original else {
+                return Json(response).into_response();
+            };
+            let body = match serde_json::to_value(&response) {
+                Ok(body) => body,
+                Err(e) => return map_error_with_status(500, &format!("Serialization error: {e}")),
+            };
+            let post_processing_start = std::time::Instant::now();
+            let processed = state.post_processor.apply(&original, body).await;
+            debug_trace::record_stage("post_processing", post_processing_start);
+            match processed {
+                Ok(body) => Json(body).into_response(),
+                Err(e) => post_processing_error_response(&e),
+            }
         }
         Err(e) => {
             error!("Provider execution error: {}", e);
-            let status = map_provider_error_to_status(&e);
             state.metrics.record_request(false).await;
-            map_error_with_status(status, &e.to_string())
+            provider_error_response(&e, &state.config)
+        }
+    }
+}
+
+/// The request a metered stream answers.
+struct MeteredRequest {
+    key_name: String,
+    model: String,
+    prompt_tokens: usize,
+    include_usage: bool,
+}
+
+/// Records the usage a provider reports on a stream's final chunk, and strips
+/// it from the chunk unless the client set `stream_options.include_usage`.
+/// When the stream ends without any, usage estimated from the streamed text
+/// is recorded instead.
+fn meter_stream(
+    state: AppState,
+    request: MeteredRequest,
+    provider_stream: StreamingResponse,
+) -> StreamingResponse {
+    // Tokens of streamed content, until the provider reports usage
+    let completion_tokens = Arc::new(std::sync::Mutex::new(Some(0usize)));
+    let counted = completion_tokens.clone();
+    let model = request.model.clone();
+    let metering = state.clone();
+    let key_name = request.key_name.clone();
+    let include_usage = request.include_usage;
+    let provider_stream = provider_stream.map(move |chunk| {
+        let chunk = chunk?;
+        if let Ok(mut tokens) = counted.lock() {
+            if let Some(tokens) = tokens.as_mut() {
+                *tokens += streamed_tokens(&model, &chunk);
+            }
+        }
+        if !chunk.contains("\"usage\"") {
+            return Ok(chunk);
+        }
+        Ok(finish_reason::rewrite_sse_chunk(&chunk, |event| {
+            let Some(usage) = event.get("usage").filter(|usage| !usage.is_null()) else {
+                return false;
+            };
+            match serde_json::from_value::<Usage>(usage.clone()) {
+                Ok(usage) => {
+                    if let Ok(mut tokens) = counted.lock() {
+                        *tokens = None;
+                    }
+                    let state = metering.clone();
+                    let key_name = key_name.clone();
+                    let model = event["model"].as_str().unwrap_or_default().to_string();
+                    // Recorded right away so a client leaving early is still billed
+                    tokio::spawn(async move {
+                        state
+                            .usage
+                            .record(&key_name, &model, &usage, &state.model_registry)
+                            .await;
+                    });
+                }
+                Err(e) => warn!("Ignoring malformed stream usage: {e}"),
+            }
+            !include_usage
+                && event
+                    .as_object_mut()
+                    .and_then(|event| event.remove("usage"))
+                    .is_some()
+        }))
+    });
+    let estimate = futures::stream::once(async move {
+        let tokens = completion_tokens
+            .lock()
+            .ok()
+            .and_then(|mut guard| guard.take());
+        if let Some(completion_tokens) = tokens {
+            debug!(
+                "Provider reported no stream usage for {}; estimating",
+                request.model
+            );
+            let usage = tokenizer::estimate_usage(request.prompt_tokens, completion_tokens);
+            state
+                .usage
+                .record(
+                    &request.key_name,
+                    &request.model,
+                    &usage,
+                    &state.model_registry,
+                )
+                .await;
+        }
+    })
+    .filter_map(|()| futures::future::ready(None));
+    Box::pin(provider_stream.chain(estimate))
+}
+
+/// Characters of delta content in the `data:` lines of an SSE chunk.
+fn streamed_tokens(model: &str, chunk: &str) -> usize {
+    chunk
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<ChatCompletionChunk>(data).ok())
+        .flat_map(|chunk| chunk.choices)
+        .filter_map(|choice| choice.delta.content)
+        .map(|content| tokenizer::count(model, &content))
+        .sum()
+}
+
+/// Passes a stream through unchanged, mirroring the reassembled completion
+/// once it finishes. Streams that fail or are abandoned are not mirrored.
+fn mirror_stream(
+    state: AppState,
+    key_name: String,
+    provider: &'static str,
+    request: ChatCompletionRequest,
+    request_start: std::time::Instant,
+    provider_stream: StreamingResponse,
+) -> StreamingResponse {
+    let chunks = Arc::new(std::sync::Mutex::new(Some(Vec::new())));
+    let collected = chunks.clone();
+    let provider_stream = provider_stream.inspect(move |chunk| {
+        if let Ok(mut guard) = collected.lock() {
+            match chunk {
+                Ok(chunk) => {
+                    if let Some(chunks) = guard.as_mut() {
+                        chunks.push(chunk.clone());
+                    }
+                }
+                Err(_) => *guard = None,
+            }
+        }
+    });
+    let publish = futures::stream::once(async move {
+        let chunks = chunks.lock().ok().and_then(|mut guard| guard.take());
+        if let Some(chunks) = chunks {
+            let latency_ms = u64::try_from(request_start.elapsed().as_millis()).unwrap_or(u64::MAX);
+            let response = post_processor::reassemble_stream(&chunks);
+            state
+                .mirror
+                .publish(&key_name, provider, latency_ms, &request, response);
+        }
+    })
+    .filter_map(|()| futures::future::ready(None));
+    Box::pin(provider_stream.chain(publish))
+}
+
+/// Buffers a stream so the post-processing webhook sees the whole completion,
+/// then replays the processed result as one chunk followed by `[DONE]`.
+fn post_process_stream(
+    state: AppState,
+    request: ChatCompletionRequest,
+    mut provider_stream: StreamingResponse,
+) -> impl futures::Stream<Item = Result<Event, String>> + Send {
+    futures::stream::once(async move {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = provider_stream.next().await {
+            chunks.push(chunk.map_err(|e| e.to_string())?);
+        }
+        let response = post_processor::reassemble_stream(&chunks);
+        let processed = state
+            .post_processor
+            .apply(&request, response)
+            .await
+            .map_err(|e| e.to_string())?;
+        let chunk = Event::default()
+            .json_data(post_processor::into_chunk(processed))
+            .map_err(|e| format!("Failed to serialize post-processed chunk: {e}"))?;
+        Ok(vec![chunk, sse::done_event()])
+    })
+    .flat_map(|events: Result<Vec<Event>, String>| {
+        futures::stream::iter(match events {
+            Ok(events) => events.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        })
+    })
+}
+
+fn post_processing_error_response(error: &PostProcessError) -> axum::response::Response {
+    map_error_with_code(502, &error.to_string(), CODE_POST_PROCESSING_FAILED, None)
+}
+
+/// Runs a non-streaming completion, waiting on an identical request that is
+/// already in flight instead of calling the provider again. With a private
+/// cache only requests from the same key are coalesced.
+async fn execute_coalesced(
+    state: &AppState,
+    key: &AuthenticatedKey,
+    provider: &dyn LLMProvider,
+    req: ChatCompletionRequest,
+    cancel: &CancellationToken,
+) -> Result<ChatCompletionResponse, Arc<ProviderError>> {
+    let prompt_tokens = tokenizer::prompt_tokens(&req);
+    let model = req.model.clone();
+    let key = match state.cache.scoped_key(&key.name, &req) {
+        Ok(key) if state.config.cache.coalesce_requests => key,
+        _ => {
+            debug_trace::record_cache(CacheResult::Bypass);
+            return provider
+                .execute(req, state, cancel)
+                .await
+                .map(|response| with_estimated_usage(response, &model, prompt_tokens))
+                .map_err(Arc::new);
         }
+    };
+    let (result, coalesced) = state
+        .in_flight
+        .run(key, || async move {
+            provider.execute(req, state, cancel).await.map_err(Arc::new)
+        })
+        .await;
+    if coalesced {
+        state.metrics.record_coalesced_request().await;
+    }
+    debug_trace::record_cache(if coalesced {
+        CacheResult::Coalesced
+    } else {
+        CacheResult::Miss
+    });
+    result.map(|response| with_estimated_usage(response, &model, prompt_tokens))
+}
+
+/// Fills in usage estimated from the text when the provider reported none.
+fn with_estimated_usage(
+    mut response: ChatCompletionResponse,
+    model: &str,
+    prompt_tokens: usize,
+) -> ChatCompletionResponse {
+    if response.usage.is_none() {
+        let completion_tokens = response
+            .choices
+            .iter()
+            .map(|choice| tokenizer::count(model, &choice.message.content))
+            .sum();
+        debug!(
+            "Provider reported no usage for {}; estimating",
+            response.model
+        );
+        response.usage = Some(tokenizer::estimate_usage(prompt_tokens, completion_tokens));
     }
+    response
 }
 
-fn map_provider_error_to_status(error: &ProviderError) -> u16 {
+/// Runs a `prompts` request as one completion per prompt, at most
+/// `limits.max_concurrent_prompts` at a time, and merges them into a single
+/// response whose choices follow prompt order.
+///
+/// If any prompt fails the whole request fails with that error; the usage of
+/// prompts that did complete is still recorded, as it was spent.
+async fn execute_fan_out(
+    state: &AppState,
+    key: &AuthenticatedKey,
+    provider: &dyn LLMProvider,
+    req: &ChatCompletionRequest,
+    cancel: &CancellationToken,
+) -> Result<ChatCompletionResponse, Arc<ProviderError>> {
+    info!(
+        "Fanning out {} prompts for {} ({} at a time)",
+        req.prompts.len(),
+        req.model,
+        state.config.limits.max_concurrent_prompts
+    );
+    let results: Vec<_> = futures::stream::iter(req.fan_out())
+        .map(|prompt| execute_coalesced(state, key, provider, prompt, cancel))
+        .buffered(state.config.limits.max_concurrent_prompts)
+        .collect()
+        .await;
+
+    let mut merged: Option<ChatCompletionResponse> = None;
+    let mut failure = None;
+    for result in results {
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                failure.get_or_insert(e);
+                continue;
+            }
+        };
+        let Some(merged) = merged.as_mut() else {
+            merged = Some(response);
+            continue;
+        };
+        let next_index = u32::try_from(merged.choices.len()).unwrap_or(u32::MAX);
+        merged
+            .choices
+            .extend(response.choices.into_iter().enumerate().map(|(i, mut c)| {
+                c.index = next_index.saturating_add(u32::try_from(i).unwrap_or(u32::MAX));
+                c
+            }));
+        merged.usage = match (merged.usage.take(), response.usage) {
+            (Some(total), Some(usage)) => Some(total.add(&usage)),
+            (total, usage) => total.or(usage),
+        };
+    }
+    match (failure, merged) {
+        (None, Some(merged)) => Ok(merged),
+        (Some(e), merged) => {
+            if let Some(usage) = merged.as_ref().and_then(|m| m.usage.as_ref()) {
+                state
+                    .usage
+                    .record(&key.name, &req.model, usage, &state.model_registry)
+                    .await;
+            }
+            Err(e)
+        }
+        (None, None) => Err(Arc::new(ProviderError::InvalidRequest(
+            "prompts cannot be empty".to_string(),
+        ))),
+    }
+}
+
+/// The configured fallback `message` as a completion (or a one-chunk stream)
+/// from `model`.
+fn fallback_response(model: &str, message: &str, stream: bool) -> axum::response::Response {
+    let mut response = if stream {
+        let chunk = Event::default()
+            .json_data(fallback_responses::chunk(model, message))
+            .unwrap_or_else(|e| sse::error_event(&format!("Failed to serialize chunk: {e}")));
+        let events = [chunk, sse::done_event()].map(Ok::<_, std::convert::Infallible>);
+        Sse::new(futures::stream::iter(events)).into_response()
+    } else {
+        Json(fallback_responses::completion(model, message)).into_response()
+    };
+    response
+        .headers_mut()
+        .insert(X_FALLBACK_RESPONSE, HeaderValue::from_static("true"));
+    response
+}
+
+/// Network failures, timeouts, upstream 5xx and an open circuit: the
+/// provider is down rather than the request being wrong.
+fn is_outage(error: &ProviderError) -> bool {
     match error {
-        ProviderError::Auth(_) => 401,
-        ProviderError::Network(_) => 502,
-        ProviderError::Unavailable(_) | ProviderError::CircuitOpen(_) => 503,
-        ProviderError::Timeout(_) => 504,
-        ProviderError::InvalidRequest(_) => 400,
-        ProviderError::RateLimited(_) => 429,
-        ProviderError::Internal(_) => 500,
+        ProviderError::Network(_)
+        | ProviderError::Unavailable(_)
+        | ProviderError::Timeout(_)
+        | ProviderError::CircuitOpen(_) => true,
+        ProviderError::Upstream { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
+fn provider_error_response(error: &ProviderError, config: &AppConfig) -> axum::response::Response {
+    let message = error.to_string();
+    let detail = error
+        .detail()
+        .filter(|_| config.errors.provider_detail)
+        .map(|detail| redaction::redact_value(&config.redaction, detail));
+    let mut response = map_error_with_detail(error.status(), &message, detail.as_ref());
+    if error.status() == 400 && is_context_length_error(&message) {
+        response.extensions_mut().insert(ContextOverflow);
+    }
+    if is_outage(error) {
+        response.extensions_mut().insert(ProviderOutage);
+    }
+    if let Some(retry_after) = error.retry_after() {
+        response.headers_mut().insert(
+            axum::http::header::RETRY_AFTER,
+            axum::http::HeaderValue::from(retry_after.as_secs()),
+        );
     }
+    response
 }
@@ -0,0 +1,100 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::error;
+
+use crate::openai::errors::{
+    map_error_with_code, map_error_with_status, OpenAIError, CODE_CONVERSATION_NOT_FOUND,
+};
+use crate::services::keys::AuthenticatedKey;
+use crate::services::transcripts::{self, Transcript};
+use crate::state::AppState;
+
+fn not_found(id: &str) -> Response {
+    map_error_with_code(
+        404,
+        &format!("No conversation '{id}'"),
+        CODE_CONVERSATION_NOT_FOUND,
+        None,
+    )
+}
+
+/// The error to send instead of looking up `id`: transcripts are off, or
+/// `id` is not a valid conversation ID.
+fn rejection(state: &AppState, id: &str) -> Option<Response> {
+    if !state.transcripts.is_enabled() {
+        return Some(map_error_with_code(
+            404,
+            "Conversation transcripts are not enabled",
+            CODE_CONVERSATION_NOT_FOUND,
+            None,
+        ));
+    }
+    transcripts::validate_id(id)
+        .err()
+        .map(|e| map_error_with_status(400, &e))
+}
+
+/// `GET /v1/conversations/:id`: the calling key's transcript of a session.
+#[utoipa::path(
+    get,
+    path = "/v1/conversations/{id}",
+    tag = "openai",
+    params(("id" = String, Path, description = "Session ID sent in the session header")),
+    responses(
+        (status = 200, description = "The conversation's messages, including assistant replies", body = Transcript),
+        (status = 404, description = "Unknown conversation, or transcripts disabled", body = OpenAIError)
+    )
+)]
+pub async fn get_conversation(
+    State(state): State<AppState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(response) = rejection(&state, &id) {
+        return response;
+    }
+    let key = key.map_or_else(AuthenticatedKey::anonymous, |Extension(k)| k);
+    match state.transcripts.get(&key.name, &id).await {
+        Ok(Some(transcript)) => Json(transcript).into_response(),
+        Ok(None) => not_found(&id),
+        Err(e) => {
+            error!("Failed to read conversation {id}: {e:#}");
+            map_error_with_status(500, "Failed to read conversation")
+        }
+    }
+}
+
+/// `DELETE /v1/conversations/:id`: forgets the calling key's transcript of a
+/// session.
+#[utoipa::path(
+    delete,
+    path = "/v1/conversations/{id}",
+    tag = "openai",
+    params(("id" = String, Path, description = "Session ID sent in the session header")),
+    responses(
+        (status = 204, description = "Conversation deleted"),
+        (status = 404, description = "Unknown conversation, or transcripts disabled", body = OpenAIError)
+    )
+)]
+pub async fn delete_conversation(
+    State(state): State<AppState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(response) = rejection(&state, &id) {
+        return response;
+    }
+    let key = key.map_or_else(AuthenticatedKey::anonymous, |Extension(k)| k);
+    match state.transcripts.delete(&key.name, &id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => not_found(&id),
+        Err(e) => {
+            error!("Failed to delete conversation {id}: {e:#}");
+            map_error_with_status(500, "Failed to delete conversation")
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use axum::{extract::State, response::IntoResponse, Json};
 use reqwest::Client;
+use serde::Serialize;
 use serde_json::json;
 use std::time::Duration;
 use tracing::{error, warn};
+use utoipa::ToSchema;
 
 use crate::openai::harvester::HarvesterClient;
+use crate::services::warmup::WarmupReport;
 use crate::state::AppState;
 
 const HEALTH_CHECK_TIMEOUT_SECS: u64 = 2;
@@ -104,6 +107,16 @@ async fn check_anthropic_bridge_health(bridge_url: &str) -> serde_json::Value {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "Every dependency is reachable", body = Object),
+        (status = 503, description = "A dependency is unavailable", body = Object)
+    )
+)]
 pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
     let harvester_status = check_harvester_health(&state.config).await;
     let anthropic_bridge_status =
@@ -118,7 +131,10 @@ pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
         .and_then(serde_json::Value::as_bool)
         .unwrap_or(false);
 
-    let overall_status = if harvester_available && bridge_available {
+    let provider_health = state.provider_registry.health_snapshot().await;
+    let probes_available = provider_health.iter().all(|(_, health)| health.available);
+
+    let overall_status = if harvester_available && bridge_available && probes_available {
         "ok"
     } else if !harvester_available && !bridge_available {
         "unhealthy"
@@ -138,12 +154,60 @@ pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
             axum::http::header::CACHE_CONTROL,
             axum::http::HeaderValue::from_static(CACHE_CONTROL_NO_CACHE),
         )],
-        Json(json!({
-            "status": overall_status,
-            "version": env!("CARGO_PKG_VERSION"),
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-            "harvester": harvester_status,
-            "anthropic_bridge": anthropic_bridge_status
-        })),
+        Json({
+            let mut body = json!({
+                "status": overall_status,
+                "version": env!("CARGO_PKG_VERSION"),
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "harvester": harvester_status,
+                "anthropic_bridge": anthropic_bridge_status
+            });
+            for (provider, health) in provider_health {
+                body[provider.name()] = json!(health);
+            }
+            body
+        }),
+    )
+}
+
+/// Body of `/health/ready`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Outcome of the startup warm-up, once it has run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<WarmupReport>,
+}
+
+/// `GET /health/ready`: 503 until the startup warm-up has finished (it is
+/// skipped when not enabled), then 200 whether or not its steps succeeded.
+/// Unlike `/health` it contacts no upstream.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "Ready to serve; includes the warm-up outcome", body = Readiness),
+        (status = 503, description = "Startup warm-up still running", body = Readiness)
+    )
+)]
+pub async fn readiness(State(state): State<AppState>) -> impl IntoResponse {
+    let ready = state.warmup.is_ready().await;
+    let status_code = if ready {
+        axum::http::StatusCode::OK
+    } else {
+        axum::http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status_code,
+        [(
+            axum::http::header::CACHE_CONTROL,
+            axum::http::HeaderValue::from_static(CACHE_CONTROL_NO_CACHE),
+        )],
+        Json(Readiness {
+            ready,
+            warmup: state.warmup.report().await,
+        }),
     )
 }
@@ -0,0 +1,120 @@
+// Keeps slow non-streaming completions from being cut off as idle.
+//
+// Load balancers and proxies commonly drop connections that carry no bytes
+// for 60 seconds, which a long generation on a pro model easily exceeds.
+// Streaming clients get SSE keep-alive comments; a non-streaming client
+// waits on a silent socket. `respond` therefore sends the status and headers
+// once the completion has run for `keepalive.after_secs`, then trickles
+// spaces until the JSON follows. Leading whitespace is valid JSON, so clients
+// parse the body unchanged. The padded response is marked `SkipCompression`:
+// a gzip encoder would hold the single-byte writes until its block fills, and
+// the connection would look idle all the same.
+//
+// `102 Processing` would say the same without touching the body, but hyper
+// cannot send informational responses and most intermediaries drop them.
+//
+// Once the 200 is out, the completion's own status and headers are lost: an
+// error arrives as the OpenAI error envelope under a 200.
+
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderValue};
+use axum::response::Response;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::config::KeepaliveConfig;
+use crate::middleware::compression::SkipCompression;
+
+// Sent every interval while the completion is running
+const PADDING: &[u8] = b" ";
+
+/// Resolves `response`, switching to a padded `200 OK` if it takes longer
+/// than `config.after_secs`.
+pub async fn respond<F>(config: &KeepaliveConfig, response: F) -> Response
+where
+    F: Future<Output = Response> + Send + 'static,
+{
+    if config.after_secs == 0 {
+        return response.await;
+    }
+    let mut response = Box::pin(response);
+    tokio::select! {
+        response = &mut response => return response,
+        () = tokio::time::sleep(Duration::from_secs(config.after_secs)) => {}
+    }
+    debug!(
+        "Completion still running after {}s, keeping the connection alive",
+        config.after_secs
+    );
+    let interval = Duration::from_secs(config.interval_secs);
+    let body = stream::unfold(Some(response), move |pending| async move {
+        let mut pending = pending?;
+        tokio::select! {
+            response = &mut pending => {
+                Some((response.into_body().into_data_stream().left_stream(), None))
+            }
+            () = tokio::time::sleep(interval) => {
+                let padding = stream::once(future::ready(Ok(Bytes::from_static(PADDING))));
+                Some((padding.right_stream(), Some(pending)))
+            }
+        }
+    })
+    .flatten();
+    let mut response = Response::new(Body::from_stream(body));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response.extensions_mut().insert(SkipCompression);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use axum::Json;
+    use serde_json::{json, Value};
+
+    fn config(after_secs: u64) -> KeepaliveConfig {
+        KeepaliveConfig {
+            after_secs,
+            interval_secs: 1,
+        }
+    }
+
+    async fn slow(delay: Duration, response: Response) -> Response {
+        tokio::time::sleep(delay).await;
+        response
+    }
+
+    #[tokio::test]
+    async fn test_fast_completion_is_returned_unchanged() {
+        let mut completion = Json(json!({"error": "busy"})).into_response();
+        *completion.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+        let response = respond(&config(5), async { completion }).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_slow_completion_is_padded_with_whitespace() {
+        let mut completion = Json(json!({"error": "upstream"})).into_response();
+        *completion.status_mut() = StatusCode::BAD_GATEWAY;
+        let response = respond(&config(1), slow(Duration::from_millis(2500), completion)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert!(response.extensions().get::<SkipCompression>().is_some());
+
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        assert!(body.starts_with(PADDING));
+        let json: Value = serde_json::from_slice(&body).expect("padded body should be JSON");
+        assert_eq!(json, json!({"error": "upstream"}));
+    }
+}
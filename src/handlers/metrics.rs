@@ -1,4 +1,5 @@
 use crate::openai::metrics::MetricsStats;
+use crate::services::slo::SloStatus;
 use crate::state::AppState;
 use axum::{
     extract::State,
@@ -61,6 +62,12 @@ fn validate_metric_value(value: f64) -> f64 {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses((status = 200, description = "Request, cache and provider metrics", body = Object))
+)]
 pub async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
     let metrics_data = state.metrics.get_stats().await;
     (
@@ -155,6 +162,11 @@ fn create_metric_definitions(
             "Total number of failed requests",
             stats.failed_requests,
         ),
+        create_counter_metric(
+            "requests_coalesced_total",
+            "Requests answered by sharing an identical in-flight upstream call",
+            stats.coalesced_requests,
+        ),
         create_gauge_metric(
             "request_success_rate",
             "Request success rate percentage",
@@ -223,6 +235,130 @@ fn build_prometheus_output(metric_definitions: &[(&str, &str, &str, String)]) ->
     prom_output
 }
 
+fn format_routing_decisions(stats: &MetricsStats) -> String {
+    let mut output = String::from(
+        "# HELP routing_decisions_total Requests routed per provider and routing reason\n# TYPE routing_decisions_total counter\n",
+    );
+    for decision in &stats.routing_decisions {
+        output.push_str(&format!(
+            "routing_decisions_total{{provider=\"{}\",reason=\"{}\"}} {}\n",
+            validate_metric_name(&decision.provider),
+            validate_metric_name(&decision.reason),
+            decision.count
+        ));
+    }
+    output
+}
+
+fn format_experiment_variants(stats: &MetricsStats) -> String {
+    let mut requests = String::from(
+        "# HELP experiment_requests_total Requests per experiment variant and outcome\n# TYPE experiment_requests_total counter\n",
+    );
+    let mut latency = String::from(
+        "# HELP experiment_latency_avg_ms Average request latency per experiment variant in milliseconds\n# TYPE experiment_latency_avg_ms gauge\n",
+    );
+    for variant in &stats.experiment_variants {
+        let labels = format!(
+            "experiment=\"{}\",variant=\"{}\"",
+            validate_metric_name(&variant.experiment),
+            validate_metric_name(&variant.variant)
+        );
+        requests.push_str(&format!(
+            "experiment_requests_total{{{labels},outcome=\"success\"}} {}\n",
+            variant.requests - variant.failed_requests
+        ));
+        requests.push_str(&format!(
+            "experiment_requests_total{{{labels},outcome=\"failure\"}} {}\n",
+            variant.failed_requests
+        ));
+        latency.push_str(&format!(
+            "experiment_latency_avg_ms{{{labels}}} {:.2}\n",
+            validate_metric_value(variant.avg_latency_ms)
+        ));
+    }
+    requests + &latency
+}
+
+fn format_upstream_traffic(stats: &MetricsStats) -> String {
+    let mut requests = String::from(
+        "# HELP upstream_requests_total Calls sent to each provider's upstream API\n# TYPE upstream_requests_total counter\n",
+    );
+    let mut request_bytes = String::from(
+        "# HELP upstream_request_bytes_total Request body bytes sent to each provider\n# TYPE upstream_request_bytes_total counter\n",
+    );
+    let mut response_bytes = String::from(
+        "# HELP upstream_response_bytes_total Response body bytes received from each provider\n# TYPE upstream_response_bytes_total counter\n",
+    );
+    for traffic in &stats.upstream_traffic {
+        let provider = validate_metric_name(&traffic.provider);
+        requests.push_str(&format!(
+            "upstream_requests_total{{provider=\"{provider}\"}} {}\n",
+            traffic.requests
+        ));
+        request_bytes.push_str(&format!(
+            "upstream_request_bytes_total{{provider=\"{provider}\"}} {}\n",
+            traffic.request_bytes
+        ));
+        response_bytes.push_str(&format!(
+            "upstream_response_bytes_total{{provider=\"{provider}\"}} {}\n",
+            traffic.response_bytes
+        ));
+    }
+    requests + &request_bytes + &response_bytes
+}
+
+fn format_deprecated_model_requests(stats: &MetricsStats) -> String {
+    let mut output = String::from(
+        "# HELP deprecated_model_requests_total Requests for models marked deprecated\n# TYPE deprecated_model_requests_total counter\n",
+    );
+    for (model, count) in &stats.deprecated_model_requests {
+        let model = validate_metric_name(model);
+        output.push_str(&format!(
+            "deprecated_model_requests_total{{model=\"{model}\"}} {count}\n"
+        ));
+    }
+    output
+}
+
+fn format_slos(slos: &[SloStatus]) -> String {
+    let mut target = String::from(
+        "# HELP slo_target_ratio Share of requests per model that must be good\n# TYPE slo_target_ratio gauge\n",
+    );
+    let mut requests = String::from(
+        "# HELP slo_window_requests Requests per model and outcome within the SLO window\n# TYPE slo_window_requests gauge\n",
+    );
+    let mut compliance = String::from(
+        "# HELP slo_compliance_ratio Share of good requests per model within the SLO window\n# TYPE slo_compliance_ratio gauge\n",
+    );
+    let mut burn_rate = String::from(
+        "# HELP slo_burn_rate Rate at which each model spends its error budget; 1 spends it exactly\n# TYPE slo_burn_rate gauge\n",
+    );
+    for slo in slos {
+        let model = validate_metric_name(&slo.model);
+        target.push_str(&format!(
+            "slo_target_ratio{{model=\"{model}\"}} {}\n",
+            slo.target
+        ));
+        requests.push_str(&format!(
+            "slo_window_requests{{model=\"{model}\",outcome=\"good\"}} {}\n",
+            slo.good_requests
+        ));
+        requests.push_str(&format!(
+            "slo_window_requests{{model=\"{model}\",outcome=\"bad\"}} {}\n",
+            slo.requests - slo.good_requests
+        ));
+        compliance.push_str(&format!(
+            "slo_compliance_ratio{{model=\"{model}\"}} {:.4}\n",
+            validate_metric_value(slo.compliance)
+        ));
+        burn_rate.push_str(&format!(
+            "slo_burn_rate{{model=\"{model}\"}} {:.4}\n",
+            validate_metric_value(slo.burn_rate)
+        ));
+    }
+    target + &requests + &compliance + &burn_rate
+}
+
 fn build_prometheus_response(body: String) -> Result<Response, axum::http::Error> {
     Response::builder()
         .status(200)
@@ -252,11 +388,24 @@ fn build_error_response() -> Response {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/metrics/prometheus",
+    tag = "metrics",
+    responses((status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"))
+)]
 pub async fn prometheus_metrics_handler(State(state): State<AppState>) -> Response {
     let metrics_stats = state.metrics.get_stats().await;
     let validated_stats = validate_metrics_stats(&metrics_stats);
     let metric_definitions = create_metric_definitions(&metrics_stats, &validated_stats);
-    let prom_output = build_prometheus_output(&metric_definitions);
+    let mut prom_output = build_prometheus_output(&metric_definitions);
+    prom_output.push_str(&format_routing_decisions(&metrics_stats));
+    prom_output.push_str(&format_experiment_variants(&metrics_stats));
+    prom_output.push_str(&format_upstream_traffic(&metrics_stats));
+    prom_output.push_str(&format_deprecated_model_requests(&metrics_stats));
+    if state.slos.is_enabled() {
+        prom_output.push_str(&format_slos(&state.slos.report()));
+    }
 
     match build_prometheus_response(prom_output) {
         Ok(response) => response,
@@ -1,4 +1,13 @@
+pub mod admin;
 pub mod chat;
+pub mod conversations;
 pub mod health;
+pub mod keepalive;
 pub mod metrics;
+pub mod models;
 pub mod openai_chat;
+pub mod openapi;
+pub mod sse;
+pub mod status;
+pub mod tokens;
+pub mod usage;
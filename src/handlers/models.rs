@@ -0,0 +1,86 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::openai::errors::{map_error_with_code, OpenAIError, CODE_MODEL_NOT_FOUND};
+use crate::services::model_registry::ModelInfo;
+use crate::state::AppState;
+
+/// OpenAI-compatible model object, extended with registry metadata.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ModelObject<'a> {
+    pub object: &'static str,
+    pub created: i64,
+    #[serde(flatten)]
+    pub info: &'a ModelInfo,
+}
+
+impl<'a> From<&'a ModelInfo> for ModelObject<'a> {
+    fn from(info: &'a ModelInfo) -> Self {
+        Self {
+            object: "model",
+            created: 0,
+            info,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ModelList<'a> {
+    pub object: &'static str,
+    pub data: Vec<ModelObject<'a>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/models",
+    tag = "openai",
+    responses((status = 200, description = "Every model in the registry, plus upstream models when discovery is enabled", body = ModelList))
+)]
+pub async fn list_models(State(state): State<AppState>) -> Response {
+    let models = state.model_catalog.list(&state).await;
+    let data = models.iter().map(ModelObject::from).collect();
+    Json(ModelList {
+        object: "list",
+        data,
+    })
+    .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/models/{model_id}",
+    tag = "openai",
+    params(("model_id" = String, Path, description = "Model ID or alias")),
+    responses(
+        (status = 200, description = "The model's metadata", body = ModelObject),
+        (status = 404, description = "Unknown model", body = OpenAIError)
+    )
+)]
+pub async fn get_model(State(state): State<AppState>, Path(model_id): Path<String>) -> Response {
+    let resolved = state.model_registry.resolve_alias(&model_id);
+    let id = resolved.as_deref().unwrap_or(&model_id);
+    if let Some(info) = state.model_registry.get(id) {
+        return Json(ModelObject::from(info)).into_response();
+    }
+    let discovered = state
+        .model_catalog
+        .vertex_models(&state)
+        .await
+        .into_iter()
+        .find(|model| model.id == id)
+        .map(|model| model.to_model_info(&state.model_registry, "google"));
+    match discovered {
+        Some(info) => Json(ModelObject::from(&info)).into_response(),
+        None => map_error_with_code(
+            404,
+            &format!("The model '{model_id}' does not exist"),
+            CODE_MODEL_NOT_FOUND,
+            Some("model"),
+        ),
+    }
+}
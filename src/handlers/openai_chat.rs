@@ -4,10 +4,11 @@ use axum::{
     Json,
 };
 use futures::stream::{self, StreamExt};
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use crate::{
+    handlers::sse,
     models::openai::{ChatCompletionRequest, ChatCompletionResponse},
     openai::{
         backend::{BackendError, OpenAIBackendClient},
@@ -15,61 +16,134 @@ use crate::{
         harvester::HarvesterClient,
         models::BackendConversationRequest,
         models::TokenResponse,
+        output_limit::OutputLimit,
         sse_parser::SSEParser,
         transformer::{transform_sse_to_openai_chunk, transform_to_backend},
+        waf_cooldown,
     },
+    services::{notifier::AlertEvent, providers::metered_bytes_stream, tokenizer},
     state::AppState,
 };
 
+/// Name the harvester backend is reported under in routing and traffic metrics.
+pub const PROVIDER_NAME: &str = "openai";
+
 async fn execute_backend_request(
     backend_client: &OpenAIBackendClient,
-    circuit_breaker: &std::sync::Arc<crate::openai::circuit_breaker::CircuitBreaker>,
+    state: &AppState,
     backend_req: BackendConversationRequest,
-    access_token: &str,
-    arkose_token: Option<&str>,
-    metrics: &std::sync::Arc<crate::openai::metrics::Metrics>,
+    tokens: &TokenResponse,
 ) -> Result<reqwest::Response, BackendError> {
-    circuit_breaker
+    // Counted once per call; the backend client's internal retries resend it
+    let request_bytes = serde_json::to_vec(&backend_req).map_or(0, |body| body.len());
+    state
+        .metrics
+        .record_upstream_request(PROVIDER_NAME, request_bytes);
+    state
+        .circuit_breaker
         .call(async {
             backend_client
-                .send_request(backend_req, access_token, arkose_token)
+                .send_request(
+                    backend_req,
+                    &tokens.access_token,
+                    tokens.arkose_token.as_deref(),
+                )
                 .await
         })
         .await
+        .map_err(|e| e.redacted(&state.config.redaction))
+        .inspect(|_| state.waf_cooldown.record_success(&tokens.access_token))
         .inspect_err(|e| {
             let status = e.status_code();
+            if status == 401 || status == 403 {
+                // Conversations bound to rejected tokens pick up fresh ones next turn
+                state.token_bindings.release(&tokens.access_token);
+            }
             if status == 403 {
                 // Record WAF block asynchronously - don't block on metrics
-                let metrics_clone = metrics.clone();
+                let metrics_clone = state.metrics.clone();
                 tokio::spawn(async move {
                     metrics_clone.record_waf_block().await;
                 });
+                if state.waf_cooldown.record_block(&tokens.access_token) {
+                    cool_down_session(state, &tokens.access_token);
+                }
             }
         })
 }
 
+/// Refuses a request whose harvester session is cooling down after WAF blocks.
+fn session_cooling_down(remaining: std::time::Duration) -> axum::response::Response {
+    let mut response = map_error_with_status(
+        503,
+        "OpenAI session is cooling down after repeated WAF blocks; a fresh session has been requested",
+    );
+    response.headers_mut().insert(
+        axum::http::header::RETRY_AFTER,
+        axum::http::HeaderValue::from(remaining.as_secs().max(1)),
+    );
+    response
+}
+
+/// Alerts about a session the WAF keeps blocking and asks the harvester for a
+/// fresh one in the background.
+fn cool_down_session(state: &AppState, access_token: &str) {
+    let session = waf_cooldown::session_id(access_token);
+    warn!("WAF keeps blocking session {session}; cooling it down and refreshing");
+    state.notifier.notify(&AlertEvent::WafSessionCooledDown {
+        session: session.clone(),
+        blocks: state.config.openai.waf_cooldown.threshold,
+    });
+    let harvester = match HarvesterClient::new(&state.config) {
+        Ok(harvester) => harvester,
+        Err(e) => {
+            error!("Failed to create harvester client for session refresh: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        match harvester.refresh_tokens(false).await {
+            Ok(tokens) if waf_cooldown::session_id(&tokens.access_token) == session => {
+                warn!("Harvester refresh kept WAF-blocked session {session}");
+            }
+            Ok(_) => info!("Harvester replaced WAF-blocked session {session}"),
+            Err(e) => error!("Failed to refresh WAF-blocked session {session}: {e}"),
+        }
+    });
+}
+
+/// Translates backend SSE bytes into completion chunk events, cut short by
+/// `limit`. Unless `strict`, a serialization failure becomes a comment, as
+/// does a read that completes no event.
 fn process_stream_chunk(
     parser: &mut SSEParser,
+    limit: &mut OutputLimit,
     bytes: &[u8],
     model: &str,
     request_id: &str,
+    strict: bool,
 ) -> Vec<Event> {
     let events = parser.parse_chunk(bytes);
     let mut sse_events = Vec::new();
-    for event in events {
-        if let Some(chunk) = transform_sse_to_openai_chunk(&event, model, request_id) {
+    let chunks = events
+        .iter()
+        .filter_map(|event| transform_sse_to_openai_chunk(event, model, request_id));
+    for chunk in chunks {
+        for chunk in limit.apply(chunk) {
             match Event::default().json_data(chunk) {
                 Ok(e) => sse_events.push(e),
                 Err(e) => {
                     error!("Failed to serialize SSE chunk: {}", e);
-                    sse_events.push(
-                        Event::default().comment(format!("error: serialization failed: {e}")),
-                    );
+                    if !strict {
+                        sse_events.push(
+                            Event::default().comment(format!("error: serialization failed: {e}")),
+                        );
+                    }
                 }
             }
         }
     }
-    if sse_events.is_empty() {
+    if sse_events.is_empty() && !strict && !limit.is_finished() {
         sse_events.push(Event::default().comment("keep-alive"));
     }
     sse_events
@@ -128,10 +202,10 @@ async fn fetch_tokens(
 
 struct StreamingContext<'a> {
     backend_client: &'a OpenAIBackendClient,
-    circuit_breaker: &'a std::sync::Arc<crate::openai::circuit_breaker::CircuitBreaker>,
+    state: &'a AppState,
     backend_req: BackendConversationRequest,
+    limit: OutputLimit,
     tokens: &'a TokenResponse,
-    metrics: &'a std::sync::Arc<crate::openai::metrics::Metrics>,
     model: &'a str,
     request_id: &'a str,
     request_start: std::time::Instant,
@@ -140,24 +214,16 @@ struct StreamingContext<'a> {
 async fn handle_streaming(ctx: StreamingContext<'_>) -> axum::response::Response {
     let StreamingContext {
         backend_client,
-        circuit_breaker,
+        state,
         backend_req,
+        limit,
         tokens,
-        metrics,
         model,
         request_id,
         request_start,
     } = ctx;
-    let response = match execute_backend_request(
-        backend_client,
-        circuit_breaker,
-        backend_req,
-        &tokens.access_token,
-        tokens.arkose_token.as_deref(),
-        metrics,
-    )
-    .await
-    {
+    let metrics = &state.metrics;
+    let response = match execute_backend_request(backend_client, state, backend_req, tokens).await {
         Ok(r) => r,
         Err(e) => {
             error!("Backend request failed: {}", e);
@@ -167,62 +233,56 @@ async fn handle_streaming(ctx: StreamingContext<'_>) -> axum::response::Response
         }
     };
 
-    let mut parser = SSEParser::new();
     let model_clone = model.to_string();
     let request_id_clone = request_id.to_string();
-    let stream = response
-        .bytes_stream()
-        .map(move |chunk_result| -> Vec<Result<Event, reqwest::Error>> {
-            match chunk_result {
-                Ok(bytes) => {
-                    process_stream_chunk(&mut parser, &bytes, &model_clone, &request_id_clone)
-                        .into_iter()
-                        .map(Ok::<Event, reqwest::Error>)
-                        .collect()
-                }
-                Err(e) => {
-                    error!("Stream error: {}", e);
-                    let error_chunk = serde_json::json!({
-                        "error": {
-                            "message": format!("Stream error: {}", e),
-                            "type": "stream_error",
-                            "code": "stream_failed"
-                        }
-                    });
-                    match Event::default().json_data(error_chunk) {
-                        Ok(event) => vec![Ok(event)],
-                        Err(serialize_err) => {
-                            error!("Failed to serialize error chunk: {}", serialize_err);
-                            vec![Ok(Event::default().comment(format!(
-                                "error: stream failed: {e} (serialization error: {serialize_err})"
-                            )))]
-                        }
-                    }
+    let framing = sse::Framing::new(&state.config.sse, model);
+    let strict = framing.is_strict();
+    // Ends once a limit finishes the completion, dropping the backend response
+    let events = metered_bytes_stream(response, metrics.clone(), PROVIDER_NAME)
+        .scan(
+            (SSEParser::new(), limit),
+            move |(parser, limit), chunk_result| {
+                if limit.is_finished() {
+                    return futures::future::ready(None);
                 }
-            }
-        })
+                let events: Vec<Result<Event, reqwest::Error>> = match chunk_result {
+                    Ok(bytes) => process_stream_chunk(
+                        parser,
+                        limit,
+                        &bytes,
+                        &model_clone,
+                        &request_id_clone,
+                        strict,
+                    )
+                    .into_iter()
+                    .map(Ok)
+                    .collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                futures::future::ready(Some(events))
+            },
+        )
         .flat_map(stream::iter);
 
-    let duration_ms = u64::try_from(
-        request_start
-            .elapsed()
-            .as_millis()
-            .min(u128::from(u64::MAX)),
-    )
-    .unwrap_or(u64::MAX);
-    metrics.record_request(true).await;
-    metrics.record_request_duration(duration_ms).await;
-    Sse::new(stream)
-        .keep_alive(axum::response::sse::KeepAlive::default())
-        .into_response()
+    let keep_alive = framing.keep_alive();
+    Sse::new(sse::with_error_contract(
+        events,
+        metrics.clone(),
+        request_start,
+        framing,
+    ))
+    .keep_alive(keep_alive)
+    .into_response()
 }
 
 struct NonStreamingContext<'a> {
     backend_client: &'a OpenAIBackendClient,
-    circuit_breaker: &'a std::sync::Arc<crate::openai::circuit_breaker::CircuitBreaker>,
+    state: &'a AppState,
+    key_name: &'a str,
+    prompt_tokens: usize,
     backend_req: BackendConversationRequest,
+    limit: OutputLimit,
     tokens: &'a TokenResponse,
-    metrics: &'a std::sync::Arc<crate::openai::metrics::Metrics>,
     model: &'a str,
     request_id: &'a str,
     request_start: std::time::Instant,
@@ -231,24 +291,18 @@ struct NonStreamingContext<'a> {
 async fn handle_non_streaming(ctx: NonStreamingContext<'_>) -> axum::response::Response {
     let NonStreamingContext {
         backend_client,
-        circuit_breaker,
+        state,
+        key_name,
+        prompt_tokens,
         backend_req,
+        limit,
         tokens,
-        metrics,
         model,
         request_id,
         request_start,
     } = ctx;
-    let response = match execute_backend_request(
-        backend_client,
-        circuit_breaker,
-        backend_req,
-        &tokens.access_token,
-        tokens.arkose_token.as_deref(),
-        metrics,
-    )
-    .await
-    {
+    let metrics = &state.metrics;
+    let response = match execute_backend_request(backend_client, state, backend_req, tokens).await {
         Ok(r) => r,
         Err(e) => {
             error!("Backend request failed: {}", e);
@@ -259,7 +313,7 @@ async fn handle_non_streaming(ctx: NonStreamingContext<'_>) -> axum::response::R
     };
 
     let (full_content, finish_reason) =
-        match collect_stream_response(response, model, request_id).await {
+        match collect_stream_response(response, metrics, limit, model, request_id).await {
             Ok((content, reason)) => (content, reason),
             Err(e) => {
                 error!("Stream error during collection: {}", e);
@@ -273,6 +327,12 @@ async fn handle_non_streaming(ctx: NonStreamingContext<'_>) -> axum::response::R
         .map(|d| d.as_secs())
         .unwrap_or(0);
 
+    // The backend reports no usage, so it is estimated for accounting
+    let usage = tokenizer::estimate_usage(prompt_tokens, tokenizer::count(model, &full_content));
+    state
+        .usage
+        .record(key_name, model, &usage, &state.model_registry)
+        .await;
     let response = ChatCompletionResponse {
         id: format!("chatcmpl-{request_id}"),
         object: "chat.completion".to_string(),
@@ -284,10 +344,13 @@ async fn handle_non_streaming(ctx: NonStreamingContext<'_>) -> axum::response::R
                 role: crate::models::openai::Role::Assistant,
                 content: full_content,
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             },
             finish_reason,
         }],
-        usage: None, // Backend doesn't provide usage info
+        usage: Some(usage),
     };
 
     let duration_ms = u64::try_from(
@@ -304,6 +367,8 @@ async fn handle_non_streaming(ctx: NonStreamingContext<'_>) -> axum::response::R
 
 pub async fn openai_chat_completions(
     State(state): State<AppState>,
+    key_name: &str,
+    session: Option<&str>,
     Json(req): Json<ChatCompletionRequest>,
 ) -> axum::response::Response {
     // Validate request
@@ -327,33 +392,50 @@ pub async fn openai_chat_completions(
     };
 
     let requires_arkose = req.model.starts_with("gpt-4");
-    let token_start = std::time::Instant::now();
-    let tokens = match fetch_tokens(&harvester, requires_arkose, &state.metrics, token_start).await
-    {
-        Ok(tokens) => tokens,
-        Err(resp) => return resp,
-    };
-
-    let backend_req = match transform_to_backend(
-        &req.model,
-        &req.messages,
-        Some(req.temperature),
-        req.max_tokens,
-    ) {
-        Ok(r) => r,
-        Err(e) => {
-            error!("Transform error: {}", e);
-            return map_error_with_status(400, &format!("Invalid request format: {e}"));
+    // Sessions are scoped to the calling key, as for sticky alias routing
+    let session = session.map(|s| format!("{key_name}:{s}"));
+    let bound = session
+        .as_deref()
+        .and_then(|s| state.token_bindings.get(s, requires_arkose));
+    let tokens = if let Some(tokens) = bound {
+        debug!("Reusing tokens bound to the conversation session");
+        tokens
+    } else {
+        let token_start = std::time::Instant::now();
+        let tokens =
+            match fetch_tokens(&harvester, requires_arkose, &state.metrics, token_start).await {
+                Ok(tokens) => tokens,
+                Err(resp) => return resp,
+            };
+        if let Some(session) = &session {
+            state.token_bindings.bind(session, &tokens);
         }
+        tokens
     };
+    if let Some(remaining) = state.waf_cooldown.remaining(&tokens.access_token) {
+        state.token_bindings.release(&tokens.access_token);
+        return session_cooling_down(remaining);
+    }
+
+    let backend_req =
+        match transform_to_backend(&req.model, &req.messages, req.temperature, req.max_tokens) {
+            Ok(r) => r,
+            Err(e) => {
+                error!("Transform error: {}", e);
+                return map_error_with_status(400, &format!("Invalid request format: {e}"));
+            }
+        };
+
+    // The backend takes no stop sequences, so they and max_tokens are enforced here
+    let limit = OutputLimit::new(req.stop.as_deref(), req.max_tokens);
 
     if req.stream {
         return handle_streaming(StreamingContext {
             backend_client: &backend_client,
-            circuit_breaker: &state.circuit_breaker,
+            state: &state,
             backend_req,
+            limit,
             tokens: &tokens,
-            metrics: &state.metrics,
             model: &req.model,
             request_id: &request_id,
             request_start,
@@ -363,10 +445,12 @@ pub async fn openai_chat_completions(
 
     handle_non_streaming(NonStreamingContext {
         backend_client: &backend_client,
-        circuit_breaker: &state.circuit_breaker,
+        state: &state,
+        key_name,
+        prompt_tokens: tokenizer::prompt_tokens(&req),
         backend_req,
+        limit,
         tokens: &tokens,
-        metrics: &state.metrics,
         model: &req.model,
         request_id: &request_id,
         request_start,
@@ -376,6 +460,8 @@ pub async fn openai_chat_completions(
 
 async fn collect_stream_response(
     response: reqwest::Response,
+    metrics: &std::sync::Arc<crate::openai::metrics::Metrics>,
+    mut limit: OutputLimit,
     model: &str,
     request_id: &str,
 ) -> Result<(String, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
@@ -383,23 +469,31 @@ async fn collect_stream_response(
     let mut full_content = String::new();
     let mut finish_reason = None;
 
-    let mut stream = response.bytes_stream();
+    let mut stream = Box::pin(metered_bytes_stream(
+        response,
+        metrics.clone(),
+        PROVIDER_NAME,
+    ));
     while let Some(chunk_result) = stream.next().await {
         match chunk_result {
             Ok(bytes) => {
                 let events = parser.parse_chunk(&bytes);
-                for event in events {
-                    if let Some(chunk) = transform_sse_to_openai_chunk(&event, model, request_id) {
-                        if let Some(choice) = chunk.choices.first() {
-                            if let Some(content) = &choice.delta.content {
-                                full_content.push_str(content);
-                            }
-                            if let Some(reason) = &choice.finish_reason {
-                                finish_reason = Some(reason.clone());
-                            }
+                let chunks = events
+                    .iter()
+                    .filter_map(|event| transform_sse_to_openai_chunk(event, model, request_id));
+                for chunk in chunks.flat_map(|chunk| limit.apply(chunk)) {
+                    if let Some(choice) = chunk.choices.first() {
+                        if let Some(content) = &choice.delta.content {
+                            full_content.push_str(content);
+                        }
+                        if let Some(reason) = &choice.finish_reason {
+                            finish_reason = Some(reason.clone());
                         }
                     }
                 }
+                if limit.is_finished() {
+                    break;
+                }
             }
             Err(e) => {
                 return Err(Box::new(e));
@@ -418,7 +512,14 @@ mod tests {
         let mut parser = SSEParser::new();
         let chunk = b"data: {\"message\":{\"id\":\"msg_1\",\"content\":{\"content_type\":\"text\",\"parts\":[\"hello\"]}}}\n\ndata: [DONE]\n\n";
 
-        let events = process_stream_chunk(&mut parser, chunk, "gpt-4", "req-1");
+        let events = process_stream_chunk(
+            &mut parser,
+            &mut OutputLimit::default(),
+            chunk,
+            "gpt-4",
+            "req-1",
+            false,
+        );
 
         assert_eq!(
             events.len(),
@@ -0,0 +1,108 @@
+use axum::{response::IntoResponse, Json};
+use std::sync::OnceLock;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::handlers::{admin, chat, conversations, health, metrics, models, status, tokens, usage};
+
+/// The proxy's API contract. Every route except `/health` and `/status` takes the API key
+/// as a bearer token; `/admin/*` routes need an admin key.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "vertex-bridge",
+        description = "OpenAI-compatible LLM proxy with usage, budget and admin APIs."
+    ),
+    paths(
+        health::health_check,
+        health::readiness,
+        status::status,
+        metrics::metrics_handler,
+        metrics::prometheus_metrics_handler,
+        chat::chat_completions,
+        models::list_models,
+        models::get_model,
+        tokens::count_tokens,
+        conversations::get_conversation,
+        conversations::delete_conversation,
+        usage::get_usage,
+        admin::list_budgets,
+        admin::set_budget,
+        admin::list_model_aliases,
+        admin::set_model_alias,
+        admin::delete_model_alias,
+        admin::list_prompt_templates,
+        admin::set_prompt_template,
+        admin::delete_prompt_template,
+        admin::rate_limit_stats,
+        admin::validate_providers,
+        admin::maintenance_status,
+        admin::start_maintenance,
+        admin::end_maintenance,
+        admin::incident_status,
+        admin::raise_incident,
+        admin::resolve_incident,
+        admin::refresh_credentials,
+        admin::refresh_models,
+        usage::export_usage,
+    ),
+    modifiers(&BearerAuth),
+    security(("bearer_auth" = [])),
+    tags(
+        (name = "openai", description = "OpenAI-compatible endpoints"),
+        (name = "usage", description = "Usage reporting"),
+        (name = "admin", description = "Runtime administration; requires an admin key"),
+        (name = "metrics", description = "Operational metrics"),
+        (name = "health", description = "Liveness and dependency checks"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// The generated document, built on first use.
+pub fn api_doc() -> &'static utoipa::openapi::OpenApi {
+    static DOC: OnceLock<utoipa::openapi::OpenApi> = OnceLock::new();
+    DOC.get_or_init(ApiDoc::openapi)
+}
+
+/// `GET /openapi.json`: the OpenAPI 3.1 document for every proxy route.
+pub async fn openapi_json() -> impl IntoResponse {
+    Json(api_doc())
+}
+
+/// Swagger UI at `/docs`, rendering `/openapi.json`.
+#[cfg(feature = "swagger-ui")]
+pub fn swagger_ui<S>() -> axum::Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    use axum::http::{header::CONTENT_SECURITY_POLICY, HeaderValue};
+    use utoipa_swagger_ui::{Config, SwaggerUi};
+
+    // The UI needs its own scripts, inline styles and data: images, which the
+    // API-wide `default-src 'none'` policy would block
+    const SWAGGER_UI_CSP: &str =
+        "default-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:";
+
+    // Relative, so the UI still finds the document under a path prefix
+    axum::Router::from(SwaggerUi::new("/docs").config(Config::from("../openapi.json"))).layer(
+        axum::middleware::map_response(|mut response: axum::response::Response| async move {
+            response.headers_mut().insert(
+                CONTENT_SECURITY_POLICY,
+                HeaderValue::from_static(SWAGGER_UI_CSP),
+            );
+            response
+        }),
+    )
+}
@@ -0,0 +1,254 @@
+// Server-sent event helpers shared by every streaming chat completion path.
+//
+// Mid-stream error contract: once a 200 and the first events have been sent
+// the status can no longer change, so a provider failure is reported in-band.
+// The stream emits one final `data:` event carrying the OpenAI error envelope
+// (`{"error": {"message", "type": "server_error", "param": null, "code":
+// "stream_error"}}`), then `data: [DONE]`, and closes. The request is recorded
+// as failed in metrics. Providers signal such failures by yielding `Err` from
+// their stream rather than encoding errors into chunks themselves.
+//
+// Some OpenAI SDKs fail on anything but completion chunks. With `sse.strict`
+// a stream only carries `chat.completion.chunk` frames and `[DONE]`: comment
+// and placeholder events are dropped, a failure ends the stream with a chunk
+// whose `finish_reason` is `error` in place of the error envelope, and idle
+// heartbeats are `: ping` comments, which every SSE parser skips.
+
+use axum::response::sse::{Event, KeepAlive};
+use futures::stream::{self, Stream, StreamExt};
+use std::{convert::Infallible, fmt::Display, sync::Arc, time::Instant};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::config::SseConfig;
+use crate::models::openai::{ChatCompletionChunk, ChatCompletionChunkChoice, DeltaMessage};
+use crate::openai::{
+    errors::{ErrorDetail, OpenAIError},
+    metrics::Metrics,
+};
+use crate::services::fallback_responses;
+
+pub const CODE_STREAM_ERROR: &str = "stream_error";
+
+/// What a stream may carry besides completion chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Framing {
+    /// Comment events pass through and failures are sent as an error envelope.
+    Lenient,
+    /// Only chunks from `model` and `[DONE]`, with `: ping` heartbeats.
+    Strict { model: String },
+}
+
+impl Framing {
+    /// The framing configured for a stream from `model`.
+    #[must_use]
+    pub fn new(config: &SseConfig, model: &str) -> Self {
+        if config.strict {
+            Self::Strict {
+                model: model.to_string(),
+            }
+        } else {
+            Self::Lenient
+        }
+    }
+
+    #[must_use]
+    pub fn is_strict(&self) -> bool {
+        matches!(self, Self::Strict { .. })
+    }
+
+    /// Heartbeat sent while the stream is idle.
+    pub fn keep_alive(&self) -> KeepAlive {
+        match self {
+            Self::Lenient => KeepAlive::default(),
+            Self::Strict { .. } => KeepAlive::default().text("ping"),
+        }
+    }
+
+    /// The events that end a stream failing with `message`.
+    fn failure(&self, message: &str) -> Vec<Event> {
+        let last = match self {
+            Self::Lenient => error_event(message),
+            Self::Strict { model } => Event::default()
+                .json_data(error_chunk(model))
+                .unwrap_or_else(|_| error_event(message)),
+        };
+        vec![last, done_event()]
+    }
+}
+
+/// The `data: [DONE]` terminator OpenAI clients wait for.
+pub fn done_event() -> Event {
+    Event::default().data("[DONE]")
+}
+
+/// An OpenAI error envelope sent as a `data:` event.
+pub fn error_event(message: &str) -> Event {
+    let error = OpenAIError {
+        error: ErrorDetail {
+            message: message.to_string(),
+            error_type: "server_error".to_string(),
+            param: None,
+            code: Some(CODE_STREAM_ERROR.to_string()),
+            provider_detail: None,
+        },
+    };
+    Event::default()
+        .json_data(&error)
+        .unwrap_or_else(|_| Event::default().comment(format!("error: {message}")))
+}
+
+/// The final chunk of a strictly framed stream that failed.
+fn error_chunk(model: &str) -> ChatCompletionChunk {
+    ChatCompletionChunk {
+        id: format!("chatcmpl-{}", Uuid::new_v4()),
+        object: "chat.completion.chunk".to_string(),
+        created: u64::try_from(chrono::Utc::now().timestamp()).unwrap_or_default(),
+        model: model.to_string(),
+        choices: vec![ChatCompletionChunkChoice {
+            index: 0,
+            delta: DeltaMessage {
+                role: None,
+                content: None,
+                tool_calls: None,
+            },
+            finish_reason: Some(fallback_responses::FINISH_REASON.to_string()),
+        }],
+        usage: None,
+    }
+}
+
+/// Applies the mid-stream error contract to a stream of events.
+///
+/// Events pass through until the first `Err`, which is replaced by an error
+/// event (an error chunk under strict `framing`) and `[DONE]` before the
+/// stream ends. Success and duration are recorded when the upstream finishes
+/// cleanly; a failure is recorded on error.
+pub fn with_error_contract<S, E>(
+    events: S,
+    metrics: Arc<Metrics>,
+    request_start: Instant,
+    framing: Framing,
+) -> impl Stream<Item = Result<Event, Infallible>> + Send
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Display + Send + 'static,
+{
+    let state = Some((Box::pin(events), metrics, framing));
+    stream::unfold(state, move |state| async move {
+        let (mut events, metrics, framing) = state?;
+        match events.next().await {
+            Some(Ok(event)) => Some((vec![event], Some((events, metrics, framing)))),
+            Some(Err(e)) => {
+                error!("Stream failed after it started: {e}");
+                metrics.record_request(false).await;
+                Some((framing.failure(&format!("Stream error: {e}")), None))
+            }
+            None => {
+                let duration_ms = u64::try_from(
+                    request_start
+                        .elapsed()
+                        .as_millis()
+                        .min(u128::from(u64::MAX)),
+                )
+                .unwrap_or(u64::MAX);
+                metrics.record_request(true).await;
+                metrics.record_request_duration(duration_ms).await;
+                None
+            }
+        }
+    })
+    .flat_map(|events| stream::iter(events.into_iter().map(Ok)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::{IntoResponse, Sse};
+
+    async fn render(
+        events: Vec<Result<Event, String>>,
+        metrics: Arc<Metrics>,
+        framing: Framing,
+    ) -> String {
+        let stream = with_error_contract(stream::iter(events), metrics, Instant::now(), framing);
+        let body = Sse::new(stream).into_response().into_body();
+        let bytes = axum::body::to_bytes(body, 64 * 1024)
+            .await
+            .expect("body should be readable");
+        String::from_utf8(bytes.to_vec()).expect("body should be UTF-8")
+    }
+
+    #[tokio::test]
+    async fn test_error_mid_stream_emits_error_then_done() {
+        let metrics = Arc::new(Metrics::new());
+        let body = render(
+            vec![
+                Ok(Event::default().data("first")),
+                Err("upstream reset".to_string()),
+                Ok(Event::default().data("never sent")),
+            ],
+            metrics.clone(),
+            Framing::Lenient,
+        )
+        .await;
+
+        let data: Vec<&str> = body
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.len(), 3, "unexpected body: {body}");
+        assert_eq!(data[0], "first");
+        let error: serde_json::Value = serde_json::from_str(data[1]).expect("error is JSON");
+        assert_eq!(error["error"]["code"], CODE_STREAM_ERROR);
+        assert_eq!(error["error"]["type"], "server_error");
+        assert!(error["error"]["message"]
+            .as_str()
+            .is_some_and(|m| m.contains("upstream reset")));
+        assert_eq!(data[2], "[DONE]");
+
+        assert_eq!(metrics.get_stats().await.failed_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn test_clean_stream_records_success() {
+        let metrics = Arc::new(Metrics::new());
+        let body = render(
+            vec![Ok(Event::default().data("a")), Ok(done_event())],
+            metrics.clone(),
+            Framing::Lenient,
+        )
+        .await;
+
+        assert!(body.contains("data: [DONE]"));
+        let stats = metrics.get_stats().await;
+        assert_eq!(stats.total_requests, 1);
+        assert_eq!(stats.failed_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_strict_failure_ends_with_error_chunk() {
+        let metrics = Arc::new(Metrics::new());
+        let framing = Framing::new(&SseConfig { strict: true }, "gemini-2.5-flash");
+        let body = render(
+            vec![
+                Ok(Event::default().data("first")),
+                Err("upstream reset".to_string()),
+            ],
+            metrics,
+            framing,
+        )
+        .await;
+
+        let data: Vec<&str> = body
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.len(), 3, "unexpected body: {body}");
+        let chunk: ChatCompletionChunk =
+            serde_json::from_str(data[1]).expect("failure is a completion chunk");
+        assert_eq!(chunk.model, "gemini-2.5-flash");
+        assert_eq!(chunk.choices[0].finish_reason.as_deref(), Some("error"));
+        assert_eq!(data[2], "[DONE]");
+    }
+}
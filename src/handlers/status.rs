@@ -0,0 +1,135 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use utoipa::ToSchema;
+
+use crate::handlers::openai_chat::PROVIDER_NAME as OPENAI_PROVIDER_NAME;
+use crate::services::status_page::Incident;
+use crate::state::AppState;
+
+const CACHE_CONTROL_NO_CACHE: &str = "no-cache, no-store, must-revalidate";
+
+/// Whether one provider is currently usable.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProviderStatus {
+    pub name: String,
+    pub up: bool,
+}
+
+/// Public summary served by `/status`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StatusSummary {
+    /// `operational`, `degraded` (a provider is down), `maintenance` or `incident`.
+    pub status: String,
+    pub version: String,
+    pub uptime_secs: u64,
+    pub providers: Vec<ProviderStatus>,
+    pub maintenance: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incident: Option<Incident>,
+}
+
+/// Builds the summary from state the proxy already tracks; nothing here
+/// calls an upstream, so the endpoint is cheap to poll.
+async fn summary(state: &AppState) -> StatusSummary {
+    let mut providers = Vec::new();
+    for provider in state.provider_registry.list_providers() {
+        providers.push(ProviderStatus {
+            name: provider.name().to_string(),
+            up: state.provider_registry.is_available(&provider).await,
+        });
+    }
+    providers.push(ProviderStatus {
+        name: OPENAI_PROVIDER_NAME.to_string(),
+        up: !state.circuit_breaker.is_open().await,
+    });
+    let maintenance = state.maintenance_mode.active().await.is_some();
+    let incident = state.status_page.incident().await;
+    let status = if incident.is_some() {
+        "incident"
+    } else if maintenance {
+        "maintenance"
+    } else if providers.iter().any(|p| !p.up) {
+        "degraded"
+    } else {
+        "operational"
+    };
+    StatusSummary {
+        status: status.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: state.status_page.uptime().as_secs(),
+        providers,
+        maintenance,
+        incident,
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(summary: &StatusSummary) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Status</title></head><body>\n<h1>Status: {}</h1>\n<p>Version {}, up {}s</p>\n",
+        summary.status, summary.version, summary.uptime_secs
+    );
+    if let Some(incident) = &summary.incident {
+        let _ = writeln!(
+            html,
+            "<p><strong>Incident since {}</strong>: {}</p>",
+            incident.started_at.to_rfc3339(),
+            escape_html(incident.settings.message.as_deref().unwrap_or_default())
+        );
+    }
+    if summary.maintenance {
+        html.push_str("<p>Maintenance in progress</p>\n");
+    }
+    html.push_str("<ul>\n");
+    for provider in &summary.providers {
+        let _ = writeln!(
+            html,
+            "<li>{}: {}</li>",
+            provider.name,
+            if provider.up { "up" } else { "down" }
+        );
+    }
+    html.push_str("</ul>\n</body></html>\n");
+    html
+}
+
+/// `GET /status`: unauthenticated summary for status dashboards, as HTML
+/// when the client accepts it and JSON otherwise.
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "Uptime, provider availability and any open incident", body = StatusSummary)
+    )
+)]
+pub async fn status(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let summary = summary(&state).await;
+    let wants_html = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    let mut response = if wants_html {
+        Html(render_html(&summary)).into_response()
+    } else {
+        Json(summary).into_response()
+    };
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL_NO_CACHE),
+    );
+    response
+}
@@ -0,0 +1,91 @@
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::models::openai::ChatMessage;
+use crate::openai::errors::{map_error_with_status, map_json_rejection, OpenAIError};
+use crate::services::providers::{vertex::VertexProvider, LLMProvider};
+use crate::services::tokenizer;
+use crate::state::AppState;
+
+/// Reported for prompts counted by Vertex's `countTokens` method.
+pub const VERTEX_TOKENIZER: &str = "vertex_count_tokens";
+
+/// The prompt to count, in the shape of a chat completion request.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenCountRequest {
+    /// Model ID or alias; picks the tokenizer.
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<ChatMessage>,
+    /// Prompts fanned out over the shared `messages`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompts: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenCount {
+    /// Always `tokens.count`.
+    pub object: String,
+    /// The model counted for, after resolving aliases.
+    pub model: String,
+    /// Tokenizer the prompt was counted with, e.g. `o200k_base`.
+    pub tokenizer: String,
+    pub prompt_tokens: u32,
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/tokens/count",
+    tag = "openai",
+    request_body = TokenCountRequest,
+    responses(
+        (status = 200, description = "Prompt tokens, counted by the model family's tokenizer the way context-length checks and usage estimates count them; Gemini prompts are counted by Vertex when it answers", body = TokenCount),
+        (status = 400, description = "Malformed request", body = OpenAIError)
+    )
+)]
+pub async fn count_tokens(
+    State(state): State<AppState>,
+    payload: Result<Json<TokenCountRequest>, JsonRejection>,
+) -> Response {
+    let Json(req) = match payload {
+        Ok(payload) => payload,
+        Err(rejection) => return map_json_rejection(&rejection),
+    };
+    if req.model.is_empty() {
+        return map_error_with_status(400, "model must not be empty");
+    }
+    let model = state
+        .model_registry
+        .resolve_alias(&req.model)
+        .unwrap_or(req.model);
+    let vertex_count = if VertexProvider::new().supports_model(&model) {
+        VertexProvider::count_tokens(&state, &model, &req.messages, &req.prompts)
+            .await
+            .inspect_err(|e| warn!("Vertex token count for {model} failed, estimating: {e}"))
+            .ok()
+    } else {
+        None
+    };
+    let (tokenizer, tokens) = vertex_count.map_or_else(
+        || {
+            (
+                tokenizer::for_model(&model).name(),
+                tokenizer::count_prompt(&model, &req.messages, &req.prompts),
+            )
+        },
+        |tokens| (VERTEX_TOKENIZER, tokens),
+    );
+    Json(TokenCount {
+        object: "tokens.count".to_string(),
+        tokenizer: tokenizer.to_string(),
+        prompt_tokens: u32::try_from(tokens).unwrap_or(u32::MAX),
+        model,
+    })
+    .into_response()
+}
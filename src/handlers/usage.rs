@@ -0,0 +1,357 @@
+use axum::{
+    body::Body,
+    extract::{Extension, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::openai::errors::{map_error_with_status, OpenAIError};
+use crate::services::keys::AuthenticatedKey;
+use crate::services::usage::UsageSummary;
+use crate::state::AppState;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    /// Range start, RFC 3339 or `YYYY-MM-DD` (default: start of this month).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Range end, RFC 3339 or `YYYY-MM-DD` (inclusive of that day).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Only this key; admin keys only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageReport {
+    pub object: &'static str,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub persistent: bool,
+    pub data: Vec<UsageSummary>,
+}
+
+/// Parses an RFC 3339 timestamp or a `YYYY-MM-DD` date.
+///
+/// Bare dates resolve to the start of that day, or to the start of the next
+/// day when `end_of_day` is set, so `to=2024-05-31` includes all of May 31st.
+pub(crate) fn parse_time_bound(value: &str, end_of_day: bool) -> Result<DateTime<Utc>, String> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Ok(ts.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid timestamp '{value}': expected RFC 3339 or YYYY-MM-DD"))?;
+    let date = if end_of_day {
+        date.succ_opt().unwrap_or(date)
+    } else {
+        date
+    };
+    Ok(midnight(date))
+}
+
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = now.date_naive();
+    midnight(today.with_day(1).unwrap_or(today))
+}
+
+/// `GET /usage?from=&to=&key=`: usage per key and model in a time range.
+///
+/// The range defaults to the current calendar month. Non-admin keys only see
+/// their own usage; admins see every key unless `key` narrows it.
+#[utoipa::path(
+    get,
+    path = "/usage",
+    tag = "usage",
+    params(UsageQuery),
+    responses(
+        (status = 200, description = "Usage per key and model", body = UsageReport),
+        (status = 400, description = "Invalid time range", body = OpenAIError)
+    )
+)]
+pub async fn get_usage(
+    State(state): State<AppState>,
+    caller: Option<Extension<AuthenticatedKey>>,
+    Query(query): Query<UsageQuery>,
+) -> Response {
+    let caller = caller.map_or_else(AuthenticatedKey::anonymous, |Extension(k)| k);
+    let now = Utc::now();
+    let from = match query.from.as_deref().map(|v| parse_time_bound(v, false)) {
+        None => month_start(now),
+        Some(Ok(ts)) => ts,
+        Some(Err(e)) => return map_error_with_status(400, &e),
+    };
+    let to = match query.to.as_deref().map(|v| parse_time_bound(v, true)) {
+        None => now,
+        Some(Ok(ts)) => ts,
+        Some(Err(e)) => return map_error_with_status(400, &e),
+    };
+    if from >= to {
+        return map_error_with_status(400, "'from' must be earlier than 'to'");
+    }
+
+    let key = if caller.admin {
+        query.key
+    } else {
+        if query.key.as_ref().is_some_and(|k| *k != caller.name) {
+            return map_error_with_status(403, "Only admin keys can query other keys' usage");
+        }
+        Some(caller.name)
+    };
+
+    match state.usage.query(from, to, key.as_deref()).await {
+        Ok(data) => Json(UsageReport {
+            object: "list",
+            from,
+            to,
+            persistent: state.storage.is_some(),
+            data,
+        })
+        .into_response(),
+        Err(e) => map_error_with_status(500, &format!("Failed to query usage: {e}")),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// `csv` (default) or `jsonl`.
+    pub format: Option<String>,
+    /// `month` (default), `today`, `last_month`, `YYYY-MM` or `YYYY-MM-DD`.
+    pub period: Option<String>,
+    /// Only this key.
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "jsonl" | "ndjson" => Ok(Self::Jsonl),
+            other => Err(format!(
+                "Invalid format '{other}': expected 'csv' or 'jsonl'"
+            )),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Resolves a report period to a `[from, to)` range.
+///
+/// Accepts `month` (the default, month to date), `today`, `last_month`, a
+/// calendar month `YYYY-MM` or a single day `YYYY-MM-DD`.
+fn resolve_period(
+    period: &str,
+    now: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    match period {
+        "month" => Ok((month_start(now), now)),
+        "today" => Ok((midnight(now.date_naive()), now)),
+        "last_month" => {
+            let end = month_start(now);
+            let start = end
+                .checked_sub_months(Months::new(1))
+                .ok_or("Period out of range")?;
+            Ok((start, end))
+        }
+        _ => {
+            if let Ok(day) = NaiveDate::parse_from_str(period, "%Y-%m-%d") {
+                return Ok((midnight(day), midnight(day.succ_opt().unwrap_or(day))));
+            }
+            let start = NaiveDate::parse_from_str(&format!("{period}-01"), "%Y-%m-%d")
+                .map(midnight)
+                .map_err(|_| {
+                    format!(
+                        "Invalid period '{period}': expected month, today, last_month, YYYY-MM or YYYY-MM-DD"
+                    )
+                })?;
+            let end = start
+                .checked_add_months(Months::new(1))
+                .ok_or("Period out of range")?;
+            Ok((start, end))
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn report_lines(
+    format: ExportFormat,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    rows: Vec<UsageSummary>,
+) -> Vec<String> {
+    let (from, to) = (from.to_rfc3339(), to.to_rfc3339());
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    if format == ExportFormat::Csv {
+        lines.push(
+            "period_start,period_end,key,model,requests,prompt_tokens,completion_tokens,cost_usd\n"
+                .to_string(),
+        );
+    }
+    for row in rows {
+        let line = match format {
+            ExportFormat::Csv => format!(
+                "{from},{to},{},{},{},{},{},{:.6}\n",
+                csv_field(&row.key),
+                csv_field(&row.model),
+                row.totals.requests,
+                row.totals.prompt_tokens,
+                row.totals.completion_tokens,
+                row.totals.cost_usd
+            ),
+            ExportFormat::Jsonl => {
+                let mut value = serde_json::to_value(&row).unwrap_or_default();
+                value["period_start"] = serde_json::Value::String(from.clone());
+                value["period_end"] = serde_json::Value::String(to.clone());
+                format!("{value}\n")
+            }
+        };
+        lines.push(line);
+    }
+    lines
+}
+
+/// `GET /admin/usage/export?format=csv|jsonl&period=&key=`: per-key, per-model
+/// usage report for billing reconciliation, streamed as a file download.
+#[utoipa::path(
+    get,
+    path = "/admin/usage/export",
+    tag = "admin",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Usage report download", content(
+            (String = "text/csv"),
+            (String = "application/x-ndjson")
+        )),
+        (status = 400, description = "Invalid format or period", body = OpenAIError)
+    )
+)]
+pub async fn export_usage(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let format = match ExportFormat::parse(query.format.as_deref().unwrap_or("csv")) {
+        Ok(f) => f,
+        Err(e) => return map_error_with_status(400, &e),
+    };
+    let period = query.period.as_deref().unwrap_or("month");
+    let (from, to) = match resolve_period(period, Utc::now()) {
+        Ok(range) => range,
+        Err(e) => return map_error_with_status(400, &e),
+    };
+
+    let rows = match state.usage.query(from, to, query.key.as_deref()).await {
+        Ok(rows) => rows,
+        Err(e) => return map_error_with_status(500, &format!("Failed to query usage: {e}")),
+    };
+    let lines = report_lines(format, from, to, rows);
+    let filename = format!(
+        "usage-{}-{}.{}",
+        from.format("%Y%m%d"),
+        to.format("%Y%m%d"),
+        format.extension()
+    );
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .body(Body::from_stream(futures::stream::iter(
+            lines.into_iter().map(Ok::<_, Infallible>),
+        )))
+        .unwrap_or_else(|e| map_error_with_status(500, &format!("Failed to build report: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::usage::UsageTotals;
+
+    #[test]
+    fn test_parse_time_bound_formats() {
+        let start = parse_time_bound("2024-05-31", false).expect("date should parse");
+        assert_eq!(start.to_rfc3339(), "2024-05-31T00:00:00+00:00");
+        let end = parse_time_bound("2024-05-31", true).expect("date should parse");
+        assert_eq!(end.to_rfc3339(), "2024-06-01T00:00:00+00:00");
+        let ts = parse_time_bound("2024-05-31T12:00:00+02:00", true).expect("ts should parse");
+        assert_eq!(ts.to_rfc3339(), "2024-05-31T10:00:00+00:00");
+        assert!(parse_time_bound("yesterday", false).is_err());
+    }
+
+    #[test]
+    fn test_resolve_period() {
+        let now = DateTime::parse_from_rfc3339("2024-03-15T08:00:00Z")
+            .expect("ts should parse")
+            .with_timezone(&Utc);
+        let (from, to) = resolve_period("last_month", now).expect("period should resolve");
+        assert_eq!(from.to_rfc3339(), "2024-02-01T00:00:00+00:00");
+        assert_eq!(to.to_rfc3339(), "2024-03-01T00:00:00+00:00");
+        let (from, to) = resolve_period("2023-12", now).expect("period should resolve");
+        assert_eq!(from.to_rfc3339(), "2023-12-01T00:00:00+00:00");
+        assert_eq!(to.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        let (from, _) = resolve_period("month", now).expect("period should resolve");
+        assert_eq!(from.to_rfc3339(), "2024-03-01T00:00:00+00:00");
+        assert!(resolve_period("2024-13", now).is_err());
+    }
+
+    #[test]
+    fn test_csv_report_escapes_fields() {
+        let now = Utc::now();
+        let lines = report_lines(
+            ExportFormat::Csv,
+            now,
+            now,
+            vec![UsageSummary {
+                key: "team, \"a\"".to_string(),
+                model: "gemini-pro".to_string(),
+                totals: UsageTotals {
+                    requests: 2,
+                    prompt_tokens: 10,
+                    completion_tokens: 5,
+                    cost_usd: 0.5,
+                },
+            }],
+        );
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("period_start,"));
+        assert!(lines[1].contains(",\"team, \"\"a\"\"\",gemini-pro,2,10,5,0.500000\n"));
+    }
+}
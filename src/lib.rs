@@ -1,7 +1,12 @@
+#[cfg(feature = "client")]
+pub mod client;
 pub mod config;
 pub mod handlers;
 pub mod middleware;
 pub mod models;
 pub mod openai;
+pub mod server;
 pub mod services;
 pub mod state;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
@@ -1,43 +1,22 @@
-use axum::{
-    middleware,
-    routing::{get, post},
-    Router,
-};
 use reqwest::StatusCode;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::io::{BufRead, IsTerminal};
+use std::sync::{Arc, Mutex};
 use tokio::{
-    io::{self, AsyncBufReadExt, BufReader},
-    sync::oneshot,
+    sync::{broadcast, mpsc, oneshot},
+    task::JoinHandle,
 };
-use tracing::{error, info, warn};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing::{info, warn};
 use vertex_bridge::config::AppConfig;
-use vertex_bridge::handlers::{chat, health, metrics};
-use vertex_bridge::middleware::{
-    api_version::api_version_middleware,
-    auth::auth_middleware,
-    rate_limit::{rate_limit_middleware, RateLimiter},
-    security_headers::security_headers_middleware,
+use vertex_bridge::middleware::access_log::AccessLog;
+use vertex_bridge::server::Server;
+use vertex_bridge::services::doctor;
+use vertex_bridge::services::log_control::{
+    self, LogControl, LogFormat, LogUpdate, DEFAULT_TARGETS,
 };
-use vertex_bridge::openai::circuit_breaker::CircuitBreaker;
-use vertex_bridge::openai::metrics::Metrics;
-use vertex_bridge::services::auth::TokenManager;
-use vertex_bridge::services::cache::Cache;
-use vertex_bridge::services::providers::ProviderRegistry;
 use vertex_bridge::state::AppState;
 
-type ServicesInit = (
-    TokenManager,
-    RateLimiter,
-    Arc<CircuitBreaker>,
-    Arc<Metrics>,
-    Arc<ProviderRegistry>,
-    Arc<Cache>,
-);
-
-type LogReloadHandle =
-    tracing_subscriber::reload::Handle<tracing_subscriber::EnvFilter, tracing_subscriber::Registry>;
+// Busiest keys listed by the `/rate-limit` command
+const CLI_RATE_LIMIT_TOP_KEYS: usize = 5;
 
 struct CommandResult {
     message: String,
@@ -47,7 +26,9 @@ struct CommandResult {
 #[derive(Clone)]
 struct CliContext {
     state: AppState,
-    log_handle: Option<LogReloadHandle>,
+    log_control: Option<&'static LogControl>,
+    access_log: AccessLog,
+    trace_task: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 fn parse_command(input: &str) -> (&str, Vec<&str>) {
@@ -100,6 +81,24 @@ fn format_provider_status(state: &AppState) -> String {
             .unwrap_or_else(|| "gemini".to_string())
     ));
 
+    for (provider, quota) in state.provider_registry.quotas() {
+        let mut remaining = Vec::new();
+        if let Some(minute) = quota.minute_remaining {
+            remaining.push(format!("{minute} this minute"));
+        }
+        if let (Some(day), Some(resets_at)) = (quota.day_remaining, quota.day_resets_at) {
+            remaining.push(format!(
+                "{day} today (resets {})",
+                resets_at.format("%Y-%m-%d %H:%M UTC")
+            ));
+        }
+        lines.push(format!(
+            "  {} quota remaining: {}",
+            provider.name(),
+            remaining.join(", ")
+        ));
+    }
+
     lines.join("\n")
 }
 
@@ -165,16 +164,17 @@ fn command_help(args: &[&str]) -> CommandResult {
                 "/rate-limit",
                 "/cache stats|clear",
                 "/circuit",
-                "/logs level <trace|debug|info|warn|error>",
+                "/logs [level <level> | format <pretty|json> | targets <directives|default|none>]",
                 "/reload",
                 "/connections",
                 "/test <model> <text>",
+                "/trace on [filter]|off",
                 "/quit"
             ]
         })
         .to_string()
     } else {
-        "/help - show commands\n/status - show service status\n/models [filter] - list supported model prefixes\n/providers - show provider/proxy configuration\n/health - call local health endpoint\n/metrics - fetch metrics summary\n/rate-limit - show rate limiter stats\n/cache stats|clear - show or clear cache\n/circuit - show circuit breaker status\n/logs level <level> - change log level\n/reload - validate config reload (dry-run)\n/connections - check backend reachability\n/test <model> <text> - send a local probe request\n/quit - stop the service"
+        "/help - show commands\n/status - show service status\n/models [filter] - list supported model prefixes\n/providers - show provider/proxy configuration\n/health - call local health endpoint\n/metrics - fetch metrics summary\n/rate-limit - show rate limiter stats\n/cache stats|clear - show or clear cache\n/circuit - show circuit breaker status\n/logs [level|format|targets <value>] - show or change log level, format and target filters\n/reload - validate config reload (dry-run)\n/connections - check backend reachability\n/test <model> <text> - send a local probe request\n/trace on [filter]|off - tail live request summaries\n/quit - stop the service"
             .to_string()
     };
 
@@ -296,12 +296,19 @@ async fn command_metrics(ctx: &CliContext) -> CommandResult {
 }
 
 async fn command_rate_limit(ctx: &CliContext) -> CommandResult {
-    let stats = ctx.state.rate_limiter.stats().await;
+    let stats = ctx.state.rate_limiter.stats(CLI_RATE_LIMIT_TOP_KEYS).await;
+    let mut message = format!(
+        "Rate limiter: capacity={}, refill_per_second={}, active_keys={}",
+        stats.capacity, stats.refill_per_second, stats.active_keys
+    );
+    for key in &stats.top_keys {
+        message.push_str(&format!(
+            "\n  {} tokens={} allowed={} rejected={} recent_rejects={}",
+            key.key, key.tokens, key.allowed, key.rejected, key.recent_rejects
+        ));
+    }
     CommandResult {
-        message: format!(
-            "Rate limiter: capacity={}, refill_per_second={}, active_keys={}",
-            stats.capacity, stats.refill_per_second, stats.active_keys
-        ),
+        message,
         shutdown: false,
     }
 }
@@ -341,40 +348,63 @@ async fn command_circuit(ctx: &CliContext) -> CommandResult {
     }
 }
 
-fn command_logs(args: &[&str], ctx: &CliContext) -> CommandResult {
-    if args.len() == 2 && args[0] == "level" {
-        let level = args[1].to_lowercase();
-        if let Some(handle) = &ctx.log_handle {
-            let filter_str = format!("{level},tower_http=debug");
-            return match EnvFilter::try_new(filter_str) {
-                Ok(filter) => {
-                    if handle.reload(filter).is_ok() {
-                        CommandResult {
-                            message: format!("Log level set to {level}"),
-                            shutdown: false,
-                        }
-                    } else {
-                        CommandResult {
-                            message: "Failed to update log level".to_string(),
-                            shutdown: false,
-                        }
-                    }
-                }
-                Err(e) => CommandResult {
-                    message: format!("Invalid log level: {e}"),
-                    shutdown: false,
-                },
-            };
-        }
+const LOGS_USAGE: &str = "Usage: /logs [level <trace|debug|info|warn|error> | format <pretty|json> | targets <directives|default|none>]";
 
+fn command_logs(args: &[&str], ctx: &CliContext) -> CommandResult {
+    let Some(control) = ctx.log_control else {
         return CommandResult {
-            message: "Log level reload not available in this build".to_string(),
+            message: "Log reload not available in this build".to_string(),
             shutdown: false,
         };
-    }
-
+    };
+    let update = match args {
+        [] => None,
+        ["level", level] => Some(LogUpdate {
+            level: Some((*level).to_string()),
+            ..LogUpdate::default()
+        }),
+        ["format", format] => match format.parse::<LogFormat>() {
+            Ok(format) => Some(LogUpdate {
+                format: Some(format),
+                ..LogUpdate::default()
+            }),
+            Err(e) => {
+                return CommandResult {
+                    message: e,
+                    shutdown: false,
+                }
+            }
+        },
+        ["targets", targets] => Some(LogUpdate {
+            targets: Some(match *targets {
+                "default" => DEFAULT_TARGETS.to_string(),
+                "none" => String::new(),
+                targets => targets.to_string(),
+            }),
+            ..LogUpdate::default()
+        }),
+        _ => {
+            return CommandResult {
+                message: LOGS_USAGE.to_string(),
+                shutdown: false,
+            }
+        }
+    };
+    let message = match update.map_or_else(|| Ok(control.settings()), |u| control.update(u)) {
+        Ok(settings) => format!(
+            "Logs: level={}, format={}, targets={}",
+            settings.level,
+            settings.format,
+            if settings.targets.is_empty() {
+                "none"
+            } else {
+                &settings.targets
+            }
+        ),
+        Err(e) => e,
+    };
     CommandResult {
-        message: "Usage: /logs level <trace|debug|info|warn|error>".to_string(),
+        message,
         shutdown: false,
     }
 }
@@ -454,6 +484,68 @@ async fn command_test(args: &[&str], ctx: &CliContext) -> CommandResult {
     }
 }
 
+fn command_trace(args: &[&str], ctx: &CliContext) -> CommandResult {
+    let mut trace_task = ctx
+        .trace_task
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    match args.first().copied() {
+        Some("on") => {
+            if let Some(previous) = trace_task.take() {
+                previous.abort();
+            }
+
+            let filter = (args.len() > 1).then(|| args[1..].join(" ").to_lowercase());
+            let mut rx = ctx.access_log.subscribe();
+            let task_filter = filter.clone();
+            *trace_task = Some(tokio::spawn(async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(entry) => {
+                            let line = entry.summary();
+                            if task_filter
+                                .as_ref()
+                                .is_none_or(|f| line.to_lowercase().contains(f))
+                            {
+                                println!("[trace] {line}");
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            println!("[trace] skipped {skipped} entries (console too slow)");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            }));
+
+            CommandResult {
+                message: filter.map_or_else(
+                    || "Trace enabled".to_string(),
+                    |f| format!("Trace enabled (filter: {f})"),
+                ),
+                shutdown: false,
+            }
+        }
+        Some("off") => {
+            let message = if let Some(task) = trace_task.take() {
+                task.abort();
+                "Trace disabled"
+            } else {
+                "Trace is not running"
+            };
+            CommandResult {
+                message: message.to_string(),
+                shutdown: false,
+            }
+        }
+        _ => CommandResult {
+            message: "Usage: /trace on [filter] | /trace off".to_string(),
+            shutdown: false,
+        },
+    }
+}
+
 fn command_quit() -> CommandResult {
     CommandResult {
         message: "Shutting down service...".to_string(),
@@ -485,6 +577,7 @@ async fn process_command(input: &str, ctx: &CliContext) -> CommandResult {
         "/reload" | "reload" => command_reload(),
         "/connections" | "connections" => command_connections(ctx).await,
         "/test" | "test" => command_test(&args, ctx).await,
+        "/trace" | "trace" => command_trace(&args, ctx),
         "/quit" | "/exit" | "quit" | "exit" => command_quit(),
         _ => command_unknown(),
     }
@@ -562,167 +655,70 @@ async fn setup_shutdown_signal() {
     #[cfg(not(unix))]
     {
         if let Err(e) = tokio::signal::ctrl_c().await {
-            error!("Failed to install Ctrl+C handler: {}", e);
+            tracing::error!("Failed to install Ctrl+C handler: {}", e);
             return;
         }
         info!("Received Ctrl+C, initiating graceful shutdown");
     }
 }
 
-fn setup_logging(config: &AppConfig) -> LogReloadHandle {
-    let log_format = config.log.format.as_str();
-    let filter = EnvFilter::try_new(format!(
-        "{level},tower_http=debug",
-        level = config.log.level
-    ))
-    .unwrap_or_else(|_| EnvFilter::new(&config.log.level));
-
-    let (filter_layer, reload_handle) = tracing_subscriber::reload::Layer::new(filter);
-
-    match log_format {
-        "json" => {
-            tracing_subscriber::registry()
-                .with(filter_layer)
-                .with(
-                    tracing_subscriber::fmt::layer()
-                        .json()
-                        .with_target(false)
-                        .with_file(true)
-                        .with_line_number(true)
-                        .with_current_span(true)
-                        .with_span_list(true),
-                )
-                .init();
-        }
-        _ => {
-            tracing_subscriber::registry()
-                .with(filter_layer)
-                .with(
-                    tracing_subscriber::fmt::layer()
-                        .with_target(true)
-                        .with_file(true)
-                        .with_line_number(true),
-                )
-                .init();
-        }
+/// Resolves when the process is signalled or the CLI asks for shutdown.
+async fn shutdown_signal(shutdown_rx: oneshot::Receiver<()>) {
+    tokio::select! {
+        () = setup_shutdown_signal() => {},
+        result = shutdown_rx => {
+            // A dropped sender means the CLI went away without asking for shutdown
+            // (stdin closed, non-interactive mode); keep serving until a signal arrives.
+            if result.is_err() {
+                setup_shutdown_signal().await;
+            }
+        },
     }
-
-    reload_handle
-}
-
-fn initialize_services(config: &AppConfig) -> anyhow::Result<ServicesInit> {
-    let token_manager = TokenManager::new(
-        config.vertex.api_key.clone(),
-        config.vertex.credentials_file.clone(),
-        config.vertex.project_id.clone(),
-    )
-    .map_err(|e| {
-        error!("Failed to initialize TokenManager: {e}");
-        anyhow::anyhow!("TokenManager initialization failed: {e}")
-    })?;
-
-    let rate_limiter = RateLimiter::new(
-        config.rate_limit.capacity,
-        config.rate_limit.refill_per_second,
-    );
-    let circuit_breaker = Arc::new(CircuitBreaker::new(
-        config.circuit_breaker.failure_threshold,
-        config.circuit_breaker.timeout_secs,
-        config.circuit_breaker.success_threshold,
-    ));
-    let metrics = Arc::new(Metrics::new());
-    let provider_registry = Arc::new(ProviderRegistry::with_config(
-        &Some(config.anthropic.bridge_url.clone()),
-        &Some(config.gemini_cli.clone()),
-    ));
-    let cache = Arc::new(Cache::new(
-        config.cache.enabled,
-        config.cache.default_ttl_secs,
-    ));
-
-    Ok((
-        token_manager,
-        rate_limiter,
-        circuit_breaker,
-        metrics,
-        provider_registry,
-        cache,
-    ))
 }
 
-fn create_app_router(config: &AppConfig, state: AppState, rate_limiter: RateLimiter) -> Router {
-    let public_routes = Router::new().route("/health", get(health::health_check));
-
-    let protected_routes = Router::new()
-        .route("/metrics", get(metrics::metrics_handler))
-        .route(
-            "/metrics/prometheus",
-            get(metrics::prometheus_metrics_handler),
-        )
-        .route("/v1/chat/completions", post(chat::chat_completions))
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            auth_middleware,
-        ))
-        .layer(middleware::from_fn_with_state(
-            rate_limiter,
-            rate_limit_middleware,
-        ));
-
-    Router::new()
-        .merge(public_routes)
-        .merge(protected_routes)
-        .layer(tower_http::limit::RequestBodyLimitLayer::new(
-            config.server.max_request_size,
-        ))
-        .layer(tower_http::compression::CompressionLayer::new())
-        .layer(middleware::from_fn(security_headers_middleware))
-        .layer(middleware::from_fn(api_version_middleware))
-        .layer(tower_http::trace::TraceLayer::new_for_http())
-        .with_state(state)
-}
-
-async fn run_server(
-    app: Router,
-    host: &str,
-    port: u16,
-    mut shutdown_rx: oneshot::Receiver<()>,
-) -> anyhow::Result<()> {
-    let addr: SocketAddr = format!("{host}:{port}")
-        .parse()
-        .map_err(|e| anyhow::anyhow!("Invalid server address {host}:{port}: {e}"))?;
-
-    info!("Listening on {addr}");
-
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-
-    let shutdown = async move {
-        tokio::select! {
-            () = setup_shutdown_signal() => {},
-            _ = &mut shutdown_rx => {},
+/// Decides whether the stdin command loop should run.
+///
+/// The CLI is disabled by `--no-interactive` or when stdin is not a terminal
+/// (containers, systemd units, piped input).
+fn interactive_enabled<I: IntoIterator<Item = String>>(args: I, stdin_is_tty: bool) -> bool {
+    stdin_is_tty && !args.into_iter().any(|arg| arg == "--no-interactive")
+}
+
+/// Reads stdin on a dedicated OS thread.
+///
+/// tokio's stdin performs an uncancellable blocking read on the runtime's blocking
+/// pool, which keeps runtime shutdown waiting for the next line of input. A plain
+/// thread is simply abandoned when the process exits.
+fn spawn_stdin_reader() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to read CLI input: {e}");
+                    break;
+                }
+            }
         }
-    };
-
-    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown);
-
-    if let Err(e) = server.await {
-        error!("Server error: {e}");
-        return Err(anyhow::anyhow!("Server failed: {e}"));
-    }
-
-    info!("Server shutdown complete");
-    Ok(())
+    });
+    rx
 }
 
-async fn run_command_loop(ctx: CliContext, shutdown_tx: oneshot::Sender<()>) -> anyhow::Result<()> {
-    let stdin = io::stdin();
-    let reader = BufReader::new(stdin);
-    let mut lines = reader.lines();
+async fn run_command_loop(
+    ctx: CliContext,
+    mut lines: mpsc::UnboundedReceiver<String>,
+    shutdown_tx: oneshot::Sender<()>,
+) {
     let mut shutdown_tx = Some(shutdown_tx);
 
     println!("Interactive CLI ready. Type /help for available commands.");
 
-    while let Some(line) = lines.next_line().await? {
+    while let Some(line) = lines.recv().await {
         if line.trim().is_empty() {
             continue;
         }
@@ -736,14 +732,18 @@ async fn run_command_loop(ctx: CliContext, shutdown_tx: oneshot::Sender<()>) ->
             break;
         }
     }
-
-    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     vertex_bridge::services::flags::FeatureFlags::init();
 
+    if std::env::args().nth(1).as_deref() == Some("doctor") {
+        let report = doctor::run(AppConfig::new().map_err(|e| e.to_string())).await;
+        println!("{report}");
+        std::process::exit(i32::from(!report.passed()));
+    }
+
     let config = AppConfig::new()
         .map_err(|e| {
             anyhow::anyhow!(
@@ -751,7 +751,7 @@ async fn main() -> anyhow::Result<()> {
             )
         })?;
 
-    let log_handle = Some(setup_logging(&config));
+    let log_control = Some(log_control::init(&config.log));
 
     info!("Starting Vertex Bridge v{}", env!("CARGO_PKG_VERSION"));
     info!(
@@ -759,38 +759,38 @@ async fn main() -> anyhow::Result<()> {
         config.server.host, config.server.port
     );
 
-    let (token_manager, rate_limiter, circuit_breaker, metrics, provider_registry, cache) =
-        initialize_services(&config)?;
-
-    let state = AppState {
-        config: Arc::new(config.clone()),
-        token_manager,
-        provider_registry,
-        rate_limiter: rate_limiter.clone(),
-        circuit_breaker,
-        metrics,
-        cache,
-    };
-
-    let app = create_app_router(&config, state.clone(), rate_limiter);
+    let server = Server::builder(config).build().await?;
 
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
-    let cli_context = CliContext {
-        state: state.clone(),
-        log_handle,
-    };
-    tokio::spawn(async move {
-        if let Err(e) = run_command_loop(cli_context, shutdown_tx).await {
-            warn!("CLI loop terminated with error: {e}");
-        }
-    });
+    if interactive_enabled(std::env::args().skip(1), std::io::stdin().is_terminal()) {
+        let cli_context = CliContext {
+            state: server.state().clone(),
+            log_control,
+            access_log: server.access_log().clone(),
+            trace_task: Arc::new(Mutex::new(None)),
+        };
+        tokio::spawn(run_command_loop(
+            cli_context,
+            spawn_stdin_reader(),
+            shutdown_tx,
+        ));
+    } else {
+        info!("Interactive CLI disabled (stdin is not a TTY or --no-interactive was given)");
+        drop(shutdown_tx);
+    }
 
-    run_server(app, &config.server.host, config.server.port, shutdown_rx).await
+    server.serve(shutdown_signal(shutdown_rx)).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use vertex_bridge::middleware::rate_limit::RateLimiter;
+    use vertex_bridge::openai::circuit_breaker::CircuitBreaker;
+    use vertex_bridge::openai::metrics::Metrics;
+    use vertex_bridge::services::auth::TokenManager;
+    use vertex_bridge::services::cache::Cache;
+    use vertex_bridge::services::providers::ProviderRegistry;
 
     fn make_test_state() -> AppState {
         let config = AppConfig {
@@ -798,6 +798,10 @@ mod tests {
                 host: "127.0.0.1".to_string(),
                 port: 4000,
                 max_request_size: 1024 * 1024,
+                request_timeout_secs: 0,
+                shutdown_grace_secs: 30,
+                body_limits: Vec::new(),
+                reuse_port: false,
             },
             auth: vertex_bridge::config::AuthConfig {
                 require_auth: false,
@@ -810,6 +814,7 @@ mod tests {
                 credentials_file: None,
                 api_key_base_url: None,
                 oauth_base_url: None,
+                generation: Default::default(),
             },
             log: vertex_bridge::config::LogConfig {
                 level: "info".to_string(),
@@ -817,13 +822,20 @@ mod tests {
             },
             openai: vertex_bridge::config::OpenAIConfig {
                 harvester_url: "http://localhost:3001".to_string(),
+                backend_url: None,
                 access_token_ttl_secs: 3600,
                 arkose_token_ttl_secs: 120,
+                waf_cooldown: Default::default(),
             },
             anthropic: vertex_bridge::config::AnthropicConfig {
                 bridge_url: "http://localhost:4001".to_string(),
+                health_check_interval_secs: 30,
             },
             gemini_cli: vertex_bridge::config::GeminiCliConfig::default(),
+            ollama: vertex_bridge::config::OllamaConfig::default(),
+            deepseek: Default::default(),
+            bedrock: Default::default(),
+            openai_compatible: Default::default(),
             rate_limit: vertex_bridge::config::RateLimitConfig {
                 capacity: 100,
                 refill_per_second: 10,
@@ -832,11 +844,44 @@ mod tests {
                 failure_threshold: 10,
                 timeout_secs: 60,
                 success_threshold: 3,
+                persist: false,
+                state_file: None,
             },
             cache: vertex_bridge::config::CacheConfig {
                 enabled: false,
                 default_ttl_secs: 3600,
+                coalesce_requests: true,
+                vary_on_key: true,
             },
+            maintenance: Default::default(),
+            maintenance_mode: Default::default(),
+            warmup: Default::default(),
+            models: Default::default(),
+            keys: Default::default(),
+            scheduler: Default::default(),
+            alerts: Default::default(),
+            storage: Default::default(),
+            cluster: Default::default(),
+            limits: Default::default(),
+            upstream_headers: Default::default(),
+            upstream_clients: Default::default(),
+            model_policy: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_process: Default::default(),
+            mirror: Default::default(),
+            errors: Default::default(),
+            redaction: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            keepalive: Default::default(),
+            sse: Default::default(),
+            admin_signature: Default::default(),
+            model_discovery: Default::default(),
+            transcripts: Default::default(),
+            sampling: Default::default(),
+            slo: Default::default(),
         };
 
         let token_manager =
@@ -844,7 +889,9 @@ mod tests {
         let rate_limiter = RateLimiter::new(100, 10);
         let circuit_breaker = Arc::new(CircuitBreaker::new(10, 60, 3));
         let metrics = Arc::new(Metrics::new());
-        let provider_registry = Arc::new(ProviderRegistry::with_config(&None, &None));
+        let provider_registry = Arc::new(ProviderRegistry::with_config(
+            &None, &None, &None, &None, &None, &None,
+        ));
         let cache = Arc::new(Cache::new(false, 3600));
 
         AppState {
@@ -855,13 +902,43 @@ mod tests {
             circuit_breaker,
             metrics,
             cache,
+            in_flight: Default::default(),
+            waf_cooldown: Default::default(),
+            token_bindings: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            affinity: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_processor: Default::default(),
+            mirror: Default::default(),
+            shutdown: Default::default(),
+            routing_rules: Default::default(),
+            latency: Default::default(),
+            model_registry: Default::default(),
+            model_catalog: Default::default(),
+            transcripts: Default::default(),
+            slos: Default::default(),
+            key_store: Default::default(),
+            scheduler: Default::default(),
+            usage: Default::default(),
+            budgets: Default::default(),
+            notifier: Default::default(),
+            maintenance_mode: Default::default(),
+            status_page: Default::default(),
+            warmup: Default::default(),
+            store: None,
+            storage: None,
         }
     }
 
     fn make_test_ctx() -> CliContext {
         CliContext {
             state: make_test_state(),
-            log_handle: None,
+            log_control: None,
+            access_log: AccessLog::default(),
+            trace_task: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -880,6 +957,14 @@ mod tests {
         assert!(result.shutdown);
     }
 
+    #[tokio::test]
+    async fn command_logs_without_control() {
+        let ctx = make_test_ctx();
+        let result = process_command("/logs format json", &ctx).await;
+        assert!(result.message.contains("not available"));
+        assert!(!result.shutdown);
+    }
+
     #[tokio::test]
     async fn command_models_lists_prefixes() {
         let ctx = make_test_ctx();
@@ -889,4 +974,48 @@ mod tests {
         assert!(result.message.contains("claude-*"));
         assert!(!result.shutdown);
     }
+
+    #[tokio::test]
+    async fn command_trace_toggles_task() {
+        let ctx = make_test_ctx();
+
+        let result = process_command("/trace off", &ctx).await;
+        assert_eq!(result.message, "Trace is not running");
+
+        let result = process_command("/trace on gemini", &ctx).await;
+        assert!(result.message.contains("filter: gemini"));
+        assert!(ctx
+            .trace_task
+            .lock()
+            .expect("trace lock should not be poisoned")
+            .is_some());
+
+        let result = process_command("/trace off", &ctx).await;
+        assert_eq!(result.message, "Trace disabled");
+        assert!(!result.shutdown);
+    }
+
+    #[test]
+    fn interactive_enabled_respects_tty_and_flag() {
+        assert!(interactive_enabled(Vec::<String>::new(), true));
+        assert!(!interactive_enabled(Vec::<String>::new(), false));
+        assert!(!interactive_enabled(
+            vec!["--no-interactive".to_string()],
+            true
+        ));
+    }
+
+    #[tokio::test]
+    async fn command_loop_exits_on_closed_input_without_shutdown() {
+        let (lines_tx, lines_rx) = mpsc::unbounded_channel();
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+        drop(lines_tx);
+
+        run_command_loop(make_test_ctx(), lines_rx, shutdown_tx).await;
+
+        assert!(matches!(
+            shutdown_rx.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        ));
+    }
 }
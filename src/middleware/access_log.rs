@@ -0,0 +1,183 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use std::time::Instant;
+use tokio::sync::broadcast;
+use tracing::info;
+
+use crate::middleware::rate_limit::extract_rate_limit_key;
+use crate::services::trace_context::TraceContext;
+
+const DEFAULT_CHANNEL_CAPACITY: usize = 256;
+
+/// Summary of a completed request, published to live subscribers (e.g. CLI `/trace`).
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogEntry {
+    pub method: String,
+    pub path: String,
+    pub model: Option<String>,
+    pub key: String,
+    pub status: u16,
+    pub latency_ms: u64,
+    pub request_id: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Formats the entry as a single console line.
+    #[must_use]
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} model={} key={} status={} latency={}ms request_id={}",
+            self.method,
+            self.path,
+            self.model.as_deref().unwrap_or("-"),
+            self.key,
+            self.status,
+            self.latency_ms,
+            self.request_id.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// Response extension set by handlers so the access log can report the requested model.
+#[derive(Debug, Clone)]
+pub struct RequestModel(pub String);
+
+/// Broadcast hub for access log entries.
+///
+/// Publishing never blocks; entries are dropped when nobody is subscribed and
+/// slow subscribers observe `RecvError::Lagged` instead of stalling requests.
+#[derive(Clone)]
+pub struct AccessLog {
+    sender: broadcast::Sender<AccessLogEntry>,
+}
+
+impl AccessLog {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<AccessLogEntry> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, entry: AccessLogEntry) {
+        // Err only means there are no subscribers, which is the common case
+        let _ = self.sender.send(entry);
+    }
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHANNEL_CAPACITY)
+    }
+}
+
+/// Access log middleware.
+///
+/// Records method, path, model, caller key, status, latency and request ID for
+/// every request and feeds the summary to the `AccessLog` broadcast channel.
+pub async fn access_log_middleware(
+    State(access_log): State<AccessLog>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let key = extract_rate_limit_key(&request);
+    let request_id = request
+        .extensions()
+        .get::<TraceContext>()
+        .map(|ctx| ctx.request_id.clone());
+
+    let response = next.run(request).await;
+
+    let latency_ms =
+        u64::try_from(start.elapsed().as_millis().min(u128::from(u64::MAX))).unwrap_or(u64::MAX);
+    let entry = AccessLogEntry {
+        method,
+        path,
+        model: response
+            .extensions()
+            .get::<RequestModel>()
+            .map(|m| m.0.clone()),
+        key,
+        status: response.status().as_u16(),
+        latency_ms,
+        request_id,
+    };
+
+    info!(target: "access_log", "{}", entry.summary());
+    access_log.publish(entry);
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, response::IntoResponse, Router};
+    use tower::util::ServiceExt;
+
+    #[tokio::test]
+    async fn test_access_log_publishes_entry() {
+        let access_log = AccessLog::default();
+        let mut rx = access_log.subscribe();
+
+        let app = Router::new()
+            .route(
+                "/v1/chat/completions",
+                axum::routing::post(|| async {
+                    let mut response = StatusCode::CREATED.into_response();
+                    response
+                        .extensions_mut()
+                        .insert(RequestModel("gemini-pro".to_string()));
+                    response
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                access_log.clone(),
+                access_log_middleware,
+            ));
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("x-real-ip", "10.0.0.1")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = app
+            .oneshot(req)
+            .await
+            .expect("request execution should succeed");
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let entry = rx.try_recv().expect("entry should be published");
+        assert_eq!(entry.method, "POST");
+        assert_eq!(entry.path, "/v1/chat/completions");
+        assert_eq!(entry.model.as_deref(), Some("gemini-pro"));
+        assert_eq!(entry.key, "10.0.0.1");
+        assert_eq!(entry.status, 201);
+    }
+
+    #[test]
+    fn test_publish_without_subscribers_is_noop() {
+        let access_log = AccessLog::new(4);
+        access_log.publish(AccessLogEntry {
+            method: "GET".to_string(),
+            path: "/health".to_string(),
+            model: None,
+            key: "unknown".to_string(),
+            status: 200,
+            latency_ms: 1,
+            request_id: None,
+        });
+    }
+}
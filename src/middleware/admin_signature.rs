@@ -0,0 +1,298 @@
+// Replay protection for mutating admin calls.
+//
+// Where TLS terminates in front of the proxy, anything between the terminator
+// and the proxy can capture an admin call and send it again. With
+// `admin_signature.required`, `POST`, `PUT`, `PATCH` and `DELETE` requests to
+// `/admin/*` must carry a timestamp, a nonce and an HMAC-SHA256 over both and
+// the request, keyed with the admin key presenting them. The proxy rejects
+// timestamps outside `max_skew_secs` and nonces it has already accepted
+// within that window, so a captured call cannot be sent twice.
+//
+// Nonces are remembered per process; replicas behind a load balancer each
+// accept a given nonce once.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{OriginalUri, Request, State},
+    http::{HeaderMap, Method},
+    middleware::Next,
+    response::Response,
+};
+use ring::hmac;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::config::AdminSignatureConfig;
+use crate::openai::errors::{map_error_with_code, CODE_INVALID_SIGNATURE};
+use crate::services::clock::{self, SharedClock};
+
+/// Unix seconds at which the request was signed.
+pub const TIMESTAMP_HEADER: &str = "x-admin-timestamp";
+/// Caller-chosen value, unique per request.
+pub const NONCE_HEADER: &str = "x-admin-nonce";
+/// Hex HMAC-SHA256 of [`canonical_request`], keyed with the admin key.
+pub const SIGNATURE_HEADER: &str = "x-admin-signature";
+
+const MAX_NONCE_LEN: usize = 128;
+
+/// The string signed for a request: method, path with query, timestamp,
+/// nonce and the hex SHA-256 of the body, one per line.
+#[must_use]
+pub fn canonical_request(
+    method: &Method,
+    path_and_query: &str,
+    timestamp: i64,
+    nonce: &str,
+    body: &[u8],
+) -> String {
+    let body_hash: String = Sha256::digest(body)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("{method}\n{path_and_query}\n{timestamp}\n{nonce}\n{body_hash}")
+}
+
+/// Hex HMAC-SHA256 of `canonical` keyed with `admin_key`.
+#[must_use]
+pub fn sign(admin_key: &str, canonical: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, admin_key.as_bytes());
+    hmac::sign(&key, canonical.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Verifies signed admin requests and remembers the nonces they used.
+pub struct AdminSignatures {
+    required: bool,
+    max_skew: Duration,
+    nonces: Mutex<HashMap<String, Instant>>,
+    clock: SharedClock,
+}
+
+impl AdminSignatures {
+    #[must_use]
+    pub fn from_config(config: &AdminSignatureConfig) -> Self {
+        Self {
+            required: config.required,
+            max_skew: Duration::from_secs(config.max_skew_secs),
+            nonces: Mutex::new(HashMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Checks the signature headers of a request to `path_and_query` with
+    /// `body`, made with `admin_key`, and records its nonce.
+    fn verify(
+        &self,
+        admin_key: &str,
+        method: &Method,
+        path_and_query: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), &'static str> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let (Some(timestamp), Some(nonce), Some(signature)) = (
+            header(TIMESTAMP_HEADER),
+            header(NONCE_HEADER),
+            header(SIGNATURE_HEADER),
+        ) else {
+            return Err("Admin request must be signed");
+        };
+        let timestamp: i64 = timestamp
+            .parse()
+            .map_err(|_| "Invalid admin request timestamp")?;
+        let skew = (self.clock.wall().timestamp() - timestamp).unsigned_abs();
+        if skew > self.max_skew.as_secs() {
+            return Err("Admin request timestamp is outside the allowed window");
+        }
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+            return Err("Invalid admin request nonce");
+        }
+        let canonical = canonical_request(method, path_and_query, timestamp, nonce, body);
+        let key = hmac::Key::new(hmac::HMAC_SHA256, admin_key.as_bytes());
+        let signature = decode_hex(signature).ok_or("Invalid admin request signature")?;
+        hmac::verify(&key, canonical.as_bytes(), &signature)
+            .map_err(|_| "Invalid admin request signature")?;
+
+        let now = self.clock.now();
+        let mut nonces = self
+            .nonces
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // A nonce only needs remembering while its timestamp could still pass
+        let retention = self.max_skew * 2;
+        nonces.retain(|_, seen| now.duration_since(*seen) <= retention);
+        if nonces.insert(nonce.to_string(), now).is_some() {
+            return Err("Admin request nonce was already used");
+        }
+        Ok(())
+    }
+}
+
+/// Requires a valid signature on mutating admin requests when
+/// `admin_signature.required` is set.
+///
+/// Must run after `auth_middleware`, as the signature is keyed with the bearer
+/// token it accepted. Rejects with a 401 OpenAI error envelope (code
+/// `invalid_signature`) if the signature is missing, stale, reused or wrong.
+pub async fn admin_signature_middleware(
+    State(signatures): State<Arc<AdminSignatures>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mutating = !matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    if !signatures.required || !mutating {
+        return next.run(request).await;
+    }
+    let admin_key = request
+        .headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .to_string();
+    // The path as the client sent it, before any prefix was stripped
+    let path_and_query = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or_else(|| request.uri().clone(), |uri| uri.0.clone())
+        .path_and_query()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    let (parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, usize::MAX).await else {
+        return map_error_with_code(400, "Failed to read request body", "invalid_request", None);
+    };
+    if let Err(message) = signatures.verify(
+        &admin_key,
+        &parts.method,
+        &path_and_query,
+        &parts.headers,
+        &body,
+    ) {
+        warn!("Rejecting admin request to {path_and_query}: {message}");
+        return map_error_with_code(401, message, CODE_INVALID_SIGNATURE, None);
+    }
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::clock::{Clock, ManualClock};
+    use axum::http::StatusCode;
+    use axum::{routing::put, Router};
+    use tower::util::ServiceExt;
+
+    const KEY: &str = "admin-key";
+
+    fn app(clock: Arc<ManualClock>) -> Router {
+        let signatures = AdminSignatures::from_config(&AdminSignatureConfig {
+            required: true,
+            max_skew_secs: 300,
+        })
+        .with_clock(clock);
+        Router::new()
+            .route(
+                "/admin/budgets/:key",
+                put(|| async { "ok" }).get(|| async { "ok" }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(signatures),
+                admin_signature_middleware,
+            ))
+    }
+
+    fn signed(method: Method, timestamp: i64, nonce: &str, key: &str) -> Request {
+        let path = "/admin/budgets/team-a";
+        let body = r#"{"daily_usd": 5}"#;
+        let canonical = canonical_request(&method, path, timestamp, nonce, body.as_bytes());
+        Request::builder()
+            .method(method)
+            .uri(path)
+            .header("authorization", format!("Bearer {KEY}"))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(NONCE_HEADER, nonce)
+            .header(SIGNATURE_HEADER, sign(key, &canonical))
+            .body(Body::from(body))
+            .expect("request should build")
+    }
+
+    #[tokio::test]
+    async fn test_signed_request_is_accepted_once() {
+        let clock = Arc::new(ManualClock::new());
+        let app = app(clock.clone());
+        let now = clock.wall().timestamp();
+
+        let response = app
+            .clone()
+            .oneshot(signed(Method::PUT, now, "n-1", KEY))
+            .await
+            .expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let replayed = app
+            .clone()
+            .oneshot(signed(Method::PUT, now, "n-1", KEY))
+            .await
+            .expect("request should succeed");
+        assert_eq!(replayed.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_bad_signatures_are_rejected() {
+        let clock = Arc::new(ManualClock::new());
+        let app = app(clock.clone());
+        let now = clock.wall().timestamp();
+
+        for request in [
+            signed(Method::PUT, now, "n-2", "other-key"),
+            signed(Method::PUT, now - 301, "n-3", KEY),
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/admin/budgets/team-a")
+                .body(Body::empty())
+                .expect("request should build"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(request)
+                .await
+                .expect("request should succeed");
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        // Reads need no signature
+        let read = Request::builder()
+            .uri("/admin/budgets/team-a")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = app.oneshot(read).await.expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -1,93 +1,115 @@
+use crate::openai::errors::map_error_with_status;
+use crate::services::keys::{hash_key, AuthenticatedKey};
+use crate::services::log_context;
 use crate::state::AppState;
-use axum::{
-    extract::State,
-    http::{Request, StatusCode},
-    middleware::Next,
-    response::Response,
-};
-use sha2::{Digest, Sha256};
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
 use subtle::ConstantTimeEq;
 use tracing::warn;
 
-fn hash_token(token: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(token.as_bytes());
-    format!("{:x}", hasher.finalize())
-}
-
 /// Authentication middleware for API requests.
 ///
 /// Validates Bearer tokens using constant-time comparison to prevent timing attacks.
-/// Supports optional authentication mode and master key authentication.
+/// Supports optional authentication mode, the master key, and per-client keys from
+/// the key store. The resolved `AuthenticatedKey` is attached to the request.
 ///
-/// # Errors
-///
-/// Returns `StatusCode::UNAUTHORIZED` if:
+/// Rejects with a 401 OpenAI error envelope if:
 /// - Authentication is required but no Authorization header is provided
 /// - The Authorization header is not in "Bearer <token>" format
-/// - The provided token does not match the master key
+/// - The provided token matches neither the master key nor a configured key
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    req: Request<axum::body::Body>,
+    mut req: Request<axum::body::Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Response {
     if !state.config.auth.require_auth {
-        return Ok(next.run(req).await);
+        let identity = AuthenticatedKey::anonymous();
+        log_context::record("key", &identity.name);
+        req.extensions_mut().insert(identity);
+        return next.run(req).await;
     }
 
-    let auth_header = req
+    let Some(auth_header) = req
         .headers()
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    if !auth_header.starts_with("Bearer ") {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    else {
+        return map_error_with_status(401, "Missing API key in Authorization header");
+    };
 
-    let token = auth_header
-        .strip_prefix("Bearer ")
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let Some(token) = auth_header.strip_prefix("Bearer ") else {
+        return map_error_with_status(401, "Authorization header must use the Bearer scheme");
+    };
 
     // Use constant-time comparison to prevent timing attacks
-    let token_hash = hash_token(token);
-    let master_key_hash = hash_token(&state.config.auth.master_key);
+    let token_hash = hash_key(token);
+    let master_key_hash = hash_key(&state.config.auth.master_key);
 
     // Compare hashes using constant-time comparison
     // ct_eq returns Choice which can be converted to bool
     let tokens_match = token_hash.as_bytes().ct_eq(master_key_hash.as_bytes());
-    if !bool::from(tokens_match) {
+    let identity = if bool::from(tokens_match) {
+        AuthenticatedKey::master()
+    } else if let Some(key) = state.key_store.authenticate(token) {
+        key.clone()
+    } else {
         warn!(
             "Invalid API Key attempt: {}...",
             &token_hash[..token_hash.len().min(8)]
         );
-        return Err(StatusCode::UNAUTHORIZED);
+        return map_error_with_status(401, "Incorrect API key provided");
+    };
+
+    log_context::record("key", &identity.name);
+    // Keys with their own Vertex identity are tenants, named by their project
+    if state
+        .key_store
+        .vertex_credentials()
+        .contains_key(&identity.name)
+    {
+        if let Some(project) = state.token_manager.for_key(&identity.name).get_project_id() {
+            log_context::record("tenant", project);
+        }
     }
+    req.extensions_mut().insert(identity);
+    next.run(req).await
+}
 
-    Ok(next.run(req).await)
+/// Restricts a route to admin callers (the master key or keys with `admin: true`).
+///
+/// Must run after `auth_middleware`. Non-admin callers get a 403 OpenAI error envelope.
+pub async fn admin_middleware(req: Request<axum::body::Body>, next: Next) -> Response {
+    let is_admin = req
+        .extensions()
+        .get::<AuthenticatedKey>()
+        .is_some_and(|k| k.admin);
+    if !is_admin {
+        return map_error_with_status(403, "This endpoint requires an admin key");
+    }
+    next.run(req).await
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::config::{
         AnthropicConfig, AppConfig, AuthConfig, CacheConfig, CircuitBreakerConfig, LogConfig,
         OpenAIConfig, RateLimitConfig, ServerConfig, VertexConfig,
     };
-    use axum::{
-        body::Body,
-        http::{Request, StatusCode},
-        Router,
-    };
+    use axum::http::StatusCode;
+    use axum::{body::Body, http::Request, Router};
     use std::sync::Arc;
     use tower::util::ServiceExt;
 
-    fn create_test_state(require_auth: bool, master_key: &str) -> AppState {
+    pub(crate) fn create_test_state(require_auth: bool, master_key: &str) -> AppState {
         let config = AppConfig {
             server: ServerConfig {
                 host: "127.0.0.1".to_string(),
                 port: 4000,
                 max_request_size: 10_000_000,
+                request_timeout_secs: 0,
+                shutdown_grace_secs: 30,
+                body_limits: Vec::new(),
+                reuse_port: false,
             },
             auth: AuthConfig {
                 require_auth,
@@ -100,6 +122,7 @@ mod tests {
                 credentials_file: None,
                 api_key_base_url: None,
                 oauth_base_url: None,
+                generation: Default::default(),
             },
             log: LogConfig {
                 level: "info".to_string(),
@@ -107,18 +130,26 @@ mod tests {
             },
             openai: OpenAIConfig {
                 harvester_url: "http://localhost:3001".to_string(),
+                backend_url: None,
                 access_token_ttl_secs: 3600,
                 arkose_token_ttl_secs: 120,
+                waf_cooldown: Default::default(),
             },
             anthropic: AnthropicConfig {
                 bridge_url: "http://localhost:4001".to_string(),
+                health_check_interval_secs: 30,
             },
             gemini_cli: crate::config::GeminiCliConfig {
                 enabled: false,
                 cli_path: None,
                 timeout_secs: 30,
                 max_concurrency: 4,
+                ..Default::default()
             },
+            ollama: crate::config::OllamaConfig::default(),
+            deepseek: Default::default(),
+            bedrock: Default::default(),
+            openai_compatible: Default::default(),
             rate_limit: RateLimitConfig {
                 capacity: 100,
                 refill_per_second: 10,
@@ -127,11 +158,44 @@ mod tests {
                 failure_threshold: 10,
                 timeout_secs: 60,
                 success_threshold: 3,
+                persist: false,
+                state_file: None,
             },
             cache: CacheConfig {
                 enabled: false,
                 default_ttl_secs: 3600,
+                coalesce_requests: true,
+                vary_on_key: true,
             },
+            maintenance: Default::default(),
+            maintenance_mode: Default::default(),
+            warmup: Default::default(),
+            models: Default::default(),
+            keys: Default::default(),
+            scheduler: Default::default(),
+            alerts: Default::default(),
+            storage: Default::default(),
+            cluster: Default::default(),
+            limits: Default::default(),
+            upstream_headers: Default::default(),
+            upstream_clients: Default::default(),
+            model_policy: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_process: Default::default(),
+            mirror: Default::default(),
+            errors: Default::default(),
+            redaction: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            keepalive: Default::default(),
+            sse: Default::default(),
+            admin_signature: Default::default(),
+            model_discovery: Default::default(),
+            transcripts: Default::default(),
+            sampling: Default::default(),
+            slo: Default::default(),
         };
 
         AppState {
@@ -139,7 +203,7 @@ mod tests {
             token_manager: crate::services::auth::TokenManager::new(None, None, None)
                 .expect("Failed to initialize TokenManager in test"),
             provider_registry: Arc::new(crate::services::providers::ProviderRegistry::with_config(
-                &None, &None,
+                &None, &None, &None, &None, &None, &None,
             )),
             rate_limiter: crate::middleware::rate_limit::RateLimiter::new(100, 10),
             circuit_breaker: Arc::new(crate::openai::circuit_breaker::CircuitBreaker::new(
@@ -147,6 +211,34 @@ mod tests {
             )),
             metrics: Arc::new(crate::openai::metrics::Metrics::new()),
             cache: Arc::new(crate::services::cache::Cache::new(false, 3600)),
+            in_flight: Default::default(),
+            waf_cooldown: Default::default(),
+            token_bindings: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            affinity: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_processor: Default::default(),
+            mirror: Default::default(),
+            shutdown: Default::default(),
+            routing_rules: Default::default(),
+            latency: Default::default(),
+            model_registry: Default::default(),
+            model_catalog: Default::default(),
+            transcripts: Default::default(),
+            slos: Default::default(),
+            key_store: Default::default(),
+            scheduler: Default::default(),
+            usage: Default::default(),
+            budgets: Default::default(),
+            notifier: Default::default(),
+            maintenance_mode: Default::default(),
+            status_page: Default::default(),
+            warmup: Default::default(),
+            store: None,
+            storage: None,
         }
     }
 
@@ -235,4 +327,43 @@ mod tests {
             .expect("request execution should succeed");
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_key_store_key_authenticates_but_is_not_admin() {
+        let mut state = create_test_state(true, "test-master-key-123");
+        state.key_store = Arc::new(crate::services::keys::KeyStore::from_definitions(
+            serde_json::from_str(r#"[{"name": "ci", "key": "sk-ci-key-0000000"}]"#)
+                .expect("keys should parse"),
+        ));
+        let app = Router::new()
+            .route("/test", axum::routing::get(|| async { StatusCode::OK }))
+            .route(
+                "/admin",
+                axum::routing::get(|| async { StatusCode::OK })
+                    .route_layer(axum::middleware::from_fn(admin_middleware)),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                auth_middleware,
+            ))
+            .with_state(state);
+
+        for (uri, token, expected) in [
+            ("/test", "sk-ci-key-0000000", StatusCode::OK),
+            ("/admin", "sk-ci-key-0000000", StatusCode::FORBIDDEN),
+            ("/admin", "test-master-key-123", StatusCode::OK),
+        ] {
+            let req = Request::builder()
+                .uri(uri)
+                .header("Authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .expect("request should build");
+            let response = app
+                .clone()
+                .oneshot(req)
+                .await
+                .expect("request execution should succeed");
+            assert_eq!(response.status(), expected, "{uri} with {token}");
+        }
+    }
 }
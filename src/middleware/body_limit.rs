@@ -0,0 +1,157 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+use crate::config::ServerConfig;
+use crate::openai::errors::map_error_with_status;
+
+/// One `path=bytes` entry from `server.body_limits`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BodyLimitRule {
+    /// Exact path, or a prefix when the configured path ends with `*`.
+    path: String,
+    prefix: bool,
+    limit: usize,
+}
+
+impl BodyLimitRule {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (path, limit) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("body limit '{spec}' must be in 'path=bytes' form"))?;
+        let path = path.trim();
+        if !path.starts_with('/') {
+            return Err(format!("body limit path '{path}' must start with '/'"));
+        }
+        let limit = limit
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| format!("invalid byte count in body limit '{spec}': {e}"))?;
+        let (path, prefix) = match path.strip_suffix('*') {
+            Some(prefix) => (prefix, true),
+            None => (path, false),
+        };
+        Ok(Self {
+            path: path.to_string(),
+            prefix,
+            limit,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        if self.prefix {
+            path.starts_with(&self.path)
+        } else {
+            path == self.path
+        }
+    }
+}
+
+/// Request body size budgets by route.
+///
+/// An exact path beats any prefix and a longer prefix beats a shorter one;
+/// paths without a rule fall back to `server.max_request_size`.
+#[derive(Debug, Clone)]
+pub struct BodyLimits {
+    default: usize,
+    rules: Vec<BodyLimitRule>,
+}
+
+impl BodyLimits {
+    /// # Errors
+    ///
+    /// Returns a description of the first malformed `server.body_limits` entry.
+    pub fn from_config(config: &ServerConfig) -> Result<Self, String> {
+        let rules = config
+            .body_limits
+            .iter()
+            .map(|spec| BodyLimitRule::parse(spec))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            default: config.max_request_size,
+            rules,
+        })
+    }
+
+    /// The largest body accepted for `path`.
+    #[must_use]
+    pub fn limit_for(&self, path: &str) -> usize {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(path))
+            .max_by_key(|rule| (!rule.prefix, rule.path.len()))
+            .map_or(self.default, |rule| rule.limit)
+    }
+}
+
+/// Per-route request body limit middleware.
+///
+/// Rejects bodies whose declared `Content-Length` exceeds the route's budget
+/// with 413 up front, and caps the body stream for chunked uploads so reading
+/// past the budget fails (JSON extractors turn that into a 413 as well).
+pub async fn body_limit_middleware(
+    State(limits): State<Arc<BodyLimits>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limit = limits.limit_for(request.uri().path());
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|len| len > limit as u64) {
+        return map_error_with_status(
+            413,
+            &format!("Request body exceeds the {limit} byte limit for this endpoint"),
+        );
+    }
+    let request = request.map(|body| Body::new(http_body_util::Limited::new(body, limit)));
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(specs: &[&str]) -> Result<BodyLimits, String> {
+        let config = ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 4000,
+            max_request_size: 1_000,
+            request_timeout_secs: 0,
+            shutdown_grace_secs: 30,
+            body_limits: specs.iter().map(ToString::to_string).collect(),
+            reuse_port: false,
+        };
+        BodyLimits::from_config(&config)
+    }
+
+    #[test]
+    fn test_most_specific_rule_wins() {
+        let limits = limits(&[
+            "/v1/*=5000",
+            "/v1/chat/completions=50000",
+            "/v1/audio/*=90000",
+            "/admin/*=100",
+        ])
+        .expect("specs should parse");
+        assert_eq!(limits.limit_for("/v1/chat/completions"), 50_000);
+        assert_eq!(limits.limit_for("/v1/audio/transcriptions"), 90_000);
+        assert_eq!(limits.limit_for("/v1/moderations"), 5_000);
+        assert_eq!(limits.limit_for("/admin/budgets/team"), 100);
+        assert_eq!(limits.limit_for("/health"), 1_000);
+    }
+
+    #[test]
+    fn test_invalid_specs_rejected() {
+        assert!(limits(&["/v1/chat/completions"]).is_err());
+        assert!(limits(&["v1/chat=10"]).is_err());
+        assert!(limits(&["/v1/chat=ten"]).is_err());
+    }
+}
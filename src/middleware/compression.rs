@@ -0,0 +1,158 @@
+use axum::{
+    body::HttpBody,
+    extract::Request,
+    http::{self, header::CONTENT_TYPE, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::compression::{
+    predicate::{And, NotForContentType, Predicate, SizeAbove},
+    CompressionLayer,
+};
+
+// Responses smaller than this gain nothing from compression
+const MIN_COMPRESS_SIZE: u16 = 32;
+
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// Response extension that keeps [`compression_layer`] from encoding a
+/// response whose bytes must reach the client as they are written, such as
+/// keep-alive padding.
+#[derive(Debug, Clone, Copy)]
+pub struct SkipCompression;
+
+/// Passes responses not marked [`SkipCompression`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotSkipped;
+
+impl Predicate for NotSkipped {
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        response.extensions().get::<SkipCompression>().is_none()
+    }
+}
+
+/// Which responses [`compression_layer`] compresses.
+pub type CompressionPredicate = And<
+    And<And<And<SizeAbove, NotForContentType>, NotForContentType>, NotForContentType>,
+    NotSkipped,
+>;
+
+/// Gzip/brotli for JSON and other buffered responses.
+///
+/// Server-sent events and responses marked [`SkipCompression`] are left
+/// alone: an encoder holds bytes until its block fills, so a compressed
+/// stream reaches the client in bursts instead of one chunk per event.
+#[must_use]
+pub fn compression_layer() -> CompressionLayer<CompressionPredicate> {
+    CompressionLayer::new().compress_when(
+        SizeAbove::new(MIN_COMPRESS_SIZE)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE)
+            .and(NotSkipped),
+    )
+}
+
+/// Marks event streams `X-Accel-Buffering: no` so nginx and compatible
+/// proxies forward each event as it arrives.
+pub async fn stream_buffering_middleware(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let is_event_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(EVENT_STREAM));
+    if is_event_stream {
+        response.headers_mut().insert(
+            HeaderName::from_static("x-accel-buffering"),
+            HeaderValue::from_static("no"),
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::header::{ACCEPT_ENCODING, CONTENT_ENCODING},
+        middleware,
+        response::{sse::Event, IntoResponse, Sse},
+        routing::get,
+        Json, Router,
+    };
+    use futures::stream;
+    use std::convert::Infallible;
+    use tower::util::ServiceExt;
+
+    fn app() -> Router {
+        let text = "x".repeat(1_024);
+        Router::new()
+            .route(
+                "/json",
+                get({
+                    let text = text.clone();
+                    move || async move { Json(serde_json::json!({ "text": text })) }
+                }),
+            )
+            .route(
+                "/skipped",
+                get({
+                    let text = text.clone();
+                    move || async move {
+                        let mut response =
+                            Json(serde_json::json!({ "text": text })).into_response();
+                        response.extensions_mut().insert(SkipCompression);
+                        response
+                    }
+                }),
+            )
+            .route(
+                "/stream",
+                get(move || async move {
+                    Sse::new(stream::iter([Ok::<_, Infallible>(
+                        Event::default().data(text),
+                    )]))
+                    .into_response()
+                }),
+            )
+            .layer(compression_layer())
+            .layer(middleware::from_fn(stream_buffering_middleware))
+    }
+
+    async fn get_gzip(path: &str) -> Response {
+        app()
+            .oneshot(
+                Request::get(path)
+                    .header(ACCEPT_ENCODING, "gzip")
+                    .body(Body::empty())
+                    .expect("request should build"),
+            )
+            .await
+            .expect("request should succeed")
+    }
+
+    #[tokio::test]
+    async fn test_json_is_compressed() {
+        let response = get_gzip("/json").await;
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert!(response.headers().get("x-accel-buffering").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_marked_response_is_not_compressed() {
+        let response = get_gzip("/skipped").await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_event_stream_is_not_compressed() {
+        let response = get_gzip("/stream").await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()["x-accel-buffering"], "no");
+    }
+}
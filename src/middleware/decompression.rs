@@ -0,0 +1,100 @@
+use async_compression::tokio::bufread::{GzipDecoder, ZlibDecoder, ZstdDecoder};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::header::{CONTENT_ENCODING, CONTENT_LENGTH},
+    middleware::Next,
+    response::Response,
+};
+use futures::TryStreamExt;
+use std::{pin::Pin, sync::Arc};
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::middleware::body_limit::BodyLimits;
+use crate::openai::errors::map_error_with_status;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Identity,
+    Gzip,
+    Deflate,
+    Zstd,
+}
+
+impl Encoding {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Some(Self::Identity),
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Inflates `Content-Encoding: gzip|deflate|zstd` request bodies.
+///
+/// The decompressed stream is capped at the route's body limit, so a small
+/// compressed payload can't expand past it (the handler sees a 413 once the
+/// cap is hit). The wire size is still checked by [`body_limit_middleware`].
+///
+/// [`body_limit_middleware`]: crate::middleware::body_limit::body_limit_middleware
+pub async fn decompression_middleware(
+    State(limits): State<Arc<BodyLimits>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(value) = request.headers().get(CONTENT_ENCODING) else {
+        return next.run(request).await;
+    };
+    let encoding = match value.to_str().ok().and_then(Encoding::parse) {
+        Some(Encoding::Identity) => return next.run(request).await,
+        Some(encoding) => encoding,
+        None => {
+            return map_error_with_status(
+                415,
+                "Unsupported Content-Encoding; use gzip, deflate or zstd",
+            )
+        }
+    };
+
+    let limit = limits.limit_for(request.uri().path());
+    let (mut parts, body) = request.into_parts();
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+
+    let reader = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+    let decoded: Pin<Box<dyn AsyncRead + Send>> = match encoding {
+        Encoding::Gzip => {
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+            Box::pin(decoder)
+        }
+        Encoding::Deflate => Box::pin(ZlibDecoder::new(reader)),
+        Encoding::Zstd => Box::pin(ZstdDecoder::new(reader)),
+        Encoding::Identity => Box::pin(reader),
+    };
+    let body = Body::new(http_body_util::Limited::new(
+        Body::from_stream(ReaderStream::new(decoded)),
+        limit,
+    ));
+    next.run(Request::from_parts(parts, body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_parse() {
+        assert_eq!(Encoding::parse("gzip"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::parse(" X-GZIP "), Some(Encoding::Gzip));
+        assert_eq!(Encoding::parse("deflate"), Some(Encoding::Deflate));
+        assert_eq!(Encoding::parse("zstd"), Some(Encoding::Zstd));
+        assert_eq!(Encoding::parse("identity"), Some(Encoding::Identity));
+        assert_eq!(Encoding::parse("br"), None);
+        assert_eq!(Encoding::parse("gzip, zstd"), None);
+    }
+}
@@ -0,0 +1,41 @@
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+
+use crate::openai::errors::{map_error_with_code, CODE_MAINTENANCE};
+use crate::state::AppState;
+
+// Health, metrics, usage and admin routes stay up during maintenance
+const DATA_PLANE_PREFIX: &str = "/v1/";
+
+/// Answers data-plane requests with 503 and `Retry-After` while a maintenance
+/// window is open.
+pub async fn maintenance_mode_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !request.uri().path().starts_with(DATA_PLANE_PREFIX) {
+        return next.run(request).await;
+    }
+    let Some(window) = state.maintenance_mode.active().await else {
+        return next.run(request).await;
+    };
+
+    let defaults = &state.config.maintenance_mode;
+    let message = window
+        .settings
+        .message
+        .as_deref()
+        .unwrap_or(&defaults.message);
+    let mut response = map_error_with_code(503, message, CODE_MAINTENANCE, None);
+    let retry_after = window.retry_after_secs(defaults.retry_after_secs, Utc::now());
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
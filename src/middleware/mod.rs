@@ -1,4 +1,12 @@
+pub mod access_log;
+pub mod admin_signature;
 pub mod api_version;
 pub mod auth;
+pub mod body_limit;
+pub mod compression;
+pub mod decompression;
+pub mod maintenance_mode;
+pub mod priority;
 pub mod rate_limit;
 pub mod security_headers;
+pub mod trace_context;
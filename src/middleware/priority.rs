@@ -0,0 +1,156 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures::StreamExt;
+
+use crate::openai::errors::map_error_with_status;
+use crate::services::keys::AuthenticatedKey;
+use crate::services::scheduler::Priority;
+use crate::state::AppState;
+
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+/// Priority scheduling middleware.
+///
+/// Parses `X-Priority` (default `normal`), checks it against the caller's
+/// `max_priority` permission, then waits for a scheduler slot. The slot is held
+/// until the response body (including a streamed one) has been fully sent.
+///
+/// Must run after `auth_middleware` so the caller's `AuthenticatedKey` is set.
+pub async fn priority_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let priority = match request
+        .headers()
+        .get(PRIORITY_HEADER)
+        .map(|v| v.to_str().map_err(|e| e.to_string()))
+    {
+        None => Priority::default(),
+        Some(Ok(value)) => match value.parse::<Priority>() {
+            Ok(p) => p,
+            Err(e) => return map_error_with_status(400, &e),
+        },
+        Some(Err(e)) => return map_error_with_status(400, &format!("Invalid X-Priority: {e}")),
+    };
+
+    // Without auth info (middleware misordered) fall back to the most restrictive default
+    let max_priority = request
+        .extensions()
+        .get::<AuthenticatedKey>()
+        .map_or(Priority::default(), |k| k.max_priority);
+    if priority > max_priority {
+        return map_error_with_status(
+            403,
+            &format!(
+                "Priority '{}' exceeds this key's maximum of '{}'",
+                priority.as_str(),
+                max_priority.as_str()
+            ),
+        );
+    }
+
+    let permit = state.scheduler.acquire(priority).await;
+    request.extensions_mut().insert(priority);
+
+    let response = next.run(request).await;
+    let (parts, body) = response.into_parts();
+    let body = Body::from_stream(body.into_data_stream().map(move |chunk| {
+        let _hold = &permit;
+        chunk
+    }));
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::scheduler::PriorityScheduler;
+    use axum::{http::StatusCode, Router};
+    use std::sync::Arc;
+    use tower::util::ServiceExt;
+
+    fn app_with_key(key: AuthenticatedKey, scheduler: Arc<PriorityScheduler>) -> Router {
+        let mut state = crate::middleware::auth::tests::create_test_state(false, "");
+        state.scheduler = scheduler;
+        Router::new()
+            .route(
+                "/v1/chat/completions",
+                axum::routing::post(|| async { StatusCode::OK }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                priority_middleware,
+            ))
+            .layer(axum::Extension(key))
+            .with_state(state)
+    }
+
+    fn request(priority: Option<&str>) -> Request {
+        let mut builder = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions");
+        if let Some(p) = priority {
+            builder = builder.header(PRIORITY_HEADER, p);
+        }
+        builder.body(Body::empty()).expect("request should build")
+    }
+
+    #[tokio::test]
+    async fn test_priority_rejected_above_key_permission() {
+        let key = AuthenticatedKey {
+            name: "batch".to_string(),
+            max_priority: Priority::Low,
+            admin: false,
+            exempt: false,
+        };
+        let app = app_with_key(key, Arc::new(PriorityScheduler::new(4)));
+
+        let response = app
+            .clone()
+            .oneshot(request(Some("high")))
+            .await
+            .expect("request execution should succeed");
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app
+            .oneshot(request(Some("low")))
+            .await
+            .expect("request execution should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_priority_is_bad_request() {
+        let app = app_with_key(
+            AuthenticatedKey::master(),
+            Arc::new(PriorityScheduler::new(4)),
+        );
+        let response = app
+            .oneshot(request(Some("urgent")))
+            .await
+            .expect("request execution should succeed");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_permit_released_after_body_consumed() {
+        let scheduler = Arc::new(PriorityScheduler::new(1));
+        let app = app_with_key(AuthenticatedKey::master(), Arc::clone(&scheduler));
+
+        let response = app
+            .oneshot(request(None))
+            .await
+            .expect("request execution should succeed");
+        assert_eq!(scheduler.stats().in_flight, 1);
+
+        axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .expect("body should be readable");
+        assert_eq!(scheduler.stats().in_flight, 0);
+    }
+}
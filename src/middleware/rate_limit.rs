@@ -4,25 +4,37 @@ use axum::{
     middleware::Next,
     response::Response,
 };
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     net::IpAddr,
     sync::Arc,
-    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant},
 };
 use tokio::sync::RwLock;
 use tracing::{error, warn};
+use utoipa::ToSchema;
 
-const CLEANUP_INTERVAL: Duration = Duration::from_secs(300);
+use crate::openai::errors::map_error_with_status;
+use crate::services::clock::{self, SharedClock};
+use crate::services::keys::hash_key;
+use crate::services::storage::SharedStorage;
+
+// Buckets idle for longer than this are dropped by the maintenance sweep
+const BUCKET_IDLE_EXPIRY: Duration = Duration::from_secs(600);
 const MAX_BUCKETS: usize = 10_000;
+// Rejections older than this no longer count as recent
+const RECENT_REJECT_WINDOW: Duration = Duration::from_secs(300);
 const UNKNOWN_KEY: &str = "unknown";
+// Shared buckets are stored under `ratelimit:<key>`
+const STORAGE_PREFIX: &str = "ratelimit:";
 
 fn is_valid_ip(ip_str: &str) -> bool {
     ip_str.parse::<IpAddr>().is_ok()
 }
 
-fn extract_rate_limit_key(request: &Request) -> String {
+pub(crate) fn extract_rate_limit_key(request: &Request) -> String {
     // SECURITY: Hash authorization token instead of using it directly
     // This prevents token exposure in logs/metrics and enumeration attacks
     if let Some(auth_header) = request
@@ -94,19 +106,42 @@ fn extract_rate_limit_key(request: &Request) -> String {
 ///
 /// Uses SHA256-hashed auth tokens as keys to prevent token exposure.
 /// Implements LRU eviction for memory efficiency.
+///
+/// With a shared store attached (cluster mode) token counts live in the
+/// [`Storage`](crate::services::storage::Storage) backend and the local
+/// buckets only mirror them for headers and stats.
+///
+/// Requests bearing an exempt key pass without drawing from any bucket.
 #[derive(Clone)]
 pub struct RateLimiter {
     buckets: Arc<RwLock<HashMap<String, TokenBucket>>>,
     capacity: u32,
     refill_rate: Duration,
-    last_cleanup: Arc<RwLock<Instant>>,
+    shared: Option<SharedStorage>,
+    exempt: Arc<HashSet<String>>,
+    clock: SharedClock,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct RateLimitStats {
     pub capacity: u32,
     pub refill_per_second: u32,
     pub active_keys: usize,
+    /// Busiest keys, most requests first.
+    pub top_keys: Vec<KeyRateLimitStats>,
+}
+
+/// Consumption of a single rate limit key since its bucket was created.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KeyRateLimitStats {
+    /// Client IP, or `auth:` plus a hash of the caller's credentials.
+    pub key: String,
+    pub tokens: u32,
+    pub allowed: u64,
+    pub rejected: u64,
+    /// Rejections within the last five minutes.
+    pub recent_rejects: u32,
+    pub idle_secs: u64,
 }
 
 #[derive(Clone)]
@@ -114,6 +149,41 @@ struct TokenBucket {
     tokens: u32,
     last_refill: Instant,
     last_access: Instant, // Track last access for LRU eviction
+    allowed: u64,
+    rejected: u64,
+    recent_rejects: u32,
+    reject_window_start: Instant,
+}
+
+impl TokenBucket {
+    fn full(capacity: u32, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: now,
+            last_access: now,
+            allowed: 0,
+            rejected: 0,
+            recent_rejects: 0,
+            reject_window_start: now,
+        }
+    }
+
+    fn record_reject(&mut self, now: Instant) {
+        self.rejected += 1;
+        if now.duration_since(self.reject_window_start) > RECENT_REJECT_WINDOW {
+            self.reject_window_start = now;
+            self.recent_rejects = 0;
+        }
+        self.recent_rejects = self.recent_rejects.saturating_add(1);
+    }
+
+    fn recent_rejects(&self, now: Instant) -> u32 {
+        if now.duration_since(self.reject_window_start) > RECENT_REJECT_WINDOW {
+            0
+        } else {
+            self.recent_rejects
+        }
+    }
 }
 
 /// Information about current rate limit status for a request.
@@ -133,7 +203,71 @@ impl RateLimiter {
             buckets: Arc::new(RwLock::new(HashMap::new())),
             capacity,
             refill_rate: Duration::from_secs(1) / refill_per_second,
-            last_cleanup: Arc::new(RwLock::new(Instant::now())),
+            shared: None,
+            exempt: Arc::new(HashSet::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Lets requests bearing the keys with these digests (see
+    /// [`KeyStore::exempt_key_hashes`](crate::services::keys::KeyStore::exempt_key_hashes))
+    /// through unlimited.
+    #[must_use]
+    pub fn with_exempt_keys(mut self, key_hashes: HashSet<String>) -> Self {
+        self.exempt = Arc::new(key_hashes);
+        self
+    }
+
+    /// Whether `request` bears an exempt key.
+    fn is_exempt(&self, request: &Request) -> bool {
+        !self.exempt.is_empty()
+            && request
+                .headers()
+                .get("authorization")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .is_some_and(|token| self.exempt.contains(&hash_key(token)))
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Keeps token buckets in `store` so all replicas draw from the same budget.
+    #[must_use]
+    pub fn with_shared_store(mut self, store: SharedStorage) -> Self {
+        self.shared = Some(store);
+        self
+    }
+
+    fn refill_per_second(&self) -> u32 {
+        let nanos = self.refill_rate.as_nanos();
+        if nanos == 0 {
+            return 0;
+        }
+        let rounded = (1_000_000_000u128 + nanos / 2) / nanos;
+        u32::try_from(rounded.min(u128::from(u32::MAX))).unwrap_or(u32::MAX)
+    }
+
+    async fn take_shared_token(&self, key: &str) -> Option<(bool, u32)> {
+        let store = self.shared.as_ref()?;
+        match store
+            .take_token(
+                &format!("{STORAGE_PREFIX}{key}"),
+                self.capacity,
+                self.refill_per_second(),
+                self.clock.wall(),
+            )
+            .await
+        {
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("Shared rate limit check failed, using the local bucket: {e:#}");
+                None
+            }
         }
     }
 
@@ -149,65 +283,75 @@ impl RateLimiter {
         u32::try_from(elapsed_nanos / refill_nanos).unwrap_or(u32::MAX)
     }
 
-    async fn cleanup_if_needed(&self) {
-        let mut last_cleanup = self.last_cleanup.write().await;
-        if last_cleanup.elapsed() >= CLEANUP_INTERVAL {
-            let mut buckets = self.buckets.write().await;
-            let initial_size = buckets.len();
-            let now = Instant::now();
-            let expiration_threshold = CLEANUP_INTERVAL * 2;
-
-            buckets
-                .retain(|_, bucket| now.duration_since(bucket.last_refill) <= expiration_threshold);
-
-            if buckets.len() > MAX_BUCKETS {
-                let to_remove = buckets.len() - MAX_BUCKETS;
-                // Fix non-deterministic cleanup: Use LRU eviction instead of arbitrary removal
-                // Sort buckets by last_access time and remove oldest ones
-                let mut bucket_entries: Vec<(String, Instant)> = buckets
-                    .iter()
-                    .map(|(k, v)| (k.clone(), v.last_access))
-                    .collect();
-                bucket_entries.sort_by_key(|(_, access_time)| *access_time);
-
-                let keys_to_remove: Vec<String> = bucket_entries
-                    .iter()
-                    .take(to_remove)
-                    .map(|(k, _)| k.clone())
-                    .collect();
-
-                for key in keys_to_remove {
-                    buckets.remove(&key);
-                }
-                warn!(
-                    "Rate limiter: removed {} oldest buckets (LRU) to enforce size limit",
-                    to_remove
-                );
+    /// Drops idle buckets and enforces the bucket cap, returning how many were removed.
+    ///
+    /// Called periodically by the maintenance task rather than on the request path.
+    pub async fn cleanup(&self) -> usize {
+        let mut buckets = self.buckets.write().await;
+        let initial_size = buckets.len();
+        let now = self.clock.now();
+
+        buckets.retain(|_, bucket| now.duration_since(bucket.last_refill) <= BUCKET_IDLE_EXPIRY);
+
+        if buckets.len() > MAX_BUCKETS {
+            let to_remove = buckets.len() - MAX_BUCKETS;
+            // Fix non-deterministic cleanup: Use LRU eviction instead of arbitrary removal
+            // Sort buckets by last_access time and remove oldest ones
+            let mut bucket_entries: Vec<(String, Instant)> = buckets
+                .iter()
+                .map(|(k, v)| (k.clone(), v.last_access))
+                .collect();
+            bucket_entries.sort_by_key(|(_, access_time)| *access_time);
+
+            let keys_to_remove: Vec<String> = bucket_entries
+                .iter()
+                .take(to_remove)
+                .map(|(k, _)| k.clone())
+                .collect();
+
+            for key in keys_to_remove {
+                buckets.remove(&key);
             }
-            *last_cleanup = Instant::now();
-            let removed = initial_size.saturating_sub(buckets.len());
-            if removed > 0 {
-                warn!("Rate limiter cleanup: {} expired buckets removed", removed);
+            warn!(
+                "Rate limiter: removed {} oldest buckets (LRU) to enforce size limit",
+                to_remove
+            );
+        }
+        let removed = initial_size.saturating_sub(buckets.len());
+        drop(buckets);
+        if removed > 0 {
+            warn!("Rate limiter cleanup: {} expired buckets removed", removed);
+        }
+        if let Some(store) = &self.shared {
+            if let Err(e) = store.purge_expired(STORAGE_PREFIX).await {
+                warn!("Failed to purge shared rate limit buckets: {e:#}");
             }
         }
+        removed
     }
 
     pub async fn check(&self, key: &str) -> bool {
-        self.cleanup_if_needed().await;
-
+        let shared = self.take_shared_token(key).await;
         let mut buckets = self.buckets.write().await;
-        let now = Instant::now();
+        let now = self.clock.now();
         let bucket = buckets
             .entry(key.to_string())
-            .or_insert_with(|| TokenBucket {
-                tokens: self.capacity,
-                last_refill: now,
-                last_access: now,
-            });
+            .or_insert_with(|| TokenBucket::full(self.capacity, now));
 
         // Update last access for LRU eviction
         bucket.last_access = now;
 
+        if let Some((allowed, remaining)) = shared {
+            bucket.tokens = remaining;
+            bucket.last_refill = now;
+            if allowed {
+                bucket.allowed += 1;
+            } else {
+                bucket.record_reject(now);
+            }
+            return allowed;
+        }
+
         let elapsed = now.duration_since(bucket.last_refill);
         let tokens_to_add = Self::calculate_tokens_to_add(elapsed, self.refill_rate);
 
@@ -218,8 +362,10 @@ impl RateLimiter {
 
         if bucket.tokens > 0 {
             bucket.tokens -= 1;
+            bucket.allowed += 1;
             true
         } else {
+            bucket.record_reject(now);
             false
         }
     }
@@ -227,13 +373,12 @@ impl RateLimiter {
     pub async fn get_info(&self, key: &str) -> RateLimitInfo {
         // Fix race condition: check() modifies bucket, so we need to read current state
         // after potential refill. We'll calculate based on current bucket state.
-        let now = Instant::now();
+        let now = self.clock.now();
         let buckets = self.buckets.read().await;
-        let bucket = buckets.get(key).cloned().unwrap_or(TokenBucket {
-            tokens: self.capacity,
-            last_refill: now,
-            last_access: now,
-        });
+        let bucket = buckets
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| TokenBucket::full(self.capacity, now));
 
         // Note: We don't update last_access here since get_info is read-only
         // Only check() updates last_access for LRU tracking
@@ -255,13 +400,10 @@ impl RateLimiter {
             0
         };
 
-        // Fix reset timestamp bug: use SystemTime instead of Instant::elapsed()
+        // Fix reset timestamp bug: use wall-clock time instead of Instant::elapsed()
         // reset should be Unix timestamp (seconds since epoch), not elapsed time
-        let reset_timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::ZERO)
-            .as_secs()
-            + reset_seconds;
+        let reset_timestamp =
+            u64::try_from(self.clock.wall().timestamp()).unwrap_or(0) + reset_seconds;
 
         RateLimitInfo {
             limit: self.capacity,
@@ -270,21 +412,39 @@ impl RateLimiter {
         }
     }
 
-    /// Returns a lightweight snapshot of limiter configuration and active bucket count.
-    pub async fn stats(&self) -> RateLimitStats {
+    /// Returns limiter configuration, the active bucket count and the `top`
+    /// keys by requests made (allowed plus rejected).
+    pub async fn stats(&self, top: usize) -> RateLimitStats {
         let buckets = self.buckets.read().await;
-        let per_second = if self.refill_rate.as_nanos() == 0 {
-            0
-        } else {
-            let nanos = self.refill_rate.as_nanos();
-            let rounded = (1_000_000_000u128 + nanos / 2) / nanos;
-            let capped = rounded.min(u128::from(u32::MAX));
-            u32::try_from(capped).unwrap_or(u32::MAX)
-        };
+        let now = self.clock.now();
+        let mut top_keys: Vec<KeyRateLimitStats> = buckets
+            .iter()
+            .map(|(key, bucket)| {
+                let refilled = Self::calculate_tokens_to_add(
+                    now.duration_since(bucket.last_refill),
+                    self.refill_rate,
+                );
+                KeyRateLimitStats {
+                    key: key.clone(),
+                    tokens: bucket.tokens.saturating_add(refilled).min(self.capacity),
+                    allowed: bucket.allowed,
+                    rejected: bucket.rejected,
+                    recent_rejects: bucket.recent_rejects(now),
+                    idle_secs: now.duration_since(bucket.last_access).as_secs(),
+                }
+            })
+            .collect();
+        top_keys.sort_by(|a, b| {
+            (b.allowed + b.rejected)
+                .cmp(&(a.allowed + a.rejected))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+        top_keys.truncate(top);
         RateLimitStats {
             capacity: self.capacity,
-            refill_per_second: per_second,
+            refill_per_second: self.refill_per_second(),
             active_keys: buckets.len(),
+            top_keys,
         }
     }
 }
@@ -331,6 +491,9 @@ pub async fn rate_limit_middleware(
     request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
+    if limiter.is_exempt(&request) {
+        return Ok(next.run(request).await);
+    }
     let key = extract_rate_limit_key(&request);
     // Fix race condition: call check() first to update bucket state, then get_info()
     let allowed = limiter.check(&key).await;
@@ -338,23 +501,11 @@ pub async fn rate_limit_middleware(
 
     if !allowed {
         warn!("Rate limit exceeded for key: {}", key);
-        let error_body = serde_json::json!({
-            "error": {
-                "message": "Rate limit exceeded",
-                "type": "rate_limit_error",
-                "code": "rate_limit_exceeded"
-            }
-        })
-        .to_string();
-
-        let mut response_builder = Response::builder()
-            .status(StatusCode::TOO_MANY_REQUESTS)
-            .header(axum::http::header::CONTENT_TYPE, "application/json");
-
+        let mut response = map_error_with_status(429, "Rate limit exceeded");
         match build_rate_limit_headers(&info) {
             Ok(headers) => {
                 for (name, value) in headers {
-                    response_builder = response_builder.header(name, value);
+                    response.headers_mut().insert(name, value);
                 }
             }
             Err(e) => {
@@ -362,11 +513,6 @@ pub async fn rate_limit_middleware(
                 return Err(StatusCode::INTERNAL_SERVER_ERROR);
             }
         }
-
-        let response = response_builder.body(error_body.into()).map_err(|e| {
-            error!("Failed to build rate limit response: {e}");
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
         return Ok(response);
     }
 
@@ -390,6 +536,8 @@ pub async fn rate_limit_middleware(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::clock::ManualClock;
+    use crate::services::sqlite_store::SqliteStore;
 
     #[test]
     fn test_is_valid_ip() {
@@ -414,7 +562,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_rate_limiter_refill() {
-        let limiter = RateLimiter::new(10, 10);
+        let clock = Arc::new(ManualClock::new());
+        let limiter = RateLimiter::new(10, 10).with_clock(clock.clone());
         let key = "test-key";
 
         for _ in 0..10 {
@@ -423,9 +572,50 @@ mod tests {
 
         assert!(!limiter.check(key).await);
 
-        tokio::time::sleep(Duration::from_millis(150)).await;
+        clock.advance(Duration::from_millis(99));
+        assert!(!limiter.check(key).await);
 
+        clock.advance(Duration::from_millis(1));
         assert!(limiter.check(key).await);
+        assert!(!limiter.check(key).await);
+    }
+
+    #[tokio::test]
+    async fn test_stats_rank_keys_by_requests() {
+        let limiter = RateLimiter::new(2, 1);
+        for _ in 0..5 {
+            limiter.check("abuser").await;
+        }
+        limiter.check("quiet").await;
+
+        let stats = limiter.stats(1).await;
+        assert_eq!(stats.active_keys, 2);
+        assert_eq!(stats.top_keys.len(), 1);
+        let top = &stats.top_keys[0];
+        assert_eq!(top.key, "abuser");
+        assert_eq!(top.allowed, 2);
+        assert_eq!(top.rejected, 3);
+        assert_eq!(top.recent_rejects, 3);
+        assert_eq!(top.tokens, 0);
+
+        let all = limiter.stats(10).await;
+        assert_eq!(all.top_keys[1].key, "quiet");
+        assert_eq!(all.top_keys[1].tokens, 1);
+    }
+
+    #[tokio::test]
+    async fn test_shared_store_limits_across_replicas() {
+        let store: SharedStorage =
+            Arc::new(SqliteStore::open_in_memory().expect("store should open"));
+        let replica_a = RateLimiter::new(3, 1).with_shared_store(Arc::clone(&store));
+        let replica_b = RateLimiter::new(3, 1).with_shared_store(store);
+
+        assert!(replica_a.check("key").await);
+        assert!(replica_b.check("key").await);
+        assert!(replica_a.check("key").await);
+        assert!(!replica_b.check("key").await);
+        assert_eq!(replica_b.get_info("key").await.remaining, 0);
+        assert_eq!(replica_b.stats(1).await.top_keys[0].rejected, 1);
     }
 
     #[test]
@@ -442,7 +632,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_rate_limiter_cleanup_expires_buckets() {
-        let limiter = RateLimiter::new(10, 5);
+        let clock = Arc::new(ManualClock::new());
+        let limiter = RateLimiter::new(10, 5).with_clock(clock.clone());
 
         limiter.check("key1").await;
         limiter.check("key2").await;
@@ -452,27 +643,43 @@ mod tests {
         assert_eq!(buckets.len(), 3);
         drop(buckets);
 
-        tokio::time::sleep(Duration::from_secs(1)).await;
-
-        let mut last_cleanup = limiter.last_cleanup.write().await;
-        *last_cleanup = Instant::now()
-            .checked_sub(CLEANUP_INTERVAL)
-            .and_then(|i| i.checked_sub(Duration::from_secs(1)))
-            .unwrap_or(Instant::now());
-        drop(last_cleanup);
-
-        let mut buckets = limiter.buckets.write().await;
-        let old_time = Instant::now()
-            .checked_sub(CLEANUP_INTERVAL * 3)
-            .unwrap_or(Instant::now());
-        for (_, bucket) in buckets.iter_mut() {
-            bucket.last_refill = old_time;
-        }
-        drop(buckets);
-
-        limiter.cleanup_if_needed().await;
+        clock.advance(BUCKET_IDLE_EXPIRY);
+        assert_eq!(limiter.cleanup().await, 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.cleanup().await, 3);
 
         let buckets = limiter.buckets.read().await;
         assert_eq!(buckets.len(), 0, "Expired buckets should be removed");
     }
+
+    #[tokio::test]
+    async fn test_exempt_key_is_not_limited() {
+        use axum::{body::Body, routing::get, Router};
+        use tower::util::ServiceExt;
+
+        let limiter =
+            RateLimiter::new(1, 1).with_exempt_keys(HashSet::from([hash_key("sk-probe")]));
+        let app = Router::new().route("/", get(|| async { "ok" })).layer(
+            axum::middleware::from_fn_with_state(limiter.clone(), rate_limit_middleware),
+        );
+        let call = |token: &str| {
+            let request = Request::builder()
+                .uri("/")
+                .header("authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .expect("request should build");
+            app.clone().oneshot(request)
+        };
+
+        for _ in 0..3 {
+            let response = call("sk-probe").await.expect("request should succeed");
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!(limiter.stats(10).await.active_keys, 0);
+
+        let response = call("sk-app").await.expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = call("sk-app").await.expect("request should succeed");
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
 }
@@ -20,12 +20,14 @@ pub async fn security_headers_middleware(request: Request, next: Next) -> Respon
     let mut response = next.run(request).await;
 
     // Content-Security-Policy: For API endpoints, use default-src 'none' to block all resources
-    // API endpoints typically don't need CSP, but if set, should be restrictive
+    // API endpoints typically don't need CSP, but if set, should be restrictive.
+    // Routes that serve pages (Swagger UI) set their own policy, which is kept.
     match HeaderValue::from_str("default-src 'none'") {
         Ok(header_value) => {
             response
                 .headers_mut()
-                .insert("Content-Security-Policy", header_value);
+                .entry("Content-Security-Policy")
+                .or_insert(header_value);
         }
         Err(e) => {
             warn!("Failed to create CSP header value: {}", e);
@@ -0,0 +1,77 @@
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use tracing::Instrument;
+
+use crate::services::log_context;
+use crate::services::trace_context::{TraceContext, REQUEST_ID_HEADER};
+
+/// Request ID and trace context middleware.
+///
+/// Joins the caller's `traceparent` (or starts a trace), keeps its
+/// `X-Request-ID` (or assigns one), and makes the context current while the
+/// request is handled so upstream calls carry it. The request ID is echoed in
+/// the `X-Request-ID` response header and the context is added to the request
+/// extensions for later middleware. The `request` span declares the key,
+/// tenant, model and provider, which later stages fill in through
+/// [`log_context::record`].
+pub async fn trace_context_middleware(mut request: Request, next: Next) -> Response {
+    let ctx = TraceContext::from_headers(request.headers());
+    request.extensions_mut().insert(ctx.clone());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %ctx.request_id,
+        trace_id = %ctx.trace_id,
+        span_id = %ctx.span_id,
+        key = tracing::field::Empty,
+        tenant = tracing::field::Empty,
+        model = tracing::field::Empty,
+        provider = tracing::field::Empty
+    );
+    let request_id = HeaderValue::from_str(&ctx.request_id).ok();
+
+    let handled = log_context::scope(span.clone(), ctx.scope(next.run(request)));
+    let mut response = handled.instrument(span).await;
+    if let Some(request_id) = request_id {
+        response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::trace_context::{propagation_headers, TRACEPARENT_HEADER};
+    use axum::{body::Body, routing::get, Router};
+    use tower::util::ServiceExt;
+
+    #[tokio::test]
+    async fn test_context_is_current_in_handler_and_id_echoed() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    propagation_headers()[TRACEPARENT_HEADER]
+                        .to_str()
+                        .map(str::to_string)
+                        .unwrap_or_default()
+                }),
+            )
+            .layer(axum::middleware::from_fn(trace_context_middleware));
+
+        let req = Request::builder()
+            .uri("/")
+            .header(
+                TRACEPARENT_HEADER,
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .header(REQUEST_ID_HEADER, "client-7")
+            .body(Body::empty())
+            .expect("request should build");
+        let response = app.oneshot(req).await.expect("request should succeed");
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-7");
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .expect("body should read");
+        let traceparent = String::from_utf8(body.to_vec()).expect("utf-8");
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    }
+}
@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::result::Result;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Role {
     System,
@@ -10,48 +13,166 @@ pub enum Role {
     Tool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[serde(from = "WireChatMessage")]
 pub struct ChatMessage {
     pub role: Role,
-    #[serde(deserialize_with = "deserialize_content")]
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Image parts in the client's multimodal content. Only text is forwarded
+    /// upstream, but routing rules can still see that images were sent.
+    #[serde(skip_serializing)]
+    pub images: usize,
+    /// Tools the assistant asked to call.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// The call a `tool` message answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
-fn deserialize_content<'de, D>(deserializer: D) -> Result<String, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    #[derive(Deserialize)]
-    #[serde(untagged)]
-    enum Content {
-        String(String),
-        Array(Vec<serde_json::Value>),
-    }
-
-    let content = Content::deserialize(deserializer)?;
-    match content {
-        Content::String(s) => Ok(s),
-        Content::Array(arr) => {
-            // Fix content deserialization limitation: Document that we only support text content
-            // Multimodal content (images, etc.) is not supported - only extracts "text" fields
-            // This is a known limitation of the current implementation
-            let parts: Vec<String> = arr
-                .into_iter()
-                .filter_map(|v| {
-                    // Extract text field if present (for text content)
-                    v.get("text")
-                        .and_then(|t| t.as_str())
-                        .map(std::string::ToString::to_string)
-                        // Fallback: if value is a string, use it directly
-                        .or_else(|| v.as_str().map(std::string::ToString::to_string))
-                })
-                .collect();
-            // Fix: Document that joining with "\n" may not be correct for all content types
-            // For text-only content, newline separator is appropriate
-            // For multimodal content, this would lose structure - limitation documented
-            Ok(parts.join("\n"))
+/// A function call requested by the model.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "default_tool_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments.
+    pub arguments: String,
+}
+
+/// Streamed fragment of a tool call; fragments with the same `index` belong
+/// to one call.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct ToolCallDelta {
+    pub index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub function: FunctionCallDelta,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default, ToSchema)]
+pub struct FunctionCallDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+/// Merges streamed tool call fragments into complete calls, in index order.
+#[must_use]
+pub fn merge_tool_call_deltas<'a>(
+    deltas: impl IntoIterator<Item = &'a ToolCallDelta>,
+) -> Vec<ToolCall> {
+    let mut calls: Vec<(u32, ToolCall)> = Vec::new();
+    for delta in deltas {
+        let position = match calls.iter().position(|(index, _)| *index == delta.index) {
+            Some(position) => position,
+            None => {
+                calls.push((
+                    delta.index,
+                    ToolCall {
+                        id: String::new(),
+                        kind: default_tool_type(),
+                        function: FunctionCall {
+                            name: String::new(),
+                            arguments: String::new(),
+                        },
+                    },
+                ));
+                calls.len() - 1
+            }
+        };
+        let call = &mut calls[position].1;
+        if let Some(id) = &delta.id {
+            call.id.clone_from(id);
+        }
+        if let Some(name) = &delta.function.name {
+            call.function.name.push_str(name);
+        }
+        if let Some(arguments) = &delta.function.arguments {
+            call.function.arguments.push_str(arguments);
+        }
+    }
+    calls.sort_by_key(|(index, _)| *index);
+    calls.into_iter().map(|(_, call)| call).collect()
+}
+
+fn default_tool_type() -> String {
+    "function".to_string()
+}
+
+/// A chat message as clients send it, before multimodal content is flattened.
+#[derive(Deserialize)]
+struct WireChatMessage {
+    role: Role,
+    // Null or absent on assistant messages that only call tools
+    #[serde(default)]
+    content: Option<Content>,
+    name: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default)]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Content {
+    String(String),
+    Array(Vec<serde_json::Value>),
+}
+
+impl From<WireChatMessage> for ChatMessage {
+    fn from(wire: WireChatMessage) -> Self {
+        let (content, images) = match wire.content.unwrap_or(Content::String(String::new())) {
+            Content::String(s) => (s, 0),
+            Content::Array(arr) => {
+                let images = arr
+                    .iter()
+                    .filter(|v| {
+                        v.get("type")
+                            .and_then(|t| t.as_str())
+                            .is_some_and(|t| t.starts_with("image") || t == "input_image")
+                    })
+                    .count();
+                // Fix content deserialization limitation: Document that we only support text content
+                // Multimodal content (images, etc.) is not supported - only extracts "text" fields
+                // This is a known limitation of the current implementation
+                let parts: Vec<String> = arr
+                    .into_iter()
+                    .filter_map(|v| {
+                        // Extract text field if present (for text content)
+                        v.get("text")
+                            .and_then(|t| t.as_str())
+                            .map(std::string::ToString::to_string)
+                            // Fallback: if value is a string, use it directly
+                            .or_else(|| v.as_str().map(std::string::ToString::to_string))
+                    })
+                    .collect();
+                // Fix: Document that joining with "\n" may not be correct for all content types
+                // For text-only content, newline separator is appropriate
+                // For multimodal content, this would lose structure - limitation documented
+                (parts.join("\n"), images)
+            }
+        };
+        Self {
+            role: wire.role,
+            content,
+            name: wire.name,
+            images,
+            tool_calls: wire.tool_calls,
+            tool_call_id: wire.tool_call_id,
         }
     }
 }
@@ -76,31 +197,119 @@ where
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ChatCompletionRequest {
     pub model: String,
+    #[serde(default)]
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
     pub stream: bool,
-    #[serde(default = "default_temperature")]
-    pub temperature: f32,
-    #[serde(default = "default_top_p")]
-    pub top_p: f32,
+    /// Omitted values are left to the provider: OpenAI's defaults
+    /// ([`DEFAULT_TEMPERATURE`], [`DEFAULT_TOP_P`]) for most, the configured
+    /// generation defaults for Vertex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Extension: sample from the `top_k` most likely tokens. Forwarded to
+    /// Vertex, the Anthropic bridge and OpenAI-compatible upstreams; other
+    /// providers ignore it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
     pub max_tokens: Option<u32>,
     #[serde(default, deserialize_with = "deserialize_stop")]
     pub stop: Option<Vec<String>>,
+    /// End-user identifier; also keys sticky sessions when no session header is sent.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Named prompt template whose messages are placed ahead of `messages`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<String>,
+    /// Values for the template's `{{name}}` placeholders.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, Value>,
+    /// Tool definitions. Forwarded to the Anthropic bridge, DeepSeek and
+    /// OpenAI-compatible upstreams;
+    /// routing rules can also send tool-using requests to a suitable provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Value>,
+    /// `auto`, `none`, `required` or a `{"type": "function", ...}` choice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    /// Extension: runs one completion per prompt, each sent as a final user
+    /// message after `messages` (which may then be empty), and returns their
+    /// choices in prompt order. Not available with `stream`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompts: Vec<String>,
+    /// Fields the proxy does not interpret, such as `response_format`, `seed`
+    /// or OpenRouter's `provider` and `models`. Forwarded as they are to
+    /// OpenAI-compatible upstreams; other providers ignore them.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct StreamOptions {
+    /// Report token usage on the final chunk of the stream.
+    #[serde(default)]
+    pub include_usage: bool,
 }
 
 impl ChatCompletionRequest {
+    /// Whether the request defines tools or carries earlier tool calls or results.
+    #[must_use]
+    pub fn uses_tools(&self) -> bool {
+        !self.tools.is_empty()
+            || self
+                .messages
+                .iter()
+                .any(|m| m.role == Role::Tool || !m.tool_calls.is_empty())
+    }
+
+    /// Whether a streaming client asked for usage on the final chunk.
+    #[must_use]
+    pub fn include_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .is_some_and(|o| o.include_usage)
+    }
+
+    /// The single-prompt requests a `prompts` request fans out to, in order.
+    #[must_use]
+    pub fn fan_out(&self) -> Vec<Self> {
+        self.prompts
+            .iter()
+            .map(|prompt| {
+                let mut req = Self {
+                    prompts: Vec::new(),
+                    ..self.clone()
+                };
+                req.messages.push(ChatMessage {
+                    role: Role::User,
+                    content: prompt.clone(),
+                    name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
+                });
+                req
+            })
+            .collect()
+    }
+
     /// Validates the chat completion request parameters.
     ///
     /// # Errors
     ///
     /// Returns an error string if:
     /// - Model field is empty
-    /// - Messages array is empty
+    /// - Messages array is empty and no `prompts` are given
+    /// - `prompts` is combined with `stream` or holds an empty prompt
     /// - Temperature is outside the valid range [0, 2]
     /// - Top-p is outside the valid range [0, 1]
+    /// - Top-k is 0
     /// - Max tokens is 0 or negative
     pub fn validate(&self) -> Result<(), String> {
         // Validate model name
@@ -109,21 +318,39 @@ impl ChatCompletionRequest {
         }
 
         // Validate messages
-        if self.messages.is_empty() {
+        if self.messages.is_empty() && self.prompts.is_empty() {
             return Err("messages field cannot be empty".to_string());
         }
 
+        // Validate prompts
+        if !self.prompts.is_empty() {
+            if self.stream {
+                return Err("prompts cannot be combined with stream".to_string());
+            }
+            if let Some(index) = self.prompts.iter().position(|p| p.trim().is_empty()) {
+                return Err(format!("prompts[{index}] cannot be empty"));
+            }
+        }
+
         // Validate temperature range (0-2)
-        if self.temperature < 0.0 || self.temperature > 2.0 {
-            return Err(format!(
-                "temperature must be between 0 and 2, got {}",
-                self.temperature
-            ));
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(format!(
+                    "temperature must be between 0 and 2, got {temperature}"
+                ));
+            }
         }
 
         // Validate top_p range (0-1)
-        if self.top_p < 0.0 || self.top_p > 1.0 {
-            return Err(format!("top_p must be between 0 and 1, got {}", self.top_p));
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err(format!("top_p must be between 0 and 1, got {top_p}"));
+            }
+        }
+
+        // Validate top_k
+        if self.top_k == Some(0) {
+            return Err("top_k must be greater than 0".to_string());
         }
 
         // Validate max_tokens
@@ -137,15 +364,13 @@ impl ChatCompletionRequest {
     }
 }
 
-fn default_temperature() -> f32 {
-    1.0
-}
+/// OpenAI's `temperature` when a request omits it.
+pub const DEFAULT_TEMPERATURE: f32 = 1.0;
 
-fn default_top_p() -> f32 {
-    1.0
-}
+/// OpenAI's `top_p` when a request omits it.
+pub const DEFAULT_TOP_P: f32 = 1.0;
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ChatCompletionResponse {
     pub id: String,
     pub object: String,
@@ -155,42 +380,123 @@ pub struct ChatCompletionResponse {
     pub usage: Option<Usage>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ChatCompletionChoice {
     pub index: u32,
     pub message: ChatMessage,
     pub finish_reason: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// Breakdown of `prompt_tokens`, when the provider reports one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    /// Breakdown of `completion_tokens`, when the provider reports one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+    /// Set when the proxy estimated the counts because the provider reported
+    /// none.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+}
+
+impl Usage {
+    /// Usage with the given counts and no breakdown.
+    #[must_use]
+    pub fn new(prompt_tokens: u32, completion_tokens: u32, total_tokens: u32) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+            ..Self::default()
+        }
+    }
+
+    /// The sum of two requests' usage. A breakdown is kept if either reports
+    /// one, and the sum is estimated if either part is.
+    #[must_use]
+    pub fn add(&self, other: &Self) -> Self {
+        fn sum<T>(a: Option<&T>, b: Option<&T>, add: impl Fn(&T, &T) -> T) -> Option<T>
+        where
+            T: Clone,
+        {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(add(a, b)),
+                (a, b) => a.or(b).cloned(),
+            }
+        }
+        Self {
+            prompt_tokens: self.prompt_tokens.saturating_add(other.prompt_tokens),
+            completion_tokens: self
+                .completion_tokens
+                .saturating_add(other.completion_tokens),
+            total_tokens: self.total_tokens.saturating_add(other.total_tokens),
+            prompt_tokens_details: sum(
+                self.prompt_tokens_details.as_ref(),
+                other.prompt_tokens_details.as_ref(),
+                |a, b| PromptTokensDetails {
+                    cached_tokens: a.cached_tokens.saturating_add(b.cached_tokens),
+                },
+            ),
+            completion_tokens_details: sum(
+                self.completion_tokens_details.as_ref(),
+                other.completion_tokens_details.as_ref(),
+                |a, b| CompletionTokensDetails {
+                    reasoning_tokens: a.reasoning_tokens.saturating_add(b.reasoning_tokens),
+                },
+            ),
+            estimated: self.estimated || other.estimated,
+        }
+    }
+}
+
+/// Prompt tokens by kind, as in OpenAI's `usage.prompt_tokens_details`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct PromptTokensDetails {
+    /// Prompt tokens served from the provider's prompt cache.
+    #[serde(default)]
+    pub cached_tokens: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Completion tokens by kind, as in OpenAI's `usage.completion_tokens_details`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct CompletionTokensDetails {
+    /// Tokens the model spent thinking before it answered.
+    #[serde(default)]
+    pub reasoning_tokens: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ChatCompletionChunk {
     pub id: String,
     pub object: String,
     pub created: u64,
     pub model: String,
     pub choices: Vec<ChatCompletionChunkChoice>,
+    /// Token usage, on the final chunk when the provider reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ChatCompletionChunkChoice {
     pub index: u32,
     pub delta: DeltaMessage,
     pub finish_reason: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct DeltaMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub role: Option<Role>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
 }
 
 #[cfg(test)]
@@ -245,4 +551,60 @@ mod tests {
         let msg: ChatMessage = serde_json::from_str(json).expect("chat message should deserialize");
         assert_eq!(msg.content, "hello\nworld");
     }
+
+    #[test]
+    fn test_deserialize_assistant_tool_calls() {
+        let json = r#"{
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{"id": "call_1", "type": "function",
+                            "function": {"name": "lookup", "arguments": "{}"}}]
+        }"#;
+        let msg: ChatMessage = serde_json::from_str(json).expect("chat message should deserialize");
+        assert_eq!(msg.content, "");
+        assert_eq!(msg.tool_calls[0].function.name, "lookup");
+    }
+
+    #[test]
+    fn test_merge_tool_call_deltas() {
+        let deltas: Vec<ToolCallDelta> = serde_json::from_value(serde_json::json!([
+            {"index": 1, "id": "call_b", "function": {"name": "b", "arguments": ""}},
+            {"index": 0, "id": "call_a", "type": "function", "function": {"name": "a"}},
+            {"index": 0, "function": {"arguments": "{\"x\":"}},
+            {"index": 0, "function": {"arguments": "1}"}}
+        ]))
+        .expect("deltas should deserialize");
+        let calls = merge_tool_call_deltas(&deltas);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_a");
+        assert_eq!(calls[0].function.arguments, r#"{"x":1}"#);
+        assert_eq!(calls[1].function.name, "b");
+    }
+
+    #[test]
+    fn test_prompts_fan_out_after_shared_messages() {
+        let json = r#"{
+            "model": "test",
+            "messages": [{"role": "system", "content": "Answer yes or no."}],
+            "prompts": ["Is water wet?", "Is fire cold?"]
+        }"#;
+        let req: ChatCompletionRequest =
+            serde_json::from_str(json).expect("chat completion request should deserialize");
+        assert!(req.validate().is_ok());
+        let requests = req.fan_out();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.prompts.is_empty()));
+        assert_eq!(requests[1].messages.len(), 2);
+        assert_eq!(requests[1].messages[1].role, Role::User);
+        assert_eq!(requests[1].messages[1].content, "Is fire cold?");
+
+        let mut streamed = req.clone();
+        streamed.stream = true;
+        assert!(streamed.validate().is_err());
+        let mut blank = req;
+        blank.messages.clear();
+        assert!(blank.validate().is_ok());
+        blank.prompts.push(" ".to_string());
+        assert!(blank.validate().is_err());
+    }
 }
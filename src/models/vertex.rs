@@ -37,6 +37,8 @@ pub struct GenerationConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_output_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
@@ -71,10 +73,16 @@ pub struct Candidate {
     pub index: Option<u32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct UsageMetadata {
     pub prompt_token_count: Option<u32>,
     pub candidates_token_count: Option<u32>,
     pub total_token_count: Option<u32>,
+    /// Part of `prompt_token_count` read from cached content.
+    #[serde(default)]
+    pub cached_content_token_count: Option<u32>,
+    /// Thinking tokens, not included in `candidates_token_count`.
+    #[serde(default)]
+    pub thoughts_token_count: Option<u32>,
 }
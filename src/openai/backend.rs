@@ -1,5 +1,6 @@
-use crate::config::AppConfig;
+use crate::config::{AppConfig, RedactionConfig};
 use crate::openai::models::BackendConversationRequest;
+use crate::services::{redaction, upstream_clients};
 use anyhow::{Context, Result};
 use reqwest::Client;
 use std::sync::Arc;
@@ -19,11 +20,31 @@ pub enum BackendError {
     HttpError(u16, String),
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
+    #[error("Impersonation client error: {0}")]
+    Impersonation(String),
     #[error("Circuit breaker is open")]
     CircuitOpen(#[from] crate::openai::circuit_breaker::CircuitOpenError),
 }
 
 impl BackendError {
+    /// The error with the backend's response text redacted under `config`.
+    #[must_use]
+    pub fn redacted(self, config: &RedactionConfig) -> Self {
+        match self {
+            BackendError::Auth(text) => BackendError::Auth(redaction::redact(config, &text)),
+            BackendError::WafBlocked(text) => {
+                BackendError::WafBlocked(redaction::redact(config, &text))
+            }
+            BackendError::RateLimited(text) => {
+                BackendError::RateLimited(redaction::redact(config, &text))
+            }
+            BackendError::HttpError(status, text) => {
+                BackendError::HttpError(status, redaction::redact(config, &text))
+            }
+            other => other,
+        }
+    }
+
     #[must_use]
     pub fn status_code(&self) -> u16 {
         match self {
@@ -31,7 +52,7 @@ impl BackendError {
             BackendError::WafBlocked(_) => 403,
             BackendError::RateLimited(_) => 429,
             BackendError::HttpError(status, _) => *status,
-            BackendError::Network(_) => 502,
+            BackendError::Network(_) | BackendError::Impersonation(_) => 502,
             BackendError::CircuitOpen(_) => 503,
         }
     }
@@ -51,6 +72,8 @@ fn calculate_backoff_ms(attempt: u32) -> u64 {
 
 pub struct OpenAIBackendClient {
     client: Client,
+    #[cfg(feature = "impersonate")]
+    impersonator: Option<crate::openai::impersonate::ImpersonateClient>,
     base_url: String,
     user_agent: String,
 }
@@ -70,24 +93,34 @@ impl OpenAIBackendClient {
             .replace(":3001", "")
             .replace("http://", "https://");
 
-        let base_url = if base_url.contains("backend-api") {
+        let base_url = if let Some(url) = &config.openai.backend_url {
+            url.clone()
+        } else if base_url.contains("backend-api") {
             base_url
         } else {
             DEFAULT_BASE_URL.to_string()
         };
 
-        // Fix hardcoded values: Make user agent configurable via env var
-        let user_agent =
-            std::env::var("BACKEND_USER_AGENT").unwrap_or_else(|_| DEFAULT_USER_AGENT.to_string());
+        let profile = &config.upstream_clients.openai;
+        let user_agent = profile
+            .user_agent
+            .clone()
+            .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
 
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(CLIENT_TIMEOUT_SECS))
-            .user_agent(&user_agent)
+        let builder = reqwest::Client::builder().timeout(Duration::from_secs(CLIENT_TIMEOUT_SECS));
+        let client = upstream_clients::apply(builder, profile, Some(&user_agent))
             .build()
             .context("Failed to create HTTP client")?;
 
         Ok(Self {
             client,
+            #[cfg(feature = "impersonate")]
+            impersonator: profile.impersonate_command.clone().map(|command| {
+                crate::openai::impersonate::ImpersonateClient::new(
+                    command,
+                    Duration::from_secs(REQUEST_TIMEOUT_SECS),
+                )
+            }),
             base_url,
             user_agent,
         })
@@ -119,29 +152,12 @@ impl OpenAIBackendClient {
 
         // Fix: Add retry logic with exponential backoff for transient failures
         for attempt in 1..=RETRY_ATTEMPTS {
-            let mut req_builder = self
-                .client
-                .post(&self.base_url)
-                .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
-                .header("User-Agent", &self.user_agent)
-                .header("Accept-Language", "en-US,en;q=0.9")
-                .header("Referer", "https://chatgpt.com/")
-                .header("Authorization", format!("Bearer {access_token}"))
-                .json(&request);
-
-            if let Some(arkose) = arkose_token {
-                // Validate arkose token format
-                if !arkose.is_empty() && !arkose.contains('\n') && !arkose.contains('\r') {
-                    req_builder = req_builder.header("Openai-Sentinel-Arkose-Token", arkose);
-                }
-            }
-
-            let response = match req_builder.send().await {
+            let response = match self.send_once(&request, access_token, arkose_token).await {
                 Ok(r) => r,
                 Err(e) => {
                     // Network errors are retryable
                     if attempt == RETRY_ATTEMPTS {
-                        return Err(BackendError::Network(e));
+                        return Err(e);
                     }
                     tokio::time::sleep(Duration::from_millis(calculate_backoff_ms(attempt))).await;
                     continue;
@@ -190,6 +206,49 @@ impl OpenAIBackendClient {
         // Unreachable, but required by type system
         unreachable!("Retry loop exhausted without returning")
     }
+
+    /// Makes one attempt, through the impersonation client when configured.
+    async fn send_once(
+        &self,
+        request: &BackendConversationRequest,
+        access_token: &str,
+        arkose_token: Option<&str>,
+    ) -> Result<reqwest::Response, BackendError> {
+        let mut headers = vec![
+            ("Accept-Language", "en-US,en;q=0.9".to_string()),
+            ("Referer", "https://chatgpt.com/".to_string()),
+            ("Authorization", format!("Bearer {access_token}")),
+        ];
+        if let Some(arkose) = arkose_token {
+            // Validate arkose token format
+            if !arkose.is_empty() && !arkose.contains('\n') && !arkose.contains('\r') {
+                headers.push(("Openai-Sentinel-Arkose-Token", arkose.to_string()));
+            }
+        }
+
+        #[cfg(feature = "impersonate")]
+        if let Some(impersonator) = &self.impersonator {
+            headers.push(("Content-Type", "application/json".to_string()));
+            let body = serde_json::to_vec(request).map_err(|e| {
+                BackendError::Impersonation(format!("Failed to encode request: {e}"))
+            })?;
+            return impersonator
+                .post(&self.base_url, &headers, &body)
+                .await
+                .map_err(BackendError::Impersonation);
+        }
+
+        let mut req_builder = self
+            .client
+            .post(&self.base_url)
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .header("User-Agent", &self.user_agent)
+            .json(request);
+        for (name, value) in headers {
+            req_builder = req_builder.header(name, value);
+        }
+        Ok(req_builder.send().await?)
+    }
 }
 
 #[cfg(test)]
@@ -1,9 +1,20 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tracing::{error, info, warn};
 
-#[derive(Clone, Copy, Debug)]
+use crate::services::clock::{self, SharedClock};
+use crate::services::storage::SharedStorage;
+
+// Key of the breaker's state in shared storage
+const SNAPSHOT_KEY: &str = "circuit:upstream";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CircuitState {
     Closed,
     Open,
@@ -14,6 +25,64 @@ pub enum CircuitState {
 #[error("Circuit breaker is open")]
 pub struct CircuitOpenError;
 
+/// Breaker state as saved between restarts. The last failure is kept as
+/// wall-clock time because an `Instant` means nothing to the next process.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitSnapshot {
+    pub state: CircuitState,
+    pub failure_count: u32,
+    pub success_count: u32,
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+/// Where a breaker saves its state, so a crash-looping proxy does not retry a
+/// known-down upstream right after every restart.
+#[derive(Clone)]
+pub enum CircuitStateStore {
+    /// A JSON file on local disk.
+    File(PathBuf),
+    /// The storage backend, shared by every replica in cluster mode.
+    Shared(SharedStorage),
+}
+
+impl CircuitStateStore {
+    async fn load(&self) -> anyhow::Result<Option<CircuitSnapshot>> {
+        let json = match self {
+            Self::File(path) => match tokio::fs::read_to_string(path).await {
+                Ok(json) => json,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", path.display()))
+                }
+            },
+            Self::Shared(store) => match store.get(SNAPSHOT_KEY).await? {
+                Some(json) => json,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(
+            serde_json::from_str(&json).context("Invalid circuit breaker snapshot")?,
+        ))
+    }
+
+    async fn save(&self, snapshot: &CircuitSnapshot) -> anyhow::Result<()> {
+        let json = serde_json::to_string(snapshot)?;
+        match self {
+            Self::File(path) => {
+                // Written aside and renamed so a crash never leaves a torn file
+                let tmp = path.with_extension("tmp");
+                tokio::fs::write(&tmp, json)
+                    .await
+                    .with_context(|| format!("Failed to write {}", tmp.display()))?;
+                tokio::fs::rename(&tmp, path)
+                    .await
+                    .with_context(|| format!("Failed to replace {}", path.display()))
+            }
+            Self::Shared(store) => store.set(SNAPSHOT_KEY, json, None).await,
+        }
+    }
+}
+
 pub struct CircuitBreaker {
     state: Arc<RwLock<CircuitState>>,
     failure_count: Arc<RwLock<u32>>,
@@ -22,6 +91,10 @@ pub struct CircuitBreaker {
     failure_threshold: u32,
     success_threshold: u32,
     timeout: Duration,
+    state_store: Option<CircuitStateStore>,
+    // Serializes saves so an older snapshot never overwrites a newer one
+    save_lock: Mutex<()>,
+    clock: SharedClock,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -50,6 +123,77 @@ impl CircuitBreaker {
             failure_threshold,
             success_threshold,
             timeout: Duration::from_secs(timeout_secs),
+            state_store: None,
+            save_lock: Mutex::new(()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Saves state to `store` whenever it changes; see [`CircuitBreaker::restore`].
+    #[must_use]
+    pub fn with_state_store(mut self, store: CircuitStateStore) -> Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    /// Loads the state saved by a previous process, so an open breaker stays
+    /// open for the rest of its timeout. Returns whether a snapshot was found.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the saved state cannot be read or parsed.
+    pub async fn restore(&self) -> anyhow::Result<bool> {
+        let Some(store) = &self.state_store else {
+            return Ok(false);
+        };
+        let Some(snapshot) = store.load().await? else {
+            return Ok(false);
+        };
+        let now = self.clock.now();
+        let last_failure = snapshot.last_failure_at.map(|at| {
+            let age = (self.clock.wall() - at).to_std().unwrap_or_default();
+            now.checked_sub(age).unwrap_or(now)
+        });
+        *self.state.write().await = snapshot.state;
+        *self.failure_count.write().await = snapshot.failure_count;
+        *self.success_count.write().await = snapshot.success_count;
+        *self.last_failure.write().await = last_failure;
+        info!(
+            "Circuit breaker: Restored {:?} state ({} failures)",
+            snapshot.state, snapshot.failure_count
+        );
+        Ok(true)
+    }
+
+    async fn snapshot(&self) -> CircuitSnapshot {
+        let now = self.clock.now();
+        let last_failure_at = self.last_failure.read().await.map(|at| {
+            let age = now.saturating_duration_since(at);
+            self.clock.wall() - chrono::Duration::from_std(age).unwrap_or_default()
+        });
+        CircuitSnapshot {
+            state: *self.state.read().await,
+            failure_count: *self.failure_count.read().await,
+            success_count: *self.success_count.read().await,
+            last_failure_at,
+        }
+    }
+
+    async fn save(&self) {
+        let Some(store) = &self.state_store else {
+            return;
+        };
+        let _guard = self.save_lock.lock().await;
+        let snapshot = self.snapshot().await;
+        if let Err(e) = store.save(&snapshot).await {
+            warn!("Failed to save circuit breaker state: {e:#}");
         }
     }
 
@@ -63,6 +207,22 @@ impl CircuitBreaker {
         F: std::future::Future<Output = Result<T, E>>,
         E: From<CircuitOpenError>,
     {
+        self.call_if(f, |_| true).await
+    }
+
+    /// Like `call`, but only errors for which `counts_as_failure` returns true
+    /// move the breaker toward Open. Other errors are returned without affecting
+    /// breaker state, so a malformed request cannot trip it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the original error from the function `f`, or `CircuitOpenError` if the circuit is open.
+    pub async fn call_if<F, T, E>(&self, f: F, counts_as_failure: fn(&E) -> bool) -> Result<T, E>
+    where
+        F: std::future::Future<Output = Result<T, E>>,
+        E: From<CircuitOpenError>,
+    {
+        let mut changed = false;
         // Fix race condition: acquire write lock immediately to check and transition atomically
         {
             let mut state_guard = self.state.write().await;
@@ -73,19 +233,20 @@ impl CircuitBreaker {
                     let mut last_failure_guard = self.last_failure.write().await;
                     if last_failure_guard.is_none() {
                         warn!("Circuit breaker: Open state but no last_failure timestamp, initializing");
-                        *last_failure_guard = Some(Instant::now());
+                        *last_failure_guard = Some(self.clock.now());
                     }
                     *last_failure_guard
                 };
 
                 if let Some(last) = last_failure {
-                    if last.elapsed() >= self.timeout {
+                    if self.clock.now().saturating_duration_since(last) >= self.timeout {
                         // Double-check state is still Open before transitioning
                         if matches!(*state_guard, CircuitState::Open) {
                             info!("Circuit breaker: Transitioning to HalfOpen");
                             *state_guard = CircuitState::HalfOpen;
                             *self.failure_count.write().await = 0;
                             *self.success_count.write().await = 0;
+                            changed = true;
                         }
                     } else {
                         drop(state_guard); // Release lock before returning
@@ -110,6 +271,7 @@ impl CircuitBreaker {
                 if matches!(*state_guard, CircuitState::HalfOpen) {
                     let mut count = self.success_count.write().await;
                     *count += 1;
+                    changed = true;
                     if *count >= self.success_threshold {
                         info!("Circuit breaker: Transitioning to Closed");
                         *state_guard = CircuitState::Closed;
@@ -120,10 +282,11 @@ impl CircuitBreaker {
                 // Fix logic bug: Don't reset failure_count on every success in Closed state
                 // Only reset after sustained success (handled in HalfOpen) or when circuit closes
                 // This allows failures to accumulate properly
-            } else {
+            } else if result.as_ref().err().is_some_and(counts_as_failure) {
                 let mut failure_count = self.failure_count.write().await;
                 *failure_count += 1;
-                *self.last_failure.write().await = Some(Instant::now());
+                *self.last_failure.write().await = Some(self.clock.now());
+                changed = true;
 
                 if *failure_count >= self.failure_threshold {
                     error!(
@@ -135,6 +298,9 @@ impl CircuitBreaker {
             }
         }
 
+        if changed {
+            self.save().await;
+        }
         result
     }
 
@@ -174,6 +340,8 @@ impl CircuitBreaker {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::clock::ManualClock;
+    use crate::services::sqlite_store::SqliteStore;
     use std::time::Duration;
 
     #[tokio::test]
@@ -195,6 +363,34 @@ mod tests {
         assert_eq!(cb.get_failure_count().await, 3);
     }
 
+    #[tokio::test]
+    async fn test_call_if_ignores_non_counted_errors() {
+        // Test: Errors the classifier rejects do not move the breaker toward Open
+        use crate::services::providers::ProviderError;
+
+        let cb = CircuitBreaker::new(2, 1, 2);
+        for _ in 0..5 {
+            let _ = cb
+                .call_if(
+                    async { Result::<(), _>::Err(ProviderError::InvalidRequest(String::new())) },
+                    ProviderError::is_retryable,
+                )
+                .await;
+        }
+        assert!(matches!(cb.get_state().await, CircuitState::Closed));
+        assert_eq!(cb.get_failure_count().await, 0);
+
+        for _ in 0..2 {
+            let _ = cb
+                .call_if(
+                    async { Result::<(), _>::Err(ProviderError::Timeout(String::new())) },
+                    ProviderError::is_retryable,
+                )
+                .await;
+        }
+        assert!(matches!(cb.get_state().await, CircuitState::Open));
+    }
+
     #[tokio::test]
     async fn test_circuit_breaker_open_rejects_requests() {
         // Test: Open circuit rejects requests immediately
@@ -217,7 +413,8 @@ mod tests {
     #[tokio::test]
     async fn test_circuit_breaker_open_to_halfopen_after_timeout() {
         // Test: Circuit transitions from Open to HalfOpen after timeout
-        let cb = CircuitBreaker::new(2, 1, 2);
+        let clock = Arc::new(ManualClock::new());
+        let cb = CircuitBreaker::new(2, 1, 2).with_clock(clock.clone());
 
         // Open the circuit
         for _ in 0..2 {
@@ -228,8 +425,12 @@ mod tests {
 
         assert!(matches!(cb.get_state().await, CircuitState::Open));
 
-        // Wait for timeout
-        tokio::time::sleep(Duration::from_millis(1100)).await;
+        // Still open just before the timeout
+        clock.advance(Duration::from_millis(999));
+        let result = cb.call(async { Ok::<(), CircuitOpenError>(()) }).await;
+        assert!(result.is_err());
+
+        clock.advance(Duration::from_millis(1));
 
         // Next call should transition to HalfOpen
         let _ = cb.call(async { Ok::<(), CircuitOpenError>(()) }).await;
@@ -240,7 +441,8 @@ mod tests {
     #[tokio::test]
     async fn test_circuit_breaker_halfopen_to_closed_on_success() {
         // Test: HalfOpen circuit transitions to Closed after success threshold
-        let cb = CircuitBreaker::new(2, 1, 2);
+        let clock = Arc::new(ManualClock::new());
+        let cb = CircuitBreaker::new(2, 1, 2).with_clock(clock.clone());
 
         // Open the circuit
         for _ in 0..2 {
@@ -249,8 +451,8 @@ mod tests {
                 .await;
         }
 
-        // Wait for timeout to transition to HalfOpen
-        tokio::time::sleep(Duration::from_millis(1100)).await;
+        // Timeout passes, so the next call transitions to HalfOpen
+        clock.advance(Duration::from_secs(1));
 
         // Succeed 2 times - should close circuit
         for _ in 0..2 {
@@ -288,6 +490,36 @@ mod tests {
         assert_eq!(cb.get_failure_count().await, 3);
     }
 
+    #[tokio::test]
+    async fn test_open_state_survives_restart() {
+        let path = std::env::temp_dir().join(format!("breaker-{}.json", uuid::Uuid::new_v4()));
+        let stores = [
+            CircuitStateStore::File(path.clone()),
+            CircuitStateStore::Shared(Arc::new(
+                SqliteStore::open_in_memory().expect("store should open"),
+            )),
+        ];
+        for store in stores {
+            let cb = CircuitBreaker::new(2, 60, 1).with_state_store(store.clone());
+            for _ in 0..2 {
+                let _ = cb
+                    .call(async { Result::<(), CircuitOpenError>::Err(CircuitOpenError) })
+                    .await;
+            }
+            assert!(cb.is_open().await);
+
+            let restarted = CircuitBreaker::new(2, 60, 1).with_state_store(store);
+            assert!(restarted.restore().await.expect("restore should succeed"));
+            assert!(restarted.is_open().await);
+            assert_eq!(restarted.get_failure_count().await, 2);
+            let result = restarted
+                .call(async { Ok::<(), CircuitOpenError>(()) })
+                .await;
+            assert!(result.is_err());
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
     #[tokio::test]
     async fn test_circuit_breaker_parameter_validation() {
         // Test: Parameters are validated (min value is 1)
@@ -1,42 +1,95 @@
-use axum::response::IntoResponse;
-use serde::Serialize;
+use axum::{
+    extract::rejection::JsonRejection,
+    http::{Method, Uri},
+    response::IntoResponse,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::OnceLock;
 use tracing::error;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct OpenAIError {
     pub error: ErrorDetail,
 }
 
-#[derive(Debug, Serialize)]
+/// Mirrors OpenAI's error object. `param` and `code` are always serialized
+/// (as `null` when absent), matching what the official SDKs expect.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ErrorDetail {
     pub message: String,
     #[serde(rename = "type")]
     pub error_type: String,
+    pub param: Option<String>,
     pub code: Option<String>,
+    /// Extension: the provider's own error body, sanitized. Only sent when
+    /// `errors.provider_detail` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_detail: Option<Value>,
 }
 
-// Removed wrapper function - use map_error_with_status directly
+pub const CODE_MODEL_NOT_FOUND: &str = "model_not_found";
+pub const CODE_CONTEXT_LENGTH_EXCEEDED: &str = "context_length_exceeded";
+pub const CODE_RATE_LIMIT_EXCEEDED: &str = "rate_limit_exceeded";
+pub const CODE_MODEL_NOT_ALLOWED: &str = "model_not_allowed";
+pub const CODE_PROMPT_TEMPLATE_NOT_FOUND: &str = "prompt_template_not_found";
+pub const CODE_POST_PROCESSING_FAILED: &str = "post_processing_failed";
+pub const CODE_MAINTENANCE: &str = "maintenance";
+pub const CODE_IDEMPOTENCY_KEY_IN_USE: &str = "idempotency_key_in_use";
+pub const CODE_IDEMPOTENCY_KEY_REUSED: &str = "idempotency_key_reused";
+pub const CODE_INVALID_SIGNATURE: &str = "invalid_signature";
+pub const CODE_MODEL_RETIRED: &str = "model_retired";
+pub const CODE_CONVERSATION_NOT_FOUND: &str = "conversation_not_found";
+pub const CODE_UNSUPPORTED_CAPABILITY: &str = "unsupported_capability";
 
-pub fn map_error_with_status(status: u16, message: &str) -> axum::response::Response {
-    // Sanitize message to prevent injection in error responses
-    let sanitized_message = message
-        .chars()
-        .take(1000) // Limit length
-        .filter(|c| {
-            c.is_ascii() || c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | ':' | ',')
-        })
-        .collect::<String>();
+const REDACTED: &str = "[REDACTED]";
+// Bounds on how much of a provider error body is echoed back
+const DETAIL_MAX_DEPTH: usize = 6;
+const DETAIL_MAX_ITEMS: usize = 20;
+const DETAIL_MAX_STRING: usize = 1000;
+// Field names whose values are dropped from provider details wholesale. A
+// field matches when its snake_cased name is one of these or ends in `_` and
+// one of these, so `x_goog_api_key` and `accessToken` match but `max_tokens`,
+// `tokenCount` and `keyword` do not
+const SENSITIVE_FIELDS: &[&str] = &[
+    "key",
+    "apikey",
+    "token",
+    "secret",
+    "password",
+    "passwd",
+    "credential",
+    "credentials",
+    "authorization",
+    "cookie",
+];
 
-    let (error_type, code) = match status {
-        400 => ("invalid_request_error", Some("invalid_request".to_string())),
-        401 => ("authentication_error", Some("invalid_api_key".to_string())),
-        403 => ("authentication_error", Some("forbidden".to_string())),
-        404 => ("invalid_request_error", Some("not_found".to_string())),
-        429 => ("rate_limit_error", Some("rate_limit_exceeded".to_string())),
-        500 | 501 | 505..=599 => ("server_error", Some("upstream_error".to_string())),
-        502 => ("server_error", Some("bad_gateway".to_string())),
-        503 => ("server_error", Some("service_unavailable".to_string())),
-        504 => ("server_error", Some("timeout".to_string())),
+// Upstream phrasings (Vertex, Anthropic, OpenAI) for an oversized prompt
+const CONTEXT_LENGTH_PATTERNS: &[&str] = &[
+    "context length",
+    "context_length",
+    "context window",
+    "maximum number of tokens",
+    "too many tokens",
+    "prompt is too long",
+    "input token count",
+];
+
+fn default_type_and_code(status: u16) -> (&'static str, Option<&'static str>) {
+    match status {
+        400 => ("invalid_request_error", Some("invalid_request")),
+        401 => ("authentication_error", Some("invalid_api_key")),
+        402 => ("insufficient_quota", Some("insufficient_quota")),
+        403 => ("authentication_error", Some("forbidden")),
+        404 => ("invalid_request_error", Some("not_found")),
+        413 => ("invalid_request_error", Some("request_too_large")),
+        429 => ("rate_limit_error", Some(CODE_RATE_LIMIT_EXCEEDED)),
+        500 | 501 | 505..=599 => ("server_error", Some("upstream_error")),
+        502 => ("server_error", Some("bad_gateway")),
+        503 => ("server_error", Some("service_unavailable")),
+        504 => ("server_error", Some("timeout")),
         _ => {
             if !(100..=599).contains(&status) {
                 tracing::warn!(
@@ -46,7 +99,135 @@ pub fn map_error_with_status(status: u16, message: &str) -> axum::response::Resp
             }
             ("invalid_request_error", None)
         }
-    };
+    }
+}
+
+/// Whether an upstream error message reports a prompt too long for the model.
+#[must_use]
+pub fn is_context_length_error(message: &str) -> bool {
+    let lower = message.to_lowercase();
+    CONTEXT_LENGTH_PATTERNS.iter().any(|p| lower.contains(p))
+}
+
+/// Credentials that can appear inside provider error text: Google API keys
+/// and OAuth tokens, OpenAI-style secret keys, bearer tokens and key or
+/// token query parameters.
+fn secret_patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (r"AIza[0-9A-Za-z_\-]{20,}", REDACTED),
+            (r"ya29\.[0-9A-Za-z_\-.]+", REDACTED),
+            (r"sk-[0-9A-Za-z_\-]{8,}", REDACTED),
+            (r"(?i)bearer\s+[0-9A-Za-z_\-.=]+", "Bearer [REDACTED]"),
+            (
+                r"(?i)([?&](?:key|api_key|access_token|token)=)[^&\s\x22']+",
+                "${1}[REDACTED]",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(pattern, replacement)| {
+            Regex::new(pattern).ok().map(|regex| (regex, replacement))
+        })
+        .collect()
+    })
+}
+
+/// Makes a provider error body safe to echo to clients: drops fields named
+/// like credentials, masks secrets in strings, and bounds its size.
+#[must_use]
+pub fn sanitize_provider_detail(detail: &Value) -> Value {
+    sanitize_value(detail, 0)
+}
+
+fn sanitize_value(value: &Value, depth: usize) -> Value {
+    match value {
+        Value::String(s) => {
+            let mut s: String = s.chars().take(DETAIL_MAX_STRING).collect();
+            for (pattern, replacement) in secret_patterns() {
+                if let std::borrow::Cow::Owned(masked) = pattern.replace_all(&s, *replacement) {
+                    s = masked;
+                }
+            }
+            Value::String(s)
+        }
+        Value::Array(_) | Value::Object(_) if depth >= DETAIL_MAX_DEPTH => {
+            Value::String("[TRUNCATED]".to_string())
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .take(DETAIL_MAX_ITEMS)
+                .map(|item| sanitize_value(item, depth + 1))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .take(DETAIL_MAX_ITEMS)
+                .map(|(name, value)| {
+                    let value = if is_sensitive_field(name) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        sanitize_value(value, depth + 1)
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// `name` as lowercase words joined by `_`, split on `_`, `-`, `.` and
+/// camelCase humps: `maxOutputTokens` becomes `max_output_tokens`.
+fn snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    let mut after_lower = false;
+    for c in name.chars() {
+        if matches!(c, '_' | '-' | '.' | ' ') {
+            if !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            after_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && after_lower {
+            out.push('_');
+        }
+        after_lower = c.is_lowercase() || c.is_ascii_digit();
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+fn is_sensitive_field(name: &str) -> bool {
+    let name = snake_case(name);
+    SENSITIVE_FIELDS.iter().any(|field| {
+        name == *field
+            || name
+                .strip_suffix(field)
+                .is_some_and(|rest| rest.ends_with('_'))
+    })
+}
+
+fn build_error_response(
+    status: u16,
+    message: &str,
+    code: Option<&str>,
+    param: Option<&str>,
+    provider_detail: Option<Value>,
+) -> axum::response::Response {
+    // Sanitize message to prevent injection in error responses
+    let sanitized_message = message
+        .chars()
+        .take(1000) // Limit length
+        .filter(|c| {
+            c.is_ascii() || c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | ':' | ',')
+        })
+        .collect::<String>();
+
+    let (error_type, default_code) = default_type_and_code(status);
 
     error!("Error response: {} - {}", status, sanitized_message);
 
@@ -54,7 +235,9 @@ pub fn map_error_with_status(status: u16, message: &str) -> axum::response::Resp
         error: ErrorDetail {
             message: sanitized_message,
             error_type: error_type.to_string(),
-            code,
+            param: param.map(str::to_string),
+            code: code.or(default_code).map(str::to_string),
+            provider_detail,
         },
     };
 
@@ -68,3 +251,168 @@ pub fn map_error_with_status(status: u16, message: &str) -> axum::response::Resp
 
     (status_code, axum::Json(error_response)).into_response()
 }
+
+/// Builds an OpenAI error envelope with the type and code implied by `status`.
+///
+/// 400 responses whose message reports an oversized prompt are tagged
+/// `context_length_exceeded` so SDKs can tell them apart from other bad requests.
+pub fn map_error_with_status(status: u16, message: &str) -> axum::response::Response {
+    map_error_with_detail(status, message, None)
+}
+
+/// [`map_error_with_status`] carrying a provider's error body, which is
+/// sanitized with [`sanitize_provider_detail`] before it is sent.
+pub fn map_error_with_detail(
+    status: u16,
+    message: &str,
+    provider_detail: Option<&Value>,
+) -> axum::response::Response {
+    let provider_detail = provider_detail.map(sanitize_provider_detail);
+    if status == 400 && is_context_length_error(message) {
+        return build_error_response(
+            status,
+            message,
+            Some(CODE_CONTEXT_LENGTH_EXCEEDED),
+            Some("messages"),
+            provider_detail,
+        );
+    }
+    build_error_response(status, message, None, None, provider_detail)
+}
+
+/// Builds an OpenAI error envelope with an explicit `code` and optional `param`.
+pub fn map_error_with_code(
+    status: u16,
+    message: &str,
+    code: &str,
+    param: Option<&str>,
+) -> axum::response::Response {
+    build_error_response(status, message, Some(code), param, None)
+}
+
+/// Converts a request body extraction failure into an OpenAI `invalid_request_error`.
+///
+/// OpenAI answers malformed bodies with 400 rather than axum's default 422;
+/// oversized bodies keep their 413.
+#[must_use]
+pub fn map_json_rejection(rejection: &JsonRejection) -> axum::response::Response {
+    let status = match rejection.status().as_u16() {
+        413 => 413,
+        _ => 400,
+    };
+    map_error_with_status(
+        status,
+        &format!("Invalid request body: {}", rejection.body_text()),
+    )
+}
+
+/// Fallback for unknown routes, shaped like OpenAI's invalid URL error.
+pub async fn not_found_handler(method: Method, uri: Uri) -> axum::response::Response {
+    map_error_with_status(404, &format!("Invalid URL ({method} {})", uri.path()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    async fn body_json(response: axum::response::Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), 64 * 1024)
+            .await
+            .expect("body should be readable");
+        serde_json::from_slice(&bytes).expect("body should be JSON")
+    }
+
+    #[tokio::test]
+    async fn test_envelope_always_has_all_fields() {
+        let json = body_json(map_error_with_status(401, "Invalid API key")).await;
+        let error = json["error"]
+            .as_object()
+            .expect("error should be an object");
+        for field in ["message", "type", "param", "code"] {
+            assert!(error.contains_key(field), "missing field {field}");
+        }
+        assert_eq!(json["error"]["type"], "authentication_error");
+        assert_eq!(json["error"]["code"], "invalid_api_key");
+        assert!(json["error"]["param"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_specific_codes() {
+        let json = body_json(map_error_with_status(
+            400,
+            "The input token count (2000000) exceeds the maximum number of tokens allowed",
+        ))
+        .await;
+        assert_eq!(json["error"]["code"], CODE_CONTEXT_LENGTH_EXCEEDED);
+        assert_eq!(json["error"]["param"], "messages");
+
+        let json = body_json(map_error_with_status(429, "slow down")).await;
+        assert_eq!(json["error"]["code"], CODE_RATE_LIMIT_EXCEEDED);
+
+        let response = map_error_with_code(
+            404,
+            "The model 'x' does not exist",
+            CODE_MODEL_NOT_FOUND,
+            Some("model"),
+        );
+        assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+        let json = body_json(response).await;
+        assert_eq!(json["error"]["code"], CODE_MODEL_NOT_FOUND);
+        assert_eq!(json["error"]["param"], "model");
+        assert_eq!(json["error"]["type"], "invalid_request_error");
+    }
+
+    #[tokio::test]
+    async fn test_provider_detail_is_sanitized() {
+        let detail = serde_json::json!({
+            "status": "INVALID_ARGUMENT",
+            "message": "API key AIzaSyA1234567890abcdefghijklmnop not valid",
+            "details": [{"reason": "API_KEY_INVALID", "api_key": "secret"}],
+            "link": "https://example.com/v1?key=abc123&alt=sse"
+        });
+        let json = body_json(map_error_with_detail(400, "bad key", Some(&detail))).await;
+        let provider_detail = &json["error"]["provider_detail"];
+        assert_eq!(provider_detail["status"], "INVALID_ARGUMENT");
+        assert_eq!(provider_detail["message"], "API key [REDACTED] not valid");
+        assert_eq!(provider_detail["details"][0]["reason"], "API_KEY_INVALID");
+        assert_eq!(provider_detail["details"][0]["api_key"], REDACTED);
+        assert_eq!(
+            provider_detail["link"],
+            "https://example.com/v1?key=[REDACTED]&alt=sse"
+        );
+
+        let json = body_json(map_error_with_status(400, "bad key")).await;
+        assert!(json["error"].get("provider_detail").is_none());
+    }
+
+    #[test]
+    fn test_sensitive_fields_match_whole_words() {
+        for name in [
+            "key",
+            "api_key",
+            "apiKey",
+            "X-Goog-Api-Key",
+            "access_token",
+            "refreshToken",
+            "client_secret",
+            "Authorization",
+            "Set-Cookie",
+            "credentials",
+        ] {
+            assert!(is_sensitive_field(name), "{name} should be redacted");
+        }
+        for name in [
+            "max_tokens",
+            "maxOutputTokens",
+            "tokenCount",
+            "promptTokenCount",
+            "keyword",
+            "keys_used",
+            "monkey",
+        ] {
+            assert!(!is_sensitive_field(name), "{name} should be kept");
+        }
+        assert_eq!(snake_case("maxOutputTokens"), "max_output_tokens");
+    }
+}
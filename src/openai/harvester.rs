@@ -1,5 +1,6 @@
 use crate::config::AppConfig;
 use crate::openai::models::{HealthResponse, TokenResponse};
+use crate::services::trace_context;
 use anyhow::{Context, Result};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
@@ -133,7 +134,13 @@ impl HarvesterClient {
         let url = self.build_tokens_url();
 
         for attempt in 1..=RETRY_ATTEMPTS {
-            let response = match self.client.get(&url).send().await {
+            let response = match self
+                .client
+                .get(&url)
+                .headers(trace_context::propagation_headers())
+                .send()
+                .await
+            {
                 Ok(r) => r,
                 Err(e) => {
                     if attempt == RETRY_ATTEMPTS {
@@ -208,7 +215,14 @@ impl HarvesterClient {
         });
 
         for attempt in 1..=RETRY_ATTEMPTS {
-            let response = match self.client.post(&url).json(&body).send().await {
+            let response = match self
+                .client
+                .post(&url)
+                .headers(trace_context::propagation_headers())
+                .json(&body)
+                .send()
+                .await
+            {
                 Ok(r) => r,
                 Err(e) => {
                     if attempt == RETRY_ATTEMPTS {
@@ -272,7 +286,13 @@ impl HarvesterClient {
         let url = self.build_health_url();
 
         for attempt in 1..=RETRY_ATTEMPTS {
-            let response = match self.client.get(&url).send().await {
+            let response = match self
+                .client
+                .get(&url)
+                .headers(trace_context::propagation_headers())
+                .send()
+                .await
+            {
                 Ok(r) => r,
                 Err(e) => {
                     if attempt == RETRY_ATTEMPTS {
@@ -0,0 +1,230 @@
+// Browser-impersonating transport for the ChatGPT backend.
+//
+// The backend's WAF fingerprints the TLS and HTTP/2 handshake, which reqwest
+// cannot make look like a browser. curl-impersonate can: its wrapper scripts
+// (`curl_chrome116`, `curl_ff117`, ...) replay a browser's ClientHello and
+// HTTP/2 settings along with its default headers. Setting
+// `upstream_clients.openai.impersonate_command` sends backend requests through
+// such a wrapper and reads the response from its stdout, so streamed replies
+// still arrive incrementally.
+//
+// The wrapper's own user agent is kept, since it has to match the fingerprint.
+// Headers and body go to curl on stdin as a config file, which keeps the access
+// token out of the process list.
+
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use futures::StreamExt;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdout, Command};
+use tokio_util::io::ReaderStream;
+
+/// Sends requests by running a curl-impersonate wrapper.
+pub struct ImpersonateClient {
+    command: String,
+    timeout: Duration,
+}
+
+impl ImpersonateClient {
+    #[must_use]
+    pub fn new(command: String, timeout: Duration) -> Self {
+        Self { command, timeout }
+    }
+
+    /// POSTs `body` to `url` and returns the response once its headers have
+    /// arrived; the body is streamed from the still-running process.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the failure if the command cannot be run or
+    /// exits without producing an HTTP response.
+    pub async fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, String)],
+        body: &[u8],
+    ) -> Result<reqwest::Response, String> {
+        let mut child = Command::new(&self.command)
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run '{}': {e}", self.command))?;
+
+        let config = curl_config(url, headers, body, self.timeout);
+        let mut stdin = child.stdin.take().ok_or("curl stdin unavailable")?;
+        stdin
+            .write_all(config.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to send request to '{}': {e}", self.command))?;
+        drop(stdin);
+
+        let stdout = child.stdout.take().ok_or("curl stdout unavailable")?;
+        let mut reader = BufReader::new(stdout);
+        let Some((status, response_headers)) = read_head(&mut reader).await? else {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            let _ = child.wait().await;
+            return Err(format!(
+                "'{}' returned no response: {}",
+                self.command,
+                stderr.trim()
+            ));
+        };
+
+        // The stream owns the process so it is killed if the caller stops reading
+        let stream = ReaderStream::new(reader).map(move |chunk| {
+            let _ = &child;
+            chunk
+        });
+        let mut response = axum::http::Response::new(reqwest::Body::wrap_stream(stream));
+        *response.status_mut() = status;
+        *response.headers_mut() = response_headers;
+        Ok(reqwest::Response::from(response))
+    }
+}
+
+/// Renders a curl config file; values are double-quoted with `\` and `"`
+/// escaped, and `data-binary` never starts with `@` since the body is JSON.
+fn curl_config(url: &str, headers: &[(&str, String)], body: &[u8], timeout: Duration) -> String {
+    let quote = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut config = format!(
+        "silent\nshow-error\nno-buffer\ninclude\nrequest = \"POST\"\nmax-time = {}\nurl = {}\n",
+        timeout.as_secs(),
+        quote(url)
+    );
+    for (name, value) in headers {
+        config.push_str(&format!(
+            "header = {}\n",
+            quote(&format!("{name}: {value}"))
+        ));
+    }
+    config.push_str(&format!(
+        "data-binary = {}\n",
+        quote(&String::from_utf8_lossy(body))
+    ));
+    config
+}
+
+/// Reads the status line and headers curl prints before the body, skipping
+/// interim (1xx) responses. `None` means the output ended first.
+async fn read_head(
+    reader: &mut BufReader<ChildStdout>,
+) -> Result<Option<(StatusCode, reqwest::header::HeaderMap)>, String> {
+    loop {
+        let Some(status_line) = read_line(reader).await? else {
+            return Ok(None);
+        };
+        let status = parse_status_line(&status_line)?;
+        let mut headers = reqwest::header::HeaderMap::new();
+        while let Some(line) = read_line(reader).await? {
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if let (Ok(name), Ok(value)) = (
+                    HeaderName::from_bytes(name.trim().as_bytes()),
+                    HeaderValue::from_str(value.trim()),
+                ) {
+                    headers.append(name, value);
+                }
+            }
+        }
+        if !status.is_informational() {
+            return Ok(Some((status, headers)));
+        }
+    }
+}
+
+/// One output line without its line ending; `None` at end of output.
+async fn read_line(reader: &mut BufReader<ChildStdout>) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    let read = reader
+        .read_line(&mut line)
+        .await
+        .map_err(|e| format!("Failed to read curl output: {e}"))?;
+    Ok((read > 0).then(|| line.trim_end().to_string()))
+}
+
+/// Parses `HTTP/2 200` or `HTTP/1.1 403 Forbidden`.
+fn parse_status_line(line: &str) -> Result<StatusCode, String> {
+    line.strip_prefix("HTTP/")
+        .and_then(|rest| rest.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| format!("Unexpected curl status line '{line}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_line_and_config() {
+        assert_eq!(parse_status_line("HTTP/2 200"), Ok(StatusCode::OK));
+        assert_eq!(
+            parse_status_line("HTTP/1.1 403 Forbidden"),
+            Ok(StatusCode::FORBIDDEN)
+        );
+        assert!(parse_status_line("curl: (6) Could not resolve host").is_err());
+
+        let config = curl_config(
+            "https://chatgpt.com/backend-api/conversation",
+            &[("Authorization", "Bearer token".to_string())],
+            br#"{"content":"say \"hi\""}"#,
+            Duration::from_secs(30),
+        );
+        assert!(config.contains("header = \"Authorization: Bearer token\"\n"));
+        assert!(config.contains(r#"data-binary = "{\"content\":\"say \\\"hi\\\"\"}""#));
+        assert!(config.contains("max-time = 30\n"));
+    }
+
+    // The fake curl is a shell script
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_response_is_read_from_curl_output() {
+        let dir = std::env::temp_dir().join(format!("impersonate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("temp dir should be created");
+        let script = dir.join("curl_fake");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\ncat > /dev/null\nprintf 'HTTP/1.1 100 Continue\\r\\n\\r\\nHTTP/2 200\\r\\ncontent-type: text/event-stream\\r\\n\\r\\ndata: hi\\n\\n'\n",
+        )
+        .expect("script should be written");
+        let mut permissions = std::fs::metadata(&script)
+            .expect("script should exist")
+            .permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+        std::fs::set_permissions(&script, permissions).expect("script should be executable");
+
+        let client = ImpersonateClient::new(
+            script.to_string_lossy().into_owned(),
+            Duration::from_secs(5),
+        );
+        let response = client
+            .post("https://example.com", &[], b"{}")
+            .await
+            .expect("fake curl should respond");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        assert_eq!(
+            response.text().await.expect("body should be readable"),
+            "data: hi\n\n"
+        );
+
+        let missing = ImpersonateClient::new(
+            dir.join("missing").to_string_lossy().into_owned(),
+            Duration::from_secs(5),
+        );
+        assert!(missing
+            .post("https://example.com", &[], b"{}")
+            .await
+            .is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
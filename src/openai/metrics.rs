@@ -1,7 +1,7 @@
 use num_traits::ToPrimitive;
 use serde::Serialize;
-use std::collections::VecDeque;
-use std::sync::Arc;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
 
 const MAX_LATENCY_HISTORY: usize = 100;
@@ -30,6 +30,48 @@ fn percentile(sorted_data: &[u64], p: u8) -> u64 {
     sorted_data.get(index).copied().unwrap_or_default()
 }
 
+/// Number of requests routed to `provider` for `reason`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RoutingDecisionCount {
+    pub provider: String,
+    pub reason: String,
+    pub count: u64,
+}
+
+/// Outcomes of requests assigned to one experiment variant.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExperimentVariantStats {
+    pub experiment: String,
+    pub variant: String,
+    pub requests: u64,
+    pub failed_requests: u64,
+    pub success_rate: f64,
+    pub avg_latency_ms: f64,
+}
+
+/// Traffic exchanged with one provider's upstream API.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct UpstreamTraffic {
+    pub provider: String,
+    pub requests: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+struct TrafficTotals {
+    requests: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+struct VariantTotals {
+    requests: u64,
+    failed_requests: u64,
+    latency_ms_sum: u64,
+}
+
 #[derive(Clone, Default, Serialize)]
 pub struct MetricsStats {
     pub cache_hits: u64,
@@ -46,6 +88,11 @@ pub struct MetricsStats {
     pub p50_latency_ms: u64,
     pub p95_latency_ms: u64,
     pub p99_latency_ms: u64,
+    pub routing_decisions: Vec<RoutingDecisionCount>,
+    pub coalesced_requests: u64,
+    pub deprecated_model_requests: BTreeMap<String, u64>,
+    pub experiment_variants: Vec<ExperimentVariantStats>,
+    pub upstream_traffic: Vec<UpstreamTraffic>,
 }
 
 pub struct Metrics {
@@ -59,6 +106,12 @@ pub struct Metrics {
     failed_requests: Arc<RwLock<u64>>,
     // Fix inefficient remove(0): Use VecDeque for O(1) removal from front
     request_durations_ms: Arc<RwLock<VecDeque<u64>>>,
+    routing_decisions: Arc<RwLock<BTreeMap<(String, String), u64>>>,
+    coalesced_requests: Arc<RwLock<u64>>,
+    deprecated_model_requests: Arc<RwLock<BTreeMap<String, u64>>>,
+    experiment_results: Arc<RwLock<BTreeMap<(String, String), VariantTotals>>>,
+    // Synchronous so response streams can count bytes as they pass through
+    upstream_traffic: Arc<Mutex<BTreeMap<String, TrafficTotals>>>,
 }
 
 impl Metrics {
@@ -73,6 +126,11 @@ impl Metrics {
             total_requests: Arc::new(RwLock::new(0)),
             failed_requests: Arc::new(RwLock::new(0)),
             request_durations_ms: Arc::new(RwLock::new(VecDeque::new())),
+            routing_decisions: Arc::new(RwLock::new(BTreeMap::new())),
+            coalesced_requests: Arc::new(RwLock::new(0)),
+            deprecated_model_requests: Arc::new(RwLock::new(BTreeMap::new())),
+            experiment_results: Arc::new(RwLock::new(BTreeMap::new())),
+            upstream_traffic: Arc::new(Mutex::new(BTreeMap::new())),
         }
     }
 
@@ -114,6 +172,92 @@ impl Metrics {
         }
     }
 
+    pub async fn record_routing_decision(&self, provider: &str, reason: &str) {
+        *self
+            .routing_decisions
+            .write()
+            .await
+            .entry((provider.to_string(), reason.to_string()))
+            .or_default() += 1;
+    }
+
+    /// Counts a request answered from another caller's in-flight upstream call.
+    pub async fn record_coalesced_request(&self) {
+        *self.coalesced_requests.write().await += 1;
+    }
+
+    /// Counts a request for a deprecated model.
+    pub async fn record_deprecated_model_request(&self, model: &str) {
+        *self
+            .deprecated_model_requests
+            .write()
+            .await
+            .entry(model.to_string())
+            .or_default() += 1;
+    }
+
+    /// Records the outcome of a request served under an experiment variant.
+    pub async fn record_experiment_result(
+        &self,
+        experiment: &str,
+        variant: &str,
+        success: bool,
+        duration_ms: u64,
+    ) {
+        let mut results = self.experiment_results.write().await;
+        let totals = results
+            .entry((experiment.to_string(), variant.to_string()))
+            .or_default();
+        totals.requests += 1;
+        if !success {
+            totals.failed_requests += 1;
+        }
+        totals.latency_ms_sum = totals.latency_ms_sum.saturating_add(duration_ms);
+    }
+
+    fn with_traffic(&self, provider: &str, update: impl FnOnce(&mut TrafficTotals)) {
+        let mut traffic = self
+            .upstream_traffic
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        match traffic.get_mut(provider) {
+            Some(totals) => update(totals),
+            None => update(traffic.entry(provider.to_string()).or_default()),
+        }
+    }
+
+    /// Counts a call sent to `provider`'s upstream API with a `bytes` body.
+    pub fn record_upstream_request(&self, provider: &str, bytes: usize) {
+        let bytes = u64::try_from(bytes).unwrap_or(u64::MAX);
+        self.with_traffic(provider, |totals| {
+            totals.requests += 1;
+            totals.request_bytes = totals.request_bytes.saturating_add(bytes);
+        });
+    }
+
+    /// Adds `bytes` of response body received from `provider`; streamed
+    /// responses are recorded chunk by chunk.
+    pub fn record_upstream_response_bytes(&self, provider: &str, bytes: usize) {
+        let bytes = u64::try_from(bytes).unwrap_or(u64::MAX);
+        self.with_traffic(provider, |totals| {
+            totals.response_bytes = totals.response_bytes.saturating_add(bytes);
+        });
+    }
+
+    fn upstream_traffic(&self) -> Vec<UpstreamTraffic> {
+        self.upstream_traffic
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .iter()
+            .map(|(provider, totals)| UpstreamTraffic {
+                provider: provider.clone(),
+                requests: totals.requests,
+                request_bytes: totals.request_bytes,
+                response_bytes: totals.response_bytes,
+            })
+            .collect()
+    }
+
     #[must_use]
     pub async fn get_stats(&self) -> MetricsStats {
         let cache_hits = *self.cache_hits.read().await;
@@ -180,6 +324,36 @@ impl Metrics {
             p50_latency_ms: p50,
             p95_latency_ms: p95,
             p99_latency_ms: p99,
+            routing_decisions: self
+                .routing_decisions
+                .read()
+                .await
+                .iter()
+                .map(|((provider, reason), &count)| RoutingDecisionCount {
+                    provider: provider.clone(),
+                    reason: reason.clone(),
+                    count,
+                })
+                .collect(),
+            coalesced_requests: *self.coalesced_requests.read().await,
+            deprecated_model_requests: self.deprecated_model_requests.read().await.clone(),
+            experiment_variants: self
+                .experiment_results
+                .read()
+                .await
+                .iter()
+                .map(|((experiment, variant), totals)| ExperimentVariantStats {
+                    experiment: experiment.clone(),
+                    variant: variant.clone(),
+                    requests: totals.requests,
+                    failed_requests: totals.failed_requests,
+                    success_rate: to_f64(totals.requests - totals.failed_requests)
+                        / to_f64(totals.requests)
+                        * 100.0,
+                    avg_latency_ms: to_f64(totals.latency_ms_sum) / to_f64(totals.requests),
+                })
+                .collect(),
+            upstream_traffic: self.upstream_traffic(),
         }
     }
 }
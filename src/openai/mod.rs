@@ -2,7 +2,12 @@ pub mod backend;
 pub mod circuit_breaker;
 pub mod errors;
 pub mod harvester;
+#[cfg(feature = "impersonate")]
+pub mod impersonate;
 pub mod metrics;
 pub mod models;
+pub mod output_limit;
 pub mod sse_parser;
+pub mod token_bindings;
 pub mod transformer;
+pub mod waf_cooldown;
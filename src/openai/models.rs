@@ -56,6 +56,9 @@ pub struct BackendSSEEvent {
     #[serde(rename = "event")]
     pub event_type: String,
     pub data: serde_json::Value,
+    /// Last event ID set by the stream (`id:` field), if any.
+    #[serde(default)]
+    pub id: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
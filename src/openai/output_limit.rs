@@ -0,0 +1,225 @@
+// Client-side `stop` and `max_tokens` for the ChatGPT backend.
+//
+// The conversation API takes no stop sequences and does not reliably honour an
+// output limit, so both are enforced on the text it streams back. Output is
+// cut before the first stop sequence (which, as with OpenAI, is not returned)
+// or once it reaches the estimated character length of `max_tokens`, and the
+// completion finishes with `stop` or `length`. Text that could be the start
+// of a stop sequence split across events is held back until the next event
+// settles it.
+
+use crate::models::openai::ChatCompletionChunk;
+use crate::services::{finish_reason, request_limits};
+
+/// Enforces `stop` and `max_tokens` on one completion's output.
+#[derive(Debug, Default)]
+pub struct OutputLimit {
+    stop: Vec<String>,
+    // Characters a stop sequence can span minus one: the most that may be held back
+    hold_back: usize,
+    remaining_chars: Option<usize>,
+    pending: String,
+    limited: Option<&'static str>,
+    finished: bool,
+}
+
+impl OutputLimit {
+    #[must_use]
+    pub fn new(stop: Option<&[String]>, max_tokens: Option<u32>) -> Self {
+        let stop: Vec<String> = stop
+            .unwrap_or_default()
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect();
+        let hold_back = stop
+            .iter()
+            .map(|s| s.chars().count() - 1)
+            .max()
+            .unwrap_or(0);
+        Self {
+            stop,
+            hold_back,
+            remaining_chars: max_tokens.map(request_limits::estimate_chars),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the completion has finished, by a limit or upstream.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Applies the limits to a streamed chunk, returning the chunks to send in
+    /// its place. Once a limit is hit, a final chunk carrying its finish
+    /// reason is returned and everything after it is dropped.
+    pub fn apply(&mut self, chunk: ChatCompletionChunk) -> Vec<ChatCompletionChunk> {
+        if self.finished {
+            return Vec::new();
+        }
+        let Some(choice) = chunk.choices.first() else {
+            return vec![chunk];
+        };
+        let upstream_reason = choice.finish_reason.clone();
+        let mut text = choice
+            .delta
+            .content
+            .as_deref()
+            .map(|content| self.push(content))
+            .unwrap_or_default();
+        if upstream_reason.is_some() {
+            text.push_str(&self.flush());
+        }
+
+        let mut chunks = Vec::new();
+        if !text.is_empty() {
+            chunks.push(with_delta(&chunk, Some(text), None));
+        }
+        if let Some(reason) = self.limited.map(str::to_string).or(upstream_reason) {
+            self.finished = true;
+            chunks.push(with_delta(&chunk, None, Some(reason)));
+        }
+        chunks
+    }
+
+    /// Adds streamed text, returning the part that can be sent now.
+    fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let earliest_stop = self
+            .stop
+            .iter()
+            .filter_map(|stop| self.pending.find(stop.as_str()))
+            .min();
+        if let Some(index) = earliest_stop {
+            self.pending.truncate(index);
+            let text = self.flush();
+            self.limited.get_or_insert(finish_reason::STOP);
+            return text;
+        }
+
+        let held = self.pending.chars().count().min(self.hold_back);
+        let release = if held == 0 {
+            self.pending.len()
+        } else {
+            self.pending
+                .char_indices()
+                .rev()
+                .nth(held - 1)
+                .map_or(0, |(index, _)| index)
+        };
+        let text: String = self.pending.drain(..release).collect();
+        self.truncate(text)
+    }
+
+    /// Releases held-back text at the end of the completion.
+    fn flush(&mut self) -> String {
+        let text = std::mem::take(&mut self.pending);
+        self.truncate(text)
+    }
+
+    /// Cuts `text` to the characters `max_tokens` still allows.
+    fn truncate(&mut self, text: String) -> String {
+        if self.limited.is_some() {
+            return String::new();
+        }
+        let Some(remaining) = self.remaining_chars else {
+            return text;
+        };
+        let chars = text.chars().count();
+        if chars < remaining {
+            self.remaining_chars = Some(remaining - chars);
+            return text;
+        }
+        self.remaining_chars = Some(0);
+        self.limited = Some(finish_reason::LENGTH);
+        text.chars().take(remaining).collect()
+    }
+}
+
+fn with_delta(
+    chunk: &ChatCompletionChunk,
+    content: Option<String>,
+    finish_reason: Option<String>,
+) -> ChatCompletionChunk {
+    let mut chunk = chunk.clone();
+    for choice in &mut chunk.choices {
+        choice.delta.content.clone_from(&content);
+        choice.finish_reason.clone_from(&finish_reason);
+    }
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::{ChatCompletionChunkChoice, DeltaMessage};
+
+    fn chunk(content: Option<&str>, finish_reason: Option<&str>) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: "req-1".to_string(),
+            object: "chat.completion.chunk".to_string(),
+            created: 0,
+            model: "gpt-4".to_string(),
+            choices: vec![ChatCompletionChunkChoice {
+                index: 0,
+                delta: DeltaMessage {
+                    role: None,
+                    content: content.map(str::to_string),
+                    tool_calls: None,
+                },
+                finish_reason: finish_reason.map(str::to_string),
+            }],
+            usage: None,
+        }
+    }
+
+    fn run(limit: &mut OutputLimit, deltas: &[&str]) -> (String, Option<String>) {
+        let mut chunks: Vec<_> = deltas.iter().map(|d| chunk(Some(d), None)).collect();
+        chunks.push(chunk(None, Some("stop")));
+        let mut text = String::new();
+        let mut reason = None;
+        for out in chunks.into_iter().flat_map(|c| limit.apply(c)) {
+            let choice = &out.choices[0];
+            text.push_str(choice.delta.content.as_deref().unwrap_or_default());
+            if choice.finish_reason.is_some() {
+                assert!(reason.is_none(), "finished twice");
+                reason.clone_from(&choice.finish_reason);
+            }
+        }
+        (text, reason)
+    }
+
+    #[test]
+    fn test_without_limits_passes_through() {
+        let mut limit = OutputLimit::new(None, None);
+        assert_eq!(
+            run(&mut limit, &["Hello", ", world"]),
+            ("Hello, world".to_string(), Some("stop".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_stop_sequence_split_across_chunks() {
+        let stop = vec!["END".to_string(), "\n\n".to_string()];
+        let mut limit = OutputLimit::new(Some(&stop), None);
+        assert_eq!(
+            run(&mut limit, &["one two E", "N", "D three"]),
+            ("one two ".to_string(), Some("stop".to_string()))
+        );
+        assert!(limit.is_finished());
+
+        // A partial match that never completes is released at the end
+        let mut limit = OutputLimit::new(Some(&stop), None);
+        assert_eq!(run(&mut limit, &["ab", "EN"]).0, "abEN");
+    }
+
+    #[test]
+    fn test_max_tokens_truncates_with_length() {
+        let mut limit = OutputLimit::new(None, Some(2));
+        assert_eq!(
+            run(&mut limit, &["abcdé", "fghij", "klm"]),
+            ("abcdéfgh".to_string(), Some("length".to_string()))
+        );
+    }
+}
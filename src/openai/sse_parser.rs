@@ -1,101 +1,126 @@
+// Incremental parser for the ChatGPT backend's event stream.
+//
+// Follows the framing rules of the SSE specification: lines end in CRLF, LF or
+// a lone CR (which may be split from its LF across chunks), a leading BOM is
+// dropped, `data:` lines accumulate into one payload, `event:` and `id:` set
+// the event type and last event ID, and lines starting with `:` are comments.
+// Lines are decoded only once complete, so a multi-byte character split
+// across chunks stays intact.
+
 use crate::openai::models::BackendSSEEvent;
 use crate::openai::transformer::parse_sse_event;
 
 const DEFAULT_EVENT_TYPE: &str = "message";
-
-fn finish_current_event(
-    event_type: Option<String>,
-    data: &mut Vec<String>,
-) -> Option<(String, String)> {
-    if event_type.is_some() || !data.is_empty() {
-        let event = event_type.unwrap_or_else(|| DEFAULT_EVENT_TYPE.to_string());
-        let data_str = data.join("\n");
-        data.clear();
-        Some((event, data_str))
-    } else {
-        None
-    }
-}
+const BOM: char = '\u{feff}';
 
 pub struct SSEParser {
-    buffer: String,
-    current_event: Option<String>,
-    current_data: Vec<String>,
+    // Bytes of the current, not yet terminated line
+    line: Vec<u8>,
+    // The previous line ended in CR, so a leading LF belongs to it
+    after_cr: bool,
+    // Whether the first line (which may carry a BOM) has been seen
+    started: bool,
+    event_type: String,
+    data: String,
+    last_event_id: Option<String>,
 }
 
 impl SSEParser {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            buffer: String::new(),
-            current_event: None,
-            current_data: Vec::new(),
+            line: Vec::new(),
+            after_cr: false,
+            started: false,
+            event_type: String::new(),
+            data: String::new(),
+            last_event_id: None,
         }
     }
 
     pub fn parse_chunk(&mut self, chunk: &[u8]) -> Vec<BackendSSEEvent> {
-        // Fix data corruption risk: Handle invalid UTF-8 explicitly
-        // For SSE, we expect text data, so invalid UTF-8 is an error condition
-        let text = match String::from_utf8(chunk.to_vec()) {
-            Ok(s) => s,
-            Err(e) => {
-                tracing::warn!("Invalid UTF-8 in SSE chunk: {}", e);
-                // Use lossy conversion but log detailed error information
-                // This preserves partial data while alerting to corruption
-                String::from_utf8_lossy(chunk).to_string()
-            }
-        };
-        self.buffer.push_str(&text);
-
         let mut events = Vec::new();
-        // Fix inefficiency: Process lines directly without creating Vec<String> for all lines
-        // Fix complex incomplete line handling: Handle \r\n split across chunks properly
-        let ends_with_newline = text.ends_with('\n') || text.ends_with('\r');
-
-        // Split buffer into lines efficiently - collect as Vec<String> only for incomplete handling
-        let lines: Vec<String> = self.buffer.lines().map(str::to_string).collect();
-        let (complete_lines, incomplete) = if !ends_with_newline && !lines.is_empty() {
-            // Last line is incomplete - keep it in buffer
-            let incomplete = lines.last().cloned();
-            let complete = lines[..lines.len().saturating_sub(1)].to_vec();
-            (complete, incomplete)
-        } else {
-            // All lines are complete
-            (lines, None)
-        };
+        let mut rest = chunk;
+        if rest.is_empty() {
+            return events;
+        }
+        if self.after_cr && rest[0] == b'\n' {
+            rest = &rest[1..];
+        }
+        self.after_cr = false;
 
-        self.buffer = incomplete.unwrap_or_default();
+        while let Some(end) = rest.iter().position(|&b| b == b'\n' || b == b'\r') {
+            self.line.extend_from_slice(&rest[..end]);
+            let line = std::mem::take(&mut self.line);
+            self.process_line(&line, &mut events);
 
-        // Fix code duplication: Extract helper function for processing event
-        // Fix code duplication: Extract helper to process event completion
-        let mut process_completed_event = |event_type: Option<String>, data: &mut Vec<String>| {
-            if let Some((evt_type, data_str)) = finish_current_event(event_type, data) {
-                if let Some(event) = parse_sse_event(&evt_type, &data_str) {
-                    events.push(event);
+            let crlf = rest[end] == b'\r';
+            rest = &rest[end + 1..];
+            if crlf {
+                if rest.is_empty() {
+                    self.after_cr = true;
+                } else if rest[0] == b'\n' {
+                    rest = &rest[1..];
                 }
             }
-        };
+        }
+        self.line.extend_from_slice(rest);
+        events
+    }
 
-        for line in complete_lines {
-            if line.trim().is_empty() {
-                // Empty line completes current event
-                process_completed_event(self.current_event.take(), &mut self.current_data);
-            } else if let Some(event_data) = line.strip_prefix("event:") {
-                // Fix SSE format deviation: Require space after colon per SSE spec
-                // But handle both "event:" and "event: " for compatibility
-                process_completed_event(self.current_event.take(), &mut self.current_data);
-                self.current_event = Some(event_data.trim().to_string());
-            } else if let Some(data) = line.strip_prefix("data:") {
-                // Fix SSE format deviation: Require space after colon per SSE spec
-                // But handle both "data:" and "data: " for compatibility
-                self.current_data.push(data.trim().to_string());
-            } else {
-                // Fix error handling: Log malformed SSE lines for debugging
-                tracing::warn!("Malformed SSE line (skipping): {}", line);
+    fn process_line(&mut self, line: &[u8], events: &mut Vec<BackendSSEEvent>) {
+        let decoded = String::from_utf8_lossy(line);
+        if matches!(decoded, std::borrow::Cow::Owned(_)) {
+            tracing::warn!("Invalid UTF-8 in SSE line; replacing invalid bytes");
+        }
+        let mut line: &str = &decoded;
+        if !self.started {
+            self.started = true;
+            line = line.strip_prefix(BOM).unwrap_or(line);
+        }
+
+        if line.is_empty() {
+            self.dispatch(events);
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => value.clone_into(&mut self.event_type),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
             }
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            // Reconnection is not attempted, so retry hints are irrelevant
+            "id" | "retry" => {}
+            _ => tracing::debug!("Ignoring unknown SSE field: {}", field),
         }
+    }
 
-        events
+    /// Completes the pending event on a blank line; an event without data is
+    /// discarded, as the spec requires.
+    fn dispatch(&mut self, events: &mut Vec<BackendSSEEvent>) {
+        let event_type = std::mem::take(&mut self.event_type);
+        if self.data.is_empty() {
+            return;
+        }
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+        let event_type = if event_type.is_empty() {
+            DEFAULT_EVENT_TYPE
+        } else {
+            &event_type
+        };
+        if let Some(mut event) = parse_sse_event(event_type, &data) {
+            event.id.clone_from(&self.last_event_id);
+            events.push(event);
+        }
     }
 }
 
@@ -186,4 +211,119 @@ mod tests {
         assert_eq!(events.len(), 1);
         assert_eq!(events[0].event_type, "done");
     }
+
+    #[test]
+    fn test_sse_parser_multiline_data_and_fields() {
+        let mut parser = SSEParser::new();
+        let chunk = b": keep-alive\nid: 7\nevent: delta\ndata: {\"text\":\ndata:  \"two lines\"}\nretry: 1000\n\n";
+        let events = parser.parse_chunk(chunk);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "delta");
+        assert_eq!(events[0].data["text"], "two lines");
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+
+        // The ID persists, the event type does not
+        let events = parser.parse_chunk(b"data: {}\n\n");
+        assert_eq!(events[0].event_type, "message");
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+    }
+
+    #[test]
+    fn test_sse_parser_bom_and_lone_cr() {
+        let mut parser = SSEParser::new();
+        assert!(parser.parse_chunk(b"\xEF\xBB").is_empty());
+        let events = parser.parse_chunk(b"\xBFdata: {\"text\":\"caf\xC3");
+        assert!(events.is_empty());
+        let events = parser.parse_chunk(b"\xA9\"}\r\rdata: [DONE]\r\r");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data["text"], "caf\u{e9}");
+        assert_eq!(events[1].event_type, "done");
+    }
+
+    #[test]
+    fn test_sse_parser_event_without_data_is_discarded() {
+        let mut parser = SSEParser::new();
+        let events = parser.parse_chunk(b"event: ping\n\ndata: {}\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "message");
+    }
+
+    /// Renders a stream exercising every framing rule with `newline` endings.
+    fn sample_stream(newline: &str) -> Vec<u8> {
+        let lines = [
+            "\u{feff}: comment",
+            "id: 1",
+            "event: delta",
+            "data: {\"message\":",
+            "data: {\"content\":\"h\u{e9}llo \u{1f600}\"}}",
+            "",
+            "data:{\"n\":2}",
+            "unknown: ignored",
+            "",
+            "event: empty",
+            "",
+            "id: 3",
+            "data: [DONE]",
+            "",
+        ];
+        lines
+            .iter()
+            .flat_map(|line| [*line, newline])
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn parse_in_chunks(stream: &[u8], cuts: &[usize]) -> Vec<(String, String, Option<String>)> {
+        let mut parser = SSEParser::new();
+        let mut events = Vec::new();
+        let mut start = 0;
+        for &cut in cuts.iter().chain(std::iter::once(&stream.len())) {
+            events.extend(parser.parse_chunk(&stream[start..cut]));
+            start = cut;
+        }
+        events
+            .into_iter()
+            .map(|e| (e.event_type, e.data.to_string(), e.id))
+            .collect()
+    }
+
+    #[test]
+    fn test_sse_parser_fuzz_chunk_boundaries() {
+        let expected = parse_in_chunks(&sample_stream("\n"), &[]);
+        assert_eq!(expected.len(), 3);
+        assert_eq!(expected[0].0, "delta");
+        assert_eq!(expected[0].2.as_deref(), Some("1"));
+        assert_eq!(expected[2].0, "done");
+        assert_eq!(expected[2].2.as_deref(), Some("3"));
+
+        // Small xorshift so failures reproduce
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for newline in ["\n", "\r\n", "\r"] {
+            let stream = sample_stream(newline);
+            for cut in 0..=stream.len() {
+                assert_eq!(
+                    parse_in_chunks(&stream, &[cut]),
+                    expected,
+                    "split at {cut} with {newline:?} endings"
+                );
+            }
+            for _ in 0..500 {
+                let mut cuts: Vec<usize> = (0..next() % 12)
+                    .map(|_| usize::try_from(next() % stream.len() as u64).unwrap_or(0))
+                    .collect();
+                cuts.sort_unstable();
+                assert_eq!(
+                    parse_in_chunks(&stream, &cuts),
+                    expected,
+                    "cuts {cuts:?} with {newline:?} endings"
+                );
+            }
+        }
+    }
 }
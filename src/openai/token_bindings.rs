@@ -0,0 +1,164 @@
+// Sticky harvester tokens per conversation session.
+//
+// The harvester rotates its ChatGPT session whenever it sees fit, and every
+// request otherwise takes whatever tokens it currently hands out. A
+// conversation whose turns straddle a rotation then mixes access and Arkose
+// tokens from two sessions, which the backend answers with sporadic 401s.
+// Requests that carry a session ID therefore keep the tokens their session
+// first used until those expire (after `openai.access_token_ttl_secs`, or
+// `openai.arkose_token_ttl_secs` when an Arkose token is needed) or the
+// backend rejects them, and only then pick up the harvester's current ones.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::OpenAIConfig;
+use crate::openai::models::TokenResponse;
+use crate::services::clock::{self, SharedClock};
+
+const DEFAULT_ACCESS_TOKEN_TTL_SECS: u64 = 3600;
+const DEFAULT_ARKOSE_TOKEN_TTL_SECS: u64 = 120;
+
+/// Tokens bound to conversation sessions.
+pub struct TokenBindings {
+    access_token_ttl: Duration,
+    arkose_token_ttl: Duration,
+    bindings: Mutex<HashMap<String, (TokenResponse, Instant)>>,
+    clock: SharedClock,
+}
+
+impl Default for TokenBindings {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_secs(DEFAULT_ACCESS_TOKEN_TTL_SECS),
+            Duration::from_secs(DEFAULT_ARKOSE_TOKEN_TTL_SECS),
+        )
+    }
+}
+
+impl TokenBindings {
+    #[must_use]
+    pub fn new(access_token_ttl: Duration, arkose_token_ttl: Duration) -> Self {
+        Self {
+            access_token_ttl,
+            arkose_token_ttl,
+            bindings: Mutex::new(HashMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    #[must_use]
+    pub fn from_config(config: &OpenAIConfig) -> Self {
+        Self::new(
+            Duration::from_secs(config.access_token_ttl_secs),
+            Duration::from_secs(config.arkose_token_ttl_secs),
+        )
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (TokenResponse, Instant)>> {
+        self.bindings
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Tokens bound to `session`, if they are still fresh enough for a
+    /// request that does (or does not) need an Arkose token.
+    #[must_use]
+    pub fn get(&self, session: &str, require_arkose: bool) -> Option<TokenResponse> {
+        let now = self.clock.now();
+        let mut bindings = self.lock();
+        let (tokens, bound_at) = bindings.get(session)?;
+        let age = now.duration_since(*bound_at);
+        if age >= self.access_token_ttl {
+            bindings.remove(session);
+            return None;
+        }
+        if require_arkose && (tokens.arkose_token.is_none() || age >= self.arkose_token_ttl) {
+            return None;
+        }
+        Some(tokens.clone())
+    }
+
+    /// Binds `session` to `tokens`, replacing any earlier binding.
+    pub fn bind(&self, session: &str, tokens: &TokenResponse) {
+        let now = self.clock.now();
+        let mut bindings = self.lock();
+        bindings.retain(|_, (_, bound_at)| now.duration_since(*bound_at) < self.access_token_ttl);
+        bindings.insert(session.to_string(), (tokens.clone(), now));
+    }
+
+    /// Unbinds every session using `access_token`, after the backend rejected
+    /// it. Returns how many sessions were unbound.
+    pub fn release(&self, access_token: &str) -> usize {
+        let mut bindings = self.lock();
+        let before = bindings.len();
+        bindings.retain(|_, (tokens, _)| tokens.access_token != access_token);
+        before - bindings.len()
+    }
+
+    /// Unbinds every session. Returns how many were bound.
+    pub fn clear(&self) -> usize {
+        let mut bindings = self.lock();
+        let bound = bindings.len();
+        bindings.clear();
+        bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::clock::ManualClock;
+    use std::sync::Arc;
+
+    fn tokens(access_token: &str, arkose_token: Option<&str>) -> TokenResponse {
+        TokenResponse {
+            access_token: access_token.to_string(),
+            arkose_token: arkose_token.map(str::to_string),
+            expires_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_sessions_keep_their_tokens_until_expiry_or_rejection() {
+        let clock = Arc::new(ManualClock::new());
+        let bindings = TokenBindings::new(Duration::from_secs(600), Duration::from_secs(60))
+            .with_clock(clock.clone());
+        bindings.bind("conv-a", &tokens("access-1", Some("arkose-1")));
+        // The harvester rotated; a second conversation picks up the new session
+        bindings.bind("conv-b", &tokens("access-2", None));
+        assert_eq!(
+            bindings.get("conv-a", true).map(|t| t.access_token),
+            Some("access-1".to_string())
+        );
+        assert_eq!(
+            bindings.get("conv-b", false).map(|t| t.access_token),
+            Some("access-2".to_string())
+        );
+        // No Arkose token bound
+        assert!(bindings.get("conv-b", true).is_none());
+        assert!(bindings.get("conv-c", false).is_none());
+
+        // Arkose tokens expire first; the access token stays bound
+        clock.advance(Duration::from_secs(120));
+        assert!(bindings.get("conv-a", true).is_none());
+        assert!(bindings.get("conv-a", false).is_some());
+
+        // A rejected token unbinds every session using it
+        bindings.bind("conv-c", &tokens("access-2", None));
+        assert_eq!(bindings.release("access-2"), 2);
+        assert!(bindings.get("conv-b", false).is_none());
+
+        clock.advance(Duration::from_secs(600));
+        assert!(bindings.get("conv-a", false).is_none());
+        assert_eq!(bindings.clear(), 0);
+    }
+}
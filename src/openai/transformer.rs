@@ -56,11 +56,25 @@ pub fn transform_to_backend(
     })
 }
 
+/// At most the first 200 bytes of `data`, cut on a character boundary, for logs.
+fn preview(data: &str) -> String {
+    const PREVIEW_BYTES: usize = 200;
+    if data.len() <= PREVIEW_BYTES {
+        return data.to_string();
+    }
+    let mut end = PREVIEW_BYTES;
+    while !data.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &data[..end])
+}
+
 pub fn parse_sse_event(event_type: &str, data_str: &str) -> Option<BackendSSEEvent> {
     if data_str == "[DONE]" {
         return Some(BackendSSEEvent {
             event_type: "done".to_string(),
             data: serde_json::json!({}),
+            id: None,
         });
     }
 
@@ -68,6 +82,7 @@ pub fn parse_sse_event(event_type: &str, data_str: &str) -> Option<BackendSSEEve
         Ok(data) => Some(BackendSSEEvent {
             event_type: event_type.to_string(),
             data,
+            id: None,
         }),
         Err(e) => {
             // Fix error swallowing: Log detailed error information
@@ -76,11 +91,7 @@ pub fn parse_sse_event(event_type: &str, data_str: &str) -> Option<BackendSSEEve
                 event_type,
                 e,
                 data_str.len(),
-                if data_str.len() > 200 {
-                    format!("{}...", &data_str[..200])
-                } else {
-                    data_str.to_string()
-                }
+                preview(data_str)
             );
             None
         }
@@ -101,6 +112,7 @@ pub fn transform_sse_to_openai_chunk(
         return Some(ChatCompletionChunk {
             id: request_id.to_string(),
             object: "chat.completion.chunk".to_string(),
+            usage: None,
             created,
             model: model.to_string(),
             choices: vec![ChatCompletionChunkChoice {
@@ -108,6 +120,7 @@ pub fn transform_sse_to_openai_chunk(
                 delta: DeltaMessage {
                     role: None,
                     content: None,
+                    tool_calls: None,
                 },
                 finish_reason: Some("stop".to_string()),
             }],
@@ -131,11 +144,7 @@ pub fn transform_sse_to_openai_chunk(
             warn!(
                 "Failed to parse backend message data (error: {}, data preview: {}): {}",
                 e,
-                if data_str.len() > 200 {
-                    format!("{}...", &data_str[..200])
-                } else {
-                    data_str.clone()
-                },
+                preview(&data_str),
                 e
             );
             return None;
@@ -161,6 +170,7 @@ pub fn transform_sse_to_openai_chunk(
     Some(ChatCompletionChunk {
         id: request_id.to_string(),
         object: "chat.completion.chunk".to_string(),
+        usage: None,
         created,
         model: model.to_string(),
         choices: vec![ChatCompletionChunkChoice {
@@ -168,6 +178,7 @@ pub fn transform_sse_to_openai_chunk(
             delta: DeltaMessage {
                 role: None,
                 content: Some(content_str),
+                tool_calls: None,
             },
             finish_reason: None,
         }],
@@ -178,6 +189,7 @@ pub fn transform_sse_to_openai_chunk(
 mod tests {
     use super::*;
     use crate::models::openai::{ChatCompletionRequest, ChatMessage, Role};
+    use proptest::prelude::*;
 
     #[test]
     fn test_transform_request_basic() {
@@ -187,22 +199,92 @@ mod tests {
                 role: Role::User,
                 content: "Hello".to_string(),
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             }],
             stream: false,
-            temperature: 0.7,
-            top_p: 0.9,
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            top_k: None,
             max_tokens: Some(100),
             stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
         };
 
-        let backend_req = transform_to_backend(
-            &req.model,
-            &req.messages,
-            Some(req.temperature),
-            req.max_tokens,
-        )
-        .unwrap();
+        let backend_req =
+            transform_to_backend(&req.model, &req.messages, req.temperature, req.max_tokens)
+                .unwrap();
         assert_eq!(backend_req.messages.len(), 1);
         assert_eq!(backend_req.messages[0].role, "user");
     }
+
+    proptest! {
+        #[test]
+        fn prop_transform_to_backend_keeps_every_message(
+            messages in prop::collection::vec((0..4u8, "\\PC{0,40}"), 0..12)
+        ) {
+            let roles = [Role::System, Role::User, Role::Assistant, Role::Tool];
+            let messages: Vec<ChatMessage> = messages
+                .into_iter()
+                .map(|(role, content)| ChatMessage {
+                    role: roles[usize::from(role)].clone(),
+                    content,
+                    name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
+                })
+                .collect();
+            let backend_req = transform_to_backend("gpt-4", &messages, None, None)
+                .expect("every message list should transform");
+
+            prop_assert_eq!(backend_req.messages.len(), messages.len());
+            for (sent, original) in backend_req.messages.iter().zip(&messages) {
+                let BackendContent::Text { parts, .. } = &sent.content else {
+                    panic!("messages are sent as text parts");
+                };
+                prop_assert_eq!(parts, &vec![original.content.clone()]);
+            }
+        }
+
+        #[test]
+        fn prop_message_event_content_survives(
+            parts in prop::collection::vec("\\PC{0,40}", 0..5)
+        ) {
+            let data = serde_json::json!({
+                "message": {"id": "msg_1", "content": {"content_type": "text", "parts": parts}}
+            });
+            let event = parse_sse_event("message", &data.to_string())
+                .expect("valid JSON should parse");
+            let chunk = transform_sse_to_openai_chunk(&event, "gpt-4", "req-1")
+                .expect("message events produce a chunk");
+            prop_assert_eq!(chunk.choices[0].delta.content.clone(), Some(parts.concat()));
+        }
+
+        #[test]
+        fn prop_arbitrary_event_data_never_panics(
+            event_type in "message|done|delta|\\PC{0,8}",
+            data in "\\PC{0,400}"
+        ) {
+            if let Some(event) = parse_sse_event(&event_type, &data) {
+                let _ = transform_sse_to_openai_chunk(&event, "gpt-4", "req-1");
+            }
+            // Message JSON of the wrong shape is logged with a preview
+            let wrapped = serde_json::json!({"message": {"content": {"parts": data}}});
+            let event = BackendSSEEvent {
+                event_type: "message".to_string(),
+                data: wrapped,
+                id: None,
+            };
+            let _ = transform_sse_to_openai_chunk(&event, "gpt-4", "req-1");
+        }
+    }
 }
@@ -0,0 +1,163 @@
+// Cooldown for harvester sessions the ChatGPT backend's WAF keeps blocking.
+//
+// Once the WAF has flagged a session's fingerprint, every further request from
+// it is another 403 that deepens the flag. After `threshold` blocks within
+// `window_secs` the session is cooled down: requests that would use it are
+// refused locally with a `Retry-After` while the harvester is asked for a
+// fresh session. Sessions are identified by a hash of their access token, so a
+// refreshed session is usable straight away.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::WafCooldownConfig;
+
+#[derive(Default)]
+struct SessionBlocks {
+    blocks: Vec<Instant>,
+    cooled_until: Option<Instant>,
+}
+
+/// Tracks WAF blocks per harvester session.
+pub struct WafCooldown {
+    threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    sessions: Mutex<HashMap<String, SessionBlocks>>,
+}
+
+impl Default for WafCooldown {
+    fn default() -> Self {
+        Self::from_config(&WafCooldownConfig::default())
+    }
+}
+
+impl WafCooldown {
+    #[must_use]
+    pub fn new(threshold: usize, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            cooldown,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn from_config(config: &WafCooldownConfig) -> Self {
+        Self::new(
+            config.threshold,
+            Duration::from_secs(config.window_secs),
+            Duration::from_secs(config.cooldown_secs),
+        )
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, SessionBlocks>> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Time left before the session behind `access_token` may be used again.
+    #[must_use]
+    pub fn remaining(&self, access_token: &str) -> Option<Duration> {
+        let sessions = self.lock();
+        let until = sessions.get(&session_id(access_token))?.cooled_until?;
+        until
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())
+    }
+
+    /// Counts a WAF block against the session; returns `true` when this block
+    /// puts the session into cooldown.
+    pub fn record_block(&self, access_token: &str) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        let now = Instant::now();
+        let mut sessions = self.lock();
+        sessions.retain(|_, session| {
+            session
+                .blocks
+                .last()
+                .is_some_and(|at| now.duration_since(*at) < self.window)
+                || session.cooled_until.is_some_and(|until| until > now)
+        });
+        let session = sessions.entry(session_id(access_token)).or_default();
+        if session.cooled_until.is_some_and(|until| until > now) {
+            return false;
+        }
+        session
+            .blocks
+            .retain(|at| now.duration_since(*at) < self.window);
+        session.blocks.push(now);
+        if session.blocks.len() < self.threshold {
+            return false;
+        }
+        session.blocks.clear();
+        session.cooled_until = Some(now + self.cooldown);
+        true
+    }
+
+    /// Forgets every session's blocks and cooldown. Returns how many sessions
+    /// were cooling down.
+    pub fn clear(&self) -> usize {
+        let now = Instant::now();
+        let mut sessions = self.lock();
+        let cooling = sessions
+            .values()
+            .filter(|session| session.cooled_until.is_some_and(|until| until > now))
+            .count();
+        sessions.clear();
+        cooling
+    }
+
+    /// Clears the block count after a request from the session got through.
+    pub fn record_success(&self, access_token: &str) {
+        let mut sessions = self.lock();
+        if let Some(session) = sessions.get_mut(&session_id(access_token)) {
+            session.blocks.clear();
+        }
+    }
+}
+
+/// Short, non-reversible identifier for a session, safe to log.
+#[must_use]
+pub fn session_id(access_token: &str) -> String {
+    let digest = Sha256::digest(access_token.as_bytes());
+    digest[..6].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_blocks_cool_the_session_down() {
+        let cooldown = WafCooldown::new(3, Duration::from_secs(60), Duration::from_secs(600));
+        assert!(!cooldown.record_block("session-a"));
+        assert!(!cooldown.record_block("session-a"));
+        // A request that got through resets the count
+        cooldown.record_success("session-a");
+        assert!(!cooldown.record_block("session-a"));
+        assert!(!cooldown.record_block("session-a"));
+        assert!(cooldown.remaining("session-a").is_none());
+
+        assert!(cooldown.record_block("session-a"));
+        assert!(cooldown
+            .remaining("session-a")
+            .is_some_and(|left| left > Duration::from_secs(590)));
+        // Already cooling down: further blocks do not trigger again
+        assert!(!cooldown.record_block("session-a"));
+        // A refreshed session is unaffected
+        assert!(cooldown.remaining("session-b").is_none());
+
+        assert_eq!(cooldown.clear(), 1);
+        assert!(cooldown.remaining("session-a").is_none());
+
+        let disabled = WafCooldown::new(0, Duration::from_secs(60), Duration::from_secs(600));
+        assert!(!disabled.record_block("session-a"));
+    }
+}
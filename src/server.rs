@@ -0,0 +1,597 @@
+// The proxy as a library.
+//
+// `Server::builder(config)` sets up the shared state, background tasks and
+// axum router that the `vertex-bridge` binary runs. Other axum apps can take
+// the router and mount it under a path prefix of their own, optionally with a
+// state they built or adjusted themselves, without the binary's stdin CLI.
+
+use axum::{
+    middleware,
+    routing::{get, post, put},
+    Router,
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::config::AppConfig;
+use crate::handlers::{
+    admin, chat, conversations, health, metrics, models, openapi, status, tokens, usage,
+};
+use crate::middleware::{
+    access_log::{access_log_middleware, AccessLog},
+    admin_signature::{admin_signature_middleware, AdminSignatures},
+    api_version::api_version_middleware,
+    auth::{admin_middleware, auth_middleware},
+    body_limit::{body_limit_middleware, BodyLimits},
+    compression::{compression_layer, stream_buffering_middleware},
+    decompression::decompression_middleware,
+    maintenance_mode::maintenance_mode_middleware,
+    priority::priority_middleware,
+    rate_limit::{rate_limit_middleware, RateLimiter},
+    security_headers::security_headers_middleware,
+    trace_context::trace_context_middleware,
+};
+use crate::openai::circuit_breaker::{CircuitBreaker, CircuitStateStore};
+use crate::openai::errors;
+use crate::openai::metrics::Metrics;
+use crate::openai::token_bindings::TokenBindings;
+use crate::openai::waf_cooldown::WafCooldown;
+use crate::services::auth::TokenManager;
+use crate::services::budgets::BudgetManager;
+use crate::services::cache::Cache;
+use crate::services::experiments::Experiments;
+use crate::services::fallback_responses::FallbackResponses;
+use crate::services::idempotency::IdempotencyStore;
+use crate::services::keys::{self, KeyStore};
+use crate::services::listener;
+use crate::services::maintenance;
+use crate::services::mirror::Mirror;
+use crate::services::model_catalog::ModelCatalog;
+use crate::services::model_registry::ModelRegistry;
+use crate::services::notifier::{self, Notifier};
+use crate::services::post_processor::PostProcessor;
+use crate::services::prompt_templates::PromptTemplateStore;
+use crate::services::providers::{self, Provider, ProviderRegistry};
+use crate::services::routing::{LatencyTracker, SessionAffinity};
+use crate::services::routing_rules::RoutingRules;
+use crate::services::scheduler::PriorityScheduler;
+use crate::services::slo::Slos;
+use crate::services::sqlite_store::SqliteStore;
+use crate::services::storage::{self, SharedStorage};
+use crate::services::transcripts::Transcripts;
+use crate::services::usage::UsageTracker;
+use crate::services::warmup::{self, Warmup};
+use crate::services::watermark::Watermark;
+use crate::state::AppState;
+
+/// Builds the shared state for `config`: loads keys, models, templates,
+/// experiments and routing rules, and opens persistent storage if configured.
+///
+/// # Errors
+///
+/// Returns an error if any configured file cannot be loaded or the SQLite
+/// store cannot be opened.
+pub async fn initialize_state(config: &AppConfig) -> anyhow::Result<AppState> {
+    for warning in config.cluster_warnings() {
+        warn!("{warning}");
+    }
+    let mut rate_limiter = RateLimiter::new(
+        config.rate_limit.capacity,
+        config.rate_limit.refill_per_second,
+    );
+    let mut circuit_breaker = CircuitBreaker::new(
+        config.circuit_breaker.failure_threshold,
+        config.circuit_breaker.timeout_secs,
+        config.circuit_breaker.success_threshold,
+    );
+    let metrics = Arc::new(Metrics::new());
+    let provider_registry = Arc::new(ProviderRegistry::with_config(
+        &Some(config.anthropic.bridge_url.clone()),
+        &Some(config.gemini_cli.clone()),
+        &Some(config.ollama.clone()),
+        &Some(config.deepseek.clone()),
+        &Some(config.bedrock.clone()),
+        &Some(config.openai_compatible.clone()),
+    ));
+    let mut cache = Cache::new(config.cache.enabled, config.cache.default_ttl_secs)
+        .with_vary_on_key(config.cache.vary_on_key);
+    let model_registry = Arc::new(
+        ModelRegistry::load(
+            config.models.overrides_file.as_deref(),
+            &config.models.aliases,
+        )
+        .and_then(|registry| registry.with_context_fallbacks(&config.models.context_fallbacks))
+        .and_then(|registry| registry.with_deprecations(&config.models.deprecations))
+        .map_err(|e| {
+            error!("Failed to initialize model registry: {e}");
+            anyhow::anyhow!("Model registry initialization failed: {e}")
+        })?,
+    );
+    let mut key_store = KeyStore::load(config.keys.file.as_deref()).map_err(|e| {
+        error!("Failed to load API keys: {e}");
+        anyhow::anyhow!("Key store initialization failed: {e}")
+    })?;
+    let prompts = Arc::new(
+        PromptTemplateStore::load(config.prompts.file.as_deref()).map_err(|e| {
+            error!("Failed to load prompt templates: {e:#}");
+            anyhow::anyhow!("Prompt template initialization failed: {e:#}")
+        })?,
+    );
+    let experiments = Arc::new(
+        Experiments::load(config.experiments.file.as_deref()).map_err(|e| {
+            error!("Failed to load experiments: {e:#}");
+            anyhow::anyhow!("Experiments initialization failed: {e:#}")
+        })?,
+    );
+    let fallback_responses = Arc::new(
+        FallbackResponses::load(config.fallback_responses.file.as_deref()).map_err(|e| {
+            error!("Failed to load fallback responses: {e:#}");
+            anyhow::anyhow!("Fallback responses initialization failed: {e:#}")
+        })?,
+    );
+    let mirror = Arc::new(Mirror::from_config(&config.mirror).map_err(|e| {
+        error!("Failed to start request mirroring: {e:#}");
+        anyhow::anyhow!("Request mirroring initialization failed: {e:#}")
+    })?);
+    let routing_rules = Arc::new(
+        RoutingRules::load(config.models.rules_file.as_deref()).map_err(|e| {
+            error!("Failed to load routing rules: {e:#}");
+            anyhow::anyhow!("Routing rules initialization failed: {e:#}")
+        })?,
+    );
+    let scheduler = Arc::new(PriorityScheduler::new(config.scheduler.max_in_flight));
+
+    let token_manager = TokenManager::new(
+        config.vertex.api_key.clone(),
+        config.vertex.credentials_file.clone(),
+        config.vertex.project_id.clone(),
+    )
+    .and_then(|tm| tm.with_tenants(key_store.vertex_credentials()))
+    .map_err(|e| {
+        error!("Failed to initialize TokenManager: {e:#}");
+        anyhow::anyhow!("TokenManager initialization failed: {e:#}")
+    })?;
+
+    let store = match config.storage.sqlite_path.as_deref() {
+        Some(path) => {
+            let store = Arc::new(SqliteStore::open(path).map_err(|e| {
+                error!("Failed to open SQLite store: {e:#}");
+                anyhow::anyhow!("SQLite store initialization failed: {e}")
+            })?);
+            info!("Persistent storage enabled at {}", path);
+            Some(store)
+        }
+        None => None,
+    };
+    let storage: Option<SharedStorage> = match (config.storage.redis_url.as_deref(), &store) {
+        (Some(url), _) => {
+            let storage = storage::open_redis(url).await.map_err(|e| {
+                error!("Failed to open Redis storage: {e:#}");
+                anyhow::anyhow!("Redis storage initialization failed: {e:#}")
+            })?;
+            info!("Key-value storage enabled in Redis");
+            Some(storage)
+        }
+        (None, Some(store)) => Some(Arc::clone(store) as SharedStorage),
+        (None, None) => None,
+    };
+    let mut idempotency = IdempotencyStore::from_config(&config.idempotency);
+    let usage = match &storage {
+        Some(storage) => {
+            // The keys file is authoritative: its keys are written through and
+            // stored keys it no longer lists are deleted before the rest merge
+            keys::save_stored(storage.as_ref(), key_store.to_stored()).await?;
+            let removed = keys::prune_stored(storage.as_ref(), &key_store.key_hashes()).await?;
+            if removed > 0 {
+                info!("Removed {removed} stored API keys no longer in the keys file");
+            }
+            key_store.merge_stored(keys::load_stored(storage.as_ref()).await?);
+            let mut usage = UsageTracker::with_store(Arc::clone(storage));
+            usage.hydrate().await?;
+            if config.cluster.enabled {
+                cache = cache.with_shared_store(Arc::clone(storage));
+                idempotency = idempotency.with_shared_store(Arc::clone(storage));
+                usage = usage.with_shared_spend();
+            }
+            Arc::new(usage)
+        }
+        None => Arc::new(UsageTracker::new()),
+    };
+    if let (true, Some(storage)) = (config.cluster.enabled, &storage) {
+        rate_limiter = rate_limiter.with_shared_store(Arc::clone(storage));
+        info!("Cluster mode: rate limits, cache, idempotent responses and spend are shared");
+    }
+    rate_limiter = rate_limiter.with_exempt_keys(key_store.exempt_key_hashes());
+    let budgets = Arc::new(BudgetManager::new(key_store.budget_limits()));
+
+    if config.circuit_breaker.persist {
+        let state_store = match (&config.circuit_breaker.state_file, &storage) {
+            (Some(path), _) => CircuitStateStore::File(path.into()),
+            (None, Some(storage)) => CircuitStateStore::Shared(Arc::clone(storage)),
+            (None, None) => anyhow::bail!(
+                "Circuit breaker persistence needs circuit_breaker.state_file, \
+                 storage.sqlite_path or storage.redis_url"
+            ),
+        };
+        circuit_breaker = circuit_breaker.with_state_store(state_store);
+        // A missing or unreadable snapshot only costs the head start
+        if let Err(e) = circuit_breaker.restore().await {
+            warn!("Failed to restore circuit breaker state: {e:#}");
+        }
+    }
+
+    Ok(AppState {
+        config: Arc::new(config.clone()),
+        token_manager,
+        provider_registry,
+        rate_limiter,
+        circuit_breaker: Arc::new(circuit_breaker),
+        metrics,
+        cache: Arc::new(cache),
+        in_flight: Default::default(),
+        waf_cooldown: Arc::new(WafCooldown::from_config(&config.openai.waf_cooldown)),
+        token_bindings: Arc::new(TokenBindings::from_config(&config.openai)),
+        idempotency: Arc::new(idempotency),
+        affinity: Arc::new(SessionAffinity::new(Duration::from_secs(
+            config.models.session_ttl_secs,
+        ))),
+        latency: Arc::new(LatencyTracker::new(Duration::from_secs(
+            config.models.latency_window_secs,
+        ))),
+        model_registry,
+        model_catalog: Arc::new(ModelCatalog::from_config(&config.model_discovery)),
+        key_store: Arc::new(key_store),
+        prompts,
+        experiments,
+        fallback_responses,
+        routing_rules,
+        scheduler,
+        usage,
+        budgets,
+        notifier: Arc::new(Notifier::from_config(&config.alerts)),
+        maintenance_mode: Arc::default(),
+        status_page: Arc::default(),
+        warmup: Arc::new(Warmup::from_config(&config.warmup)),
+        post_processor: Arc::new(PostProcessor::from_config(&config.post_process)),
+        mirror,
+        watermark: Arc::new(Watermark::from_config(&config.watermark)),
+        transcripts: Arc::new(Transcripts::from_config(
+            &config.transcripts,
+            storage.clone(),
+        )),
+        slos: Arc::new(Slos::from_config(&config.slo)),
+        shutdown: CancellationToken::new(),
+        store,
+        storage,
+    })
+}
+
+/// The proxy's routes and middleware stack over `state`.
+///
+/// Body limits come from `state.config.server`.
+///
+/// # Errors
+///
+/// Returns an error if `server.body_limits` is malformed.
+pub fn create_router(state: AppState, access_log: AccessLog) -> anyhow::Result<Router> {
+    let body_limits = Arc::new(
+        BodyLimits::from_config(&state.config.server)
+            .map_err(|e| anyhow::anyhow!("Invalid server.body_limits: {e}"))?,
+    );
+    let admin_signatures = Arc::new(AdminSignatures::from_config(&state.config.admin_signature));
+    let public_routes = Router::new()
+        .route("/health", get(health::health_check))
+        .route("/health/ready", get(health::readiness))
+        .route("/status", get(status::status))
+        .route("/openapi.json", get(openapi::openapi_json));
+    #[cfg(feature = "swagger-ui")]
+    let public_routes = public_routes.merge(openapi::swagger_ui());
+
+    let admin_routes = Router::new()
+        .route("/admin/budgets", get(admin::list_budgets))
+        .route("/admin/budgets/:key", put(admin::set_budget))
+        .route("/admin/models/aliases", get(admin::list_model_aliases))
+        .route(
+            "/admin/models/aliases/:alias",
+            put(admin::set_model_alias).delete(admin::delete_model_alias),
+        )
+        .route("/admin/prompts", get(admin::list_prompt_templates))
+        .route(
+            "/admin/prompts/:name",
+            put(admin::set_prompt_template).delete(admin::delete_prompt_template),
+        )
+        .route("/admin/rate-limit", get(admin::rate_limit_stats))
+        .route("/admin/providers/validate", post(admin::validate_providers))
+        .route(
+            "/admin/maintenance",
+            get(admin::maintenance_status)
+                .put(admin::start_maintenance)
+                .delete(admin::end_maintenance),
+        )
+        .route(
+            "/admin/incident",
+            get(admin::incident_status)
+                .put(admin::raise_incident)
+                .delete(admin::resolve_incident),
+        )
+        .route(
+            "/admin/credentials/refresh",
+            post(admin::refresh_credentials),
+        )
+        .route("/admin/models/refresh", post(admin::refresh_models))
+        .route(
+            "/admin/logs",
+            get(admin::log_settings).put(admin::update_log_settings),
+        )
+        .route("/admin/usage/export", get(usage::export_usage))
+        .route_layer(middleware::from_fn_with_state(
+            admin_signatures,
+            admin_signature_middleware,
+        ))
+        .route_layer(middleware::from_fn(admin_middleware));
+
+    let protected_routes = Router::new()
+        .route("/metrics", get(metrics::metrics_handler))
+        .route(
+            "/metrics/prometheus",
+            get(metrics::prometheus_metrics_handler),
+        )
+        .route(
+            "/v1/chat/completions",
+            post(chat::chat_completions)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    priority_middleware,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    body_limits.clone(),
+                    decompression_middleware,
+                )),
+        )
+        .route("/v1/models", get(models::list_models))
+        .route("/v1/models/:model_id", get(models::get_model))
+        .route("/v1/tokens/count", post(tokens::count_tokens))
+        .route(
+            "/v1/conversations/:id",
+            get(conversations::get_conversation).delete(conversations::delete_conversation),
+        )
+        .route("/usage", get(usage::get_usage))
+        .merge(admin_routes)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            maintenance_mode_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.rate_limiter.clone(),
+            rate_limit_middleware,
+        ));
+
+    Ok(Router::new()
+        .merge(public_routes)
+        .merge(protected_routes)
+        .fallback(errors::not_found_handler)
+        .layer(middleware::from_fn_with_state(
+            body_limits,
+            body_limit_middleware,
+        ))
+        .layer(axum::extract::DefaultBodyLimit::disable())
+        .layer(compression_layer())
+        .layer(middleware::from_fn(stream_buffering_middleware))
+        .layer(middleware::from_fn(security_headers_middleware))
+        .layer(middleware::from_fn(api_version_middleware))
+        .layer(middleware::from_fn_with_state(
+            access_log,
+            access_log_middleware,
+        ))
+        .layer(middleware::from_fn(trace_context_middleware))
+        .layer(tower_http::trace::TraceLayer::new_for_http())
+        .with_state(state))
+}
+
+/// Configures a [`Server`].
+pub struct ServerBuilder {
+    config: AppConfig,
+    state: Option<AppState>,
+    access_log: AccessLog,
+    path_prefix: Option<String>,
+    background_tasks: bool,
+}
+
+impl ServerBuilder {
+    /// Uses `state` instead of building one from the config, e.g. to share
+    /// a metrics collector or key store with the host application. Its
+    /// `config` replaces the builder's.
+    #[must_use]
+    pub fn state(mut self, state: AppState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Publishes completed requests to `access_log` (the binary's `/trace`
+    /// command subscribes to it).
+    #[must_use]
+    pub fn access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = access_log;
+        self
+    }
+
+    /// Serves every route under `prefix` (e.g. `/llm`, giving
+    /// `/llm/v1/chat/completions`). Body limits still name the unprefixed paths.
+    #[must_use]
+    pub fn path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.path_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Whether to spawn cache/rate limiter maintenance, the Gemini CLI health
+    /// probe and the alert monitor (default: yes).
+    #[must_use]
+    pub fn background_tasks(mut self, enabled: bool) -> Self {
+        self.background_tasks = enabled;
+        self
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the state cannot be initialized (see
+    /// [`initialize_state`]), the body limits are malformed, or the path
+    /// prefix is not of the form `/name`.
+    pub async fn build(self) -> anyhow::Result<Server> {
+        let state = match self.state {
+            Some(state) => state,
+            None => initialize_state(&self.config).await?,
+        };
+        let mut router = create_router(state.clone(), self.access_log.clone())?;
+        if let Some(prefix) = self.path_prefix {
+            let prefix = prefix.trim_end_matches('/');
+            if !prefix.starts_with('/') || prefix.len() < 2 {
+                anyhow::bail!("Path prefix '{prefix}' must start with '/' and name a path");
+            }
+            router = Router::new().nest(prefix, router);
+        }
+        let mut tasks = if self.background_tasks {
+            spawn_background_tasks(&state)
+        } else {
+            Vec::new()
+        };
+        // Runs even without background tasks, as `/health/ready` waits for it
+        if state.warmup.is_enabled() {
+            tasks.push(warmup::spawn(state.clone()));
+        }
+        Ok(Server {
+            state,
+            access_log: self.access_log,
+            router,
+            tasks,
+        })
+    }
+}
+
+fn spawn_background_tasks(state: &AppState) -> Vec<JoinHandle<()>> {
+    let config = &state.config;
+    let mut tasks = vec![maintenance::spawn_maintenance_task(
+        state.cache.clone(),
+        state.rate_limiter.clone(),
+        state.affinity.clone(),
+        Duration::from_secs(config.maintenance.interval_secs),
+    )];
+    if config.gemini_cli.enabled {
+        tasks.push(providers::spawn_health_probe(
+            state.provider_registry.clone(),
+            Provider::GeminiCLI,
+            Duration::from_secs(config.gemini_cli.health_check_interval_secs),
+        ));
+    }
+    if config.ollama.enabled {
+        tasks.push(providers::spawn_health_probe(
+            state.provider_registry.clone(),
+            Provider::Ollama,
+            Duration::from_secs(config.ollama.health_check_interval_secs),
+        ));
+    }
+    tasks.push(providers::spawn_health_probe(
+        state.provider_registry.clone(),
+        Provider::AnthropicCLI,
+        Duration::from_secs(config.anthropic.health_check_interval_secs),
+    ));
+    if state.notifier.is_enabled() {
+        tasks.push(notifier::spawn_alert_monitor(
+            state.notifier.clone(),
+            state.circuit_breaker.clone(),
+            state.metrics.clone(),
+            state.slos.clone(),
+            &config.alerts,
+            &config.slo,
+        ));
+    }
+    tasks
+}
+
+/// A configured proxy: its state, router and background tasks.
+pub struct Server {
+    state: AppState,
+    access_log: AccessLog,
+    router: Router,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    #[must_use]
+    pub fn builder(config: AppConfig) -> ServerBuilder {
+        ServerBuilder {
+            config,
+            state: None,
+            access_log: AccessLog::default(),
+            path_prefix: None,
+            background_tasks: true,
+        }
+    }
+
+    #[must_use]
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    #[must_use]
+    pub fn access_log(&self) -> &AccessLog {
+        &self.access_log
+    }
+
+    /// The router, for merging into a host application. Background tasks
+    /// keep running for the life of the runtime.
+    pub fn into_router(self) -> Router {
+        self.router
+    }
+
+    /// Serves on `server.host:server.port` (or the socket passed by systemd)
+    /// until `shutdown` completes, then
+    /// drains in-flight requests for up to `server.shutdown_grace_secs` before
+    /// cancelling the rest.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is invalid, cannot be bound, or the
+    /// server fails.
+    pub async fn serve<F>(self, shutdown: F) -> anyhow::Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let server_config = &self.state.config.server;
+        let listener = listener::bind(server_config).await?;
+        info!("Listening on {}", listener.local_addr()?);
+
+        let grace = Duration::from_secs(server_config.shutdown_grace_secs);
+        let cancel_requests = self.state.shutdown.clone();
+        let shutdown = async move {
+            shutdown.await;
+            // Requests still running after the grace period are cancelled so
+            // long streams and CLI processes cannot hold shutdown open
+            info!("Shutting down; cancelling requests still running in {grace:?}");
+            tokio::spawn(async move {
+                tokio::time::sleep(grace).await;
+                cancel_requests.cancel();
+            });
+        };
+
+        let server = axum::serve(listener, self.router).with_graceful_shutdown(shutdown);
+
+        if let Err(e) = server.await {
+            error!("Server error: {e}");
+            return Err(anyhow::anyhow!("Server failed: {e}"));
+        }
+
+        for task in self.tasks {
+            task.abort();
+        }
+        info!("Server shutdown complete");
+        Ok(())
+    }
+}
@@ -1,26 +1,53 @@
 use anyhow::{Context, Result};
-use std::{env, sync::Arc, time::Duration};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::process::Command;
 use tokio::sync::RwLock;
 use tokio::time::timeout;
 use tracing::warn;
 
+use crate::services::clock::{self, SharedClock};
+
 const TOKEN_CACHE_TTL_SECS: u64 = 3300;
 const GCLOUD_TIMEOUT_SECS: u64 = 10;
 const MAX_RETRIES: u32 = 3;
 const INITIAL_RETRY_DELAY_MS: u64 = 100;
 
+/// Vertex credentials for one API key, set in the keys file.
+///
+/// Unset fields fall back to the global `APP_VERTEX__*` configuration, so a key
+/// can bill a different project while sharing the default identity.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct VertexCredentials {
+    pub api_key: Option<String>,
+    pub credentials_file: Option<String>,
+    pub project_id: Option<String>,
+}
+
+/// Resolves Google credentials and the Vertex project for a request.
+///
+/// Holds the global credentials plus per-key overrides; `for_key` returns the
+/// manager to use for a given caller. Tokens are cached per identity.
 #[derive(Clone)]
 pub struct TokenManager {
     api_key: Option<String>,
     credentials_file: Option<String>,
     cached_token: Arc<RwLock<Option<CachedToken>>>,
     project_id: Option<String>,
+    // Token comes only from `credentials_file`, never the ambient gcloud account
+    isolated: bool,
+    tenants: Arc<HashMap<String, TokenManager>>,
+    clock: SharedClock,
 }
 
 struct CachedToken {
     token: String,
-    expires_at: u64,
+    expires_at: Instant,
 }
 
 impl TokenManager {
@@ -34,21 +61,8 @@ impl TokenManager {
         credentials_file: Option<String>,
         project_id: Option<String>,
     ) -> Result<Self> {
-        // Validate credentials_file exists and is readable if provided
         if let Some(ref file) = credentials_file {
-            let path = std::path::Path::new(file);
-            if !path.exists() {
-                return Err(anyhow::anyhow!("Credentials file does not exist: {file}"));
-            }
-            if !path.is_file() {
-                return Err(anyhow::anyhow!("Credentials path is not a file: {file}"));
-            }
-            // Check readability by attempting to read metadata
-            if std::fs::metadata(file).is_err() {
-                return Err(anyhow::anyhow!(
-                    "Cannot read credentials file (permission denied?): {file}"
-                ));
-            }
+            Self::validate_credentials_file(file)?;
         }
 
         let project_id = project_id
@@ -68,9 +82,110 @@ impl TokenManager {
             credentials_file,
             cached_token: Arc::new(RwLock::new(None)),
             project_id,
+            isolated: false,
+            tenants: Arc::new(HashMap::new()),
+            clock: clock::system(),
         })
     }
 
+    /// Reads the time from `clock` instead of the system clock, for this
+    /// manager and the per-key managers added so far.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.tenants = Arc::new(
+            self.tenants
+                .iter()
+                .map(|(name, tenant)| {
+                    (
+                        name.clone(),
+                        Self {
+                            clock: Arc::clone(&clock),
+                            ..tenant.clone()
+                        },
+                    )
+                })
+                .collect(),
+        );
+        self.clock = clock;
+        self
+    }
+
+    fn validate_credentials_file(file: &str) -> Result<()> {
+        let path = std::path::Path::new(file);
+        if !path.exists() {
+            return Err(anyhow::anyhow!("Credentials file does not exist: {file}"));
+        }
+        if !path.is_file() {
+            return Err(anyhow::anyhow!("Credentials path is not a file: {file}"));
+        }
+        // Check readability by attempting to read metadata
+        if std::fs::metadata(file).is_err() {
+            return Err(anyhow::anyhow!(
+                "Cannot read credentials file (permission denied?): {file}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Adds per-key credentials on top of the global ones.
+    ///
+    /// A key with its own API key or credentials file gets its own identity and
+    /// token cache; its project defaults to the one in its credentials file. A
+    /// key that only sets `project_id` reuses the global identity.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the key if its credentials file is unusable.
+    pub fn with_tenants(mut self, tenants: &HashMap<String, VertexCredentials>) -> Result<Self> {
+        let mut resolved = HashMap::with_capacity(tenants.len());
+        for (name, creds) in tenants {
+            let manager = if creds.api_key.is_some() || creds.credentials_file.is_some() {
+                if let Some(file) = &creds.credentials_file {
+                    Self::validate_credentials_file(file)
+                        .with_context(|| format!("Invalid Vertex credentials for key '{name}'"))?;
+                }
+                Self {
+                    api_key: creds.api_key.clone(),
+                    credentials_file: creds.credentials_file.clone(),
+                    cached_token: Arc::new(RwLock::new(None)),
+                    project_id: creds
+                        .project_id
+                        .clone()
+                        .or_else(|| {
+                            creds
+                                .credentials_file
+                                .as_deref()
+                                .and_then(Self::project_from_credentials_file)
+                        })
+                        .or_else(|| self.project_id.clone()),
+                    isolated: creds.credentials_file.is_some(),
+                    tenants: Arc::new(HashMap::new()),
+                    clock: Arc::clone(&self.clock),
+                }
+            } else {
+                Self {
+                    project_id: creds.project_id.clone().or_else(|| self.project_id.clone()),
+                    ..self.clone()
+                }
+            };
+            resolved.insert(name.clone(), manager);
+        }
+        self.tenants = Arc::new(resolved);
+        Ok(self)
+    }
+
+    /// The manager to use for requests made with the API key named `name`.
+    #[must_use]
+    pub fn for_key(&self, name: &str) -> Self {
+        self.tenants.get(name).map_or_else(
+            || self.clone(),
+            |tenant| Self {
+                tenants: Arc::clone(&self.tenants),
+                ..tenant.clone()
+            },
+        )
+    }
+
     #[must_use]
     pub fn is_api_key(&self) -> bool {
         self.api_key.is_some()
@@ -96,31 +211,16 @@ impl TokenManager {
 
         // Fix race condition: Use write lock for double-checked locking pattern
         // First check with read lock (fast path)
-        {
-            let cached = self.cached_token.read().await;
-            if let Some(ref cached_token) = *cached {
-                let now = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
-                if now < cached_token.expires_at {
-                    return Ok(cached_token.token.clone());
-                }
-            }
+        if let Some(token) = self.fresh_token(self.cached_token.read().await.as_ref()) {
+            return Ok(token);
         }
 
         // Acquire write lock for check-and-set (prevents concurrent fetches)
         let mut cached = self.cached_token.write().await;
 
         // Double-check: another thread might have updated cache while we waited for write lock
-        if let Some(ref cached_token) = *cached {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
-            if now < cached_token.expires_at {
-                return Ok(cached_token.token.clone());
-            }
+        if let Some(token) = self.fresh_token(cached.as_ref()) {
+            return Ok(token);
         }
 
         let token = self
@@ -128,11 +228,7 @@ impl TokenManager {
             .await
             .context("Failed to fetch Google Cloud access token")?;
 
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let expires_at = now.saturating_add(TOKEN_CACHE_TTL_SECS);
+        let expires_at = self.clock.now() + Duration::from_secs(TOKEN_CACHE_TTL_SECS);
 
         *cached = Some(CachedToken {
             token: token.clone(),
@@ -142,7 +238,30 @@ impl TokenManager {
         Ok(token)
     }
 
+    /// Drops the cached access token of the global identity and of every
+    /// per-key identity, so the next request fetches a new one. Returns how
+    /// many cached tokens were dropped.
+    pub async fn clear_cached_tokens(&self) -> usize {
+        let mut cleared = usize::from(self.cached_token.write().await.take().is_some());
+        for tenant in self.tenants.values() {
+            // Tenants sharing the global identity share its cache, already cleared
+            cleared += usize::from(tenant.cached_token.write().await.take().is_some());
+        }
+        cleared
+    }
+
+    /// The cached token, unless it has expired.
+    fn fresh_token(&self, cached: Option<&CachedToken>) -> Option<String> {
+        cached
+            .filter(|cached| self.clock.now() < cached.expires_at)
+            .map(|cached| cached.token.clone())
+    }
+
     async fn fetch_token(&self) -> Result<String> {
+        if self.isolated {
+            return self.fetch_token_with_retry(true).await;
+        }
+
         // Try primary method with retries
         let result = self.fetch_token_with_retry(false).await;
         if result.is_ok() {
@@ -221,21 +340,26 @@ impl TokenManager {
         })
     }
 
-    fn extract_project_id(credentials_file: Option<&String>) -> Option<String> {
-        if let Some(file) = credentials_file {
-            match std::fs::read_to_string(file) {
-                Ok(contents) => {
-                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&contents) {
-                        if let Some(project_id) = json.get("project_id").and_then(|v| v.as_str()) {
-                            return Some(project_id.to_string());
-                        }
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to read credentials file {file}: {e}");
-                }
+    fn project_from_credentials_file(file: &str) -> Option<String> {
+        match std::fs::read_to_string(file) {
+            Ok(contents) => serde_json::from_str::<serde_json::Value>(&contents)
+                .ok()?
+                .get("project_id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            Err(e) => {
+                warn!("Failed to read credentials file {file}: {e}");
+                None
             }
         }
+    }
+
+    fn extract_project_id(credentials_file: Option<&String>) -> Option<String> {
+        if let Some(project_id) =
+            credentials_file.and_then(|f| Self::project_from_credentials_file(f))
+        {
+            return Some(project_id);
+        }
 
         // Fix: Use blocking command but only during initialization
         // This is acceptable since extract_project_id is only called from new() during startup
@@ -282,6 +406,7 @@ impl TokenManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::clock::{Clock, ManualClock};
 
     #[tokio::test]
     async fn test_token_manager_api_key() {
@@ -302,6 +427,39 @@ mod tests {
         assert!(!tm.is_api_key());
     }
 
+    #[tokio::test]
+    async fn test_cached_token_expires_with_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let tm = TokenManager::new(None, None, None)
+            .expect("TokenManager should initialize without credentials")
+            .with_clock(clock.clone());
+        *tm.cached_token.write().await = Some(CachedToken {
+            token: "cached".to_string(),
+            expires_at: clock.now() + Duration::from_secs(TOKEN_CACHE_TTL_SECS),
+        });
+
+        let cached = tm.cached_token.read().await;
+        assert_eq!(tm.fresh_token(cached.as_ref()).as_deref(), Some("cached"));
+        clock.advance(Duration::from_secs(TOKEN_CACHE_TTL_SECS - 1));
+        assert_eq!(tm.fresh_token(cached.as_ref()).as_deref(), Some("cached"));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(tm.fresh_token(cached.as_ref()), None);
+    }
+
+    #[tokio::test]
+    async fn test_clear_cached_tokens_forces_a_fetch() {
+        let tm = TokenManager::new(None, None, None)
+            .expect("TokenManager should initialize without credentials");
+        *tm.cached_token.write().await = Some(CachedToken {
+            token: "cached".to_string(),
+            expires_at: tm.clock.now() + Duration::from_secs(TOKEN_CACHE_TTL_SECS),
+        });
+        let copy = tm.for_key("team-a");
+        assert_eq!(tm.clear_cached_tokens().await, 1);
+        assert!(copy.cached_token.read().await.is_none());
+        assert_eq!(tm.clear_cached_tokens().await, 0);
+    }
+
     #[test]
     fn test_token_manager_invalid_credentials_file() {
         // Test with non-existent file
@@ -341,7 +499,7 @@ mod tests {
         // Cache might be None if fetch failed, which is expected in test environment
         if let Some(cached_token) = cached.as_ref() {
             assert!(!cached_token.token.is_empty());
-            assert!(cached_token.expires_at > 0);
+            assert!(cached_token.expires_at > tm.clock.now());
         }
     }
 
@@ -396,7 +554,7 @@ mod tests {
         // Cache state should be consistent (either None or valid CachedToken)
         if let Some(ref token) = *cached {
             assert!(!token.token.is_empty());
-            assert!(token.expires_at > 0);
+            assert!(token.expires_at > tm.clock.now());
         }
     }
 
@@ -461,4 +619,63 @@ mod tests {
         // Cleanup
         let _ = std::fs::remove_file(&temp_file);
     }
+
+    #[test]
+    fn test_tenant_credentials_override_project_and_identity() {
+        let path = std::env::temp_dir().join(format!("tenant-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"{"project_id": "tenant-proj", "type": "service_account"}"#,
+        )
+        .expect("write credentials file");
+        let file = path.to_string_lossy().to_string();
+
+        let tenants = HashMap::from([
+            (
+                "team-a".to_string(),
+                VertexCredentials {
+                    credentials_file: Some(file.clone()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "team-b".to_string(),
+                VertexCredentials {
+                    project_id: Some("billing-b".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let tm = TokenManager::new(Some("global-key".into()), None, Some("global-proj".into()))
+            .expect("global credentials")
+            .with_tenants(&tenants)
+            .expect("tenant credentials");
+
+        let team_a = tm.for_key("team-a");
+        assert_eq!(team_a.get_project_id(), Some("tenant-proj"));
+        assert!(!team_a.is_api_key());
+        assert!(team_a.isolated);
+
+        // Project-only override keeps the global identity
+        let team_b = tm.for_key("team-b");
+        assert_eq!(team_b.get_project_id(), Some("billing-b"));
+        assert!(team_b.is_api_key());
+
+        assert_eq!(tm.for_key("other").get_project_id(), Some("global-proj"));
+        assert_eq!(team_a.for_key("team-b").get_project_id(), Some("billing-b"));
+
+        let missing = HashMap::from([(
+            "team-c".to_string(),
+            VertexCredentials {
+                credentials_file: Some("/nonexistent/creds.json".to_string()),
+                ..Default::default()
+            },
+        )]);
+        assert!(TokenManager::new(None, None, None)
+            .expect("global credentials")
+            .with_tenants(&missing)
+            .is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
 }
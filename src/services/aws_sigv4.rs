@@ -0,0 +1,399 @@
+// AWS Signature Version 4 request signing and credential lookup.
+//
+// AWS APIs (Bedrock here) take no bearer token; every request carries an
+// `Authorization` header holding an HMAC over its method, path, signed headers
+// and body hash, keyed by a key derived from the secret access key, the date,
+// region and service. Credentials come from the config, the standard
+// `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY`/`AWS_SESSION_TOKEN` variables or,
+// on EC2, the instance profile served by the instance metadata service
+// (IMDSv2). Instance profile credentials are temporary and cached until
+// shortly before they expire.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, Url};
+use ring::hmac;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const METADATA_TIMEOUT: Duration = Duration::from_secs(2);
+const METADATA_TOKEN_TTL_SECS: &str = "21600";
+const CREDENTIALS_PATH: &str = "/latest/meta-data/iam/security-credentials/";
+// Instance profile credentials are refreshed this long before they expire
+const EXPIRY_MARGIN_SECS: i64 = 300;
+
+/// An access key pair, with the session token of temporary credentials.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    /// When temporary credentials stop working.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwsCredentials {
+    /// Credentials from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`, if the first two are set.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+        Some(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+            expires_at: None,
+        })
+    }
+
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| expires_at - ChronoDuration::seconds(EXPIRY_MARGIN_SECS) > now)
+    }
+}
+
+/// The instance metadata service's credentials document.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InstanceCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    token: String,
+    expiration: DateTime<Utc>,
+}
+
+/// Finds credentials: configured keys first, then the environment, then the
+/// EC2 instance profile.
+pub struct CredentialChain {
+    configured: Option<AwsCredentials>,
+    metadata_url: String,
+    instance: RwLock<Option<AwsCredentials>>,
+}
+
+impl CredentialChain {
+    #[must_use]
+    pub fn new(configured: Option<AwsCredentials>, metadata_url: &str) -> Self {
+        Self {
+            configured,
+            metadata_url: metadata_url.trim_end_matches('/').to_string(),
+            instance: RwLock::new(None),
+        }
+    }
+
+    /// Current credentials.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the failure when no keys are configured or
+    /// set in the environment and the instance metadata service has none.
+    pub async fn resolve(&self) -> Result<AwsCredentials, String> {
+        if let Some(credentials) = &self.configured {
+            return Ok(credentials.clone());
+        }
+        if let Some(credentials) = AwsCredentials::from_env() {
+            return Ok(credentials);
+        }
+        if let Some(credentials) = self
+            .instance
+            .read()
+            .await
+            .as_ref()
+            .filter(|c| c.is_fresh(Utc::now()))
+        {
+            return Ok(credentials.clone());
+        }
+        let credentials = self.fetch_instance_credentials().await.map_err(|e| {
+            format!("No AWS credentials configured or in the environment, and the instance profile is unavailable: {e}")
+        })?;
+        *self.instance.write().await = Some(credentials.clone());
+        Ok(credentials)
+    }
+
+    /// Fetches the instance profile's credentials over IMDSv2: a session
+    /// token first, then the role name, then the role's credentials.
+    async fn fetch_instance_credentials(&self) -> Result<AwsCredentials, String> {
+        let client = Client::builder()
+            .timeout(METADATA_TIMEOUT)
+            .build()
+            .map_err(|e| format!("HTTP client unavailable: {e}"))?;
+        let token = client
+            .put(format!("{}/latest/api/token", self.metadata_url))
+            .header(
+                "x-aws-ec2-metadata-token-ttl-seconds",
+                METADATA_TOKEN_TTL_SECS,
+            )
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| format!("metadata token request failed: {e}"))?
+            .text()
+            .await
+            .map_err(|e| format!("metadata token unreadable: {e}"))?;
+        let get = |path: String| {
+            client
+                .get(format!("{}{path}", self.metadata_url))
+                .header("x-aws-ec2-metadata-token", token.as_str())
+                .send()
+        };
+        let roles = get(CREDENTIALS_PATH.to_string())
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| format!("instance profile lookup failed: {e}"))?
+            .text()
+            .await
+            .map_err(|e| format!("instance profile unreadable: {e}"))?;
+        let role = roles
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .ok_or_else(|| "the instance has no IAM role".to_string())?;
+        debug!("Fetching AWS credentials for instance role {role}");
+        let document: InstanceCredentials = get(format!("{CREDENTIALS_PATH}{role}"))
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| format!("role credentials request failed: {e}"))?
+            .json()
+            .await
+            .map_err(|e| format!("role credentials unreadable: {e}"))?;
+        info!(
+            "Loaded AWS credentials for instance role {role}, expiring at {}",
+            document.expiration
+        );
+        Ok(AwsCredentials {
+            access_key_id: document.access_key_id,
+            secret_access_key: document.secret_access_key,
+            session_token: Some(document.token),
+            expires_at: Some(document.expiration),
+        })
+    }
+}
+
+/// Percent-encodes `value` as SigV4 requires: everything but unreserved
+/// characters, and `/` too unless `keep_slash`.
+#[must_use]
+pub fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(char::from(byte));
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> hmac::Tag {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+}
+
+/// The host and port a client sends as `Host` for `url`.
+fn host_header(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    }
+}
+
+/// Sorted, encoded query string of `url`.
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (uri_encode(&k, false), uri_encode(&v, false)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Headers that sign a request to `url` for `service` in `region` at `now`:
+/// `x-amz-date`, `x-amz-security-token` for temporary credentials and
+/// `authorization`. The path is encoded once more for the signature, as all
+/// services but S3 expect.
+#[must_use]
+pub fn sign(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    url: &Url,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> Vec<(HeaderName, HeaderValue)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let mut headers = vec![
+        ("host".to_string(), host_header(url)),
+        ("x-amz-date".to_string(), amz_date.clone()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token".to_string(), token.clone()));
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{method}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{}",
+        uri_encode(url.path(), true),
+        canonical_query(url),
+        hex(&Sha256::digest(body)),
+    );
+
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "{ALGORITHM}\n{amz_date}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let secret = format!("AWS4{}", credentials.secret_access_key);
+    let key = [region, service, "aws4_request"]
+        .into_iter()
+        .fold(hmac_sha256(secret.as_bytes(), &date), |key, part| {
+            hmac_sha256(key.as_ref(), part)
+        });
+    let signature = hex(hmac_sha256(key.as_ref(), &string_to_sign).as_ref());
+    let authorization = format!(
+        "{ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+        credentials.access_key_id
+    );
+
+    headers
+        .into_iter()
+        // The client sends `host` itself
+        .skip(1)
+        .chain(std::iter::once((AUTHORIZATION.to_string(), authorization)))
+        .filter_map(|(name, value)| {
+            Some((
+                HeaderName::from_bytes(name.as_bytes()).ok()?,
+                HeaderValue::from_str(&value).ok()?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_credentials() -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+            expires_at: None,
+        }
+    }
+
+    fn example_time() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_sign_matches_aws_test_suite() {
+        // `get-vanilla` from the AWS SigV4 test suite
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = sign(
+            &example_credentials(),
+            "us-east-1",
+            "service",
+            "GET",
+            &url,
+            b"",
+            example_time(),
+        );
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.to_str().unwrap().to_string())
+        };
+        assert_eq!(header("x-amz-date").as_deref(), Some("20150830T123600Z"));
+        assert_eq!(
+            header("authorization").as_deref(),
+            Some("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31")
+        );
+        assert!(header("x-amz-security-token").is_none());
+    }
+
+    #[test]
+    fn test_session_token_is_signed() {
+        let credentials = AwsCredentials {
+            session_token: Some("session".to_string()),
+            ..example_credentials()
+        };
+        let url =
+            Url::parse("https://bedrock-runtime.us-east-1.amazonaws.com/model/m/converse").unwrap();
+        let headers = sign(
+            &credentials,
+            "us-east-1",
+            "bedrock",
+            "POST",
+            &url,
+            b"{}",
+            example_time(),
+        );
+        let authorization = headers
+            .iter()
+            .find(|(n, _)| n == AUTHORIZATION)
+            .map(|(_, v)| v.to_str().unwrap().to_string())
+            .unwrap();
+        assert!(authorization.contains("SignedHeaders=host;x-amz-date;x-amz-security-token"));
+        assert!(headers
+            .iter()
+            .any(|(n, v)| n == "x-amz-security-token" && v == "session"));
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("a-b_c.d~e", false), "a-b_c.d~e");
+        assert_eq!(uri_encode("v1:0", false), "v1%3A0");
+        // Paths are encoded again for the signature
+        assert_eq!(
+            uri_encode("/model/v1%3A0/converse", true),
+            "/model/v1%253A0/converse"
+        );
+    }
+
+    #[test]
+    fn test_instance_credentials_freshness() {
+        let now = example_time();
+        let expiring = |minutes| AwsCredentials {
+            expires_at: Some(now + ChronoDuration::minutes(minutes)),
+            ..example_credentials()
+        };
+        assert!(expiring(60).is_fresh(now));
+        assert!(!expiring(2).is_fresh(now));
+        assert!(example_credentials().is_fresh(now));
+    }
+}
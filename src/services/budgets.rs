@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+use crate::services::usage::UsageTracker;
+
+/// Spend ceilings for one API key, in USD. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BudgetLimits {
+    #[serde(default)]
+    pub daily_usd: Option<f64>,
+    #[serde(default)]
+    pub monthly_usd: Option<f64>,
+}
+
+impl BudgetLimits {
+    #[must_use]
+    pub fn is_unlimited(&self) -> bool {
+        self.daily_usd.is_none() && self.monthly_usd.is_none()
+    }
+}
+
+/// Budget exhaustion details surfaced to the client.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{period} budget of ${limit:.2} exhausted for key '{key}' (spent ${spent:.4})")]
+pub struct BudgetExceeded {
+    pub key: String,
+    pub period: &'static str,
+    pub limit: f64,
+    pub spent: f64,
+}
+
+/// Per-key spend limits, seeded from the keys file and adjustable at runtime.
+#[derive(Debug, Default)]
+pub struct BudgetManager {
+    limits: RwLock<HashMap<String, BudgetLimits>>,
+}
+
+impl BudgetManager {
+    #[must_use]
+    pub fn new(limits: HashMap<String, BudgetLimits>) -> Self {
+        Self {
+            limits: RwLock::new(limits),
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> BudgetLimits {
+        self.limits
+            .read()
+            .await
+            .get(key)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Replaces the limits for `key`; unlimited limits remove the entry.
+    pub async fn set(&self, key: &str, limits: BudgetLimits) {
+        let mut map = self.limits.write().await;
+        if limits.is_unlimited() {
+            map.remove(key);
+        } else {
+            map.insert(key.to_string(), limits);
+        }
+    }
+
+    pub async fn all(&self) -> HashMap<String, BudgetLimits> {
+        self.limits.read().await.clone()
+    }
+
+    /// Checks whether `key` may start another request.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BudgetExceeded` when today's or this month's spend has reached the limit.
+    pub async fn check(&self, key: &str, usage: &UsageTracker) -> Result<(), BudgetExceeded> {
+        let limits = self.get(key).await;
+        if limits.is_unlimited() {
+            return Ok(());
+        }
+        let (daily, monthly) = usage.current_spend(key).await;
+        for (period, limit, spent) in [
+            ("Daily", limits.daily_usd, daily),
+            ("Monthly", limits.monthly_usd, monthly),
+        ] {
+            if let Some(limit) = limit {
+                if spent >= limit {
+                    return Err(BudgetExceeded {
+                        key: key.to_string(),
+                        period,
+                        limit,
+                        spent,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::Usage;
+    use crate::services::model_registry::ModelRegistry;
+
+    #[tokio::test]
+    async fn test_check_rejects_after_daily_limit() {
+        let budgets = BudgetManager::default();
+        let usage = UsageTracker::new();
+        let registry = ModelRegistry::default();
+
+        budgets
+            .set(
+                "team-a",
+                BudgetLimits {
+                    daily_usd: Some(10.0),
+                    monthly_usd: None,
+                },
+            )
+            .await;
+        assert!(budgets.check("team-a", &usage).await.is_ok());
+
+        // 1M input tokens on claude-3-opus costs $15
+        usage
+            .record(
+                "team-a",
+                "claude-3-opus",
+                &Usage::new(1_000_000, 0, 1_000_000),
+                &registry,
+            )
+            .await;
+        let err = budgets
+            .check("team-a", &usage)
+            .await
+            .expect_err("budget should be exhausted");
+        assert_eq!(err.period, "Daily");
+        assert!(budgets.check("team-b", &usage).await.is_ok());
+
+        budgets.set("team-a", BudgetLimits::default()).await;
+        assert!(budgets.check("team-a", &usage).await.is_ok());
+        assert!(budgets.all().await.is_empty());
+    }
+}
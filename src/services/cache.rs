@@ -1,4 +1,6 @@
 use crate::models::openai::ChatCompletionRequest;
+use crate::services::clock::{self, SharedClock};
+use crate::services::storage::SharedStorage;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -8,6 +10,9 @@ use tracing::{debug, warn};
 
 const MAX_CACHE_SIZE: usize = 10_000;
 
+// Storage prefix of shared entries
+const STORAGE_PREFIX: &str = "cache:";
+
 #[derive(Clone, Serialize, Deserialize)]
 struct CachedResponse {
     response: String,
@@ -17,19 +22,28 @@ struct CachedResponse {
 }
 
 impl CachedResponse {
-    fn is_expired(&self) -> bool {
-        let now = Utc::now();
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
         let ttl_secs_i64 = i64::try_from(self.ttl_secs).unwrap_or(i64::MAX);
         let expires_at = self.cached_at + chrono::Duration::seconds(ttl_secs_i64);
         now > expires_at
     }
 }
 
+/// Response cache keyed on the request parameters that affect the output.
+///
+/// Entries live in process memory, or in shared storage when attached
+/// (cluster mode) so every replica serves the same hits.
+///
+/// By default the cache is private: entries are also keyed on the caller's
+/// API key, so one tenant is never served a response computed for another.
 #[derive(Clone)]
 pub struct Cache {
     store: Arc<RwLock<HashMap<String, CachedResponse>>>,
     default_ttl_secs: u64,
     enabled: bool,
+    vary_on_key: bool,
+    shared: Option<SharedStorage>,
+    clock: SharedClock,
 }
 
 impl Cache {
@@ -39,25 +53,54 @@ impl Cache {
             store: Arc::new(RwLock::new(HashMap::new())),
             default_ttl_secs,
             enabled,
+            vary_on_key: true,
+            shared: None,
+            clock: clock::system(),
         }
     }
 
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Whether entries are keyed on the caller's API key (private) or shared
+    /// by every key with the same request.
+    #[must_use]
+    pub fn with_vary_on_key(mut self, vary_on_key: bool) -> Self {
+        self.vary_on_key = vary_on_key;
+        self
+    }
+
+    /// Keeps entries in `store` instead of process memory.
+    #[must_use]
+    pub fn with_shared_store(mut self, store: SharedStorage) -> Self {
+        self.shared = Some(store);
+        self
+    }
+
     #[must_use]
     pub fn is_enabled(&self) -> bool {
         self.enabled
     }
 
-    fn cache_key(request: &ChatCompletionRequest) -> Result<String, serde_json::Error> {
+    pub fn cache_key(request: &ChatCompletionRequest) -> Result<String, serde_json::Error> {
         // Fix incomplete cache key: Include all parameters that affect response
         // Fix collision risk: Use structured format with delimiter that won't appear in model names
         // Use "|" as delimiter (unlikely in model names) and include all relevant params
 
         let messages_str = serde_json::to_string(&request.messages)?;
-        let temperature_str = format!("{:.6}", request.temperature); // Use fixed precision
+        let temperature_str = request
+            .temperature
+            .map_or_else(|| "none".to_string(), |v| format!("{v:.6}")); // Use fixed precision
         let max_tokens_str = request
             .max_tokens
             .map_or_else(|| "none".to_string(), |v| v.to_string());
-        let top_p_str = format!("{:.6}", request.top_p);
+        let top_p_str = request
+            .top_p
+            .map_or_else(|| "none".to_string(), |v| format!("{v:.6}"));
         let stop_str = request
             .stop
             .as_ref()
@@ -67,20 +110,67 @@ impl Cache {
 
         // Format: model|messages|temperature|max_tokens|top_p|stop
         // Using "|" delimiter which is unlikely to appear in model names or JSON
-        Ok(format!(
+        let mut key = format!(
             "{}|{}|{}|{}|{}|{}",
             request.model, messages_str, temperature_str, max_tokens_str, top_p_str, stop_str
-        ))
+        );
+        // Extensions change the answer; appended only when present so keys of
+        // requests without them are unchanged
+        if let Some(top_k) = request.top_k {
+            key.push_str(&format!("|top_k={top_k}"));
+        }
+        if request.uses_tools() {
+            key.push('|');
+            key.push_str(&serde_json::to_string(&request.tools)?);
+            key.push('|');
+            key.push_str(&serde_json::to_string(&request.tool_choice)?);
+        }
+        if !request.extra.is_empty() {
+            key.push('|');
+            key.push_str(&serde_json::to_string(&request.extra)?);
+        }
+        Ok(key)
+    }
+
+    /// [`Cache::cache_key`] scoped to `api_key` when the cache is private.
+    ///
+    /// The key name is length-prefixed so no name can run into the request
+    /// part of another key.
+    pub fn scoped_key(
+        &self,
+        api_key: &str,
+        request: &ChatCompletionRequest,
+    ) -> Result<String, serde_json::Error> {
+        let key = Self::cache_key(request)?;
+        if self.vary_on_key {
+            Ok(format!("{}:{api_key}|{key}", api_key.len()))
+        } else {
+            Ok(key)
+        }
     }
 
-    async fn cleanup_expired(&self) {
+    /// Removes all expired entries and returns how many were dropped.
+    ///
+    /// Called periodically by the maintenance task rather than on the request path.
+    pub async fn cleanup_expired(&self) -> usize {
+        if let Some(shared) = &self.shared {
+            return shared
+                .purge_expired(STORAGE_PREFIX)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to purge expired shared cache entries: {e:#}");
+                    0
+                });
+        }
         let mut store = self.store.write().await;
         let initial_size = store.len();
-        store.retain(|_, v| !v.is_expired());
+        let now = self.clock.wall();
+        store.retain(|_, v| !v.is_expired(now));
         let removed = initial_size - store.len();
         if removed > 0 {
             debug!("Cache cleanup: removed {} expired entries", removed);
         }
+        removed
     }
 
     async fn enforce_size_limit(&self) {
@@ -115,12 +205,12 @@ impl Cache {
         }
     }
 
-    pub async fn get(&self, request: &ChatCompletionRequest) -> Option<String> {
+    pub async fn get(&self, api_key: &str, request: &ChatCompletionRequest) -> Option<String> {
         if !self.enabled {
             return None;
         }
 
-        let key = match Self::cache_key(request) {
+        let key = match self.scoped_key(api_key, request) {
             Ok(k) => k,
             Err(e) => {
                 warn!("Failed to generate cache key: {}", e);
@@ -128,12 +218,23 @@ impl Cache {
             }
         };
 
+        if let Some(shared) = &self.shared {
+            return match shared.get(&format!("{STORAGE_PREFIX}{key}")).await {
+                Ok(hit) => hit,
+                Err(e) => {
+                    warn!("Shared cache lookup failed: {e:#}");
+                    None
+                }
+            };
+        }
+
         // Fix race condition: Use write lock to atomically check and remove expired entry
         // This prevents entry from being re-inserted between check and cleanup
         let mut store = self.store.write().await;
 
+        let now = self.clock.wall();
         if let Some(cached) = store.get_mut(&key) {
-            if cached.is_expired() {
+            if cached.is_expired(now) {
                 debug!("Cache miss (expired): {}", key);
                 // Remove expired entry atomically while holding write lock
                 store.remove(&key);
@@ -141,7 +242,7 @@ impl Cache {
                 return None;
             }
             // Fix LRU: Update last_access on cache hit
-            cached.last_access = Utc::now();
+            cached.last_access = now;
             debug!("Cache hit: {}", key);
             let response = cached.response.clone();
             drop(store);
@@ -155,6 +256,7 @@ impl Cache {
 
     pub async fn set(
         &self,
+        api_key: &str,
         request: &ChatCompletionRequest,
         response: String,
         ttl_secs: Option<u64>,
@@ -163,7 +265,7 @@ impl Cache {
             return;
         }
 
-        let key = match Self::cache_key(request) {
+        let key = match self.scoped_key(api_key, request) {
             Ok(k) => k,
             Err(e) => {
                 warn!("Failed to generate cache key: {}", e);
@@ -173,7 +275,15 @@ impl Cache {
 
         let ttl = ttl_secs.unwrap_or(self.default_ttl_secs);
 
-        let now = Utc::now();
+        if let Some(shared) = &self.shared {
+            let ttl = std::time::Duration::from_secs(ttl);
+            let key = format!("{STORAGE_PREFIX}{key}");
+            if let Err(e) = shared.set(&key, response, Some(ttl)).await {
+                warn!("Failed to store shared cache entry: {e:#}");
+            }
+            return;
+        }
+        let now = self.clock.wall();
         let cached = CachedResponse {
             response,
             cached_at: now,
@@ -190,18 +300,30 @@ impl Cache {
     }
 
     pub async fn clear(&self) {
+        if let Some(shared) = &self.shared {
+            match shared.scan(STORAGE_PREFIX).await {
+                Ok(entries) => {
+                    for (key, _) in entries {
+                        if let Err(e) = shared.delete(&key).await {
+                            warn!("Failed to clear shared cache entry: {e:#}");
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to clear shared cache: {e:#}"),
+            }
+        }
         let mut store = self.store.write().await;
         store.clear();
         debug!("Cache cleared");
     }
 
     // Fix: Add cache invalidation API for manual invalidation
-    pub async fn invalidate(&self, request: &ChatCompletionRequest) -> bool {
+    pub async fn invalidate(&self, api_key: &str, request: &ChatCompletionRequest) -> bool {
         if !self.enabled {
             return false;
         }
 
-        let key = match Self::cache_key(request) {
+        let key = match self.scoped_key(api_key, request) {
             Ok(k) => k,
             Err(e) => {
                 warn!("Failed to generate cache key for invalidation: {}", e);
@@ -209,6 +331,16 @@ impl Cache {
             }
         };
 
+        if let Some(shared) = &self.shared {
+            return shared
+                .delete(&format!("{STORAGE_PREFIX}{key}"))
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to invalidate shared cache entry: {e:#}");
+                    false
+                });
+        }
+
         let mut store = self.store.write().await;
         let removed = store.remove(&key).is_some();
         if removed {
@@ -222,9 +354,27 @@ impl Cache {
         // This ensures active_entries calculation is accurate
         self.cleanup_expired().await;
 
+        if let Some(shared) = &self.shared {
+            // Storage only returns live entries, and expired ones were just purged
+            let active_entries = shared.scan(STORAGE_PREFIX).await.map_or_else(
+                |e| {
+                    warn!("Failed to count shared cache entries: {e:#}");
+                    0
+                },
+                |entries| entries.len(),
+            );
+            return CacheStats {
+                total_entries: active_entries,
+                active_entries,
+                expired_entries: 0,
+                enabled: self.enabled,
+            };
+        }
+
         let store = self.store.read().await;
         let total_entries = store.len();
-        let expired_entries = store.values().filter(|v| v.is_expired()).count();
+        let now = self.clock.wall();
+        let expired_entries = store.values().filter(|v| v.is_expired(now)).count();
 
         CacheStats {
             total_entries,
@@ -248,6 +398,8 @@ pub struct CacheStats {
 mod tests {
     use super::*;
     use crate::models::openai::{ChatMessage, Role};
+    use crate::services::clock::ManualClock;
+    use crate::services::sqlite_store::SqliteStore;
 
     #[tokio::test]
     async fn test_cache_get_set() {
@@ -258,48 +410,179 @@ mod tests {
                 role: Role::User,
                 content: "test".to_string(),
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             }],
             stream: false,
-            temperature: 1.0,
+            temperature: Some(1.0),
             max_tokens: None,
-            top_p: 1.0,
+            top_p: Some(1.0),
+            top_k: None,
             stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
         };
 
-        assert!(cache.get(&request).await.is_none());
+        assert!(cache.get("key", &request).await.is_none());
 
-        cache.set(&request, "test response".to_string(), None).await;
+        cache
+            .set("key", &request, "test response".to_string(), None)
+            .await;
+
+        assert_eq!(
+            cache.get("key", &request).await,
+            Some("test response".to_string())
+        );
+
+        // Forwarded fields such as `seed` change the answer
+        let mut seeded = request.clone();
+        seeded.extra.insert("seed".to_string(), 7.into());
+        assert!(cache.get("key", &seeded).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_private_cache_varies_on_key() {
+        let request = ChatCompletionRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage {
+                role: Role::User,
+                content: "test".to_string(),
+                name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+            stream: false,
+            temperature: Some(1.0),
+            max_tokens: None,
+            top_p: Some(1.0),
+            top_k: None,
+            stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
+        };
+
+        let private = Cache::new(true, 60);
+        private
+            .set("team-a", &request, "for a".to_string(), None)
+            .await;
+        assert_eq!(
+            private.get("team-a", &request).await,
+            Some("for a".to_string())
+        );
+        assert!(private.get("team-b", &request).await.is_none());
+
+        let shared = Cache::new(true, 60).with_vary_on_key(false);
+        shared
+            .set("team-a", &request, "for a".to_string(), None)
+            .await;
+        assert_eq!(
+            shared.get("team-b", &request).await,
+            Some("for a".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_cache_visible_to_other_replicas() {
+        let store: SharedStorage =
+            Arc::new(SqliteStore::open_in_memory().expect("store should open"));
+        let replica_a = Cache::new(true, 60).with_shared_store(Arc::clone(&store));
+        let replica_b = Cache::new(true, 60).with_shared_store(store);
+        let request = ChatCompletionRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage {
+                role: Role::User,
+                content: "test".to_string(),
+                name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+            stream: false,
+            temperature: Some(1.0),
+            max_tokens: None,
+            top_p: Some(1.0),
+            top_k: None,
+            stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
+        };
 
-        assert_eq!(cache.get(&request).await, Some("test response".to_string()));
+        replica_a
+            .set("key", &request, "test response".to_string(), None)
+            .await;
+        assert_eq!(
+            replica_b.get("key", &request).await,
+            Some("test response".to_string())
+        );
+        assert_eq!(replica_b.stats().await.active_entries, 1);
+
+        assert!(replica_b.invalidate("key", &request).await);
+        assert!(replica_a.get("key", &request).await.is_none());
     }
 
     #[tokio::test]
     async fn test_cache_expiration() {
-        let cache = Cache::new(true, 1);
+        let clock = Arc::new(ManualClock::new());
+        let cache = Cache::new(true, 1).with_clock(clock.clone());
         let request = ChatCompletionRequest {
             model: "test-model".to_string(),
             messages: vec![ChatMessage {
                 role: Role::User,
                 content: "test".to_string(),
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             }],
             stream: false,
-            temperature: 1.0,
+            temperature: Some(1.0),
             max_tokens: None,
-            top_p: 1.0,
+            top_p: Some(1.0),
+            top_k: None,
             stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
         };
 
-        cache.set(&request, "test response".to_string(), None).await;
-        assert!(cache.get(&request).await.is_some());
+        cache
+            .set("key", &request, "test response".to_string(), None)
+            .await;
+        assert!(cache.get("key", &request).await.is_some());
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-        assert!(cache.get(&request).await.is_none());
+        clock.advance(std::time::Duration::from_secs(2));
+        assert!(cache.get("key", &request).await.is_none());
     }
 
     #[tokio::test]
     async fn test_cache_cleanup() {
-        let cache = Cache::new(true, 1);
+        let clock = Arc::new(ManualClock::new());
+        let cache = Cache::new(true, 1).with_clock(clock.clone());
         let mut requests = Vec::new();
         for i in 0..5 {
             requests.push(ChatCompletionRequest {
@@ -308,20 +591,32 @@ mod tests {
                     role: Role::User,
                     content: format!("test{i}"),
                     name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
                 }],
                 stream: false,
-                temperature: 1.0,
+                temperature: Some(1.0),
                 max_tokens: None,
-                top_p: 1.0,
+                top_p: Some(1.0),
+                top_k: None,
                 stop: None,
+                user: None,
+                prompt_template: None,
+                variables: Default::default(),
+                tools: Vec::new(),
+                tool_choice: None,
+                stream_options: None,
+                prompts: Vec::new(),
+                extra: Default::default(),
             });
         }
 
         for req in &requests {
-            cache.set(req, "response".to_string(), None).await;
+            cache.set("key", req, "response".to_string(), None).await;
         }
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        clock.advance(std::time::Duration::from_secs(2));
 
         // stats() now cleans up expired entries first, so expired entries should be 0
         let stats = cache.stats().await;
@@ -0,0 +1,140 @@
+// Capability checks run before a chat completion is sent upstream.
+//
+// Sending images to a text-only model or tools to a provider that drops them
+// either fails with an upstream error that does not say why or, worse,
+// succeeds with the images or tools silently ignored. The model registry
+// records what each model accepts (`input_modalities`, `supports_tools`,
+// `supports_streaming`) and providers report whether they can carry tools, so
+// such requests are rejected with a 400 naming what is missing. Models the
+// registry does not know are not checked.
+
+use crate::models::openai::ChatCompletionRequest;
+use crate::services::model_registry::{Modality, ModelInfo};
+
+/// A capability the request needs that its target lacks.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MissingCapability {
+    #[error(
+        "Model '{model}' does not accept image input; the model registry lists its input modalities as [{modalities}]"
+    )]
+    Images { model: String, modalities: String },
+    #[error("Model '{model}' does not support tools according to the model registry")]
+    Tools { model: String },
+    #[error("Model '{model}' does not support streaming according to the model registry")]
+    Streaming { model: String },
+    #[error("The {provider} provider serving '{model}' does not support tools")]
+    ProviderTools { provider: String, model: String },
+}
+
+impl MissingCapability {
+    /// The request field the error is reported against.
+    #[must_use]
+    pub fn param(&self) -> &'static str {
+        match self {
+            Self::Images { .. } => "messages",
+            Self::Tools { .. } | Self::ProviderTools { .. } => "tools",
+            Self::Streaming { .. } => "stream",
+        }
+    }
+}
+
+/// Checks `req` against the registry's metadata for its model.
+///
+/// # Errors
+///
+/// Returns the first capability `req` uses that `info` does not list.
+pub fn check_model(
+    req: &ChatCompletionRequest,
+    info: Option<&ModelInfo>,
+) -> Result<(), MissingCapability> {
+    let Some(info) = info else {
+        return Ok(());
+    };
+    let has_images = req.messages.iter().any(|m| m.images > 0);
+    if has_images && !info.input_modalities.contains(&Modality::Image) {
+        let modalities = info
+            .input_modalities
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(MissingCapability::Images {
+            model: req.model.clone(),
+            modalities,
+        });
+    }
+    if req.uses_tools() && !info.supports_tools {
+        return Err(MissingCapability::Tools {
+            model: req.model.clone(),
+        });
+    }
+    if req.stream && !info.supports_streaming {
+        return Err(MissingCapability::Streaming {
+            model: req.model.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the provider chosen for `req` can carry its tools.
+///
+/// # Errors
+///
+/// Returns `ProviderTools` if `req` uses tools and the provider cannot.
+pub fn check_provider(
+    req: &ChatCompletionRequest,
+    provider: &str,
+    supports_tools: bool,
+) -> Result<(), MissingCapability> {
+    if req.uses_tools() && !supports_tools {
+        return Err(MissingCapability::ProviderTools {
+            provider: provider.to_string(),
+            model: req.model.clone(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::model_registry::ModelRegistry;
+
+    fn request(json: serde_json::Value) -> ChatCompletionRequest {
+        serde_json::from_value(json).expect("valid request")
+    }
+
+    #[test]
+    fn test_capabilities_checked_against_registry() {
+        let registry = ModelRegistry::default();
+        let image = serde_json::json!([
+            {"type": "text", "text": "What is this?"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+        ]);
+
+        let req = request(serde_json::json!({
+            "model": "gpt-4",
+            "messages": [{"role": "user", "content": image}]
+        }));
+        let err = check_model(&req, registry.get(&req.model)).unwrap_err();
+        assert_eq!(err.param(), "messages");
+        assert!(err.to_string().contains("[text]"), "{err}");
+
+        let req = request(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": image}]
+        }));
+        assert!(check_model(&req, registry.get(&req.model)).is_ok());
+        assert!(check_model(&req, None).is_ok());
+
+        let tools = request(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "tools": [{"type": "function", "function": {"name": "f", "parameters": {}}}]
+        }));
+        assert!(check_provider(&tools, "vertex", true).is_ok());
+        let err = check_provider(&tools, "gemini_cli", false).unwrap_err();
+        assert_eq!(err.param(), "tools");
+        assert!(err.to_string().contains("gemini_cli"));
+    }
+}
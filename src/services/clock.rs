@@ -0,0 +1,114 @@
+// Time source for expiry and timeout logic.
+//
+// The cache, rate limiter, circuit breaker and token manager read the time
+// through a `Clock` instead of calling `Instant::now()` or `Utc::now()`
+// themselves, so tests can step time forward with a `ManualClock` instead of
+// sleeping. `now` is monotonic and measures durations; `wall` is for
+// timestamps that leave the process (response headers, the shared SQLite
+// store) and may jump when the system clock is adjusted.
+
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where a component gets the current time from.
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for measuring how long something has been around.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for timestamps shared outside the process.
+    fn wall(&self) -> DateTime<Utc>;
+}
+
+/// A clock shared by the components it drives.
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real system clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// The system clock, as used outside of tests.
+#[must_use]
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// A clock that only moves when told to. Both readings start at the real
+/// time of creation and advance together.
+pub struct ManualClock {
+    start: Instant,
+    start_wall: DateTime<Utc>,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            start_wall: Utc::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self
+            .elapsed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let mut elapsed = self
+            .elapsed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        *elapsed += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn wall(&self) -> DateTime<Utc> {
+        self.start_wall
+            + chrono::Duration::from_std(self.elapsed()).unwrap_or(chrono::Duration::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::new();
+        let (now, wall) = (clock.now(), clock.wall());
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), now);
+        assert_eq!(clock.wall(), wall);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now() - now, Duration::from_secs(90));
+        assert_eq!((clock.wall() - wall).num_seconds(), 90);
+    }
+}
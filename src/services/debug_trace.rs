@@ -0,0 +1,248 @@
+// Per-request debug breakdown.
+//
+// When a user reports a slow or wrong answer, the routing decision, the
+// attempts made and where the time went are spread over log lines that the
+// user cannot see. An admin caller sending `X-Debug: true` gets them in the
+// response instead: non-streaming responses carry a top-level `debug`
+// object, streaming responses an `X-Debug` header with the same JSON, as the
+// body is already on its way by the time the stream ends.
+//
+// Like `log_context`, the trace lives in a task-local, so the stages of the
+// chat pipeline record into it without threading it through every call.
+// Outside a traced request the `record_*` functions do nothing.
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::Response;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Request header asking for the breakdown, and the response header carrying
+/// it on streams.
+pub const DEBUG_HEADER: &str = "x-debug";
+
+tokio::task_local! {
+    static TRACE: Arc<Mutex<DebugTrace>>;
+}
+
+/// How the routed provider was chosen.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteInfo {
+    pub provider: String,
+    pub reason: &'static str,
+}
+
+/// One dispatch of the request to a provider.
+#[derive(Debug, Clone, Serialize)]
+pub struct Attempt {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    pub status: u16,
+    pub duration_ms: u64,
+}
+
+/// Whether an identical in-flight request answered this one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheResult {
+    /// Not eligible: streaming, or coalescing is disabled.
+    Bypass,
+    /// Ran against the provider.
+    Miss,
+    /// Answered by an identical request already in flight.
+    Coalesced,
+}
+
+/// What a traced request went through.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DebugTrace {
+    pub requested_model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<RouteInfo>,
+    pub attempts: Vec<Attempt>,
+    /// Attempts after the first, such as context fallbacks.
+    pub retries: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheResult>,
+    /// Milliseconds spent per pipeline stage, summed over attempts.
+    pub timings_ms: BTreeMap<&'static str, u64>,
+}
+
+/// Whether `headers` ask for a debug breakdown.
+#[must_use]
+pub fn requested(headers: &HeaderMap) -> bool {
+    headers
+        .get(DEBUG_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("true") || v == "1")
+}
+
+fn with_trace(update: impl FnOnce(&mut DebugTrace)) {
+    let _ = TRACE.try_with(|trace| {
+        update(
+            &mut trace
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        );
+    });
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Records the provider the request was routed to.
+pub fn record_route(provider: &str, reason: &'static str) {
+    with_trace(|trace| {
+        trace.route = Some(RouteInfo {
+            provider: provider.to_string(),
+            reason,
+        });
+    });
+}
+
+/// Records a dispatch that started at `started` and ended with `status`.
+pub fn record_attempt(model: &str, provider: Option<&str>, status: u16, started: Instant) {
+    with_trace(|trace| {
+        trace.attempts.push(Attempt {
+            model: model.to_string(),
+            provider: provider.map(str::to_string),
+            status,
+            duration_ms: millis(started.elapsed()),
+        });
+        trace.retries = trace.attempts.len().saturating_sub(1);
+    });
+}
+
+/// Records how the request was served relative to identical ones.
+pub fn record_cache(result: CacheResult) {
+    with_trace(|trace| trace.cache = Some(result));
+}
+
+/// Adds the time since `started` to `stage`.
+pub fn record_stage(stage: &'static str, started: Instant) {
+    with_trace(|trace| {
+        *trace.timings_ms.entry(stage).or_default() += millis(started.elapsed());
+    });
+}
+
+/// Runs `completion` with a trace, then attaches the trace to its response.
+pub async fn capture<F>(requested_model: String, completion: F) -> Response
+where
+    F: Future<Output = Response>,
+{
+    let trace = Arc::new(Mutex::new(DebugTrace {
+        requested_model,
+        ..DebugTrace::default()
+    }));
+    let started = Instant::now();
+    let response = TRACE.scope(trace.clone(), completion).await;
+    let mut trace = trace
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clone();
+    trace.timings_ms.insert("total", millis(started.elapsed()));
+    attach(response, &trace).await
+}
+
+/// `response` with `trace` as its `debug` field, or as the `X-Debug` header
+/// if the body is not a JSON object.
+async fn attach(response: Response, trace: &DebugTrace) -> Response {
+    let Ok(debug) = serde_json::to_value(trace) else {
+        return response;
+    };
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json {
+        let mut response = response;
+        if let Ok(value) = HeaderValue::from_str(&debug.to_string()) {
+            response.headers_mut().insert(DEBUG_HEADER, value);
+        }
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read response body for debug output: {e}");
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let body = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut object)) => {
+            object.insert("debug".to_string(), debug);
+            Value::Object(object).to_string().into()
+        }
+        _ => bytes,
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use axum::Json;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        serde_json::from_slice(&bytes).expect("body should be JSON")
+    }
+
+    #[tokio::test]
+    async fn test_trace_is_attached_to_json_responses() {
+        let response = capture("fast".to_string(), async {
+            record_route("vertex", "sticky");
+            let started = Instant::now();
+            record_stage("upstream", started);
+            record_attempt("gemini-2.5-pro", Some("vertex"), 400, started);
+            record_attempt("gemini-2.5-flash", Some("vertex"), 200, started);
+            record_cache(CacheResult::Miss);
+            Json(serde_json::json!({"id": "chatcmpl-1"})).into_response()
+        })
+        .await;
+        record_cache(CacheResult::Coalesced);
+
+        let json = body_json(response).await;
+        assert_eq!(json["id"], "chatcmpl-1");
+        let debug = &json["debug"];
+        assert_eq!(debug["requested_model"], "fast");
+        assert_eq!(debug["route"]["reason"], "sticky");
+        assert_eq!(debug["attempts"][1]["model"], "gemini-2.5-flash");
+        assert_eq!(debug["retries"], 1);
+        assert_eq!(debug["cache"], "miss");
+        assert!(debug["timings_ms"]["upstream"].is_u64());
+        assert!(debug["timings_ms"]["total"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_trace_goes_to_header_on_streams() {
+        let response = capture("gemini-2.5-flash".to_string(), async {
+            (
+                [(header::CONTENT_TYPE, "text/event-stream")],
+                "data: [DONE]\n\n",
+            )
+                .into_response()
+        })
+        .await;
+        let header = response
+            .headers()
+            .get(DEBUG_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .expect("stream should carry the debug header");
+        let debug: Value = serde_json::from_str(header).expect("header should be JSON");
+        assert_eq!(debug["requested_model"], "gemini-2.5-flash");
+    }
+}
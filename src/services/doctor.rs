@@ -0,0 +1,497 @@
+// Startup self-test.
+//
+// `vertex-bridge doctor` runs the checks a failed start usually comes down to
+// — configuration, Google credentials, the gcloud and Gemini CLI binaries,
+// upstream reachability, the listening port and the system clock — and
+// prints one line per check with a hint for anything that needs fixing. It
+// serves nothing and exits non-zero if any check failed, so it also works as
+// a pre-flight step in deploy scripts.
+
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::openai::harvester::HarvesterClient;
+use crate::server::initialize_state;
+use crate::services::auth::TokenManager;
+use crate::services::listener;
+use crate::services::providers::vertex;
+
+const NETWORK_TIMEOUT_SECS: u64 = 5;
+// gcloud can take a while on first use while it refreshes its own credentials
+const TOKEN_TIMEOUT_SECS: u64 = 30;
+const BRIDGE_HEALTH_PATH: &str = "/health";
+// Above this, some upstreams start rejecting signed requests and tokens
+const CLOCK_SKEW_FAIL_SECS: u64 = 300;
+const CLOCK_SKEW_WARN_SECS: u64 = 30;
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+    Skip,
+}
+
+impl CheckStatus {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Ok => " OK ",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+            Self::Skip => "SKIP",
+        }
+    }
+}
+
+/// One line of the report.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about a warning or failure.
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn skip(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Skip,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: CheckStatus,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Results of all checks, in the order they ran.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Whether no check failed. Warnings do not count.
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.count(CheckStatus::Fail) == 0
+    }
+
+    fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(
+                f,
+                "[{}] {}: {}",
+                check.status.label(),
+                check.name,
+                check.detail
+            )?;
+            if let Some(hint) = &check.hint {
+                writeln!(f, "       hint: {hint}")?;
+            }
+        }
+        write!(
+            f,
+            "\n{} passed, {} warnings, {} failed, {} skipped",
+            self.count(CheckStatus::Ok),
+            self.count(CheckStatus::Warn),
+            self.count(CheckStatus::Fail),
+            self.count(CheckStatus::Skip)
+        )
+    }
+}
+
+/// Runs every check against `config`, or against the error loading it
+/// produced.
+pub async fn run(config: Result<AppConfig, String>) -> Report {
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            let mut report = Report::default();
+            report.checks.push(config_error(&e));
+            for name in [
+                "startup",
+                "credentials",
+                "gcloud",
+                "gemini_cli",
+                "vertex",
+                "anthropic_bridge",
+                "harvester",
+                "port",
+                "clock",
+            ] {
+                report
+                    .checks
+                    .push(Check::skip(name, "configuration did not load"));
+            }
+            return report;
+        }
+    };
+
+    let mut checks = vec![check_config(&config), check_startup(&config).await];
+    checks.extend(check_credentials(&config).await);
+    checks.push(check_gemini_cli(&config));
+    let (vertex, upstream_date) = check_vertex(&config).await;
+    checks.push(vertex);
+    checks.push(check_anthropic_bridge(&config).await);
+    checks.push(check_harvester(&config).await);
+    checks.push(check_port(&config).await);
+    checks.push(match upstream_date {
+        Some(upstream) => check_clock(Utc::now(), upstream),
+        None => Check::skip("clock", "no Date header from Vertex to compare against"),
+    });
+    Report { checks }
+}
+
+fn config_error(error: &str) -> Check {
+    let hint = if error.contains("GOOGLE_API_KEY") || error.contains("project ID") {
+        "Set GOOGLE_API_KEY for AI Studio, or GOOGLE_APPLICATION_CREDENTIALS (or \
+         APP_VERTEX__CREDENTIALS_FILE) and APP_VERTEX__PROJECT_ID for Vertex"
+    } else {
+        "Fix the setting named in the error; .env.example lists every variable"
+    };
+    Check::problem("config", CheckStatus::Fail, error, hint)
+}
+
+fn check_config(config: &AppConfig) -> Check {
+    let warnings = config.cluster_warnings();
+    if warnings.is_empty() {
+        Check::ok("config", "configuration is valid")
+    } else {
+        Check::problem(
+            "config",
+            CheckStatus::Warn,
+            warnings.join("; "),
+            "Valid, but review these settings before running more than one replica",
+        )
+    }
+}
+
+async fn check_startup(config: &AppConfig) -> Check {
+    match initialize_state(config).await {
+        Ok(_) => Check::ok("startup", "keys, models, templates and storage load"),
+        Err(e) => Check::problem(
+            "startup",
+            CheckStatus::Fail,
+            format!("{e:#}"),
+            "Fix or remove the file named in the error",
+        ),
+    }
+}
+
+/// The credential check, and the gcloud check it depends on.
+async fn check_credentials(config: &AppConfig) -> Vec<Check> {
+    let manager = match TokenManager::new(
+        config.vertex.api_key.clone(),
+        config.vertex.credentials_file.clone(),
+        config.vertex.project_id.clone(),
+    ) {
+        Ok(manager) => manager,
+        Err(e) => {
+            return vec![
+                Check::problem(
+                    "credentials",
+                    CheckStatus::Fail,
+                    format!("{e:#}"),
+                    "Point APP_VERTEX__CREDENTIALS_FILE at a readable service account key",
+                ),
+                Check::skip("gcloud", "credentials did not resolve"),
+            ];
+        }
+    };
+    if manager.is_api_key() {
+        return vec![
+            Check::ok("credentials", "using GOOGLE_API_KEY (AI Studio)"),
+            Check::skip("gcloud", "not needed with an API key"),
+        ];
+    }
+
+    let Some(gcloud) = find_executable("gcloud") else {
+        return vec![
+            Check::skip("credentials", "gcloud is not available to fetch a token"),
+            Check::problem(
+                "gcloud",
+                CheckStatus::Fail,
+                "gcloud not found on PATH",
+                "Install the Google Cloud SDK, or set GOOGLE_API_KEY to use AI Studio instead",
+            ),
+        ];
+    };
+    let gcloud = Check::ok("gcloud", gcloud.display().to_string());
+
+    let project = manager.get_project_id().map(str::to_string);
+    let token =
+        tokio::time::timeout(Duration::from_secs(TOKEN_TIMEOUT_SECS), manager.get_token()).await;
+    let credentials = match (token, project) {
+        (Ok(Ok(_)), Some(project)) => Check::ok(
+            "credentials",
+            format!("obtained an access token for project {project}"),
+        ),
+        (Ok(Ok(_)), None) => Check::problem(
+            "credentials",
+            CheckStatus::Warn,
+            "obtained an access token, but no project ID is set",
+            "Set APP_VERTEX__PROJECT_ID or GOOGLE_CLOUD_PROJECT",
+        ),
+        (Ok(Err(e)), _) => Check::problem(
+            "credentials",
+            CheckStatus::Fail,
+            format!("{e:#}"),
+            "Run `gcloud auth application-default login`, or check the service account key",
+        ),
+        (Err(_), _) => Check::problem(
+            "credentials",
+            CheckStatus::Fail,
+            format!("gcloud did not return a token within {TOKEN_TIMEOUT_SECS}s"),
+            "Run `gcloud auth print-access-token` by hand to see what it is waiting for",
+        ),
+    };
+    vec![credentials, gcloud]
+}
+
+fn check_gemini_cli(config: &AppConfig) -> Check {
+    if !config.gemini_cli.enabled {
+        return Check::skip("gemini_cli", "disabled");
+    }
+    let cli_path = config.gemini_cli.cli_path.as_deref().unwrap_or("gemini");
+    match find_executable(cli_path) {
+        Some(path) => Check::ok("gemini_cli", path.display().to_string()),
+        None => Check::problem(
+            "gemini_cli",
+            CheckStatus::Fail,
+            format!("'{cli_path}' not found"),
+            "Install the Gemini CLI, set APP_GEMINI_CLI__CLI_PATH to its location, or \
+             disable it with APP_GEMINI_CLI__ENABLED=false",
+        ),
+    }
+}
+
+/// Reachability of the Vertex endpoint, and the `Date` it reported.
+async fn check_vertex(config: &AppConfig) -> (Check, Option<DateTime<Utc>>) {
+    let base = vertex::endpoint_base(&config.vertex, config.vertex.api_key.is_some());
+    let response = match http_client() {
+        Ok(client) => client.get(&base).send().await,
+        Err(e) => {
+            return (
+                Check::problem(
+                    "vertex",
+                    CheckStatus::Fail,
+                    e,
+                    "Check the TLS setup of this host",
+                ),
+                None,
+            );
+        }
+    };
+    match response {
+        // Any HTTP answer means the endpoint is reachable; the root path
+        // itself is not an API route
+        Ok(response) => {
+            let date = response
+                .headers()
+                .get(reqwest::header::DATE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+                .map(|date| date.with_timezone(&Utc));
+            (Check::ok("vertex", format!("{base} reachable")), date)
+        }
+        Err(e) => (
+            Check::problem(
+                "vertex",
+                CheckStatus::Fail,
+                format!("{base} unreachable: {e}"),
+                "Check DNS, firewall rules and HTTPS_PROXY for this host",
+            ),
+            None,
+        ),
+    }
+}
+
+async fn check_anthropic_bridge(config: &AppConfig) -> Check {
+    let url = format!(
+        "{}{BRIDGE_HEALTH_PATH}",
+        config.anthropic.bridge_url.trim_end_matches('/')
+    );
+    let result = match http_client() {
+        Ok(client) => client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r.error_for_status().map(drop).map_err(|e| e.to_string())),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Check::ok("anthropic_bridge", format!("{url} healthy")),
+        Err(e) => Check::problem(
+            "anthropic_bridge",
+            CheckStatus::Warn,
+            format!("{url}: {e}"),
+            "claude-* models are unavailable until the bridge is running; start it or set \
+             APP_ANTHROPIC__BRIDGE_URL",
+        ),
+    }
+}
+
+async fn check_harvester(config: &AppConfig) -> Check {
+    let url = &config.openai.harvester_url;
+    let result = match HarvesterClient::new(&Arc::new(config.clone())) {
+        Ok(harvester) => tokio::time::timeout(
+            Duration::from_secs(NETWORK_TIMEOUT_SECS),
+            harvester.health_check(),
+        )
+        .await
+        .map_err(|_| format!("no answer within {NETWORK_TIMEOUT_SECS}s"))
+        .and_then(|health| health.map_err(|e| e.to_string())),
+        Err(e) => Err(e.to_string()),
+    };
+    match result {
+        Ok(health) if health.session_valid => Check::ok("harvester", format!("{url} healthy")),
+        Ok(_) => Check::problem(
+            "harvester",
+            CheckStatus::Warn,
+            format!("{url} is up but has no valid ChatGPT session"),
+            "Log in through the harvester's browser session",
+        ),
+        Err(e) => Check::problem(
+            "harvester",
+            CheckStatus::Warn,
+            format!("{url}: {e}"),
+            "gpt-* models are unavailable until the harvester is running; start it or set \
+             APP_OPENAI__HARVESTER_URL",
+        ),
+    }
+}
+
+async fn check_port(config: &AppConfig) -> Check {
+    match listener::bind(&config.server).await {
+        Ok(listener) => {
+            let addr = listener
+                .local_addr()
+                .map_or_else(|_| "listener".to_string(), |a| a.to_string());
+            Check::ok("port", format!("{addr} can be bound"))
+        }
+        Err(e) => Check::problem(
+            "port",
+            CheckStatus::Fail,
+            format!("{e:#}"),
+            "Stop whatever holds the port (another proxy instance?) or set APP_SERVER__PORT",
+        ),
+    }
+}
+
+/// Compares the local clock at `local` with the time an upstream reported.
+fn check_clock(local: DateTime<Utc>, upstream: DateTime<Utc>) -> Check {
+    let skew = (local - upstream).num_seconds().unsigned_abs();
+    let detail = format!("{skew}s from the Vertex clock");
+    let hint = "Enable time synchronisation (NTP) on this host";
+    if skew > CLOCK_SKEW_FAIL_SECS {
+        Check::problem("clock", CheckStatus::Fail, detail, hint)
+    } else if skew > CLOCK_SKEW_WARN_SECS {
+        Check::problem("clock", CheckStatus::Warn, detail, hint)
+    } else {
+        Check::ok("clock", detail)
+    }
+}
+
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(NETWORK_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))
+}
+
+/// `program` itself if it is a path to a file, otherwise the first match for
+/// it on `PATH`.
+fn find_executable(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew_thresholds() {
+        let now = Utc::now();
+        let status = |offset| check_clock(now, now + chrono::Duration::seconds(offset)).status;
+        assert_eq!(status(5), CheckStatus::Ok);
+        assert_eq!(status(-45), CheckStatus::Warn);
+        assert_eq!(status(600), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_find_executable() {
+        let tool = std::env::temp_dir().join(format!("doctor-tool-{}", std::process::id()));
+        std::fs::write(&tool, "").expect("file should be written");
+
+        let tool_path = tool.to_str().expect("path should be UTF-8");
+        assert_eq!(find_executable(tool_path), Some(tool.clone()));
+        assert_eq!(find_executable(&format!("{tool_path}-missing")), None);
+        assert_eq!(find_executable("no-such-program-anywhere"), None);
+        let _ = std::fs::remove_file(&tool);
+    }
+
+    #[tokio::test]
+    async fn test_config_error_skips_remaining_checks() {
+        let report = run(Err(
+            "Missing configuration: Must provide either GOOGLE_API_KEY or ...".to_string(),
+        ))
+        .await;
+
+        assert!(!report.passed());
+        assert_eq!(report.checks[0].status, CheckStatus::Fail);
+        assert!(report.checks[0]
+            .hint
+            .as_deref()
+            .is_some_and(|hint| hint.contains("GOOGLE_API_KEY")));
+        assert!(report.checks[1..]
+            .iter()
+            .all(|c| c.status == CheckStatus::Skip));
+
+        let text = report.to_string();
+        assert!(text.starts_with("[FAIL] config: Missing configuration"));
+        assert!(text.contains("       hint: Set GOOGLE_API_KEY"));
+        assert!(text.ends_with("0 passed, 0 warnings, 1 failed, 9 skipped"));
+    }
+}
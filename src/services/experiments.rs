@@ -0,0 +1,189 @@
+// A/B experiments over models and prompts.
+//
+// An experiment splits the traffic for one requested model across weighted
+// variants, each of which may swap in a different model or prompt template.
+// Assignment hashes the experiment name with the end user (or API key) so a
+// caller stays in the same variant across requests and restarts.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use tracing::info;
+
+/// One arm of an experiment. Unset fields leave the request unchanged.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    pub weight: u32,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+}
+
+/// Traffic split for requests naming `model`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Experiment {
+    pub name: String,
+    pub model: String,
+    pub variants: Vec<Variant>,
+}
+
+impl Experiment {
+    fn total_weight(&self) -> u64 {
+        self.variants.iter().map(|v| u64::from(v.weight)).sum()
+    }
+
+    /// The variant `subject` falls into; stable for a given experiment name.
+    #[must_use]
+    pub fn assign(&self, subject: &str) -> &Variant {
+        let digest = Sha256::digest(format!("{}:{subject}", self.name).as_bytes());
+        let mut bucket_bytes = [0u8; 8];
+        bucket_bytes.copy_from_slice(&digest[..8]);
+        let mut bucket = u64::from_be_bytes(bucket_bytes) % self.total_weight();
+        for variant in &self.variants {
+            let weight = u64::from(variant.weight);
+            if bucket < weight {
+                return variant;
+            }
+            bucket -= weight;
+        }
+        // Unreachable while the weights sum to more than the bucket
+        &self.variants[self.variants.len() - 1]
+    }
+}
+
+/// Experiments defined in configuration.
+#[derive(Debug, Default)]
+pub struct Experiments {
+    experiments: Vec<Experiment>,
+}
+
+impl Experiments {
+    /// Loads experiments from an optional JSON array file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or an
+    /// experiment is invalid (see [`Experiments::new`]).
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read experiments file '{path}'"))?;
+        let experiments: Vec<Experiment> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse experiments file '{path}'"))?;
+        info!("Loaded {} experiments from {}", experiments.len(), path);
+        Self::new(experiments)
+    }
+
+    /// # Errors
+    ///
+    /// Rejects duplicate experiment names, two experiments on the same model,
+    /// and experiments without variants, with duplicate variant names or with
+    /// no positive weight.
+    pub fn new(experiments: Vec<Experiment>) -> Result<Self> {
+        let mut names = HashSet::new();
+        let mut models = HashSet::new();
+        for experiment in &experiments {
+            if !names.insert(experiment.name.as_str()) {
+                bail!("Duplicate experiment '{}'", experiment.name);
+            }
+            if !models.insert(experiment.model.as_str()) {
+                bail!(
+                    "Experiment '{}' targets model '{}', which another experiment already uses",
+                    experiment.name,
+                    experiment.model
+                );
+            }
+            let mut variants = HashSet::new();
+            for variant in &experiment.variants {
+                if !variants.insert(variant.name.as_str()) {
+                    bail!(
+                        "Experiment '{}' has duplicate variant '{}'",
+                        experiment.name,
+                        variant.name
+                    );
+                }
+            }
+            if experiment.total_weight() == 0 {
+                bail!(
+                    "Experiment '{}' needs at least one variant with a positive weight",
+                    experiment.name
+                );
+            }
+        }
+        Ok(Self { experiments })
+    }
+
+    /// The experiment running on requests for `model`, if any.
+    #[must_use]
+    pub fn for_model(&self, model: &str) -> Option<&Experiment> {
+        self.experiments.iter().find(|e| e.model == model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(name: &str, weight: u32, model: Option<&str>) -> Variant {
+        Variant {
+            name: name.to_string(),
+            weight,
+            model: model.map(str::to_string),
+            prompt_template: None,
+        }
+    }
+
+    fn experiment(variants: Vec<Variant>) -> Experiment {
+        Experiment {
+            name: "flash-vs-haiku".to_string(),
+            model: "fast".to_string(),
+            variants,
+        }
+    }
+
+    #[test]
+    fn test_assignment_is_deterministic_and_weighted() {
+        let exp = experiment(vec![
+            variant("control", 1, None),
+            variant("haiku", 3, Some("claude-3-5-haiku")),
+        ]);
+        for user in ["alice", "bob", "carol"] {
+            assert_eq!(exp.assign(user).name, exp.assign(user).name);
+        }
+
+        let haiku = (0..4000)
+            .filter(|i| exp.assign(&format!("user-{i}")).name == "haiku")
+            .count();
+        assert!((2700..3300).contains(&haiku), "haiku share was {haiku}");
+
+        let only = experiment(vec![variant("off", 0, None), variant("on", 5, None)]);
+        assert_eq!(only.assign("anyone").name, "on");
+    }
+
+    #[test]
+    fn test_invalid_experiments_rejected() {
+        assert!(Experiments::new(vec![experiment(vec![])]).is_err());
+        assert!(Experiments::new(vec![experiment(vec![variant("a", 0, None)])]).is_err());
+        assert!(Experiments::new(vec![experiment(vec![
+            variant("a", 1, None),
+            variant("a", 1, None)
+        ])])
+        .is_err());
+        assert!(Experiments::new(vec![
+            experiment(vec![variant("a", 1, None)]),
+            experiment(vec![variant("b", 1, None)]),
+        ])
+        .is_err());
+
+        let experiments =
+            Experiments::new(vec![experiment(vec![variant("a", 1, None)])]).expect("valid");
+        assert!(experiments.for_model("fast").is_some());
+        assert!(experiments.for_model("slow").is_none());
+    }
+}
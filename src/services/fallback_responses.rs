@@ -0,0 +1,169 @@
+// Canned replies for when no provider can serve a request.
+//
+// Chat UIs render an assistant message far more gracefully than an HTTP
+// error, so operators can configure a "service degraded" reply per model. It
+// is returned in place of upstream outages (network errors, timeouts, 5xx, an
+// open circuit) with `finish_reason: "error"` so clients can still tell it
+// apart from a real completion.
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::Deserialize;
+use std::fs;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::models::openai::{
+    ChatCompletionChoice, ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionResponse,
+    ChatMessage, DeltaMessage, Role,
+};
+use crate::services::model_policy::matches_pattern;
+
+/// Finish reason marking a canned reply.
+pub const FINISH_REASON: &str = "error";
+
+/// A canned reply and the requested models it covers.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FallbackResponse {
+    /// Exact names or prefix patterns ending in `*`; empty covers every model.
+    #[serde(default)]
+    pub models: Vec<String>,
+    pub message: String,
+}
+
+/// Ordered fallback replies; the first one covering the model wins.
+#[derive(Debug, Default)]
+pub struct FallbackResponses {
+    responses: Vec<FallbackResponse>,
+}
+
+impl FallbackResponses {
+    /// Loads replies from an optional JSON array file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or a reply is
+    /// invalid (see [`FallbackResponses::new`]).
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fallback responses file '{path}'"))?;
+        let responses: Vec<FallbackResponse> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse fallback responses file '{path}'"))?;
+        info!(
+            "Loaded {} fallback responses from {}",
+            responses.len(),
+            path
+        );
+        Self::new(responses)
+    }
+
+    /// # Errors
+    ///
+    /// Rejects replies with an empty message.
+    pub fn new(responses: Vec<FallbackResponse>) -> Result<Self> {
+        if let Some(index) = responses.iter().position(|r| r.message.trim().is_empty()) {
+            bail!("Fallback response {index} has an empty message");
+        }
+        Ok(Self { responses })
+    }
+
+    /// The reply configured for `model`, if any.
+    #[must_use]
+    pub fn for_model(&self, model: &str) -> Option<&str> {
+        self.responses
+            .iter()
+            .find(|r| r.models.is_empty() || r.models.iter().any(|p| matches_pattern(p, model)))
+            .map(|r| r.message.as_str())
+    }
+}
+
+/// `message` as a chat completion from `model`.
+#[must_use]
+pub fn completion(model: &str, message: &str) -> ChatCompletionResponse {
+    ChatCompletionResponse {
+        id: format!("chatcmpl-fallback-{}", Uuid::new_v4()),
+        object: "chat.completion".to_string(),
+        created: created(),
+        model: model.to_string(),
+        choices: vec![ChatCompletionChoice {
+            index: 0,
+            message: ChatMessage {
+                role: Role::Assistant,
+                content: message.to_string(),
+                name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            },
+            finish_reason: Some(FINISH_REASON.to_string()),
+        }],
+        usage: None,
+    }
+}
+
+/// `message` as the single chunk of a streamed completion from `model`.
+#[must_use]
+pub fn chunk(model: &str, message: &str) -> ChatCompletionChunk {
+    ChatCompletionChunk {
+        id: format!("chatcmpl-fallback-{}", Uuid::new_v4()),
+        object: "chat.completion.chunk".to_string(),
+        usage: None,
+        created: created(),
+        model: model.to_string(),
+        choices: vec![ChatCompletionChunkChoice {
+            index: 0,
+            delta: DeltaMessage {
+                role: Some(Role::Assistant),
+                content: Some(message.to_string()),
+                tool_calls: None,
+            },
+            finish_reason: Some(FINISH_REASON.to_string()),
+        }],
+    }
+}
+
+fn created() -> u64 {
+    u64::try_from(Utc::now().timestamp()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_covering_reply_wins() {
+        let responses: Vec<FallbackResponse> = serde_json::from_value(serde_json::json!([
+            {"models": ["claude-*"], "message": "Claude is resting."},
+            {"models": ["gemini-2.5-pro"], "message": "Pro is resting."},
+            {"message": "Everything is resting."}
+        ]))
+        .expect("responses should parse");
+        let responses = FallbackResponses::new(responses).expect("responses should be valid");
+
+        assert_eq!(
+            responses.for_model("claude-3-opus"),
+            Some("Claude is resting.")
+        );
+        assert_eq!(
+            responses.for_model("gemini-2.5-pro"),
+            Some("Pro is resting.")
+        );
+        assert_eq!(
+            responses.for_model("gemini-2.5-flash"),
+            Some("Everything is resting.")
+        );
+        assert!(FallbackResponses::default()
+            .for_model("gemini-2.5-flash")
+            .is_none());
+
+        let blank = vec![FallbackResponse {
+            models: Vec::new(),
+            message: " ".to_string(),
+        }];
+        assert!(FallbackResponses::new(blank).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// Normalization of provider finish reasons into the OpenAI set.
+//
+// OpenAI clients only understand `stop`, `length`, `content_filter` and
+// `tool_calls`. Each provider reports its own vocabulary; every transformer
+// maps through here so clients see the same values whichever backend served
+// the request. Unrecognized reasons map to `stop`.
+
+pub const STOP: &str = "stop";
+pub const LENGTH: &str = "length";
+pub const CONTENT_FILTER: &str = "content_filter";
+pub const TOOL_CALLS: &str = "tool_calls";
+
+/// Returns the reason unchanged if it is already an OpenAI value.
+fn openai_value(reason: &str) -> Option<&'static str> {
+    [STOP, LENGTH, CONTENT_FILTER, TOOL_CALLS]
+        .into_iter()
+        .find(|v| reason.eq_ignore_ascii_case(v))
+}
+
+/// Maps a Vertex/Gemini `finishReason` (e.g. `MAX_TOKENS`, `SAFETY`).
+#[must_use]
+pub fn from_vertex(reason: &str) -> &'static str {
+    match reason.to_ascii_uppercase().as_str() {
+        "MAX_TOKENS" => LENGTH,
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            CONTENT_FILTER
+        }
+        _ => openai_value(reason).unwrap_or(STOP),
+    }
+}
+
+/// Maps an Anthropic `stop_reason` (e.g. `end_turn`, `max_tokens`, `tool_use`).
+#[must_use]
+pub fn from_anthropic(reason: &str) -> &'static str {
+    match reason.to_ascii_lowercase().as_str() {
+        "max_tokens" => LENGTH,
+        "tool_use" => TOOL_CALLS,
+        "refusal" => CONTENT_FILTER,
+        _ => openai_value(reason).unwrap_or(STOP),
+    }
+}
+
+/// Maps a Bedrock Converse `stopReason` (e.g. `end_turn`, `max_tokens`,
+/// `guardrail_intervened`).
+#[must_use]
+pub fn from_bedrock(reason: &str) -> &'static str {
+    match reason.to_ascii_lowercase().as_str() {
+        "max_tokens" => LENGTH,
+        "tool_use" => TOOL_CALLS,
+        "content_filtered" | "guardrail_intervened" => CONTENT_FILTER,
+        _ => openai_value(reason).unwrap_or(STOP),
+    }
+}
+
+/// Maps an Ollama `done_reason` (`stop`, `length`, or `load`/`unload` for
+/// requests that only loaded or unloaded the model).
+#[must_use]
+pub fn from_ollama(reason: &str) -> &'static str {
+    openai_value(reason).unwrap_or(STOP)
+}
+
+/// Rewrites `finish_reason` in each complete `data:` line of an OpenAI-style
+/// SSE chunk using `map`. Lines that do not parse, or need no change, are
+/// passed through byte for byte.
+#[must_use]
+pub fn normalize_sse_chunk(chunk: &str, map: fn(&str) -> &'static str) -> String {
+    rewrite_sse_chunk(chunk, |event| normalize_event(event, map))
+}
+
+/// Maps `finish_reason` in a parsed OpenAI-style event; returns whether it changed.
+pub fn normalize_event(event: &mut serde_json::Value, map: fn(&str) -> &'static str) -> bool {
+    let Some(reason) = event["choices"][0]["finish_reason"].as_str() else {
+        return false;
+    };
+    let mapped = map(reason);
+    if mapped == reason {
+        return false;
+    }
+    event["choices"][0]["finish_reason"] = mapped.into();
+    true
+}
+
+/// Applies `rewrite` to the JSON of each complete `data:` line of an SSE
+/// chunk. Lines that do not parse, or that `rewrite` reports unchanged, are
+/// passed through byte for byte.
+#[must_use]
+pub fn rewrite_sse_chunk(
+    chunk: &str,
+    mut rewrite: impl FnMut(&mut serde_json::Value) -> bool,
+) -> String {
+    let mut out = String::with_capacity(chunk.len());
+    for line in chunk.split_inclusive('\n') {
+        let (body, newline) = line
+            .strip_suffix('\n')
+            .map_or((line, ""), |body| (body, "\n"));
+        let rewritten = body
+            .strip_prefix("data: ")
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(json).ok())
+            .and_then(|mut value| rewrite(&mut value).then(|| format!("data: {value}")));
+        out.push_str(rewritten.as_deref().unwrap_or(body));
+        out.push_str(newline);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vertex_finish_reasons() {
+        assert_eq!(from_vertex("STOP"), STOP);
+        assert_eq!(from_vertex("MAX_TOKENS"), LENGTH);
+        assert_eq!(from_vertex("SAFETY"), CONTENT_FILTER);
+        assert_eq!(from_vertex("RECITATION"), CONTENT_FILTER);
+        assert_eq!(from_vertex("FINISH_REASON_UNSPECIFIED"), STOP);
+        assert_eq!(from_vertex("OTHER"), STOP);
+    }
+
+    #[test]
+    fn test_anthropic_finish_reasons() {
+        assert_eq!(from_anthropic("end_turn"), STOP);
+        assert_eq!(from_anthropic("stop_sequence"), STOP);
+        assert_eq!(from_anthropic("max_tokens"), LENGTH);
+        assert_eq!(from_anthropic("tool_use"), TOOL_CALLS);
+        assert_eq!(from_anthropic("refusal"), CONTENT_FILTER);
+        // Bridges that already translate are left alone
+        assert_eq!(from_anthropic("length"), LENGTH);
+    }
+
+    #[test]
+    fn test_bedrock_finish_reasons() {
+        assert_eq!(from_bedrock("end_turn"), STOP);
+        assert_eq!(from_bedrock("stop_sequence"), STOP);
+        assert_eq!(from_bedrock("max_tokens"), LENGTH);
+        assert_eq!(from_bedrock("guardrail_intervened"), CONTENT_FILTER);
+        assert_eq!(from_bedrock("content_filtered"), CONTENT_FILTER);
+    }
+
+    #[test]
+    fn test_normalize_sse_chunk_rewrites_only_finish_reason_lines() {
+        let chunk =
+            "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"},\"finish_reason\":null}]}\n\n\
+                     data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"max_tokens\"}]}\n\n\
+                     data: [DONE]\n\n";
+        let normalized = normalize_sse_chunk(chunk, from_anthropic);
+        assert!(normalized.contains("\"finish_reason\":\"length\""));
+        assert!(normalized.starts_with(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"},\"finish_reason\":null}]}\n\n"
+        ));
+        assert!(normalized.ends_with("data: [DONE]\n\n"));
+
+        let untouched = "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n";
+        assert_eq!(normalize_sse_chunk(untouched, from_anthropic), untouched);
+    }
+}
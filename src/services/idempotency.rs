@@ -0,0 +1,564 @@
+// Replay of completed responses for retried requests.
+//
+// A client that retries after a network blip cannot tell whether its first
+// attempt reached the provider. When it sends an `Idempotency-Key` header, the
+// first successful response under that key is kept for a while and replayed
+// for any retry, so the completion is neither recomputed nor billed twice.
+// Keys are scoped to the calling API key; reusing one for a different request
+// body is rejected, and a retry that arrives while the first attempt is still
+// running is told to wait rather than starting a second upstream call.
+//
+// Entries live in process memory. In cluster mode completed responses are
+// also written to the shared storage, so a retry landing on another replica
+// is replayed too; a retry that reaches another replica while the first
+// attempt is still running is not detected.
+
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::Response;
+use base64::Engine;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::config::IdempotencyConfig;
+use crate::services::storage::SharedStorage;
+
+/// Request header carrying the client's idempotency key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Response header set on replayed responses.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Longest accepted idempotency key.
+pub const MAX_KEY_LEN: usize = 255;
+
+// Responses larger than this are passed through but not kept for replay
+const MAX_STORED_BODY: usize = 8 * 1024 * 1024;
+
+// Storage prefix of completed responses shared between replicas
+const STORAGE_PREFIX: &str = "idempotency:";
+
+/// A response as it is replayed.
+#[derive(Clone)]
+struct StoredResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// A completed response as written to shared storage.
+#[derive(Serialize, Deserialize)]
+struct PersistedResponse {
+    fingerprint: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// Base64 of the body
+    body: String,
+}
+
+impl PersistedResponse {
+    fn new(fingerprint: &str, response: &StoredResponse) -> Self {
+        Self {
+            fingerprint: fingerprint.to_string(),
+            status: response.status.as_u16(),
+            headers: response
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+            body: base64::engine::general_purpose::STANDARD.encode(&response.body),
+        }
+    }
+
+    fn into_response(self) -> Option<StoredResponse> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers {
+            headers.append(
+                axum::http::HeaderName::try_from(name).ok()?,
+                HeaderValue::try_from(value).ok()?,
+            );
+        }
+        Some(StoredResponse {
+            status: StatusCode::from_u16(self.status).ok()?,
+            headers,
+            body: base64::engine::general_purpose::STANDARD
+                .decode(self.body)
+                .ok()?
+                .into(),
+        })
+    }
+}
+
+enum Entry {
+    InProgress {
+        fingerprint: String,
+        started: Instant,
+    },
+    Completed {
+        fingerprint: String,
+        response: StoredResponse,
+        stored: Instant,
+    },
+}
+
+impl Entry {
+    fn fingerprint(&self) -> &str {
+        match self {
+            Self::InProgress { fingerprint, .. } | Self::Completed { fingerprint, .. } => {
+                fingerprint
+            }
+        }
+    }
+
+    fn since(&self) -> Instant {
+        match self {
+            Self::InProgress { started, .. } => *started,
+            Self::Completed { stored, .. } => *stored,
+        }
+    }
+}
+
+/// Outcome of presenting an idempotency key.
+pub enum Claim {
+    /// First use of the key: run the request and hand its response to
+    /// [`Reservation::capture`].
+    Acquired(Reservation),
+    /// The stored response for an earlier, identical request.
+    Replay(Response),
+    /// An identical request with this key is still running.
+    InProgress,
+    /// The key was already used for a different request.
+    Mismatch,
+}
+
+/// Bounded store of recent responses keyed by idempotency key.
+pub struct IdempotencyStore {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+    shared: Option<SharedStorage>,
+}
+
+impl Default for IdempotencyStore {
+    fn default() -> Self {
+        Self::from_config(&IdempotencyConfig::default())
+    }
+}
+
+impl IdempotencyStore {
+    #[must_use]
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+            shared: None,
+        }
+    }
+
+    /// Also writes completed responses to `storage` and replays responses
+    /// other replicas stored there.
+    #[must_use]
+    pub fn with_shared_store(mut self, storage: SharedStorage) -> Self {
+        self.shared = Some(storage);
+        self
+    }
+
+    #[must_use]
+    pub fn from_config(config: &IdempotencyConfig) -> Self {
+        Self::new(Duration::from_secs(config.ttl_secs), config.max_entries)
+    }
+
+    /// Whether keys are honoured at all; a zero capacity turns the store off.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.max_entries > 0
+    }
+
+    /// Entries currently held, including requests still running.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Presents `key` from the API key `key_name` for a request whose body
+    /// hashes to `fingerprint` (see [`fingerprint`]).
+    pub async fn claim(self: &Arc<Self>, key_name: &str, key: &str, fingerprint: String) -> Claim {
+        // Length-prefixed so no key name can run into another's idempotency key
+        let scoped = format!("{}:{key_name}|{key}", key_name.len());
+        let local = self.lookup(&mut self.lock(), &scoped, &fingerprint);
+        if let Some(claim) = local {
+            return claim;
+        }
+        if let Some(claim) = self.lookup_shared(&scoped, &fingerprint).await {
+            return claim;
+        }
+        let mut entries = self.lock();
+        // Checked again, as an identical request may have claimed the key meanwhile
+        match self.lookup(&mut entries, &scoped, &fingerprint) {
+            Some(claim) => claim,
+            None => {
+                self.make_room(&mut entries);
+                entries.insert(
+                    scoped.clone(),
+                    Entry::InProgress {
+                        fingerprint: fingerprint.clone(),
+                        started: Instant::now(),
+                    },
+                );
+                Claim::Acquired(Reservation {
+                    store: Arc::clone(self),
+                    key: scoped,
+                    fingerprint,
+                    done: false,
+                })
+            }
+        }
+    }
+
+    /// What an existing local entry for `scoped` says about the request, if
+    /// there is one that has not expired.
+    fn lookup(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        scoped: &str,
+        fingerprint: &str,
+    ) -> Option<Claim> {
+        if entries
+            .get(scoped)
+            .is_some_and(|entry| entry.since().elapsed() > self.ttl)
+        {
+            entries.remove(scoped);
+        }
+        match entries.get(scoped)? {
+            entry if entry.fingerprint() != fingerprint => Some(Claim::Mismatch),
+            Entry::InProgress { .. } => Some(Claim::InProgress),
+            Entry::Completed { response, .. } => Some(Claim::Replay(replay(response.clone()))),
+        }
+    }
+
+    /// The response another replica stored in shared storage for `scoped`.
+    async fn lookup_shared(&self, scoped: &str, fingerprint: &str) -> Option<Claim> {
+        let shared = self.shared.as_ref()?;
+        let value = match shared.get(&format!("{STORAGE_PREFIX}{scoped}")).await {
+            Ok(value) => value?,
+            Err(e) => {
+                warn!("Shared idempotency lookup failed: {e:#}");
+                return None;
+            }
+        };
+        let persisted: PersistedResponse = match serde_json::from_str(&value) {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                warn!("Ignoring unreadable shared idempotency entry: {e}");
+                return None;
+            }
+        };
+        if persisted.fingerprint != fingerprint {
+            return Some(Claim::Mismatch);
+        }
+        persisted
+            .into_response()
+            .map(|response| Claim::Replay(replay(response)))
+    }
+
+    /// Drops expired entries and, when still full, the oldest completed ones.
+    fn make_room(&self, entries: &mut HashMap<String, Entry>) {
+        if entries.len() < self.max_entries {
+            return;
+        }
+        entries.retain(|_, entry| entry.since().elapsed() <= self.ttl);
+        while entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .filter(|(_, entry)| matches!(entry, Entry::Completed { .. }))
+                .min_by_key(|(_, entry)| entry.since())
+                .map(|(key, _)| key.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            debug!("Idempotency store full; evicting the oldest response");
+            entries.remove(&oldest);
+        }
+    }
+
+    async fn complete(&self, key: &str, fingerprint: String, response: StoredResponse) {
+        if let Some(shared) = &self.shared {
+            let stored = serde_json::to_string(&PersistedResponse::new(&fingerprint, &response));
+            let stored = match stored {
+                Ok(value) => {
+                    shared
+                        .set(&format!("{STORAGE_PREFIX}{key}"), value, Some(self.ttl))
+                        .await
+                }
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = stored {
+                warn!("Failed to share idempotent response: {e:#}");
+            }
+        }
+        self.lock().insert(
+            key.to_string(),
+            Entry::Completed {
+                fingerprint,
+                response,
+                stored: Instant::now(),
+            },
+        );
+    }
+
+    fn release(&self, key: &str) {
+        let mut entries = self.lock();
+        if matches!(entries.get(key), Some(Entry::InProgress { .. })) {
+            entries.remove(key);
+        }
+    }
+}
+
+/// Hex SHA-256 of a request body, identifying the request behind a key.
+#[must_use]
+pub fn fingerprint(body: &[u8]) -> String {
+    Sha256::digest(body)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = stored.status;
+    *response.headers_mut() = stored.headers;
+    response
+        .headers_mut()
+        .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+/// A claimed key whose request is running. Dropping it unused (or capturing
+/// a failed response) releases the key so the client can retry.
+pub struct Reservation {
+    store: Arc<IdempotencyStore>,
+    key: String,
+    fingerprint: String,
+    done: bool,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.done {
+            self.store.release(&self.key);
+        }
+    }
+}
+
+impl Reservation {
+    /// Passes `response` through, storing it for replay once its body has
+    /// been sent in full. Streams are stored as the complete event stream;
+    /// unsuccessful responses and bodies that fail midway are not stored.
+    pub fn capture(self, response: Response) -> Response {
+        if !response.status().is_success() {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let capture = Capture {
+            reservation: self,
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: Vec::new(),
+        };
+        let body = stream::unfold(
+            (body.into_data_stream(), Some(capture)),
+            |(mut data, mut capture)| async move {
+                let chunk = data.next().await;
+                match &chunk {
+                    Some(Ok(bytes)) => {
+                        if let Some(c) = capture.as_mut() {
+                            if c.body.len() + bytes.len() > MAX_STORED_BODY {
+                                capture = None;
+                            } else {
+                                c.body.extend_from_slice(bytes);
+                            }
+                        }
+                    }
+                    Some(Err(_)) => capture = None,
+                    None => {
+                        if let Some(capture) = capture.take() {
+                            capture.finish().await;
+                        }
+                    }
+                }
+                chunk.map(|chunk| (chunk, (data, capture)))
+            },
+        );
+        Response::from_parts(parts, Body::from_stream(body))
+    }
+}
+
+/// A successful response body being collected for replay.
+struct Capture {
+    reservation: Reservation,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Capture {
+    async fn finish(mut self) {
+        let reservation = &mut self.reservation;
+        reservation.done = true;
+        reservation
+            .store
+            .complete(
+                &reservation.key,
+                std::mem::take(&mut reservation.fingerprint),
+                StoredResponse {
+                    status: self.status,
+                    headers: self.headers,
+                    body: Bytes::from(self.body),
+                },
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::MemoryStorage;
+
+    async fn body_text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .expect("body should be readable");
+        String::from_utf8(bytes.to_vec()).expect("body should be UTF-8")
+    }
+
+    #[tokio::test]
+    async fn test_completed_response_is_replayed() {
+        let store = Arc::new(IdempotencyStore::new(Duration::from_secs(60), 10));
+        let Claim::Acquired(reservation) =
+            store.claim("team-a", "retry-1", "abc".to_string()).await
+        else {
+            panic!("first use should acquire the key");
+        };
+        assert!(matches!(
+            store.claim("team-a", "retry-1", "abc".to_string()).await,
+            Claim::InProgress
+        ));
+        assert!(matches!(
+            store.claim("team-a", "retry-1", "def".to_string()).await,
+            Claim::Mismatch
+        ));
+        // Other keys have their own namespace
+        assert!(matches!(
+            store.claim("team-b", "retry-1", "abc".to_string()).await,
+            Claim::Acquired(_)
+        ));
+
+        let response = reservation.capture(Response::new(Body::from("completion")));
+        assert_eq!(body_text(response).await, "completion");
+
+        let Claim::Replay(replayed) = store.claim("team-a", "retry-1", "abc".to_string()).await
+        else {
+            panic!("retry should replay the stored response");
+        };
+        assert_eq!(replayed.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+        assert_eq!(body_text(replayed).await, "completion");
+    }
+
+    #[tokio::test]
+    async fn test_failed_or_abandoned_requests_release_the_key() {
+        let store = Arc::new(IdempotencyStore::new(Duration::from_secs(60), 10));
+        let Claim::Acquired(reservation) = store.claim("team-a", "k", "abc".to_string()).await
+        else {
+            panic!("first use should acquire the key");
+        };
+        let mut failed = Response::new(Body::from("upstream error"));
+        *failed.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        drop(reservation.capture(failed));
+        assert!(store.is_empty());
+
+        let Claim::Acquired(reservation) = store.claim("team-a", "k", "abc".to_string()).await
+        else {
+            panic!("a failed request should not hold the key");
+        };
+        // Client went away before the body was sent
+        drop(reservation.capture(Response::new(Body::from("partial"))));
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_full_store_evicts_the_oldest_response() {
+        let store = Arc::new(IdempotencyStore::new(Duration::from_secs(60), 2));
+        for key in ["first", "second", "third"] {
+            let Claim::Acquired(reservation) = store.claim("team-a", key, key.to_string()).await
+            else {
+                panic!("{key} should acquire");
+            };
+            body_text(reservation.capture(Response::new(Body::from(key)))).await;
+        }
+        assert_eq!(store.len(), 2);
+        assert!(matches!(
+            store.claim("team-a", "first", "first".to_string()).await,
+            Claim::Acquired(_)
+        ));
+        assert!(!IdempotencyStore::new(Duration::from_secs(60), 0).is_enabled());
+    }
+
+    #[tokio::test]
+    async fn test_shared_store_replays_on_other_replicas() {
+        let shared: SharedStorage = Arc::new(MemoryStorage::new());
+        let replica = |storage: &SharedStorage| {
+            Arc::new(
+                IdempotencyStore::new(Duration::from_secs(60), 10)
+                    .with_shared_store(Arc::clone(storage)),
+            )
+        };
+        let (replica_a, replica_b) = (replica(&shared), replica(&shared));
+
+        let Claim::Acquired(reservation) = replica_a
+            .claim("team-a", "retry-1", "abc".to_string())
+            .await
+        else {
+            panic!("first use should acquire the key");
+        };
+        let mut response = Response::new(Body::from("completion"));
+        response
+            .headers_mut()
+            .insert("x-request-id", HeaderValue::from_static("req-1"));
+        body_text(reservation.capture(response)).await;
+
+        let Claim::Replay(replayed) = replica_b
+            .claim("team-a", "retry-1", "abc".to_string())
+            .await
+        else {
+            panic!("another replica should replay the stored response");
+        };
+        assert_eq!(replayed.headers()["x-request-id"], "req-1");
+        assert_eq!(body_text(replayed).await, "completion");
+        assert!(matches!(
+            replica_b
+                .claim("team-a", "retry-1", "def".to_string())
+                .await,
+            Claim::Mismatch
+        ));
+    }
+}
@@ -0,0 +1,462 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use tracing::{info, warn};
+
+use crate::services::auth::VertexCredentials;
+use crate::services::budgets::BudgetLimits;
+use crate::services::param_policy::ParamPolicy;
+use crate::services::scheduler::Priority;
+use crate::services::storage::Storage;
+
+// Storage prefix of persisted keys, each stored under its name
+const STORAGE_PREFIX: &str = "keys:";
+
+/// A client API key loaded from the keys file.
+///
+/// The keys file is a JSON array of these objects. The secret is hashed on
+/// load and never kept in plain text.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiKeyDefinition {
+    pub name: String,
+    pub key: String,
+    #[serde(default)]
+    pub max_priority: Priority,
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default, flatten)]
+    pub budget: BudgetLimits,
+    /// Generation parameter ceilings and defaults applied to this key's requests.
+    #[serde(default)]
+    pub params: ParamPolicy,
+    /// Vertex credentials and project used for this key's requests.
+    #[serde(default)]
+    pub vertex: Option<VertexCredentials>,
+    /// Exempts the key from rate limiting and spend limits, e.g. for health
+    /// checkers and internal schedulers. Its usage is still recorded.
+    #[serde(default)]
+    pub exempt: bool,
+}
+
+/// Identity and permissions of the caller, attached to requests by the auth middleware.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthenticatedKey {
+    pub name: String,
+    pub max_priority: Priority,
+    pub admin: bool,
+    /// Not subject to rate limiting or spend limits.
+    #[serde(default)]
+    pub exempt: bool,
+}
+
+impl AuthenticatedKey {
+    /// The master key carries every permission.
+    #[must_use]
+    pub fn master() -> Self {
+        Self {
+            name: "master".to_string(),
+            max_priority: Priority::High,
+            admin: true,
+            exempt: false,
+        }
+    }
+
+    /// Used when authentication is disabled; nothing is restricted.
+    #[must_use]
+    pub fn anonymous() -> Self {
+        Self {
+            name: "anonymous".to_string(),
+            max_priority: Priority::High,
+            admin: true,
+            exempt: false,
+        }
+    }
+}
+
+/// API key as persisted (secret already hashed).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredKey {
+    pub key_hash: String,
+    pub identity: AuthenticatedKey,
+    pub budget: BudgetLimits,
+}
+
+/// Writes `keys` to `storage`, replacing stored keys of the same name.
+///
+/// # Errors
+///
+/// Returns an error if a write fails.
+pub async fn save_stored(storage: &dyn Storage, keys: Vec<StoredKey>) -> Result<()> {
+    for key in keys {
+        let name = format!("{STORAGE_PREFIX}{}", key.identity.name);
+        storage
+            .set(&name, serde_json::to_string(&key)?, None)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Every key persisted in `storage`.
+///
+/// # Errors
+///
+/// Returns an error if the scan fails or an entry cannot be parsed.
+pub async fn load_stored(storage: &dyn Storage) -> Result<Vec<StoredKey>> {
+    storage
+        .scan(STORAGE_PREFIX)
+        .await?
+        .into_iter()
+        .map(|(name, value)| {
+            serde_json::from_str(&value).with_context(|| format!("Invalid stored key '{name}'"))
+        })
+        .collect()
+}
+
+/// Deletes persisted keys whose hash is not in `keep`, so a key removed from
+/// the keys file stops authenticating after a restart. Entries that cannot be
+/// parsed are deleted too. Returns how many were deleted.
+///
+/// # Errors
+///
+/// Returns an error if the scan or a delete fails.
+pub async fn prune_stored(storage: &dyn Storage, keep: &HashSet<String>) -> Result<usize> {
+    let mut removed = 0;
+    for (name, value) in storage.scan(STORAGE_PREFIX).await? {
+        let stale = match serde_json::from_str::<StoredKey>(&value) {
+            Ok(key) => !keep.contains(&key.key_hash),
+            Err(e) => {
+                warn!("Deleting invalid stored key '{name}': {e}");
+                true
+            }
+        };
+        if stale && storage.delete(&name).await? {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Updates the budget of the key persisted as `name`, returning whether
+/// there was one.
+///
+/// # Errors
+///
+/// Returns an error if the key cannot be read or written back.
+pub async fn update_stored_budget(
+    storage: &dyn Storage,
+    name: &str,
+    budget: BudgetLimits,
+) -> Result<bool> {
+    let entry = format!("{STORAGE_PREFIX}{name}");
+    let Some(value) = storage.get(&entry).await? else {
+        return Ok(false);
+    };
+    let mut key: StoredKey =
+        serde_json::from_str(&value).with_context(|| format!("Invalid stored key '{entry}'"))?;
+    key.budget = budget;
+    storage
+        .set(&entry, serde_json::to_string(&key)?, None)
+        .await?;
+    Ok(true)
+}
+
+pub(crate) fn hash_key(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Set of per-client API keys accepted in addition to the master key.
+#[derive(Debug, Clone, Default)]
+pub struct KeyStore {
+    keys: HashMap<String, AuthenticatedKey>,
+    budgets: HashMap<String, BudgetLimits>,
+    params: HashMap<String, ParamPolicy>,
+    vertex: HashMap<String, VertexCredentials>,
+}
+
+impl KeyStore {
+    #[must_use]
+    pub fn from_definitions(definitions: Vec<ApiKeyDefinition>) -> Self {
+        let budgets = definitions
+            .iter()
+            .filter(|d| !d.budget.is_unlimited())
+            .map(|d| (d.name.clone(), d.budget))
+            .collect();
+        let params = definitions
+            .iter()
+            .filter(|d| !d.params.is_empty())
+            .map(|d| (d.name.clone(), d.params))
+            .collect();
+        let vertex = definitions
+            .iter()
+            .filter_map(|d| d.vertex.clone().map(|v| (d.name.clone(), v)))
+            .collect();
+        let keys = definitions
+            .into_iter()
+            .map(|d| {
+                (
+                    hash_key(&d.key),
+                    AuthenticatedKey {
+                        name: d.name,
+                        max_priority: d.max_priority,
+                        admin: d.admin,
+                        exempt: d.exempt,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            keys,
+            budgets,
+            params,
+            vertex,
+        }
+    }
+
+    /// Loads the key store from an optional JSON keys file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read keys file '{path}'"))?;
+        let definitions: Vec<ApiKeyDefinition> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse keys file '{path}'"))?;
+        info!("Loaded {} API keys from {}", definitions.len(), path);
+        Ok(Self::from_definitions(definitions))
+    }
+
+    /// Keys in their persisted (hashed) form.
+    #[must_use]
+    pub fn to_stored(&self) -> Vec<StoredKey> {
+        self.keys
+            .iter()
+            .map(|(hash, identity)| StoredKey {
+                key_hash: hash.clone(),
+                identity: identity.clone(),
+                budget: self
+                    .budgets
+                    .get(&identity.name)
+                    .copied()
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Adds keys loaded from storage.
+    pub fn merge_stored(&mut self, stored: Vec<StoredKey>) {
+        for key in stored {
+            if !key.budget.is_unlimited() {
+                self.budgets.insert(key.identity.name.clone(), key.budget);
+            }
+            self.keys.insert(key.key_hash, key.identity);
+        }
+    }
+
+    /// Digests of every key, in the form keys are persisted under.
+    #[must_use]
+    pub fn key_hashes(&self) -> HashSet<String> {
+        self.keys.keys().cloned().collect()
+    }
+
+    /// Looks up a presented bearer token.
+    ///
+    /// Tokens are compared by SHA-256 digest so lookup time does not depend on
+    /// how much of the secret matches.
+    #[must_use]
+    pub fn authenticate(&self, token: &str) -> Option<&AuthenticatedKey> {
+        self.keys.get(&hash_key(token))
+    }
+
+    /// Digests of the keys exempt from rate limiting, in the form the rate
+    /// limiter compares presented tokens against.
+    #[must_use]
+    pub fn exempt_key_hashes(&self) -> HashSet<String> {
+        self.keys
+            .iter()
+            .filter(|(_, identity)| identity.exempt)
+            .map(|(hash, _)| hash.clone())
+            .collect()
+    }
+
+    /// Spend limits declared in the keys file, by key name.
+    #[must_use]
+    pub fn budget_limits(&self) -> HashMap<String, BudgetLimits> {
+        self.budgets.clone()
+    }
+
+    /// Parameter policy declared in the keys file for the named key.
+    #[must_use]
+    pub fn param_policy(&self, name: &str) -> Option<&ParamPolicy> {
+        self.params.get(name)
+    }
+
+    /// Vertex credentials declared in the keys file, by key name.
+    #[must_use]
+    pub fn vertex_credentials(&self) -> &HashMap<String, VertexCredentials> {
+        &self.vertex
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::MemoryStorage;
+
+    #[test]
+    fn test_authenticate_defined_key() {
+        let definitions: Vec<ApiKeyDefinition> = serde_json::from_str(
+            r#"[
+                {"name": "batch", "key": "sk-batch-0000000000", "max_priority": "low", "daily_usd": 5.0,
+                 "params": {"max_temperature": 0.7, "max_tokens": 2048}},
+                {"name": "app", "key": "sk-app-00000000000"},
+                {"name": "probe", "key": "sk-probe-000000000", "exempt": true}
+            ]"#,
+        )
+        .expect("keys should parse");
+        let store = KeyStore::from_definitions(definitions);
+
+        let batch = store
+            .authenticate("sk-batch-0000000000")
+            .expect("batch key should authenticate");
+        assert_eq!(batch.name, "batch");
+        assert_eq!(batch.max_priority, Priority::Low);
+
+        let app = store
+            .authenticate("sk-app-00000000000")
+            .expect("app key should authenticate");
+        assert_eq!(app.max_priority, Priority::Normal);
+
+        assert!(!app.admin);
+        assert!(!app.exempt);
+        assert!(store.authenticate("sk-unknown").is_none());
+        assert_eq!(
+            store.exempt_key_hashes(),
+            HashSet::from([hash_key("sk-probe-000000000")])
+        );
+
+        let budgets = store.budget_limits();
+        assert_eq!(budgets.len(), 1);
+        assert_eq!(budgets["batch"].daily_usd, Some(5.0));
+
+        let params = store
+            .param_policy("batch")
+            .expect("batch has a param policy");
+        assert_eq!(params.max_tokens, Some(2048));
+        assert!(store.param_policy("app").is_none());
+    }
+
+    #[test]
+    fn test_merge_stored_keys() {
+        let file_keys = KeyStore::from_definitions(vec![ApiKeyDefinition {
+            name: "batch".to_string(),
+            key: "sk-batch-0000000000".to_string(),
+            max_priority: Priority::Low,
+            admin: false,
+            budget: BudgetLimits {
+                daily_usd: Some(5.0),
+                monthly_usd: None,
+            },
+            params: ParamPolicy::default(),
+            vertex: None,
+            exempt: false,
+        }]);
+
+        let mut store = KeyStore::default();
+        store.merge_stored(file_keys.to_stored());
+        assert_eq!(store.len(), 1);
+        assert_eq!(
+            store
+                .authenticate("sk-batch-0000000000")
+                .map(|k| k.name.as_str()),
+            Some("batch")
+        );
+        assert_eq!(store.budget_limits()["batch"].daily_usd, Some(5.0));
+    }
+
+    #[tokio::test]
+    async fn test_stored_keys_roundtrip() {
+        let storage = MemoryStorage::new();
+        save_stored(
+            &storage,
+            vec![StoredKey {
+                key_hash: "abc".to_string(),
+                identity: AuthenticatedKey {
+                    name: "ci".to_string(),
+                    max_priority: Priority::Low,
+                    admin: false,
+                    exempt: false,
+                },
+                budget: BudgetLimits {
+                    daily_usd: Some(1.0),
+                    monthly_usd: None,
+                },
+            }],
+        )
+        .await
+        .expect("save should succeed");
+        assert!(
+            update_stored_budget(&storage, "ci", BudgetLimits::default())
+                .await
+                .expect("update should succeed")
+        );
+        assert!(
+            !update_stored_budget(&storage, "unknown", BudgetLimits::default())
+                .await
+                .expect("update should succeed")
+        );
+
+        let keys = load_stored(&storage).await.expect("keys should load");
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].identity.max_priority, Priority::Low);
+        assert!(keys[0].budget.is_unlimited());
+    }
+
+    #[tokio::test]
+    async fn test_prune_drops_keys_missing_from_file() {
+        let storage = MemoryStorage::new();
+        let stored = |name: &str, hash: &str| StoredKey {
+            key_hash: hash.to_string(),
+            identity: AuthenticatedKey {
+                name: name.to_string(),
+                max_priority: Priority::High,
+                admin: true,
+                exempt: false,
+            },
+            budget: BudgetLimits::default(),
+        };
+        save_stored(
+            &storage,
+            vec![stored("ops", "aaa"), stored("old-admin", "bbb")],
+        )
+        .await
+        .expect("save should succeed");
+        storage
+            .set("keys:broken", "not json".to_string(), None)
+            .await
+            .expect("set should succeed");
+
+        let keep = HashSet::from(["aaa".to_string()]);
+        assert_eq!(prune_stored(&storage, &keep).await.expect("prune"), 2);
+        let keys = load_stored(&storage).await.expect("keys should load");
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].identity.name, "ops");
+    }
+}
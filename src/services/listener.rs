@@ -0,0 +1,144 @@
+// Listening socket setup.
+//
+// A restart without dropped connections needs the replacement process to be
+// accepting before the old one stops. Two handovers are supported: systemd
+// socket activation, where a socket unit owns the port and passes it to each
+// new process, and `SO_REUSEPORT`, where both processes bind the port and the
+// old one drains after SIGTERM while the kernel routes new connections to the
+// new one.
+
+use anyhow::Context;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpSocket};
+use tracing::{info, warn};
+
+use crate::config::ServerConfig;
+
+// First file descriptor systemd passes (after stdin, stdout and stderr)
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: std::os::fd::RawFd = 3;
+
+const LISTEN_BACKLOG: u32 = 1024;
+
+/// Opens the listener for `config`: the socket systemd passed in if there is
+/// one, otherwise `host:port`, with `SO_REUSEPORT` if `reuse_port` is set.
+///
+/// # Errors
+///
+/// Returns an error if the address is invalid or cannot be bound.
+pub async fn bind(config: &ServerConfig) -> anyhow::Result<TcpListener> {
+    if let Some(listener) = inherited_listener()? {
+        info!("Using the listening socket passed by systemd");
+        return Ok(listener);
+    }
+
+    let host = &config.host;
+    let port = config.port;
+    let addr: SocketAddr = format!("{host}:{port}")
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid server address {host}:{port}: {e}"))?;
+    if config.reuse_port {
+        reuse_port_listener(addr)
+            .with_context(|| format!("Failed to bind {addr} with SO_REUSEPORT"))
+    } else {
+        TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind {addr}"))
+    }
+}
+
+/// Number of sockets systemd passed to process `pid`, per `LISTEN_PID` and
+/// `LISTEN_FDS`. The variables are inherited by child processes, so they only
+/// count when `LISTEN_PID` names this process.
+fn activation_fd_count(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    if listen_pid.and_then(|p| p.trim().parse::<u32>().ok()) != Some(pid) {
+        return 0;
+    }
+    listen_fds.and_then(|n| n.trim().parse().ok()).unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn inherited_listener() -> anyhow::Result<Option<TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let count = activation_fd_count(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    if count == 0 {
+        return Ok(None);
+    }
+    if count > 1 {
+        warn!("systemd passed {count} sockets; only the first is used");
+    }
+    // SAFETY: with LISTEN_PID naming this process, systemd guarantees fds
+    // SD_LISTEN_FDS_START.. are open sockets handed to us, and nothing else
+    // in the process takes ownership of them.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    listener
+        .set_nonblocking(true)
+        .context("Failed to configure the socket passed by systemd")?;
+    Ok(Some(TcpListener::from_std(listener).context(
+        "The socket passed by systemd is not a TCP listener",
+    )?))
+}
+
+#[cfg(not(unix))]
+fn inherited_listener() -> anyhow::Result<Option<TcpListener>> {
+    Ok(None)
+}
+
+fn reuse_port_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    socket.set_reuseport(true)?;
+    #[cfg(not(unix))]
+    warn!("SO_REUSEPORT is not available on this platform; binding normally");
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activation_fds_only_for_this_process() {
+        assert_eq!(activation_fd_count(Some("42"), Some("1"), 42), 1);
+        assert_eq!(activation_fd_count(Some("42"), Some("2"), 42), 2);
+        assert_eq!(activation_fd_count(Some("41"), Some("1"), 42), 0);
+        assert_eq!(activation_fd_count(None, Some("1"), 42), 0);
+        assert_eq!(activation_fd_count(Some("42"), None, 42), 0);
+        assert_eq!(activation_fd_count(Some("42"), Some("x"), 42), 0);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reuse_port_allows_second_listener() {
+        let mut config = ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            max_request_size: 1_000,
+            request_timeout_secs: 0,
+            shutdown_grace_secs: 30,
+            body_limits: Vec::new(),
+            reuse_port: true,
+        };
+        let first = bind(&config).await.expect("first bind");
+        config.port = first.local_addr().expect("bound address").port();
+        let second = bind(&config).await.expect("second bind shares the port");
+        assert_eq!(
+            first.local_addr().expect("bound address"),
+            second.local_addr().expect("bound address")
+        );
+
+        config.reuse_port = false;
+        assert!(bind(&config).await.is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// Request context on every JSON log line.
+//
+// A request's identity is spread over spans: the request ID on the `request`
+// span opened by the trace context middleware, the key and tenant recorded
+// there once the caller is authenticated, the model and provider once the
+// request is routed. With `log.format=json` those only appear in the nested
+// `spans` list, which Loki or ELK cannot filter on without parsing it.
+// `RequestContext` lifts them to top-level fields of every line logged
+// inside the request.
+//
+// `scope` makes the `request` span reachable from code running inside
+// narrower spans, so `record` can fill in a field as soon as it is known.
+
+use serde_json::Value;
+use std::fmt;
+use std::future::Future;
+use tracing::{Event, Span, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Span fields lifted to the top level of JSON log lines.
+pub const CONTEXT_FIELDS: &[&str] = &["request_id", "key", "tenant", "model", "provider"];
+
+tokio::task_local! {
+    static REQUEST_SPAN: Span;
+}
+
+/// Runs `future` with `span` as the span `record` writes to.
+pub async fn scope<F: Future>(span: Span, future: F) -> F::Output {
+    REQUEST_SPAN.scope(span, future).await
+}
+
+/// Records `value` as `field` of the current request's span. Does nothing
+/// outside a request or for fields the span does not declare.
+pub fn record(field: &str, value: &str) {
+    let _ = REQUEST_SPAN.try_with(|span| {
+        span.record(field, value);
+    });
+}
+
+/// Event format adding [`CONTEXT_FIELDS`] to the JSON lines of `F`, taken
+/// from its `spans` list with inner spans taking precedence.
+pub struct RequestContext<F> {
+    inner: F,
+}
+
+impl<F> RequestContext<F> {
+    /// Wraps a JSON format with the span list enabled.
+    #[must_use]
+    pub fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S, N, F> FormatEvent<S, N> for RequestContext<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut line = String::new();
+        self.inner
+            .format_event(ctx, Writer::new(&mut line), event)?;
+        writer.write_str(&with_context(line))
+    }
+}
+
+/// `line` with the context fields of its spans added, or unchanged if it is
+/// not a JSON object or carries no context.
+fn with_context(line: String) -> String {
+    let Ok(Value::Object(mut object)) = serde_json::from_str::<Value>(&line) else {
+        return line;
+    };
+    let mut lifted = false;
+    if let Some(Value::Array(spans)) = object.get("spans").cloned() {
+        for span in &spans {
+            for field in CONTEXT_FIELDS {
+                if let Some(value) = span.get(field) {
+                    object.insert((*field).to_string(), value.clone());
+                    lifted = true;
+                }
+            }
+        }
+    }
+    if !lifted {
+        return line;
+    }
+    let mut line = Value::Object(object).to_string();
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn lines(buffer: &Buffer) -> Vec<Value> {
+        let bytes = buffer
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("log line should be JSON"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_request_fields_reach_every_line() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let layer = tracing_subscriber::fmt::layer()
+            .json()
+            .with_span_list(true)
+            .with_writer(move || writer.clone())
+            .map_event_format(RequestContext::new);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        tracing::info!("before any request");
+        let request = tracing::info_span!(
+            "request",
+            request_id = "req-1",
+            key = tracing::field::Empty,
+            model = tracing::field::Empty,
+            provider = tracing::field::Empty
+        );
+        let handler = async {
+            record("key", "team-a");
+            let inner = tracing::info_span!("chat_completions", model = "gemini-2.5-flash");
+            let _entered = inner.enter();
+            record("provider", "vertex");
+            record("undeclared", "ignored");
+            tracing::info!("routed");
+        };
+        scope(request.clone(), handler.instrument(request)).await;
+        record("provider", "outside");
+
+        let lines = lines(&buffer);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].get("request_id").is_none());
+        assert_eq!(lines[1]["request_id"], "req-1");
+        assert_eq!(lines[1]["key"], "team-a");
+        assert_eq!(lines[1]["model"], "gemini-2.5-flash");
+        assert_eq!(lines[1]["provider"], "vertex");
+        assert!(lines[1].get("undeclared").is_none());
+        assert_eq!(lines[1]["fields"]["message"], "routed");
+    }
+
+    #[test]
+    fn test_non_json_lines_pass_through() {
+        assert_eq!(with_context("plain text\n".to_string()), "plain text\n");
+    }
+}
@@ -0,0 +1,294 @@
+// Log output the running process can reconfigure.
+//
+// Debugging a production issue usually calls for more than a higher level:
+// JSON lines to paste into a log search, or `debug` for one crate without the
+// noise of every other. The subscriber the binary installs keeps both its
+// filter and its formatting layer behind `reload` handles, and `LogControl`
+// swaps them as a unit, so the `/logs` CLI command and `PUT /admin/logs` can
+// change level, format and target directives without a restart. There is one
+// global subscriber per process, so there is one `LogControl`: `init`
+// installs it and `current` finds it. Libraries embedding the proxy that
+// install their own subscriber have none.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use tracing::{info, Subscriber};
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+use utoipa::ToSchema;
+
+use crate::config::LogConfig;
+use crate::services::log_context::RequestContext;
+
+/// Target directives applied next to the level until changed.
+pub const DEFAULT_TARGETS: &str = "tower_http=debug";
+
+type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type FormatLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+
+static CONTROL: OnceLock<LogControl> = OnceLock::new();
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines with targets, files and line numbers.
+    Pretty,
+    /// One JSON object per line, with request context lifted to the top level.
+    Json,
+}
+
+impl LogFormat {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pretty => "pretty",
+            Self::Json => "json",
+        }
+    }
+
+    fn layer(self) -> FormatLayer {
+        match self {
+            Self::Json => tracing_subscriber::fmt::layer()
+                .json()
+                .with_target(false)
+                .with_file(true)
+                .with_line_number(true)
+                .with_current_span(true)
+                .with_span_list(true)
+                .map_event_format(RequestContext::new)
+                .boxed(),
+            Self::Pretty => tracing_subscriber::fmt::layer()
+                .with_target(true)
+                .with_file(true)
+                .with_line_number(true)
+                .boxed(),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "Unknown log format '{other}' (expected pretty or json)"
+            )),
+        }
+    }
+}
+
+/// The log output currently in effect.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct LogSettings {
+    /// Default level, e.g. `info`.
+    pub level: String,
+    pub format: LogFormat,
+    /// Comma-separated per-target directives, e.g. `vertex_bridge::services=debug,hyper=warn`.
+    pub targets: String,
+}
+
+impl LogSettings {
+    fn from_config(config: &LogConfig) -> Self {
+        Self {
+            level: config.level.clone(),
+            // Anything but `json` has always meant the pretty format
+            format: config.format.parse().unwrap_or(LogFormat::Pretty),
+            targets: DEFAULT_TARGETS.to_string(),
+        }
+    }
+
+    /// The filter the settings describe: the level followed by the target
+    /// directives, which take precedence for the targets they name.
+    fn filter(&self) -> Result<EnvFilter, String> {
+        let directives = if self.targets.is_empty() {
+            self.level.clone()
+        } else {
+            format!("{},{}", self.level, self.targets)
+        };
+        EnvFilter::try_new(&directives)
+            .map_err(|e| format!("Invalid log filter '{directives}': {e}"))
+    }
+}
+
+/// Changes to the log output; fields left out keep their current value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct LogUpdate {
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub format: Option<LogFormat>,
+    /// Replaces every target directive; empty removes them.
+    #[serde(default)]
+    pub targets: Option<String>,
+}
+
+/// Reload handles for the installed subscriber's filter and format layer.
+pub struct LogControl {
+    filter: reload::Handle<EnvFilter, Registry>,
+    format: reload::Handle<FormatLayer, FilteredRegistry>,
+    settings: Mutex<LogSettings>,
+}
+
+impl LogControl {
+    /// A control and the subscriber it reconfigures, not yet installed.
+    fn new(config: &LogConfig) -> (Self, impl Subscriber + Send + Sync) {
+        let settings = LogSettings::from_config(config);
+        let filter = settings
+            .filter()
+            .unwrap_or_else(|_| EnvFilter::new(&config.level));
+        let (filter_layer, filter) = reload::Layer::new(filter);
+        let (format_layer, format) = reload::Layer::new(settings.format.layer());
+        let subscriber = tracing_subscriber::registry()
+            .with(filter_layer)
+            .with(format_layer);
+        let control = Self {
+            filter,
+            format,
+            settings: Mutex::new(settings),
+        };
+        (control, subscriber)
+    }
+
+    #[must_use]
+    pub fn settings(&self) -> LogSettings {
+        self.settings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Applies `update`, returning the settings now in effect.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, leaving the output unchanged, if the level or target
+    /// directives do not parse or a handle outlived its subscriber.
+    pub fn update(&self, update: LogUpdate) -> Result<LogSettings, String> {
+        let mut settings = self.settings.lock().unwrap_or_else(PoisonError::into_inner);
+        let next = LogSettings {
+            level: update.level.map_or_else(
+                || settings.level.clone(),
+                |level| level.trim().to_lowercase(),
+            ),
+            format: update.format.unwrap_or(settings.format),
+            targets: update.targets.map_or_else(
+                || settings.targets.clone(),
+                |targets| targets.trim().to_string(),
+            ),
+        };
+        let filter = next.filter()?;
+        self.filter
+            .reload(filter)
+            .map_err(|e| format!("Failed to update log filter: {e}"))?;
+        if next.format != settings.format {
+            self.format
+                .reload(next.format.layer())
+                .map_err(|e| format!("Failed to update log format: {e}"))?;
+        }
+        info!(
+            "Log output set to level={}, format={}, targets={:?}",
+            next.level, next.format, next.targets
+        );
+        *settings = next.clone();
+        Ok(next)
+    }
+}
+
+/// Installs the global subscriber described by `config` and returns its
+/// control; later calls return the first control.
+///
+/// # Panics
+///
+/// Panics if a global subscriber was installed by other means.
+pub fn init(config: &LogConfig) -> &'static LogControl {
+    CONTROL.get_or_init(|| {
+        let (control, subscriber) = LogControl::new(config);
+        subscriber.init();
+        control
+    })
+}
+
+/// The control installed by [`init`], if any.
+#[must_use]
+pub fn current() -> Option<&'static LogControl> {
+    CONTROL.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(format: &str) -> LogConfig {
+        LogConfig {
+            level: "info".to_string(),
+            format: format.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_parses_case_insensitively() {
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("pretty".parse::<LogFormat>(), Ok(LogFormat::Pretty));
+        assert!("xml".parse::<LogFormat>().is_err());
+        assert_eq!(
+            LogSettings::from_config(&config("compact")).format,
+            LogFormat::Pretty
+        );
+    }
+
+    #[test]
+    fn test_update_swaps_filter_and_format() {
+        let (control, subscriber) = LogControl::new(&config("pretty"));
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(!tracing::enabled!(target: "hyper", tracing::Level::DEBUG));
+            // Quiets this crate, including the confirmation `update` logs
+            let settings = control
+                .update(LogUpdate {
+                    level: Some("DEBUG".to_string()),
+                    format: Some(LogFormat::Json),
+                    targets: Some("vertex_bridge=warn".to_string()),
+                })
+                .unwrap();
+            assert_eq!(
+                settings,
+                LogSettings {
+                    level: "debug".to_string(),
+                    format: LogFormat::Json,
+                    targets: "vertex_bridge=warn".to_string(),
+                }
+            );
+            assert!(tracing::enabled!(target: "hyper", tracing::Level::DEBUG));
+            assert!(!tracing::enabled!(tracing::Level::INFO));
+            assert_eq!(control.settings(), settings);
+        });
+    }
+
+    #[test]
+    fn test_invalid_update_keeps_settings() {
+        let (control, _subscriber) = LogControl::new(&config("json"));
+        let before = control.settings();
+        assert_eq!(before.format, LogFormat::Json);
+        assert_eq!(before.targets, DEFAULT_TARGETS);
+        let error = control
+            .update(LogUpdate {
+                targets: Some("hyper=loud".to_string()),
+                ..LogUpdate::default()
+            })
+            .unwrap_err();
+        assert!(error.contains("hyper=loud"));
+        assert_eq!(control.settings(), before);
+    }
+}
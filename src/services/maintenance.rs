@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::middleware::rate_limit::RateLimiter;
+use crate::services::cache::Cache;
+use crate::services::routing::SessionAffinity;
+
+/// Result of a single maintenance sweep.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaintenanceReport {
+    pub cache_entries_removed: usize,
+    pub rate_limit_buckets_removed: usize,
+    pub session_bindings_removed: usize,
+}
+
+/// Runs one sweep over the response cache, rate-limiter buckets and sticky
+/// session bindings.
+pub async fn run_maintenance_pass(
+    cache: &Cache,
+    rate_limiter: &RateLimiter,
+    affinity: &SessionAffinity,
+) -> MaintenanceReport {
+    let report = MaintenanceReport {
+        cache_entries_removed: cache.cleanup_expired().await,
+        rate_limit_buckets_removed: rate_limiter.cleanup().await,
+        session_bindings_removed: affinity.cleanup_expired(),
+    };
+    debug!(
+        "Maintenance pass: removed {} cache entries, {} rate limit buckets, {} session bindings",
+        report.cache_entries_removed,
+        report.rate_limit_buckets_removed,
+        report.session_bindings_removed
+    );
+    report
+}
+
+/// Spawns the background maintenance task.
+///
+/// The first sweep runs one full `interval` after startup; the task lives until
+/// the runtime shuts down or the returned handle is aborted.
+#[must_use]
+pub fn spawn_maintenance_task(
+    cache: Arc<Cache>,
+    rate_limiter: RateLimiter,
+    affinity: Arc<SessionAffinity>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            run_maintenance_pass(&cache, &rate_limiter, &affinity).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::{ChatCompletionRequest, ChatMessage, Role};
+    use crate::services::clock::ManualClock;
+
+    #[tokio::test]
+    async fn test_maintenance_pass_removes_expired_cache_entries() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = Cache::new(true, 1).with_clock(clock.clone());
+        let rate_limiter = RateLimiter::new(10, 5).with_clock(clock.clone());
+        let request = ChatCompletionRequest {
+            model: "test-model".to_string(),
+            messages: vec![ChatMessage {
+                role: Role::User,
+                content: "test".to_string(),
+                name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+            stream: false,
+            temperature: Some(1.0),
+            max_tokens: None,
+            top_p: Some(1.0),
+            top_k: None,
+            stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
+        };
+
+        cache
+            .set("key", &request, "response".to_string(), None)
+            .await;
+        assert!(rate_limiter.check("key").await);
+
+        clock.advance(Duration::from_secs(2));
+
+        let report = run_maintenance_pass(&cache, &rate_limiter, &SessionAffinity::default()).await;
+        assert_eq!(report.cache_entries_removed, 1);
+        // Bucket was touched recently, so it survives the sweep
+        assert_eq!(report.rate_limit_buckets_removed, 0);
+        assert_eq!(rate_limiter.stats(0).await.active_keys, 1);
+    }
+}
@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+/// Settings for a maintenance window, as sent to `PUT /admin/maintenance`.
+///
+/// Unset fields fall back to `maintenance_mode` in the config. Without
+/// `from` the window opens at once; without `until` it stays open until it is
+/// ended explicitly.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// An open or scheduled maintenance window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceWindow {
+    /// When the window opens: `from`, or when it was set up.
+    pub started_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub settings: MaintenanceRequest,
+}
+
+impl MaintenanceWindow {
+    /// Whether `now` falls in `[from, until)`; either end may be open.
+    #[must_use]
+    pub fn is_open_at(&self, now: DateTime<Utc>) -> bool {
+        self.settings.from.is_none_or(|from| from <= now)
+            && self.settings.until.is_none_or(|until| now < until)
+    }
+
+    /// Seconds a client should wait before retrying: the time left in the
+    /// window if it has an end, otherwise the configured interval.
+    #[must_use]
+    pub fn retry_after_secs(&self, default_secs: u64, now: DateTime<Utc>) -> u64 {
+        if let Some(secs) = self.settings.retry_after_secs {
+            return secs;
+        }
+        self.settings.until.map_or(default_secs, |until| {
+            u64::try_from((until - now).num_seconds())
+                .unwrap_or(0)
+                .max(1)
+        })
+    }
+}
+
+/// Runtime maintenance switch for the data-plane (`/v1/*`) endpoints.
+#[derive(Debug, Default)]
+pub struct MaintenanceMode {
+    window: RwLock<Option<MaintenanceWindow>>,
+}
+
+impl MaintenanceMode {
+    /// Opens or schedules the maintenance window, replacing any other.
+    pub async fn enable(&self, settings: MaintenanceRequest) -> MaintenanceWindow {
+        let window = MaintenanceWindow {
+            started_at: settings.from.unwrap_or_else(Utc::now),
+            settings,
+        };
+        *self.window.write().await = Some(window.clone());
+        window
+    }
+
+    /// Ends or cancels maintenance, returning whether a window was set.
+    pub async fn disable(&self) -> bool {
+        self.window.write().await.take().is_some()
+    }
+
+    /// The window open or scheduled at `now`; a window whose `until` has
+    /// passed is closed.
+    pub async fn window_at(&self, now: DateTime<Utc>) -> Option<MaintenanceWindow> {
+        let window = self.window.read().await.clone()?;
+        if window.settings.until.is_some_and(|until| until <= now) {
+            let mut slot = self.window.write().await;
+            if slot.as_ref() == Some(&window) {
+                *slot = None;
+            }
+            return None;
+        }
+        Some(window)
+    }
+
+    /// The open window at `now`; a scheduled one is not open before `from`.
+    pub async fn active_at(&self, now: DateTime<Utc>) -> Option<MaintenanceWindow> {
+        self.window_at(now)
+            .await
+            .filter(|window| window.is_open_at(now))
+    }
+
+    pub async fn active(&self) -> Option<MaintenanceWindow> {
+        self.active_at(Utc::now()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_window_closes_at_until() {
+        let mode = MaintenanceMode::default();
+        let now = Utc::now();
+        mode.enable(MaintenanceRequest {
+            until: Some(now + Duration::minutes(10)),
+            ..MaintenanceRequest::default()
+        })
+        .await;
+
+        let window = mode.active_at(now).await.expect("window should be open");
+        assert_eq!(window.retry_after_secs(300, now), 600);
+        assert!(mode.active_at(now + Duration::minutes(11)).await.is_none());
+        assert!(!mode.disable().await);
+    }
+
+    #[tokio::test]
+    async fn test_window_not_started_yet() {
+        let mode = MaintenanceMode::default();
+        let now = Utc::now();
+        let from = now + Duration::minutes(5);
+        mode.enable(MaintenanceRequest {
+            from: Some(from),
+            until: Some(from + Duration::minutes(10)),
+            ..MaintenanceRequest::default()
+        })
+        .await;
+
+        assert!(mode.active_at(now).await.is_none());
+        let scheduled = mode.window_at(now).await.expect("window is scheduled");
+        assert_eq!(scheduled.started_at, from);
+        assert!(!scheduled.is_open_at(now));
+
+        let window = mode.active_at(from).await.expect("window opens at from");
+        assert_eq!(window.retry_after_secs(300, from), 600);
+        assert!(mode.active_at(from + Duration::minutes(10)).await.is_none());
+        assert!(mode.window_at(now).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_ended_window_uses_default_retry() {
+        let mode = MaintenanceMode::default();
+        mode.enable(MaintenanceRequest::default()).await;
+
+        let window = mode.active().await.expect("window should be open");
+        assert_eq!(window.retry_after_secs(300, Utc::now()), 300);
+        assert!(mode.disable().await);
+        assert!(mode.active().await.is_none());
+    }
+}
@@ -0,0 +1,375 @@
+// Request mirroring for offline evaluation.
+//
+// A sampled share of completed requests is published, together with the
+// provider's response, to a sink: a JSON Lines file, an HTTP endpoint or, with
+// the `kafka` feature, a Kafka topic. Records are anonymized (the API key name
+// and `user` field become hashes) and every string is passed through the
+// configured redaction patterns before it leaves the process.
+//
+// Mirroring is fire-and-forget: pairs go through a bounded queue to a
+// background task, so a slow or failing sink drops records instead of
+// delaying requests.
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::MirrorConfig;
+use crate::models::openai::ChatCompletionRequest;
+
+/// Replaces every match of a redaction pattern.
+pub const REDACTED: &str = "[REDACTED]";
+
+const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where mirrored records are published.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MirrorSinkKind {
+    /// Appends one JSON record per line to a file.
+    File,
+    /// POSTs each record as JSON.
+    Http,
+    /// Produces each record to a Kafka topic (needs the `kafka` feature).
+    Kafka,
+}
+
+impl MirrorSinkKind {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Http => "http",
+            Self::Kafka => "kafka",
+        }
+    }
+}
+
+/// One mirrored request/response pair, as written to the sink.
+#[derive(Debug, Serialize)]
+pub struct MirrorRecord {
+    pub id: String,
+    pub timestamp: String,
+    /// Hash of the calling API key's name.
+    pub key: String,
+    pub provider: String,
+    pub latency_ms: u64,
+    pub request: Value,
+    pub response: Value,
+}
+
+struct MirroredPair {
+    key_name: String,
+    provider: &'static str,
+    latency_ms: u64,
+    request: Value,
+    response: Value,
+}
+
+impl MirroredPair {
+    fn into_record(self, redactions: &[Regex]) -> MirrorRecord {
+        let mut request = self.request;
+        if let Some(user) = request.get_mut("user") {
+            if let Some(name) = user.as_str() {
+                *user = Value::String(anonymize(name));
+            }
+        }
+        let mut response = self.response;
+        redact(&mut request, redactions);
+        redact(&mut response, redactions);
+        MirrorRecord {
+            id: Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            key: anonymize(&self.key_name),
+            provider: self.provider.to_string(),
+            latency_ms: self.latency_ms,
+            request,
+            response,
+        }
+    }
+}
+
+/// Queues sampled pairs for the background publisher.
+///
+/// Without a sink nothing is sampled, so callers can check
+/// [`Mirror::sample`] unconditionally.
+#[derive(Default)]
+pub struct Mirror {
+    sender: Option<mpsc::Sender<MirroredPair>>,
+    sample_rate: f64,
+    dropped: AtomicU64,
+}
+
+impl Mirror {
+    /// Opens the configured sink and starts the publisher task.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a redaction pattern is invalid, the sink cannot be
+    /// opened, or Kafka is selected without the `kafka` feature.
+    pub fn from_config(config: &MirrorConfig) -> Result<Self> {
+        let Some(kind) = config.sink else {
+            return Ok(Self::default());
+        };
+        let redactions = config
+            .redact_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid mirror redaction pattern '{pattern}'"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let sink = Sink::open(kind, config)?;
+
+        let (sender, receiver) = mpsc::channel(config.queue_size);
+        tokio::spawn(publish(sink, receiver, redactions));
+        info!(
+            "Mirroring {:.0}% of requests to the {} sink",
+            config.sample_rate * 100.0,
+            kind.as_str()
+        );
+        Ok(Self {
+            sender: Some(sender),
+            sample_rate: config.sample_rate,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Decides whether the current request is mirrored.
+    #[must_use]
+    pub fn sample(&self) -> bool {
+        self.is_enabled() && random_unit() < self.sample_rate
+    }
+
+    /// Queues a completed pair without waiting; drops it when the queue is full.
+    pub fn publish(
+        &self,
+        key_name: &str,
+        provider: &'static str,
+        latency_ms: u64,
+        request: &ChatCompletionRequest,
+        response: Value,
+    ) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let request = match serde_json::to_value(request) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Failed to serialize mirrored request: {e}");
+                return;
+            }
+        };
+        let pair = MirroredPair {
+            key_name: key_name.to_string(),
+            provider,
+            latency_ms,
+            request,
+            response,
+        };
+        if sender.try_send(pair).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // Only every 100th drop is logged so a stuck sink cannot flood the logs
+            if dropped % 100 == 1 {
+                warn!("Mirror queue is full; {dropped} records dropped so far");
+            }
+        }
+    }
+
+    /// Records dropped because the queue was full.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+enum Sink {
+    File(tokio::fs::File),
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+    #[cfg(feature = "kafka")]
+    Kafka {
+        producer: rdkafka::producer::FutureProducer,
+        topic: String,
+    },
+}
+
+impl Sink {
+    fn open(kind: MirrorSinkKind, config: &MirrorConfig) -> Result<Self> {
+        match kind {
+            MirrorSinkKind::File => {
+                let path = config
+                    .path
+                    .as_deref()
+                    .context("Mirror file sink needs a path")?;
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open mirror file '{path}'"))?;
+                Ok(Self::File(tokio::fs::File::from_std(file)))
+            }
+            MirrorSinkKind::Http => {
+                let url = config.url.clone().context("Mirror HTTP sink needs a URL")?;
+                let client = reqwest::Client::builder()
+                    .timeout(SINK_TIMEOUT)
+                    .build()
+                    .context("Failed to build mirror HTTP client")?;
+                Ok(Self::Http { client, url })
+            }
+            #[cfg(feature = "kafka")]
+            MirrorSinkKind::Kafka => {
+                let brokers = config
+                    .kafka_brokers
+                    .as_deref()
+                    .context("Mirror Kafka sink needs brokers")?;
+                let topic = config
+                    .kafka_topic
+                    .clone()
+                    .context("Mirror Kafka sink needs a topic")?;
+                let producer = rdkafka::ClientConfig::new()
+                    .set("bootstrap.servers", brokers)
+                    .create()
+                    .context("Failed to create mirror Kafka producer")?;
+                Ok(Self::Kafka { producer, topic })
+            }
+            #[cfg(not(feature = "kafka"))]
+            MirrorSinkKind::Kafka => {
+                bail!("The Kafka mirror sink needs the proxy built with the `kafka` feature")
+            }
+        }
+    }
+
+    async fn write(&mut self, record: &MirrorRecord) -> Result<()> {
+        match self {
+            Self::File(file) => {
+                let mut line = serde_json::to_vec(record)?;
+                line.push(b'\n');
+                file.write_all(&line).await?;
+                file.flush().await?;
+            }
+            Self::Http { client, url } => {
+                let status = client
+                    .post(url.as_str())
+                    .json(record)
+                    .send()
+                    .await?
+                    .status();
+                if !status.is_success() {
+                    bail!("mirror endpoint returned status {status}");
+                }
+            }
+            #[cfg(feature = "kafka")]
+            Self::Kafka { producer, topic } => {
+                let payload = serde_json::to_vec(record)?;
+                let message = rdkafka::producer::FutureRecord::to(topic)
+                    .key(&record.id)
+                    .payload(&payload);
+                producer
+                    .send(message, SINK_TIMEOUT)
+                    .await
+                    .map_err(|(e, _)| e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn publish(
+    mut sink: Sink,
+    mut receiver: mpsc::Receiver<MirroredPair>,
+    redactions: Vec<Regex>,
+) {
+    while let Some(pair) = receiver.recv().await {
+        let record = pair.into_record(&redactions);
+        if let Err(e) = sink.write(&record).await {
+            warn!("Failed to mirror request: {e:#}");
+        }
+    }
+}
+
+/// Stable, non-reversible stand-in for an identifier.
+fn anonymize(value: &str) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    digest[..8].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Masks every pattern match in every string of `value`.
+fn redact(value: &mut Value, redactions: &[Regex]) {
+    match value {
+        Value::String(s) => {
+            for pattern in redactions {
+                if let std::borrow::Cow::Owned(masked) = pattern.replace_all(s, REDACTED) {
+                    *s = masked;
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| redact(v, redactions)),
+        Value::Object(fields) => fields.values_mut().for_each(|v| redact(v, redactions)),
+        _ => {}
+    }
+}
+
+/// Uniform in `[0, 1)`.
+fn random_unit() -> f64 {
+    let bits = Uuid::new_v4().as_u128() >> 75;
+    #[allow(clippy::cast_precision_loss)]
+    let unit = bits as f64 / (1u64 << 53) as f64;
+    unit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_is_anonymized_and_redacted() {
+        let redactions = vec![Regex::new(r"[\w.]+@[\w.]+").expect("pattern should compile")];
+        let pair = MirroredPair {
+            key_name: "team-a".to_string(),
+            provider: "vertex",
+            latency_ms: 12,
+            request: json!({
+                "model": "gemini-2.5-flash",
+                "user": "alice",
+                "messages": [{"role": "user", "content": "mail bob@example.com"}]
+            }),
+            response: json!({"choices": [{"message": {"content": "Sent to bob@example.com"}}]}),
+        };
+        let record = pair.into_record(&redactions);
+
+        assert_eq!(record.key, anonymize("team-a"));
+        assert_ne!(record.key, "team-a");
+        assert_eq!(record.request["user"], anonymize("alice"));
+        assert_eq!(record.request["messages"][0]["content"], "mail [REDACTED]");
+        assert_eq!(
+            record.response["choices"][0]["message"]["content"],
+            "Sent to [REDACTED]"
+        );
+        assert_eq!(record.request["model"], "gemini-2.5-flash");
+    }
+
+    #[test]
+    fn test_disabled_mirror_never_samples() {
+        let mirror = Mirror::default();
+        assert!(!mirror.is_enabled());
+        assert!(!mirror.sample());
+    }
+}
@@ -1,5 +1,49 @@
 pub mod auth;
+pub mod aws_sigv4;
+pub mod budgets;
 pub mod cache;
+pub mod capabilities;
+pub mod clock;
+pub mod debug_trace;
+pub mod doctor;
+pub mod experiments;
+pub mod fallback_responses;
+pub mod finish_reason;
 pub mod flags;
+pub mod idempotency;
+pub mod keys;
+pub mod listener;
+pub mod log_context;
+pub mod log_control;
+pub mod maintenance;
+pub mod maintenance_mode;
+pub mod mirror;
+pub mod model_catalog;
+pub mod model_policy;
+pub mod model_registry;
+pub mod notifier;
+pub mod param_policy;
+pub mod post_processor;
+pub mod prompt_templates;
 pub mod providers;
+pub mod quota;
+pub mod redaction;
+pub mod request_limits;
+pub mod routing;
+pub mod routing_rules;
+pub mod sampling;
+pub mod scheduler;
+pub mod single_flight;
+pub mod slo;
+pub mod sqlite_store;
+pub mod status_page;
+pub mod storage;
+pub mod tokenizer;
+pub mod trace_context;
+pub mod transcripts;
 pub mod transformer;
+pub mod upstream_clients;
+pub mod upstream_headers;
+pub mod usage;
+pub mod warmup;
+pub mod watermark;
@@ -0,0 +1,250 @@
+// Cached upstream model lists.
+//
+// The model registry describes the models the proxy knows about; upstreams
+// add new ones faster than it is updated. With `model_discovery.enabled`,
+// `/v1/models` also lists what Vertex reports. SDKs query `/v1/models` on
+// every startup, so the upstream list is fetched at most once per
+// `ttl_secs` and served from memory in between; concurrent callers of a
+// stale list wait for a single fetch. A failed fetch keeps serving the last
+// good list and is retried after `RETRY_AFTER_FAILURE_SECS`.
+//
+// Only Vertex lists its models. The Anthropic bridge and the harvester have
+// no model-list endpoint, so `claude-*` and `gpt-*` come from the registry
+// alone.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::config::ModelDiscoveryConfig;
+use crate::services::clock::{self, SharedClock};
+use crate::services::model_registry::{Modality, ModelInfo, ModelPricing, ModelRegistry};
+use crate::services::providers::vertex::VertexProvider;
+use crate::services::providers::ProviderResult;
+use crate::state::AppState;
+
+const RETRY_AFTER_FAILURE_SECS: u64 = 60;
+
+/// A model an upstream reports, with the token limits it states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamModel {
+    pub id: String,
+    pub context_window: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+}
+
+impl UpstreamModel {
+    /// Registry metadata for this model: its family's entry if the registry
+    /// has one, otherwise only what the upstream stated.
+    #[must_use]
+    pub fn to_model_info(&self, registry: &ModelRegistry, owned_by: &str) -> ModelInfo {
+        let mut info = registry
+            .get(&self.id)
+            .cloned()
+            .unwrap_or_else(|| ModelInfo {
+                id: String::new(),
+                owned_by: owned_by.to_string(),
+                context_window: 0,
+                max_output_tokens: 0,
+                input_modalities: vec![Modality::Text],
+                supports_streaming: true,
+                supports_tools: false,
+                pricing: ModelPricing::default(),
+                deprecated: None,
+            });
+        info.id.clone_from(&self.id);
+        if let Some(context_window) = self.context_window {
+            info.context_window = context_window;
+        }
+        if let Some(max_output_tokens) = self.max_output_tokens {
+            info.max_output_tokens = max_output_tokens;
+        }
+        info
+    }
+}
+
+#[derive(Default)]
+struct CachedList {
+    models: Vec<UpstreamModel>,
+    /// When the list may be fetched again; `None` until the first fetch.
+    refetch_at: Option<Instant>,
+}
+
+/// Upstream model lists, cached for the configured TTL.
+pub struct ModelCatalog {
+    enabled: bool,
+    ttl: Duration,
+    vertex: Mutex<CachedList>,
+    clock: SharedClock,
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::from_config(&ModelDiscoveryConfig::default())
+    }
+}
+
+impl ModelCatalog {
+    #[must_use]
+    pub fn from_config(config: &ModelDiscoveryConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            ttl: Duration::from_secs(config.ttl_secs),
+            vertex: Mutex::new(CachedList::default()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    #[must_use]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The models Vertex offers, fetched if the cached list has expired.
+    /// Empty when discovery is disabled.
+    pub async fn vertex_models(&self, state: &AppState) -> Vec<UpstreamModel> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        self.cached(&self.vertex, || VertexProvider::list_models(state))
+            .await
+    }
+
+    /// Registry models plus the upstream models the registry does not list
+    /// by exact ID, sorted by ID.
+    pub async fn list(&self, state: &AppState) -> Vec<ModelInfo> {
+        let registry = &state.model_registry;
+        let mut models: Vec<ModelInfo> = registry.list().into_iter().cloned().collect();
+        for model in self.vertex_models(state).await {
+            if !models.iter().any(|m| m.id == model.id) {
+                models.push(model.to_model_info(registry, "google"));
+            }
+        }
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        models
+    }
+
+    /// Fetches the Vertex model list now, regardless of its age, and
+    /// returns how many models it has.
+    ///
+    /// # Errors
+    ///
+    /// Returns the upstream error if the fetch fails; the cached list is
+    /// kept.
+    pub async fn refresh_vertex(&self, state: &AppState) -> ProviderResult<usize> {
+        let mut cached = self.vertex.lock().await;
+        let models = VertexProvider::list_models(state).await?;
+        let count = models.len();
+        cached.models = models;
+        cached.refetch_at = Some(self.clock.now() + self.ttl);
+        info!("Refreshed the Vertex model list: {count} model(s)");
+        Ok(count)
+    }
+
+    async fn cached<F, Fut>(&self, list: &Mutex<CachedList>, fetch: F) -> Vec<UpstreamModel>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = ProviderResult<Vec<UpstreamModel>>>,
+    {
+        // Held across the fetch, so concurrent callers wait for one request
+        let mut cached = list.lock().await;
+        let now = self.clock.now();
+        if cached.refetch_at.is_some_and(|at| now < at) {
+            return cached.models.clone();
+        }
+        match fetch().await {
+            Ok(models) => {
+                cached.models = models;
+                cached.refetch_at = Some(now + self.ttl);
+            }
+            Err(e) => {
+                warn!("Failed to fetch the upstream model list, serving the cached one: {e}");
+                let retry = self.ttl.min(Duration::from_secs(RETRY_AFTER_FAILURE_SECS));
+                cached.refetch_at = Some(now + retry);
+            }
+        }
+        cached.models.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::clock::ManualClock;
+    use crate::services::providers::ProviderError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn model(id: &str) -> UpstreamModel {
+        UpstreamModel {
+            id: id.to_string(),
+            context_window: Some(1_000),
+            max_output_tokens: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_is_fetched_once_per_ttl() {
+        let clock = Arc::new(ManualClock::new());
+        let catalog = ModelCatalog::from_config(&ModelDiscoveryConfig {
+            enabled: true,
+            ttl_secs: 600,
+        })
+        .with_clock(clock.clone());
+        let fetches = AtomicUsize::new(0);
+        let fetch = |result: ProviderResult<Vec<UpstreamModel>>| {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            async move { result }
+        };
+
+        let models = catalog
+            .cached(&catalog.vertex, || fetch(Ok(vec![model("gemini-x")])))
+            .await;
+        assert_eq!(models, vec![model("gemini-x")]);
+        let models = catalog
+            .cached(&catalog.vertex, || fetch(Ok(Vec::new())))
+            .await;
+        assert_eq!(models, vec![model("gemini-x")]);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // After expiry a failed fetch keeps the last list and retries sooner
+        clock.advance(Duration::from_secs(601));
+        let models = catalog
+            .cached(&catalog.vertex, || {
+                fetch(Err(ProviderError::Network("down".to_string())))
+            })
+            .await;
+        assert_eq!(models, vec![model("gemini-x")]);
+        clock.advance(Duration::from_secs(RETRY_AFTER_FAILURE_SECS + 1));
+        let models = catalog
+            .cached(&catalog.vertex, || fetch(Ok(vec![model("gemini-y")])))
+            .await;
+        assert_eq!(models, vec![model("gemini-y")]);
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_upstream_model_takes_family_metadata() {
+        let registry = ModelRegistry::default();
+        let info = model("gemini-2.5-flash-preview-09-2025").to_model_info(&registry, "google");
+        let family = registry
+            .get("gemini-2.5-flash")
+            .expect("family should be built in");
+        assert_eq!(info.id, "gemini-2.5-flash-preview-09-2025");
+        assert_eq!(info.context_window, 1_000);
+        assert_eq!(info.max_output_tokens, family.max_output_tokens);
+        assert_eq!(info.pricing, family.pricing);
+
+        let unknown = model("gemini-unknown").to_model_info(&registry, "google");
+        assert_eq!(unknown.owned_by, "google");
+        assert_eq!(unknown.max_output_tokens, 0);
+    }
+}
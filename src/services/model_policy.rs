@@ -0,0 +1,95 @@
+// Proxy-level allow/deny lists for model names.
+//
+// Evaluated before routing so blocked models never reach a provider. Keys named
+// in `exempt_keys` skip the lists entirely, which covers cases like denying an
+// expensive model to everyone except one team.
+
+use crate::config::ModelPolicyConfig;
+
+/// Matches a model against an exact name or a prefix pattern ending in `*`.
+#[must_use]
+pub fn matches_pattern(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => pattern == model,
+    }
+}
+
+/// Why a model was refused.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ModelDenied {
+    #[error("The model '{model}' is blocked by proxy policy (matched '{pattern}')")]
+    Denied { model: String, pattern: String },
+    #[error("The model '{model}' is not in the proxy's list of allowed models")]
+    NotAllowed { model: String },
+}
+
+/// Checks whether the key named `key` may use `model`.
+///
+/// # Errors
+///
+/// Returns `ModelDenied` when a deny pattern matches or a non-empty allow list
+/// doesn't.
+pub fn check(policy: &ModelPolicyConfig, key: &str, model: &str) -> Result<(), ModelDenied> {
+    if policy.exempt_keys.iter().any(|k| k == key) {
+        return Ok(());
+    }
+    if let Some(pattern) = policy.deny.iter().find(|p| matches_pattern(p, model)) {
+        return Err(ModelDenied::Denied {
+            model: model.to_string(),
+            pattern: pattern.clone(),
+        });
+    }
+    if !policy.allow.is_empty() && !policy.allow.iter().any(|p| matches_pattern(p, model)) {
+        return Err(ModelDenied::NotAllowed {
+            model: model.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str], exempt_keys: &[&str]) -> ModelPolicyConfig {
+        let owned = |items: &[&str]| items.iter().map(|s| (*s).to_string()).collect();
+        ModelPolicyConfig {
+            allow: owned(allow),
+            deny: owned(deny),
+            exempt_keys: owned(exempt_keys),
+        }
+    }
+
+    #[test]
+    fn test_empty_policy_allows_everything() {
+        assert_eq!(check(&ModelPolicyConfig::default(), "k", "gpt-4"), Ok(()));
+    }
+
+    #[test]
+    fn test_deny_with_exempt_key() {
+        let policy = policy(&[], &["gemini-2.5-pro"], &["research"]);
+        assert!(matches!(
+            check(&policy, "web", "gemini-2.5-pro"),
+            Err(ModelDenied::Denied { .. })
+        ));
+        assert_eq!(check(&policy, "research", "gemini-2.5-pro"), Ok(()));
+        assert_eq!(check(&policy, "web", "gemini-2.5-flash"), Ok(()));
+    }
+
+    #[test]
+    fn test_allow_list_and_prefix_patterns() {
+        let policy = policy(&["gemini-*", "claude-3-haiku"], &["gemini-2.5-pro*"], &[]);
+        assert_eq!(check(&policy, "k", "gemini-2.5-flash"), Ok(()));
+        assert_eq!(check(&policy, "k", "claude-3-haiku"), Ok(()));
+        assert!(matches!(
+            check(&policy, "k", "claude-3-opus"),
+            Err(ModelDenied::NotAllowed { .. })
+        ));
+        // Deny wins over allow
+        assert!(matches!(
+            check(&policy, "k", "gemini-2.5-pro-preview"),
+            Err(ModelDenied::Denied { .. })
+        ));
+    }
+}
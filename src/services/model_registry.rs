@@ -0,0 +1,697 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::RwLock;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Input modality a model accepts.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Modality {
+    Text,
+    Image,
+    Audio,
+    Video,
+}
+
+impl Modality {
+    /// Name as written in the model registry.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Image => "image",
+            Self::Audio => "audio",
+            Self::Video => "video",
+        }
+    }
+}
+
+/// Per-token pricing in USD per one million tokens.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, ToSchema)]
+pub struct ModelPricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+impl ModelPricing {
+    /// Cost in USD for the given token counts.
+    #[must_use]
+    pub fn cost(&self, prompt_tokens: u32, completion_tokens: u32) -> f64 {
+        (f64::from(prompt_tokens) * self.input_per_million
+            + f64::from(completion_tokens) * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Static metadata describing a model family.
+///
+/// `id` is matched against requested model names either exactly or as a
+/// prefix, so `claude-3-5-sonnet` also describes `claude-3-5-sonnet-20241022`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct ModelInfo {
+    pub id: String,
+    pub owned_by: String,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    #[serde(default = "default_modalities")]
+    pub input_modalities: Vec<Modality>,
+    #[serde(default = "default_true")]
+    pub supports_streaming: bool,
+    #[serde(default)]
+    pub supports_tools: bool,
+    #[serde(default)]
+    pub pricing: ModelPricing,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<Deprecation>,
+}
+
+/// Planned retirement of a model. Requests still succeed, with a `Warning`
+/// header, until the day after `end_of_life`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default, ToSchema)]
+pub struct Deprecation {
+    /// Model to migrate to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+    /// Last day the model is served.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_of_life: Option<NaiveDate>,
+}
+
+impl Deprecation {
+    /// Whether `today` is past the end of life.
+    #[must_use]
+    pub fn is_retired(&self, today: NaiveDate) -> bool {
+        self.end_of_life.is_some_and(|end| today > end)
+    }
+
+    /// Human-readable notice for `model`, as of `today`.
+    #[must_use]
+    pub fn notice(&self, model: &str, today: NaiveDate) -> String {
+        let mut notice = format!("Model '{model}' is deprecated");
+        match self.end_of_life {
+            Some(end) if self.is_retired(today) => {
+                notice = format!("Model '{model}' was retired after {end}");
+            }
+            Some(end) => notice.push_str(&format!(" and will be retired after {end}")),
+            None => {}
+        }
+        if let Some(replacement) = &self.replacement {
+            notice.push_str(&format!("; use '{replacement}' instead"));
+        }
+        notice
+    }
+}
+
+fn default_modalities() -> Vec<Modality> {
+    vec![Modality::Text]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+const TEXT: &[Modality] = &[Modality::Text];
+const TEXT_IMAGE: &[Modality] = &[Modality::Text, Modality::Image];
+const MULTIMODAL: &[Modality] = &[
+    Modality::Text,
+    Modality::Image,
+    Modality::Audio,
+    Modality::Video,
+];
+
+// (id, owned_by, context_window, max_output_tokens, input modalities, $/M input, $/M output)
+type BuiltinModel = (
+    &'static str,
+    &'static str,
+    u32,
+    u32,
+    &'static [Modality],
+    f64,
+    f64,
+);
+
+#[rustfmt::skip]
+const BUILTIN_MODELS: &[BuiltinModel] = &[
+    ("gemini-pro",            "google",    32_760,    8_192,  TEXT,       0.5,   1.5),
+    ("gemini-1.5-pro",        "google",    2_097_152, 8_192,  MULTIMODAL, 1.25,  5.0),
+    ("gemini-1.5-flash",      "google",    1_048_576, 8_192,  MULTIMODAL, 0.075, 0.3),
+    ("gemini-2.0-flash",      "google",    1_048_576, 8_192,  MULTIMODAL, 0.1,   0.4),
+    ("gemini-2.5-pro",        "google",    1_048_576, 65_536, MULTIMODAL, 1.25,  10.0),
+    ("gemini-2.5-flash",      "google",    1_048_576, 65_536, MULTIMODAL, 0.3,   2.5),
+    ("gemini-2.5-flash-lite", "google",    1_048_576, 65_536, MULTIMODAL, 0.1,   0.4),
+    ("claude-3-haiku",        "anthropic", 200_000,   4_096,  TEXT_IMAGE, 0.25,  1.25),
+    ("claude-3-opus",         "anthropic", 200_000,   4_096,  TEXT_IMAGE, 15.0,  75.0),
+    ("claude-3-sonnet",       "anthropic", 200_000,   4_096,  TEXT_IMAGE, 3.0,   15.0),
+    ("claude-3-5-haiku",      "anthropic", 200_000,   8_192,  TEXT,       0.8,   4.0),
+    ("claude-3-5-sonnet",     "anthropic", 200_000,   8_192,  TEXT_IMAGE, 3.0,   15.0),
+    ("claude-3-7-sonnet",     "anthropic", 200_000,   64_000, TEXT_IMAGE, 3.0,   15.0),
+    ("claude-sonnet-4",       "anthropic", 200_000,   64_000, TEXT_IMAGE, 3.0,   15.0),
+    ("claude-opus-4",         "anthropic", 200_000,   32_000, TEXT_IMAGE, 15.0,  75.0),
+    ("gpt-3.5-turbo",         "openai",    16_385,    4_096,  TEXT,       0.5,   1.5),
+    ("gpt-4",                 "openai",    8_192,     8_192,  TEXT,       30.0,  60.0),
+    ("gpt-4-turbo",           "openai",    128_000,   4_096,  TEXT_IMAGE, 10.0,  30.0),
+    ("gpt-4o",                "openai",    128_000,   16_384, TEXT_IMAGE, 2.5,   10.0),
+];
+
+// `*-latest` aliases shipped by default. Gemini `-latest` names are left alone
+// because Google's API resolves them itself.
+const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("claude-sonnet-latest", "claude-sonnet-4"),
+    ("claude-opus-latest", "claude-opus-4"),
+    ("claude-haiku-latest", "claude-3-5-haiku"),
+];
+
+// Tokens that name a release of a model rather than a different model
+const RELEASE_TAGS: &[&str] = &["latest", "preview", "exp"];
+
+/// Whether `suffix`, the rest of a model name after a registered id, marks a
+/// release of that model: a separator followed by a date, a version number
+/// (`001`, `v2`) or a release tag, as in `-20241022`, `@20240620` or
+/// `-preview-05-20`.
+fn is_variant_suffix(suffix: &str) -> bool {
+    let Some(rest) = suffix.strip_prefix(['-', '@', ':']) else {
+        return false;
+    };
+    let token = rest.split(['-', '@', ':']).next().unwrap_or_default();
+    let version = token.strip_prefix('v').unwrap_or(token);
+    (!version.is_empty() && version.bytes().all(|b| b.is_ascii_digit()))
+        || RELEASE_TAGS.contains(&token)
+}
+
+/// Why an alias could not be set.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AliasError {
+    #[error("Alias entry '{0}' must be in 'alias=target' form")]
+    Malformed(String),
+    #[error("'{0}' is a registered model and cannot be used as an alias")]
+    ShadowsModel(String),
+    #[error("Alias target '{0}' is not a known model")]
+    UnknownTarget(String),
+    #[error("Alias target '{0}' is itself an alias; point at a concrete model")]
+    ChainedTarget(String),
+}
+
+fn builtin_models() -> Vec<ModelInfo> {
+    BUILTIN_MODELS
+        .iter()
+        .map(
+            |&(id, owned_by, context_window, max_output_tokens, modalities, input, output)| {
+                ModelInfo {
+                    id: id.to_string(),
+                    owned_by: owned_by.to_string(),
+                    context_window,
+                    max_output_tokens,
+                    input_modalities: modalities.to_vec(),
+                    supports_streaming: true,
+                    supports_tools: true,
+                    pricing: ModelPricing {
+                        input_per_million: input,
+                        output_per_million: output,
+                    },
+                    deprecated: None,
+                }
+            },
+        )
+        .collect()
+}
+
+/// Lookup table of known models.
+///
+/// Starts from a built-in table and can be extended or overridden from a JSON
+/// file containing an array of `ModelInfo` objects; entries with an existing
+/// `id` replace the built-in definition.
+///
+/// Also holds version aliases (`claude-sonnet-latest` -> `claude-sonnet-4`)
+/// that requests are rewritten through before routing. Aliases can be changed
+/// at runtime, so operators roll clients forward without client changes.
+///
+/// An alias may name a group of equivalent models (`fast=gemini-2.5-flash|claude-3-5-haiku`);
+/// the first target is the primary and the routing strategy picks among the rest.
+///
+/// Context fallbacks (`gemini-2.5-flash=gemini-2.5-pro`) name a larger-context
+/// model to retry on when a prompt overflows a model's context window.
+#[derive(Debug)]
+pub struct ModelRegistry {
+    models: HashMap<String, ModelInfo>,
+    aliases: RwLock<HashMap<String, Vec<String>>>,
+    context_fallbacks: HashMap<String, String>,
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        Self::from_models(builtin_models())
+    }
+}
+
+impl ModelRegistry {
+    #[must_use]
+    pub fn from_models(models: Vec<ModelInfo>) -> Self {
+        Self {
+            models: models.into_iter().map(|m| (m.id.clone(), m)).collect(),
+            aliases: RwLock::new(
+                BUILTIN_ALIASES
+                    .iter()
+                    .map(|&(alias, target)| (alias.to_string(), vec![target.to_string()]))
+                    .collect(),
+            ),
+            context_fallbacks: HashMap::new(),
+        }
+    }
+
+    /// Builds the registry from the built-in table plus an optional overrides
+    /// file, then applies `alias=target` entries on top of the built-in aliases.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the overrides file cannot be read or parsed, or an
+    /// alias entry is invalid.
+    pub fn load(overrides_file: Option<&str>, aliases: &[String]) -> Result<Self> {
+        let mut registry = Self::default();
+        if let Some(path) = overrides_file {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read model overrides file '{path}'"))?;
+            let overrides: Vec<ModelInfo> = serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse model overrides file '{path}'"))?;
+            info!("Loaded {} model definitions from {}", overrides.len(), path);
+            registry.apply_overrides(overrides);
+        }
+        for entry in aliases {
+            let (alias, target) = entry
+                .split_once('=')
+                .ok_or_else(|| AliasError::Malformed(entry.clone()))?;
+            registry.set_alias(alias.trim(), target.trim())?;
+        }
+        Ok(registry)
+    }
+
+    /// Adds `model=fallback` context fallbacks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed entries and fallbacks that are not known models.
+    pub fn with_context_fallbacks(mut self, entries: &[String]) -> Result<Self> {
+        for entry in entries {
+            let (model, fallback) = entry
+                .split_once('=')
+                .map(|(m, f)| (m.trim(), f.trim()))
+                .filter(|(m, f)| !m.is_empty() && !f.is_empty())
+                .with_context(|| {
+                    format!("Context fallback '{entry}' must be in 'model=fallback' form")
+                })?;
+            if self.get(fallback).is_none() {
+                anyhow::bail!("Context fallback target '{fallback}' is not a known model");
+            }
+            self.context_fallbacks
+                .insert(model.to_string(), fallback.to_string());
+        }
+        Ok(self)
+    }
+
+    /// Marks models deprecated from `model[=replacement][@YYYY-MM-DD]`
+    /// entries. A model without its own entry, such as a dated variant, gets
+    /// a copy of its family's entry so the rest of the family is unaffected.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for malformed dates and for models or replacements
+    /// that are not known models.
+    pub fn with_deprecations(mut self, entries: &[String]) -> Result<Self> {
+        for entry in entries {
+            let (spec, end_of_life) = match entry.rsplit_once('@') {
+                Some((spec, date)) => {
+                    let date =
+                        NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").with_context(|| {
+                            format!("Deprecation '{entry}' has an invalid date; use YYYY-MM-DD")
+                        })?;
+                    (spec, Some(date))
+                }
+                None => (entry.as_str(), None),
+            };
+            let (model, replacement) = match spec.split_once('=') {
+                Some((model, replacement)) => (model.trim(), Some(replacement.trim())),
+                None => (spec.trim(), None),
+            };
+            let mut info = self
+                .get(model)
+                .cloned()
+                .with_context(|| format!("Deprecated model '{model}' is not a known model"))?;
+            if let Some(replacement) = replacement {
+                if self.get(replacement).is_none() {
+                    anyhow::bail!("Deprecation replacement '{replacement}' is not a known model");
+                }
+            }
+            model.clone_into(&mut info.id);
+            info.deprecated = Some(Deprecation {
+                replacement: replacement.map(str::to_string),
+                end_of_life,
+            });
+            self.models.insert(info.id.clone(), info);
+        }
+        Ok(self)
+    }
+
+    /// The model to retry on when a prompt is too long for `model`. Dated or
+    /// versioned variants use their family's fallback.
+    #[must_use]
+    pub fn context_fallback(&self, model: &str) -> Option<&str> {
+        self.context_fallbacks
+            .get(model)
+            .or_else(|| {
+                self.get(model)
+                    .and_then(|info| self.context_fallbacks.get(&info.id))
+            })
+            .map(String::as_str)
+    }
+
+    pub fn apply_overrides(&mut self, overrides: Vec<ModelInfo>) {
+        for model in overrides {
+            self.models.insert(model.id.clone(), model);
+        }
+    }
+
+    /// Looks up metadata for a requested model name.
+    ///
+    /// Falls back to the longest registered id that `model` extends with a
+    /// version or date suffix, so `claude-3-5-sonnet-20241022` and
+    /// `gemini-1.5-pro-002` resolve to their family entry while `gpt-4o` and
+    /// `gemini-pro-vision` do not resolve to `gpt-4` or `gemini-pro`.
+    #[must_use]
+    pub fn get(&self, model: &str) -> Option<&ModelInfo> {
+        self.models.get(model).or_else(|| {
+            self.models
+                .values()
+                .filter(|m| {
+                    model
+                        .strip_prefix(m.id.as_str())
+                        .is_some_and(is_variant_suffix)
+                })
+                .max_by_key(|m| m.id.len())
+        })
+    }
+
+    /// Primary model name for `model` if it is an alias.
+    #[must_use]
+    pub fn resolve_alias(&self, model: &str) -> Option<String> {
+        self.alias_targets(model)
+            .and_then(|targets| targets.into_iter().next())
+    }
+
+    /// All models `model` may be routed to if it is an alias, primary first.
+    #[must_use]
+    pub fn alias_targets(&self, model: &str) -> Option<Vec<String>> {
+        self.aliases
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(model)
+            .cloned()
+    }
+
+    /// Points `alias` at `target`, replacing any previous target. `target` may
+    /// list several models separated by `|` to form a group.
+    ///
+    /// # Errors
+    ///
+    /// Rejects aliases that shadow a registered model id, targets with no
+    /// metadata, and targets that are themselves aliases.
+    pub fn set_alias(&self, alias: &str, target: &str) -> Result<(), AliasError> {
+        let targets: Vec<String> = target.split('|').map(|t| t.trim().to_string()).collect();
+        if alias.is_empty() || targets.iter().any(String::is_empty) {
+            return Err(AliasError::Malformed(format!("{alias}={target}")));
+        }
+        if self.models.contains_key(alias) {
+            return Err(AliasError::ShadowsModel(alias.to_string()));
+        }
+        let mut aliases = self
+            .aliases
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        for target in &targets {
+            if aliases.contains_key(target) {
+                return Err(AliasError::ChainedTarget(target.clone()));
+            }
+            if self.get(target).is_none() {
+                return Err(AliasError::UnknownTarget(target.clone()));
+            }
+        }
+        aliases.insert(alias.to_string(), targets);
+        Ok(())
+    }
+
+    /// Removes an alias, returning its previous target.
+    pub fn remove_alias(&self, alias: &str) -> Option<String> {
+        self.aliases
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(alias)
+            .map(|targets| targets.join("|"))
+    }
+
+    /// All aliases and their targets, sorted by alias. Groups are `|`-separated.
+    #[must_use]
+    pub fn aliases(&self) -> BTreeMap<String, String> {
+        self.aliases
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .iter()
+            .map(|(alias, targets)| (alias.clone(), targets.join("|")))
+            .collect()
+    }
+
+    /// All registered models, sorted by id.
+    #[must_use]
+    pub fn list(&self) -> Vec<&ModelInfo> {
+        let mut models: Vec<&ModelInfo> = self.models.values().collect();
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        models
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_exact_and_prefix() {
+        let registry = ModelRegistry::default();
+
+        let exact = registry
+            .get("gemini-1.5-pro")
+            .expect("builtin should exist");
+        assert_eq!(exact.context_window, 2_097_152);
+
+        let dated = registry
+            .get("claude-3-5-sonnet-20241022")
+            .expect("dated variant should resolve to family");
+        assert_eq!(dated.id, "claude-3-5-sonnet");
+
+        // Longest prefix wins: claude-3-5-haiku must not resolve to claude-3-haiku
+        assert_eq!(
+            registry
+                .get("claude-3-5-haiku-latest")
+                .map(|m| m.id.as_str()),
+            Some("claude-3-5-haiku")
+        );
+        assert!(registry.get("unknown-model").is_none());
+
+        for (variant, family) in [
+            ("gpt-4-0613", "gpt-4"),
+            ("gpt-4o-2024-08-06", "gpt-4o"),
+            ("claude-sonnet-4@20250514", "claude-sonnet-4"),
+            ("gemini-2.5-flash-preview-05-20", "gemini-2.5-flash"),
+            ("gemini-2.0-flash-exp", "gemini-2.0-flash"),
+        ] {
+            assert_eq!(registry.get(variant).map(|m| m.id.as_str()), Some(family));
+        }
+    }
+
+    #[test]
+    fn test_prefix_of_another_family_does_not_match() {
+        let registry = ModelRegistry::default();
+        assert_eq!(
+            registry.get("gpt-4o").map(|m| m.id.as_str()),
+            Some("gpt-4o")
+        );
+        assert_eq!(
+            registry
+                .get("gpt-4-turbo-2024-04-09")
+                .map(|m| m.id.as_str()),
+            Some("gpt-4-turbo")
+        );
+        for model in [
+            "gpt-4.1",
+            "gpt-4o-mini",
+            "gpt-4-vision",
+            "gemini-pro-vision",
+            "gemini-2.5-flash-image",
+            "claude-3-5-sonnetx",
+        ] {
+            assert!(registry.get(model).is_none(), "{model} should not resolve");
+        }
+    }
+
+    #[test]
+    fn test_overrides_replace_and_extend() {
+        let mut registry = ModelRegistry::default();
+        let overrides: Vec<ModelInfo> = serde_json::from_str(
+            r#"[
+                {"id": "gemini-pro", "owned_by": "google", "context_window": 1000, "max_output_tokens": 100},
+                {"id": "custom-model", "owned_by": "me", "context_window": 4096, "max_output_tokens": 512,
+                 "pricing": {"input_per_million": 1.0, "output_per_million": 2.0}}
+            ]"#,
+        )
+        .expect("overrides should parse");
+        registry.apply_overrides(overrides);
+
+        let gemini = registry.get("gemini-pro").expect("override should exist");
+        assert_eq!(gemini.context_window, 1000);
+        assert_eq!(gemini.input_modalities, vec![Modality::Text]);
+        assert!(gemini.supports_streaming);
+
+        let custom = registry
+            .get("custom-model")
+            .expect("new model should exist");
+        assert!((custom.pricing.cost(1_000_000, 500_000) - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_load_missing_file_fails() {
+        assert!(ModelRegistry::load(Some("/nonexistent/models.json"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_aliases_resolve_and_update() {
+        let registry = ModelRegistry::load(None, &["gemini-flash-stable=gemini-2.5-flash".into()])
+            .expect("aliases should load");
+        assert_eq!(
+            registry.resolve_alias("claude-sonnet-latest").as_deref(),
+            Some("claude-sonnet-4")
+        );
+        assert_eq!(
+            registry.resolve_alias("gemini-flash-stable").as_deref(),
+            Some("gemini-2.5-flash")
+        );
+        assert_eq!(registry.resolve_alias("gemini-2.5-flash"), None);
+
+        registry
+            .set_alias("claude-sonnet-latest", "claude-3-7-sonnet")
+            .expect("roll alias back");
+        assert_eq!(
+            registry.resolve_alias("claude-sonnet-latest").as_deref(),
+            Some("claude-3-7-sonnet")
+        );
+        assert_eq!(
+            registry.remove_alias("gemini-flash-stable").as_deref(),
+            Some("gemini-2.5-flash")
+        );
+        assert_eq!(registry.resolve_alias("gemini-flash-stable"), None);
+    }
+
+    #[test]
+    fn test_invalid_aliases_rejected() {
+        let registry = ModelRegistry::default();
+        assert_eq!(
+            registry.set_alias("gemini-pro", "gemini-2.5-pro"),
+            Err(AliasError::ShadowsModel("gemini-pro".into()))
+        );
+        assert_eq!(
+            registry.set_alias("foo-latest", "no-such-model"),
+            Err(AliasError::UnknownTarget("no-such-model".into()))
+        );
+        assert_eq!(
+            registry.set_alias("foo-latest", "claude-opus-latest"),
+            Err(AliasError::ChainedTarget("claude-opus-latest".into()))
+        );
+        assert!(ModelRegistry::load(None, &["missing-equals".into()]).is_err());
+        assert!(registry.set_alias("fast", "gemini-2.5-flash|").is_err());
+    }
+
+    #[test]
+    fn test_context_fallbacks() {
+        let registry = ModelRegistry::default()
+            .with_context_fallbacks(&["gemini-2.5-flash = gemini-2.5-pro".into()])
+            .expect("fallback should load");
+        assert_eq!(
+            registry.context_fallback("gemini-2.5-flash"),
+            Some("gemini-2.5-pro")
+        );
+        assert_eq!(
+            registry.context_fallback("gemini-2.5-flash-001"),
+            Some("gemini-2.5-pro")
+        );
+        assert_eq!(registry.context_fallback("gemini-2.5-pro"), None);
+
+        assert!(ModelRegistry::default()
+            .with_context_fallbacks(&["gemini-2.5-flash".into()])
+            .is_err());
+        assert!(ModelRegistry::default()
+            .with_context_fallbacks(&["gemini-2.5-flash=no-such-model".into()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_deprecations() {
+        let registry = ModelRegistry::default()
+            .with_deprecations(&[
+                "gemini-1.5-pro=gemini-2.5-pro@2026-06-30".into(),
+                "claude-3-5-sonnet-20240620".into(),
+            ])
+            .expect("deprecations should load");
+
+        let deprecation = registry
+            .get("gemini-1.5-pro-002")
+            .and_then(|m| m.deprecated.clone())
+            .expect("family variants inherit the deprecation");
+        assert_eq!(deprecation.replacement.as_deref(), Some("gemini-2.5-pro"));
+        let end = NaiveDate::from_ymd_opt(2026, 6, 30).expect("valid date");
+        assert!(!deprecation.is_retired(end));
+        assert!(deprecation.is_retired(end.succ_opt().expect("valid date")));
+        assert_eq!(
+            deprecation.notice("gemini-1.5-pro", end),
+            "Model 'gemini-1.5-pro' is deprecated and will be retired after 2026-06-30; use 'gemini-2.5-pro' instead"
+        );
+
+        // A dated variant is deprecated on its own
+        assert!(registry
+            .get("claude-3-5-sonnet-20240620")
+            .is_some_and(|m| m.deprecated.is_some()));
+        assert!(registry
+            .get("claude-3-5-sonnet-20241022")
+            .is_some_and(|m| m.deprecated.is_none()));
+
+        for entry in [
+            "no-such-model",
+            "gemini-1.5-pro=no-such-model",
+            "gemini-1.5-pro@30/06/2026",
+        ] {
+            assert!(ModelRegistry::default()
+                .with_deprecations(&[entry.into()])
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_alias_groups() {
+        let registry =
+            ModelRegistry::load(None, &["fast=gemini-2.5-flash | claude-3-5-haiku".into()])
+                .expect("group alias should load");
+        assert_eq!(
+            registry.alias_targets("fast"),
+            Some(vec![
+                "gemini-2.5-flash".to_string(),
+                "claude-3-5-haiku".to_string()
+            ])
+        );
+        assert_eq!(
+            registry.resolve_alias("fast").as_deref(),
+            Some("gemini-2.5-flash")
+        );
+        assert_eq!(
+            registry.aliases().get("fast").map(String::as_str),
+            Some("gemini-2.5-flash|claude-3-5-haiku")
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use reqwest::Client;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::{AlertsConfig, SloConfig};
+use crate::openai::circuit_breaker::CircuitBreaker;
+use crate::openai::metrics::Metrics;
+use crate::services::slo::Slos;
+
+const WEBHOOK_TIMEOUT_SECS: u64 = 5;
+
+/// Significant operational events worth paging someone about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertEvent {
+    CircuitOpened,
+    ProviderDown {
+        minutes: u64,
+    },
+    ErrorRateHigh {
+        rate: f64,
+        requests: u64,
+    },
+    BudgetExceeded {
+        key: String,
+        period: String,
+    },
+    WafSessionCooledDown {
+        session: String,
+        blocks: usize,
+    },
+    SloBurnRateHigh {
+        model: String,
+        burn_rate: f64,
+        compliance: f64,
+    },
+}
+
+impl AlertEvent {
+    /// Stable identifier used for dedup; events with the same key share a cooldown.
+    #[must_use]
+    pub fn dedup_key(&self) -> String {
+        match self {
+            Self::CircuitOpened => "circuit_opened".to_string(),
+            Self::ProviderDown { .. } => "provider_down".to_string(),
+            Self::ErrorRateHigh { .. } => "error_rate_high".to_string(),
+            Self::BudgetExceeded { key, period } => format!("budget_exceeded:{key}:{period}"),
+            Self::WafSessionCooledDown { session, .. } => format!("waf_cooldown:{session}"),
+            Self::SloBurnRateHigh { model, .. } => format!("slo_burn_rate:{model}"),
+        }
+    }
+
+    #[must_use]
+    pub fn message(&self) -> String {
+        match self {
+            Self::CircuitOpened => {
+                "Circuit breaker opened: upstream requests are being rejected".to_string()
+            }
+            Self::ProviderDown { minutes } => {
+                format!("Upstream provider unavailable for {minutes} minutes (circuit still open)")
+            }
+            Self::ErrorRateHigh { rate, requests } => format!(
+                "Error rate {:.1}% over the last {requests} requests",
+                rate * 100.0
+            ),
+            Self::BudgetExceeded { key, period } => {
+                format!("{period} budget exhausted for key '{key}'")
+            }
+            Self::WafSessionCooledDown { session, blocks } => format!(
+                "ChatGPT WAF blocked session {session} {blocks} times; cooling it down and requesting a fresh session"
+            ),
+            Self::SloBurnRateHigh {
+                model,
+                burn_rate,
+                compliance,
+            } => format!(
+                "SLO for {model} is burning its error budget at {burn_rate:.1}x ({:.2}% of requests good)",
+                compliance * 100.0
+            ),
+        }
+    }
+}
+
+/// Sends alert webhooks with per-event cooldown.
+///
+/// With no webhook URLs configured every call is a no-op, so callers can
+/// notify unconditionally.
+pub struct Notifier {
+    client: Client,
+    webhook_urls: Vec<String>,
+    cooldown: Duration,
+    last_sent: Mutex<HashMap<String, Instant>>,
+}
+
+impl Notifier {
+    #[must_use]
+    pub fn new(webhook_urls: Vec<String>, cooldown: Duration) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to build webhook client, using defaults: {e}");
+                Client::new()
+            });
+        Self {
+            client,
+            webhook_urls,
+            cooldown,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn from_config(config: &AlertsConfig) -> Self {
+        Self::new(
+            config.webhook_urls.clone(),
+            Duration::from_secs(config.cooldown_secs),
+        )
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        !self.webhook_urls.is_empty()
+    }
+
+    // Returns true if the event is outside its cooldown and marks it as sent.
+    fn should_send(&self, event: &AlertEvent) -> bool {
+        let mut last_sent = self
+            .last_sent
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let key = event.dedup_key();
+        let now = Instant::now();
+        if last_sent
+            .get(&key)
+            .is_some_and(|at| now.duration_since(*at) < self.cooldown)
+        {
+            return false;
+        }
+        last_sent.insert(key, now);
+        true
+    }
+
+    /// Posts `event` to every webhook unless an identical alert fired within the cooldown.
+    ///
+    /// Delivery happens in the background; failures are logged and not retried.
+    /// Returns whether the alert was dispatched.
+    pub fn notify(&self, event: &AlertEvent) -> bool {
+        if !self.is_enabled() || !self.should_send(event) {
+            return false;
+        }
+
+        let message = event.message();
+        info!("Dispatching alert: {}", message);
+        let payload = json!({
+            "text": format!("[vertex-bridge] {message}"),
+            "event": event.dedup_key(),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+        for url in &self.webhook_urls {
+            let client = self.client.clone();
+            let url = url.clone();
+            let payload = payload.clone();
+            tokio::spawn(async move {
+                match client.post(&url).json(&payload).send().await {
+                    Ok(resp) if resp.status().is_success() => {}
+                    Ok(resp) => warn!("Alert webhook returned HTTP {}", resp.status()),
+                    Err(e) => warn!("Alert webhook delivery failed: {}", e),
+                }
+            });
+        }
+        true
+    }
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::from_config(&AlertsConfig::default())
+    }
+}
+
+/// Watches breaker state, request metrics and SLOs, raising alerts on transitions.
+struct AlertMonitor {
+    notifier: Arc<Notifier>,
+    circuit_breaker: Arc<CircuitBreaker>,
+    metrics: Arc<Metrics>,
+    slos: Arc<Slos>,
+    burn_rate_threshold: f64,
+    slo_min_requests: u64,
+    provider_down_after: Duration,
+    error_rate_threshold: f64,
+    error_rate_min_requests: u64,
+    open_since: Option<Instant>,
+    last_totals: (u64, u64),
+}
+
+impl AlertMonitor {
+    async fn check(&mut self) {
+        if self.circuit_breaker.is_open().await {
+            let since = *self.open_since.get_or_insert_with(|| {
+                self.notifier.notify(&AlertEvent::CircuitOpened);
+                Instant::now()
+            });
+            if since.elapsed() >= self.provider_down_after {
+                self.notifier.notify(&AlertEvent::ProviderDown {
+                    minutes: since.elapsed().as_secs() / 60,
+                });
+            }
+        } else {
+            self.open_since = None;
+        }
+
+        let stats = self.metrics.get_stats().await;
+        let (prev_total, prev_failed) = self.last_totals;
+        let requests = stats.total_requests.saturating_sub(prev_total);
+        if requests >= self.error_rate_min_requests {
+            let failed = stats.failed_requests.saturating_sub(prev_failed);
+            #[allow(clippy::cast_precision_loss)]
+            let rate = failed as f64 / requests as f64;
+            if rate > self.error_rate_threshold {
+                self.notifier
+                    .notify(&AlertEvent::ErrorRateHigh { rate, requests });
+            }
+            self.last_totals = (stats.total_requests, stats.failed_requests);
+        }
+
+        for status in self.slos.report() {
+            if status.requests >= self.slo_min_requests
+                && status.burn_rate > self.burn_rate_threshold
+            {
+                self.notifier.notify(&AlertEvent::SloBurnRateHigh {
+                    model: status.model,
+                    burn_rate: status.burn_rate,
+                    compliance: status.compliance,
+                });
+            }
+        }
+    }
+}
+
+/// Spawns the background alert monitor. Does nothing useful (but is cheap)
+/// when no webhooks are configured.
+#[must_use]
+pub fn spawn_alert_monitor(
+    notifier: Arc<Notifier>,
+    circuit_breaker: Arc<CircuitBreaker>,
+    metrics: Arc<Metrics>,
+    slos: Arc<Slos>,
+    config: &AlertsConfig,
+    slo: &SloConfig,
+) -> JoinHandle<()> {
+    let interval = Duration::from_secs(config.check_interval_secs);
+    let mut monitor = AlertMonitor {
+        notifier,
+        circuit_breaker,
+        metrics,
+        slos,
+        burn_rate_threshold: slo.burn_rate_threshold,
+        slo_min_requests: slo.min_requests,
+        provider_down_after: Duration::from_secs(config.provider_down_minutes * 60),
+        error_rate_threshold: config.error_rate_threshold,
+        error_rate_min_requests: config.error_rate_min_requests,
+        open_since: None,
+        last_totals: (0, 0),
+    };
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            monitor.check().await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_notify_posts_once_within_cooldown() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let notifier = Notifier::new(
+            vec![format!("{}/hook", server.uri())],
+            Duration::from_secs(60),
+        );
+        assert!(notifier.notify(&AlertEvent::CircuitOpened));
+        assert!(!notifier.notify(&AlertEvent::CircuitOpened));
+        // A different event has its own cooldown
+        assert!(notifier.notify(&AlertEvent::BudgetExceeded {
+            key: "k".to_string(),
+            period: "Daily".to_string(),
+        }));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let received = server
+            .received_requests()
+            .await
+            .expect("requests should be recorded");
+        let circuit_alerts = received
+            .iter()
+            .filter(|r| {
+                serde_json::from_slice::<serde_json::Value>(&r.body)
+                    .is_ok_and(|v| v["event"] == "circuit_opened")
+            })
+            .count();
+        assert_eq!(circuit_alerts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_monitor_raises_circuit_and_error_rate_alerts() {
+        let notifier = Arc::new(Notifier::new(
+            vec!["http://127.0.0.1:9/unused".to_string()],
+            Duration::from_secs(60),
+        ));
+        let circuit_breaker = Arc::new(CircuitBreaker::new(1, 60, 1));
+        let metrics = Arc::new(Metrics::new());
+        let mut monitor = AlertMonitor {
+            notifier: Arc::clone(&notifier),
+            circuit_breaker: Arc::clone(&circuit_breaker),
+            metrics: Arc::clone(&metrics),
+            slos: Arc::default(),
+            burn_rate_threshold: 14.4,
+            slo_min_requests: 20,
+            provider_down_after: Duration::from_secs(3600),
+            error_rate_threshold: 0.5,
+            error_rate_min_requests: 2,
+            open_since: None,
+            last_totals: (0, 0),
+        };
+
+        let _: Result<(), crate::services::providers::ProviderError> = circuit_breaker
+            .call(async {
+                Err(crate::services::providers::ProviderError::Internal(
+                    "x".into(),
+                ))
+            })
+            .await;
+        metrics.record_request(false).await;
+        metrics.record_request(false).await;
+
+        monitor.check().await;
+        assert!(monitor.open_since.is_some());
+        // Both alerts were sent, so they are now cooling down
+        assert!(!notifier.notify(&AlertEvent::CircuitOpened));
+        assert!(!notifier.notify(&AlertEvent::ErrorRateHigh {
+            rate: 1.0,
+            requests: 2
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_monitor_raises_slo_burn_rate_alert() {
+        let notifier = Arc::new(Notifier::new(
+            vec!["http://127.0.0.1:9/unused".to_string()],
+            Duration::from_secs(60),
+        ));
+        let slos = Arc::new(Slos::from_config(&SloConfig {
+            objectives: vec!["gemini-2.5-pro=99".to_string()],
+            ..SloConfig::default()
+        }));
+        let mut monitor = AlertMonitor {
+            notifier: Arc::clone(&notifier),
+            circuit_breaker: Arc::new(CircuitBreaker::new(1, 60, 1)),
+            metrics: Arc::new(Metrics::new()),
+            slos: Arc::clone(&slos),
+            burn_rate_threshold: 10.0,
+            slo_min_requests: 4,
+            provider_down_after: Duration::from_secs(3600),
+            error_rate_threshold: 0.5,
+            error_rate_min_requests: 100,
+            open_since: None,
+            last_totals: (0, 0),
+        };
+        let status = axum::http::StatusCode::OK;
+        for _ in 0..3 {
+            slos.record("gemini-2.5-pro", status, Duration::ZERO);
+        }
+        slos.record(
+            "gemini-2.5-pro",
+            axum::http::StatusCode::BAD_GATEWAY,
+            Duration::ZERO,
+        );
+
+        monitor.check().await;
+        let alert = AlertEvent::SloBurnRateHigh {
+            model: "gemini-2.5-pro".to_string(),
+            burn_rate: 25.0,
+            compliance: 0.75,
+        };
+        // Sent by the monitor, so now cooling down
+        assert!(!notifier.notify(&alert));
+    }
+}
@@ -0,0 +1,245 @@
+// Per-key generation parameter policy, applied before routing.
+//
+// Keys in the keys file may carry a `params` object. Ceilings clamp requested
+// values down rather than rejecting the request, so existing clients keep
+// working; every clamp is reported back in the `X-Parameter-Adjustments`
+// response header so callers can see what was changed. The same header
+// reports `max_tokens` clamped to the model's output limit or the proxy-wide
+// `limits.max_output_tokens`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::openai::{ChatCompletionRequest, DEFAULT_TEMPERATURE, DEFAULT_TOP_P};
+
+/// Generation parameter limits and defaults for one key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParamPolicy {
+    /// Ceiling for `temperature`.
+    #[serde(default)]
+    pub max_temperature: Option<f32>,
+    /// Ceiling for `top_p`.
+    #[serde(default)]
+    pub max_top_p: Option<f32>,
+    /// Ceiling for `max_tokens`; also applied when the request omits it.
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// `max_tokens` used when the request omits it (still capped by `max_tokens`).
+    #[serde(default)]
+    pub default_max_tokens: Option<u32>,
+}
+
+impl ParamPolicy {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A requested parameter value that was clamped by the key's policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adjustment {
+    pub param: &'static str,
+    pub requested: String,
+    pub applied: String,
+}
+
+impl std::fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}={} (requested {})",
+            self.param, self.applied, self.requested
+        )
+    }
+}
+
+/// Applies defaults and clamps to `req`, returning the clamps that changed a
+/// value the client sent. Filling in an omitted `max_tokens` is not reported.
+pub fn apply(policy: &ParamPolicy, req: &mut ChatCompletionRequest) -> Vec<Adjustment> {
+    let mut adjustments = Vec::new();
+
+    // Omitted values are checked as OpenAI's defaults, which most providers apply
+    if let Some(max) = policy.max_temperature {
+        let requested = req.temperature.unwrap_or(DEFAULT_TEMPERATURE);
+        if requested > max {
+            adjustments.push(Adjustment {
+                param: "temperature",
+                requested: requested.to_string(),
+                applied: max.to_string(),
+            });
+            req.temperature = Some(max);
+        }
+    }
+    if let Some(max) = policy.max_top_p {
+        let requested = req.top_p.unwrap_or(DEFAULT_TOP_P);
+        if requested > max {
+            adjustments.push(Adjustment {
+                param: "top_p",
+                requested: requested.to_string(),
+                applied: max.to_string(),
+            });
+            req.top_p = Some(max);
+        }
+    }
+
+    match (req.max_tokens, policy.max_tokens) {
+        (Some(requested), Some(max)) if requested > max => {
+            adjustments.push(Adjustment {
+                param: "max_tokens",
+                requested: requested.to_string(),
+                applied: max.to_string(),
+            });
+            req.max_tokens = Some(max);
+        }
+        (None, cap) => {
+            req.max_tokens = match (policy.default_max_tokens, cap) {
+                (Some(default), Some(cap)) => Some(default.min(cap)),
+                (default, cap) => default.or(cap),
+            };
+        }
+        _ => {}
+    }
+
+    adjustments
+}
+
+/// Clamps a requested `max_tokens` to `model_max` (the model's output limit
+/// from the registry) and `global_max`, whichever is lower. An omitted
+/// `max_tokens` is left for the provider to default.
+pub fn clamp_max_tokens(
+    req: &mut ChatCompletionRequest,
+    model_max: Option<u32>,
+    global_max: Option<u32>,
+) -> Option<Adjustment> {
+    let requested = req.max_tokens?;
+    let cap = match (model_max.filter(|&m| m > 0), global_max) {
+        (Some(model), Some(global)) => model.min(global),
+        (model, global) => model.or(global)?,
+    };
+    if requested <= cap {
+        return None;
+    }
+    req.max_tokens = Some(cap);
+    Some(Adjustment {
+        param: "max_tokens",
+        requested: requested.to_string(),
+        applied: cap.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::{ChatMessage, Role};
+
+    fn request(temperature: f32, max_tokens: Option<u32>) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "gemini-2.5-flash".to_string(),
+            messages: vec![ChatMessage {
+                role: Role::User,
+                content: "hi".to_string(),
+                name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+            stream: false,
+            temperature: Some(temperature),
+            top_p: Some(1.0),
+            top_k: None,
+            max_tokens,
+            stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_clamps_are_reported() {
+        let policy = ParamPolicy {
+            max_temperature: Some(0.7),
+            max_top_p: Some(0.9),
+            max_tokens: Some(2048),
+            default_max_tokens: None,
+        };
+        let mut req = request(1.2, Some(4096));
+        let adjustments = apply(&policy, &mut req);
+
+        assert!((req.temperature.unwrap_or_default() - 0.7).abs() < f32::EPSILON);
+        assert!((req.top_p.unwrap_or_default() - 0.9).abs() < f32::EPSILON);
+        assert_eq!(req.max_tokens, Some(2048));
+        let rendered: Vec<String> = adjustments.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rendered,
+            [
+                "temperature=0.7 (requested 1.2)",
+                "top_p=0.9 (requested 1)",
+                "max_tokens=2048 (requested 4096)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_values_within_limits_untouched() {
+        let policy = ParamPolicy {
+            max_temperature: Some(0.7),
+            max_tokens: Some(2048),
+            ..ParamPolicy::default()
+        };
+        let mut req = request(0.2, Some(100));
+        assert!(apply(&policy, &mut req).is_empty());
+        assert!((req.temperature.unwrap_or_default() - 0.2).abs() < f32::EPSILON);
+        assert_eq!(req.max_tokens, Some(100));
+    }
+
+    #[test]
+    fn test_default_max_tokens_when_omitted() {
+        let policy = ParamPolicy {
+            max_tokens: Some(2048),
+            default_max_tokens: Some(512),
+            ..ParamPolicy::default()
+        };
+        let mut req = request(0.5, None);
+        assert!(apply(&policy, &mut req).is_empty());
+        assert_eq!(req.max_tokens, Some(512));
+
+        // The cap alone also bounds an omitted max_tokens
+        let policy = ParamPolicy {
+            max_tokens: Some(2048),
+            ..ParamPolicy::default()
+        };
+        let mut req = request(0.5, None);
+        apply(&policy, &mut req);
+        assert_eq!(req.max_tokens, Some(2048));
+    }
+
+    #[test]
+    fn test_max_tokens_clamped_to_model_and_global_limits() {
+        let mut req = request(0.5, Some(1_000_000));
+        let adjustment = clamp_max_tokens(&mut req, Some(65_536), Some(8_192));
+        assert_eq!(
+            adjustment.map(|a| a.to_string()).as_deref(),
+            Some("max_tokens=8192 (requested 1000000)")
+        );
+        assert_eq!(req.max_tokens, Some(8_192));
+
+        let mut req = request(0.5, Some(100_000));
+        assert!(clamp_max_tokens(&mut req, Some(65_536), None).is_some());
+        assert_eq!(req.max_tokens, Some(65_536));
+
+        // Within limits, omitted, or no known limit: nothing changes
+        let mut req = request(0.5, Some(1_000));
+        assert!(clamp_max_tokens(&mut req, Some(65_536), Some(8_192)).is_none());
+        let mut req = request(0.5, None);
+        assert!(clamp_max_tokens(&mut req, Some(65_536), Some(8_192)).is_none());
+        assert_eq!(req.max_tokens, None);
+        let mut req = request(0.5, Some(1_000_000));
+        assert!(clamp_max_tokens(&mut req, Some(0), None).is_none());
+    }
+}
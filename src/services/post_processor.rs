@@ -0,0 +1,268 @@
+// Response post-processing through an external webhook.
+//
+// Completed responses are POSTed as `{"request": ..., "response": ...}`. The
+// webhook replies `200` with the response to return (mutated or with extra
+// fields added) or `204` to leave it unchanged. Any other outcome, including
+// exceeding the timeout, is handled according to the configured failure policy.
+// Streaming responses are only post-processed when enabled, since they have to
+// be buffered and reassembled first.
+
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::PostProcessConfig;
+use crate::models::openai::{ChatCompletionChunk, ChatCompletionRequest};
+
+/// What happens when the webhook fails or times out.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PostProcessFailurePolicy {
+    /// Return the unprocessed response.
+    #[default]
+    PassThrough,
+    /// Fail the request.
+    Reject,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PostProcessError {
+    #[error("Post-processing webhook timed out after {0} ms")]
+    Timeout(u128),
+    #[error("Post-processing webhook request failed: {0}")]
+    Request(String),
+    #[error("Post-processing webhook returned status {0}")]
+    Status(u16),
+    #[error("Post-processing webhook returned an invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+/// Sends completed responses to the configured webhook.
+///
+/// Without a webhook URL every call returns the response untouched, so callers
+/// can post-process unconditionally.
+pub struct PostProcessor {
+    client: Client,
+    webhook_url: Option<String>,
+    timeout: Duration,
+    on_failure: PostProcessFailurePolicy,
+    streams: bool,
+}
+
+impl Default for PostProcessor {
+    fn default() -> Self {
+        Self::from_config(&PostProcessConfig::default())
+    }
+}
+
+impl PostProcessor {
+    #[must_use]
+    pub fn from_config(config: &PostProcessConfig) -> Self {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_else(|e| {
+                warn!("Failed to build post-processing client, using defaults: {e}");
+                Client::new()
+            });
+        Self {
+            client,
+            webhook_url: config.webhook_url.clone(),
+            timeout,
+            on_failure: config.on_failure,
+            streams: config.streams,
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.webhook_url.is_some()
+    }
+
+    /// Whether streaming responses should be buffered and post-processed.
+    #[must_use]
+    pub fn applies_to_streams(&self) -> bool {
+        self.is_enabled() && self.streams
+    }
+
+    /// Runs `response` through the webhook.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only when the webhook fails and the failure policy is
+    /// `reject`; with `pass_through` the original response is returned instead.
+    pub async fn apply(
+        &self,
+        request: &ChatCompletionRequest,
+        response: Value,
+    ) -> Result<Value, PostProcessError> {
+        let Some(url) = &self.webhook_url else {
+            return Ok(response);
+        };
+        match self.call(url, request, &response).await {
+            Ok(Some(processed)) => {
+                info!("Post-processing webhook rewrote the response");
+                Ok(processed)
+            }
+            Ok(None) => Ok(response),
+            Err(e) if self.on_failure == PostProcessFailurePolicy::PassThrough => {
+                warn!("{e}; returning the unprocessed response");
+                Ok(response)
+            }
+            Err(e) => {
+                warn!("{e}; rejecting the request");
+                Err(e)
+            }
+        }
+    }
+
+    async fn call(
+        &self,
+        url: &str,
+        request: &ChatCompletionRequest,
+        response: &Value,
+    ) -> Result<Option<Value>, PostProcessError> {
+        let reply = self
+            .client
+            .post(url)
+            .json(&json!({ "request": request, "response": response }))
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    PostProcessError::Timeout(self.timeout.as_millis())
+                } else {
+                    PostProcessError::Request(e.to_string())
+                }
+            })?;
+        match reply.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            StatusCode::OK => {
+                let processed: Value = reply.json().await.map_err(|e| {
+                    if e.is_timeout() {
+                        PostProcessError::Timeout(self.timeout.as_millis())
+                    } else {
+                        PostProcessError::InvalidResponse(e.to_string())
+                    }
+                })?;
+                if !processed.get("choices").is_some_and(Value::is_array) {
+                    return Err(PostProcessError::InvalidResponse(
+                        "expected a chat completion object with a 'choices' array".to_string(),
+                    ));
+                }
+                Ok(Some(processed))
+            }
+            status => Err(PostProcessError::Status(status.as_u16())),
+        }
+    }
+}
+
+/// Builds a `chat.completion` object from the raw SSE chunks of a stream.
+#[must_use]
+pub fn reassemble_stream(chunks: &[String]) -> Value {
+    let mut id = String::new();
+    let mut created = 0;
+    let mut model = String::new();
+    let mut content = String::new();
+    let mut finish_reason = None;
+
+    // Chunks are joined first because a network read can split an SSE line
+    let raw = chunks.concat();
+    let data = raw
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("data: "))
+        .filter(|data| *data != "[DONE]");
+    for chunk in data.filter_map(|data| serde_json::from_str::<ChatCompletionChunk>(data).ok()) {
+        if id.is_empty() {
+            id = chunk.id;
+            created = chunk.created;
+            model = chunk.model;
+        }
+        if let Some(choice) = chunk.choices.first() {
+            if let Some(delta) = &choice.delta.content {
+                content.push_str(delta);
+            }
+            if let Some(reason) = &choice.finish_reason {
+                finish_reason = Some(reason.clone());
+            }
+        }
+    }
+
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": finish_reason,
+        }],
+        "usage": null,
+    })
+}
+
+/// Turns a (post-processed) `chat.completion` object back into a single
+/// `chat.completion.chunk`, keeping any fields the webhook added.
+#[must_use]
+pub fn into_chunk(mut response: Value) -> Value {
+    if let Some(object) = response.as_object_mut() {
+        object.insert("object".to_string(), json!("chat.completion.chunk"));
+        object.remove("usage");
+        if let Some(Value::Array(choices)) = object.get_mut("choices") {
+            for choice in choices.iter_mut().filter_map(Value::as_object_mut) {
+                let message = choice
+                    .remove("message")
+                    .unwrap_or(Value::Object(Map::new()));
+                choice.insert("delta".to_string(), message);
+            }
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reassemble_stream_and_back_to_chunk() {
+        let chunks = vec![
+            "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":7,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n".to_string(),
+            "data: {\"id\":\"c1\",\"object\":\"chat.completion.chunk\",\"created\":7,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo\"},\"finish_reason\":\"stop\"}]}\n\n".to_string(),
+            "data: [DONE]\n\n".to_string(),
+        ];
+        let response = reassemble_stream(&chunks);
+        assert_eq!(response["id"], "c1");
+        assert_eq!(response["choices"][0]["message"]["content"], "Hello");
+        assert_eq!(response["choices"][0]["finish_reason"], "stop");
+
+        let mut annotated = response;
+        annotated["moderation"] = json!({"flagged": false});
+        let chunk = into_chunk(annotated);
+        assert_eq!(chunk["object"], "chat.completion.chunk");
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "Hello");
+        assert!(chunk["choices"][0].get("message").is_none());
+        assert_eq!(chunk["moderation"]["flagged"], false);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_processor_passes_response_through() {
+        let processor = PostProcessor::default();
+        assert!(!processor.is_enabled());
+        let request: ChatCompletionRequest =
+            serde_json::from_value(json!({"model": "m", "messages": []}))
+                .expect("request should parse");
+        let response = json!({"choices": []});
+        assert_eq!(
+            processor
+                .apply(&request, response.clone())
+                .await
+                .expect("disabled processor never fails"),
+            response
+        );
+    }
+}
@@ -0,0 +1,256 @@
+// Named prompt templates expanded by the proxy.
+//
+// A request carrying `"prompt_template": "support_bot"` has the template's
+// messages placed ahead of its own, with `{{name}}` placeholders filled from
+// the request's `variables`. Templates come from a JSON file and can be edited
+// at runtime through the admin API, so thin clients never embed prompts.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::RwLock;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::models::openai::ChatMessage;
+
+/// Messages that make up a named prompt.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PromptTemplate {
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Why a template could not be stored or expanded.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("No prompt template named '{0}'")]
+    Unknown(String),
+    #[error("Prompt template '{0}' has no messages")]
+    Empty(String),
+    #[error("Prompt template '{0}' has an unterminated '{{{{' placeholder")]
+    Unterminated(String),
+    #[error("Prompt template '{template}' needs variable '{variable}'")]
+    MissingVariable { template: String, variable: String },
+}
+
+/// Replaces `{{name}}` placeholders in `text`; `lookup` returns a variable's value.
+fn substitute(
+    template: &str,
+    text: &str,
+    mut lookup: impl FnMut(&str) -> Option<String>,
+) -> Result<String, TemplateError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .map(|i| start + i)
+            .ok_or_else(|| TemplateError::Unterminated(template.to_string()))?;
+        let name = rest[start + 2..end].trim();
+        let value = lookup(name).ok_or_else(|| TemplateError::MissingVariable {
+            template: template.to_string(),
+            variable: name.to_string(),
+        })?;
+        out.push_str(&value);
+        rest = &rest[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn variable_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Named prompt templates, shared by all requests.
+#[derive(Debug, Default)]
+pub struct PromptTemplateStore {
+    templates: RwLock<HashMap<String, PromptTemplate>>,
+}
+
+impl PromptTemplateStore {
+    /// Loads templates from an optional JSON file mapping names to templates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or a template is
+    /// empty or malformed.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let store = Self::default();
+        let Some(path) = path else {
+            return Ok(store);
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read prompt templates file '{path}'"))?;
+        let templates: HashMap<String, PromptTemplate> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse prompt templates file '{path}'"))?;
+        info!("Loaded {} prompt templates from {}", templates.len(), path);
+        for (name, template) in templates {
+            store.set(&name, template)?;
+        }
+        Ok(store)
+    }
+
+    /// Adds or replaces a template.
+    ///
+    /// # Errors
+    ///
+    /// Rejects templates without messages or with an unterminated placeholder.
+    pub fn set(&self, name: &str, template: PromptTemplate) -> Result<(), TemplateError> {
+        if template.messages.is_empty() {
+            return Err(TemplateError::Empty(name.to_string()));
+        }
+        for message in &template.messages {
+            substitute(name, &message.content, |_| Some(String::new()))?;
+        }
+        self.templates
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(name.to_string(), template);
+        Ok(())
+    }
+
+    /// Removes a template, returning it if it existed.
+    pub fn remove(&self, name: &str) -> Option<PromptTemplate> {
+        self.templates
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(name)
+    }
+
+    /// All templates, sorted by name.
+    #[must_use]
+    pub fn list(&self) -> BTreeMap<String, PromptTemplate> {
+        self.templates
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .iter()
+            .map(|(name, template)| (name.clone(), template.clone()))
+            .collect()
+    }
+
+    /// The messages of template `name` with its placeholders filled in.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Unknown` for a missing template and `MissingVariable` when a
+    /// placeholder has no value in `variables`.
+    pub fn render(
+        &self,
+        name: &str,
+        variables: &HashMap<String, Value>,
+    ) -> Result<Vec<ChatMessage>, TemplateError> {
+        let templates = self
+            .templates
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let template = templates
+            .get(name)
+            .ok_or_else(|| TemplateError::Unknown(name.to_string()))?;
+        template
+            .messages
+            .iter()
+            .map(|message| {
+                Ok(ChatMessage {
+                    content: substitute(name, &message.content, |var| {
+                        variables.get(var).map(variable_text)
+                    })?,
+                    ..message.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::Role;
+
+    fn template(contents: &[(Role, &str)]) -> PromptTemplate {
+        PromptTemplate {
+            messages: contents
+                .iter()
+                .map(|(role, content)| ChatMessage {
+                    role: role.clone(),
+                    content: (*content).to_string(),
+                    name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_render_fills_variables() {
+        let store = PromptTemplateStore::default();
+        store
+            .set(
+                "support_bot",
+                template(&[
+                    (Role::System, "You support {{ product }} customers."),
+                    (Role::User, "Ticket #{{ticket}}: {{question}}"),
+                ]),
+            )
+            .expect("template should be valid");
+
+        let variables = HashMap::from([
+            ("product".to_string(), Value::from("Acme")),
+            ("ticket".to_string(), Value::from(42)),
+            ("question".to_string(), Value::from("How do I reset?")),
+        ]);
+        let messages = store
+            .render("support_bot", &variables)
+            .expect("template should render");
+        assert_eq!(messages[0].role, Role::System);
+        assert_eq!(messages[0].content, "You support Acme customers.");
+        assert_eq!(messages[1].content, "Ticket #42: How do I reset?");
+    }
+
+    #[test]
+    fn test_render_and_set_errors() {
+        let store = PromptTemplateStore::default();
+        store
+            .set("greet", template(&[(Role::User, "Hello {{name}}")]))
+            .expect("template should be valid");
+
+        assert_eq!(
+            store.render("missing", &HashMap::new()).err(),
+            Some(TemplateError::Unknown("missing".into()))
+        );
+        assert_eq!(
+            store.render("greet", &HashMap::new()).err(),
+            Some(TemplateError::MissingVariable {
+                template: "greet".into(),
+                variable: "name".into()
+            })
+        );
+        assert_eq!(
+            store.set("bad", template(&[(Role::User, "Hi {{name")])),
+            Err(TemplateError::Unterminated("bad".into()))
+        );
+        assert_eq!(
+            store.set("empty", template(&[])),
+            Err(TemplateError::Empty("empty".into()))
+        );
+        assert!(store.remove("greet").is_some());
+        assert!(store.list().is_empty());
+    }
+
+    #[test]
+    fn test_load_missing_file_fails() {
+        assert!(PromptTemplateStore::load(Some("/nonexistent/prompts.json")).is_err());
+        assert!(PromptTemplateStore::load(None)
+            .expect("no file is fine")
+            .list()
+            .is_empty());
+    }
+}
@@ -2,26 +2,400 @@ use async_trait::async_trait;
 use futures::stream::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::{
+    config::SamplingScaleConfig,
     models::openai::{
-        ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, ChatMessage, Role,
+        merge_tool_call_deltas, ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse,
+        ChatMessage, FunctionCall, FunctionCallDelta, PromptTokensDetails, Role, ToolCall,
+        ToolCallDelta, Usage, DEFAULT_TEMPERATURE, DEFAULT_TOP_P,
     },
+    services::finish_reason,
     services::providers::{
-        LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+        cancellable, cancellable_stream, metered_bytes_stream, read_metered, send_metered,
+        take_sse_frames, LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
     },
+    services::redaction,
+    services::sampling,
+    services::trace_context,
+    services::upstream_clients,
+    services::upstream_headers::{self, TemplateVars},
     state::AppState,
 };
 
 const DEFAULT_BRIDGE_URL: &str = "http://localhost:4001";
 const ANTHROPIC_CHAT_ENDPOINT: &str = "/anthropic/chat";
+const ANTHROPIC_COMPLETE_ENDPOINT: &str = "/anthropic/complete";
+const BRIDGE_HEALTH_ENDPOINT: &str = "/health";
+const BRIDGE_HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+// Bridge API version this proxy speaks; bridges reporting another are refused
+const BRIDGE_API_VERSION: u32 = 1;
+// Streaming chat is required; non-streaming completion and tools are optional
+const CAPABILITY_CHAT: &str = "chat";
+const CAPABILITY_COMPLETE: &str = "complete";
+const CAPABILITY_TOOLS: &str = "tools";
+// Cheapest current model, used for end-to-end provider validation
+const VALIDATION_MODEL: &str = "claude-3-5-haiku-latest";
 
 #[derive(Serialize)]
 struct AnthropicBridgeRequest {
-    messages: Vec<crate::models::openai::ChatMessage>,
+    messages: Vec<BridgeMessage>,
     model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    temperature: f32,
+    top_p: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<BridgeTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<Value>,
+}
+
+impl AnthropicBridgeRequest {
+    /// Moves system messages into `system`, as Anthropic takes the system
+    /// prompt separately from the conversation, and translates OpenAI tool
+    /// definitions, calls and results into Anthropic's form. Sampling
+    /// parameters are mapped onto Anthropic's ranges by `scale`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ProviderError::InvalidRequest` for tool definitions, calls or
+    /// results Anthropic cannot represent.
+    fn from_request(
+        request: &ChatCompletionRequest,
+        stream: bool,
+        scale: &SamplingScaleConfig,
+    ) -> ProviderResult<Self> {
+        let (system, messages): (Vec<_>, Vec<_>) = request
+            .messages
+            .iter()
+            .cloned()
+            .partition(|m| m.role == Role::System);
+        let system = (!system.is_empty()).then(|| {
+            system
+                .into_iter()
+                .map(|m| m.content)
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        });
+        let tools = request
+            .tools
+            .iter()
+            .map(BridgeTool::from_openai)
+            .collect::<ProviderResult<Vec<_>>>()?;
+        let tool_choice = match &request.tool_choice {
+            Some(choice) if !tools.is_empty() => Some(bridge_tool_choice(choice)?),
+            _ => None,
+        };
+
+        Ok(Self {
+            messages: bridge_messages(messages)?,
+            model: request.model.clone(),
+            system,
+            temperature: sampling::temperature(scale, request.temperature)
+                .unwrap_or(DEFAULT_TEMPERATURE),
+            top_p: sampling::top_p(scale, request.top_p).unwrap_or(DEFAULT_TOP_P),
+            top_k: request.top_k,
+            max_tokens: request.max_tokens,
+            stop: request.stop.clone(),
+            stream,
+            tools,
+            tool_choice,
+        })
+    }
+}
+
+/// A conversation turn. Plain text stays a string so bridges without tool
+/// support still understand tool-free requests.
+#[derive(Serialize)]
+struct BridgeMessage {
+    role: Role,
+    content: BridgeContent,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum BridgeContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+}
+
+/// Anthropic's tool definition: the OpenAI function's `parameters` become
+/// `input_schema`.
+#[derive(Serialize)]
+struct BridgeTool {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    input_schema: Value,
+}
+
+impl BridgeTool {
+    fn from_openai(tool: &Value) -> ProviderResult<Self> {
+        let function = tool
+            .get("function")
+            .filter(|_| tool.get("type").is_none_or(|t| t == "function"))
+            .ok_or_else(|| {
+                ProviderError::InvalidRequest(
+                    "Only function tools are supported for Claude models".to_string(),
+                )
+            })?;
+        let name = function
+            .get("name")
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| {
+                ProviderError::InvalidRequest("Tool function needs a name".to_string())
+            })?;
+        Ok(Self {
+            name: name.to_string(),
+            description: function
+                .get("description")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            input_schema: function
+                .get("parameters")
+                .cloned()
+                .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+        })
+    }
+}
+
+/// Maps OpenAI's `tool_choice` onto Anthropic's.
+fn bridge_tool_choice(choice: &Value) -> ProviderResult<Value> {
+    let invalid = || ProviderError::InvalidRequest(format!("Unsupported tool_choice: {choice}"));
+    match choice {
+        Value::String(mode) => match mode.as_str() {
+            "auto" => Ok(json!({"type": "auto"})),
+            "none" => Ok(json!({"type": "none"})),
+            "required" => Ok(json!({"type": "any"})),
+            _ => Err(invalid()),
+        },
+        Value::Object(_) => choice["function"]["name"]
+            .as_str()
+            .map(|name| json!({"type": "tool", "name": name}))
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+/// Assistant tool calls become `tool_use` blocks, and `tool` messages become
+/// `tool_result` blocks in a user turn, consecutive results sharing one turn.
+fn bridge_messages(messages: Vec<ChatMessage>) -> ProviderResult<Vec<BridgeMessage>> {
+    let mut turns: Vec<BridgeMessage> = Vec::with_capacity(messages.len());
+    for message in messages {
+        match message.role {
+            Role::Tool => {
+                let tool_use_id = message.tool_call_id.ok_or_else(|| {
+                    ProviderError::InvalidRequest(
+                        "Tool message is missing tool_call_id".to_string(),
+                    )
+                })?;
+                let block = ContentBlock::ToolResult {
+                    tool_use_id,
+                    content: message.content,
+                };
+                if let Some(BridgeMessage {
+                    role: Role::User,
+                    content: BridgeContent::Blocks(blocks),
+                }) = turns.last_mut()
+                {
+                    if blocks
+                        .iter()
+                        .all(|b| matches!(b, ContentBlock::ToolResult { .. }))
+                    {
+                        blocks.push(block);
+                        continue;
+                    }
+                }
+                turns.push(BridgeMessage {
+                    role: Role::User,
+                    content: BridgeContent::Blocks(vec![block]),
+                });
+            }
+            Role::Assistant if !message.tool_calls.is_empty() => {
+                let mut blocks = Vec::with_capacity(message.tool_calls.len() + 1);
+                if !message.content.is_empty() {
+                    blocks.push(ContentBlock::Text {
+                        text: message.content,
+                    });
+                }
+                for call in message.tool_calls {
+                    let arguments = call.function.arguments.trim();
+                    let input = if arguments.is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(arguments).map_err(|e| {
+                            ProviderError::InvalidRequest(format!(
+                                "Tool call '{}' has invalid JSON arguments: {e}",
+                                call.id
+                            ))
+                        })?
+                    };
+                    blocks.push(ContentBlock::ToolUse {
+                        id: call.id,
+                        name: call.function.name,
+                        input,
+                    });
+                }
+                turns.push(BridgeMessage {
+                    role: Role::Assistant,
+                    content: BridgeContent::Blocks(blocks),
+                });
+            }
+            role => turns.push(BridgeMessage {
+                role,
+                content: BridgeContent::Text(message.content),
+            }),
+        }
+    }
+    Ok(turns)
+}
+
+/// A `tool_use` block as the bridge returns it.
+#[derive(Deserialize)]
+struct BridgeToolUse {
+    id: String,
+    name: String,
+    #[serde(default)]
+    input: Value,
+}
+
+impl From<BridgeToolUse> for ToolCall {
+    fn from(tool_use: BridgeToolUse) -> Self {
+        let arguments = if tool_use.input.is_null() {
+            "{}".to_string()
+        } else {
+            tool_use.input.to_string()
+        };
+        Self {
+            id: tool_use.id,
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: tool_use.name,
+                arguments,
+            },
+        }
+    }
+}
+
+fn tool_calls_from_tool_use<'de, D>(deserializer: D) -> Result<Vec<ToolCall>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let blocks = Vec::<BridgeToolUse>::deserialize(deserializer)?;
+    Ok(blocks.into_iter().map(ToolCall::from).collect())
+}
+
+/// Token counts in the shape of the Anthropic Messages API's `usage`, which
+/// bridges may report. `input_tokens` excludes the tokens read from or
+/// written to the prompt cache.
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: u32,
+}
+
+impl From<AnthropicUsage> for Usage {
+    fn from(usage: AnthropicUsage) -> Self {
+        let prompt_tokens = usage
+            .input_tokens
+            .saturating_add(usage.cache_creation_input_tokens)
+            .saturating_add(usage.cache_read_input_tokens);
+        Self {
+            prompt_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: prompt_tokens.saturating_add(usage.output_tokens),
+            prompt_tokens_details: (usage.cache_read_input_tokens > 0).then_some(
+                PromptTokensDetails {
+                    cached_tokens: usage.cache_read_input_tokens,
+                },
+            ),
+            completion_tokens_details: None,
+            estimated: false,
+        }
+    }
+}
+
+fn usage_from_anthropic<'de, D>(deserializer: D) -> Result<Option<Usage>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<AnthropicUsage>::deserialize(deserializer)?.map(Usage::from))
+}
+
+/// Normalizes one bridge SSE event: maps the finish reason, turns a
+/// `tool_use` delta into an OpenAI `tool_calls` delta and Anthropic `usage`
+/// into OpenAI usage. `next_tool_index` numbers the calls within a stream.
+fn normalize_bridge_event(event: &mut Value, next_tool_index: &mut u32) -> bool {
+    let mut changed = finish_reason::normalize_event(event, finish_reason::from_anthropic);
+    if event["usage"].get("input_tokens").is_some() {
+        if let Ok(usage) = serde_json::from_value::<AnthropicUsage>(event["usage"].take()) {
+            event["usage"] = json!(Usage::from(usage));
+        }
+        changed = true;
+    }
+    let Some(delta) = event["choices"][0]
+        .get_mut("delta")
+        .and_then(Value::as_object_mut)
+    else {
+        return changed;
+    };
+    if let Some(tool_use) = delta.remove("tool_use") {
+        if let Ok(tool_use) = serde_json::from_value::<BridgeToolUse>(tool_use) {
+            let call = ToolCall::from(tool_use);
+            let tool_call = ToolCallDelta {
+                index: *next_tool_index,
+                id: Some(call.id),
+                kind: Some(call.kind),
+                function: FunctionCallDelta {
+                    name: Some(call.function.name),
+                    arguments: Some(call.function.arguments),
+                },
+            };
+            *next_tool_index += 1;
+            delta.insert("tool_calls".to_string(), json!([tool_call]));
+        }
+        changed = true;
+    }
+    changed
 }
 
 #[derive(Deserialize)]
@@ -29,38 +403,347 @@ struct AnthropicBridgeError {
     error: String,
 }
 
+#[derive(Deserialize)]
+struct AnthropicBridgeCompletion {
+    content: String,
+    #[serde(default)]
+    finish_reason: Option<String>,
+    #[serde(
+        default,
+        rename = "tool_use",
+        deserialize_with = "tool_calls_from_tool_use"
+    )]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default, deserialize_with = "usage_from_anthropic")]
+    usage: Option<Usage>,
+}
+
+/// What the bridge reports on its health endpoint. Bridges predating version
+/// negotiation report neither `api_version` nor `capabilities`.
+#[derive(Deserialize)]
+struct BridgeHealth {
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    api_version: Option<u32>,
+    #[serde(default)]
+    capabilities: Option<Vec<String>>,
+}
+
+impl BridgeHealth {
+    /// Checks the bridge speaks our API version and can stream chats.
+    fn negotiate(&self) -> Result<(), String> {
+        if let Some(api_version) = self.api_version.filter(|v| *v != BRIDGE_API_VERSION) {
+            return Err(format!(
+                "bridge speaks API version {api_version}, this proxy supports version {BRIDGE_API_VERSION}"
+            ));
+        }
+        if !self.supports(CAPABILITY_CHAT) {
+            return Err("bridge does not support streaming chat".to_string());
+        }
+        Ok(())
+    }
+
+    fn supports(&self, capability: &str) -> bool {
+        // Legacy bridges only guarantee streaming chat
+        self.capabilities
+            .as_ref()
+            .map_or(capability == CAPABILITY_CHAT, |capabilities| {
+                capabilities.iter().any(|c| c == capability)
+            })
+    }
+}
+
+/// Bridge state as of the last health check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BridgeState {
+    /// Not checked yet; requests are let through.
+    Unknown,
+    Up,
+    Down,
+    Incompatible,
+}
+
+impl BridgeState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Incompatible => "incompatible",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BridgeStatus {
+    state: BridgeState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_version: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capabilities: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl BridgeStatus {
+    fn unknown() -> Self {
+        Self {
+            state: BridgeState::Unknown,
+            version: None,
+            api_version: None,
+            capabilities: None,
+            error: None,
+            checked_at: None,
+        }
+    }
+
+    fn down(error: String) -> Self {
+        Self {
+            state: BridgeState::Down,
+            error: Some(error),
+            checked_at: Some(chrono::Utc::now()),
+            ..Self::unknown()
+        }
+    }
+}
+
 pub struct AnthropicBridgeProvider {
     bridge_url: String,
+    // Set once the bridge answers 404 on the non-streaming endpoint (older bridges)
+    // or reports it lacks the capability
+    complete_unsupported: AtomicBool,
+    bridge: RwLock<BridgeStatus>,
 }
 
 impl AnthropicBridgeProvider {
     #[must_use]
     pub fn new(bridge_url: String) -> Self {
-        Self { bridge_url }
+        Self {
+            bridge_url,
+            complete_unsupported: AtomicBool::new(false),
+            bridge: RwLock::new(BridgeStatus::unknown()),
+        }
     }
-}
 
-impl Default for AnthropicBridgeProvider {
-    fn default() -> Self {
-        Self::new(DEFAULT_BRIDGE_URL.to_string())
+    /// Refuses requests while the last health check found the bridge down or
+    /// incompatible, instead of letting each one fail against it.
+    fn ensure_bridge_usable(&self) -> ProviderResult<()> {
+        let Ok(bridge) = self.bridge.read() else {
+            return Ok(());
+        };
+        if matches!(bridge.state, BridgeState::Unknown | BridgeState::Up) {
+            return Ok(());
+        }
+        let mut message = format!(
+            "Anthropic bridge at {} is {}",
+            self.bridge_url,
+            bridge.state.as_str()
+        );
+        if let Some(error) = &bridge.error {
+            message.push_str(&format!(" ({error})"));
+        }
+        if let Some(checked_at) = bridge.checked_at {
+            message.push_str(&format!("; last checked {}", checked_at.to_rfc3339()));
+        }
+        Err(ProviderError::Unavailable(message))
     }
-}
 
-#[async_trait]
-impl LLMProvider for AnthropicBridgeProvider {
-    async fn execute(
+    /// Rejects tool-using requests when the bridge reported it lacks tool support.
+    fn ensure_tools_supported(&self, request: &ChatCompletionRequest) -> ProviderResult<()> {
+        if !request.uses_tools() {
+            return Ok(());
+        }
+        let Ok(bridge) = self.bridge.read() else {
+            return Ok(());
+        };
+        // Unprobed and legacy bridges are given the benefit of the doubt
+        match &bridge.capabilities {
+            Some(capabilities) if !capabilities.iter().any(|c| c == CAPABILITY_TOOLS) => {
+                Err(ProviderError::InvalidRequest(format!(
+                    "The Anthropic bridge at {} (version {}) does not support tools",
+                    self.bridge_url,
+                    bridge.version.as_deref().unwrap_or("unknown")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fetches the bridge's health endpoint and negotiates its API version
+    /// and capabilities.
+    async fn check_bridge(&self) -> BridgeStatus {
+        let url = format!("{}{}", self.bridge_url, BRIDGE_HEALTH_ENDPOINT);
+        let client = match Client::builder().timeout(BRIDGE_HEALTH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => return BridgeStatus::down(format!("HTTP client unavailable: {e}")),
+        };
+        let response = match client.get(&url).send().await {
+            Ok(resp) if resp.status().is_success() => resp,
+            Ok(resp) => {
+                return BridgeStatus::down(format!("health check returned {}", resp.status()))
+            }
+            Err(e) => return BridgeStatus::down(format!("unreachable: {e}")),
+        };
+        let health = match response.json::<BridgeHealth>().await {
+            Ok(health) => health,
+            Err(e) => return BridgeStatus::down(format!("invalid health response: {e}")),
+        };
+
+        let negotiated = health.negotiate();
+        // Legacy bridges say nothing either way; the 404 fallback covers them
+        if negotiated.is_ok() && health.capabilities.is_some() {
+            self.complete_unsupported
+                .store(!health.supports(CAPABILITY_COMPLETE), Ordering::Relaxed);
+        }
+        BridgeStatus {
+            state: if negotiated.is_ok() {
+                BridgeState::Up
+            } else {
+                BridgeState::Incompatible
+            },
+            version: health.version,
+            api_version: health.api_version,
+            capabilities: health.capabilities,
+            error: negotiated.err(),
+            checked_at: Some(chrono::Utc::now()),
+        }
+    }
+
+    /// POSTs `body` to the bridge through the circuit breaker, mapping
+    /// non-success responses to `ProviderError::Upstream`.
+    async fn post(
         &self,
-        request: ChatCompletionRequest,
         state: &AppState,
-    ) -> ProviderResult<ChatCompletionResponse> {
-        let request_id = Uuid::new_v4().to_string();
-        let model = request.model.clone();
-        info!("Anthropic: Executing non-streaming request {}", request_id);
+        endpoint: &str,
+        body: &AnthropicBridgeRequest,
+    ) -> ProviderResult<reqwest::Response> {
+        let client = upstream_clients::apply(
+            Client::builder(),
+            &state.config.upstream_clients.anthropic,
+            None,
+        )
+        .build()
+        .map_err(|e| ProviderError::Internal(format!("Failed to create HTTP client: {e}")))?;
+        let url = format!("{}{}", self.bridge_url, endpoint);
+        let extra_headers = upstream_headers::render(
+            &state.config.upstream_headers.anthropic,
+            TemplateVars {
+                project_id: state.config.vertex.project_id.as_deref(),
+            },
+        )
+        .map_err(|e| {
+            ProviderError::Internal(format!("Failed to build Anthropic bridge headers: {e}"))
+        })?;
+
+        state
+            .circuit_breaker
+            .call_if(
+                async {
+                    let request = client
+                        .post(&url)
+                        .headers(trace_context::propagation_headers())
+                        .headers(extra_headers)
+                        .json(body);
+                    let resp = send_metered(request, &state.metrics, self.provider_type().name())
+                        .await
+                        .map_err(|e| {
+                            ProviderError::Network(format!(
+                                "Failed to contact Anthropic bridge at {url}: {e}"
+                            ))
+                        })?;
+
+                    if !resp.status().is_success() {
+                        let status = resp.status();
+                        let headers = resp.headers().clone();
+                        let error_text = resp.text().await.unwrap_or_else(|e| {
+                            warn!("Failed to read error response: {}", e);
+                            String::new()
+                        });
+                        state.metrics.record_upstream_response_bytes(
+                            self.provider_type().name(),
+                            error_text.len(),
+                        );
+
+                        let message =
+                            match serde_json::from_str::<AnthropicBridgeError>(&error_text) {
+                                Ok(error) => format!(
+                                    "Anthropic bridge error: {}",
+                                    redaction::redact(&state.config.redaction, &error.error)
+                                ),
+                                Err(_) => format!(
+                                    "Anthropic bridge: {}",
+                                    redaction::redact(&state.config.redaction, &error_text)
+                                ),
+                            };
+                        return Err(ProviderError::upstream(status, &headers, message)
+                            .with_body_detail(&error_text));
+                    }
+
+                    Ok::<reqwest::Response, ProviderError>(resp)
+                },
+                ProviderError::is_retryable,
+            )
+            .await
+    }
+
+    /// Calls the bridge's non-streaming endpoint.
+    ///
+    /// Returns `Ok(None)` if the bridge predates it, so the caller can fall back
+    /// to reassembling the streaming response.
+    async fn complete(
+        &self,
+        state: &AppState,
+        request: &ChatCompletionRequest,
+    ) -> ProviderResult<Option<AnthropicBridgeCompletion>> {
+        if self.complete_unsupported.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        let body =
+            AnthropicBridgeRequest::from_request(request, false, &state.config.sampling.anthropic)?;
+        match self.post(state, ANTHROPIC_COMPLETE_ENDPOINT, &body).await {
+            Ok(resp) => {
+                let body = read_metered(resp, &state.metrics, self.provider_type().name())
+                    .await
+                    .map_err(|e| {
+                        ProviderError::Internal(format!(
+                            "Failed to read Anthropic bridge response: {e}"
+                        ))
+                    })?;
+                serde_json::from_slice(&body).map(Some).map_err(|e| {
+                    ProviderError::Internal(format!(
+                        "Failed to parse Anthropic bridge response: {e}"
+                    ))
+                })
+            }
+            Err(ProviderError::Upstream { status: 404, .. }) => {
+                warn!("Anthropic bridge has no non-streaming endpoint; reassembling streams");
+                self.complete_unsupported.store(true, Ordering::Relaxed);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
 
-        let mut stream = self.execute_stream(request, state).await?;
+    /// Builds a completion by draining the bridge's SSE stream.
+    async fn collect_stream(
+        &self,
+        state: &AppState,
+        request: ChatCompletionRequest,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<AnthropicBridgeCompletion> {
+        let mut stream = self.execute_stream(request, state, cancel).await?;
 
-        let mut full_content = String::new();
+        let mut content = String::new();
         let mut finish_reason = None;
+        let mut tool_call_deltas = Vec::new();
+        let mut usage = None;
 
         while let Some(chunk_result) = stream.next().await {
             match chunk_result {
@@ -74,9 +757,15 @@ impl LLMProvider for AnthropicBridgeProvider {
                             if let Ok(chunk) =
                                 serde_json::from_str::<ChatCompletionChunk>(json_data)
                             {
+                                if chunk.usage.is_some() {
+                                    usage = chunk.usage.clone();
+                                }
                                 if let Some(choice) = chunk.choices.first() {
-                                    if let Some(content) = &choice.delta.content {
-                                        full_content.push_str(content);
+                                    if let Some(delta) = &choice.delta.content {
+                                        content.push_str(delta);
+                                    }
+                                    if let Some(deltas) = &choice.delta.tool_calls {
+                                        tool_call_deltas.extend(deltas.iter().cloned());
                                     }
                                     if let Some(reason) = &choice.finish_reason {
                                         finish_reason = Some(reason.clone());
@@ -94,6 +783,43 @@ impl LLMProvider for AnthropicBridgeProvider {
             }
         }
 
+        Ok(AnthropicBridgeCompletion {
+            content,
+            finish_reason,
+            tool_calls: merge_tool_call_deltas(&tool_call_deltas),
+            usage,
+        })
+    }
+}
+
+impl Default for AnthropicBridgeProvider {
+    fn default() -> Self {
+        Self::new(DEFAULT_BRIDGE_URL.to_string())
+    }
+}
+
+#[async_trait]
+impl LLMProvider for AnthropicBridgeProvider {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<ChatCompletionResponse> {
+        self.ensure_bridge_usable()?;
+        self.ensure_tools_supported(&request)?;
+        let request_id = Uuid::new_v4().to_string();
+        let model = request.model.clone();
+        info!("Anthropic: Executing non-streaming request {}", request_id);
+
+        let completion = cancellable(cancel, async {
+            match self.complete(state, &request).await? {
+                Some(completion) => Ok(completion),
+                None => self.collect_stream(state, request, cancel).await,
+            }
+        })
+        .await?;
+
         let created = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
@@ -108,12 +834,17 @@ impl LLMProvider for AnthropicBridgeProvider {
                 index: 0,
                 message: ChatMessage {
                     role: Role::Assistant,
-                    content: full_content,
+                    content: completion.content,
                     name: None,
+                    images: 0,
+                    tool_calls: completion.tool_calls,
+                    tool_call_id: None,
                 },
-                finish_reason,
+                finish_reason: completion
+                    .finish_reason
+                    .map(|r| finish_reason::from_anthropic(&r).to_string()),
             }],
-            usage: None,
+            usage: completion.usage,
         };
 
         Ok(response)
@@ -123,78 +854,88 @@ impl LLMProvider for AnthropicBridgeProvider {
         &self,
         request: ChatCompletionRequest,
         state: &AppState,
+        cancel: &CancellationToken,
     ) -> ProviderResult<StreamingResponse> {
+        self.ensure_bridge_usable()?;
+        self.ensure_tools_supported(&request)?;
         let request_id = Uuid::new_v4().to_string();
         info!("Anthropic: Executing streaming request {}", request_id);
 
-        let client = Client::new();
-        let bridge_request = AnthropicBridgeRequest {
-            messages: request.messages.clone(),
-            model: request.model.clone(),
-        };
-
-        let url = format!("{}{}", self.bridge_url, ANTHROPIC_CHAT_ENDPOINT);
-
-        let response = state
-            .circuit_breaker
-            .call(async {
-                let resp = client
-                    .post(&url)
-                    .json(&bridge_request)
-                    .send()
-                    .await
-                    .map_err(|e| {
-                        ProviderError::Network(format!(
-                            "Failed to contact Anthropic bridge at {url}: {e}"
-                        ))
-                    })?;
-
-                if !resp.status().is_success() {
-                    let status = resp.status();
-                    let error_text = resp.text().await.unwrap_or_else(|e| {
-                        warn!("Failed to read error response: {}", e);
-                        String::new()
-                    });
-
-                    if let Ok(error) = serde_json::from_str::<AnthropicBridgeError>(&error_text) {
-                        return Err(ProviderError::Unavailable(format!(
-                            "Anthropic bridge error (status: {}): {}",
-                            status, error.error
-                        )));
-                    }
+        let bridge_request =
+            AnthropicBridgeRequest::from_request(&request, true, &state.config.sampling.anthropic)?;
+        let response = cancellable(
+            cancel,
+            self.post(state, ANTHROPIC_CHAT_ENDPOINT, &bridge_request),
+        )
+        .await?;
 
-                    return Err(ProviderError::Unavailable(format!(
-                        "Anthropic bridge HTTP {status}: {error_text}"
-                    )));
-                }
-
-                Ok::<reqwest::Response, ProviderError>(resp)
-            })
-            .await?;
-
-        let stream = response
-            .bytes_stream()
-            .map(move |chunk_result| match chunk_result {
-                Ok(bytes) => {
-                    let chunk_str = String::from_utf8_lossy(&bytes);
-                    Ok::<String, Box<dyn std::error::Error + Send + Sync>>(chunk_str.to_string())
-                }
-                Err(e) => {
-                    error!("Bridge stream error: {}", e);
-                    Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
-                }
-            });
+        let mut next_tool_index = 0;
+        let mut pending = Vec::new();
+        let stream =
+            metered_bytes_stream(response, state.metrics.clone(), self.provider_type().name())
+                .flat_map(move |chunk_result| {
+                    let frames = match chunk_result {
+                        Ok(bytes) => {
+                            pending.extend_from_slice(&bytes);
+                            take_sse_frames(&mut pending)
+                                .into_iter()
+                                .map(|frame| {
+                                    Ok(finish_reason::rewrite_sse_chunk(&frame, |event| {
+                                        normalize_bridge_event(event, &mut next_tool_index)
+                                    }))
+                                })
+                                .collect()
+                        }
+                        Err(e) => {
+                            error!("Bridge stream error: {}", e);
+                            vec![Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)]
+                        }
+                    };
+                    futures::stream::iter(frames)
+                });
 
-        Ok(Box::pin(stream))
+        Ok(cancellable_stream(Box::pin(stream), cancel.clone()))
     }
 
     fn provider_type(&self) -> Provider {
         Provider::AnthropicCLI
     }
 
+    async fn probe(&self) -> Option<ProviderResult<()>> {
+        let status = self.check_bridge().await;
+        let outcome = match status.state {
+            BridgeState::Up => Ok(()),
+            _ => Err(ProviderError::Unavailable(format!(
+                "Anthropic bridge is {}: {}",
+                status.state.as_str(),
+                status.error.as_deref().unwrap_or("no details")
+            ))),
+        };
+        if let Ok(mut bridge) = self.bridge.write() {
+            if bridge.state != status.state {
+                info!(
+                    "Anthropic bridge is now {} (version {})",
+                    status.state.as_str(),
+                    status.version.as_deref().unwrap_or("unknown")
+                );
+            }
+            *bridge = status;
+        }
+        Some(outcome)
+    }
+
+    fn health_details(&self) -> Option<serde_json::Value> {
+        let bridge = self.bridge.read().ok()?;
+        serde_json::to_value(&*bridge).ok()
+    }
+
     fn supports_model(&self, model: &str) -> bool {
         model.starts_with("claude-")
     }
+
+    fn validation_model(&self) -> Option<String> {
+        Some(VALIDATION_MODEL.to_string())
+    }
 }
 
 #[cfg(test)]
@@ -217,6 +958,10 @@ mod tests {
                 host: "127.0.0.1".to_string(),
                 port: 4000,
                 max_request_size: 10 * 1024 * 1024,
+                request_timeout_secs: 0,
+                shutdown_grace_secs: 30,
+                body_limits: Vec::new(),
+                reuse_port: false,
             },
             auth: AuthConfig {
                 require_auth: false,
@@ -229,6 +974,7 @@ mod tests {
                 credentials_file: None,
                 api_key_base_url: None,
                 oauth_base_url: None,
+                generation: Default::default(),
             },
             log: LogConfig {
                 level: "info".to_string(),
@@ -236,18 +982,26 @@ mod tests {
             },
             openai: OpenAIConfig {
                 harvester_url: "http://localhost:3001".to_string(),
+                backend_url: None,
                 access_token_ttl_secs: 3600,
                 arkose_token_ttl_secs: 120,
+                waf_cooldown: Default::default(),
             },
             anthropic: AnthropicConfig {
                 bridge_url: bridge_url.to_string(),
+                health_check_interval_secs: 30,
             },
             gemini_cli: crate::config::GeminiCliConfig {
                 enabled: false,
                 cli_path: None,
                 timeout_secs: 30,
                 max_concurrency: 4,
+                ..Default::default()
             },
+            ollama: crate::config::OllamaConfig::default(),
+            deepseek: Default::default(),
+            bedrock: Default::default(),
+            openai_compatible: Default::default(),
             rate_limit: RateLimitConfig {
                 capacity: 100,
                 refill_per_second: 10,
@@ -256,11 +1010,44 @@ mod tests {
                 failure_threshold: 10,
                 timeout_secs: 60,
                 success_threshold: 3,
+                persist: false,
+                state_file: None,
             },
             cache: CacheConfig {
                 enabled: false,
                 default_ttl_secs: 3600,
+                coalesce_requests: true,
+                vary_on_key: true,
             },
+            maintenance: Default::default(),
+            maintenance_mode: Default::default(),
+            warmup: Default::default(),
+            models: Default::default(),
+            keys: Default::default(),
+            scheduler: Default::default(),
+            alerts: Default::default(),
+            storage: Default::default(),
+            cluster: Default::default(),
+            limits: Default::default(),
+            upstream_headers: Default::default(),
+            upstream_clients: Default::default(),
+            model_policy: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_process: Default::default(),
+            mirror: Default::default(),
+            errors: Default::default(),
+            redaction: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            keepalive: Default::default(),
+            sse: Default::default(),
+            admin_signature: Default::default(),
+            model_discovery: Default::default(),
+            transcripts: Default::default(),
+            sampling: Default::default(),
+            slo: Default::default(),
         };
 
         AppState {
@@ -270,6 +1057,10 @@ mod tests {
             provider_registry: Arc::new(ProviderRegistry::with_config(
                 &Some(config.anthropic.bridge_url.clone()),
                 &None,
+                &None,
+                &None,
+                &None,
+                &None,
             )),
             rate_limiter: crate::middleware::rate_limit::RateLimiter::new(
                 config.rate_limit.capacity,
@@ -282,6 +1073,34 @@ mod tests {
             )),
             metrics: Arc::new(Metrics::new()),
             cache: Arc::new(Cache::new(false, 3600)),
+            in_flight: Default::default(),
+            waf_cooldown: Default::default(),
+            token_bindings: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            affinity: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_processor: Default::default(),
+            mirror: Default::default(),
+            shutdown: Default::default(),
+            routing_rules: Default::default(),
+            latency: Default::default(),
+            model_registry: Default::default(),
+            model_catalog: Default::default(),
+            transcripts: Default::default(),
+            slos: Default::default(),
+            key_store: Default::default(),
+            scheduler: Default::default(),
+            usage: Default::default(),
+            budgets: Default::default(),
+            notifier: Default::default(),
+            maintenance_mode: Default::default(),
+            status_page: Default::default(),
+            warmup: Default::default(),
+            store: None,
+            storage: None,
         }
     }
 
@@ -300,4 +1119,485 @@ mod tests {
         assert_eq!(provider.provider_type(), Provider::AnthropicCLI);
         assert!(provider.supports_model("claude-3-5-sonnet"));
     }
+
+    fn chat_request() -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-3-5-sonnet",
+            "messages": [
+                {"role": "system", "content": "Be terse."},
+                {"role": "user", "content": "Hi"}
+            ],
+            "temperature": 0.2,
+            "top_k": 20,
+            "max_tokens": 64,
+            "stop": "END"
+        }))
+        .expect("valid chat request")
+    }
+
+    #[test]
+    fn test_bridge_request_forwards_parameters() {
+        let body = serde_json::to_value(
+            AnthropicBridgeRequest::from_request(
+                &chat_request(),
+                false,
+                &SamplingScaleConfig::default(),
+            )
+            .expect("bridge request builds"),
+        )
+        .expect("bridge request serializes");
+
+        assert_eq!(body["system"], "Be terse.");
+        assert_eq!(body["messages"].as_array().map(Vec::len), Some(1));
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert!((body["temperature"].as_f64().unwrap_or_default() - 0.2).abs() < 1e-6);
+        assert_eq!(body["top_p"], 1.0);
+        assert_eq!(body["top_k"], 20);
+        assert_eq!(body["max_tokens"], 64);
+        assert_eq!(body["stop"], serde_json::json!(["END"]));
+        assert_eq!(body["stream"], false);
+    }
+
+    #[test]
+    fn test_bridge_request_scales_temperature() {
+        let scale = SamplingScaleConfig {
+            max_temperature: Some(1.0),
+            max_top_p: None,
+        };
+        let mut request = chat_request();
+        request.temperature = Some(1.6);
+        let bridge = AnthropicBridgeRequest::from_request(&request, false, &scale)
+            .expect("bridge request builds");
+        assert!((bridge.temperature - 0.8).abs() < 1e-6);
+        // Omitted parameters keep the default
+        request.temperature = None;
+        let bridge = AnthropicBridgeRequest::from_request(&request, false, &scale)
+            .expect("bridge request builds");
+        assert!((bridge.temperature - DEFAULT_TEMPERATURE).abs() < f32::EPSILON);
+    }
+
+    fn tool_request() -> ChatCompletionRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-3-5-sonnet",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris and Rome?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function",
+                     "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function",
+                     "function": {"name": "get_weather", "arguments": "{\"city\":\"Rome\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18C"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "24C"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "description": "Current weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "tool_choice": "required"
+        }))
+        .expect("valid tool request")
+    }
+
+    #[test]
+    fn test_bridge_request_translates_tools() {
+        let body = serde_json::to_value(
+            AnthropicBridgeRequest::from_request(
+                &tool_request(),
+                false,
+                &SamplingScaleConfig::default(),
+            )
+            .expect("bridge request builds"),
+        )
+        .expect("bridge request serializes");
+
+        assert_eq!(body["messages"][0]["content"], "Weather in Paris and Rome?");
+        assert_eq!(
+            body["messages"][1]["content"],
+            serde_json::json!([
+                {"type": "tool_use", "id": "call_1", "name": "get_weather", "input": {"city": "Paris"}},
+                {"type": "tool_use", "id": "call_2", "name": "get_weather", "input": {"city": "Rome"}}
+            ])
+        );
+        assert_eq!(body["messages"].as_array().map(Vec::len), Some(3));
+        assert_eq!(body["messages"][2]["role"], "user");
+        assert_eq!(
+            body["messages"][2]["content"],
+            serde_json::json!([
+                {"type": "tool_result", "tool_use_id": "call_1", "content": "18C"},
+                {"type": "tool_result", "tool_use_id": "call_2", "content": "24C"}
+            ])
+        );
+        assert_eq!(body["tools"][0]["name"], "get_weather");
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+        assert_eq!(body["tool_choice"], serde_json::json!({"type": "any"}));
+
+        let mut bad = tool_request();
+        bad.messages[1].tool_calls[0].function.arguments = "{city".to_string();
+        assert!(matches!(
+            AnthropicBridgeRequest::from_request(&bad, false, &SamplingScaleConfig::default()),
+            Err(ProviderError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_execute_returns_bridge_tool_use_as_tool_calls() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_COMPLETE_ENDPOINT))
+            .and(body_partial_json(
+                serde_json::json!({"tools": [{"name": "get_weather"}]}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "content": "",
+                "finish_reason": "tool_use",
+                "tool_use": [{"id": "toolu_1", "name": "get_weather", "input": {"city": "Oslo"}}]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let state = create_test_state(&server.uri());
+        let provider = AnthropicBridgeProvider::new(server.uri());
+        let response = provider
+            .execute(tool_request(), &state, &CancellationToken::new())
+            .await
+            .expect("bridge completion should succeed");
+
+        let choice = &response.choices[0];
+        assert_eq!(choice.finish_reason.as_deref(), Some("tool_calls"));
+        assert_eq!(choice.message.tool_calls.len(), 1);
+        assert_eq!(choice.message.tool_calls[0].id, "toolu_1");
+        assert_eq!(choice.message.tool_calls[0].function.name, "get_weather");
+        assert_eq!(
+            choice.message.tool_calls[0].function.arguments,
+            r#"{"city":"Oslo"}"#
+        );
+    }
+
+    #[test]
+    fn test_stream_tool_use_becomes_tool_call_delta() {
+        let chunk = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_use\":",
+            "{\"id\":\"toolu_1\",\"name\":\"a\",\"input\":{}}}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_use\":",
+            "{\"id\":\"toolu_2\",\"name\":\"b\",\"input\":{\"x\":1}}}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_use\"}]}\n\n"
+        );
+        let mut next_tool_index = 0;
+        let out = finish_reason::rewrite_sse_chunk(chunk, |event| {
+            normalize_bridge_event(event, &mut next_tool_index)
+        });
+        let events: Vec<Value> = out
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).expect("event is JSON"))
+            .collect();
+
+        let first = &events[0]["choices"][0]["delta"];
+        assert!(first.get("tool_use").is_none());
+        assert_eq!(first["tool_calls"][0]["index"], 0);
+        assert_eq!(first["tool_calls"][0]["id"], "toolu_1");
+        assert_eq!(first["tool_calls"][0]["function"]["arguments"], "{}");
+        let second = &events[1]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(second["index"], 1);
+        assert_eq!(second["function"]["name"], "b");
+        assert_eq!(second["function"]["arguments"], r#"{"x":1}"#);
+        assert_eq!(events[2]["choices"][0]["finish_reason"], "tool_calls");
+    }
+
+    #[test]
+    fn test_bridge_usage_is_normalized() {
+        let completion: AnthropicBridgeCompletion = serde_json::from_value(serde_json::json!({
+            "content": "Hi",
+            "usage": {
+                "input_tokens": 10,
+                "output_tokens": 5,
+                "cache_creation_input_tokens": 20,
+                "cache_read_input_tokens": 70
+            }
+        }))
+        .expect("completion parses");
+        let usage = completion.usage.expect("usage is reported");
+        assert_eq!(
+            (
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                usage.total_tokens
+            ),
+            (100, 5, 105)
+        );
+        assert_eq!(
+            usage.prompt_tokens_details,
+            Some(PromptTokensDetails { cached_tokens: 70 })
+        );
+
+        let chunk = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"end_turn\"}],",
+            "\"usage\":{\"input_tokens\":3,\"output_tokens\":4}}\n\n"
+        );
+        let out =
+            finish_reason::rewrite_sse_chunk(chunk, |event| normalize_bridge_event(event, &mut 0));
+        let event: Value = serde_json::from_str(
+            out.trim()
+                .strip_prefix("data: ")
+                .expect("frame is a data line"),
+        )
+        .expect("event is JSON");
+        assert_eq!(
+            event["usage"],
+            serde_json::json!({"prompt_tokens": 3, "completion_tokens": 4, "total_tokens": 7})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_uses_non_streaming_endpoint() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_COMPLETE_ENDPOINT))
+            .and(body_partial_json(
+                serde_json::json!({"system": "Be terse.", "stream": false}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                serde_json::json!({"content": "Hello", "finish_reason": "max_tokens"}),
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let state = create_test_state(&server.uri());
+        let provider = AnthropicBridgeProvider::new(server.uri());
+        let response = provider
+            .execute(chat_request(), &state, &CancellationToken::new())
+            .await
+            .expect("bridge completion should succeed");
+
+        assert_eq!(response.choices[0].message.content, "Hello");
+        assert_eq!(response.choices[0].finish_reason.as_deref(), Some("length"));
+    }
+
+    #[tokio::test]
+    async fn test_validation_sends_one_token_request() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_COMPLETE_ENDPOINT))
+            .and(body_partial_json(
+                serde_json::json!({"model": VALIDATION_MODEL, "max_tokens": 1}),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"content": "OK"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let state = create_test_state(&server.uri());
+        let report = state
+            .provider_registry
+            .validate(
+                &state,
+                Some("anthropic_cli"),
+                std::time::Duration::from_secs(5),
+            )
+            .await;
+        assert_eq!(report.len(), 1);
+        assert!(report[0].ok, "validation failed: {:?}", report[0].error);
+        assert_eq!(report[0].model.as_deref(), Some(VALIDATION_MODEL));
+
+        server.reset().await;
+        let report = state
+            .provider_registry
+            .validate(
+                &state,
+                Some("anthropic_cli"),
+                std::time::Duration::from_secs(5),
+            )
+            .await;
+        assert!(!report[0].ok);
+        assert!(report[0].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_configured_headers_are_sent_to_bridge() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_COMPLETE_ENDPOINT))
+            .and(header("x-gateway-key", "gw-123"))
+            .and(header("user-agent", "gateway-client/1.0"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"content": "ok"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut state = create_test_state(&server.uri());
+        let mut config = (*state.config).clone();
+        config.upstream_headers.anthropic = vec!["X-Gateway-Key: gw-123".to_string()];
+        config.upstream_clients.anthropic.user_agent = Some("gateway-client/1.0".to_string());
+        state.config = Arc::new(config);
+
+        let provider = AnthropicBridgeProvider::new(server.uri());
+        provider
+            .execute(chat_request(), &state, &CancellationToken::new())
+            .await
+            .expect("request with gateway header should succeed");
+    }
+
+    #[tokio::test]
+    async fn test_execute_falls_back_to_stream_on_older_bridge() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_COMPLETE_ENDPOINT))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let sse = concat!(
+            "data: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":0,",
+            "\"model\":\"claude-3-5-sonnet\",\"choices\":[{\"index\":0,",
+            "\"delta\":{\"content\":\"Hi there\"},\"finish_reason\":\"end_turn\"}]}\n\n",
+            "data: [DONE]\n\n"
+        );
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_CHAT_ENDPOINT))
+            .respond_with(ResponseTemplate::new(200).set_body_string(sse))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let state = create_test_state(&server.uri());
+        let provider = AnthropicBridgeProvider::new(server.uri());
+        for _ in 0..2 {
+            let response = provider
+                .execute(chat_request(), &state, &CancellationToken::new())
+                .await
+                .expect("stream fallback should succeed");
+            assert_eq!(response.choices[0].message.content, "Hi there");
+            assert_eq!(response.choices[0].finish_reason.as_deref(), Some("stop"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_aborts_bridge_call() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_COMPLETE_ENDPOINT))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"content": "too late"}))
+                    .set_delay(std::time::Duration::from_secs(30)),
+            )
+            .mount(&server)
+            .await;
+
+        let state = create_test_state(&server.uri());
+        let provider = AnthropicBridgeProvider::new(server.uri());
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            trigger.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let err = provider
+            .execute(chat_request(), &state, &cancel)
+            .await
+            .expect_err("cancelled call should fail");
+        assert!(matches!(err, ProviderError::Cancelled(_)), "got {err}");
+        assert!(!err.is_retryable());
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+    #[tokio::test]
+    async fn test_incompatible_bridge_is_refused_until_it_recovers() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(BRIDGE_HEALTH_ENDPOINT))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": "ok",
+                "version": "2.0.0",
+                "api_version": 2,
+                "capabilities": ["chat", "complete"]
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(BRIDGE_HEALTH_ENDPOINT))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": "ok",
+                "version": "1.0.1",
+                "api_version": 1,
+                "capabilities": ["chat", "complete"]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(ANTHROPIC_COMPLETE_ENDPOINT))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"content": "back"})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let state = create_test_state(&server.uri());
+        let provider = AnthropicBridgeProvider::new(server.uri());
+        assert!(matches!(provider.probe().await, Some(Err(_))));
+        let details = provider.health_details().expect("bridge details");
+        assert_eq!(details["state"], "incompatible");
+        assert_eq!(details["version"], "2.0.0");
+        let err = provider
+            .execute(chat_request(), &state, &CancellationToken::new())
+            .await
+            .expect_err("incompatible bridge should be refused");
+        assert_eq!(err.status(), 503);
+        assert!(err.to_string().contains("API version 2"), "got {err}");
+
+        assert!(matches!(provider.probe().await, Some(Ok(()))));
+        let response = provider
+            .execute(chat_request(), &state, &CancellationToken::new())
+            .await
+            .expect("compatible bridge should serve requests");
+        assert_eq!(response.choices[0].message.content, "back");
+    }
+
+    #[test]
+    fn test_legacy_bridge_health_negotiates() {
+        let legacy: BridgeHealth = serde_json::from_value(
+            serde_json::json!({"status": "ok", "service": "anthropic-bridge"}),
+        )
+        .expect("legacy health should parse");
+        assert!(legacy.negotiate().is_ok());
+        assert!(!legacy.supports(CAPABILITY_COMPLETE));
+
+        let no_chat: BridgeHealth = serde_json::from_value(
+            serde_json::json!({"api_version": 1, "capabilities": ["complete"]}),
+        )
+        .expect("health should parse");
+        assert!(no_chat.negotiate().is_err());
+    }
 }
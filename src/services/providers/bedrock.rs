@@ -0,0 +1,822 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::BedrockConfig,
+    models::openai::{
+        ChatCompletionChoice, ChatCompletionChunk, ChatCompletionChunkChoice,
+        ChatCompletionRequest, ChatCompletionResponse, ChatMessage, DeltaMessage, Role, Usage,
+    },
+    services::aws_sigv4::{self, AwsCredentials, CredentialChain},
+    services::finish_reason,
+    services::providers::{
+        cancellable, cancellable_stream, metered_bytes_stream, read_metered, send_metered,
+        LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+    },
+    services::redaction,
+    state::AppState,
+};
+
+/// Model ID prefixes served through the Converse API.
+const MODEL_PREFIXES: [&str; 2] = ["anthropic.claude-", "amazon.titan-"];
+const SIGNING_SERVICE: &str = "bedrock";
+const DEFAULT_REGION: &str = "us-east-1";
+// Bedrock names the exception in this header, e.g. `ThrottlingException:...`
+const ERROR_TYPE_HEADER: &str = "x-amzn-errortype";
+
+#[derive(Debug, Serialize)]
+struct TextBlock {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ConverseMessage {
+    role: &'static str,
+    content: Vec<TextBlock>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InferenceConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+}
+
+impl InferenceConfig {
+    fn is_empty(&self) -> bool {
+        self.max_tokens.is_none()
+            && self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.stop_sequences.is_none()
+    }
+}
+
+/// A Converse (or ConverseStream) request body.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConverseRequest {
+    messages: Vec<ConverseMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    system: Vec<TextBlock>,
+    #[serde(skip_serializing_if = "InferenceConfig::is_empty")]
+    inference_config: InferenceConfig,
+    /// Model-specific parameters Converse has no field for, such as Claude's `top_k`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    additional_model_request_fields: Option<Value>,
+}
+
+impl ConverseRequest {
+    /// System messages become `system` blocks; the rest alternate between
+    /// `user` and `assistant` as Converse requires, so consecutive messages
+    /// of one role are merged. Tool results are sent as user text.
+    fn from_request(request: &ChatCompletionRequest) -> ProviderResult<Self> {
+        if request.uses_tools() {
+            return Err(ProviderError::InvalidRequest(
+                "The Bedrock provider does not support tools".to_string(),
+            ));
+        }
+        let mut system = Vec::new();
+        let mut messages: Vec<ConverseMessage> = Vec::new();
+        for message in request.messages.iter().filter(|m| !m.content.is_empty()) {
+            let role = match message.role {
+                Role::System => {
+                    system.push(TextBlock {
+                        text: message.content.clone(),
+                    });
+                    continue;
+                }
+                Role::User | Role::Tool => "user",
+                Role::Assistant => "assistant",
+            };
+            let block = TextBlock {
+                text: message.content.clone(),
+            };
+            match messages.last_mut() {
+                Some(last) if last.role == role => last.content.push(block),
+                _ => messages.push(ConverseMessage {
+                    role,
+                    content: vec![block],
+                }),
+            }
+        }
+        Ok(Self {
+            messages,
+            system,
+            inference_config: InferenceConfig {
+                max_tokens: request.max_tokens,
+                temperature: request.temperature,
+                top_p: request.top_p,
+                stop_sequences: request.stop.clone(),
+            },
+            additional_model_request_fields: request
+                .top_k
+                .filter(|_| request.model.starts_with("anthropic."))
+                .map(|top_k| serde_json::json!({ "top_k": top_k })),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ContentBlock {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OutputMessage {
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConverseOutput {
+    #[serde(default)]
+    message: OutputMessage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+    total_tokens: u32,
+}
+
+impl From<TokenUsage> for Usage {
+    fn from(usage: TokenUsage) -> Self {
+        Self::new(usage.input_tokens, usage.output_tokens, usage.total_tokens)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConverseResponse {
+    #[serde(default)]
+    output: ConverseOutput,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+/// Maps a failed response to a `ProviderError`. Rejected credentials are an
+/// auth failure; everything else is passed on as `Upstream` so validation
+/// errors and throttling reach the client.
+fn map_error(
+    status: StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    message: &str,
+) -> ProviderError {
+    let error_type = headers
+        .get(ERROR_TYPE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(':').next())
+        .unwrap_or_default();
+    let message = match error_type {
+        "" => message.to_string(),
+        error_type => format!("{error_type}: {message}"),
+    };
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            ProviderError::Auth(format!("Bedrock rejected the request: {message}"))
+        }
+        _ => ProviderError::upstream(status, headers, format!("Bedrock error: {message}")),
+    }
+}
+
+/// The `message` of Bedrock's `{"message": "..."}` error body, or the raw body.
+fn error_message(body: &[u8]) -> String {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|v| {
+            v.get("message")
+                .or_else(|| v.get("Message"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned())
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// One message of the `application/vnd.amazon.eventstream` framing
+/// ConverseStream answers with: string headers and a JSON payload.
+#[derive(Debug, Default)]
+struct EventMessage {
+    headers: Vec<(String, String)>,
+    payload: Vec<u8>,
+}
+
+impl EventMessage {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Parses the headers of an event stream message. Only string values are
+/// kept; the rest are skipped by their type's size.
+fn parse_event_headers(mut bytes: &[u8]) -> Result<Vec<(String, String)>, String> {
+    fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+        if bytes.len() < len {
+            return Err("truncated event stream header".to_string());
+        }
+        let (taken, rest) = bytes.split_at(len);
+        *bytes = rest;
+        Ok(taken)
+    }
+    let mut headers = Vec::new();
+    while !bytes.is_empty() {
+        let name_len = usize::from(take(&mut bytes, 1)?[0]);
+        let name = String::from_utf8_lossy(take(&mut bytes, name_len)?).into_owned();
+        let value_len = match take(&mut bytes, 1)?[0] {
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            6 | 7 => {
+                let len = take(&mut bytes, 2)?;
+                usize::from(u16::from_be_bytes([len[0], len[1]]))
+            }
+            other => return Err(format!("unknown event stream header type {other}")),
+        };
+        let value = take(&mut bytes, value_len)?;
+        if let Ok(value) = std::str::from_utf8(value) {
+            headers.push((name, value.to_string()));
+        }
+    }
+    Ok(headers)
+}
+
+/// Removes the complete event stream messages from the front of `pending`.
+/// Each is a 12-byte prelude (total length, header length, prelude CRC), the
+/// headers, the payload and a message CRC. The CRCs are not checked: the
+/// bytes arrived over TLS.
+fn take_event_messages(pending: &mut Vec<u8>) -> Result<Vec<EventMessage>, String> {
+    let mut messages = Vec::new();
+    while pending.len() >= 12 {
+        let word = |at: usize| {
+            usize::try_from(u32::from_be_bytes([
+                pending[at],
+                pending[at + 1],
+                pending[at + 2],
+                pending[at + 3],
+            ]))
+            .unwrap_or(usize::MAX)
+        };
+        let (total_len, headers_len) = (word(0), word(4));
+        if total_len < 16 || headers_len > total_len - 16 {
+            return Err(format!(
+                "malformed event stream message of {total_len} bytes"
+            ));
+        }
+        if pending.len() < total_len {
+            break;
+        }
+        let message: Vec<u8> = pending.drain(..total_len).collect();
+        messages.push(EventMessage {
+            headers: parse_event_headers(&message[12..12 + headers_len])?,
+            payload: message[12 + headers_len..total_len - 4].to_vec(),
+        });
+    }
+    Ok(messages)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamDelta {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// Payload of the events the stream translator acts on.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamEvent {
+    #[serde(default)]
+    delta: Option<StreamDelta>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+/// Turns ConverseStream's event stream into OpenAI SSE frames. Network reads
+/// can split a message or hold several, so incomplete ones wait in `pending`.
+/// The stop reason arrives in `messageStop` and usage in the `metadata` event
+/// after it; both go out on the final chunk.
+struct StreamTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    pending: Vec<u8>,
+    sent_role: bool,
+    finish_reason: Option<String>,
+}
+
+impl StreamTranslator {
+    fn new(id: String, model: String) -> Self {
+        Self {
+            id,
+            model,
+            created: unix_now(),
+            pending: Vec::new(),
+            sent_role: false,
+            finish_reason: None,
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) -> Vec<ProviderResult<String>> {
+        self.pending.extend_from_slice(bytes);
+        match take_event_messages(&mut self.pending) {
+            Ok(messages) => messages
+                .into_iter()
+                .filter_map(|message| self.translate(&message))
+                .flatten()
+                .collect(),
+            Err(e) => vec![Err(ProviderError::Internal(format!(
+                "Failed to parse Bedrock stream: {e}"
+            )))],
+        }
+    }
+
+    fn chunk(&mut self, content: Option<String>, usage: Option<Usage>) -> ProviderResult<String> {
+        let chunk = ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChatCompletionChunkChoice {
+                index: 0,
+                delta: DeltaMessage {
+                    role: (!self.sent_role).then_some(Role::Assistant),
+                    content,
+                    tool_calls: None,
+                },
+                finish_reason: usage.as_ref().and(self.finish_reason.clone()),
+            }],
+            usage,
+        };
+        self.sent_role = true;
+        serde_json::to_string(&chunk)
+            .map(|json| format!("data: {json}\n\n"))
+            .map_err(|e| ProviderError::Internal(format!("Failed to serialize chunk: {e}")))
+    }
+
+    fn translate(&mut self, message: &EventMessage) -> Option<Vec<ProviderResult<String>>> {
+        let event: StreamEvent = serde_json::from_slice(&message.payload).unwrap_or_default();
+        if message.header(":message-type") == Some("exception") {
+            let kind = message.header(":exception-type").unwrap_or("exception");
+            let text = format!(
+                "Bedrock stream failed with {kind}: {}",
+                event.message.unwrap_or_default()
+            );
+            return Some(vec![Err(if kind == "throttlingException" {
+                ProviderError::RateLimited(text)
+            } else {
+                ProviderError::Unavailable(text)
+            })]);
+        }
+        match message.header(":event-type")? {
+            "contentBlockDelta" => {
+                let text = event.delta.and_then(|delta| delta.text)?;
+                Some(vec![self.chunk(Some(text), None)])
+            }
+            "messageStop" => {
+                self.finish_reason = Some(
+                    finish_reason::from_bedrock(event.stop_reason.as_deref().unwrap_or_default())
+                        .to_string(),
+                );
+                None
+            }
+            "metadata" => {
+                let usage = event.usage.map_or_else(Usage::default, Usage::from);
+                if self.finish_reason.is_none() {
+                    self.finish_reason = Some(finish_reason::STOP.to_string());
+                }
+                Some(vec![
+                    self.chunk(Some(String::new()), Some(usage)),
+                    Ok("data: [DONE]\n\n".to_string()),
+                ])
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Serves `anthropic.claude-*` and `amazon.titan-*` models through Bedrock's
+/// Converse API, signing requests with SigV4.
+pub struct BedrockProvider {
+    region: String,
+    endpoint: String,
+    credentials: CredentialChain,
+    timeout: Duration,
+}
+
+impl BedrockProvider {
+    #[must_use]
+    pub fn from_config(config: &BedrockConfig) -> Self {
+        let region = config
+            .region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .filter(|region| !region.is_empty())
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let endpoint = config.endpoint_url.clone().map_or_else(
+            || format!("https://bedrock-runtime.{region}.amazonaws.com"),
+            |url| url.trim_end_matches('/').to_string(),
+        );
+        let configured = match (&config.access_key_id, &config.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(AwsCredentials {
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret_access_key.clone(),
+                session_token: config.session_token.clone(),
+                expires_at: None,
+            }),
+            _ => None,
+        };
+        Self {
+            region,
+            endpoint,
+            credentials: CredentialChain::new(configured, &config.metadata_url),
+            timeout: Duration::from_secs(config.timeout_secs),
+        }
+    }
+
+    /// `/model/<id>/<action>` on the runtime endpoint, with the model ID
+    /// percent-encoded (versioned IDs contain `:`).
+    fn url(&self, model: &str, action: &str) -> ProviderResult<Url> {
+        let url = format!(
+            "{}/model/{}/{action}",
+            self.endpoint,
+            aws_sigv4::uri_encode(model, false)
+        );
+        Url::parse(&url)
+            .map_err(|e| ProviderError::Internal(format!("Invalid Bedrock URL {url}: {e}")))
+    }
+
+    /// Signs and POSTs `body` to `action` for `model`, mapping non-success
+    /// responses through [`map_error`].
+    async fn post(
+        &self,
+        state: &AppState,
+        model: &str,
+        action: &str,
+        body: &ConverseRequest,
+    ) -> ProviderResult<reqwest::Response> {
+        let credentials = self
+            .credentials
+            .resolve()
+            .await
+            .map_err(ProviderError::Auth)?;
+        let url = self.url(model, action)?;
+        let body = serde_json::to_vec(body)
+            .map_err(|e| ProviderError::Internal(format!("Failed to encode request: {e}")))?;
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|e| ProviderError::Internal(format!("Failed to create HTTP client: {e}")))?;
+        let mut request = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        for (name, value) in aws_sigv4::sign(
+            &credentials,
+            &self.region,
+            SIGNING_SERVICE,
+            "POST",
+            &url,
+            &body,
+            Utc::now(),
+        ) {
+            request = request.header(name, value);
+        }
+        let resp = send_metered(
+            request.body(body),
+            &state.metrics,
+            self.provider_type().name(),
+        )
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                ProviderError::Timeout(format!("Bedrock timed out: {e}"))
+            } else {
+                ProviderError::Network(format!("Failed to contact Bedrock at {url}: {e}"))
+            }
+        })?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let error_body = resp.bytes().await.unwrap_or_else(|e| {
+                warn!("Failed to read error response: {}", e);
+                Default::default()
+            });
+            state
+                .metrics
+                .record_upstream_response_bytes(self.provider_type().name(), error_body.len());
+            let message = redaction::redact(&state.config.redaction, &error_message(&error_body));
+            return Err(map_error(status, &headers, &message)
+                .with_body_detail(&String::from_utf8_lossy(&error_body)));
+        }
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl LLMProvider for BedrockProvider {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<ChatCompletionResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!("Bedrock: Executing non-streaming request {}", request_id);
+
+        let body = ConverseRequest::from_request(&request)?;
+        let completion: ConverseResponse = cancellable(cancel, async {
+            let resp = self.post(state, &request.model, "converse", &body).await?;
+            let bytes = read_metered(resp, &state.metrics, self.provider_type().name())
+                .await
+                .map_err(|e| {
+                    ProviderError::Internal(format!("Failed to read Bedrock response: {e}"))
+                })?;
+            serde_json::from_slice(&bytes).map_err(|e| {
+                ProviderError::Internal(format!("Failed to parse Bedrock response: {e}"))
+            })
+        })
+        .await?;
+
+        let content = completion
+            .output
+            .message
+            .content
+            .into_iter()
+            .filter_map(|block| block.text)
+            .collect::<String>();
+        Ok(ChatCompletionResponse {
+            id: format!("chatcmpl-{request_id}"),
+            object: "chat.completion".to_string(),
+            created: unix_now(),
+            model: request.model,
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: ChatMessage {
+                    role: Role::Assistant,
+                    content,
+                    name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
+                },
+                finish_reason: Some(
+                    finish_reason::from_bedrock(
+                        completion.stop_reason.as_deref().unwrap_or_default(),
+                    )
+                    .to_string(),
+                ),
+            }],
+            usage: completion.usage.map(Usage::from),
+        })
+    }
+
+    async fn execute_stream(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<StreamingResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!("Bedrock: Executing streaming request {}", request_id);
+
+        let body = ConverseRequest::from_request(&request)?;
+        let response = cancellable(
+            cancel,
+            self.post(state, &request.model, "converse-stream", &body),
+        )
+        .await?;
+
+        let mut translator = StreamTranslator::new(format!("chatcmpl-{request_id}"), request.model);
+        let stream =
+            metered_bytes_stream(response, state.metrics.clone(), self.provider_type().name())
+                .flat_map(move |chunk_result| {
+                    let frames: Vec<_> = match chunk_result {
+                        Ok(bytes) => translator
+                            .feed(&bytes)
+                            .into_iter()
+                            .map(|frame| {
+                                frame.map_err(|e| {
+                                    Box::new(e) as Box<dyn std::error::Error + Send + Sync>
+                                })
+                            })
+                            .collect(),
+                        Err(e) => {
+                            error!("Bedrock stream error: {}", e);
+                            vec![Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)]
+                        }
+                    };
+                    futures::stream::iter(frames)
+                });
+
+        Ok(cancellable_stream(Box::pin(stream), cancel.clone()))
+    }
+
+    fn provider_type(&self) -> Provider {
+        Provider::Bedrock
+    }
+
+    /// Resolves credentials; Bedrock's runtime API has no cheap endpoint to
+    /// call without invoking a model.
+    async fn probe(&self) -> Option<ProviderResult<()>> {
+        Some(
+            self.credentials
+                .resolve()
+                .await
+                .map(|_| ())
+                .map_err(ProviderError::Auth),
+        )
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        MODEL_PREFIXES
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+    }
+
+    fn supports_tools(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    /// Encodes an event stream message with string headers; the CRCs are zero.
+    fn event(headers: &[(&str, &str)], payload: &Value) -> Vec<u8> {
+        let mut encoded_headers = Vec::new();
+        for (name, value) in headers {
+            encoded_headers.push(u8::try_from(name.len()).unwrap());
+            encoded_headers.extend_from_slice(name.as_bytes());
+            encoded_headers.push(7);
+            encoded_headers.extend_from_slice(&u16::try_from(value.len()).unwrap().to_be_bytes());
+            encoded_headers.extend_from_slice(value.as_bytes());
+        }
+        let payload = payload.to_string().into_bytes();
+        let total = 16 + encoded_headers.len() + payload.len();
+        let mut message = Vec::new();
+        message.extend_from_slice(&u32::try_from(total).unwrap().to_be_bytes());
+        message.extend_from_slice(&u32::try_from(encoded_headers.len()).unwrap().to_be_bytes());
+        message.extend_from_slice(&[0; 4]);
+        message.extend_from_slice(&encoded_headers);
+        message.extend_from_slice(&payload);
+        message.extend_from_slice(&[0; 4]);
+        message
+    }
+
+    fn stream_event(event_type: &str, payload: &Value) -> Vec<u8> {
+        event(
+            &[(":message-type", "event"), (":event-type", event_type)],
+            payload,
+        )
+    }
+
+    #[test]
+    fn test_bedrock_supports_claude_and_titan_ids() {
+        let provider = BedrockProvider::from_config(&BedrockConfig {
+            enabled: true,
+            region: Some("eu-west-1".to_string()),
+            ..BedrockConfig::default()
+        });
+        assert!(provider.supports_model("anthropic.claude-3-5-sonnet-20240620-v1:0"));
+        assert!(provider.supports_model("amazon.titan-text-express-v1"));
+        assert!(!provider.supports_model("claude-3-5-sonnet"));
+        assert_eq!(
+            provider
+                .url("anthropic.claude-3-haiku-20240307-v1:0", "converse")
+                .unwrap()
+                .as_str(),
+            "https://bedrock-runtime.eu-west-1.amazonaws.com/model/anthropic.claude-3-haiku-20240307-v1%3A0/converse"
+        );
+    }
+
+    #[test]
+    fn test_converse_request_merges_roles() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "anthropic.claude-3-haiku-20240307-v1:0",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"},
+                {"role": "user", "content": "Anyone there?"},
+                {"role": "assistant", "content": "Yes"}
+            ],
+            "temperature": 0.5,
+            "top_k": 20,
+            "max_tokens": 64,
+            "stop": "END"
+        }))
+        .unwrap();
+        let body = serde_json::to_value(ConverseRequest::from_request(&request).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "messages": [
+                    {"role": "user", "content": [{"text": "Hi"}, {"text": "Anyone there?"}]},
+                    {"role": "assistant", "content": [{"text": "Yes"}]}
+                ],
+                "system": [{"text": "Be brief."}],
+                "inferenceConfig": {"maxTokens": 64, "temperature": 0.5, "stopSequences": ["END"]},
+                "additionalModelRequestFields": {"top_k": 20}
+            })
+        );
+    }
+
+    #[test]
+    fn test_bedrock_error_mapping() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ERROR_TYPE_HEADER,
+            HeaderValue::from_static("ThrottlingException:http://internal.amazon.com/coral/"),
+        );
+        let message = error_message(br#"{"message": "Too many requests"}"#);
+        let throttled = map_error(StatusCode::TOO_MANY_REQUESTS, &headers, &message);
+        assert_eq!(throttled.status(), 429);
+        assert!(throttled
+            .to_string()
+            .contains("ThrottlingException: Too many requests"));
+        assert!(matches!(
+            map_error(StatusCode::FORBIDDEN, &HeaderMap::new(), "denied"),
+            ProviderError::Auth(_)
+        ));
+    }
+
+    #[test]
+    fn test_stream_translator_handles_split_messages() {
+        let mut bytes = stream_event("messageStart", &serde_json::json!({"role": "assistant"}));
+        bytes.extend(stream_event(
+            "contentBlockDelta",
+            &serde_json::json!({"contentBlockIndex": 0, "delta": {"text": "Hel"}}),
+        ));
+        bytes.extend(stream_event(
+            "contentBlockDelta",
+            &serde_json::json!({"contentBlockIndex": 0, "delta": {"text": "lo"}}),
+        ));
+        bytes.extend(stream_event(
+            "messageStop",
+            &serde_json::json!({"stopReason": "max_tokens"}),
+        ));
+        bytes.extend(stream_event(
+            "metadata",
+            &serde_json::json!({"usage": {"inputTokens": 5, "outputTokens": 2, "totalTokens": 7}}),
+        ));
+
+        let mut translator = StreamTranslator::new("chatcmpl-1".to_string(), "m".to_string());
+        let split = bytes.len() / 2;
+        let mut frames = translator.feed(&bytes[..split]);
+        frames.extend(translator.feed(&bytes[split..]));
+        let frames: Vec<String> = frames.into_iter().map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 4);
+        assert!(
+            frames[0].contains(r#""role":"assistant""#) && frames[0].contains(r#""content":"Hel""#)
+        );
+        assert!(frames[1].contains(r#""content":"lo""#) && !frames[1].contains("role"));
+        assert!(frames[2].contains(r#""finish_reason":"length""#));
+        assert!(frames[2].contains(r#""total_tokens":7"#));
+        assert_eq!(frames[3], "data: [DONE]\n\n");
+
+        let failed = translator.feed(&event(
+            &[
+                (":message-type", "exception"),
+                (":exception-type", "throttlingException"),
+            ],
+            &serde_json::json!({"message": "Slow down"}),
+        ));
+        assert!(matches!(failed[0], Err(ProviderError::RateLimited(_))));
+    }
+}
@@ -0,0 +1,331 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::DeepSeekConfig,
+    models::openai::{ChatCompletionRequest, ChatCompletionResponse, ChatMessage, StreamOptions},
+    services::providers::{
+        cancellable, cancellable_stream, metered_bytes_stream, read_metered, send_metered,
+        take_sse_frames, LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+    },
+    services::redaction,
+    services::trace_context,
+    state::AppState,
+};
+
+const CHAT_ENDPOINT: &str = "/chat/completions";
+const MODELS_ENDPOINT: &str = "/models";
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+const VALIDATION_MODEL: &str = "deepseek-chat";
+
+/// The OpenAI request fields DeepSeek accepts. `top_k` and the proxy's own
+/// extensions are left out.
+#[derive(Debug, Serialize)]
+struct DeepSeekRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "<[Value]>::is_empty")]
+    tools: &'a [Value],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<&'a StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+}
+
+impl<'a> DeepSeekRequest<'a> {
+    fn from_request(request: &'a ChatCompletionRequest, stream: bool) -> Self {
+        Self {
+            model: &request.model,
+            messages: &request.messages,
+            stream,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            max_tokens: request.max_tokens,
+            stop: request.stop.as_deref(),
+            tools: &request.tools,
+            tool_choice: request.tool_choice.as_ref(),
+            stream_options: request.stream_options.as_ref().filter(|_| stream),
+            user: request.user.as_deref(),
+        }
+    }
+}
+
+/// The `message` of DeepSeek's `{"error": {...}}` body, or the raw body.
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// Maps a failed response to a `ProviderError`. A rejected key is an auth
+/// failure and an empty balance makes the provider unavailable; everything
+/// else is passed on as `Upstream` so 400s and 429s reach the client.
+fn map_error(
+    status: StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    message: String,
+) -> ProviderError {
+    match status {
+        StatusCode::UNAUTHORIZED => {
+            ProviderError::Auth(format!("DeepSeek rejected the API key: {message}"))
+        }
+        StatusCode::PAYMENT_REQUIRED => ProviderError::Unavailable(format!(
+            "DeepSeek account balance is insufficient: {message}"
+        )),
+        StatusCode::UNPROCESSABLE_ENTITY => {
+            ProviderError::InvalidRequest(format!("DeepSeek rejected the request: {message}"))
+        }
+        _ => ProviderError::upstream(status, headers, format!("DeepSeek error: {message}")),
+    }
+}
+
+/// Whether an SSE frame carries data; DeepSeek sends `: keep-alive` comments
+/// while a request is queued.
+fn is_data_frame(frame: &str) -> bool {
+    frame.lines().any(|line| line.starts_with("data:"))
+}
+
+/// Serves `deepseek-*` models from DeepSeek's OpenAI-compatible API.
+pub struct DeepSeekProvider {
+    api_key: String,
+    base_url: String,
+    timeout: Duration,
+}
+
+impl DeepSeekProvider {
+    /// `None` when no API key is configured.
+    #[must_use]
+    pub fn from_config(config: &DeepSeekConfig) -> Option<Self> {
+        let api_key = config.api_key.clone().filter(|key| !key.is_empty())?;
+        Some(Self {
+            api_key,
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            timeout: Duration::from_secs(config.timeout_secs),
+        })
+    }
+
+    /// POSTs `body` to the chat endpoint, mapping non-success responses
+    /// through [`map_error`].
+    async fn post(
+        &self,
+        state: &AppState,
+        body: &DeepSeekRequest<'_>,
+    ) -> ProviderResult<reqwest::Response> {
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|e| ProviderError::Internal(format!("Failed to create HTTP client: {e}")))?;
+        let url = format!("{}{}", self.base_url, CHAT_ENDPOINT);
+        let request = client
+            .post(&url)
+            .bearer_auth(&self.api_key)
+            .headers(trace_context::propagation_headers())
+            .json(body);
+        let resp = send_metered(request, &state.metrics, self.provider_type().name())
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    ProviderError::Timeout(format!("DeepSeek timed out: {e}"))
+                } else {
+                    ProviderError::Network(format!("Failed to contact DeepSeek at {url}: {e}"))
+                }
+            })?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let error_text = resp.text().await.unwrap_or_else(|e| {
+                warn!("Failed to read error response: {}", e);
+                String::new()
+            });
+            state
+                .metrics
+                .record_upstream_response_bytes(self.provider_type().name(), error_text.len());
+            let message = redaction::redact(&state.config.redaction, &error_message(&error_text));
+            return Err(map_error(status, &headers, message).with_body_detail(&error_text));
+        }
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl LLMProvider for DeepSeekProvider {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<ChatCompletionResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!("DeepSeek: Executing non-streaming request {}", request_id);
+
+        let body = DeepSeekRequest::from_request(&request, false);
+        cancellable(cancel, async {
+            let resp = self.post(state, &body).await?;
+            let bytes = read_metered(resp, &state.metrics, self.provider_type().name())
+                .await
+                .map_err(|e| {
+                    ProviderError::Internal(format!("Failed to read DeepSeek response: {e}"))
+                })?;
+            serde_json::from_slice(&bytes).map_err(|e| {
+                ProviderError::Internal(format!("Failed to parse DeepSeek response: {e}"))
+            })
+        })
+        .await
+    }
+
+    async fn execute_stream(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<StreamingResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!("DeepSeek: Executing streaming request {}", request_id);
+
+        let body = DeepSeekRequest::from_request(&request, true);
+        let response = cancellable(cancel, self.post(state, &body)).await?;
+
+        let mut pending = Vec::new();
+        let stream =
+            metered_bytes_stream(response, state.metrics.clone(), self.provider_type().name())
+                .flat_map(move |chunk_result| {
+                    let frames: Vec<_> = match chunk_result {
+                        Ok(bytes) => {
+                            pending.extend_from_slice(&bytes);
+                            take_sse_frames(&mut pending)
+                                .into_iter()
+                                .filter(|frame| is_data_frame(frame))
+                                .map(Ok)
+                                .collect()
+                        }
+                        Err(e) => {
+                            error!("DeepSeek stream error: {}", e);
+                            vec![Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)]
+                        }
+                    };
+                    futures::stream::iter(frames)
+                });
+
+        Ok(cancellable_stream(Box::pin(stream), cancel.clone()))
+    }
+
+    fn provider_type(&self) -> Provider {
+        Provider::DeepSeek
+    }
+
+    /// Lists models, which checks both reachability and the API key.
+    async fn probe(&self) -> Option<ProviderResult<()>> {
+        let client = match Client::builder().timeout(HEALTH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                return Some(Err(ProviderError::Internal(format!(
+                    "HTTP client unavailable: {e}"
+                ))))
+            }
+        };
+        let url = format!("{}{}", self.base_url, MODELS_ENDPOINT);
+        Some(
+            match client.get(&url).bearer_auth(&self.api_key).send().await {
+                Ok(resp) if resp.status().is_success() => Ok(()),
+                Ok(resp) => {
+                    let status = resp.status();
+                    let headers = resp.headers().clone();
+                    let body = resp.text().await.unwrap_or_default();
+                    Err(map_error(status, &headers, error_message(&body)))
+                }
+                Err(e) => Err(ProviderError::Unavailable(format!(
+                    "DeepSeek is unreachable: {e}"
+                ))),
+            },
+        )
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        model.starts_with("deepseek-")
+    }
+
+    fn validation_model(&self) -> Option<String> {
+        Some(VALIDATION_MODEL.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderMap;
+
+    #[test]
+    fn test_deepseek_request_drops_extensions() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "deepseek-chat",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "top_k": 5,
+            "max_tokens": 16,
+            "stop": "END",
+            "stream_options": {"include_usage": true}
+        }))
+        .unwrap();
+        let body = serde_json::to_value(DeepSeekRequest::from_request(&request, false)).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "deepseek-chat",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "stream": false,
+                "max_tokens": 16,
+                "stop": ["END"]
+            })
+        );
+        let body = serde_json::to_value(DeepSeekRequest::from_request(&request, true)).unwrap();
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[test]
+    fn test_deepseek_error_mapping() {
+        let headers = HeaderMap::new();
+        let message = error_message(
+            r#"{"error": {"message": "Authentication Fails", "type": "authentication_error"}}"#,
+        );
+        assert_eq!(message, "Authentication Fails");
+        assert!(matches!(
+            map_error(StatusCode::UNAUTHORIZED, &headers, message),
+            ProviderError::Auth(_)
+        ));
+        assert!(matches!(
+            map_error(StatusCode::PAYMENT_REQUIRED, &headers, String::new()),
+            ProviderError::Unavailable(_)
+        ));
+        let busy = map_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &headers,
+            "busy".to_string(),
+        );
+        assert!(busy.is_retryable());
+        assert_eq!(
+            map_error(StatusCode::TOO_MANY_REQUESTS, &headers, String::new()).status(),
+            429
+        );
+        assert!(!is_data_frame(": keep-alive\n\n"));
+        assert!(is_data_frame("data: [DONE]\n\n"));
+    }
+}
@@ -1,26 +1,62 @@
 use async_trait::async_trait;
 use futures::stream;
 use serde::Deserialize;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 use tokio::sync::Semaphore;
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use crate::{
+    config::{GeminiCliConfig, RedactionConfig},
     models::openai::{
         ChatCompletionChoice, ChatCompletionRequest, ChatCompletionResponse, ChatMessage,
         DeltaMessage, Role,
     },
-    services::providers::{
-        LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+    services::{
+        model_policy,
+        providers::{
+            cancellable, LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+        },
+        quota::{QuotaOverflow, QuotaStatus, RequestQuota},
+        redaction,
     },
     state::AppState,
 };
 
 const DEFAULT_CLI_TIMEOUT_SECS: u64 = 30;
 const MAX_CONCURRENT_REQUESTS: usize = 4;
+// Smallest prompt that still exercises auth end to end
+const HEALTH_PROBE_PROMPT: &str = "Reply with OK";
+// How long the quota counts as used up after the CLI reports it is, when the
+// local count missed it
+const QUOTA_BLOCK: Duration = Duration::from_secs(60);
+
+// Flags the provider sets itself; overriding them through `extra_args` would
+// break prompt delivery or JSON output parsing.
+const RESERVED_ARGS: &[&str] = &["-p", "--prompt", "-m", "--model", "-o", "--output-format"];
+
+/// Checks that configured extra arguments don't override provider-managed flags.
+///
+/// # Errors
+///
+/// Returns a description of the first reserved flag found.
+pub fn validate_extra_args(args: &[String]) -> Result<(), String> {
+    for arg in args {
+        let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+        if RESERVED_ARGS.contains(&flag) {
+            return Err(format!(
+                "'{arg}' is set by the proxy and cannot be overridden"
+            ));
+        }
+    }
+    Ok(())
+}
 
 /// Response structure for Gemini CLI JSON output
 #[derive(Deserialize)]
@@ -40,6 +76,39 @@ struct GeminiCliUsage {
     total: Option<u32>,
 }
 
+/// Prior conversation turns written in the CLI's checkpoint format (the
+/// `Content[]` history that `/chat save` produces). The file is readable only
+/// by the proxy's user and is removed when the request finishes.
+struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    async fn write(history: &[serde_json::Value]) -> ProviderResult<Self> {
+        let path = std::env::temp_dir().join(format!("gemini-checkpoint-{}.json", Uuid::new_v4()));
+        let body = serde_json::to_vec(history)
+            .map_err(|e| ProviderError::Internal(format!("Failed to serialize checkpoint: {e}")))?;
+        let write_error = |e| ProviderError::Internal(format!("Failed to write checkpoint: {e}"));
+        let mut options = tokio::fs::OpenOptions::new();
+        // create_new refuses a file or symlink planted at the path beforehand
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&path).await.map_err(write_error)?;
+        // From here on a failed write still removes the file
+        let checkpoint = Self { path };
+        file.write_all(&body).await.map_err(write_error)?;
+        file.flush().await.map_err(write_error)?;
+        Ok(checkpoint)
+    }
+}
+
+impl Drop for Checkpoint {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
 /// Provider for Google's Gemini CLI.
 ///
 /// This provider spawns `gemini` CLI processes to handle requests.
@@ -48,6 +117,15 @@ pub struct GeminiCliProvider {
     cli_path: String,
     timeout_secs: u64,
     concurrency_semaphore: Arc<Semaphore>,
+    working_dir: Option<PathBuf>,
+    sandbox: bool,
+    allowed_tools: Vec<String>,
+    extensions: Vec<String>,
+    extra_args: Vec<String>,
+    models: Vec<String>,
+    checkpoint_flag: Option<String>,
+    quota: RequestQuota,
+    quota_overflow: QuotaOverflow,
 }
 
 impl GeminiCliProvider {
@@ -75,6 +153,37 @@ impl GeminiCliProvider {
             cli_path: cli_path.unwrap_or_else(|| "gemini".to_string()),
             timeout_secs: timeout_secs.unwrap_or(DEFAULT_CLI_TIMEOUT_SECS),
             concurrency_semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            working_dir: None,
+            sandbox: false,
+            allowed_tools: Vec::new(),
+            extensions: Vec::new(),
+            extra_args: Vec::new(),
+            models: GeminiCliConfig::default().models,
+            checkpoint_flag: None,
+            quota: RequestQuota::new(None, None),
+            quota_overflow: QuotaOverflow::default(),
+        }
+    }
+
+    /// Create a provider from configuration, including the working directory,
+    /// sandbox, tool and extension settings used for each CLI invocation.
+    #[must_use]
+    pub fn from_config(config: &GeminiCliConfig) -> Self {
+        Self {
+            working_dir: config.working_dir.as_ref().map(PathBuf::from),
+            sandbox: config.sandbox,
+            allowed_tools: config.allowed_tools.clone(),
+            extensions: config.extensions.clone(),
+            extra_args: config.extra_args.clone(),
+            models: config.models.clone(),
+            checkpoint_flag: config.checkpoint_flag.clone(),
+            quota: RequestQuota::new(config.requests_per_minute, config.requests_per_day),
+            quota_overflow: config.quota_overflow,
+            ..Self::new(
+                config.cli_path.clone(),
+                Some(config.timeout_secs),
+                Some(config.max_concurrency),
+            )
         }
     }
 
@@ -96,16 +205,69 @@ impl GeminiCliProvider {
         })
     }
 
-    fn build_cli_command(&self, prompt: &str, model: Option<&str>) -> Command {
+    /// Counts the request against the configured quota. With the `queue`
+    /// overflow policy, waits for a free slot if one opens within the
+    /// request timeout.
+    async fn reserve_quota(&self) -> Result<(), ProviderError> {
+        loop {
+            match self.quota.try_acquire() {
+                Ok(()) => return Ok(()),
+                Err(wait)
+                    if self.quota_overflow == QuotaOverflow::Queue
+                        && wait <= Duration::from_secs(self.timeout_secs) =>
+                {
+                    debug!(
+                        "Gemini CLI quota used up, waiting {}ms for a slot",
+                        wait.as_millis()
+                    );
+                    tokio::time::sleep(wait).await;
+                }
+                Err(wait) => {
+                    return Err(ProviderError::RateLimited(format!(
+                        "Gemini CLI quota used up - next request allowed in {}s",
+                        wait.as_secs().max(1)
+                    )))
+                }
+            }
+        }
+    }
+
+    fn build_cli_command(
+        &self,
+        prompt: &str,
+        model: Option<&str>,
+        checkpoint: Option<&Path>,
+    ) -> Command {
         let mut cmd = Command::new(&self.cli_path);
         cmd.arg("-p").arg(prompt);
 
+        if let (Some(flag), Some(path)) = (&self.checkpoint_flag, checkpoint) {
+            cmd.arg(flag).arg(path);
+        }
+
         if let Some(model_name) = model {
             cmd.arg("-m").arg(model_name);
         }
 
         cmd.arg("--output-format").arg("json");
+
+        if self.sandbox {
+            cmd.arg("--sandbox");
+        }
+        for tool in &self.allowed_tools {
+            cmd.arg("--allowed-tools").arg(tool);
+        }
+        for extension in &self.extensions {
+            cmd.arg("--extensions").arg(extension);
+        }
+        cmd.args(&self.extra_args);
+
+        if let Some(dir) = &self.working_dir {
+            cmd.current_dir(dir);
+        }
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        // Timeouts and cancellation drop the wait; the child must not outlive it
+        cmd.kill_on_drop(true);
         cmd
     }
 
@@ -129,7 +291,9 @@ impl GeminiCliProvider {
             .map_err(|e| ProviderError::Internal(format!("Failed to execute Gemini CLI: {e}")))
     }
 
-    fn map_cli_error_to_provider_error(stderr: &str) -> ProviderError {
+    /// Classifies a failure by its `stderr`, which is redacted under
+    /// `redaction` wherever it is quoted.
+    fn map_cli_error_to_provider_error(stderr: &str, redaction: &RedactionConfig) -> ProviderError {
         let error_msg = stderr.to_lowercase();
 
         // Timeout errors
@@ -207,25 +371,32 @@ impl GeminiCliProvider {
         {
             return ProviderError::InvalidRequest(format!(
                 "Gemini CLI rejected request: {}",
-                stderr.trim()
+                redaction::redact(redaction, stderr.trim())
             ));
         }
 
         // Generic internal error fallback
-        ProviderError::Internal(format!("Gemini CLI command failed: {stderr}"))
+        ProviderError::Internal(format!(
+            "Gemini CLI command failed: {}",
+            redaction::redact(redaction, stderr)
+        ))
     }
 
     async fn execute_cli_command(
         &self,
         prompt: &str,
         model: Option<&str>,
+        checkpoint: Option<&Path>,
+        redaction: &RedactionConfig,
     ) -> Result<String, ProviderError> {
+        self.reserve_quota().await?;
         let _permit = self.acquire_concurrency_permit().await?;
-        let cmd = self.build_cli_command(prompt, model);
+        let cmd = self.build_cli_command(prompt, model, checkpoint);
 
         info!(
             "Gemini CLI: Executing command: {} -p \"{}\"",
-            self.cli_path, prompt
+            self.cli_path,
+            redaction::redact(redaction, prompt)
         );
 
         let output = self.execute_cli_process(cmd).await?;
@@ -235,11 +406,16 @@ impl GeminiCliProvider {
             let stdout = String::from_utf8_lossy(&output.stdout);
             error!(
                 "Gemini CLI command failed (exit code: {}): {}",
-                output.status, stderr
+                output.status,
+                redaction::redact(redaction, &stderr)
             );
 
             // Try to map based on stderr content first
-            let provider_error = Self::map_cli_error_to_provider_error(&stderr);
+            let provider_error = Self::map_cli_error_to_provider_error(&stderr, redaction);
+            if matches!(provider_error, ProviderError::RateLimited(_)) && self.quota.is_limited() {
+                warn!("Gemini CLI reported its quota used up before the local count did");
+                self.quota.block_for(QUOTA_BLOCK);
+            }
 
             // If it's still a generic internal error, add more context from exit code
             if let ProviderError::Internal(_) = provider_error {
@@ -262,8 +438,8 @@ impl GeminiCliProvider {
                         _ => ProviderError::Internal(format!(
                             "Gemini CLI failed (exit code: {}): {} (stdout: {})",
                             output.status,
-                            stderr.trim(),
-                            stdout.trim()
+                            redaction::redact(redaction, stderr.trim()),
+                            redaction::redact(redaction, stdout.trim())
                         )),
                     };
                     return Err(detailed_error);
@@ -368,14 +544,19 @@ impl GeminiCliProvider {
                 role: Role::Assistant,
                 content: cli_response.response,
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             },
             finish_reason: Some("stop".to_string()),
         };
 
-        let usage = cli_response.usage.map(|u| crate::models::openai::Usage {
-            prompt_tokens: u.prompt.unwrap_or(0),
-            completion_tokens: u.candidates.unwrap_or(0),
-            total_tokens: u.total.unwrap_or(0),
+        let usage = cli_response.usage.map(|u| {
+            crate::models::openai::Usage::new(
+                u.prompt.unwrap_or(0),
+                u.candidates.unwrap_or(0),
+                u.total.unwrap_or(0),
+            )
         });
 
         ChatCompletionResponse {
@@ -400,21 +581,37 @@ impl LLMProvider for GeminiCliProvider {
     async fn execute(
         &self,
         request: ChatCompletionRequest,
-        _state: &AppState,
+        state: &AppState,
+        cancel: &CancellationToken,
     ) -> ProviderResult<ChatCompletionResponse> {
         let request_id = Uuid::new_v4().to_string();
         info!("Gemini CLI: Executing non-streaming request {}", request_id);
 
         // Convert OpenAI messages to Gemini CLI prompt
-        let prompt = Self::convert_messages_to_prompt(&request.messages)?;
+        let (prompt, checkpoint) = self.prepare_prompt(&request.messages).await?;
+        // The CLI is the upstream here: the prompt goes in and its JSON output comes back
+        state
+            .metrics
+            .record_upstream_request(self.provider_type().name(), prompt.len());
 
         // Execute CLI command
-        let output = tokio::time::timeout(
-            std::time::Duration::from_secs(self.timeout_secs),
-            self.execute_cli_command(&prompt, Some(&request.model)),
-        )
-        .await
-        .map_err(|_| ProviderError::Timeout("Gemini CLI request timed out".to_string()))??;
+        let output = cancellable(cancel, async {
+            tokio::time::timeout(
+                std::time::Duration::from_secs(self.timeout_secs),
+                self.execute_cli_command(
+                    &prompt,
+                    Some(&request.model),
+                    checkpoint.as_ref().map(|c| c.path.as_path()),
+                    &state.config.redaction,
+                ),
+            )
+            .await
+            .map_err(|_| ProviderError::Timeout("Gemini CLI request timed out".to_string()))?
+        })
+        .await?;
+        state
+            .metrics
+            .record_upstream_response_bytes(self.provider_type().name(), output.len());
 
         // Parse response
         let cli_response = Self::parse_cli_response(&output)?;
@@ -428,24 +625,39 @@ impl LLMProvider for GeminiCliProvider {
     async fn execute_stream(
         &self,
         request: ChatCompletionRequest,
-        _state: &AppState,
+        state: &AppState,
+        cancel: &CancellationToken,
     ) -> ProviderResult<StreamingResponse> {
         let request_id = Uuid::new_v4().to_string();
         info!("Gemini CLI: Executing streaming request {}", request_id);
 
         // Convert OpenAI messages to Gemini CLI prompt
-        let prompt = Self::convert_messages_to_prompt(&request.messages)?;
+        let (prompt, checkpoint) = self.prepare_prompt(&request.messages).await?;
+        state
+            .metrics
+            .record_upstream_request(self.provider_type().name(), prompt.len());
 
         // For streaming, we'll simulate it by returning the full response as a single chunk
         // Gemini CLI doesn't have native streaming support in non-interactive mode
-        let output = tokio::time::timeout(
-            std::time::Duration::from_secs(self.timeout_secs),
-            self.execute_cli_command(&prompt, Some(&request.model)),
-        )
-        .await
-        .map_err(|_| {
-            ProviderError::Timeout("Gemini CLI streaming request timed out".to_string())
-        })??;
+        let output = cancellable(cancel, async {
+            tokio::time::timeout(
+                std::time::Duration::from_secs(self.timeout_secs),
+                self.execute_cli_command(
+                    &prompt,
+                    Some(&request.model),
+                    checkpoint.as_ref().map(|c| c.path.as_path()),
+                    &state.config.redaction,
+                ),
+            )
+            .await
+            .map_err(|_| {
+                ProviderError::Timeout("Gemini CLI streaming request timed out".to_string())
+            })?
+        })
+        .await?;
+        state
+            .metrics
+            .record_upstream_response_bytes(self.provider_type().name(), output.len());
 
         let cli_response = Self::parse_cli_response(&output)?;
 
@@ -471,8 +683,44 @@ impl LLMProvider for GeminiCliProvider {
         Provider::GeminiCLI
     }
 
+    async fn probe(&self) -> Option<ProviderResult<()>> {
+        let model = self
+            .models
+            .iter()
+            .find(|m| !m.ends_with('*'))
+            .map(String::as_str);
+        let result = self
+            .execute_cli_command(
+                HEALTH_PROBE_PROMPT,
+                model,
+                None,
+                &RedactionConfig::default(),
+            )
+            .await
+            .and_then(|output| Self::parse_cli_response(&output).map(|_| ()));
+        Some(result)
+    }
+
     fn supports_model(&self, model: &str) -> bool {
-        model.starts_with("gemini-")
+        self.models
+            .iter()
+            .any(|served| model_policy::matches_pattern(served, model))
+    }
+
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    fn validation_model(&self) -> Option<String> {
+        self.models.iter().find(|m| !m.ends_with('*')).cloned()
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.quota_overflow == QuotaOverflow::Queue || self.quota.has_capacity()
+    }
+
+    fn quota(&self) -> Option<QuotaStatus> {
+        self.quota.is_limited().then(|| self.quota.status())
     }
 }
 
@@ -493,6 +741,7 @@ impl GeminiCliProvider {
         let role_chunk = crate::models::openai::ChatCompletionChunk {
             id: request_id.to_string(),
             object: "chat.completion.chunk".to_string(),
+            usage: None,
             created: base_timestamp,
             model: model.to_string(),
             choices: vec![crate::models::openai::ChatCompletionChunkChoice {
@@ -500,6 +749,7 @@ impl GeminiCliProvider {
                 delta: DeltaMessage {
                     role: Some(Role::Assistant),
                     content: None,
+                    tool_calls: None,
                 },
                 finish_reason: None,
             }],
@@ -517,6 +767,7 @@ impl GeminiCliProvider {
             let content_chunk = crate::models::openai::ChatCompletionChunk {
                 id: request_id.to_string(),
                 object: "chat.completion.chunk".to_string(),
+                usage: None,
                 created: base_timestamp + (offset as u64 * CHUNK_DELAY_MS / 1000),
                 model: model.to_string(),
                 choices: vec![crate::models::openai::ChatCompletionChunkChoice {
@@ -524,6 +775,7 @@ impl GeminiCliProvider {
                     delta: DeltaMessage {
                         role: None,
                         content: Some(chunk_content),
+                        tool_calls: None,
                     },
                     finish_reason: if end == chars.len() {
                         Some("stop".to_string())
@@ -544,6 +796,7 @@ impl GeminiCliProvider {
             let empty_chunk = crate::models::openai::ChatCompletionChunk {
                 id: request_id.to_string(),
                 object: "chat.completion.chunk".to_string(),
+                usage: None,
                 created: base_timestamp,
                 model: model.to_string(),
                 choices: vec![crate::models::openai::ChatCompletionChunkChoice {
@@ -551,6 +804,7 @@ impl GeminiCliProvider {
                     delta: DeltaMessage {
                         role: None,
                         content: Some(String::new()),
+                        tool_calls: None,
                     },
                     finish_reason: Some("stop".to_string()),
                 }],
@@ -567,6 +821,65 @@ impl GeminiCliProvider {
         Ok(chunks)
     }
 
+    /// Builds the `-p` prompt and, when checkpoints are configured and there are
+    /// prior turns, the checkpoint file holding them.
+    async fn prepare_prompt(
+        &self,
+        messages: &[ChatMessage],
+    ) -> ProviderResult<(String, Option<Checkpoint>)> {
+        if self.checkpoint_flag.is_some() {
+            if let Some((history, prompt)) = Self::split_history(messages) {
+                return Ok((prompt, Some(Checkpoint::write(&history).await?)));
+            }
+        }
+        Ok((Self::convert_messages_to_prompt(messages)?, None))
+    }
+
+    /// Splits a conversation into checkpoint history and the final user prompt.
+    ///
+    /// User and assistant turns become `user`/`model` contents, merging
+    /// consecutive turns of the same role. System messages have no checkpoint
+    /// role, so they are prefixed to the prompt. Returns `None` when the last
+    /// message isn't from the user or there are no prior turns to carry.
+    fn split_history(messages: &[ChatMessage]) -> Option<(Vec<serde_json::Value>, String)> {
+        let (last, prior) = messages.split_last()?;
+        if !matches!(last.role, Role::User) {
+            return None;
+        }
+
+        let mut system = Vec::new();
+        let mut turns: Vec<(&str, Vec<serde_json::Value>)> = Vec::new();
+        for message in prior {
+            let role = match message.role {
+                Role::System => {
+                    system.push(format!("System: {}", message.content));
+                    continue;
+                }
+                Role::User => "user",
+                Role::Assistant => "model",
+                Role::Tool => {
+                    warn!("Tool messages not supported by Gemini CLI provider");
+                    continue;
+                }
+            };
+            let part = serde_json::json!({ "text": message.content });
+            match turns.last_mut() {
+                Some((last_role, parts)) if *last_role == role => parts.push(part),
+                _ => turns.push((role, vec![part])),
+            }
+        }
+        if turns.is_empty() {
+            return None;
+        }
+
+        let history = turns
+            .into_iter()
+            .map(|(role, parts)| serde_json::json!({ "role": role, "parts": parts }))
+            .collect();
+        system.push(last.content.clone());
+        Some((history, system.join("\n\n")))
+    }
+
     fn convert_messages_to_prompt(messages: &[ChatMessage]) -> Result<String, ProviderError> {
         let mut prompt_parts = Vec::new();
 
@@ -605,13 +918,266 @@ mod tests {
     #[test]
     fn test_supports_model() {
         let provider = GeminiCliProvider::default();
-        assert!(provider.supports_model("gemini-pro"));
+        assert!(provider.supports_model("gemini-2.5-pro"));
         assert!(provider.supports_model("gemini-2.5-flash"));
-        assert!(provider.supports_model("gemini-1.5-pro"));
+        assert!(!provider.supports_model("gemini-pro"));
+        assert!(!provider.supports_model("gemini-1.5-pro"));
         assert!(!provider.supports_model("claude-3-opus"));
         assert!(!provider.supports_model("gpt-4"));
     }
 
+    #[test]
+    fn test_supports_model_configured_list() {
+        let config = GeminiCliConfig {
+            models: vec!["gemini-3-*".to_string(), "gemini-2.5-pro".to_string()],
+            ..GeminiCliConfig::default()
+        };
+        let provider = GeminiCliProvider::from_config(&config);
+        assert!(provider.supports_model("gemini-3-pro-preview"));
+        assert!(provider.supports_model("gemini-2.5-pro"));
+        assert!(!provider.supports_model("gemini-2.5-flash"));
+    }
+
+    #[test]
+    fn test_build_cli_command_applies_config() {
+        let config = GeminiCliConfig {
+            cli_path: Some("/opt/gemini".to_string()),
+            working_dir: Some("/srv/workspace".to_string()),
+            sandbox: true,
+            allowed_tools: vec!["read_file".to_string(), "glob".to_string()],
+            extensions: vec!["docs".to_string()],
+            extra_args: vec!["--debug".to_string()],
+            ..GeminiCliConfig::default()
+        };
+        let provider = GeminiCliProvider::from_config(&config);
+        let cmd = provider.build_cli_command("hi", Some("gemini-pro"), None);
+        let cmd = cmd.as_std();
+
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(cmd.get_program(), "/opt/gemini");
+        assert_eq!(
+            args,
+            [
+                "-p",
+                "hi",
+                "-m",
+                "gemini-pro",
+                "--output-format",
+                "json",
+                "--sandbox",
+                "--allowed-tools",
+                "read_file",
+                "--allowed-tools",
+                "glob",
+                "--extensions",
+                "docs",
+                "--debug",
+            ]
+        );
+        assert_eq!(
+            cmd.get_current_dir(),
+            Some(std::path::Path::new("/srv/workspace"))
+        );
+    }
+
+    fn message(role: Role, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            name: None,
+            images: 0,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_split_history_into_checkpoint() {
+        let messages = vec![
+            message(Role::System, "Be brief"),
+            message(Role::User, "Hi"),
+            message(Role::Assistant, "Hello!"),
+            message(Role::User, "What is 2+2?"),
+            message(Role::User, "Answer in words."),
+        ];
+        let (history, prompt) =
+            GeminiCliProvider::split_history(&messages).expect("conversation has prior turns");
+        assert_eq!(
+            serde_json::Value::Array(history),
+            serde_json::json!([
+                {"role": "user", "parts": [{"text": "Hi"}]},
+                {"role": "model", "parts": [{"text": "Hello!"}]},
+                {"role": "user", "parts": [{"text": "What is 2+2?"}]},
+            ])
+        );
+        assert_eq!(prompt, "System: Be brief\n\nAnswer in words.");
+
+        // Nothing to carry over for a single turn or a trailing assistant message
+        assert!(GeminiCliProvider::split_history(&messages[..2]).is_none());
+        assert!(GeminiCliProvider::split_history(&messages[..3]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_passed_to_cli() {
+        let config = GeminiCliConfig {
+            checkpoint_flag: Some("--checkpoint".to_string()),
+            ..GeminiCliConfig::default()
+        };
+        let provider = GeminiCliProvider::from_config(&config);
+        let messages = vec![
+            message(Role::User, "Hi"),
+            message(Role::Assistant, "Hello!"),
+            message(Role::User, "Bye"),
+        ];
+
+        let (prompt, checkpoint) = provider
+            .prepare_prompt(&messages)
+            .await
+            .expect("prompt should build");
+        let checkpoint = checkpoint.expect("prior turns go to a checkpoint");
+        assert_eq!(prompt, "Bye");
+        let saved: serde_json::Value = serde_json::from_slice(
+            &std::fs::read(&checkpoint.path).expect("checkpoint file exists"),
+        )
+        .expect("checkpoint is JSON");
+        assert_eq!(saved[1]["role"], "model");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&checkpoint.path)
+                .expect("checkpoint file exists")
+                .permissions()
+                .mode();
+            assert_eq!(
+                mode & 0o777,
+                0o600,
+                "checkpoint is private to the proxy user"
+            );
+        }
+
+        let cmd = provider.build_cli_command(&prompt, None, Some(&checkpoint.path));
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert_eq!(args[2], "--checkpoint");
+        assert_eq!(args[3], checkpoint.path.as_os_str());
+
+        let path = checkpoint.path.clone();
+        drop(checkpoint);
+        assert!(!path.exists(), "checkpoint is removed after the request");
+    }
+
+    #[test]
+    fn test_validate_extra_args() {
+        assert!(validate_extra_args(&["--debug".to_string(), "--yolo".to_string()]).is_ok());
+        assert!(validate_extra_args(&["-m".to_string(), "gemini-pro".to_string()]).is_err());
+        assert!(validate_extra_args(&["--output-format=text".to_string()]).is_err());
+    }
+
+    fn fake_cli(script: &str) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("gemini-{}", Uuid::new_v4()));
+        std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).expect("write fake CLI");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .expect("make fake CLI executable");
+        path
+    }
+
+    async fn probe_with(script: &str) -> Option<ProviderResult<()>> {
+        let cli = fake_cli(script);
+        let provider =
+            GeminiCliProvider::new(Some(cli.to_string_lossy().into_owned()), Some(5), None);
+        let result = provider.probe().await;
+        let _ = std::fs::remove_file(cli);
+        result
+    }
+
+    #[tokio::test]
+    async fn test_probe_reports_cli_auth_status() {
+        assert!(matches!(
+            probe_with(r#"echo '{"response": "OK"}'"#).await,
+            Some(Ok(()))
+        ));
+        assert!(matches!(
+            probe_with("echo 'Error: not authenticated, run gemini to login' >&2; exit 1").await,
+            Some(Err(ProviderError::Auth(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_quota_exhaustion_reported_by_cli_blocks_requests() {
+        let cli = fake_cli("echo 'Error: Quota exceeded for quota metric' >&2; exit 1");
+        let provider = GeminiCliProvider::from_config(&GeminiCliConfig {
+            cli_path: Some(cli.to_string_lossy().into_owned()),
+            requests_per_minute: Some(5),
+            requests_per_day: Some(100),
+            ..GeminiCliConfig::default()
+        });
+        assert!(provider.has_capacity());
+
+        let result = provider
+            .execute_cli_command("hi", None, None, &RedactionConfig::default())
+            .await;
+        assert!(matches!(result, Err(ProviderError::RateLimited(_))));
+        let quota = provider.quota().expect("limits are configured");
+        assert_eq!(quota.minute_remaining, Some(0));
+        assert_eq!(quota.day_remaining, Some(99));
+        assert!(!provider.has_capacity());
+
+        // Refused locally without spawning the CLI again
+        let _ = std::fs::remove_file(&cli);
+        let result = provider
+            .execute_cli_command("hi", None, None, &RedactionConfig::default())
+            .await;
+        assert!(
+            matches!(result, Err(ProviderError::RateLimited(ref m)) if m.contains("quota used up"))
+        );
+        assert_eq!(provider.quota().and_then(|q| q.day_remaining), Some(99));
+        assert!(GeminiCliProvider::default().quota().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_kills_cli_process() {
+        let pid_file = std::env::temp_dir().join(format!("gemini-pid-{}", Uuid::new_v4()));
+        let cli = fake_cli(&format!("echo $$ > {}\nexec sleep 30", pid_file.display()));
+        let provider =
+            GeminiCliProvider::new(Some(cli.to_string_lossy().into_owned()), Some(60), None);
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        let pid_path = pid_file.clone();
+        tokio::spawn(async move {
+            while !pid_path.exists() {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            trigger.cancel();
+        });
+
+        let result = cancellable(
+            &cancel,
+            provider.execute_cli_command("hi", None, None, &RedactionConfig::default()),
+        )
+        .await;
+        assert!(matches!(result, Err(ProviderError::Cancelled(_))));
+
+        let pid = std::fs::read_to_string(&pid_file).expect("pid written");
+        let mut alive = true;
+        for _ in 0..50 {
+            let status = std::process::Command::new("kill")
+                .args(["-0", pid.trim()])
+                .stderr(Stdio::null())
+                .status()
+                .expect("run kill");
+            if !status.success() {
+                alive = false;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let _ = std::fs::remove_file(cli);
+        let _ = std::fs::remove_file(pid_file);
+        assert!(!alive, "CLI process should be killed on cancellation");
+    }
+
     #[test]
     fn test_provider_type() {
         let provider = GeminiCliProvider::default();
@@ -625,11 +1191,17 @@ mod tests {
                 role: Role::System,
                 content: "You are a helpful assistant".to_string(),
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             },
             ChatMessage {
                 role: Role::User,
                 content: "Hello".to_string(),
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             },
         ];
 
@@ -1,12 +1,32 @@
 pub mod anthropic;
+pub mod bedrock;
+pub mod deepseek;
 pub mod gemini_cli;
+pub mod ollama;
+pub mod openai_compatible;
 pub mod vertex;
 
 use crate::models::openai::{ChatCompletionRequest, ChatCompletionResponse};
+use crate::openai::metrics::Metrics;
+use crate::services::quota::QuotaStatus;
+use crate::services::upstream_headers;
 use crate::state::AppState;
 use async_trait::async_trait;
-use futures::stream::Stream;
+use axum::body::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+// Sent with `max_tokens: 1`, so any reply at all proves the provider works
+const VALIDATION_PROMPT: &str = "Reply with OK.";
 
 pub type ProviderResult<T> = Result<T, ProviderError>;
 pub type StreamingResponse =
@@ -17,14 +37,26 @@ pub enum Provider {
     Vertex,
     AnthropicCLI,
     GeminiCLI,
-    // Fix dead code: These variants are not implemented yet
-    // TODO: Implement DeepSeek provider or remove variant
-    #[allow(dead_code)]
     DeepSeek,
-    // Fix dead code: These variants are not implemented yet
-    // TODO: Implement Ollama provider or remove variant
-    #[allow(dead_code)]
     Ollama,
+    Bedrock,
+    OpenAICompatible,
+}
+
+impl Provider {
+    /// Stable snake_case name used in health and status output.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Vertex => "vertex",
+            Self::AnthropicCLI => "anthropic_cli",
+            Self::GeminiCLI => "gemini_cli",
+            Self::DeepSeek => "deepseek",
+            Self::Ollama => "ollama",
+            Self::Bedrock => "bedrock",
+            Self::OpenAICompatible => "openai_compatible",
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -45,57 +77,418 @@ pub enum ProviderError {
     Internal(String),
     #[error("Circuit breaker open: {0}")]
     CircuitOpen(#[from] crate::openai::circuit_breaker::CircuitOpenError),
+    /// Non-success HTTP response from the upstream API or bridge.
+    #[error("Upstream HTTP {status}: {message}")]
+    Upstream {
+        status: u16,
+        message: String,
+        retry_after: Option<Duration>,
+        /// The provider's structured error body, when it sent one.
+        detail: Option<serde_json::Value>,
+    },
+    /// The request was abandoned: the client disconnected, its deadline passed
+    /// or the server is shutting down.
+    #[error("Request cancelled: {0}")]
+    Cancelled(String),
+}
+
+impl ProviderError {
+    /// Builds an `Upstream` error from a failed response's status and headers.
+    #[must_use]
+    pub fn upstream(status: StatusCode, headers: &HeaderMap, message: String) -> Self {
+        Self::Upstream {
+            status: status.as_u16(),
+            message,
+            retry_after: parse_retry_after(headers),
+            detail: None,
+        }
+    }
+
+    /// Keeps a JSON error `body` as the `Upstream` error's detail. Google's
+    /// `{"error": {...}}` envelope (or a one-element array of it, from
+    /// streaming endpoints) is unwrapped; a bare error string becomes
+    /// `{"message": ...}`. Bodies that are not JSON objects are ignored.
+    #[must_use]
+    pub fn with_body_detail(mut self, body: &str) -> Self {
+        if let Self::Upstream { detail, .. } = &mut self {
+            *detail = parse_error_detail(body);
+        }
+        self
+    }
+
+    /// The provider's structured error body, if it sent one.
+    #[must_use]
+    pub fn detail(&self) -> Option<&serde_json::Value> {
+        match self {
+            Self::Upstream { detail, .. } => detail.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// HTTP status returned to the client for this error.
+    ///
+    /// Upstream 400s and 429s are the caller's to fix or wait out, so they pass
+    /// through; other upstream failures surface as 503 (504 for gateway timeouts).
+    #[must_use]
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::Auth(_) => 401,
+            Self::Network(_) => 502,
+            Self::Unavailable(_) | Self::CircuitOpen(_) | Self::Cancelled(_) => 503,
+            Self::Timeout(_) => 504,
+            Self::InvalidRequest(_) => 400,
+            Self::RateLimited(_) => 429,
+            Self::Internal(_) => 500,
+            Self::Upstream { status, .. } => match status {
+                400 | 429 | 504 => *status,
+                _ => 503,
+            },
+        }
+    }
+
+    /// Delay the upstream asked for before retrying, if it sent `Retry-After`.
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Upstream { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Whether the same request may succeed if retried or sent to another provider.
+    ///
+    /// Transport failures, timeouts, rate limits and upstream 408/409/5xx are
+    /// retryable. Auth and request errors, proxy-internal failures, an open
+    /// circuit and cancellation are not. The circuit breaker only counts
+    /// retryable failures.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Network(_) | Self::Timeout(_) | Self::Unavailable(_) | Self::RateLimited(_) => {
+                true
+            }
+            Self::Auth(_)
+            | Self::InvalidRequest(_)
+            | Self::Internal(_)
+            | Self::CircuitOpen(_)
+            | Self::Cancelled(_) => false,
+            Self::Upstream { status, .. } => matches!(status, 408 | 409 | 429 | 500..=599),
+        }
+    }
+}
+
+fn parse_error_detail(body: &str) -> Option<serde_json::Value> {
+    use serde_json::Value;
+
+    let body = match serde_json::from_str::<Value>(body).ok()? {
+        Value::Array(mut items) if items.len() == 1 => items.pop()?,
+        body => body,
+    };
+    match body.get("error") {
+        Some(error @ Value::Object(_)) => Some(error.clone()),
+        Some(Value::String(message)) => Some(serde_json::json!({ "message": message })),
+        _ => body.is_object().then_some(body),
+    }
+}
+
+/// Parses a `Retry-After` header given in delta-seconds.
+///
+/// The HTTP-date form is not used by the providers we talk to and is ignored.
+#[must_use]
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Runs a provider call until it completes or `cancel` fires.
+///
+/// Cancellation drops `call`, which aborts in-flight HTTP requests and kills
+/// CLI child processes (they are spawned with `kill_on_drop`).
+///
+/// # Errors
+///
+/// Returns the call's own error, or `ProviderError::Cancelled` if the token
+/// fired first.
+pub async fn cancellable<T>(
+    cancel: &CancellationToken,
+    call: impl Future<Output = ProviderResult<T>>,
+) -> ProviderResult<T> {
+    tokio::select! {
+        biased;
+        () = cancel.cancelled() => Err(ProviderError::Cancelled(
+            "request abandoned before the provider responded".to_string(),
+        )),
+        result = call => result,
+    }
+}
+
+/// Ends `inner` with a `Cancelled` error once `cancel` fires.
+#[must_use]
+pub fn cancellable_stream(
+    inner: StreamingResponse,
+    cancel: CancellationToken,
+) -> StreamingResponse {
+    let ended = cancel.clone();
+    let tail = stream::once(async move {
+        ended.is_cancelled().then(|| {
+            Err(Box::new(ProviderError::Cancelled(
+                "stream abandoned before it completed".to_string(),
+            )) as Box<dyn std::error::Error + Send + Sync>)
+        })
+    })
+    .filter_map(|item| async move { item });
+    Box::pin(inner.take_until(cancel.cancelled_owned()).chain(tail))
+}
+
+/// Sends `builder`, counting the call and its body size as `provider`'s
+/// upstream traffic, and captures the response headers the client asked to
+/// have forwarded.
+///
+/// # Errors
+///
+/// Returns the error from building or sending the request.
+pub async fn send_metered(
+    builder: reqwest::RequestBuilder,
+    metrics: &Metrics,
+    provider: &str,
+) -> reqwest::Result<reqwest::Response> {
+    let (client, request) = builder.build_split();
+    let request = request?;
+    let bytes = request
+        .body()
+        .and_then(reqwest::Body::as_bytes)
+        .map_or(0, <[u8]>::len);
+    metrics.record_upstream_request(provider, bytes);
+    let response = client.execute(request).await?;
+    upstream_headers::record_response(response.headers());
+    Ok(response)
+}
+
+/// Reads `response`'s whole body, counting it as `provider`'s upstream traffic.
+///
+/// # Errors
+///
+/// Returns the error from reading the body.
+pub async fn read_metered(
+    response: reqwest::Response,
+    metrics: &Metrics,
+    provider: &str,
+) -> reqwest::Result<Bytes> {
+    let body = response.bytes().await?;
+    metrics.record_upstream_response_bytes(provider, body.len());
+    Ok(body)
+}
+
+/// `response`'s body as a stream, counted as `provider`'s upstream traffic
+/// chunk by chunk.
+pub fn metered_bytes_stream(
+    response: reqwest::Response,
+    metrics: Arc<Metrics>,
+    provider: &'static str,
+) -> impl Stream<Item = reqwest::Result<Bytes>> {
+    response.bytes_stream().inspect(move |chunk| {
+        if let Ok(bytes) = chunk {
+            metrics.record_upstream_response_bytes(provider, bytes.len());
+        }
+    })
+}
+
+/// Removes the complete SSE frames (each ending in a blank line) from the
+/// front of `pending`. Handlers expect one frame per stream item, while a
+/// network read from an upstream can hold several or end partway through one.
+#[must_use]
+pub fn take_sse_frames(pending: &mut Vec<u8>) -> Vec<String> {
+    let mut frames = Vec::new();
+    loop {
+        // Extra blank lines between frames are not part of either
+        let blank = pending.iter().take_while(|&&b| b == b'\n').count();
+        pending.drain(..blank);
+        let Some(end) = pending.windows(2).position(|w| w == b"\n\n") else {
+            return frames;
+        };
+        let frame: Vec<u8> = pending.drain(..end + 2).collect();
+        frames.push(String::from_utf8_lossy(&frame).into_owned());
+    }
 }
 
 #[async_trait]
 pub trait LLMProvider: Send + Sync {
+    /// Runs a non-streaming completion. Implementations stop work and return
+    /// `ProviderError::Cancelled` once `cancel` fires (see [`cancellable`]).
     async fn execute(
         &self,
         request: ChatCompletionRequest,
         state: &AppState,
+        cancel: &CancellationToken,
     ) -> ProviderResult<ChatCompletionResponse>;
 
+    /// Starts a streaming completion. The returned stream ends with a
+    /// `Cancelled` error once `cancel` fires (see [`cancellable_stream`]).
     async fn execute_stream(
         &self,
         request: ChatCompletionRequest,
         state: &AppState,
+        cancel: &CancellationToken,
     ) -> ProviderResult<StreamingResponse>;
 
     fn provider_type(&self) -> Provider;
 
     fn supports_model(&self, model: &str) -> bool;
+
+    /// Whether the provider forwards function `tools` and tool messages.
+    /// Requests using tools are rejected with a 400 for providers that would
+    /// drop them.
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Runs a lightweight check that the provider can serve requests, e.g. that
+    /// a CLI is installed and authenticated. Providers whose health is covered
+    /// elsewhere return `None`.
+    async fn probe(&self) -> Option<ProviderResult<()>> {
+        None
+    }
+
+    /// Extra facts from the last probe (e.g. a bridge's version), shown on
+    /// `/health` next to the probe outcome.
+    fn health_details(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Model to send a one-token request to when validating the provider end
+    /// to end (`/admin/providers/validate`). `None` skips validation.
+    fn validation_model(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the provider can take a request now without exceeding a quota.
+    /// The router prefers the next provider serving the model over one that
+    /// cannot.
+    fn has_capacity(&self) -> bool {
+        true
+    }
+
+    /// Requests left under the provider's quotas, if it tracks any.
+    fn quota(&self) -> Option<QuotaStatus> {
+        None
+    }
+}
+
+/// Why the registry picked a provider for a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteReason {
+    /// The highest-precedence provider whose model list or prefix claims the model.
+    PrefixMatch,
+    /// The cheapest healthy model of an alias group, chosen by the `cheapest` strategy.
+    Cheapest,
+    /// The healthy alias group model with the lowest recent p95 latency.
+    Fastest,
+    /// The alias group model the request's session was already pinned to.
+    Sticky,
+    /// A content-based routing rule chose the model or ruled out providers.
+    Rule,
+    /// The first provider serving the model had used up its quota.
+    QuotaOverflow,
+}
+
+impl RouteReason {
+    /// Label used in logs, metrics and span fields.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PrefixMatch => "prefix_match",
+            Self::Cheapest => "cheapest",
+            Self::Fastest => "fastest",
+            Self::Sticky => "sticky",
+            Self::Rule => "rule",
+            Self::QuotaOverflow => "quota_overflow",
+        }
+    }
+}
+
+/// The provider selected for a request and the reason it was selected.
+pub struct RouteDecision<'a> {
+    pub provider: &'a dyn LLMProvider,
+    pub reason: RouteReason,
+}
+
+/// Outcome of the most recent health probe for a provider.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProviderHealth {
+    pub available: bool,
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Result of a real one-token request against a provider.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ProviderValidation {
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub ok: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 pub struct ProviderRegistry {
     providers: Vec<Box<dyn LLMProvider>>,
+    health: RwLock<Vec<(Provider, ProviderHealth)>>,
 }
 
 impl ProviderRegistry {
     /// Initialize provider registry with configured providers
     ///
-    /// Fix hardcoded provider initialization: Currently only Vertex and Anthropic providers are registered.
+    /// Fix hardcoded provider initialization: Providers are registered from fixed config sections.
     /// Registration order determines routing priority when multiple providers support the same model.
     /// TODO: Consider plugin/registry pattern or configuration-driven initialization for extensibility.
     #[must_use]
     pub fn with_config(
         anthropic_bridge_url: &Option<String>,
         gemini_cli_config: &Option<crate::config::GeminiCliConfig>,
+        ollama_config: &Option<crate::config::OllamaConfig>,
+        deepseek_config: &Option<crate::config::DeepSeekConfig>,
+        bedrock_config: &Option<crate::config::BedrockConfig>,
+        openai_compatible_config: &Option<crate::config::OpenAICompatibleConfig>,
     ) -> Self {
         let mut providers: Vec<Box<dyn LLMProvider>> = Vec::new();
 
-        // Register Gemini CLI provider first if enabled (takes precedence for gemini-* models)
+        // Register Gemini CLI provider first if enabled (takes precedence for the models it serves)
         if let Some(ref gemini_config) = gemini_cli_config {
             if gemini_config.enabled {
                 providers.push(Box::new(
-                    crate::services::providers::gemini_cli::GeminiCliProvider::new(
-                        gemini_config.cli_path.clone(),
-                        Some(gemini_config.timeout_secs),
-                        Some(gemini_config.max_concurrency),
+                    crate::services::providers::gemini_cli::GeminiCliProvider::from_config(
+                        gemini_config,
                     ),
                 ));
             }
         }
 
+        // Register Ollama provider if enabled (ahead of Vertex, so locally served
+        // model names are not claimed by the `gemini-` prefix match)
+        if let Some(ref ollama_config) = ollama_config {
+            if ollama_config.enabled {
+                providers.push(Box::new(
+                    crate::services::providers::ollama::OllamaProvider::from_config(ollama_config),
+                ));
+            }
+        }
+
+        // Register the OpenAI-compatible upstream if configured (ahead of the
+        // prefix-matching providers, as it serves an explicit model list)
+        if let Some(provider) = openai_compatible_config.as_ref().and_then(
+            crate::services::providers::openai_compatible::OpenAICompatibleProvider::from_config,
+        ) {
+            providers.push(Box::new(provider));
+        }
+
         // Register Vertex provider (always available)
         providers.push(Box::new(
             crate::services::providers::vertex::VertexProvider::new(),
@@ -108,7 +501,29 @@ impl ProviderRegistry {
             ));
         }
 
-        Self { providers }
+        // Register DeepSeek provider if an API key is configured
+        if let Some(provider) = deepseek_config
+            .as_ref()
+            .and_then(crate::services::providers::deepseek::DeepSeekProvider::from_config)
+        {
+            providers.push(Box::new(provider));
+        }
+
+        // Register Bedrock provider if enabled
+        if let Some(ref bedrock_config) = bedrock_config {
+            if bedrock_config.enabled {
+                providers.push(Box::new(
+                    crate::services::providers::bedrock::BedrockProvider::from_config(
+                        bedrock_config,
+                    ),
+                ));
+            }
+        }
+
+        Self {
+            providers,
+            health: RwLock::new(Vec::new()),
+        }
     }
 
     /// Route request to appropriate provider based on model name
@@ -119,12 +534,51 @@ impl ProviderRegistry {
     /// Consider: priority ordering, explicit model-to-provider mapping, or conflict detection.
     #[must_use]
     pub fn route_by_model(&self, model: &str) -> Option<&dyn LLMProvider> {
-        for provider in &self.providers {
-            if provider.supports_model(model) {
-                return Some(provider.as_ref());
+        self.route(model).map(|decision| decision.provider)
+    }
+
+    /// Like `route_by_model`, but also reports why the provider was chosen.
+    #[must_use]
+    pub fn route(&self, model: &str) -> Option<RouteDecision<'_>> {
+        self.route_avoiding(model, &[])
+    }
+
+    /// Like `route`, but skips providers named in `avoid`.
+    ///
+    /// A provider that has used up its quota is passed over for the next one
+    /// serving the model, and still chosen if there is none.
+    #[must_use]
+    pub fn route_avoiding(&self, model: &str, avoid: &[String]) -> Option<RouteDecision<'_>> {
+        let mut candidates = self
+            .providers
+            .iter()
+            .filter(|provider| {
+                let name = provider.provider_type().name();
+                !avoid.iter().any(|avoided| avoided == name)
+            })
+            .filter(|provider| provider.supports_model(model));
+        let first = candidates.next()?;
+        if !first.has_capacity() {
+            if let Some(provider) = candidates.find(|provider| provider.has_capacity()) {
+                return Some(RouteDecision {
+                    provider: provider.as_ref(),
+                    reason: RouteReason::QuotaOverflow,
+                });
             }
         }
-        None
+        Some(RouteDecision {
+            provider: first.as_ref(),
+            reason: RouteReason::PrefixMatch,
+        })
+    }
+
+    /// Requests left under each provider's quotas, for providers that track any.
+    #[must_use]
+    pub fn quotas(&self) -> Vec<(Provider, QuotaStatus)> {
+        self.providers
+            .iter()
+            .filter_map(|p| p.quota().map(|quota| (p.provider_type(), quota)))
+            .collect()
     }
 
     /// Returns the list of registered provider types for observability/CLI status.
@@ -132,30 +586,203 @@ impl ProviderRegistry {
     pub fn list_providers(&self) -> Vec<Provider> {
         self.providers.iter().map(|p| p.provider_type()).collect()
     }
+
+    /// Probes every provider that supports it and records the results.
+    pub async fn probe_health(&self) {
+        self.probe_health_of(None).await;
+    }
+
+    /// Probes providers of type `only` (every provider when `None`) and
+    /// records the results, keeping earlier results for the others.
+    pub async fn probe_health_of(&self, only: Option<Provider>) {
+        let mut results = Vec::new();
+        for provider in &self.providers {
+            let provider_type = provider.provider_type();
+            if only.as_ref().is_some_and(|only| *only != provider_type) {
+                continue;
+            }
+            let Some(outcome) = provider.probe().await else {
+                continue;
+            };
+            if let Err(e) = &outcome {
+                warn!("Health probe for {} failed: {e}", provider_type.name());
+            }
+            results.push((
+                provider_type,
+                ProviderHealth {
+                    available: outcome.is_ok(),
+                    checked_at: chrono::Utc::now(),
+                    error: outcome.err().map(|e| e.to_string()),
+                    details: provider.health_details(),
+                },
+            ));
+        }
+        let mut health = self.health.write().await;
+        health.retain(|(provider, _)| !results.iter().any(|(probed, _)| probed == provider));
+        health.extend(results);
+    }
+
+    /// Sends a tiny completion (`max_tokens: 1`) to every provider, or only
+    /// to the one named `only`, and reports pass/fail with latency. Unlike
+    /// `probe_health` this exercises credentials and the upstream API itself.
+    pub async fn validate(
+        &self,
+        state: &AppState,
+        only: Option<&str>,
+        timeout: Duration,
+    ) -> Vec<ProviderValidation> {
+        let checks = self
+            .providers
+            .iter()
+            .filter(|provider| only.is_none_or(|name| provider.provider_type().name() == name))
+            .map(|provider| Self::validate_one(provider.as_ref(), state, timeout));
+        futures::future::join_all(checks).await
+    }
+
+    async fn validate_one(
+        provider: &dyn LLMProvider,
+        state: &AppState,
+        timeout: Duration,
+    ) -> ProviderValidation {
+        let name = provider.provider_type().name().to_string();
+        let Some(model) = provider.validation_model() else {
+            return ProviderValidation {
+                provider: name,
+                model: None,
+                ok: false,
+                latency_ms: 0,
+                error: Some("No model configured for validation".to_string()),
+            };
+        };
+        let request = ChatCompletionRequest {
+            model: model.clone(),
+            messages: vec![crate::models::openai::ChatMessage {
+                role: crate::models::openai::Role::User,
+                content: VALIDATION_PROMPT.to_string(),
+                name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+            stream: false,
+            temperature: Some(0.0),
+            max_tokens: Some(1),
+            top_p: Some(1.0),
+            top_k: None,
+            stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
+        };
+
+        let cancel = CancellationToken::new();
+        let started = std::time::Instant::now();
+        let outcome =
+            tokio::time::timeout(timeout, provider.execute(request, state, &cancel)).await;
+        let latency_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        let error = match outcome {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => {
+                cancel.cancel();
+                Some(format!("Timed out after {}s", timeout.as_secs()))
+            }
+        };
+        if let Some(error) = &error {
+            warn!("Validation of {name} with {model} failed: {error}");
+        }
+        ProviderValidation {
+            provider: name,
+            model: Some(model),
+            ok: error.is_none(),
+            latency_ms,
+            error,
+        }
+    }
+
+    /// Results of the last `probe_health` run; empty until the first completes.
+    pub async fn health_snapshot(&self) -> Vec<(Provider, ProviderHealth)> {
+        self.health.read().await.clone()
+    }
+
+    /// Whether `provider` is usable; providers that have not been probed
+    /// (or cannot be) are assumed available.
+    pub async fn is_available(&self, provider: &Provider) -> bool {
+        self.health
+            .read()
+            .await
+            .iter()
+            .find(|(p, _)| p == provider)
+            .is_none_or(|(_, health)| health.available)
+    }
+}
+
+/// Spawns the background health probe for providers of type `provider`.
+///
+/// The first probe runs immediately so auth problems surface at startup rather
+/// than on the first user request; later probes repeat every `interval`.
+#[must_use]
+pub fn spawn_health_probe(
+    registry: Arc<ProviderRegistry>,
+    provider: Provider,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            registry.probe_health_of(Some(provider.clone())).await;
+        }
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_stream_is_split_into_whole_frames() {
+        let mut pending = Vec::new();
+        pending.extend_from_slice(b"data: {\"a\":1}\n\ndata: {\"b\":\"caf\xC3");
+        assert_eq!(take_sse_frames(&mut pending), vec!["data: {\"a\":1}\n\n"]);
+        pending.extend_from_slice(b"\xA9\"}\n\n\ndata: [DONE]\n\n");
+        assert_eq!(
+            take_sse_frames(&mut pending),
+            vec!["data: {\"b\":\"caf\u{e9}\"}\n\n", "data: [DONE]\n\n"]
+        );
+        assert!(pending.is_empty());
+    }
+
     #[test]
     fn test_route_by_model_gemini() {
-        let registry = ProviderRegistry::with_config(&None, &None);
+        let registry = ProviderRegistry::with_config(&None, &None, &None, &None, &None, &None);
         assert!(registry.route_by_model("gemini-pro").is_some());
         assert!(registry.route_by_model("gemini-2.5-flash").is_some());
     }
 
     #[test]
     fn test_route_by_model_claude() {
-        let registry =
-            ProviderRegistry::with_config(&Some("http://localhost:4001".to_string()), &None);
+        let registry = ProviderRegistry::with_config(
+            &Some("http://localhost:4001".to_string()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        );
         assert!(registry.route_by_model("claude-3-5-sonnet").is_some());
         assert!(registry.route_by_model("claude-3-opus").is_some());
     }
 
     #[test]
     fn test_route_by_model_unknown() {
-        let registry = ProviderRegistry::with_config(&None, &None);
+        let registry = ProviderRegistry::with_config(&None, &None, &None, &None, &None, &None);
         assert!(registry.route_by_model("unknown-model").is_none());
     }
 
@@ -169,12 +796,161 @@ mod tests {
             cli_path: None,
             timeout_secs: 30,
             max_concurrency: 4,
+            ..Default::default()
         };
 
-        let registry = ProviderRegistry::with_config(&None, &Some(gemini_config));
+        let registry =
+            ProviderRegistry::with_config(&None, &Some(gemini_config), &None, &None, &None, &None);
         let provider = registry
-            .route_by_model("gemini-pro")
-            .expect("gemini-pro should route to Gemini CLI when enabled");
+            .route_by_model("gemini-2.5-flash")
+            .expect("gemini-2.5-flash should route to Gemini CLI when enabled");
         assert_eq!(provider.provider_type(), Provider::GeminiCLI);
+
+        // Models the CLI doesn't serve fall through to Vertex
+        let provider = registry
+            .route_by_model("gemini-pro")
+            .expect("gemini-pro should route to Vertex");
+        assert_eq!(provider.provider_type(), Provider::Vertex);
+
+        // Avoiding the CLI sends its models to Vertex instead
+        let decision = registry
+            .route_avoiding("gemini-2.5-flash", &["gemini_cli".to_string()])
+            .expect("gemini-2.5-flash should fall through to Vertex");
+        assert_eq!(decision.provider.provider_type(), Provider::Vertex);
+    }
+
+    #[tokio::test]
+    async fn test_route_overflows_past_used_up_quota() {
+        use crate::config::GeminiCliConfig;
+        use crate::services::quota::QuotaOverflow;
+
+        for (overflow, expected, reason) in [
+            (
+                QuotaOverflow::Vertex,
+                Provider::Vertex,
+                RouteReason::QuotaOverflow,
+            ),
+            (
+                QuotaOverflow::Queue,
+                Provider::GeminiCLI,
+                RouteReason::PrefixMatch,
+            ),
+        ] {
+            let gemini_config = GeminiCliConfig {
+                enabled: true,
+                cli_path: Some("/nonexistent/gemini".to_string()),
+                requests_per_day: Some(1),
+                quota_overflow: overflow,
+                ..Default::default()
+            };
+            let registry = ProviderRegistry::with_config(
+                &None,
+                &Some(gemini_config),
+                &None,
+                &None,
+                &None,
+                &None,
+            );
+            // The probe spends the day's only request
+            registry.probe_health().await;
+            assert_eq!(
+                registry.quotas()[0].1.day_remaining,
+                Some(0),
+                "{overflow:?}"
+            );
+
+            let decision = registry
+                .route("gemini-2.5-flash")
+                .expect("gemini-2.5-flash is served");
+            assert_eq!(decision.provider.provider_type(), expected);
+            assert_eq!(decision.reason, reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_health_skips_providers_without_probe() {
+        let registry = ProviderRegistry::with_config(&None, &None, &None, &None, &None, &None);
+        registry.probe_health().await;
+        assert!(registry.health_snapshot().await.is_empty());
+
+        // Only the bridge is probed; nothing listens on the discard port
+        let registry = ProviderRegistry::with_config(
+            &Some("http://127.0.0.1:9".into()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        );
+        registry.probe_health().await;
+        let snapshot = registry.health_snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].0, Provider::AnthropicCLI);
+        assert!(!snapshot[0].1.available);
+        assert!(!registry.is_available(&Provider::AnthropicCLI).await);
+    }
+
+    #[test]
+    fn test_upstream_error_metadata() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "30".parse().expect("valid header value"));
+        let err = ProviderError::upstream(
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            "quota exhausted".to_string(),
+        );
+        assert_eq!(err.status(), 429);
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(30)));
+        assert!(err.is_retryable());
+
+        let err = ProviderError::upstream(
+            StatusCode::BAD_REQUEST,
+            &HeaderMap::new(),
+            "input token count exceeds the maximum".to_string(),
+        );
+        assert_eq!(err.status(), 400);
+        assert_eq!(err.retry_after(), None);
+        assert!(!err.is_retryable());
+
+        let err = ProviderError::upstream(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &HeaderMap::new(),
+            String::new(),
+        );
+        assert_eq!(err.status(), 503);
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_upstream_error_body_detail() {
+        let upstream = |body: &str| {
+            ProviderError::upstream(StatusCode::BAD_REQUEST, &HeaderMap::new(), String::new())
+                .with_body_detail(body)
+        };
+
+        let err = upstream(r#"[{"error": {"code": 400, "status": "INVALID_ARGUMENT"}}]"#);
+        assert_eq!(
+            err.detail(),
+            Some(&serde_json::json!({"code": 400, "status": "INVALID_ARGUMENT"}))
+        );
+        let err = upstream(r#"{"error": "prompt is too long"}"#);
+        assert_eq!(
+            err.detail(),
+            Some(&serde_json::json!({"message": "prompt is too long"}))
+        );
+        assert!(upstream("Bad Gateway").detail().is_none());
+        assert!(ProviderError::Timeout(String::new()).detail().is_none());
+    }
+
+    #[test]
+    fn test_retryability_by_variant() {
+        assert!(ProviderError::Timeout(String::new()).is_retryable());
+        assert!(ProviderError::Network(String::new()).is_retryable());
+        assert!(!ProviderError::Auth(String::new()).is_retryable());
+        assert!(!ProviderError::InvalidRequest(String::new()).is_retryable());
+        assert!(
+            !ProviderError::CircuitOpen(crate::openai::circuit_breaker::CircuitOpenError)
+                .is_retryable()
+        );
     }
 }
@@ -0,0 +1,532 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::OllamaConfig,
+    models::openai::{
+        ChatCompletionChoice, ChatCompletionChunk, ChatCompletionChunkChoice,
+        ChatCompletionRequest, ChatCompletionResponse, ChatMessage, DeltaMessage, Role, Usage,
+    },
+    services::finish_reason,
+    services::model_policy,
+    services::providers::{
+        cancellable, cancellable_stream, metered_bytes_stream, read_metered, send_metered,
+        LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+    },
+    services::redaction,
+    services::trace_context,
+    state::AppState,
+};
+
+/// Models requested as `ollama/<name>` are sent to Ollama as `<name>`.
+pub const MODEL_PREFIX: &str = "ollama/";
+const CHAT_ENDPOINT: &str = "/api/chat";
+const TAGS_ENDPOINT: &str = "/api/tags";
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "OllamaOptions::is_empty")]
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    #[serde(default)]
+    content: String,
+}
+
+/// Sampling parameters, under Ollama's names. Unset ones use the model's
+/// Modelfile defaults.
+#[derive(Debug, Default, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+}
+
+impl OllamaOptions {
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.top_k.is_none()
+            && self.num_predict.is_none()
+            && self.stop.is_none()
+    }
+}
+
+impl OllamaChatRequest {
+    fn from_request(request: &ChatCompletionRequest, stream: bool) -> ProviderResult<Self> {
+        if request.uses_tools() {
+            return Err(ProviderError::InvalidRequest(
+                "The Ollama provider does not support tools".to_string(),
+            ));
+        }
+        Ok(Self {
+            model: upstream_model(&request.model).to_string(),
+            messages: request
+                .messages
+                .iter()
+                .map(|message| OllamaMessage {
+                    role: role_name(&message.role).to_string(),
+                    content: message.content.clone(),
+                })
+                .collect(),
+            stream,
+            options: OllamaOptions {
+                temperature: request.temperature,
+                top_p: request.top_p,
+                top_k: request.top_k,
+                num_predict: request.max_tokens,
+                stop: request.stop.clone(),
+            },
+        })
+    }
+}
+
+fn role_name(role: &Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::Tool => "tool",
+    }
+}
+
+/// The name Ollama knows `model` by.
+fn upstream_model(model: &str) -> &str {
+    model.strip_prefix(MODEL_PREFIX).unwrap_or(model)
+}
+
+/// One `/api/chat` response object: the whole reply when not streaming, or
+/// one line of the NDJSON stream. A failure partway through a stream arrives
+/// as a line holding only `error`.
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl OllamaChatResponse {
+    fn content(&self) -> &str {
+        self.message.as_ref().map_or("", |m| m.content.as_str())
+    }
+
+    fn finish_reason(&self) -> Option<String> {
+        self.done.then(|| {
+            finish_reason::from_ollama(self.done_reason.as_deref().unwrap_or_default()).to_string()
+        })
+    }
+
+    /// Token counts, reported on the final object only.
+    fn usage(&self) -> Option<Usage> {
+        let (prompt_tokens, completion_tokens) = match (self.prompt_eval_count, self.eval_count) {
+            (None, None) => return None,
+            (prompt, completion) => (prompt.unwrap_or(0), completion.unwrap_or(0)),
+        };
+        Some(Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            ..Usage::default()
+        })
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Turns Ollama's NDJSON stream into OpenAI SSE frames. Network reads can
+/// split a line or hold several, so incomplete lines wait in `pending`.
+struct StreamTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    pending: Vec<u8>,
+    sent_role: bool,
+}
+
+impl StreamTranslator {
+    fn new(id: String, model: String) -> Self {
+        Self {
+            id,
+            model,
+            created: unix_now(),
+            pending: Vec::new(),
+            sent_role: false,
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) -> Vec<ProviderResult<String>> {
+        self.pending.extend_from_slice(bytes);
+        let mut frames = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if !line.is_empty() {
+                frames.extend(self.translate_line(line));
+            }
+        }
+        frames
+    }
+
+    fn translate_line(&mut self, line: &str) -> Vec<ProviderResult<String>> {
+        let response = match serde_json::from_str::<OllamaChatResponse>(line) {
+            Ok(response) => response,
+            Err(e) => {
+                return vec![Err(ProviderError::Internal(format!(
+                    "Failed to parse Ollama stream line: {e}"
+                )))]
+            }
+        };
+        if let Some(message) = response.error {
+            return vec![Err(ProviderError::Unavailable(format!(
+                "Ollama stream failed: {message}"
+            )))];
+        }
+
+        let chunk = ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChatCompletionChunkChoice {
+                index: 0,
+                delta: DeltaMessage {
+                    role: (!self.sent_role).then_some(Role::Assistant),
+                    content: Some(response.content().to_string()),
+                    tool_calls: None,
+                },
+                finish_reason: response.finish_reason(),
+            }],
+            usage: response.usage(),
+        };
+        self.sent_role = true;
+        let mut frames = vec![serde_json::to_string(&chunk)
+            .map(|json| format!("data: {json}\n\n"))
+            .map_err(|e| ProviderError::Internal(format!("Failed to serialize chunk: {e}")))];
+        if response.done {
+            frames.push(Ok("data: [DONE]\n\n".to_string()));
+        }
+        frames
+    }
+}
+
+/// Serves models from a local Ollama server through its native `/api/chat`.
+pub struct OllamaProvider {
+    base_url: String,
+    models: Vec<String>,
+    timeout: Duration,
+}
+
+impl OllamaProvider {
+    #[must_use]
+    pub fn from_config(config: &OllamaConfig) -> Self {
+        Self {
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            models: config.models.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+        }
+    }
+
+    /// POSTs `body` to `/api/chat`, mapping non-success responses to
+    /// `ProviderError::Upstream`.
+    async fn post(
+        &self,
+        state: &AppState,
+        body: &OllamaChatRequest,
+    ) -> ProviderResult<reqwest::Response> {
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|e| ProviderError::Internal(format!("Failed to create HTTP client: {e}")))?;
+        let url = format!("{}{}", self.base_url, CHAT_ENDPOINT);
+        let request = client
+            .post(&url)
+            .headers(trace_context::propagation_headers())
+            .json(body);
+        let resp = send_metered(request, &state.metrics, self.provider_type().name())
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    ProviderError::Timeout(format!("Ollama at {url} timed out: {e}"))
+                } else {
+                    ProviderError::Network(format!("Failed to contact Ollama at {url}: {e}"))
+                }
+            })?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let error_text = resp.text().await.unwrap_or_else(|e| {
+                warn!("Failed to read error response: {}", e);
+                String::new()
+            });
+            state
+                .metrics
+                .record_upstream_response_bytes(self.provider_type().name(), error_text.len());
+            let message = serde_json::from_str::<OllamaChatResponse>(&error_text)
+                .ok()
+                .and_then(|body| body.error)
+                .unwrap_or_else(|| error_text.clone());
+            return Err(ProviderError::upstream(
+                status,
+                &headers,
+                format!(
+                    "Ollama error: {}",
+                    redaction::redact(&state.config.redaction, &message)
+                ),
+            )
+            .with_body_detail(&error_text));
+        }
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl LLMProvider for OllamaProvider {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<ChatCompletionResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!("Ollama: Executing non-streaming request {}", request_id);
+
+        let body = OllamaChatRequest::from_request(&request, false)?;
+        let completion: OllamaChatResponse = cancellable(cancel, async {
+            let resp = self.post(state, &body).await?;
+            let bytes = read_metered(resp, &state.metrics, self.provider_type().name())
+                .await
+                .map_err(|e| {
+                    ProviderError::Internal(format!("Failed to read Ollama response: {e}"))
+                })?;
+            serde_json::from_slice(&bytes).map_err(|e| {
+                ProviderError::Internal(format!("Failed to parse Ollama response: {e}"))
+            })
+        })
+        .await?;
+        if let Some(message) = &completion.error {
+            return Err(ProviderError::Unavailable(format!(
+                "Ollama error: {}",
+                redaction::redact(&state.config.redaction, message)
+            )));
+        }
+
+        Ok(ChatCompletionResponse {
+            id: format!("chatcmpl-{request_id}"),
+            object: "chat.completion".to_string(),
+            created: unix_now(),
+            model: request.model,
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: ChatMessage {
+                    role: Role::Assistant,
+                    content: completion.content().to_string(),
+                    name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
+                },
+                finish_reason: completion
+                    .finish_reason()
+                    .or_else(|| Some(finish_reason::STOP.to_string())),
+            }],
+            usage: completion.usage(),
+        })
+    }
+
+    async fn execute_stream(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<StreamingResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!("Ollama: Executing streaming request {}", request_id);
+
+        let body = OllamaChatRequest::from_request(&request, true)?;
+        let response = cancellable(cancel, self.post(state, &body)).await?;
+
+        let mut translator = StreamTranslator::new(format!("chatcmpl-{request_id}"), request.model);
+        let stream =
+            metered_bytes_stream(response, state.metrics.clone(), self.provider_type().name())
+                .flat_map(move |chunk_result| {
+                    let frames: Vec<_> = match chunk_result {
+                        Ok(bytes) => translator
+                            .feed(&bytes)
+                            .into_iter()
+                            .map(|frame| {
+                                frame.map_err(|e| {
+                                    Box::new(e) as Box<dyn std::error::Error + Send + Sync>
+                                })
+                            })
+                            .collect(),
+                        Err(e) => {
+                            error!("Ollama stream error: {}", e);
+                            vec![Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)]
+                        }
+                    };
+                    futures::stream::iter(frames)
+                });
+
+        Ok(cancellable_stream(Box::pin(stream), cancel.clone()))
+    }
+
+    fn provider_type(&self) -> Provider {
+        Provider::Ollama
+    }
+
+    async fn probe(&self) -> Option<ProviderResult<()>> {
+        let url = format!("{}{}", self.base_url, TAGS_ENDPOINT);
+        let client = match Client::builder().timeout(HEALTH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                return Some(Err(ProviderError::Internal(format!(
+                    "HTTP client unavailable: {e}"
+                ))))
+            }
+        };
+        Some(match client.get(&url).send().await {
+            Ok(resp) if resp.status().is_success() => Ok(()),
+            Ok(resp) => Err(ProviderError::Unavailable(format!(
+                "Ollama at {} returned {}",
+                self.base_url,
+                resp.status()
+            ))),
+            Err(e) => Err(ProviderError::Unavailable(format!(
+                "Ollama at {} is unreachable: {e}",
+                self.base_url
+            ))),
+        })
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        model.starts_with(MODEL_PREFIX)
+            || self
+                .models
+                .iter()
+                .any(|served| model_policy::matches_pattern(served, model))
+    }
+
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    fn validation_model(&self) -> Option<String> {
+        self.models.iter().find(|m| !m.ends_with('*')).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(models: &[&str]) -> OllamaProvider {
+        OllamaProvider::from_config(&OllamaConfig {
+            enabled: true,
+            models: models.iter().map(|m| (*m).to_string()).collect(),
+            ..OllamaConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_ollama_supports_prefixed_and_configured_models() {
+        let provider = provider(&["llama3*", "qwen2.5:7b"]);
+        assert!(provider.supports_model("ollama/mistral"));
+        assert!(provider.supports_model("llama3.1:8b"));
+        assert!(provider.supports_model("qwen2.5:7b"));
+        assert!(!provider.supports_model("qwen2.5:14b"));
+        assert!(!provider.supports_model("gemini-2.5-flash"));
+        assert_eq!(provider.validation_model().as_deref(), Some("qwen2.5:7b"));
+    }
+
+    #[test]
+    fn test_ollama_request_maps_parameters() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "ollama/llama3.1",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"}
+            ],
+            "temperature": 0.5,
+            "max_tokens": 64,
+            "stop": "END"
+        }))
+        .unwrap();
+        let body =
+            serde_json::to_value(OllamaChatRequest::from_request(&request, true).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "llama3.1",
+                "messages": [
+                    {"role": "system", "content": "Be brief."},
+                    {"role": "user", "content": "Hi"}
+                ],
+                "stream": true,
+                "options": {"temperature": 0.5, "num_predict": 64, "stop": ["END"]}
+            })
+        );
+    }
+
+    #[test]
+    fn test_stream_translator_handles_split_lines() {
+        let mut translator = StreamTranslator::new("chatcmpl-1".to_string(), "m".to_string());
+        let first = translator.feed(
+            br#"{"message":{"role":"assistant","content":"Hel"},"done":false}
+{"message":{"role":"assistant","con"#,
+        );
+        assert_eq!(first.len(), 1);
+        let first = first[0].as_ref().unwrap();
+        assert!(first.contains(r#""role":"assistant""#) && first.contains(r#""content":"Hel""#));
+
+        let rest = translator.feed(
+            br#"tent":"lo"},"done":false}
+{"message":{"role":"assistant","content":""},"done":true,"done_reason":"length","prompt_eval_count":5,"eval_count":2}
+"#,
+        );
+        let rest: Vec<String> = rest.into_iter().map(Result::unwrap).collect();
+        assert_eq!(rest.len(), 3);
+        assert!(rest[0].contains(r#""content":"lo""#) && !rest[0].contains("role"));
+        assert!(rest[1].contains(r#""finish_reason":"length""#));
+        assert!(rest[1].contains(r#""total_tokens":7"#));
+        assert_eq!(rest[2], "data: [DONE]\n\n");
+
+        let failed = translator.feed(b"{\"error\":\"model crashed\"}\n");
+        assert!(matches!(failed[0], Err(ProviderError::Unavailable(_))));
+    }
+}
@@ -0,0 +1,403 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::OpenAICompatibleConfig,
+    models::openai::{ChatCompletionRequest, ChatCompletionResponse, ChatMessage, StreamOptions},
+    services::model_policy,
+    services::providers::{
+        cancellable, cancellable_stream, metered_bytes_stream, read_metered, send_metered,
+        take_sse_frames, LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+    },
+    services::redaction,
+    services::trace_context,
+    state::AppState,
+};
+
+const CHAT_ENDPOINT: &str = "/chat/completions";
+const MODELS_ENDPOINT: &str = "/models";
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The request as the client sent it, minus the proxy's own extensions
+/// (prompt templates and fan-out prompts, already applied by the handler).
+/// `top_k` is kept, as OpenRouter, Together and vLLM accept it, and fields
+/// the proxy does not know, such as `response_format`, `seed` or OpenRouter's
+/// `provider`, are passed on unchanged.
+#[derive(Debug, Serialize)]
+struct PassthroughRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "<[Value]>::is_empty")]
+    tools: &'a [Value],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<&'a Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<&'a StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<&'a str>,
+    #[serde(flatten)]
+    extra: &'a Map<String, Value>,
+}
+
+impl<'a> PassthroughRequest<'a> {
+    fn from_request(request: &'a ChatCompletionRequest, stream: bool) -> Self {
+        Self {
+            model: &request.model,
+            messages: &request.messages,
+            stream,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k,
+            max_tokens: request.max_tokens,
+            stop: request.stop.as_deref(),
+            tools: &request.tools,
+            tool_choice: request.tool_choice.as_ref(),
+            stream_options: request.stream_options.as_ref().filter(|_| stream),
+            user: request.user.as_deref(),
+            extra: &request.extra,
+        }
+    }
+}
+
+/// The `message` of an OpenAI `{"error": {...}}` body, or the raw body.
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// Maps a failed response to a `ProviderError`. A rejected key is an auth
+/// failure and exhausted credits (OpenRouter's `402`) make the provider
+/// unavailable; everything else is passed on as `Upstream` so 400s and 429s
+/// reach the client.
+fn map_error(
+    status: StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    message: String,
+) -> ProviderError {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ProviderError::Auth(format!(
+            "OpenAI-compatible upstream rejected the API key: {message}"
+        )),
+        StatusCode::PAYMENT_REQUIRED => ProviderError::Unavailable(format!(
+            "OpenAI-compatible upstream account has no credits left: {message}"
+        )),
+        StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY => ProviderError::InvalidRequest(
+            format!("OpenAI-compatible upstream rejected the request: {message}"),
+        ),
+        _ => ProviderError::upstream(
+            status,
+            headers,
+            format!("OpenAI-compatible upstream error: {message}"),
+        ),
+    }
+}
+
+/// Whether an SSE frame carries data; OpenRouter sends `: OPENROUTER
+/// PROCESSING` comments while a request is queued.
+fn is_data_frame(frame: &str) -> bool {
+    frame.lines().any(|line| line.starts_with("data:"))
+}
+
+/// Forwards the configured models to any OpenAI-compatible API, such as
+/// OpenRouter, Together, Groq or a local vLLM server.
+pub struct OpenAICompatibleProvider {
+    base_url: String,
+    api_key: Option<String>,
+    models: Vec<String>,
+    timeout: Duration,
+}
+
+impl OpenAICompatibleProvider {
+    /// `None` when no base URL or no models are configured.
+    #[must_use]
+    pub fn from_config(config: &OpenAICompatibleConfig) -> Option<Self> {
+        let base_url = config.base_url.as_deref().filter(|url| !url.is_empty())?;
+        if config.models.is_empty() {
+            return None;
+        }
+        Some(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone().filter(|key| !key.is_empty()),
+            models: config.models.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+        })
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// POSTs `body` to the chat endpoint, mapping non-success responses
+    /// through [`map_error`].
+    async fn post(
+        &self,
+        state: &AppState,
+        body: &PassthroughRequest<'_>,
+    ) -> ProviderResult<reqwest::Response> {
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(|e| ProviderError::Internal(format!("Failed to create HTTP client: {e}")))?;
+        let url = format!("{}{}", self.base_url, CHAT_ENDPOINT);
+        let request = self
+            .authorize(client.post(&url))
+            .headers(trace_context::propagation_headers())
+            .json(body);
+        let resp = send_metered(request, &state.metrics, self.provider_type().name())
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    ProviderError::Timeout(format!("OpenAI-compatible upstream timed out: {e}"))
+                } else {
+                    ProviderError::Network(format!(
+                        "Failed to contact OpenAI-compatible upstream at {url}: {e}"
+                    ))
+                }
+            })?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let error_text = resp.text().await.unwrap_or_else(|e| {
+                warn!("Failed to read error response: {}", e);
+                String::new()
+            });
+            state
+                .metrics
+                .record_upstream_response_bytes(self.provider_type().name(), error_text.len());
+            let message = redaction::redact(&state.config.redaction, &error_message(&error_text));
+            return Err(map_error(status, &headers, message).with_body_detail(&error_text));
+        }
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl LLMProvider for OpenAICompatibleProvider {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<ChatCompletionResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!(
+            "OpenAI-compatible: Executing non-streaming request {}",
+            request_id
+        );
+
+        let body = PassthroughRequest::from_request(&request, false);
+        cancellable(cancel, async {
+            let resp = self.post(state, &body).await?;
+            let bytes = read_metered(resp, &state.metrics, self.provider_type().name())
+                .await
+                .map_err(|e| {
+                    ProviderError::Internal(format!(
+                        "Failed to read OpenAI-compatible upstream response: {e}"
+                    ))
+                })?;
+            serde_json::from_slice(&bytes).map_err(|e| {
+                ProviderError::Internal(format!(
+                    "Failed to parse OpenAI-compatible upstream response: {e}"
+                ))
+            })
+        })
+        .await
+    }
+
+    async fn execute_stream(
+        &self,
+        request: ChatCompletionRequest,
+        state: &AppState,
+        cancel: &CancellationToken,
+    ) -> ProviderResult<StreamingResponse> {
+        let request_id = Uuid::new_v4().to_string();
+        info!(
+            "OpenAI-compatible: Executing streaming request {}",
+            request_id
+        );
+
+        let body = PassthroughRequest::from_request(&request, true);
+        let response = cancellable(cancel, self.post(state, &body)).await?;
+
+        let mut pending = Vec::new();
+        let stream =
+            metered_bytes_stream(response, state.metrics.clone(), self.provider_type().name())
+                .flat_map(move |chunk_result| {
+                    let frames: Vec<_> = match chunk_result {
+                        Ok(bytes) => {
+                            pending.extend_from_slice(&bytes);
+                            take_sse_frames(&mut pending)
+                                .into_iter()
+                                .filter(|frame| is_data_frame(frame))
+                                .map(Ok)
+                                .collect()
+                        }
+                        Err(e) => {
+                            error!("OpenAI-compatible stream error: {}", e);
+                            vec![Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)]
+                        }
+                    };
+                    futures::stream::iter(frames)
+                });
+
+        Ok(cancellable_stream(Box::pin(stream), cancel.clone()))
+    }
+
+    fn provider_type(&self) -> Provider {
+        Provider::OpenAICompatible
+    }
+
+    /// Lists models, which checks both reachability and the API key.
+    async fn probe(&self) -> Option<ProviderResult<()>> {
+        let client = match Client::builder().timeout(HEALTH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                return Some(Err(ProviderError::Internal(format!(
+                    "HTTP client unavailable: {e}"
+                ))))
+            }
+        };
+        let url = format!("{}{}", self.base_url, MODELS_ENDPOINT);
+        Some(match self.authorize(client.get(&url)).send().await {
+            Ok(resp) if resp.status().is_success() => Ok(()),
+            Ok(resp) => {
+                let status = resp.status();
+                let headers = resp.headers().clone();
+                let body = resp.text().await.unwrap_or_default();
+                Err(map_error(status, &headers, error_message(&body)))
+            }
+            Err(e) => Err(ProviderError::Unavailable(format!(
+                "OpenAI-compatible upstream at {} is unreachable: {e}",
+                self.base_url
+            ))),
+        })
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.models
+            .iter()
+            .any(|served| model_policy::matches_pattern(served, model))
+    }
+
+    fn validation_model(&self) -> Option<String> {
+        self.models.iter().find(|m| !m.ends_with('*')).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderMap;
+
+    fn config(models: &[&str]) -> OpenAICompatibleConfig {
+        OpenAICompatibleConfig {
+            base_url: Some("https://openrouter.ai/api/v1/".to_string()),
+            models: models.iter().map(|m| (*m).to_string()).collect(),
+            ..OpenAICompatibleConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_registered_only_with_url_and_models() {
+        assert!(OpenAICompatibleProvider::from_config(&config(&[])).is_none());
+        assert!(
+            OpenAICompatibleProvider::from_config(&OpenAICompatibleConfig {
+                base_url: None,
+                ..config(&["llama-3.1-70b"])
+            })
+            .is_none()
+        );
+
+        let provider =
+            OpenAICompatibleProvider::from_config(&config(&["meta-llama/*", "mixtral-8x7b"]))
+                .unwrap();
+        assert_eq!(provider.base_url, "https://openrouter.ai/api/v1");
+        assert!(provider.supports_model("meta-llama/llama-3.1-70b-instruct"));
+        assert!(provider.supports_model("mixtral-8x7b"));
+        assert!(!provider.supports_model("mixtral-8x22b"));
+        assert_eq!(provider.validation_model().as_deref(), Some("mixtral-8x7b"));
+    }
+
+    #[test]
+    fn test_passthrough_request_keeps_openai_fields() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "meta-llama/llama-3.1-8b-instruct",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "top_k": 40,
+            "max_tokens": 16,
+            "stop": "END",
+            "user": "u-1",
+            "variables": {"name": "x"},
+            "stream_options": {"include_usage": true},
+            "seed": 7,
+            "provider": {"order": ["together"]}
+        }))
+        .unwrap();
+        let body = serde_json::to_value(PassthroughRequest::from_request(&request, false)).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "meta-llama/llama-3.1-8b-instruct",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "stream": false,
+                "top_k": 40,
+                "max_tokens": 16,
+                "stop": ["END"],
+                "user": "u-1",
+                "seed": 7,
+                "provider": {"order": ["together"]}
+            })
+        );
+        let body = serde_json::to_value(PassthroughRequest::from_request(&request, true)).unwrap();
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[test]
+    fn test_openai_compatible_error_mapping() {
+        let headers = HeaderMap::new();
+        let message =
+            error_message(r#"{"error": {"message": "No auth credentials found", "code": 401}}"#);
+        assert_eq!(message, "No auth credentials found");
+        assert!(matches!(
+            map_error(StatusCode::UNAUTHORIZED, &headers, message),
+            ProviderError::Auth(_)
+        ));
+        assert!(matches!(
+            map_error(StatusCode::PAYMENT_REQUIRED, &headers, String::new()),
+            ProviderError::Unavailable(_)
+        ));
+        assert_eq!(
+            map_error(StatusCode::NOT_FOUND, &headers, String::new()).status(),
+            400
+        );
+        assert_eq!(
+            map_error(StatusCode::TOO_MANY_REQUESTS, &headers, String::new()).status(),
+            429
+        );
+        assert!(!is_data_frame(": OPENROUTER PROCESSING\n\n"));
+    }
+}
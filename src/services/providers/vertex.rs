@@ -1,18 +1,28 @@
 use async_trait::async_trait;
 use futures::stream::StreamExt;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::{
     models::{
-        openai::{ChatCompletionRequest, ChatCompletionResponse},
-        vertex::{GenerateContentRequest, GenerateContentResponse},
+        openai::{ChatCompletionRequest, ChatCompletionResponse, ChatMessage, Role},
+        vertex::{Content, GenerateContentRequest, GenerateContentResponse, Part},
     },
     services::{
-        providers::{LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse},
+        model_catalog::UpstreamModel,
+        providers::{
+            cancellable, cancellable_stream, metered_bytes_stream, read_metered, send_metered,
+            LLMProvider, Provider, ProviderError, ProviderResult, StreamingResponse,
+        },
+        redaction, sampling, trace_context,
         transformer::{transform_request, transform_response, transform_stream_chunk},
+        upstream_clients,
+        upstream_headers::{self, TemplateVars},
     },
     state::AppState,
 };
@@ -20,7 +30,126 @@ use crate::{
 const API_KEY_BASE_URL: &str = "https://generativelanguage.googleapis.com";
 const NON_STREAMING_TIMEOUT_SECS: u64 = 30;
 const STREAMING_TIMEOUT_SECS: u64 = 60;
+// Counting is an estimate callers can fall back from, so it waits less
+const COUNT_TOKENS_TIMEOUT_SECS: u64 = 5;
 const UNKNOWN_PROJECT_ID: &str = "unknown";
+// Cheapest current model, used for end-to-end provider validation
+const VALIDATION_MODEL: &str = "gemini-2.5-flash-lite";
+const MODEL_LIST_PAGE_SIZE: u32 = 1000;
+const MAX_MODEL_LIST_PAGES: usize = 10;
+
+/// Converts one Vertex SSE frame into an OpenAI chunk event.
+fn transform_sse_frame(
+    frame: &str,
+    model: &str,
+    request_id: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let cleaned = frame
+        .trim()
+        .trim_start_matches("data: ")
+        .trim()
+        .trim_start_matches('[')
+        .trim_start_matches(',')
+        .trim_end_matches(',')
+        .trim_end_matches(']');
+
+    if cleaned.is_empty() {
+        return Ok(": keep-alive\n\n".to_string());
+    }
+
+    match serde_json::from_str::<GenerateContentResponse>(cleaned) {
+        Ok(vertex_parser) => {
+            let openai_chunk =
+                transform_stream_chunk(&vertex_parser, model.to_string(), request_id.to_string())
+                    .map_err(|e| {
+                    error!("Transform error: {}", e);
+                    Box::new(ProviderError::Internal(format!(
+                        "Failed to transform Vertex chunk: {e}"
+                    )))
+                })?;
+            let chunk_data = serde_json::to_string(&openai_chunk)?;
+            Ok(format!("data: {chunk_data}\n\n"))
+        }
+        Err(e) => {
+            // Chunk boundaries can split a JSON object; skip rather than abort
+            error!("Parse error: {e}");
+            Ok(": parse-error\n\n".to_string())
+        }
+    }
+}
+
+/// Base URL requests go to: AI Studio with an API key, the regional Vertex
+/// endpoint otherwise, unless overridden in `config`.
+#[must_use]
+pub fn endpoint_base(config: &crate::config::VertexConfig, is_api_key: bool) -> String {
+    let configured = if is_api_key {
+        config.api_key_base_url.as_ref()
+    } else {
+        config.oauth_base_url.as_ref()
+    };
+    match configured {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None if is_api_key => API_KEY_BASE_URL.to_string(),
+        None => format!("https://{}-aiplatform.googleapis.com", config.region),
+    }
+}
+
+/// The model method a request calls.
+#[derive(Debug, Clone, Copy)]
+enum VertexMethod {
+    Generate,
+    StreamGenerate,
+    CountTokens,
+}
+
+impl VertexMethod {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Generate => "generateContent",
+            Self::StreamGenerate => "streamGenerateContent",
+            Self::CountTokens => "countTokens",
+        }
+    }
+
+    fn streaming(self) -> bool {
+        matches!(self, Self::StreamGenerate)
+    }
+}
+
+/// A request carrying just the text of `messages`, for counting its tokens.
+fn prompt_request(messages: &[ChatMessage]) -> GenerateContentRequest {
+    let text = |role: &str, text: &str| Content {
+        role: role.to_string(),
+        parts: vec![Part {
+            text: Some(text.to_string()),
+        }],
+    };
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| matches!(m.role, Role::System))
+        .map(|m| m.content.as_str())
+        .collect();
+    let contents = messages
+        .iter()
+        .filter_map(|m| match m.role {
+            Role::System => None,
+            Role::User | Role::Tool => Some(text("user", &m.content)),
+            Role::Assistant => Some(text("model", &m.content)),
+        })
+        .collect();
+    GenerateContentRequest {
+        contents,
+        system_instruction: (!system.is_empty()).then(|| text("system", &system.join("\n\n"))),
+        generation_config: None,
+        safety_settings: None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CountTokensResponse {
+    total_tokens: usize,
+}
 
 struct VertexUrlBuilder;
 
@@ -41,17 +170,12 @@ impl VertexUrlBuilder {
     }
 
     fn build_oauth_url(
-        oauth_base: Option<&String>,
+        base: &str,
         project_id: &str,
         region: &str,
         model: &str,
         streaming: bool,
     ) -> (String, String) {
-        let base = oauth_base.map_or_else(
-            || format!("https://{region}-aiplatform.googleapis.com"),
-            |url| url.trim_end_matches('/').to_string(),
-        );
-
         let base_url = format!(
             "{base}/v1/projects/{project_id}/locations/{region}/publishers/google/models/{model}"
         );
@@ -68,25 +192,16 @@ impl VertexUrlBuilder {
         streaming: bool,
     ) -> (String, String) {
         let is_api_key = token_manager.is_api_key();
+        let base = endpoint_base(config, is_api_key);
 
         if is_api_key {
-            let api_base = config.api_key_base_url.as_ref().map_or_else(
-                || API_KEY_BASE_URL.to_string(),
-                |url| url.trim_end_matches('/').to_string(),
-            );
-            Self::build_api_key_url(&api_base, model, token, streaming)
+            Self::build_api_key_url(&base, model, token, streaming)
         } else {
             let project_id = token_manager.get_project_id().map_or_else(
                 || UNKNOWN_PROJECT_ID.to_string(),
                 std::string::ToString::to_string,
             );
-            Self::build_oauth_url(
-                config.oauth_base_url.as_ref(),
-                &project_id,
-                &config.region,
-                model,
-                streaming,
-            )
+            Self::build_oauth_url(&base, &project_id, &config.region, model, streaming)
         }
     }
 }
@@ -107,9 +222,9 @@ impl VertexProvider {
             .map_err(|e| ProviderError::Auth(e.to_string()))
     }
 
-    fn build_client(timeout_secs: u64) -> ProviderResult<Client> {
-        Client::builder()
-            .timeout(Duration::from_secs(timeout_secs))
+    fn build_client(state: &AppState, timeout_secs: u64) -> ProviderResult<Client> {
+        let builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
+        upstream_clients::apply(builder, &state.config.upstream_clients.vertex, None)
             .build()
             .map_err(|e| ProviderError::Internal(format!("Failed to create HTTP client: {e}")))
     }
@@ -117,66 +232,257 @@ impl VertexProvider {
     fn build_request_builder(
         client: &Client,
         state: &AppState,
-        request: &ChatCompletionRequest,
+        model: &str,
         token: &str,
-        streaming: bool,
-        vertex_req: &GenerateContentRequest,
-    ) -> reqwest::RequestBuilder {
+        method: VertexMethod,
+        body: &impl Serialize,
+    ) -> ProviderResult<reqwest::RequestBuilder> {
         let (base_url, query_param) = VertexUrlBuilder::build_url(
             &state.config.vertex,
             &state.token_manager,
-            &request.model,
+            model,
             token,
-            streaming,
+            method.streaming(),
         );
-
-        let url = if streaming {
-            format!("{base_url}:streamGenerateContent{query_param}")
-        } else {
-            format!("{base_url}:generateContent{query_param}")
-        };
-
-        let mut req_builder = client.post(&url).json(vertex_req);
+        let url = format!("{base_url}:{}{query_param}", method.name());
+
+        let extra_headers = upstream_headers::render(
+            &state.config.upstream_headers.vertex,
+            TemplateVars {
+                project_id: state.token_manager.get_project_id().or(state
+                    .config
+                    .vertex
+                    .project_id
+                    .as_deref()),
+            },
+        )
+        .map_err(|e| ProviderError::Internal(format!("Failed to build Vertex headers: {e}")))?;
+
+        let mut req_builder = client
+            .post(&url)
+            .headers(trace_context::propagation_headers())
+            .headers(extra_headers)
+            .json(body);
         if !state.token_manager.is_api_key() {
             req_builder = req_builder.bearer_auth(token);
         }
-        req_builder
+        Ok(req_builder)
     }
 
     async fn send_vertex_request(
         req_builder: reqwest::RequestBuilder,
-        request: &ChatCompletionRequest,
+        state: &AppState,
+        model: &str,
         request_id: &str,
     ) -> ProviderResult<reqwest::Response> {
-        let res = req_builder.send().await.map_err(|e| {
-            if e.is_timeout() {
-                ProviderError::Timeout(format!(
-                    "Vertex API request timeout (model: {}, request_id: {}): {}",
-                    request.model, request_id, e
-                ))
-            } else {
-                ProviderError::Network(format!(
-                    "Vertex API request failed (model: {}, request_id: {}): {}",
-                    request.model, request_id, e
-                ))
-            }
-        })?;
+        let provider = Provider::Vertex.name();
+        let res = send_metered(req_builder, &state.metrics, provider)
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    ProviderError::Timeout(format!(
+                        "Vertex API request timeout (model: {}, request_id: {}): {}",
+                        model, request_id, e
+                    ))
+                } else {
+                    ProviderError::Network(format!(
+                        "Vertex API request failed (model: {}, request_id: {}): {}",
+                        model, request_id, e
+                    ))
+                }
+            })?;
 
         if !res.status().is_success() {
             let status = res.status();
+            let headers = res.headers().clone();
             let text = res.text().await.unwrap_or_else(|e| {
                 warn!("Failed to read Vertex error response: {}", e);
                 String::new()
             });
-            error!("Vertex API error: {} - {}", status, text);
-            return Err(ProviderError::Unavailable(format!(
-                "Vertex API Error (model: {}, request_id: {}, status: {}): {}",
-                request.model, request_id, status, text
-            )));
+            state
+                .metrics
+                .record_upstream_response_bytes(provider, text.len());
+            let redacted = redaction::redact(&state.config.redaction, &text);
+            error!("Vertex API error: {} - {}", status, redacted);
+            return Err(ProviderError::upstream(
+                status,
+                &headers,
+                format!(
+                    "Vertex API Error (model: {}, request_id: {}): {}",
+                    model, request_id, redacted
+                ),
+            )
+            .with_body_detail(&text));
         }
 
         Ok(res)
     }
+
+    /// Tokens `model` sees in `messages`, counted by the `countTokens`
+    /// method, once per prompt when there are `prompts` to fan out, plus the
+    /// prompts themselves.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no token can be obtained or the endpoint does not
+    /// answer with a count.
+    pub async fn count_tokens(
+        state: &AppState,
+        model: &str,
+        messages: &[ChatMessage],
+        prompts: &[String],
+    ) -> ProviderResult<usize> {
+        let token = Self::get_token(state).await?;
+        let client = Self::build_client(state, COUNT_TOKENS_TIMEOUT_SECS)?;
+        let shared = Self::count_contents(&client, state, model, &token, messages).await?;
+        let prompt_messages: Vec<ChatMessage> = prompts
+            .iter()
+            .map(|prompt| ChatMessage {
+                role: Role::User,
+                content: prompt.clone(),
+                name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            })
+            .collect();
+        let prompts_tokens =
+            Self::count_contents(&client, state, model, &token, &prompt_messages).await?;
+        Ok(shared
+            .saturating_mul(prompts.len().max(1))
+            .saturating_add(prompts_tokens))
+    }
+
+    async fn count_contents(
+        client: &Client,
+        state: &AppState,
+        model: &str,
+        token: &str,
+        messages: &[ChatMessage],
+    ) -> ProviderResult<usize> {
+        let request = prompt_request(messages);
+        if request.contents.is_empty() && request.system_instruction.is_none() {
+            return Ok(0);
+        }
+        let mut body = serde_json::to_value(&request).map_err(|e| {
+            ProviderError::Internal(format!("Failed to build Vertex token count: {e}"))
+        })?;
+        // AI Studio only counts a system instruction inside a full request
+        if state.token_manager.is_api_key() {
+            body["model"] = json!(format!("models/{model}"));
+            body = json!({ "generateContentRequest": body });
+        }
+        let request_id = Uuid::new_v4().to_string();
+        let req_builder = Self::build_request_builder(
+            client,
+            state,
+            model,
+            token,
+            VertexMethod::CountTokens,
+            &body,
+        )?;
+        let res = Self::send_vertex_request(req_builder, state, model, &request_id).await?;
+        let count: CountTokensResponse = res.json().await.map_err(|e| {
+            ProviderError::Internal(format!("Failed to parse Vertex token count: {e}"))
+        })?;
+        Ok(count.total_tokens)
+    }
+
+    /// The Gemini models the configured endpoint offers: the AI Studio model
+    /// list with an API key, the Vertex publisher models otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no token can be obtained or the endpoint does not
+    /// answer with a model list.
+    pub async fn list_models(state: &AppState) -> ProviderResult<Vec<UpstreamModel>> {
+        let token = Self::get_token(state).await?;
+        let client = Self::build_client(state, NON_STREAMING_TIMEOUT_SECS)?;
+        let is_api_key = state.token_manager.is_api_key();
+        let base = endpoint_base(&state.config.vertex, is_api_key);
+        let url = if is_api_key {
+            format!("{base}/v1beta/models")
+        } else {
+            format!("{base}/v1beta1/publishers/google/models")
+        };
+
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        for _ in 0..MAX_MODEL_LIST_PAGES {
+            let mut query = vec![("pageSize", MODEL_LIST_PAGE_SIZE.to_string())];
+            if let Some(page_token) = page_token.take() {
+                query.push(("pageToken", page_token));
+            }
+            let mut req_builder = client
+                .get(&url)
+                .headers(trace_context::propagation_headers());
+            if is_api_key {
+                query.push(("key", token.clone()));
+            } else {
+                req_builder = req_builder.bearer_auth(&token);
+            }
+            let res =
+                req_builder.query(&query).send().await.map_err(|e| {
+                    ProviderError::Network(format!("Vertex model list failed: {e}"))
+                })?;
+            if !res.status().is_success() {
+                let status = res.status();
+                let headers = res.headers().clone();
+                let text = res.text().await.unwrap_or_default();
+                return Err(ProviderError::upstream(
+                    status,
+                    &headers,
+                    format!(
+                        "Vertex model list error: {}",
+                        redaction::redact(&state.config.redaction, &text)
+                    ),
+                ));
+            }
+            let page: ModelListPage = res.json().await.map_err(|e| {
+                ProviderError::Internal(format!("Failed to parse Vertex model list: {e}"))
+            })?;
+            models.extend(
+                page.models
+                    .into_iter()
+                    .filter_map(ModelListEntry::into_gemini_model),
+            );
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(next) => page_token = Some(next),
+                None => break,
+            }
+        }
+        Ok(models)
+    }
+}
+
+/// One page of the AI Studio model list or the Vertex publisher model list.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelListPage {
+    #[serde(default, alias = "publisherModels")]
+    models: Vec<ModelListEntry>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelListEntry {
+    /// `models/<id>` or `publishers/google/models/<id>`.
+    name: String,
+    input_token_limit: Option<u32>,
+    output_token_limit: Option<u32>,
+}
+
+impl ModelListEntry {
+    /// Embedding and imaging models share the list but are not routed here.
+    fn into_gemini_model(self) -> Option<UpstreamModel> {
+        let id = self.name.rsplit('/').next().unwrap_or_default();
+        id.starts_with("gemini-").then(|| UpstreamModel {
+            id: id.to_string(),
+            context_window: self.input_token_limit,
+            max_output_tokens: self.output_token_limit,
+        })
+    }
 }
 
 impl Default for VertexProvider {
@@ -191,23 +497,48 @@ impl LLMProvider for VertexProvider {
         &self,
         request: ChatCompletionRequest,
         state: &AppState,
+        cancel: &CancellationToken,
     ) -> ProviderResult<ChatCompletionResponse> {
         let request_id = Uuid::new_v4().to_string();
         info!("Vertex: Executing non-streaming request {}", request_id);
 
-        let token = Self::get_token(state).await?;
-        let vertex_req = transform_request(request.clone())
-            .map_err(|e| ProviderError::InvalidRequest(e.to_string()))?;
-        let client = Self::build_client(NON_STREAMING_TIMEOUT_SECS)?;
-        let req_builder =
-            Self::build_request_builder(&client, state, &request, &token, false, &vertex_req);
-        let res = Self::send_vertex_request(req_builder, &request, &request_id).await?;
-        let vertex_result: GenerateContentResponse = res.json().await.map_err(|e| {
-            ProviderError::Internal(format!(
-                "Failed to parse Vertex response (model: {}, request_id: {}): {}",
-                request.model, request_id, e
-            ))
-        })?;
+        let token = cancellable(cancel, Self::get_token(state)).await?;
+        let vertex_req = transform_request(
+            sampling::normalize(&state.config.sampling.vertex, request.clone()),
+            &state.config.vertex.generation,
+        )
+        .map_err(|e| ProviderError::InvalidRequest(e.to_string()))?;
+        let client = Self::build_client(state, NON_STREAMING_TIMEOUT_SECS)?;
+        let req_builder = Self::build_request_builder(
+            &client,
+            state,
+            &request.model,
+            &token,
+            VertexMethod::Generate,
+            &vertex_req,
+        )?;
+        let res = cancellable(
+            cancel,
+            Self::send_vertex_request(req_builder, state, &request.model, &request_id),
+        )
+        .await?;
+        let vertex_result: GenerateContentResponse = cancellable(cancel, async {
+            let body = read_metered(res, &state.metrics, Provider::Vertex.name())
+                .await
+                .map_err(|e| {
+                    ProviderError::Internal(format!(
+                        "Failed to read Vertex response (model: {}, request_id: {}): {}",
+                        request.model, request_id, e
+                    ))
+                })?;
+            serde_json::from_slice(&body).map_err(|e| {
+                ProviderError::Internal(format!(
+                    "Failed to parse Vertex response (model: {}, request_id: {}): {}",
+                    request.model, request_id, e
+                ))
+            })
+        })
+        .await?;
 
         let response = transform_response(&vertex_result, request.model.clone(), request_id.clone()).map_err(|e| {
             ProviderError::Internal(format!(
@@ -223,86 +554,54 @@ impl LLMProvider for VertexProvider {
         &self,
         request: ChatCompletionRequest,
         state: &AppState,
+        cancel: &CancellationToken,
     ) -> ProviderResult<StreamingResponse> {
         let request_id = Uuid::new_v4().to_string();
         info!("Vertex: Executing streaming request {}", request_id);
 
-        let token = Self::get_token(state).await?;
-        let vertex_req = transform_request(request.clone())
-            .map_err(|e| ProviderError::InvalidRequest(e.to_string()))?;
-        let client = Self::build_client(STREAMING_TIMEOUT_SECS)?;
-        let req_builder =
-            Self::build_request_builder(&client, state, &request, &token, true, &vertex_req);
+        let token = cancellable(cancel, Self::get_token(state)).await?;
+        let vertex_req = transform_request(
+            sampling::normalize(&state.config.sampling.vertex, request.clone()),
+            &state.config.vertex.generation,
+        )
+        .map_err(|e| ProviderError::InvalidRequest(e.to_string()))?;
+        let client = Self::build_client(state, STREAMING_TIMEOUT_SECS)?;
+        let req_builder = Self::build_request_builder(
+            &client,
+            state,
+            &request.model,
+            &token,
+            VertexMethod::StreamGenerate,
+            &vertex_req,
+        )?;
 
-        let res = Self::send_vertex_request(req_builder, &request, &request_id).await?;
+        let res = cancellable(
+            cancel,
+            Self::send_vertex_request(req_builder, state, &request.model, &request_id),
+        )
+        .await?;
 
         let model = request.model.clone();
         let request_id_clone = request_id.clone();
-        let stream = res
-            .bytes_stream()
-            .map(move |chunk_result| match chunk_result {
-                Ok(bytes) => {
-                    let s = String::from_utf8_lossy(&bytes);
-                    let cleaned = s
-                        .trim()
-                        .trim_start_matches("data: ")
-                        .trim()
-                        .trim_start_matches('[')
-                        .trim_start_matches(',')
-                        .trim_end_matches(',')
-                        .trim_end_matches(']');
-
-                    if cleaned.is_empty() {
-                        return Ok::<String, Box<dyn std::error::Error + Send + Sync>>(
-                            "data: {\"comment\": \"keep-alive\"}\n\n".to_string(),
-                        );
-                    }
-
-                    match serde_json::from_str::<GenerateContentResponse>(cleaned) {
-                        Ok(vertex_parser) => {
-                            match transform_stream_chunk(
-                                &vertex_parser,
-                                model.clone(),
-                                request_id_clone.clone(),
-                            ) {
-                                Ok(openai_chunk) => match serde_json::to_string(&openai_chunk) {
-                                    Ok(chunk_data) => {
-                                        Ok::<String, Box<dyn std::error::Error + Send + Sync>>(
-                                            format!("data: {chunk_data}\n\n"),
-                                        )
-                                    }
-                                    Err(e) => {
-                                        error!("Transform error: {}", e);
-                                        Ok::<String, Box<dyn std::error::Error + Send + Sync>>(
-                                            format!("data: {{\"error\": \"{e}\"}}"),
-                                        )
-                                    }
-                                },
-                                Err(e) => {
-                                    error!("Transform error: {}", e);
-                                    Ok::<String, Box<dyn std::error::Error + Send + Sync>>(format!(
-                                        "data: {{\"error\": \"{e}\"}}"
-                                    ))
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            error!("Parse error: {e}");
-                            Ok::<String, Box<dyn std::error::Error + Send + Sync>>(
-                                "data: {\"comment\": \"parse-error\"}\n\n".to_string(),
-                            )
-                        }
+        let stream = metered_bytes_stream(res, state.metrics.clone(), Provider::Vertex.name())
+            .flat_map(move |chunk_result| {
+                let events = match chunk_result {
+                    // One network chunk can carry several SSE frames; the
+                    // terminal one (with the final usage) often shares a chunk
+                    Ok(bytes) => String::from_utf8_lossy(&bytes)
+                        .split("\n\n")
+                        .filter(|frame| !frame.trim().is_empty())
+                        .map(|frame| transform_sse_frame(frame, &model, &request_id_clone))
+                        .collect(),
+                    Err(e) => {
+                        error!("Stream error: {e}");
+                        vec![Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)]
                     }
-                }
-                Err(e) => {
-                    error!("Stream error: {e}");
-                    Ok::<String, Box<dyn std::error::Error + Send + Sync>>(format!(
-                        "data: {{\"error\": \"stream-error: {e}\"}}"
-                    ))
-                }
+                };
+                futures::stream::iter(events)
             });
 
-        Ok(Box::pin(stream))
+        Ok(cancellable_stream(Box::pin(stream), cancel.clone()))
     }
 
     fn provider_type(&self) -> Provider {
@@ -312,6 +611,10 @@ impl LLMProvider for VertexProvider {
     fn supports_model(&self, model: &str) -> bool {
         model.starts_with("gemini-")
     }
+
+    fn validation_model(&self) -> Option<String> {
+        Some(VALIDATION_MODEL.to_string())
+    }
 }
 
 #[cfg(test)]
@@ -332,6 +635,10 @@ mod tests {
                 host: "127.0.0.1".to_string(),
                 port: 4000,
                 max_request_size: 10 * 1024 * 1024,
+                request_timeout_secs: 0,
+                shutdown_grace_secs: 30,
+                body_limits: Vec::new(),
+                reuse_port: false,
             },
             auth: AuthConfig {
                 require_auth: false,
@@ -344,6 +651,7 @@ mod tests {
                 credentials_file: None,
                 api_key_base_url: None,
                 oauth_base_url: None,
+                generation: Default::default(),
             },
             log: LogConfig {
                 level: "info".to_string(),
@@ -351,18 +659,26 @@ mod tests {
             },
             openai: OpenAIConfig {
                 harvester_url: "http://localhost:3001".to_string(),
+                backend_url: None,
                 access_token_ttl_secs: 3600,
                 arkose_token_ttl_secs: 120,
+                waf_cooldown: Default::default(),
             },
             anthropic: AnthropicConfig {
                 bridge_url: "http://localhost:4001".to_string(),
+                health_check_interval_secs: 30,
             },
             gemini_cli: crate::config::GeminiCliConfig {
                 enabled: false,
                 cli_path: None,
                 timeout_secs: 30,
                 max_concurrency: 4,
+                ..Default::default()
             },
+            ollama: crate::config::OllamaConfig::default(),
+            deepseek: Default::default(),
+            bedrock: Default::default(),
+            openai_compatible: Default::default(),
             rate_limit: RateLimitConfig {
                 capacity: 100,
                 refill_per_second: 10,
@@ -371,24 +687,87 @@ mod tests {
                 failure_threshold: 10,
                 timeout_secs: 60,
                 success_threshold: 3,
+                persist: false,
+                state_file: None,
             },
             cache: CacheConfig {
                 enabled: false,
                 default_ttl_secs: 3600,
+                coalesce_requests: true,
+                vary_on_key: true,
             },
+            maintenance: Default::default(),
+            maintenance_mode: Default::default(),
+            warmup: Default::default(),
+            models: Default::default(),
+            keys: Default::default(),
+            scheduler: Default::default(),
+            alerts: Default::default(),
+            storage: Default::default(),
+            cluster: Default::default(),
+            limits: Default::default(),
+            upstream_headers: Default::default(),
+            upstream_clients: Default::default(),
+            model_policy: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_process: Default::default(),
+            mirror: Default::default(),
+            errors: Default::default(),
+            redaction: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            keepalive: Default::default(),
+            sse: Default::default(),
+            admin_signature: Default::default(),
+            model_discovery: Default::default(),
+            transcripts: Default::default(),
+            sampling: Default::default(),
+            slo: Default::default(),
         };
 
         AppState {
             config: Arc::new(config),
             token_manager: TokenManager::new(None, None, None)
                 .expect("Failed to initialize TokenManager in test"),
-            provider_registry: Arc::new(ProviderRegistry::with_config(&None, &None)),
+            provider_registry: Arc::new(ProviderRegistry::with_config(
+                &None, &None, &None, &None, &None, &None,
+            )),
             rate_limiter: crate::middleware::rate_limit::RateLimiter::new(100, 10),
             circuit_breaker: Arc::new(crate::openai::circuit_breaker::CircuitBreaker::new(
                 10, 60, 3,
             )),
             metrics: Arc::new(crate::openai::metrics::Metrics::new()),
             cache: Arc::new(Cache::new(false, 3600)),
+            in_flight: Default::default(),
+            waf_cooldown: Default::default(),
+            token_bindings: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            affinity: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_processor: Default::default(),
+            mirror: Default::default(),
+            shutdown: Default::default(),
+            routing_rules: Default::default(),
+            latency: Default::default(),
+            model_registry: Default::default(),
+            model_catalog: Default::default(),
+            transcripts: Default::default(),
+            slos: Default::default(),
+            key_store: Default::default(),
+            scheduler: Default::default(),
+            usage: Default::default(),
+            budgets: Default::default(),
+            notifier: Default::default(),
+            maintenance_mode: Default::default(),
+            status_page: Default::default(),
+            warmup: Default::default(),
+            store: None,
+            storage: None,
         }
     }
 
@@ -408,4 +787,27 @@ mod tests {
         assert!(provider.supports_model("gemini-pro"));
         assert_eq!(state.config.vertex.region, "us-central1");
     }
+
+    #[test]
+    fn test_prompt_request_for_token_count() {
+        let message = |role, content: &str| ChatMessage {
+            role,
+            content: content.to_string(),
+            name: None,
+            images: 0,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        };
+        let request = prompt_request(&[
+            message(Role::System, "Be brief"),
+            message(Role::User, "Hi"),
+            message(Role::Assistant, "Hello"),
+        ]);
+        let roles: Vec<&str> = request.contents.iter().map(|c| c.role.as_str()).collect();
+        assert_eq!(roles, ["user", "model"]);
+        let instruction = request.system_instruction.expect("system message kept");
+        assert_eq!(instruction.parts[0].text.as_deref(), Some("Be brief"));
+        assert!(request.generation_config.is_none());
+        assert!(prompt_request(&[]).contents.is_empty());
+    }
 }
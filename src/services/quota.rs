@@ -0,0 +1,281 @@
+// Request quotas the proxy enforces on a provider's behalf.
+//
+// The Gemini CLI's free tier allows a fixed number of requests per minute and
+// per day. Without a local count the proxy only learns the quota is gone when
+// the CLI fails with a quota message on stderr, after the request has waited
+// for a process. `RequestQuota` counts requests against configured limits so
+// the router can send overflow elsewhere, or wait for the next slot, before
+// spawning anything.
+//
+// The minute limit is a sliding 60-second window. The day limit resets at
+// midnight Pacific, as Google's quotas do; standard time (UTC-8) is used all
+// year, so during daylight saving time the count resets an hour late rather
+// than early.
+
+use chrono::{DateTime, Days, FixedOffset, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::services::clock::{self, SharedClock};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+// UTC offset of Pacific standard time, in seconds
+const PACIFIC_OFFSET_SECS: i32 = -8 * 3600;
+
+/// What happens to requests while the quota is used up.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaOverflow {
+    /// Route them to the next provider serving the model (Vertex for Gemini).
+    #[default]
+    Vertex,
+    /// Wait for a free slot, up to the provider's timeout.
+    Queue,
+}
+
+/// Requests left under each configured limit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuotaStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minute_remaining: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day_remaining: Option<u32>,
+    /// When the day's count starts over.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day_resets_at: Option<DateTime<Utc>>,
+}
+
+struct QuotaState {
+    minute: VecDeque<Instant>,
+    day_count: u32,
+    day_resets_at: DateTime<Utc>,
+    blocked_until: Option<Instant>,
+}
+
+/// Per-minute and per-day request counts against fixed limits.
+pub struct RequestQuota {
+    per_minute: Option<u32>,
+    per_day: Option<u32>,
+    state: Mutex<QuotaState>,
+    clock: SharedClock,
+}
+
+/// The first midnight Pacific after `now`.
+fn next_reset(now: DateTime<Utc>) -> DateTime<Utc> {
+    let pacific = FixedOffset::east_opt(PACIFIC_OFFSET_SECS).expect("offset is in range");
+    let local = now.with_timezone(&pacific).date_naive();
+    local
+        .checked_add_days(Days::new(1))
+        .unwrap_or(local)
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(pacific)
+        .single()
+        .map_or(now, |midnight| midnight.with_timezone(&Utc))
+}
+
+impl RequestQuota {
+    /// Limits of `None` are not counted.
+    #[must_use]
+    pub fn new(per_minute: Option<u32>, per_day: Option<u32>) -> Self {
+        let clock = clock::system();
+        Self {
+            per_minute,
+            per_day,
+            state: Mutex::new(QuotaState {
+                minute: VecDeque::new(),
+                day_count: 0,
+                day_resets_at: next_reset(clock.wall()),
+                blocked_until: None,
+            }),
+            clock,
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        {
+            let state = self
+                .state
+                .get_mut()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            state.day_resets_at = next_reset(clock.wall());
+        }
+        self.clock = clock;
+        self
+    }
+
+    /// Whether any limit is configured.
+    #[must_use]
+    pub fn is_limited(&self) -> bool {
+        self.per_minute.is_some() || self.per_day.is_some()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QuotaState> {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let (now, wall) = (self.clock.now(), self.clock.wall());
+        while state
+            .minute
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= MINUTE)
+        {
+            state.minute.pop_front();
+        }
+        if wall >= state.day_resets_at {
+            state.day_count = 0;
+            state.day_resets_at = next_reset(wall);
+        }
+        if state.blocked_until.is_some_and(|until| now >= until) {
+            state.blocked_until = None;
+        }
+        state
+    }
+
+    /// How long until a request fits, or `None` if one fits now.
+    fn wait(&self, state: &QuotaState) -> Option<Duration> {
+        let now = self.clock.now();
+        let mut wait = state.blocked_until.map(|until| until - now);
+        if self.per_day.is_some_and(|limit| state.day_count >= limit) {
+            let until_reset = (state.day_resets_at - self.clock.wall())
+                .to_std()
+                .unwrap_or_default();
+            wait = wait.max(Some(until_reset));
+        }
+        if let Some(limit) = self.per_minute {
+            let used = u32::try_from(state.minute.len()).unwrap_or(u32::MAX);
+            if used >= limit {
+                // The slot frees when the oldest request of the full window ages out
+                let oldest = state.minute[state.minute.len() - limit as usize];
+                wait = wait.max(Some(MINUTE.saturating_sub(now.duration_since(oldest))));
+            }
+        }
+        wait
+    }
+
+    /// Whether a request would fit now.
+    #[must_use]
+    pub fn has_capacity(&self) -> bool {
+        let state = self.lock();
+        self.wait(&state).is_none()
+    }
+
+    /// Counts a request if it fits. `Err` carries how long until one would.
+    ///
+    /// # Errors
+    ///
+    /// Returns the time until the next free slot when a limit is reached.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.lock();
+        if let Some(wait) = self.wait(&state) {
+            return Err(wait);
+        }
+        let now = self.clock.now();
+        if self.per_minute.is_some() {
+            state.minute.push_back(now);
+        }
+        state.day_count += 1;
+        Ok(())
+    }
+
+    /// Treats the quota as used up for `duration`, e.g. after the provider
+    /// reported exhaustion the local count missed.
+    pub fn block_for(&self, duration: Duration) {
+        let mut state = self.lock();
+        let until = self.clock.now() + duration;
+        state.blocked_until = state.blocked_until.max(Some(until));
+    }
+
+    /// Requests left under each configured limit.
+    #[must_use]
+    pub fn status(&self) -> QuotaStatus {
+        let state = self.lock();
+        let blocked = state.blocked_until.is_some();
+        QuotaStatus {
+            minute_remaining: self.per_minute.map(|limit| {
+                let used = u32::try_from(state.minute.len()).unwrap_or(u32::MAX);
+                if blocked {
+                    0
+                } else {
+                    limit.saturating_sub(used)
+                }
+            }),
+            day_remaining: self
+                .per_day
+                .map(|limit| limit.saturating_sub(state.day_count)),
+            day_resets_at: self.per_day.map(|_| state.day_resets_at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::clock::{Clock, ManualClock};
+    use chrono::{TimeZone, Timelike};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_minute_window_slides() {
+        let clock = Arc::new(ManualClock::new());
+        let quota = RequestQuota::new(Some(2), None).with_clock(clock.clone());
+        assert!(quota.try_acquire().is_ok());
+        clock.advance(Duration::from_secs(20));
+        assert!(quota.try_acquire().is_ok());
+        assert_eq!(quota.try_acquire(), Err(Duration::from_secs(40)));
+        assert!(!quota.has_capacity());
+        assert_eq!(quota.status().minute_remaining, Some(0));
+
+        clock.advance(Duration::from_secs(40));
+        assert!(quota.has_capacity());
+        assert_eq!(quota.status().minute_remaining, Some(1));
+        assert_eq!(quota.status().day_remaining, None);
+    }
+
+    #[test]
+    fn test_day_limit_resets_at_pacific_midnight() {
+        let clock = Arc::new(ManualClock::new());
+        let quota = RequestQuota::new(None, Some(1)).with_clock(clock.clone());
+        assert!(quota.try_acquire().is_ok());
+        let status = quota.status();
+        assert_eq!(status.day_remaining, Some(0));
+        let resets_at = status.day_resets_at.expect("day limit has a reset");
+        assert_eq!(resets_at.hour(), 8);
+        assert!(resets_at > clock.wall());
+
+        let wait = quota.try_acquire().expect_err("day limit is used up");
+        clock.advance(wait);
+        assert!(quota.try_acquire().is_ok());
+        assert_eq!(quota.status().day_resets_at, Some(next_reset(clock.wall())));
+    }
+
+    #[test]
+    fn test_block_for_overrides_the_count() {
+        let clock = Arc::new(ManualClock::new());
+        let quota = RequestQuota::new(Some(10), None).with_clock(clock.clone());
+        quota.block_for(Duration::from_secs(30));
+        assert_eq!(quota.try_acquire(), Err(Duration::from_secs(30)));
+        assert_eq!(quota.status().minute_remaining, Some(0));
+        clock.advance(Duration::from_secs(30));
+        assert!(quota.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn test_next_reset() {
+        let before = Utc.with_ymd_and_hms(2026, 3, 1, 7, 59, 0).unwrap();
+        assert_eq!(
+            next_reset(before),
+            Utc.with_ymd_and_hms(2026, 3, 1, 8, 0, 0).unwrap()
+        );
+        let after = Utc.with_ymd_and_hms(2026, 3, 1, 8, 0, 0).unwrap();
+        assert_eq!(
+            next_reset(after),
+            Utc.with_ymd_and_hms(2026, 3, 2, 8, 0, 0).unwrap()
+        );
+    }
+}
@@ -0,0 +1,105 @@
+// Redaction of text that may carry a user's prompt.
+//
+// Upstream error bodies regularly quote the request back ("prompt is too
+// long: ..."), and from there they reach the logs, the error message sent to
+// the client and, via admin actions, the audit log. Every such place passes
+// the text through `redact` with the configured `redaction` policy, so a
+// deployment that must not keep prompts gets the same treatment everywhere.
+// A redacted context-length error says so, as context fallbacks are chosen
+// by reading the error message.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::config::{RedactionConfig, RedactionMode};
+use crate::openai::errors::is_context_length_error;
+
+/// `text` as the policy allows it to be kept.
+#[must_use]
+pub fn redact(config: &RedactionConfig, text: &str) -> String {
+    let redacted = apply(config, text);
+    if redacted != text && is_context_length_error(text) && !is_context_length_error(&redacted) {
+        return format!("{redacted} (context length exceeded)");
+    }
+    redacted
+}
+
+fn apply(config: &RedactionConfig, text: &str) -> String {
+    let chars = text.chars().count();
+    match config.mode {
+        RedactionMode::None => text.to_string(),
+        _ if text.is_empty() => String::new(),
+        RedactionMode::Full => format!("[redacted {chars} chars]"),
+        RedactionMode::Hashed => {
+            let digest = Sha256::digest(text.as_bytes());
+            let hash: String = digest[..6].iter().map(|b| format!("{b:02x}")).collect();
+            format!("[sha256:{hash} {chars} chars]")
+        }
+        RedactionMode::Truncated if chars <= config.max_chars => text.to_string(),
+        RedactionMode::Truncated => {
+            let kept: String = text.chars().take(config.max_chars).collect();
+            format!("{kept}…[{} more chars]", chars - config.max_chars)
+        }
+    }
+}
+
+/// `value` with every string in it redacted, for structured error bodies.
+#[must_use]
+pub fn redact_value(config: &RedactionConfig, value: &Value) -> Value {
+    match value {
+        _ if config.mode == RedactionMode::None => value.clone(),
+        Value::String(s) => Value::String(redact(config, s)),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|v| redact_value(config, v)).collect())
+        }
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, v)| (name.clone(), redact_value(config, v)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(mode: RedactionMode) -> RedactionConfig {
+        RedactionConfig { mode, max_chars: 5 }
+    }
+
+    #[test]
+    fn test_redaction_modes() {
+        let text = "invalid input: tell me a secret";
+        assert_eq!(redact(&policy(RedactionMode::None), text), text);
+        assert_eq!(
+            redact(&policy(RedactionMode::Full), text),
+            "[redacted 31 chars]"
+        );
+        let hashed = redact(&policy(RedactionMode::Hashed), text);
+        assert!(hashed.starts_with("[sha256:") && hashed.ends_with(" 31 chars]"));
+        assert_eq!(hashed, redact(&policy(RedactionMode::Hashed), text));
+        assert_ne!(hashed, redact(&policy(RedactionMode::Hashed), "other"));
+        assert_eq!(
+            redact(&policy(RedactionMode::Truncated), text),
+            "inval…[26 more chars]"
+        );
+        assert_eq!(redact(&policy(RedactionMode::Truncated), "short"), "short");
+        assert_eq!(redact(&policy(RedactionMode::Full), ""), "");
+        assert_eq!(
+            redact(
+                &policy(RedactionMode::Full),
+                "Prompt is too long for the model"
+            ),
+            "[redacted 32 chars] (context length exceeded)"
+        );
+
+        let body = serde_json::json!({"error": {"code": 400, "message": "echo: hi"}});
+        assert_eq!(
+            redact_value(&policy(RedactionMode::Full), &body),
+            serde_json::json!({"error": {"code": 400, "message": "[redacted 8 chars]"}})
+        );
+    }
+}
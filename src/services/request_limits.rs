@@ -0,0 +1,259 @@
+// Input size limits checked before a chat completion is routed.
+//
+// `server.max_request_size` only bounds the raw body. A request well under that
+// can still carry thousands of tiny messages or one enormous prompt, both of
+// which are expensive for the CLI-spawn and prompt-join code paths. These
+// checks run on the parsed request and reject it with a 400 that names the
+// limit that was hit.
+
+use crate::config::LimitsConfig;
+use crate::models::openai::ChatCompletionRequest;
+use crate::services::model_registry::ModelInfo;
+use crate::services::tokenizer;
+
+/// The limit a request exceeded.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LimitExceeded {
+    #[error("Request has {count} messages, exceeding the limit of {limit}")]
+    TooManyMessages { count: usize, limit: usize },
+    #[error("Request has {count} prompts, exceeding the limit of {limit}")]
+    TooManyPrompts { count: usize, limit: usize },
+    #[error("Message {index} has {chars} characters, exceeding the per-message limit of {limit}")]
+    MessageTooLarge {
+        index: usize,
+        chars: usize,
+        limit: usize,
+    },
+    #[error("Prompt {index} has {chars} characters, exceeding the per-message limit of {limit}")]
+    PromptTooLarge {
+        index: usize,
+        chars: usize,
+        limit: usize,
+    },
+    #[error("Request has {chars} characters of message content, exceeding the limit of {limit}")]
+    TotalTooLarge { chars: usize, limit: usize },
+    #[error(
+        "This model's maximum context length is {limit} tokens, but the messages are estimated at {tokens} tokens"
+    )]
+    ContextLength { tokens: usize, limit: usize },
+}
+
+/// Estimated characters of text in `tokens` tokens.
+#[must_use]
+pub fn estimate_chars(tokens: u32) -> usize {
+    usize::try_from(tokens)
+        .unwrap_or(usize::MAX)
+        .saturating_mul(tokenizer::CHARS_PER_TOKEN)
+}
+
+/// Checks `req` against the configured limits and, when known, the model's context window.
+///
+/// Each of a request's `prompts` counts as a message: all of them towards the
+/// total size, and the longest towards the context window, as every prompt is
+/// sent with the shared `messages`.
+///
+/// # Errors
+///
+/// Returns the first `LimitExceeded` violation found.
+pub fn check(
+    limits: &LimitsConfig,
+    req: &ChatCompletionRequest,
+    model: Option<&ModelInfo>,
+) -> Result<(), LimitExceeded> {
+    if req.messages.len() > limits.max_messages {
+        return Err(LimitExceeded::TooManyMessages {
+            count: req.messages.len(),
+            limit: limits.max_messages,
+        });
+    }
+
+    if req.prompts.len() > limits.max_prompts {
+        return Err(LimitExceeded::TooManyPrompts {
+            count: req.prompts.len(),
+            limit: limits.max_prompts,
+        });
+    }
+
+    let mut total = 0usize;
+    for (index, message) in req.messages.iter().enumerate() {
+        let chars = message.content.chars().count();
+        if chars > limits.max_message_chars {
+            return Err(LimitExceeded::MessageTooLarge {
+                index,
+                chars,
+                limit: limits.max_message_chars,
+            });
+        }
+        total = total.saturating_add(chars);
+    }
+    for (index, prompt) in req.prompts.iter().enumerate() {
+        let chars = prompt.chars().count();
+        if chars > limits.max_message_chars {
+            return Err(LimitExceeded::PromptTooLarge {
+                index,
+                chars,
+                limit: limits.max_message_chars,
+            });
+        }
+        total = total.saturating_add(chars);
+    }
+    if total > limits.max_total_chars {
+        return Err(LimitExceeded::TotalTooLarge {
+            chars: total,
+            limit: limits.max_total_chars,
+        });
+    }
+
+    if let Some(model) = model {
+        let longest_prompt = req
+            .prompts
+            .iter()
+            .map(|prompt| tokenizer::count(&req.model, prompt))
+            .max()
+            .unwrap_or(0);
+        let tokens =
+            tokenizer::count_prompt(&req.model, &req.messages, &[]).saturating_add(longest_prompt);
+        let limit = usize::try_from(model.context_window).unwrap_or(usize::MAX);
+        if tokens > limit {
+            return Err(LimitExceeded::ContextLength { tokens, limit });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::{ChatMessage, Role};
+    use crate::services::model_registry::ModelRegistry;
+
+    fn request(contents: &[&str]) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: "gemini-pro".to_string(),
+            messages: contents
+                .iter()
+                .map(|c| ChatMessage {
+                    role: Role::User,
+                    content: (*c).to_string(),
+                    name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
+                })
+                .collect(),
+            stream: false,
+            temperature: Some(0.7),
+            top_p: Some(1.0),
+            top_k: None,
+            max_tokens: None,
+            stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
+        }
+    }
+
+    fn limits(
+        max_messages: usize,
+        max_message_chars: usize,
+        max_total_chars: usize,
+    ) -> LimitsConfig {
+        LimitsConfig {
+            max_messages,
+            max_message_chars,
+            max_total_chars,
+            max_output_tokens: None,
+            ..LimitsConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_within_limits() {
+        let req = request(&["hello", "world"]);
+        assert_eq!(check(&LimitsConfig::default(), &req, None), Ok(()));
+    }
+
+    #[test]
+    fn test_configured_limits() {
+        let req = request(&["a", "b", "c"]);
+        assert_eq!(
+            check(&limits(2, 100, 100), &req, None),
+            Err(LimitExceeded::TooManyMessages { count: 3, limit: 2 })
+        );
+
+        let req = request(&["short", "much too long"]);
+        assert_eq!(
+            check(&limits(10, 6, 100), &req, None),
+            Err(LimitExceeded::MessageTooLarge {
+                index: 1,
+                chars: 13,
+                limit: 6
+            })
+        );
+
+        let req = request(&["12345", "12345"]);
+        assert_eq!(
+            check(&limits(10, 100, 8), &req, None),
+            Err(LimitExceeded::TotalTooLarge {
+                chars: 10,
+                limit: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_model_context_window() {
+        let registry = ModelRegistry::default();
+        let model = registry.get("gpt-4").expect("gpt-4 is built in");
+        // GPT tokenizers split numbers into groups of three digits
+        let prompt = "1".repeat(8_192 * 3 + 1);
+        let mut req = request(&[&prompt]);
+        req.model = "gpt-4".to_string();
+        assert_eq!(
+            check(&LimitsConfig::default(), &req, Some(model)),
+            Err(LimitExceeded::ContextLength {
+                tokens: 8_193,
+                limit: 8_192
+            })
+        );
+        assert_eq!(check(&LimitsConfig::default(), &req, None), Ok(()));
+    }
+
+    #[test]
+    fn test_prompt_limits() {
+        let mut req = request(&["Score 1-5."]);
+        req.prompts = vec!["a".repeat(10), "b".repeat(30), "c".repeat(20)];
+        assert_eq!(check(&limits(10, 100, 100), &req, None), Ok(()));
+
+        let few = LimitsConfig {
+            max_prompts: 2,
+            ..limits(10, 100, 100)
+        };
+        assert_eq!(
+            check(&few, &req, None),
+            Err(LimitExceeded::TooManyPrompts { count: 3, limit: 2 })
+        );
+        assert_eq!(
+            check(&limits(10, 25, 1000), &req, None),
+            Err(LimitExceeded::PromptTooLarge {
+                index: 1,
+                chars: 30,
+                limit: 25
+            })
+        );
+        // Every prompt counts towards the total size
+        assert_eq!(
+            check(&limits(10, 100, 60), &req, None),
+            Err(LimitExceeded::TotalTooLarge {
+                chars: 70,
+                limit: 60
+            })
+        );
+    }
+}
@@ -0,0 +1,432 @@
+// Strategy for choosing a model within an alias group.
+//
+// An alias group lists equivalent models that may live on different providers
+// (`fast=gemini-2.5-flash|claude-3-5-haiku`). `primary` always takes the first
+// entry; `cheapest` takes the lowest-priced entry whose provider is currently
+// healthy; `fastest` takes the healthy entry with the lowest recent p95
+// latency. The configured default can be overridden per request with the
+// `X-Routing-Strategy` header.
+//
+// With sticky sessions enabled, the first model chosen for a session is pinned
+// for later turns of that session, so a conversation does not drift between
+// providers while its model stays healthy.
+
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::services::model_registry::ModelRegistry;
+use crate::services::providers::{ProviderRegistry, RouteReason};
+
+pub const ROUTING_STRATEGY_HEADER: &str = "x-routing-strategy";
+
+/// How a model is picked from an alias group.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingStrategy {
+    #[default]
+    Primary,
+    Cheapest,
+    Fastest,
+}
+
+impl RoutingStrategy {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::Cheapest => "cheapest",
+            Self::Fastest => "fastest",
+        }
+    }
+
+    /// Reason recorded when this strategy picked a non-primary group member.
+    #[must_use]
+    pub fn route_reason(self) -> RouteReason {
+        match self {
+            Self::Primary => RouteReason::PrefixMatch,
+            Self::Cheapest => RouteReason::Cheapest,
+            Self::Fastest => RouteReason::Fastest,
+        }
+    }
+}
+
+impl FromStr for RoutingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "primary" => Ok(Self::Primary),
+            "cheapest" => Ok(Self::Cheapest),
+            "fastest" => Ok(Self::Fastest),
+            other => Err(format!(
+                "Invalid routing strategy '{other}': expected one of primary, cheapest, fastest"
+            )),
+        }
+    }
+}
+
+pub const DEFAULT_LATENCY_WINDOW_SECS: u64 = 300;
+
+// Upper bound on samples kept per provider/model within the window
+const MAX_LATENCY_SAMPLES: usize = 200;
+
+// Oldest first, as (recorded at, duration in ms)
+type LatencySamples = VecDeque<(Instant, u64)>;
+
+/// Rolling upstream latency per provider and model.
+///
+/// Samples older than the window are discarded, so a provider that was slow
+/// (or has not been used lately) loses its history and is tried again.
+pub struct LatencyTracker {
+    window: Duration,
+    samples: Mutex<HashMap<(String, String), LatencySamples>>,
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_LATENCY_WINDOW_SECS))
+    }
+}
+
+impl LatencyTracker {
+    #[must_use]
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, provider: &str, model: &str, duration_ms: u64) {
+        let mut samples = self
+            .samples
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let entry = samples
+            .entry((provider.to_string(), model.to_string()))
+            .or_default();
+        entry.push_back((Instant::now(), duration_ms));
+        if entry.len() > MAX_LATENCY_SAMPLES {
+            entry.pop_front();
+        }
+    }
+
+    /// 95th percentile of the samples still inside the window, if any.
+    #[must_use]
+    pub fn p95(&self, provider: &str, model: &str) -> Option<u64> {
+        let mut samples = self
+            .samples
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let entry = samples.get_mut(&(provider.to_string(), model.to_string()))?;
+        while entry
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() > self.window)
+        {
+            entry.pop_front();
+        }
+        let mut durations: Vec<u64> = entry.iter().map(|&(_, ms)| ms).collect();
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort_unstable();
+        Some(durations[(durations.len() * 95).div_ceil(100) - 1])
+    }
+}
+
+pub const DEFAULT_SESSION_HEADER: &str = "x-session-id";
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 3600;
+
+/// Which group member each conversation session is pinned to.
+///
+/// Bindings are keyed by session and alias and expire after `ttl` without use.
+pub struct SessionAffinity {
+    ttl: Duration,
+    bindings: Mutex<HashMap<(String, String), (String, Instant)>>,
+}
+
+impl Default for SessionAffinity {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_SESSION_TTL_SECS))
+    }
+}
+
+impl SessionAffinity {
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            bindings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Model pinned for `session` under `alias`, refreshing its expiry.
+    #[must_use]
+    pub fn get(&self, session: &str, alias: &str) -> Option<String> {
+        let mut bindings = self
+            .bindings
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let key = (session.to_string(), alias.to_string());
+        match bindings.get_mut(&key) {
+            Some((_, used)) if used.elapsed() > self.ttl => {
+                bindings.remove(&key);
+                None
+            }
+            Some((target, used)) => {
+                *used = Instant::now();
+                Some(target.clone())
+            }
+            None => None,
+        }
+    }
+
+    /// Pins `session` to `target` for `alias`.
+    pub fn bind(&self, session: &str, alias: &str, target: &str) {
+        self.bindings
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(
+                (session.to_string(), alias.to_string()),
+                (target.to_string(), Instant::now()),
+            );
+    }
+
+    /// Removes expired bindings and returns how many were dropped.
+    ///
+    /// Called periodically by the maintenance task.
+    pub fn cleanup_expired(&self) -> usize {
+        let mut bindings = self
+            .bindings
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let before = bindings.len();
+        bindings.retain(|_, (_, used)| used.elapsed() <= self.ttl);
+        before - bindings.len()
+    }
+}
+
+/// Whether a registered provider serves `target` and passed its last health probe.
+pub async fn is_servable(target: &str, providers: &ProviderRegistry) -> bool {
+    match providers.route_by_model(target) {
+        Some(provider) => providers.is_available(&provider.provider_type()).await,
+        None => false,
+    }
+}
+
+/// Picks the model to use from an alias group's `targets` (primary first).
+///
+/// Returns `None` when the strategy settled on the primary, either because it
+/// was asked to or because no other target qualified. Targets are only
+/// considered when a registered provider serves them and that provider passed
+/// its last health probe; ties go to the earlier target.
+pub async fn select(
+    strategy: RoutingStrategy,
+    targets: &[String],
+    models: &ModelRegistry,
+    providers: &ProviderRegistry,
+    latency: &LatencyTracker,
+) -> Option<String> {
+    if strategy == RoutingStrategy::Primary {
+        return None;
+    }
+    let mut best: Option<(f64, &String)> = None;
+    for target in targets {
+        if !is_servable(target, providers).await {
+            continue;
+        }
+        let Some(provider) = providers.route_by_model(target) else {
+            continue;
+        };
+        let score = match strategy {
+            RoutingStrategy::Primary => continue,
+            RoutingStrategy::Cheapest => {
+                let Some(info) = models.get(target) else {
+                    continue;
+                };
+                info.pricing.input_per_million + info.pricing.output_per_million
+            }
+            // Unmeasured targets score zero so they get traffic and a baseline
+            #[allow(clippy::cast_precision_loss)]
+            RoutingStrategy::Fastest => latency
+                .p95(provider.provider_type().name(), target)
+                .map_or(0.0, |ms| ms as f64),
+        };
+        if best.is_none_or(|(lowest, _)| score < lowest) {
+            best = Some((score, target));
+        }
+    }
+    best.map(|(_, target)| target.clone())
+        .filter(|target| targets.first() != Some(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(targets: &[&str]) -> Vec<String> {
+        targets.iter().map(|t| (*t).to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!(
+            " Cheapest ".parse::<RoutingStrategy>(),
+            Ok(RoutingStrategy::Cheapest)
+        );
+        assert_eq!(
+            "primary".parse::<RoutingStrategy>(),
+            Ok(RoutingStrategy::Primary)
+        );
+        assert!("random".parse::<RoutingStrategy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cheapest_picks_lowest_price_across_providers() {
+        let models = ModelRegistry::default();
+        let providers = ProviderRegistry::with_config(
+            &Some("http://bridge".into()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        );
+        // claude-3-5-haiku ($0.8 + $4) is cheaper than gemini-1.5-pro ($1.25 + $5)
+        let targets = group(&["gemini-1.5-pro", "claude-3-5-haiku"]);
+
+        assert_eq!(
+            select(
+                RoutingStrategy::Cheapest,
+                &targets,
+                &models,
+                &providers,
+                &LatencyTracker::default()
+            )
+            .await
+            .as_deref(),
+            Some("claude-3-5-haiku")
+        );
+        assert_eq!(
+            select(
+                RoutingStrategy::Primary,
+                &targets,
+                &models,
+                &providers,
+                &LatencyTracker::default()
+            )
+            .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cheapest_skips_unroutable_targets() {
+        let models = ModelRegistry::default();
+        // Without a bridge URL no provider serves Claude models
+        let providers = ProviderRegistry::with_config(&None, &None, &None, &None, &None, &None);
+        let targets = group(&["gemini-1.5-pro", "claude-3-5-haiku"]);
+
+        assert_eq!(
+            select(
+                RoutingStrategy::Cheapest,
+                &targets,
+                &models,
+                &providers,
+                &LatencyTracker::default()
+            )
+            .await,
+            None
+        );
+    }
+
+    #[test]
+    fn test_latency_p95_and_decay() {
+        let tracker = LatencyTracker::new(Duration::from_millis(50));
+        assert_eq!(tracker.p95("vertex", "gemini-2.5-flash"), None);
+        for ms in 1..=100 {
+            tracker.record("vertex", "gemini-2.5-flash", ms);
+        }
+        assert_eq!(tracker.p95("vertex", "gemini-2.5-flash"), Some(95));
+        assert_eq!(tracker.p95("vertex", "gemini-2.5-pro"), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(tracker.p95("vertex", "gemini-2.5-flash"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fastest_prefers_lowest_p95_and_retries_unmeasured() {
+        let models = ModelRegistry::default();
+        let providers = ProviderRegistry::with_config(
+            &Some("http://bridge".into()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        );
+        let targets = group(&["gemini-1.5-pro", "claude-3-5-haiku"]);
+        let latency = LatencyTracker::default();
+
+        // Nothing measured yet: ties keep the primary
+        assert_eq!(
+            select(
+                RoutingStrategy::Fastest,
+                &targets,
+                &models,
+                &providers,
+                &latency
+            )
+            .await,
+            None
+        );
+
+        latency.record("vertex", "gemini-1.5-pro", 900);
+        assert_eq!(
+            select(
+                RoutingStrategy::Fastest,
+                &targets,
+                &models,
+                &providers,
+                &latency
+            )
+            .await
+            .as_deref(),
+            Some("claude-3-5-haiku")
+        );
+
+        latency.record("anthropic_cli", "claude-3-5-haiku", 2_000);
+        assert_eq!(
+            select(
+                RoutingStrategy::Fastest,
+                &targets,
+                &models,
+                &providers,
+                &latency
+            )
+            .await,
+            None
+        );
+    }
+
+    #[test]
+    fn test_session_affinity_pins_and_expires() {
+        let affinity = SessionAffinity::new(Duration::from_millis(50));
+        assert_eq!(affinity.get("team-a:conv-1", "fast"), None);
+
+        affinity.bind("team-a:conv-1", "fast", "claude-3-5-haiku");
+        assert_eq!(
+            affinity.get("team-a:conv-1", "fast").as_deref(),
+            Some("claude-3-5-haiku")
+        );
+        assert_eq!(affinity.get("team-a:conv-1", "smart"), None);
+        assert_eq!(affinity.get("team-a:conv-2", "fast"), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(affinity.cleanup_expired(), 1);
+        assert_eq!(affinity.get("team-a:conv-1", "fast"), None);
+    }
+}
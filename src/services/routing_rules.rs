@@ -0,0 +1,305 @@
+// Content-based routing rules.
+//
+// Rules look at what a request contains rather than which model it names:
+// its estimated prompt size, whether it carries images or tools, and the
+// script its latest user message is written in. The first matching rule can
+// swap the model (e.g. very long prompts to a long-context model) and/or steer
+// the request away from providers that handle such content poorly.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use tracing::info;
+
+use crate::models::openai::{ChatCompletionRequest, Role};
+use crate::services::providers::Provider;
+use crate::services::tokenizer;
+
+/// Writing system of a message, used as a proxy for its language.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Chinese,
+    Japanese,
+    Korean,
+}
+
+impl Script {
+    fn of_char(c: char) -> Option<Self> {
+        match c {
+            'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' => Some(Self::Latin),
+            '\u{0370}'..='\u{03FF}' => Some(Self::Greek),
+            '\u{0400}'..='\u{04FF}' => Some(Self::Cyrillic),
+            '\u{0590}'..='\u{05FF}' => Some(Self::Hebrew),
+            '\u{0600}'..='\u{06FF}' => Some(Self::Arabic),
+            '\u{0900}'..='\u{097F}' => Some(Self::Devanagari),
+            '\u{0E00}'..='\u{0E7F}' => Some(Self::Thai),
+            '\u{3040}'..='\u{30FF}' => Some(Self::Japanese),
+            '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' => Some(Self::Korean),
+            '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' => Some(Self::Chinese),
+            _ => None,
+        }
+    }
+
+    /// The dominant script of `text`. Han characters count as Japanese when
+    /// the text also contains kana.
+    #[must_use]
+    pub fn detect(text: &str) -> Option<Self> {
+        let mut counts: Vec<(Self, usize)> = Vec::new();
+        for script in text.chars().filter_map(Self::of_char) {
+            match counts.iter_mut().find(|(s, _)| *s == script) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((script, 1)),
+            }
+        }
+        let has_kana = counts.iter().any(|(s, _)| *s == Self::Japanese);
+        if has_kana {
+            let han = counts
+                .iter()
+                .find(|(s, _)| *s == Self::Chinese)
+                .map_or(0, |(_, n)| *n);
+            counts.retain(|(s, _)| *s != Self::Chinese);
+            if let Some((_, kana)) = counts.iter_mut().find(|(s, _)| *s == Self::Japanese) {
+                *kana += han;
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(script, _)| script)
+    }
+}
+
+/// Request properties rules are matched against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestProfile {
+    pub estimated_tokens: usize,
+    pub has_images: bool,
+    pub has_tools: bool,
+    pub script: Option<Script>,
+}
+
+impl RequestProfile {
+    #[must_use]
+    pub fn of(req: &ChatCompletionRequest) -> Self {
+        Self {
+            estimated_tokens: tokenizer::count_prompt(&req.model, &req.messages, &[]),
+            has_images: req.messages.iter().any(|m| m.images > 0),
+            has_tools: !req.tools.is_empty(),
+            script: req
+                .messages
+                .iter()
+                .rev()
+                .find(|m| m.role == Role::User)
+                .and_then(|m| Script::detect(&m.content)),
+        }
+    }
+}
+
+/// Conditions that must all hold for a rule to apply; unset ones always do.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConditions {
+    /// Requested models the rule is limited to.
+    #[serde(default)]
+    pub models: Vec<String>,
+    pub min_tokens: Option<usize>,
+    pub max_tokens: Option<usize>,
+    pub has_images: Option<bool>,
+    pub has_tools: Option<bool>,
+    /// Scripts the latest user message may be written in.
+    #[serde(default)]
+    pub language: Vec<Script>,
+}
+
+impl RuleConditions {
+    fn matches(&self, model: &str, profile: &RequestProfile) -> bool {
+        (self.models.is_empty() || self.models.iter().any(|m| m == model))
+            && self
+                .min_tokens
+                .is_none_or(|min| profile.estimated_tokens >= min)
+            && self
+                .max_tokens
+                .is_none_or(|max| profile.estimated_tokens <= max)
+            && self.has_images.is_none_or(|v| profile.has_images == v)
+            && self.has_tools.is_none_or(|v| profile.has_tools == v)
+            && (self.language.is_empty()
+                || profile
+                    .script
+                    .is_some_and(|script| self.language.contains(&script)))
+    }
+}
+
+/// A rule and what it does to matching requests.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingRule {
+    pub name: String,
+    #[serde(default)]
+    pub when: RuleConditions,
+    /// Model (or alias) to use instead of the requested one.
+    pub model: Option<String>,
+    /// Providers not to route to, by name (e.g. `gemini_cli`).
+    #[serde(default)]
+    pub avoid_providers: Vec<String>,
+}
+
+/// Ordered routing rules; the first match wins.
+#[derive(Debug, Default)]
+pub struct RoutingRules {
+    rules: Vec<RoutingRule>,
+}
+
+impl RoutingRules {
+    /// Loads rules from an optional JSON array file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or a rule is
+    /// invalid (see [`RoutingRules::new`]).
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read routing rules file '{path}'"))?;
+        let rules: Vec<RoutingRule> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse routing rules file '{path}'"))?;
+        info!("Loaded {} routing rules from {}", rules.len(), path);
+        Self::new(rules)
+    }
+
+    /// # Errors
+    ///
+    /// Rejects rules that change nothing and rules naming unknown providers.
+    pub fn new(rules: Vec<RoutingRule>) -> Result<Self> {
+        let known = [
+            Provider::Vertex,
+            Provider::AnthropicCLI,
+            Provider::GeminiCLI,
+        ];
+        for rule in &rules {
+            if rule.model.is_none() && rule.avoid_providers.is_empty() {
+                bail!(
+                    "Routing rule '{}' needs a 'model' or 'avoid_providers'",
+                    rule.name
+                );
+            }
+            if let Some(unknown) = rule
+                .avoid_providers
+                .iter()
+                .find(|name| !known.iter().any(|p| p.name() == name.as_str()))
+            {
+                bail!(
+                    "Routing rule '{}' avoids unknown provider '{unknown}'",
+                    rule.name
+                );
+            }
+        }
+        Ok(Self { rules })
+    }
+
+    /// The first rule matching `req`, if any.
+    #[must_use]
+    pub fn evaluate(&self, req: &ChatCompletionRequest) -> Option<&RoutingRule> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let profile = RequestProfile::of(req);
+        self.rules
+            .iter()
+            .find(|rule| rule.when.matches(&req.model, &profile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: serde_json::Value) -> ChatCompletionRequest {
+        serde_json::from_value(body).expect("request should parse")
+    }
+
+    #[test]
+    fn test_script_detection() {
+        assert_eq!(Script::detect("Hello there"), Some(Script::Latin));
+        assert_eq!(Script::detect("Привет, как дела?"), Some(Script::Cyrillic));
+        assert_eq!(Script::detect("你好，世界"), Some(Script::Chinese));
+        assert_eq!(Script::detect("日本語を話します"), Some(Script::Japanese));
+        assert_eq!(Script::detect("안녕하세요"), Some(Script::Korean));
+        assert_eq!(Script::detect("1234 !?"), None);
+    }
+
+    #[test]
+    fn test_profile_sees_images_tools_and_language() {
+        let req = request(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "messages": [
+                {"role": "system", "content": "Answer briefly."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Что на картинке?"},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+                ]}
+            ],
+            "tools": [{"type": "function", "function": {"name": "lookup"}}]
+        }));
+        let profile = RequestProfile::of(&req);
+        assert!(profile.has_images);
+        assert!(profile.has_tools);
+        assert_eq!(profile.script, Some(Script::Cyrillic));
+        assert!(profile.estimated_tokens > 0);
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules: Vec<RoutingRule> = serde_json::from_value(serde_json::json!([
+            {"name": "long", "when": {"min_tokens": 1000}, "model": "gemini-2.5-pro"},
+            {"name": "tools", "when": {"has_tools": true}, "avoid_providers": ["gemini_cli"]},
+            {"name": "catch-all", "when": {"models": ["gemini-2.5-flash"]}, "model": "gemini-2.0-flash"}
+        ]))
+        .expect("rules should parse");
+        let rules = RoutingRules::new(rules).expect("rules should be valid");
+
+        let long = request(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{"role": "user", "content": "word ".repeat(2000)}],
+            "tools": [{"type": "function"}]
+        }));
+        assert_eq!(rules.evaluate(&long).map(|r| r.name.as_str()), Some("long"));
+
+        let tools = request(serde_json::json!({
+            "model": "claude-3-opus",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"type": "function"}]
+        }));
+        assert_eq!(
+            rules.evaluate(&tools).map(|r| r.name.as_str()),
+            Some("tools")
+        );
+
+        let plain = request(serde_json::json!({
+            "model": "claude-3-opus",
+            "messages": [{"role": "user", "content": "hi"}]
+        }));
+        assert!(rules.evaluate(&plain).is_none());
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let no_action: Vec<RoutingRule> =
+            serde_json::from_value(serde_json::json!([{"name": "noop"}])).expect("parses");
+        assert!(RoutingRules::new(no_action).is_err());
+        let unknown: Vec<RoutingRule> = serde_json::from_value(serde_json::json!([
+            {"name": "bad", "avoid_providers": ["nope"]}
+        ]))
+        .expect("parses");
+        assert!(RoutingRules::new(unknown).is_err());
+    }
+}
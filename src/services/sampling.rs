@@ -0,0 +1,70 @@
+// Per-provider scaling of sampling parameters, applied as a request is
+// transformed for its provider.
+//
+// Providers disagree on parameter ranges: OpenAI and Gemini take a
+// `temperature` of 0–2, Anthropic 0–1. Passed through unchanged, the same
+// client temperature samples far hotter on one backend than another, or is
+// rejected outright. Where `sampling.<provider>` configures a range, values
+// on OpenAI's scale are mapped linearly onto it. Omitted parameters stay
+// omitted, so each provider keeps its own default.
+
+use crate::config::SamplingScaleConfig;
+use crate::models::openai::ChatCompletionRequest;
+
+/// Upper bound of OpenAI's `temperature`, the scale clients send.
+pub const OPENAI_MAX_TEMPERATURE: f32 = 2.0;
+
+/// Upper bound of OpenAI's `top_p`.
+pub const OPENAI_MAX_TOP_P: f32 = 1.0;
+
+/// `temperature` on the provider's scale.
+#[must_use]
+pub fn temperature(scale: &SamplingScaleConfig, temperature: Option<f32>) -> Option<f32> {
+    temperature.map(|t| rescale(t, OPENAI_MAX_TEMPERATURE, scale.max_temperature))
+}
+
+/// `top_p` on the provider's scale.
+#[must_use]
+pub fn top_p(scale: &SamplingScaleConfig, top_p: Option<f32>) -> Option<f32> {
+    top_p.map(|p| rescale(p, OPENAI_MAX_TOP_P, scale.max_top_p))
+}
+
+/// `req` with its sampling parameters on the provider's scale.
+#[must_use]
+pub fn normalize(
+    scale: &SamplingScaleConfig,
+    mut req: ChatCompletionRequest,
+) -> ChatCompletionRequest {
+    req.temperature = temperature(scale, req.temperature);
+    req.top_p = top_p(scale, req.top_p);
+    req
+}
+
+fn rescale(value: f32, from_max: f32, to_max: Option<f32>) -> f32 {
+    match to_max {
+        Some(max) => (value / from_max * max).clamp(0.0, max),
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rescales_onto_configured_range() {
+        let anthropic = SamplingScaleConfig {
+            max_temperature: Some(1.0),
+            max_top_p: None,
+        };
+        assert_eq!(temperature(&anthropic, Some(2.0)), Some(1.0));
+        assert_eq!(temperature(&anthropic, Some(0.7)), Some(0.35));
+        assert_eq!(temperature(&anthropic, None), None);
+        // No configured range passes values through
+        assert_eq!(top_p(&anthropic, Some(0.9)), Some(0.9));
+        assert_eq!(
+            temperature(&SamplingScaleConfig::default(), Some(1.5)),
+            Some(1.5)
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// Scheduling priority requested via the `X-Priority` header.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    const ALL: [Self; 3] = [Self::Low, Self::Normal, Self::High];
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "normal" => Ok(Self::Normal),
+            "high" => Ok(Self::High),
+            other => Err(format!(
+                "Invalid priority '{other}': expected one of low, normal, high"
+            )),
+        }
+    }
+}
+
+struct SchedulerState {
+    in_flight: usize,
+    // Indexed by `Priority::index`; served highest first, FIFO within a level
+    queues: [VecDeque<oneshot::Sender<()>>; 3],
+}
+
+/// Bounded concurrency gate for upstream calls with priority-ordered admission.
+///
+/// While fewer than `max_in_flight` requests are running, `acquire` returns
+/// immediately. Once saturated, callers queue and are admitted strictly by
+/// priority so batch (`low`) traffic yields to interactive (`high`) traffic.
+pub struct PriorityScheduler {
+    max_in_flight: usize,
+    state: Mutex<SchedulerState>,
+}
+
+/// Slot held for the duration of an upstream call; dropping it admits the next waiter.
+pub struct SchedulerPermit {
+    scheduler: Arc<PriorityScheduler>,
+}
+
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+// Guards a queued waiter so a cancelled `acquire` never leaks a slot handed to it.
+struct Waiter {
+    rx: Option<oneshot::Receiver<()>>,
+    scheduler: Arc<PriorityScheduler>,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            rx.close();
+            if rx.try_recv().is_ok() {
+                self.scheduler.release();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SchedulerStats {
+    pub max_in_flight: usize,
+    pub in_flight: usize,
+    pub queued_low: usize,
+    pub queued_normal: usize,
+    pub queued_high: usize,
+}
+
+impl PriorityScheduler {
+    #[must_use]
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            state: Mutex::new(SchedulerState {
+                in_flight: 0,
+                queues: Default::default(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SchedulerState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Waits for a free slot, queueing behind higher-priority callers when saturated.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> SchedulerPermit {
+        let rx = {
+            let mut state = self.lock();
+            if state.in_flight < self.max_in_flight {
+                state.in_flight += 1;
+                return SchedulerPermit {
+                    scheduler: Arc::clone(self),
+                };
+            }
+            let (tx, rx) = oneshot::channel();
+            state.queues[priority.index()].push_back(tx);
+            rx
+        };
+
+        let mut waiter = Waiter {
+            rx: Some(rx),
+            scheduler: Arc::clone(self),
+        };
+        if let Some(rx) = waiter.rx.as_mut() {
+            // The sender is only dropped after a successful hand-off or when the
+            // scheduler itself is gone, so both outcomes mean we own a slot.
+            let _ = rx.await;
+        }
+        waiter.rx = None;
+        SchedulerPermit {
+            scheduler: Arc::clone(self),
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.lock();
+        for priority in Priority::ALL.iter().rev() {
+            while let Some(tx) = state.queues[priority.index()].pop_front() {
+                // The slot transfers to the waiter; in_flight stays unchanged
+                if tx.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.in_flight = state.in_flight.saturating_sub(1);
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> SchedulerStats {
+        let state = self.lock();
+        SchedulerStats {
+            max_in_flight: self.max_in_flight,
+            in_flight: state.in_flight,
+            queued_low: state.queues[Priority::Low.index()].len(),
+            queued_normal: state.queues[Priority::Normal.index()].len(),
+            queued_high: state.queues[Priority::High.index()].len(),
+        }
+    }
+}
+
+impl Default for PriorityScheduler {
+    fn default() -> Self {
+        Self::new(crate::config::DEFAULT_MAX_IN_FLIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_priority_parsing() {
+        assert_eq!("HIGH".parse::<Priority>(), Ok(Priority::High));
+        assert_eq!(" low ".parse::<Priority>(), Ok(Priority::Low));
+        assert!("urgent".parse::<Priority>().is_err());
+        assert!(Priority::High > Priority::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_admitted_before_low() {
+        let scheduler = Arc::new(PriorityScheduler::new(1));
+        let first = scheduler.acquire(Priority::Normal).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let spawn_waiter = |priority: Priority| {
+            let scheduler = Arc::clone(&scheduler);
+            let order = Arc::clone(&order);
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire(priority).await;
+                order.lock().expect("order lock").push(priority);
+            })
+        };
+
+        let low = spawn_waiter(Priority::Low);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let high = spawn_waiter(Priority::High);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(scheduler.stats().queued_low, 1);
+        assert_eq!(scheduler.stats().queued_high, 1);
+
+        drop(first);
+        low.await.expect("low waiter should finish");
+        high.await.expect("high waiter should finish");
+
+        assert_eq!(
+            *order.lock().expect("order lock"),
+            vec![Priority::High, Priority::Low]
+        );
+        assert_eq!(scheduler.stats().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_does_not_leak_slot() {
+        let scheduler = Arc::new(PriorityScheduler::new(1));
+        let first = scheduler.acquire(Priority::Normal).await;
+
+        let pending =
+            tokio::time::timeout(Duration::from_millis(20), scheduler.acquire(Priority::High))
+                .await;
+        assert!(pending.is_err(), "acquire should block while saturated");
+
+        drop(first);
+        assert_eq!(scheduler.stats().in_flight, 0);
+        let _again = scheduler.acquire(Priority::Low).await;
+        assert_eq!(scheduler.stats().in_flight, 1);
+    }
+}
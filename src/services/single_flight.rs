@@ -0,0 +1,184 @@
+// Coalescing of identical concurrent requests.
+//
+// The first caller for a key becomes the leader and runs the upstream call;
+// callers arriving with the same key while it is in flight wait for the
+// leader's result instead of issuing their own. If the leader is cancelled
+// (client disconnect) before finishing, waiters fall back to running the call
+// themselves so a dropped connection never fails other clients.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Shares one in-flight computation among concurrent callers with the same key.
+pub struct SingleFlight<T: Clone> {
+    in_flight: Mutex<HashMap<String, broadcast::Sender<T>>>,
+}
+
+impl<T: Clone> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Removes the leader's entry if its future is dropped mid-call.
+struct LeaderGuard<'a, T: Clone> {
+    flight: &'a SingleFlight<T>,
+    key: &'a str,
+    finished: bool,
+}
+
+impl<T: Clone> Drop for LeaderGuard<'_, T> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.flight.take(self.key);
+        }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn take(&self, key: &str) -> Option<broadcast::Sender<T>> {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(key)
+    }
+
+    /// Runs `call` unless an identical call is already in flight, in which
+    /// case its result is awaited instead. Returns the result and whether it
+    /// was shared from another caller.
+    pub async fn run<F, Fut>(&self, key: String, call: F) -> (T, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let waiting = {
+            let mut in_flight = self
+                .in_flight
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if let Some(sender) = in_flight.get(&key) {
+                Some(sender.subscribe())
+            } else {
+                in_flight.insert(key.clone(), broadcast::channel(1).0);
+                None
+            }
+        };
+
+        if let Some(mut receiver) = waiting {
+            if let Ok(value) = receiver.recv().await {
+                return (value, true);
+            }
+            return (call().await, false);
+        }
+
+        let mut guard = LeaderGuard {
+            flight: self,
+            key: &key,
+            finished: false,
+        };
+        let value = call().await;
+        // Remove the entry before publishing so late arrivals start a fresh call
+        // rather than subscribing to a channel that has already sent.
+        guard.finished = true;
+        if let Some(sender) = self.take(&key) {
+            let _ = sender.send(value.clone());
+        }
+        (value, false)
+    }
+
+    /// Number of distinct keys currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_identical_calls_share_one_execution() {
+        let flight = Arc::new(SingleFlight::<u32>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let flight = flight.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run("same".to_string(), || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            42
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        let mut shared = 0;
+        for task in tasks {
+            let (value, coalesced) = task.await.expect("task should finish");
+            assert_eq!(value, 42);
+            shared += usize::from(coalesced);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(shared, 4);
+        assert_eq!(flight.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_distinct_keys_and_sequential_calls_are_not_shared() {
+        let flight = SingleFlight::<&str>::new();
+        let (first, coalesced) = flight.run("a".to_string(), || async { "a" }).await;
+        assert_eq!((first, coalesced), ("a", false));
+        let (second, coalesced) = flight.run("a".to_string(), || async { "again" }).await;
+        assert_eq!((second, coalesced), ("again", false));
+        let (other, coalesced) = flight.run("b".to_string(), || async { "b" }).await;
+        assert_eq!((other, coalesced), ("b", false));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_leader_lets_waiter_run() {
+        let flight = Arc::new(SingleFlight::<u32>::new());
+
+        let leader = {
+            let flight = flight.clone();
+            tokio::spawn(async move {
+                flight
+                    .run("k".to_string(), || async {
+                        tokio::time::sleep(Duration::from_secs(30)).await;
+                        1
+                    })
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let waiter = {
+            let flight = flight.clone();
+            tokio::spawn(async move { flight.run("k".to_string(), || async { 2 }).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        leader.abort();
+
+        let (value, coalesced) = waiter.await.expect("waiter should finish");
+        assert_eq!((value, coalesced), (2, false));
+        assert_eq!(flight.in_flight(), 0);
+    }
+}
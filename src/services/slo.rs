@@ -0,0 +1,300 @@
+// Per-model service level objectives and their burn rates.
+//
+// A request counts towards its model's objective once the response is ready
+// (for streams, when the first bytes are sent). It is good when it did not
+// fail on the proxy's side (any status below 500) and, if the objective sets a
+// latency, was answered within it; client errors count as good, since they
+// say nothing about the service. Outcomes are kept in time buckets over the
+// trailing window, so memory stays flat however busy a model is.
+//
+// Burn rate is the share of bad requests divided by the error budget: at 1.0
+// the budget is being spent exactly as fast as the objective allows, at 14.4
+// a 30-day budget would be gone in about two days.
+
+use axum::http::StatusCode;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::SloConfig;
+use crate::services::clock::{self, SharedClock};
+
+// Resolution of the rolling window
+const BUCKETS_PER_WINDOW: u32 = 60;
+
+/// An objective for one model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Objective {
+    pub model: String,
+    /// Fraction of requests that must be good, e.g. `0.995`.
+    pub target: f64,
+    /// Requests slower than this are bad.
+    pub latency_ms: Option<u64>,
+}
+
+/// Parses `model=<target>[@<ms>]` entries, with `target` a percentage.
+///
+/// # Errors
+///
+/// Returns a description of the first malformed entry.
+pub fn parse_objectives(entries: &[String]) -> Result<Vec<Objective>, String> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (model, spec) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("'{entry}' is not model=<target>[@<ms>]"))?;
+            let model = model.trim();
+            if model.is_empty() {
+                return Err(format!("'{entry}' names no model"));
+            }
+            let (target, latency) = match spec.split_once('@') {
+                Some((target, latency)) => (target, Some(latency)),
+                None => (spec, None),
+            };
+            let target = target
+                .trim()
+                .trim_end_matches('%')
+                .parse::<f64>()
+                .ok()
+                .filter(|t| *t > 0.0 && *t < 100.0)
+                .ok_or_else(|| format!("'{entry}' needs a target percentage between 0 and 100"))?;
+            let latency_ms = latency
+                .map(|ms| {
+                    ms.trim()
+                        .trim_end_matches("ms")
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|ms| *ms > 0)
+                        .ok_or_else(|| format!("'{entry}' has an invalid latency in milliseconds"))
+                })
+                .transpose()?;
+            Ok(Objective {
+                model: model.to_string(),
+                target: target / 100.0,
+                latency_ms,
+            })
+        })
+        .collect()
+}
+
+/// Compliance with one objective over the window.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SloStatus {
+    pub model: String,
+    pub target: f64,
+    pub latency_ms: Option<u64>,
+    pub requests: u64,
+    pub good_requests: u64,
+    /// Share of good requests; 1.0 with no requests.
+    pub compliance: f64,
+    pub burn_rate: f64,
+}
+
+struct Bucket {
+    start: Instant,
+    requests: u64,
+    good: u64,
+}
+
+/// Tracks requests against the configured objectives.
+///
+/// With no objectives configured every call is a no-op.
+pub struct Slos {
+    objectives: HashMap<String, Objective>,
+    window: Duration,
+    bucket_width: Duration,
+    buckets: Mutex<HashMap<String, VecDeque<Bucket>>>,
+    clock: SharedClock,
+}
+
+impl Default for Slos {
+    fn default() -> Self {
+        Self::from_config(&SloConfig::default())
+    }
+}
+
+impl Slos {
+    /// Objectives that fail to parse are skipped; config loading rejects them.
+    #[must_use]
+    pub fn from_config(config: &SloConfig) -> Self {
+        let window = Duration::from_secs(config.window_secs);
+        Self {
+            objectives: parse_objectives(&config.objectives)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|objective| (objective.model.clone(), objective))
+                .collect(),
+            window,
+            bucket_width: (window / BUCKETS_PER_WINDOW).max(Duration::from_secs(1)),
+            buckets: Mutex::new(HashMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        !self.objectives.is_empty()
+    }
+
+    /// Records a request for `model` answered with `status` after `duration`.
+    pub fn record(&self, model: &str, status: StatusCode, duration: Duration) {
+        let Some(objective) = self.objectives.get(model) else {
+            return;
+        };
+        let good = !status.is_server_error()
+            && objective
+                .latency_ms
+                .is_none_or(|ms| duration <= Duration::from_millis(ms));
+        let now = self.clock.now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let buckets = buckets.entry(model.to_string()).or_default();
+        if buckets
+            .back()
+            .is_none_or(|b| now.duration_since(b.start) >= self.bucket_width)
+        {
+            buckets.push_back(Bucket {
+                start: now,
+                requests: 0,
+                good: 0,
+            });
+        }
+        if let Some(bucket) = buckets.back_mut() {
+            bucket.requests += 1;
+            bucket.good += u64::from(good);
+        }
+        self.expire(buckets, now);
+    }
+
+    fn expire(&self, buckets: &mut VecDeque<Bucket>, now: Instant) {
+        while buckets
+            .front()
+            .is_some_and(|b| now.duration_since(b.start) > self.window)
+        {
+            buckets.pop_front();
+        }
+    }
+
+    /// Every objective's compliance over the window, ordered by model.
+    #[must_use]
+    pub fn report(&self) -> Vec<SloStatus> {
+        let now = self.clock.now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut report: Vec<SloStatus> = self
+            .objectives
+            .values()
+            .map(|objective| {
+                let (requests, good_requests) =
+                    buckets.get_mut(&objective.model).map_or((0, 0), |buckets| {
+                        self.expire(buckets, now);
+                        buckets
+                            .iter()
+                            .fold((0, 0), |(r, g), b| (r + b.requests, g + b.good))
+                    });
+                #[allow(clippy::cast_precision_loss)]
+                let compliance = if requests == 0 {
+                    1.0
+                } else {
+                    good_requests as f64 / requests as f64
+                };
+                SloStatus {
+                    model: objective.model.clone(),
+                    target: objective.target,
+                    latency_ms: objective.latency_ms,
+                    requests,
+                    good_requests,
+                    compliance,
+                    burn_rate: (1.0 - compliance) / (1.0 - objective.target),
+                }
+            })
+            .collect();
+        report.sort_by(|a, b| a.model.cmp(&b.model));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::clock::ManualClock;
+    use std::sync::Arc;
+
+    fn slos(objectives: &[&str], clock: Arc<ManualClock>) -> Slos {
+        Slos::from_config(&SloConfig {
+            objectives: objectives.iter().map(|o| (*o).to_string()).collect(),
+            window_secs: 600,
+            ..SloConfig::default()
+        })
+        .with_clock(clock)
+    }
+
+    #[test]
+    fn test_parse_objectives() {
+        let entries = vec![
+            "gemini-2.5-pro=99.5@2000".to_string(),
+            "gpt-4=99%".to_string(),
+        ];
+        assert_eq!(
+            parse_objectives(&entries),
+            Ok(vec![
+                Objective {
+                    model: "gemini-2.5-pro".to_string(),
+                    target: 0.995,
+                    latency_ms: Some(2000),
+                },
+                Objective {
+                    model: "gpt-4".to_string(),
+                    target: 0.99,
+                    latency_ms: None,
+                },
+            ])
+        );
+        for bad in ["gpt-4", "gpt-4=100", "gpt-4=99@fast", "=99"] {
+            assert!(parse_objectives(&[bad.to_string()]).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_compliance_and_burn_rate() {
+        let clock = Arc::new(ManualClock::new());
+        let slos = slos(&["gemini-2.5-pro=90@1000"], clock.clone());
+        let fast = Duration::from_millis(200);
+        for _ in 0..6 {
+            slos.record("gemini-2.5-pro", StatusCode::OK, fast);
+        }
+        // Client errors are good; server errors and slow answers are bad
+        slos.record("gemini-2.5-pro", StatusCode::BAD_REQUEST, fast);
+        slos.record("gemini-2.5-pro", StatusCode::BAD_GATEWAY, fast);
+        slos.record("gemini-2.5-pro", StatusCode::OK, Duration::from_secs(3));
+        slos.record("gemini-2.5-pro", StatusCode::OK, fast);
+        // Models without an objective are not tracked
+        slos.record("gpt-4", StatusCode::BAD_GATEWAY, fast);
+
+        let report = slos.report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].requests, 10);
+        assert_eq!(report[0].good_requests, 8);
+        assert!((report[0].compliance - 0.8).abs() < 1e-9);
+        assert!((report[0].burn_rate - 2.0).abs() < 1e-9);
+
+        // Requests age out of the window
+        clock.advance(Duration::from_secs(700));
+        let report = slos.report();
+        assert_eq!(report[0].requests, 0);
+        assert!(report[0].burn_rate.abs() < f64::EPSILON);
+    }
+}
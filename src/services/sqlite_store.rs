@@ -0,0 +1,474 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::services::storage::{bucket_ttl, take_bucket_token, Storage};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS kv_entries (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    expires_ms INTEGER
+);
+CREATE TABLE IF NOT EXISTS audit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ts INTEGER NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    detail TEXT NOT NULL
+);
+";
+
+// Tables from before usage records, API keys, rate limit buckets and circuit
+// breaker state moved to `kv_entries`, with the statements that copy their
+// rows over in the format their readers expect. Cached responses and rate
+// limit buckets are not worth carrying over.
+const LEGACY_TABLES: &[(&str, &str)] = &[
+    (
+        "usage_records",
+        "INSERT OR IGNORE INTO kv_entries (key, value)
+         SELECT 'usage:' || date(ts / 1000, 'unixepoch') || ':' || printf('%013d', ts) || ':' || id,
+                json_object('timestamp', ts, 'key', key, 'model', model,
+                            'prompt_tokens', prompt_tokens,
+                            'completion_tokens', completion_tokens, 'cost_usd', cost_usd)
+         FROM usage_records",
+    ),
+    (
+        "api_keys",
+        "INSERT OR IGNORE INTO kv_entries (key, value)
+         SELECT 'keys:' || name,
+                json_object('key_hash', key_hash,
+                            'identity', json_object('name', name, 'max_priority', max_priority,
+                                                    'admin', json(CASE WHEN admin THEN 'true' ELSE 'false' END)),
+                            'budget', json_object('daily_usd', daily_usd, 'monthly_usd', monthly_usd))
+         FROM api_keys",
+    ),
+    ("response_cache", ""),
+    ("rate_limit_buckets", ""),
+    (
+        "circuit_breakers",
+        "INSERT OR IGNORE INTO kv_entries (key, value)
+         SELECT 'circuit:' || name, snapshot FROM circuit_breakers",
+    ),
+];
+
+// Replicas share the database file, so writers wait for each other's locks
+// instead of failing with SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bound for keys starting with `prefix`: no key continues a prefix
+/// with a character above U+10FFFF.
+fn prefix_end(prefix: &str) -> String {
+    format!("{prefix}\u{10FFFF}")
+}
+
+fn expires_ms(ttl: Option<Duration>) -> Option<i64> {
+    ttl.map(|ttl| {
+        let ttl_ms = i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX);
+        Utc::now().timestamp_millis().saturating_add(ttl_ms)
+    })
+}
+
+/// SQLite-backed persistence: the key-value entries behind [`Storage`] and
+/// audit events.
+///
+/// `rusqlite` is blocking, so every call runs on the blocking thread pool
+/// behind a single shared connection.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Opens (creating if needed) the database at `path` and applies the schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or migrated.
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database '{path}'"))?;
+        Self::from_connection(conn)
+    }
+
+    /// In-memory database, mainly for tests.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the schema cannot be applied.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .context("Failed to set SQLite busy timeout")?;
+        conn.execute_batch(SCHEMA)
+            .context("Failed to apply SQLite schema")?;
+        Self::migrate_legacy_tables(&conn).context("Failed to migrate SQLite tables")?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    fn migrate_legacy_tables(conn: &Connection) -> rusqlite::Result<()> {
+        for (table, copy) in LEGACY_TABLES {
+            let exists: bool = conn.query_row(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                params![table],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                continue;
+            }
+            let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+            if !copy.is_empty() {
+                tx.execute(copy, [])?;
+            }
+            tx.execute(&format!("DROP TABLE {table}"), [])?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || {
+            let conn = conn
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            f(&conn)
+        })
+        .await
+        .context("SQLite task panicked")?
+        .context("SQLite query failed")
+    }
+
+    /// Appends an audit event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert fails.
+    pub async fn record_audit(&self, actor: &str, action: &str, detail: &str) -> Result<()> {
+        let (actor, action, detail) = (actor.to_string(), action.to_string(), detail.to_string());
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO audit_events (ts, actor, action, detail) VALUES (?1, ?2, ?3, ?4)",
+                params![Utc::now().timestamp_millis(), actor, action, detail],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Most recent audit event as `(actor, action, detail)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    pub async fn last_audit_event(&self) -> Result<Option<(String, String, String)>> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT actor, action, detail FROM audit_events ORDER BY id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStore {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let key = key.to_string();
+        let now_ms = Utc::now().timestamp_millis();
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT value FROM kv_entries
+                 WHERE key = ?1 AND (expires_ms IS NULL OR expires_ms > ?2)",
+                params![key, now_ms],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Option<Duration>) -> Result<()> {
+        let key = key.to_string();
+        let expires_ms = expires_ms(ttl);
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO kv_entries (key, value, expires_ms) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET
+                    value = excluded.value, expires_ms = excluded.expires_ms",
+                params![key, value, expires_ms],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn increment(&self, key: &str, by: f64) -> Result<f64> {
+        let key = key.to_string();
+        let now_ms = Utc::now().timestamp_millis();
+        self.with_conn(move |conn| {
+            // An expired entry counts as missing, so the sum restarts without expiry
+            conn.query_row(
+                "INSERT INTO kv_entries (key, value, expires_ms) VALUES (?1, ?2, NULL)
+                 ON CONFLICT(key) DO UPDATE SET
+                    value = CASE WHEN expires_ms IS NULL OR expires_ms > ?3
+                                 THEN CAST(value AS REAL) + excluded.value
+                                 ELSE excluded.value END,
+                    expires_ms = CASE WHEN expires_ms > ?3 THEN expires_ms END
+                 RETURNING CAST(value AS REAL)",
+                params![key, by, now_ms],
+                |row| row.get(0),
+            )
+        })
+        .await
+    }
+
+    async fn take_token(
+        &self,
+        key: &str,
+        capacity: u32,
+        refill_per_second: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(bool, u32)> {
+        let key = key.to_string();
+        let (now_ms, expires_ms) = (
+            Utc::now().timestamp_millis(),
+            expires_ms(Some(bucket_ttl(capacity, refill_per_second))),
+        );
+        self.with_conn(move |conn| {
+            // Immediate, so replicas sharing the file take turns on the bucket
+            let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+            let stored: Option<String> = tx
+                .query_row(
+                    "SELECT value FROM kv_entries
+                     WHERE key = ?1 AND (expires_ms IS NULL OR expires_ms > ?2)",
+                    params![key, now_ms],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let (allowed, tokens, value) = take_bucket_token(
+                stored.as_deref(),
+                capacity,
+                refill_per_second,
+                now.timestamp_millis(),
+            );
+            tx.execute(
+                "INSERT INTO kv_entries (key, value, expires_ms) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET
+                    value = excluded.value, expires_ms = excluded.expires_ms",
+                params![key, value, expires_ms],
+            )?;
+            tx.commit()?;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Ok((allowed, tokens.floor() as u32))
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        let key = key.to_string();
+        let now_ms = Utc::now().timestamp_millis();
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "DELETE FROM kv_entries WHERE key = ?1
+                 RETURNING expires_ms IS NULL OR expires_ms > ?2",
+                params![key, now_ms],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(|live| live.unwrap_or(false))
+        })
+        .await
+    }
+
+    async fn scan(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let (start, end) = (prefix.to_string(), prefix_end(prefix));
+        let now_ms = Utc::now().timestamp_millis();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT key, value FROM kv_entries
+                 WHERE key >= ?1 AND key < ?2 AND (expires_ms IS NULL OR expires_ms > ?3)
+                 ORDER BY key",
+            )?;
+            let rows = stmt.query_map(params![start, end, now_ms], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn purge_expired(&self, prefix: &str) -> Result<usize> {
+        let (start, end) = (prefix.to_string(), prefix_end(prefix));
+        let now_ms = Utc::now().timestamp_millis();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM kv_entries WHERE key >= ?1 AND key < ?2 AND expires_ms <= ?3",
+                params![start, end, now_ms],
+            )
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::keys;
+    use crate::services::scheduler::Priority;
+
+    #[tokio::test]
+    async fn test_kv_entries_expire_and_scan_by_prefix() {
+        let store = SqliteStore::open_in_memory().expect("store should open");
+        store
+            .set("usage:b", "2".to_string(), None)
+            .await
+            .expect("set should succeed");
+        store
+            .set("usage:a", "1".to_string(), None)
+            .await
+            .expect("set should succeed");
+        store
+            .set("cache:a", "gone".to_string(), Some(Duration::ZERO))
+            .await
+            .expect("set should succeed");
+        store
+            .set("usage:a", "1'".to_string(), None)
+            .await
+            .expect("set should replace");
+
+        assert_eq!(
+            store.scan("usage:").await.expect("scan should succeed"),
+            vec![
+                ("usage:a".to_string(), "1'".to_string()),
+                ("usage:b".to_string(), "2".to_string())
+            ]
+        );
+        assert_eq!(store.get("cache:a").await.expect("get should work"), None);
+        assert_eq!(store.purge_expired("usage:").await.expect("purge"), 0);
+        assert_eq!(store.purge_expired("cache:").await.expect("purge"), 1);
+        assert!(store.delete("usage:b").await.expect("delete should work"));
+        assert!(!store.delete("usage:b").await.expect("delete should work"));
+        assert_eq!(store.scan("usage:").await.expect("scan").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_increment_adds_to_stored_number() {
+        let store = SqliteStore::open_in_memory().expect("store should open");
+        assert!((store.increment("spend:a", 1.25).await.expect("increment") - 1.25).abs() < 1e-9);
+        assert!((store.increment("spend:a", 0.5).await.expect("increment") - 1.75).abs() < 1e-9);
+        assert_eq!(
+            store.get("spend:a").await.expect("get should work"),
+            Some("1.75".to_string())
+        );
+
+        store
+            .set("spend:b", "9".to_string(), Some(Duration::ZERO))
+            .await
+            .expect("set should succeed");
+        assert!((store.increment("spend:b", 2.0).await.expect("increment") - 2.0).abs() < 1e-9);
+        assert_eq!(
+            store.get("spend:b").await.expect("get should work"),
+            Some("2.0".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_legacy_tables_migrate_to_kv_entries() {
+        let conn = Connection::open_in_memory().expect("database should open");
+        conn.execute_batch(
+            "CREATE TABLE usage_records (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, ts INTEGER NOT NULL, key TEXT NOT NULL,
+                model TEXT NOT NULL, prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL, cost_usd REAL NOT NULL);
+             CREATE TABLE api_keys (
+                name TEXT PRIMARY KEY, key_hash TEXT NOT NULL UNIQUE,
+                max_priority TEXT NOT NULL DEFAULT 'normal', admin INTEGER NOT NULL DEFAULT 0,
+                daily_usd REAL, monthly_usd REAL);
+             CREATE TABLE response_cache (
+                key TEXT PRIMARY KEY, response TEXT NOT NULL, expires_ms INTEGER NOT NULL);
+             CREATE TABLE rate_limit_buckets (
+                key TEXT PRIMARY KEY, tokens REAL NOT NULL, updated_ms INTEGER NOT NULL);
+             CREATE TABLE circuit_breakers (name TEXT PRIMARY KEY, snapshot TEXT NOT NULL);
+             INSERT INTO circuit_breakers (name, snapshot) VALUES ('upstream', '{}');
+             INSERT INTO usage_records (ts, key, model, prompt_tokens, completion_tokens, cost_usd)
+                VALUES (1700000000000, 'a', 'gemini-pro', 10, 5, 2.0);
+             INSERT INTO api_keys (name, key_hash, max_priority, admin, daily_usd)
+                VALUES ('ci', 'abc', 'low', 1, 1.5);",
+        )
+        .expect("legacy schema should apply");
+        let store = SqliteStore::from_connection(conn).expect("migration should succeed");
+
+        let usage = store.scan("usage:").await.expect("scan should succeed");
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].0, "usage:2023-11-14:1700000000000:1");
+        let record: crate::services::usage::UsageRecord =
+            serde_json::from_str(&usage[0].1).expect("record should parse");
+        assert_eq!(record.timestamp.timestamp_millis(), 1_700_000_000_000);
+        assert!((record.cost_usd - 2.0).abs() < 1e-9);
+
+        let stored = keys::load_stored(&store).await.expect("keys should load");
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].key_hash, "abc");
+        assert_eq!(stored[0].identity.max_priority, Priority::Low);
+        assert!(stored[0].identity.admin);
+        assert_eq!(stored[0].budget.daily_usd, Some(1.5));
+        assert_eq!(stored[0].budget.monthly_usd, None);
+        assert_eq!(
+            store
+                .get("circuit:upstream")
+                .await
+                .expect("get should work"),
+            Some("{}".to_string())
+        );
+
+        let legacy_tables = store
+            .with_conn(|conn| {
+                conn.query_row(
+                    "SELECT COUNT(*) FROM sqlite_master
+                     WHERE name IN ('usage_records', 'api_keys', 'response_cache',
+                                    'rate_limit_buckets', 'circuit_breakers')",
+                    [],
+                    |row| row.get::<_, i64>(0),
+                )
+            })
+            .await
+            .expect("query should succeed");
+        assert_eq!(legacy_tables, 0);
+    }
+
+    #[tokio::test]
+    async fn test_audit_roundtrip() {
+        let store = SqliteStore::open_in_memory().expect("store should open");
+        store
+            .record_audit("master", "budget.set", "ci")
+            .await
+            .expect("audit should succeed");
+        assert_eq!(
+            store
+                .last_audit_event()
+                .await
+                .expect("query should succeed"),
+            Some((
+                "master".to_string(),
+                "budget.set".to_string(),
+                "ci".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+/// An incident announcement, as sent to `PUT /admin/incident`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct IncidentRequest {
+    /// Shown on the public status page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// An open incident.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Incident {
+    pub started_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub settings: IncidentRequest,
+}
+
+/// State behind the public `/status` page: when the proxy started and the
+/// incident flag operators raise through the admin API.
+#[derive(Debug)]
+pub struct StatusPage {
+    started: Instant,
+    incident: RwLock<Option<Incident>>,
+}
+
+impl Default for StatusPage {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            incident: RwLock::new(None),
+        }
+    }
+}
+
+impl StatusPage {
+    /// Time since the proxy started.
+    #[must_use]
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Raises (or replaces) the incident flag.
+    pub async fn raise(&self, settings: IncidentRequest) -> Incident {
+        let incident = Incident {
+            started_at: Utc::now(),
+            settings,
+        };
+        *self.incident.write().await = Some(incident.clone());
+        incident
+    }
+
+    /// Clears the incident flag, returning whether it was raised.
+    pub async fn resolve(&self) -> bool {
+        self.incident.write().await.take().is_some()
+    }
+
+    pub async fn incident(&self) -> Option<Incident> {
+        self.incident.read().await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_incident_flag() {
+        let page = StatusPage::default();
+        assert!(page.incident().await.is_none());
+        assert!(!page.resolve().await);
+
+        page.raise(IncidentRequest {
+            message: Some("Vertex errors".to_string()),
+        })
+        .await;
+        let incident = page.incident().await.expect("incident should be raised");
+        assert_eq!(incident.settings.message.as_deref(), Some("Vertex errors"));
+        assert!(page.resolve().await);
+        assert!(page.incident().await.is_none());
+    }
+}
@@ -0,0 +1,502 @@
+// Key-value persistence shared by the stateful subsystems.
+//
+// The response cache, idempotency store, usage tracker, API key store, rate
+// limiter and circuit breaker keep their durable state through a `Storage`:
+// string values under string keys, each optionally expiring. Every subsystem writes under its own key
+// prefix, so one backend holds them all. `MemoryStorage` keeps entries in
+// process memory, `SqliteStore` in the `kv_entries` table of the database at
+// `storage.sqlite_path`, and `RedisStorage` (with the `redis` feature) in the
+// Redis server at `storage.redis_url`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::services::clock::{self, SharedClock};
+
+/// Durable string-to-string map with per-entry expiry.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// The value under `key`, unless it is missing or expired.
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+
+    /// Stores `value` under `key`, replacing any previous value. With a `ttl`
+    /// the entry expires that long from now.
+    async fn set(&self, key: &str, value: String, ttl: Option<Duration>) -> Result<()>;
+
+    /// Adds `by` to the number stored under `key`, counting a missing entry as
+    /// 0, and returns the new value. Concurrent increments from any process
+    /// sharing the backend are never lost.
+    async fn increment(&self, key: &str, by: f64) -> Result<f64>;
+
+    /// Refills the token bucket under `key` at `refill_per_second`, up to
+    /// `capacity`, for the time since it was last drawn from and takes one
+    /// token if there is one. Returns whether a token was taken and how many
+    /// whole tokens remain. Concurrent callers never take the same token twice,
+    /// and a bucket expires once it would have refilled completely.
+    async fn take_token(
+        &self,
+        key: &str,
+        capacity: u32,
+        refill_per_second: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(bool, u32)>;
+
+    /// Removes `key`, returning whether a live entry was there.
+    async fn delete(&self, key: &str) -> Result<bool>;
+
+    /// Live entries whose key starts with `prefix`, in key order.
+    async fn scan(&self, prefix: &str) -> Result<Vec<(String, String)>>;
+
+    /// Drops expired entries under `prefix` and returns how many. Backends
+    /// that expire entries on their own return 0.
+    async fn purge_expired(&self, prefix: &str) -> Result<usize>;
+}
+
+/// A storage backend shared by the subsystems using it.
+pub type SharedStorage = Arc<dyn Storage>;
+
+/// Takes one token from a bucket stored as `<tokens>:<updated ms>`, refilled
+/// up to `now_ms`; a missing or unreadable bucket starts full. Returns whether
+/// a token was taken and the bucket's new value.
+pub(crate) fn take_bucket_token(
+    stored: Option<&str>,
+    capacity: u32,
+    refill_per_second: u32,
+    now_ms: i64,
+) -> (bool, f64, String) {
+    let capacity = f64::from(capacity);
+    let stored = stored
+        .and_then(|value| value.split_once(':'))
+        .and_then(|(tokens, at)| Some((tokens.parse::<f64>().ok()?, at.parse::<i64>().ok()?)));
+    #[allow(clippy::cast_precision_loss)]
+    let tokens = stored.map_or(capacity, |(tokens, updated_ms)| {
+        let elapsed_secs = now_ms.saturating_sub(updated_ms).max(0) as f64 / 1000.0;
+        (tokens + elapsed_secs * f64::from(refill_per_second)).min(capacity)
+    });
+    let allowed = tokens >= 1.0;
+    let tokens = if allowed { tokens - 1.0 } else { tokens };
+    (allowed, tokens, format!("{tokens}:{now_ms}"))
+}
+
+/// How long an emptied bucket takes to refill, after which it can be dropped.
+pub(crate) fn bucket_ttl(capacity: u32, refill_per_second: u32) -> Duration {
+    Duration::from_secs(u64::from(capacity.div_ceil(refill_per_second.max(1))).max(1))
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn whole_tokens(tokens: f64) -> u32 {
+    tokens.floor() as u32
+}
+
+struct MemoryEntry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl MemoryEntry {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires_at.is_none_or(|at| now < at)
+    }
+}
+
+/// Entries in process memory, lost on restart.
+pub struct MemoryStorage {
+    entries: Mutex<BTreeMap<String, MemoryEntry>>,
+    clock: SharedClock,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStorage {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    #[must_use]
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, MemoryEntry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let now = self.clock.now();
+        Ok(self
+            .lock()
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Option<Duration>) -> Result<()> {
+        let expires_at = ttl.map(|ttl| self.clock.now() + ttl);
+        self.lock()
+            .insert(key.to_string(), MemoryEntry { value, expires_at });
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, by: f64) -> Result<f64> {
+        let now = self.clock.now();
+        let mut entries = self.lock();
+        let entry = entries.entry(key.to_string()).or_insert(MemoryEntry {
+            value: "0".to_string(),
+            expires_at: None,
+        });
+        if !entry.is_live(now) {
+            *entry = MemoryEntry {
+                value: "0".to_string(),
+                expires_at: None,
+            };
+        }
+        let value = entry.value.parse::<f64>()? + by;
+        entry.value = value.to_string();
+        Ok(value)
+    }
+
+    async fn take_token(
+        &self,
+        key: &str,
+        capacity: u32,
+        refill_per_second: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(bool, u32)> {
+        let at = self.clock.now();
+        let mut entries = self.lock();
+        let stored = entries
+            .get(key)
+            .filter(|entry| entry.is_live(at))
+            .map(|entry| entry.value.as_str());
+        let (allowed, tokens, value) =
+            take_bucket_token(stored, capacity, refill_per_second, now.timestamp_millis());
+        let expires_at = Some(at + bucket_ttl(capacity, refill_per_second));
+        entries.insert(key.to_string(), MemoryEntry { value, expires_at });
+        Ok((allowed, whole_tokens(tokens)))
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        let now = self.clock.now();
+        Ok(self.lock().remove(key).is_some_and(|e| e.is_live(now)))
+    }
+
+    async fn scan(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let now = self.clock.now();
+        Ok(self
+            .lock()
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .filter(|(_, entry)| entry.is_live(now))
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect())
+    }
+
+    async fn purge_expired(&self, prefix: &str) -> Result<usize> {
+        let now = self.clock.now();
+        let mut entries = self.lock();
+        let before = entries.len();
+        entries.retain(|key, entry| !key.starts_with(prefix) || entry.is_live(now));
+        Ok(before - entries.len())
+    }
+}
+
+/// Opens the Redis backend at `url`.
+///
+/// # Errors
+///
+/// Returns an error if the URL is invalid, the server cannot be reached, or
+/// the proxy was built without the `redis` feature.
+#[cfg(feature = "redis")]
+pub async fn open_redis(url: &str) -> Result<SharedStorage> {
+    Ok(Arc::new(RedisStorage::open(url).await?))
+}
+
+#[cfg(not(feature = "redis"))]
+pub async fn open_redis(_url: &str) -> Result<SharedStorage> {
+    anyhow::bail!("Redis storage needs the proxy built with the `redis` feature")
+}
+
+/// Entries in a Redis server, which expires them itself.
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct RedisStorage {
+    conn: redis::aio::ConnectionManager,
+}
+
+#[cfg(feature = "redis")]
+impl RedisStorage {
+    /// Connects to the Redis server at `url`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or the server cannot be reached.
+    pub async fn open(url: &str) -> Result<Self> {
+        use anyhow::Context;
+
+        let client = redis::Client::open(url).context("Invalid Redis URL")?;
+        let conn = client
+            .get_connection_manager()
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(Self { conn })
+    }
+}
+
+// `take_bucket_token` as a script, so the read and write are one atomic step
+#[cfg(feature = "redis")]
+const TAKE_TOKEN_SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local refill = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local tokens = capacity
+local stored = redis.call('GET', KEYS[1])
+if stored then
+    local left, at = string.match(stored, '^([^:]+):(.+)$')
+    left, at = tonumber(left), tonumber(at)
+    if left and at then
+        tokens = math.min(capacity, left + math.max(now - at, 0) / 1000 * refill)
+    end
+end
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('SET', KEYS[1], tokens .. ':' .. ARGV[3], 'PX', ARGV[4])
+return {allowed, math.floor(tokens)}
+";
+
+/// `prefix` as a `SCAN MATCH` pattern matching only keys that start with it.
+#[cfg(feature = "redis")]
+fn match_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl Storage for RedisStorage {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        use redis::AsyncCommands;
+
+        Ok(self.conn.clone().get(key).await?)
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Option<Duration>) -> Result<()> {
+        use redis::AsyncCommands;
+
+        let mut conn = self.conn.clone();
+        match ttl {
+            // PX rejects 0, and an entry that expires immediately is no entry
+            Some(ttl) if ttl.is_zero() => {
+                let _: usize = conn.del(key).await?;
+            }
+            Some(ttl) => {
+                let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+                conn.pset_ex::<_, _, ()>(key, value, millis).await?;
+            }
+            None => conn.set::<_, _, ()>(key, value).await?,
+        }
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, by: f64) -> Result<f64> {
+        use redis::AsyncCommands;
+
+        // INCRBYFLOAT
+        Ok(self.conn.clone().incr(key, by).await?)
+    }
+
+    async fn take_token(
+        &self,
+        key: &str,
+        capacity: u32,
+        refill_per_second: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(bool, u32)> {
+        let ttl_ms = bucket_ttl(capacity, refill_per_second).as_millis();
+        let (allowed, tokens): (i64, u32) = redis::cmd("EVAL")
+            .arg(TAKE_TOKEN_SCRIPT)
+            .arg(1)
+            .arg(key)
+            .arg(capacity)
+            .arg(refill_per_second)
+            .arg(now.timestamp_millis())
+            .arg(u64::try_from(ttl_ms).unwrap_or(u64::MAX))
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok((allowed == 1, tokens))
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        use redis::AsyncCommands;
+
+        let removed: usize = self.conn.clone().del(key).await?;
+        Ok(removed > 0)
+    }
+
+    async fn scan(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        use redis::AsyncCommands;
+
+        let mut conn = self.conn.clone();
+        let mut keys: Vec<String> = Vec::new();
+        {
+            let mut iter = conn.scan_match::<_, String>(match_prefix(prefix)).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        keys.sort();
+        keys.dedup();
+        // Keys that expired between SCAN and MGET come back as nil
+        let values: Vec<Option<String>> =
+            redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+        Ok(keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect())
+    }
+
+    async fn purge_expired(&self, _prefix: &str) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::clock::ManualClock;
+
+    #[tokio::test]
+    async fn test_memory_storage_expires_and_scans_by_prefix() {
+        let clock = Arc::new(ManualClock::new());
+        let storage = MemoryStorage::new().with_clock(clock.clone());
+        storage
+            .set("cache:b", "2".to_string(), Some(Duration::from_secs(10)))
+            .await
+            .expect("set should succeed");
+        storage
+            .set("cache:a", "1".to_string(), None)
+            .await
+            .expect("set should succeed");
+        storage
+            .set("keys:a", "k".to_string(), None)
+            .await
+            .expect("set should succeed");
+
+        assert_eq!(
+            storage.scan("cache:").await.expect("scan should succeed"),
+            vec![
+                ("cache:a".to_string(), "1".to_string()),
+                ("cache:b".to_string(), "2".to_string())
+            ]
+        );
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(storage.get("cache:b").await.expect("get should work"), None);
+        assert!(!storage.delete("cache:b").await.expect("delete should work"));
+        storage
+            .set("cache:c", "3".to_string(), Some(Duration::from_secs(1)))
+            .await
+            .expect("set should succeed");
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(storage.purge_expired("keys:").await.expect("purge"), 0);
+        assert_eq!(storage.purge_expired("cache:").await.expect("purge"), 1);
+        assert_eq!(storage.scan("cache:").await.expect("scan").len(), 1);
+        assert!(storage.delete("keys:a").await.expect("delete should work"));
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_increment_starts_from_zero() {
+        let clock = Arc::new(ManualClock::new());
+        let storage = MemoryStorage::new().with_clock(clock.clone());
+        assert!((storage.increment("spend:a", 1.5).await.expect("increment") - 1.5).abs() < 1e-9);
+        assert!((storage.increment("spend:a", 2.0).await.expect("increment") - 3.5).abs() < 1e-9);
+
+        storage
+            .set("spend:b", "7".to_string(), Some(Duration::from_secs(1)))
+            .await
+            .expect("set should succeed");
+        clock.advance(Duration::from_secs(1));
+        assert!((storage.increment("spend:b", 1.0).await.expect("increment") - 1.0).abs() < 1e-9);
+        storage
+            .set("spend:c", "x".to_string(), None)
+            .await
+            .expect("set should succeed");
+        assert!(storage.increment("spend:c", 1.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_token_bucket_refills_and_expires() {
+        let clock = Arc::new(ManualClock::new());
+        let storage = MemoryStorage::new().with_clock(clock.clone());
+        let start = Utc::now();
+        assert_eq!(
+            storage.take_token("rl:a", 2, 1, start).await.expect("take"),
+            (true, 1)
+        );
+        assert_eq!(
+            storage.take_token("rl:a", 2, 1, start).await.expect("take"),
+            (true, 0)
+        );
+        assert_eq!(
+            storage.take_token("rl:a", 2, 1, start).await.expect("take"),
+            (false, 0)
+        );
+        let later = start + chrono::Duration::milliseconds(1500);
+        assert_eq!(
+            storage.take_token("rl:a", 2, 1, later).await.expect("take"),
+            (true, 0)
+        );
+
+        // Once it would be full again the bucket is dropped
+        clock.advance(bucket_ttl(2, 1));
+        assert_eq!(storage.purge_expired("rl:").await.expect("purge"), 1);
+    }
+
+    #[test]
+    fn test_take_bucket_token_starts_full_on_unreadable_value() {
+        let (allowed, tokens, value) = take_bucket_token(Some("garbage"), 3, 1, 5_000);
+        assert!(allowed);
+        assert!((tokens - 2.0).abs() < 1e-9);
+        assert_eq!(value, "2:5000");
+        assert_eq!(bucket_ttl(10, 3), Duration::from_secs(4));
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_redis_match_prefix_escapes_glob_characters() {
+        assert_eq!(match_prefix("usage:"), "usage:*");
+        assert_eq!(match_prefix("cache:5:a*b|[x]"), "cache:5:a\\*b|\\[x\\]*");
+    }
+}
@@ -0,0 +1,370 @@
+// Token counting per model family.
+//
+// Context-length checks, routing rules, dry runs and the usage recorded for
+// providers that report none all need a token count before (or without) the
+// provider saying how many tokens it saw. A flat characters-per-token ratio
+// is far off for code, numbers and non-Latin scripts, and the families
+// differ. Each family gets its own `Tokenizer`, picked by model name. GPT
+// models are counted exactly with OpenAI's published BPE vocabularies:
+// `o200k_base` for GPT-4o and later, `cl100k_base` for GPT-4 and GPT-3.5.
+// Gemini's SentencePiece vocabulary and Claude's tokenizer are not available
+// offline, so they are approximated from the text; `/v1/tokens/count` asks
+// Vertex for an exact Gemini count and only falls back to the estimate when
+// that fails. Counting here never needs a network call. Counts of long texts
+// are cached, as the same conversation history is counted again on every
+// turn.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+use tiktoken_rs::CoreBPE;
+
+use crate::models::openai::{ChatCompletionRequest, ChatMessage, Usage};
+
+/// Characters per token of the generic estimate, for models of no known family.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+// Texts shorter than this are counted again rather than cached
+const CACHE_MIN_BYTES: usize = 256;
+// Entries kept before the cache is cleared
+const CACHE_CAPACITY: usize = 4096;
+
+/// Counts the tokens a model family would see in a text.
+pub trait Tokenizer: Send + Sync {
+    /// Name reported with counts, e.g. `o200k_base`.
+    fn name(&self) -> &'static str;
+
+    /// Tokens in `text`.
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Approximates a BPE or SentencePiece tokenizer from the runs of letters,
+/// digits, symbols and whitespace its pre-tokenizer would split `text` into.
+struct RunTokenizer {
+    name: &'static str,
+    letters_per_token: usize,
+    digits_per_token: usize,
+    symbols_per_token: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Letter,
+    Digit,
+    Space,
+    Symbol,
+    /// Outside ASCII; roughly a token per character for CJK, and never merged.
+    Other,
+}
+
+impl CharClass {
+    fn of(c: char) -> Self {
+        if c.is_ascii_alphabetic() {
+            Self::Letter
+        } else if c.is_ascii_digit() {
+            Self::Digit
+        } else if c.is_whitespace() {
+            Self::Space
+        } else if c.is_ascii() {
+            Self::Symbol
+        } else {
+            Self::Other
+        }
+    }
+}
+
+impl RunTokenizer {
+    fn run_tokens(&self, class: CharClass, len: usize) -> usize {
+        match class {
+            CharClass::Letter => len.div_ceil(self.letters_per_token),
+            CharClass::Digit => len.div_ceil(self.digits_per_token),
+            CharClass::Symbol => len.div_ceil(self.symbols_per_token),
+            // A single space joins the word after it, longer runs are one token
+            CharClass::Space => usize::from(len > 1),
+            CharClass::Other => len,
+        }
+    }
+}
+
+impl Tokenizer for RunTokenizer {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        let mut tokens = 0;
+        let mut run: Option<(CharClass, usize)> = None;
+        for class in text.chars().map(CharClass::of) {
+            run = match run {
+                Some((current, len)) if current == class && class != CharClass::Other => {
+                    Some((current, len + 1))
+                }
+                Some((current, len)) => {
+                    tokens += self.run_tokens(current, len);
+                    Some((class, 1))
+                }
+                None => Some((class, 1)),
+            };
+        }
+        if let Some((class, len)) = run {
+            tokens += self.run_tokens(class, len);
+        }
+        tokens
+    }
+}
+
+/// Counts exactly with one of OpenAI's BPE vocabularies, loaded on first use.
+struct BpeTokenizer {
+    name: &'static str,
+    encoding: fn() -> &'static CoreBPE,
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        // Special-token text in a prompt is sent as plain text
+        (self.encoding)().count_ordinary(text)
+    }
+}
+
+/// Estimates `tokens` tokens per `chars` characters of text.
+struct RatioTokenizer {
+    name: &'static str,
+    chars: usize,
+    tokens: usize,
+}
+
+impl Tokenizer for RatioTokenizer {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn count(&self, text: &str) -> usize {
+        text.chars()
+            .count()
+            .saturating_mul(self.tokens)
+            .div_ceil(self.chars)
+    }
+}
+
+/// GPT-4o, GPT-4.1, GPT-5 and the o-series reasoning models.
+static O200K: BpeTokenizer = BpeTokenizer {
+    name: "o200k_base",
+    encoding: tiktoken_rs::o200k_base_singleton,
+};
+
+/// GPT-4 and GPT-3.5.
+static CL100K: BpeTokenizer = BpeTokenizer {
+    name: "cl100k_base",
+    encoding: tiktoken_rs::cl100k_base_singleton,
+};
+
+/// Gemini models: SentencePiece with a large vocabulary, which keeps longer
+/// words whole but gives every digit and symbol its own piece.
+static GEMINI: RunTokenizer = RunTokenizer {
+    name: "gemini_approx",
+    letters_per_token: 7,
+    digits_per_token: 1,
+    symbols_per_token: 1,
+};
+
+/// Claude models: Anthropic's tokenizer is not published; its guidance is
+/// about 3.5 characters per token.
+static CLAUDE: RatioTokenizer = RatioTokenizer {
+    name: "claude_approx",
+    chars: 7,
+    tokens: 2,
+};
+
+/// Every other model.
+static GENERIC: RatioTokenizer = RatioTokenizer {
+    name: "chars_approx",
+    chars: CHARS_PER_TOKEN,
+    tokens: 1,
+};
+
+/// Model name prefixes and the tokenizer of their family; the first match
+/// wins.
+static FAMILIES: &[(&str, &dyn Tokenizer)] = &[
+    ("gpt-4o", &O200K),
+    ("gpt-4.", &O200K),
+    ("gpt-4", &CL100K),
+    ("gpt-3.5", &CL100K),
+    ("gpt-", &O200K),
+    ("chatgpt-", &O200K),
+    ("o1", &O200K),
+    ("o3", &O200K),
+    ("o4", &O200K),
+    ("gemini-", &GEMINI),
+    ("gemma-", &GEMINI),
+    ("claude-", &CLAUDE),
+    ("anthropic.claude-", &CLAUDE),
+];
+
+/// The tokenizer for `model`'s family, or the generic estimate.
+#[must_use]
+pub fn for_model(model: &str) -> &'static dyn Tokenizer {
+    FAMILIES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map_or(&GENERIC, |(_, tokenizer)| *tokenizer)
+}
+
+/// Tokens in `text` for `model`, cached for long texts.
+#[must_use]
+pub fn count(model: &str, text: &str) -> usize {
+    let tokenizer = for_model(model);
+    if text.len() < CACHE_MIN_BYTES {
+        return tokenizer.count(text);
+    }
+    static CACHE: OnceLock<Mutex<HashMap<(&'static str, u64), usize>>> = OnceLock::new();
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    let key = (tokenizer.name(), hasher.finish());
+    let cache = CACHE.get_or_init(Mutex::default);
+    if let Some(tokens) = cache.lock().ok().and_then(|cache| cache.get(&key).copied()) {
+        return tokens;
+    }
+    let tokens = tokenizer.count(text);
+    if let Ok(mut cache) = cache.lock() {
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(key, tokens);
+    }
+    tokens
+}
+
+/// Tokens `model` is sent for `messages`, once per prompt when there are
+/// `prompts` to fan out, plus the prompts themselves.
+#[must_use]
+pub fn count_prompt(model: &str, messages: &[ChatMessage], prompts: &[String]) -> usize {
+    let shared: usize = messages.iter().map(|m| count(model, &m.content)).sum();
+    let prompts_tokens: usize = prompts.iter().map(|p| count(model, p)).sum();
+    shared
+        .saturating_mul(prompts.len().max(1))
+        .saturating_add(prompts_tokens)
+}
+
+/// Tokens of prompt text `req` sends upstream.
+#[must_use]
+pub fn prompt_tokens(req: &ChatCompletionRequest) -> usize {
+    count_prompt(&req.model, &req.messages, &req.prompts)
+}
+
+/// Usage from estimated prompt and completion token counts, for providers
+/// that report none.
+#[must_use]
+pub fn estimate_usage(prompt_tokens: usize, completion_tokens: usize) -> Usage {
+    let prompt_tokens = u32::try_from(prompt_tokens).unwrap_or(u32::MAX);
+    let completion_tokens = u32::try_from(completion_tokens).unwrap_or(u32::MAX);
+    Usage {
+        estimated: true,
+        ..Usage::new(
+            prompt_tokens,
+            completion_tokens,
+            prompt_tokens.saturating_add(completion_tokens),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::Role;
+
+    fn message(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: Role::User,
+            content: content.to_string(),
+            name: None,
+            images: 0,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_family_by_model_name() {
+        assert_eq!(for_model("gpt-4o").name(), "o200k_base");
+        assert_eq!(for_model("gpt-4o-mini").name(), "o200k_base");
+        assert_eq!(for_model("gpt-4.1").name(), "o200k_base");
+        assert_eq!(for_model("gpt-5").name(), "o200k_base");
+        assert_eq!(for_model("o3-mini").name(), "o200k_base");
+        assert_eq!(for_model("gpt-4").name(), "cl100k_base");
+        assert_eq!(for_model("gpt-4-turbo").name(), "cl100k_base");
+        assert_eq!(for_model("gpt-3.5-turbo").name(), "cl100k_base");
+        assert_eq!(for_model("gemini-1.5-pro").name(), "gemini_approx");
+        assert_eq!(for_model("claude-3-5-sonnet").name(), "claude_approx");
+        assert_eq!(for_model("deepseek-chat").name(), "chars_approx");
+    }
+
+    #[test]
+    fn test_gpt_counts_match_tiktoken() {
+        let gpt4 = for_model("gpt-4");
+        assert_eq!(gpt4.count(""), 0);
+        assert_eq!(gpt4.count("hello world"), 2);
+        assert_eq!(gpt4.count("tiktoken is great!"), 6);
+        assert_eq!(gpt4.count("antidisestablishmentarianism"), 6);
+        assert_eq!(gpt4.count("2 + 2 = 4"), 7);
+        // Special tokens in user text are counted as plain text
+        assert!(gpt4.count("<|endoftext|>") > 1);
+
+        // The larger vocabulary merges more of other scripts
+        let gpt4o = for_model("gpt-4o");
+        assert_eq!(gpt4o.count("antidisestablishmentarianism"), 6);
+        assert_eq!(gpt4.count("お誕生日おめでとう"), 9);
+        assert_eq!(gpt4o.count("お誕生日おめでとう"), 8);
+    }
+
+    #[test]
+    fn test_gemini_splits_digits_and_symbols() {
+        let gemini = for_model("gemini-pro");
+        assert_eq!(gemini.count("Hello there"), 2);
+        assert_eq!(gemini.count("1234567"), 7);
+        assert_eq!(gemini.count("a, b"), 3);
+    }
+
+    #[test]
+    fn test_ratio_tokenizers() {
+        assert_eq!(for_model("claude-3-opus").count("1234567"), 2);
+        assert_eq!(for_model("claude-3-opus").count("12345678"), 3);
+        assert_eq!(for_model("llama3").count("12345678"), 2);
+        assert_eq!(for_model("llama3").count("123456789"), 3);
+    }
+
+    #[test]
+    fn test_cached_count_matches_uncached() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let expected = for_model("gpt-4").count(&text);
+        assert_eq!(count("gpt-4", &text), expected);
+        assert_eq!(count("gpt-4-0613", &text), expected);
+        // Same text, different tokenizers
+        assert_eq!(count("gpt-4o", &text), for_model("gpt-4o").count(&text));
+        assert_eq!(
+            count("gemini-pro", &text),
+            for_model("gemini-pro").count(&text)
+        );
+    }
+
+    #[test]
+    fn test_prompt_tokens_fan_out() {
+        let messages = [message("Hello there")];
+        assert_eq!(count_prompt("gpt-4", &messages, &[]), 2);
+        // Shared messages are sent with every prompt
+        let prompts = ["one".to_string(), "two three".to_string()];
+        assert_eq!(count_prompt("gpt-4", &messages, &prompts), 7);
+    }
+
+    #[test]
+    fn test_estimated_usage() {
+        let usage = estimate_usage(6, 3);
+        assert_eq!(usage.prompt_tokens, 6);
+        assert_eq!(usage.completion_tokens, 3);
+        assert_eq!(usage.total_tokens, 9);
+        assert!(usage.estimated);
+    }
+}
@@ -0,0 +1,254 @@
+// Request IDs and W3C trace context.
+//
+// Every request gets an ID (the caller's `X-Request-ID` when it sends a usable
+// one) and a trace context: an incoming `traceparent` is joined, otherwise a
+// new trace starts. Both are forwarded to Vertex, the Anthropic bridge and the
+// harvester so their logs can be matched to the proxy's. The context is scoped
+// to the handling task, so upstream clients read it without it being threaded
+// through every call.
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::future::Future;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+// Longer client-supplied IDs are replaced rather than truncated
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// Identity of one proxied request within a (possibly larger) trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub request_id: String,
+    /// 32 lowercase hex digits shared by every hop of the trace.
+    pub trace_id: String,
+    /// 16 lowercase hex digits identifying the proxy's span; upstream calls
+    /// name it as their parent.
+    pub span_id: String,
+    /// The caller's span, when the request joined an existing trace.
+    pub parent_id: Option<String>,
+    pub sampled: bool,
+    /// Vendor state from the caller, forwarded untouched.
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Builds the context for an incoming request from its headers.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let request_id = headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|id| is_valid_request_id(id))
+            .map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+        let parent = headers
+            .get(TRACEPARENT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_traceparent);
+        let span_id = new_span_id();
+        match parent {
+            Some(parent) => Self {
+                request_id,
+                trace_id: parent.trace_id,
+                span_id,
+                parent_id: Some(parent.parent_id),
+                sampled: parent.sampled,
+                tracestate: headers
+                    .get(TRACESTATE_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+            },
+            None => Self {
+                request_id,
+                trace_id: Uuid::new_v4().simple().to_string(),
+                span_id,
+                parent_id: None,
+                sampled: true,
+                tracestate: None,
+            },
+        }
+    }
+
+    /// `traceparent` naming the proxy's span as the parent of upstream calls.
+    #[must_use]
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{}",
+            self.trace_id,
+            self.span_id,
+            if self.sampled { "01" } else { "00" }
+        )
+    }
+
+    /// Headers sent to upstreams: `traceparent`, `tracestate` and `X-Request-ID`.
+    #[must_use]
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let values = [
+            (TRACEPARENT_HEADER, Some(self.traceparent())),
+            (TRACESTATE_HEADER, self.tracestate.clone()),
+            (REQUEST_ID_HEADER, Some(self.request_id.clone())),
+        ];
+        for (name, value) in values {
+            if let Some(value) = value.and_then(|v| HeaderValue::from_str(&v).ok()) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        }
+        headers
+    }
+
+    /// Runs `future` with this context as the current one.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    /// The context of the request being handled, if any.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+}
+
+/// Headers identifying the current request to an upstream; empty outside a
+/// request (e.g. for background health probes).
+#[must_use]
+pub fn propagation_headers() -> HeaderMap {
+    TraceContext::current()
+        .map(|ctx| ctx.headers())
+        .unwrap_or_default()
+}
+
+struct ParentSpan {
+    trace_id: String,
+    parent_id: String,
+    sampled: bool,
+}
+
+fn is_lower_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Parses a `traceparent` header, rejecting it (and so starting a new trace)
+/// when it is malformed or uses an all-zero ID, per the W3C spec. Versions
+/// after `00` may append fields, which are ignored.
+fn parse_traceparent(value: &str) -> Option<ParentSpan> {
+    let mut parts = value.trim().splitn(5, '-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+    let extra = parts.next();
+    if !is_lower_hex(version, 2) || version == "ff" || (version == "00" && extra.is_some()) {
+        return None;
+    }
+    if !is_lower_hex(trace_id, 32) || trace_id.bytes().all(|b| b == b'0') {
+        return None;
+    }
+    if !is_lower_hex(parent_id, 16) || parent_id.bytes().all(|b| b == b'0') {
+        return None;
+    }
+    if !is_lower_hex(flags, 2) {
+        return None;
+    }
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    Some(ParentSpan {
+        trace_id: trace_id.to_string(),
+        parent_id: parent_id.to_string(),
+        sampled: flags & 0x01 == 0x01,
+    })
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+fn new_span_id() -> String {
+    let mut id = Uuid::new_v4().simple().to_string();
+    id.truncate(16);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).expect("valid value"));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_joins_incoming_trace() {
+        let ctx = TraceContext::from_headers(&headers(&[
+            (
+                TRACEPARENT_HEADER,
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ),
+            (TRACESTATE_HEADER, "vendor=abc"),
+            (REQUEST_ID_HEADER, "req-42"),
+        ]));
+        assert_eq!(ctx.request_id, "req-42");
+        assert_eq!(ctx.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(ctx.parent_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_ne!(ctx.span_id, "00f067aa0ba902b7");
+
+        let upstream = ctx.headers();
+        let traceparent = upstream[TRACEPARENT_HEADER].to_str().expect("ascii");
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(traceparent.ends_with("-01"));
+        assert_eq!(upstream[TRACESTATE_HEADER], "vendor=abc");
+        assert_eq!(upstream[REQUEST_ID_HEADER], "req-42");
+    }
+
+    #[test]
+    fn test_starts_new_trace_for_missing_or_invalid_parent() {
+        for bad in [
+            "",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            let ctx = TraceContext::from_headers(&headers(&[(TRACEPARENT_HEADER, bad)]));
+            assert!(ctx.parent_id.is_none(), "accepted {bad:?}");
+            assert!(is_lower_hex(&ctx.trace_id, 32));
+            assert!(is_lower_hex(&ctx.span_id, 16));
+        }
+        let future = TraceContext::from_headers(&headers(&[(
+            TRACEPARENT_HEADER,
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra",
+        )]));
+        assert_eq!(future.parent_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert!(!future.sampled);
+    }
+
+    #[test]
+    fn test_unusable_request_id_replaced() {
+        let long = "x".repeat(MAX_REQUEST_ID_LEN + 1);
+        for bad in ["", "has space", long.as_str()] {
+            let ctx = TraceContext::from_headers(&headers(&[(REQUEST_ID_HEADER, bad)]));
+            assert_ne!(ctx.request_id, bad);
+            assert!(Uuid::parse_str(&ctx.request_id).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_propagation_headers_follow_scope() {
+        assert!(propagation_headers().is_empty());
+        let ctx = TraceContext::from_headers(&HeaderMap::new());
+        let request_id = ctx.request_id.clone();
+        let inside = ctx.scope(async { propagation_headers() }).await;
+        assert_eq!(inside[REQUEST_ID_HEADER], request_id.as_str());
+    }
+}
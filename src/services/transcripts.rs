@@ -0,0 +1,389 @@
+// Server-side conversation history.
+//
+// Clients that want the proxy to keep their chat history send a session ID in
+// the session header. Each completion under that ID appends the request's new
+// messages and the assistant's reply to a transcript, which the client can
+// read back or delete through `/v1/conversations/{id}`. A client resending the
+// whole conversation each turn is recognised: messages the transcript already
+// starts with are not appended again.
+//
+// Transcripts are scoped to the calling API key and kept in the configured
+// storage, or in process memory without one. Two turns of one conversation
+// finishing at the same moment may drop one of them, as each rewrites the
+// whole transcript.
+
+use anyhow::Result;
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap};
+use axum::response::Response;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::config::TranscriptsConfig;
+use crate::models::openai::ChatMessage;
+use crate::services::post_processor;
+use crate::services::storage::{MemoryStorage, SharedStorage};
+
+/// Longest accepted conversation ID.
+pub const MAX_ID_LEN: usize = 255;
+
+// Storage prefix of transcripts
+const STORAGE_PREFIX: &str = "conversations:";
+
+// Replies larger than this are passed through but not recorded
+const MAX_RECORDED_BODY: usize = 8 * 1024 * 1024;
+
+/// A stored conversation.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Transcript {
+    pub id: String,
+    /// Always `conversation`.
+    pub object: String,
+    /// Unix time of the first recorded turn.
+    pub created: i64,
+    /// Unix time of the latest recorded turn.
+    pub updated: i64,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// Conversation transcripts keyed by API key and session ID.
+pub struct Transcripts {
+    enabled: bool,
+    ttl: Option<Duration>,
+    storage: SharedStorage,
+}
+
+impl Default for Transcripts {
+    fn default() -> Self {
+        Self::from_config(&TranscriptsConfig::default(), None)
+    }
+}
+
+impl Transcripts {
+    /// Keeps transcripts in `storage`, or in memory without one.
+    #[must_use]
+    pub fn from_config(config: &TranscriptsConfig, storage: Option<SharedStorage>) -> Self {
+        Self {
+            enabled: config.enabled,
+            ttl: (config.ttl_secs > 0).then(|| Duration::from_secs(config.ttl_secs)),
+            storage: storage.unwrap_or_else(|| Arc::new(MemoryStorage::new())),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The conversation ID in `headers` under `header_name`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the ID is not valid.
+    pub fn session_id(headers: &HeaderMap, header_name: &str) -> Result<Option<String>, String> {
+        let Some(value) = headers.get(header_name) else {
+            return Ok(None);
+        };
+        match value.to_str() {
+            Ok(id) if validate_id(id).is_ok() => Ok(Some(id.to_string())),
+            _ => Err(format!(
+                "{header_name} must be 1 to {MAX_ID_LEN} visible ASCII characters"
+            )),
+        }
+    }
+
+    /// The transcript `id` of the API key `key_name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if storage fails or holds a malformed transcript.
+    pub async fn get(&self, key_name: &str, id: &str) -> Result<Option<Transcript>> {
+        match self.storage.get(&storage_key(key_name, id)).await? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Deletes the transcript `id` of the API key `key_name`, returning
+    /// whether there was one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if storage fails.
+    pub async fn delete(&self, key_name: &str, id: &str) -> Result<bool> {
+        self.storage.delete(&storage_key(key_name, id)).await
+    }
+
+    /// Appends a turn to the transcript `id`: the request `messages` it does
+    /// not already start with, then `reply`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if storage fails.
+    pub async fn append(
+        &self,
+        key_name: &str,
+        id: &str,
+        messages: &[ChatMessage],
+        reply: ChatMessage,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let mut transcript = self.get(key_name, id).await?.unwrap_or_else(|| Transcript {
+            id: id.to_string(),
+            object: "conversation".to_string(),
+            created: now,
+            updated: now,
+            messages: Vec::new(),
+        });
+        let known = resent_prefix(&transcript.messages, messages);
+        transcript.messages.extend_from_slice(&messages[known..]);
+        transcript.messages.push(reply);
+        transcript.updated = now;
+        self.storage
+            .set(
+                &storage_key(key_name, id),
+                serde_json::to_string(&transcript)?,
+                self.ttl,
+            )
+            .await
+    }
+
+    /// Passes `response` through, appending the turn to the transcript `id`
+    /// once the reply has been sent. Failed, oversized and abandoned
+    /// responses are not recorded.
+    pub fn record(
+        self: &Arc<Self>,
+        key_name: &str,
+        id: &str,
+        messages: Vec<ChatMessage>,
+        response: Response,
+    ) -> Response {
+        if !response.status().is_success() {
+            return response;
+        }
+        let is_stream = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        let turn = Turn {
+            transcripts: Arc::clone(self),
+            key_name: key_name.to_string(),
+            id: id.to_string(),
+            messages,
+            is_stream,
+            body: Vec::new(),
+        };
+        let (parts, body) = response.into_parts();
+        let body = stream::unfold(
+            (body.into_data_stream(), Some(turn)),
+            |(mut data, mut turn)| async move {
+                let chunk = data.next().await;
+                match &chunk {
+                    Some(Ok(bytes)) => {
+                        if let Some(t) = turn.as_mut() {
+                            if t.body.len() + bytes.len() > MAX_RECORDED_BODY {
+                                turn = None;
+                            } else {
+                                t.body.extend_from_slice(bytes);
+                            }
+                        }
+                    }
+                    Some(Err(_)) => turn = None,
+                    None => {
+                        if let Some(turn) = turn.take() {
+                            turn.finish().await;
+                        }
+                    }
+                }
+                chunk.map(|chunk| (chunk, (data, turn)))
+            },
+        );
+        Response::from_parts(parts, Body::from_stream(body))
+    }
+}
+
+/// A turn whose reply is being collected.
+struct Turn {
+    transcripts: Arc<Transcripts>,
+    key_name: String,
+    id: String,
+    messages: Vec<ChatMessage>,
+    is_stream: bool,
+    body: Vec<u8>,
+}
+
+impl Turn {
+    async fn finish(self) {
+        let Some(reply) = reply_message(&Bytes::from(self.body), self.is_stream) else {
+            return;
+        };
+        if let Err(e) = self
+            .transcripts
+            .append(&self.key_name, &self.id, &self.messages, reply)
+            .await
+        {
+            warn!("Failed to record conversation {}: {e:#}", self.id);
+        }
+    }
+}
+
+/// The assistant message of a JSON completion or of a stream's SSE body.
+fn reply_message(body: &Bytes, is_stream: bool) -> Option<ChatMessage> {
+    let completion = if is_stream {
+        let text = String::from_utf8_lossy(body).into_owned();
+        post_processor::reassemble_stream(&[text])
+    } else {
+        serde_json::from_slice::<Value>(body).ok()?
+    };
+    serde_json::from_value(completion["choices"][0]["message"].clone()).ok()
+}
+
+/// How many of `messages` repeat the start of `stored`: all of `stored` when
+/// the client resent the conversation so far, otherwise none.
+fn resent_prefix(stored: &[ChatMessage], messages: &[ChatMessage]) -> usize {
+    let same = |a: &ChatMessage, b: &ChatMessage| {
+        serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+    };
+    let resent = !stored.is_empty()
+        && messages.len() >= stored.len()
+        && stored.iter().zip(messages).all(|(a, b)| same(a, b));
+    if resent {
+        stored.len()
+    } else {
+        0
+    }
+}
+
+/// Checks that `id` can name a conversation.
+///
+/// # Errors
+///
+/// Returns a description of the problem.
+pub fn validate_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_ID_LEN || !id.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(format!(
+            "Conversation IDs are 1 to {MAX_ID_LEN} visible ASCII characters"
+        ));
+    }
+    Ok(())
+}
+
+// Length-prefixed so no key name can run into another's conversation ID
+fn storage_key(key_name: &str, id: &str) -> String {
+    format!("{STORAGE_PREFIX}{}:{key_name}|{id}", key_name.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::openai::Role;
+    use axum::body::to_bytes;
+
+    fn message(role: Role, content: &str) -> ChatMessage {
+        serde_json::from_value(serde_json::json!({
+            "role": role,
+            "content": content,
+        }))
+        .expect("valid message")
+    }
+
+    fn enabled() -> Arc<Transcripts> {
+        Arc::new(Transcripts::from_config(
+            &TranscriptsConfig {
+                enabled: true,
+                ttl_secs: 0,
+            },
+            None,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_append_skips_resent_history() {
+        let transcripts = enabled();
+        let hi = message(Role::User, "hi");
+        let hello = message(Role::Assistant, "hello");
+        let bye = message(Role::User, "bye");
+        transcripts
+            .append("k", "c1", std::slice::from_ref(&hi), hello.clone())
+            .await
+            .expect("append should succeed");
+        // Full history resent
+        transcripts
+            .append(
+                "k",
+                "c1",
+                &[hi, hello, bye.clone()],
+                message(Role::Assistant, "ciao"),
+            )
+            .await
+            .expect("append should succeed");
+        // Only the new message sent
+        transcripts
+            .append("k", "c1", &[bye], message(Role::Assistant, "ciao again"))
+            .await
+            .expect("append should succeed");
+
+        let transcript = transcripts
+            .get("k", "c1")
+            .await
+            .expect("get should succeed")
+            .expect("transcript should exist");
+        let contents: Vec<_> = transcript
+            .messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            ["hi", "hello", "bye", "ciao", "bye", "ciao again"]
+        );
+
+        // Scoped to the key
+        assert!(transcripts.get("other", "c1").await.expect("get").is_none());
+        assert!(transcripts.delete("k", "c1").await.expect("delete"));
+        assert!(transcripts.get("k", "c1").await.expect("get").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_record_captures_streamed_reply() {
+        let transcripts = enabled();
+        let sse = "data: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"finish_reason\":null}]}\n\ndata: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":1,\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo\"},\"finish_reason\":\"stop\"}]}\n\ndata: [DONE]\n\n";
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from(sse))
+            .expect("valid response");
+        let response = transcripts.record("k", "c2", vec![message(Role::User, "hi")], response);
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should read");
+        assert_eq!(body, sse);
+
+        let transcript = transcripts
+            .get("k", "c2")
+            .await
+            .expect("get should succeed")
+            .expect("transcript should exist");
+        assert_eq!(transcript.messages.len(), 2);
+        assert_eq!(transcript.messages[1].content, "Hello");
+        assert_eq!(transcript.messages[1].role, Role::Assistant);
+    }
+
+    #[test]
+    fn test_session_id_validation() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Transcripts::session_id(&headers, "x-session-id"), Ok(None));
+        headers.insert("x-session-id", "abc-1".parse().expect("valid header"));
+        assert_eq!(
+            Transcripts::session_id(&headers, "x-session-id"),
+            Ok(Some("abc-1".to_string()))
+        );
+        headers.insert("x-session-id", "a b".parse().expect("valid header"));
+        assert!(Transcripts::session_id(&headers, "x-session-id").is_err());
+    }
+}
@@ -1,19 +1,28 @@
+use crate::config::VertexGenerationConfig;
 use crate::models::{
     openai::{
-        ChatCompletionChoice, ChatCompletionRequest, ChatCompletionResponse, ChatMessage, Role,
-        Usage,
+        ChatCompletionChoice, ChatCompletionRequest, ChatCompletionResponse, ChatMessage,
+        CompletionTokensDetails, PromptTokensDetails, Role, Usage,
+    },
+    vertex::{
+        Content, GenerateContentRequest, GenerateContentResponse, GenerationConfig, Part,
+        UsageMetadata,
     },
-    vertex::{Content, GenerateContentRequest, GenerateContentResponse, GenerationConfig, Part},
 };
+use crate::services::finish_reason;
 use anyhow::Result;
 use tracing::warn;
 
-/// Transforms an OpenAI-style chat completion request into a Vertex request.
+/// Transforms an OpenAI-style chat completion request into a Vertex request,
+/// filling parameters the client omitted from `defaults`.
 ///
 /// # Errors
 ///
 /// Returns an error if the input request cannot be converted to the Vertex format.
-pub fn transform_request(req: ChatCompletionRequest) -> Result<GenerateContentRequest> {
+pub fn transform_request(
+    req: ChatCompletionRequest,
+    defaults: &VertexGenerationConfig,
+) -> Result<GenerateContentRequest> {
     // Collect all system messages and concatenate them
     let system_messages: Vec<String> = req
         .messages
@@ -64,11 +73,12 @@ pub fn transform_request(req: ChatCompletionRequest) -> Result<GenerateContentRe
             parts: vec![Part { text: Some(text) }],
         }),
         generation_config: Some(GenerationConfig {
-            temperature: Some(req.temperature),
-            top_p: Some(req.top_p),
-            max_output_tokens: req.max_tokens,
+            temperature: req.temperature.or(defaults.temperature),
+            top_p: req.top_p.or(defaults.top_p),
+            top_k: req.top_k.or(defaults.top_k),
+            max_output_tokens: req.max_tokens.or(defaults.max_output_tokens),
             stop_sequences: req.stop,
-            candidate_count: None,
+            candidate_count: defaults.candidate_count,
         }),
         safety_settings: None,
     };
@@ -95,32 +105,15 @@ pub fn transform_response(
     let content = candidate
         .content
         .as_ref()
-        .and_then(|c| c.parts.first())
-        .and_then(|p| p.text.as_ref())
-        .ok_or_else(|| anyhow::anyhow!("No content in Vertex response"))?
-        .clone();
+        .and_then(text_of)
+        .ok_or_else(|| anyhow::anyhow!("No content in Vertex response"))?;
 
-    let finish_reason = candidate.finish_reason.as_ref().map(|s| s.to_lowercase());
+    let finish_reason = candidate
+        .finish_reason
+        .as_deref()
+        .map(|r| finish_reason::from_vertex(r).to_string());
 
-    // Fix error swallowing: Log detailed error information instead of silently continuing
-    let usage = vertex_res.usage_metadata.as_ref().and_then(|u| {
-        if u.prompt_token_count.is_none()
-            || u.candidates_token_count.is_none()
-            || u.total_token_count.is_none()
-        {
-            warn!(
-                "Vertex response missing token counts (prompt: {:?}, candidates: {:?}, total: {:?}) - returning None. This may indicate API contract violation.",
-                u.prompt_token_count, u.candidates_token_count, u.total_token_count
-            );
-            None
-        } else {
-            Some(Usage {
-                prompt_tokens: u.prompt_token_count.unwrap_or(0),
-                completion_tokens: u.candidates_token_count.unwrap_or(0),
-                total_tokens: u.total_token_count.unwrap_or(0),
-            })
-        }
-    });
+    let usage = vertex_res.usage_metadata.as_ref().and_then(transform_usage);
 
     let created = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -138,6 +131,9 @@ pub fn transform_response(
                 role: Role::Assistant,
                 content,
                 name: None,
+                images: 0,
+                tool_calls: Vec::new(),
+                tool_call_id: None,
             },
             finish_reason,
         }],
@@ -145,8 +141,58 @@ pub fn transform_response(
     })
 }
 
+/// The text of all of a candidate's parts, in order; `None` if none has text.
+fn text_of(content: &Content) -> Option<String> {
+    let mut texts = content
+        .parts
+        .iter()
+        .filter_map(|p| p.text.as_deref())
+        .peekable();
+    texts.peek()?;
+    Some(texts.collect())
+}
+
+/// Converts Vertex token counts to OpenAI usage; `None` unless all counts are present.
+///
+/// Thinking tokens count as completion tokens, as OpenAI counts reasoning
+/// tokens, so the counts add up to Vertex's total.
+#[must_use]
+pub fn transform_usage(u: &UsageMetadata) -> Option<Usage> {
+    // Fix error swallowing: Log detailed error information instead of silently continuing
+    if u.prompt_token_count.is_none()
+        || u.candidates_token_count.is_none()
+        || u.total_token_count.is_none()
+    {
+        warn!(
+            "Vertex response missing token counts (prompt: {:?}, candidates: {:?}, total: {:?}) - returning None. This may indicate API contract violation.",
+            u.prompt_token_count, u.candidates_token_count, u.total_token_count
+        );
+        None
+    } else {
+        let thoughts = u.thoughts_token_count.unwrap_or(0);
+        Some(Usage {
+            prompt_tokens: u.prompt_token_count.unwrap_or(0),
+            completion_tokens: u
+                .candidates_token_count
+                .unwrap_or(0)
+                .saturating_add(thoughts),
+            total_tokens: u.total_token_count.unwrap_or(0),
+            prompt_tokens_details: u
+                .cached_content_token_count
+                .map(|cached_tokens| PromptTokensDetails { cached_tokens }),
+            completion_tokens_details: u
+                .thoughts_token_count
+                .map(|reasoning_tokens| CompletionTokensDetails { reasoning_tokens }),
+            estimated: false,
+        })
+    }
+}
+
 /// Transforms a streaming Vertex response chunk into an OpenAI-compatible streaming chunk.
 ///
+/// Usage is attached only to the terminal frame (the one carrying a finish
+/// reason), where Vertex reports the final token counts.
+///
 /// # Errors
 ///
 /// Returns an error if the Vertex response does not include required fields.
@@ -161,14 +207,18 @@ pub fn transform_stream_chunk(
         .and_then(|c| c.first())
         .ok_or_else(|| anyhow::anyhow!("No candidates in Vertex response"))?;
 
-    let content = candidate
-        .content
-        .as_ref()
-        .and_then(|c| c.parts.first())
-        .and_then(|p| p.text.as_ref())
-        .cloned();
+    let content = candidate.content.as_ref().and_then(text_of);
 
-    let finish_reason = candidate.finish_reason.as_ref().map(|s| s.to_lowercase());
+    let finish_reason = candidate
+        .finish_reason
+        .as_deref()
+        .map(|r| finish_reason::from_vertex(r).to_string());
+
+    // Earlier frames carry running counts; only the terminal one is final
+    let usage = finish_reason
+        .as_ref()
+        .and(vertex_res.usage_metadata.as_ref())
+        .and_then(transform_usage);
 
     let created = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -185,9 +235,11 @@ pub fn transform_stream_chunk(
             delta: crate::models::openai::DeltaMessage {
                 role: None,
                 content,
+                tool_calls: None,
             },
             finish_reason,
         }],
+        usage,
     })
 }
 
@@ -196,6 +248,7 @@ mod tests {
     use super::*;
     use crate::models::openai::{ChatMessage, Role};
     use crate::models::vertex::{Candidate, UsageMetadata};
+    use proptest::prelude::*;
 
     #[test]
     fn test_transform_request_basic() {
@@ -206,22 +259,37 @@ mod tests {
                     role: Role::User,
                     content: "Hello".to_string(),
                     name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
                 },
                 ChatMessage {
                     role: Role::Assistant,
                     content: "Hi there".to_string(),
                     name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
                 },
             ],
             stream: false,
-            temperature: 0.7,
-            top_p: 0.9,
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            top_k: None,
             max_tokens: Some(100),
             stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
         };
 
-        let vertex_req =
-            transform_request(req).expect("transform_request should succeed for valid input");
+        let vertex_req = transform_request(req, &VertexGenerationConfig::default())
+            .expect("transform_request should succeed for valid input");
         assert_eq!(vertex_req.contents.len(), 2);
         assert_eq!(vertex_req.contents[0].role, "user");
         assert_eq!(vertex_req.contents[1].role, "model");
@@ -252,27 +320,81 @@ mod tests {
                     role: Role::System,
                     content: "You are a helpful assistant".to_string(),
                     name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
                 },
                 ChatMessage {
                     role: Role::User,
                     content: "Hello".to_string(),
                     name: None,
+                    images: 0,
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
                 },
             ],
             stream: false,
-            temperature: 1.0,
-            top_p: 1.0,
+            temperature: Some(1.0),
+            top_p: Some(1.0),
+            top_k: None,
             max_tokens: None,
             stop: None,
+            user: None,
+            prompt_template: None,
+            variables: Default::default(),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream_options: None,
+            prompts: Vec::new(),
+            extra: Default::default(),
         };
 
-        let vertex_req =
-            transform_request(req).expect("transform_request should succeed with system message");
+        let vertex_req = transform_request(req, &VertexGenerationConfig::default())
+            .expect("transform_request should succeed with system message");
         assert!(vertex_req.system_instruction.is_some());
         assert_eq!(vertex_req.contents.len(), 1);
         assert_eq!(vertex_req.contents[0].role, "user");
     }
 
+    #[test]
+    fn test_transform_request_applies_generation_defaults() {
+        let defaults = VertexGenerationConfig {
+            temperature: Some(0.4),
+            top_p: Some(0.95),
+            top_k: Some(40),
+            max_output_tokens: Some(2048),
+            candidate_count: Some(1),
+        };
+        let mut req: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "messages": [{"role": "user", "content": "Hello"}],
+            "top_p": 0.5
+        }))
+        .expect("valid chat request");
+
+        let config = transform_request(req.clone(), &defaults)
+            .expect("transform_request should succeed")
+            .generation_config
+            .expect("generation_config should exist");
+        assert_eq!(config.temperature, Some(0.4));
+        assert_eq!(config.top_p, Some(0.5));
+        assert_eq!(config.top_k, Some(40));
+        assert_eq!(config.max_output_tokens, Some(2048));
+        assert_eq!(config.candidate_count, Some(1));
+
+        // Without configured defaults omitted values are left to Gemini
+        req.top_p = None;
+        req.top_k = Some(8);
+        let config = transform_request(req, &VertexGenerationConfig::default())
+            .expect("transform_request should succeed")
+            .generation_config
+            .expect("generation_config should exist");
+        assert_eq!(config.temperature, None);
+        assert_eq!(config.top_p, None);
+        assert_eq!(config.top_k, Some(8));
+        assert_eq!(config.max_output_tokens, None);
+    }
+
     #[test]
     fn test_transform_response() {
         let vertex_res = GenerateContentResponse {
@@ -290,6 +412,7 @@ mod tests {
                 prompt_token_count: Some(10),
                 candidates_token_count: Some(5),
                 total_token_count: Some(15),
+                ..Default::default()
             }),
         };
 
@@ -311,6 +434,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_transform_usage_reports_cached_and_thinking_tokens() {
+        let usage = transform_usage(&UsageMetadata {
+            prompt_token_count: Some(100),
+            candidates_token_count: Some(20),
+            total_token_count: Some(150),
+            cached_content_token_count: Some(60),
+            thoughts_token_count: Some(30),
+        })
+        .expect("all counts are present");
+        assert_eq!(usage.prompt_tokens, 100);
+        assert_eq!(usage.completion_tokens, 50);
+        assert_eq!(usage.total_tokens, 150);
+        assert_eq!(
+            usage.prompt_tokens_details,
+            Some(PromptTokensDetails { cached_tokens: 60 })
+        );
+        assert_eq!(
+            usage.completion_tokens_details,
+            Some(CompletionTokensDetails {
+                reasoning_tokens: 30
+            })
+        );
+
+        let json = serde_json::to_value(transform_usage(&UsageMetadata {
+            prompt_token_count: Some(10),
+            candidates_token_count: Some(5),
+            total_token_count: Some(15),
+            ..Default::default()
+        }))
+        .expect("usage serializes");
+        assert!(json.get("prompt_tokens_details").is_none());
+        assert!(json.get("completion_tokens_details").is_none());
+    }
     #[test]
     fn test_transform_response_no_candidates() {
         let vertex_res = GenerateContentResponse {
@@ -322,4 +479,161 @@ mod tests {
             transform_response(&vertex_res, "gemini-pro".to_string(), "test-id".to_string());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_transform_stream_chunk_usage_on_terminal_frame() {
+        let frame =
+            |text: &str, finish_reason: Option<&str>, completion: u32| GenerateContentResponse {
+                candidates: Some(vec![Candidate {
+                    content: Some(Content {
+                        role: "model".to_string(),
+                        parts: vec![Part {
+                            text: Some(text.to_string()),
+                        }],
+                    }),
+                    finish_reason: finish_reason.map(str::to_string),
+                    index: Some(0),
+                }]),
+                usage_metadata: Some(UsageMetadata {
+                    prompt_token_count: Some(10),
+                    candidates_token_count: Some(completion),
+                    total_token_count: Some(10 + completion),
+                    ..Default::default()
+                }),
+            };
+
+        let first = transform_stream_chunk(
+            &frame("Hel", None, 1),
+            "gemini-pro".to_string(),
+            "test-id".to_string(),
+        )
+        .expect("first frame should transform");
+        assert!(first.usage.is_none());
+
+        let last = transform_stream_chunk(
+            &frame("lo", Some("STOP"), 2),
+            "gemini-pro".to_string(),
+            "test-id".to_string(),
+        )
+        .expect("last frame should transform");
+        let usage = last.usage.expect("terminal frame should carry usage");
+        assert_eq!(usage.prompt_tokens, 10);
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.total_tokens, 12);
+    }
+
+    fn arb_role() -> impl Strategy<Value = Role> {
+        prop_oneof![
+            Just(Role::System),
+            Just(Role::User),
+            Just(Role::Assistant),
+            Just(Role::Tool),
+        ]
+    }
+
+    fn vertex_response(parts: &[Option<String>]) -> GenerateContentResponse {
+        GenerateContentResponse {
+            candidates: Some(vec![Candidate {
+                content: Some(Content {
+                    role: "model".to_string(),
+                    parts: parts
+                        .iter()
+                        .map(|text| Part { text: text.clone() })
+                        .collect(),
+                }),
+                finish_reason: Some("STOP".to_string()),
+                index: Some(0),
+            }]),
+            usage_metadata: None,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_transform_request_keeps_every_message(
+            messages in proptest::collection::vec((arb_role(), "\\PC{0,40}"), 0..12)
+        ) {
+            let req = ChatCompletionRequest {
+                model: "gemini-pro".to_string(),
+                messages: messages
+                    .iter()
+                    .map(|(role, content)| ChatMessage {
+                        role: role.clone(),
+                        content: content.clone(),
+                        name: None,
+                        images: 0,
+                        tool_calls: Vec::new(),
+                        tool_call_id: None,
+                    })
+                    .collect(),
+                stream: false,
+                temperature: None,
+                top_p: None,
+                top_k: None,
+                max_tokens: None,
+                stop: None,
+                user: None,
+                prompt_template: None,
+                variables: Default::default(),
+                tools: Vec::new(),
+                tool_choice: None,
+                stream_options: None,
+                prompts: Vec::new(),
+                extra: Default::default(),
+            };
+            let vertex_req = transform_request(req, &VertexGenerationConfig::default())
+                .expect("every request shape should transform");
+
+            let system: Vec<&str> = messages
+                .iter()
+                .filter(|(role, _)| matches!(role, Role::System))
+                .map(|(_, content)| content.as_str())
+                .collect();
+            let instruction = vertex_req
+                .system_instruction
+                .as_ref()
+                .and_then(|c| c.parts[0].text.clone());
+            prop_assert_eq!(
+                instruction,
+                (!system.is_empty()).then(|| system.join("\n\n"))
+            );
+
+            let turns: Vec<(&str, &str)> = messages
+                .iter()
+                .filter(|(role, _)| !matches!(role, Role::System))
+                .map(|(role, content)| {
+                    let role = if matches!(role, Role::Assistant) { "model" } else { "user" };
+                    (role, content.as_str())
+                })
+                .collect();
+            let contents: Vec<(&str, &str)> = vertex_req
+                .contents
+                .iter()
+                .map(|c| (c.role.as_str(), c.parts[0].text.as_deref().unwrap_or_default()))
+                .collect();
+            prop_assert_eq!(contents, turns);
+        }
+
+        #[test]
+        fn prop_transform_response_keeps_all_text(
+            parts in proptest::collection::vec(proptest::option::of("\\PC{0,40}"), 1..5)
+        ) {
+            let vertex_res = vertex_response(&parts);
+            let text: Vec<&str> = parts.iter().filter_map(Option::as_deref).collect();
+
+            let response =
+                transform_response(&vertex_res, "gemini-pro".to_string(), "id".to_string());
+            let chunk =
+                transform_stream_chunk(&vertex_res, "gemini-pro".to_string(), "id".to_string())
+                    .expect("a candidate is present");
+            if text.is_empty() {
+                prop_assert!(response.is_err());
+                prop_assert_eq!(chunk.choices[0].delta.content.clone(), None);
+            } else {
+                let response = response.expect("text parts are present");
+                prop_assert_eq!(&response.choices[0].message.content, &text.concat());
+                prop_assert_eq!(chunk.choices[0].delta.content.clone(), Some(text.concat()));
+            }
+        }
+    }
 }
@@ -0,0 +1,104 @@
+// Per-provider HTTP client settings for upstream calls.
+//
+// Upstreams differ in what they accept from a client: the ChatGPT backend
+// rejects requests that do not look like a browser, while gateways in front of
+// Vertex or the Anthropic bridge may pin a user agent or TLS version. Each
+// provider therefore gets its own user agent, HTTP version and TLS version
+// range. Clients with a TLS version range use rustls, since the platform TLS
+// library cannot bound TLS 1.3; the others keep the default backend. Beyond
+// that choice the TLS handshake is not customized.
+
+use reqwest::{tls, ClientBuilder};
+use serde::Deserialize;
+
+use crate::config::ClientProfileConfig;
+
+/// A TLS protocol version, as named in configuration.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    #[serde(rename = "tls1.2")]
+    Tls12,
+    #[serde(rename = "tls1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    fn to_reqwest(self) -> tls::Version {
+        match self {
+            Self::Tls12 => tls::Version::TLS_1_2,
+            Self::Tls13 => tls::Version::TLS_1_3,
+        }
+    }
+}
+
+/// Applies `profile` to `builder`; `default_user_agent` is used when the
+/// profile does not set one.
+pub fn apply(
+    builder: ClientBuilder,
+    profile: &ClientProfileConfig,
+    default_user_agent: Option<&str>,
+) -> ClientBuilder {
+    let mut builder = builder;
+    if let Some(user_agent) = profile.user_agent.as_deref().or(default_user_agent) {
+        builder = builder.user_agent(user_agent);
+    }
+    if profile.http1_only {
+        builder = builder.http1_only();
+    }
+    if profile.min_tls_version.is_some() || profile.max_tls_version.is_some() {
+        builder = builder.use_rustls_tls();
+    }
+    if let Some(version) = profile.min_tls_version {
+        builder = builder.min_tls_version(version.to_reqwest());
+    }
+    if let Some(version) = profile.max_tls_version {
+        builder = builder.max_tls_version(version.to_reqwest());
+    }
+    builder
+}
+
+/// Checks a profile at startup.
+///
+/// # Errors
+///
+/// Returns a description of the problem if the user agent is not a valid
+/// header value or the TLS version range is empty.
+pub fn validate(profile: &ClientProfileConfig) -> Result<(), String> {
+    if let Some(user_agent) = &profile.user_agent {
+        reqwest::header::HeaderValue::from_str(user_agent)
+            .map_err(|e| format!("invalid user_agent: {e}"))?;
+    }
+    if let (Some(min), Some(max)) = (profile.min_tls_version, profile.max_tls_version) {
+        if min > max {
+            return Err("min_tls_version is newer than max_tls_version".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_validation() {
+        let mut profile = ClientProfileConfig {
+            user_agent: Some("gateway-client/1.0".to_string()),
+            min_tls_version: Some(TlsVersion::Tls12),
+            max_tls_version: Some(TlsVersion::Tls13),
+            ..Default::default()
+        };
+        assert!(validate(&profile).is_ok());
+        assert!(apply(reqwest::Client::builder(), &profile, None)
+            .build()
+            .is_ok());
+
+        profile.min_tls_version = Some(TlsVersion::Tls13);
+        profile.max_tls_version = Some(TlsVersion::Tls12);
+        assert!(validate(&profile).is_err());
+
+        profile.max_tls_version = None;
+        profile.user_agent = Some("bad\nagent".to_string());
+        assert!(validate(&profile).is_err());
+    }
+}
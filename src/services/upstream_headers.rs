@@ -0,0 +1,264 @@
+// Config-defined headers attached to upstream provider requests.
+//
+// Each spec is `Name: value`. Values may contain `{project_id}`, replaced with
+// the resolved Vertex project (for `x-goog-user-project` quota billing), and
+// `${VAR}`, replaced with an environment variable (for gateway credentials that
+// should not live in config files). Specs are checked when configuration loads,
+// so rendering at request time only fails if the environment changed since.
+//
+// In the other direction, response headers named in `upstream_headers.forward`
+// (such as Vertex quota headers or the bridge's request ID) are passed on to
+// the client as `x-upstream-<name>`. Like `debug_trace`, the allowlist and the
+// captured headers live in a task-local, so `send_metered` records every
+// upstream response without the providers threading headers back; the last
+// response of the request wins.
+
+use axum::response::Response;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::env;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Prefix of the client-facing names of forwarded headers.
+pub const FORWARDED_PREFIX: &str = "x-upstream-";
+
+tokio::task_local! {
+    static FORWARDED: Arc<Mutex<Forwarded>>;
+}
+
+/// The allowlist of a request and the headers captured for it.
+#[derive(Debug, Default)]
+struct Forwarded {
+    allowlist: Vec<String>,
+    headers: HeaderMap,
+}
+
+/// Values available to header templates.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TemplateVars<'a> {
+    pub project_id: Option<&'a str>,
+}
+
+fn split_spec(spec: &str) -> Result<(HeaderName, &str), String> {
+    let (name, value) = spec
+        .split_once(':')
+        .ok_or_else(|| format!("header '{spec}' must be in 'Name: value' form"))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|e| format!("invalid header name in '{spec}': {e}"))?;
+    Ok((name, value.trim()))
+}
+
+fn render_value(template: &str, vars: TemplateVars<'_>) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let env_ref = start > 0 && rest.as_bytes()[start - 1] == b'$';
+        out.push_str(&rest[..if env_ref { start - 1 } else { start }]);
+        let end = rest[start..]
+            .find('}')
+            .map(|i| start + i)
+            .ok_or_else(|| format!("unterminated placeholder in '{template}'"))?;
+        let key = &rest[start + 1..end];
+        if env_ref {
+            let value =
+                env::var(key).map_err(|_| format!("environment variable '{key}' is not set"))?;
+            out.push_str(&value);
+        } else if key == "project_id" {
+            out.push_str(
+                vars.project_id
+                    .ok_or("'{project_id}' is used but no Vertex project is configured")?,
+            );
+        } else {
+            return Err(format!("unknown placeholder '{{{key}}}' in '{template}'"));
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Renders header specs into a header map.
+///
+/// # Errors
+///
+/// Returns a description of the first spec that is malformed, references an
+/// unknown placeholder or unset environment variable, or renders to an invalid
+/// header value.
+pub fn render(specs: &[String], vars: TemplateVars<'_>) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for spec in specs {
+        let (name, template) = split_spec(spec)?;
+        let value = render_value(template, vars)?;
+        let value = HeaderValue::from_str(&value)
+            .map_err(|e| format!("invalid value for header '{name}': {e}"))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// Checks specs at startup. `{project_id}` is accepted without a value since
+/// the project may only be known once credentials are loaded.
+///
+/// # Errors
+///
+/// Returns a description of the first invalid spec.
+pub fn validate(specs: &[String]) -> Result<(), String> {
+    render(
+        specs,
+        TemplateVars {
+            project_id: Some("project"),
+        },
+    )
+    .map(|_| ())
+}
+
+/// Whether `name` matches an allowlist entry: a header name, or a prefix
+/// ending in `*`, compared case-insensitively.
+fn allowed(allowlist: &[String], name: &HeaderName) -> bool {
+    allowlist.iter().any(|entry| {
+        let entry = entry.to_ascii_lowercase();
+        match entry.strip_suffix('*') {
+            Some(prefix) => name.as_str().starts_with(prefix),
+            None => name.as_str() == entry,
+        }
+    })
+}
+
+/// Checks forward allowlist entries at startup.
+///
+/// # Errors
+///
+/// Returns a description of the first entry that is not a valid header name.
+pub fn validate_forward(allowlist: &[String]) -> Result<(), String> {
+    for entry in allowlist {
+        // A bare `*` forwards every header
+        if entry == "*" {
+            continue;
+        }
+        let name = entry.strip_suffix('*').unwrap_or(entry);
+        HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("invalid header name '{entry}': {e}"))?;
+    }
+    Ok(())
+}
+
+/// Captures the allowlisted headers of an upstream response, replacing those
+/// of an earlier attempt. Outside [`forward`] it does nothing.
+pub fn record_response(headers: &HeaderMap) {
+    let _ = FORWARDED.try_with(|forwarded| {
+        let mut forwarded = forwarded
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let captured = headers
+            .iter()
+            .filter(|(name, _)| allowed(&forwarded.allowlist, name))
+            .filter_map(|(name, value)| {
+                let name =
+                    HeaderName::from_bytes(format!("{FORWARDED_PREFIX}{name}").as_bytes()).ok()?;
+                Some((name, value.clone()))
+            });
+        let mut map = HeaderMap::new();
+        for (name, value) in captured {
+            map.append(name, value);
+        }
+        forwarded.headers = map;
+    });
+}
+
+/// Runs `completion`, then adds the allowlisted headers of the last upstream
+/// response it made to its response.
+pub async fn forward<F>(allowlist: Vec<String>, completion: F) -> Response
+where
+    F: Future<Output = Response>,
+{
+    if allowlist.is_empty() {
+        return completion.await;
+    }
+    let forwarded = Arc::new(Mutex::new(Forwarded {
+        allowlist,
+        headers: HeaderMap::new(),
+    }));
+    let mut response = FORWARDED.scope(forwarded.clone(), completion).await;
+    let headers = std::mem::take(
+        &mut forwarded
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .headers,
+    );
+    for (name, value) in &headers {
+        response.headers_mut().append(name, value.clone());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_and_project_headers() {
+        let specs = vec![
+            "x-goog-user-project: {project_id}".to_string(),
+            "X-Team: platform".to_string(),
+        ];
+        let headers = render(
+            &specs,
+            TemplateVars {
+                project_id: Some("billing-proj"),
+            },
+        )
+        .expect("specs should render");
+        assert_eq!(headers["x-goog-user-project"], "billing-proj");
+        assert_eq!(headers["x-team"], "platform");
+    }
+
+    #[test]
+    fn test_env_placeholder() {
+        temp_env::with_var("UPSTREAM_HEADERS_TEST_TOKEN", Some("s3cret"), || {
+            let specs = vec!["Authorization: Bearer ${UPSTREAM_HEADERS_TEST_TOKEN}".to_string()];
+            let headers = render(&specs, TemplateVars::default()).expect("specs should render");
+            assert_eq!(headers["authorization"], "Bearer s3cret");
+        });
+        temp_env::with_var_unset("UPSTREAM_HEADERS_TEST_TOKEN", || {
+            let specs = vec!["Authorization: Bearer ${UPSTREAM_HEADERS_TEST_TOKEN}".to_string()];
+            assert!(validate(&specs).is_err());
+        });
+    }
+
+    #[test]
+    fn test_invalid_specs() {
+        assert!(validate(&["no-colon".to_string()]).is_err());
+        assert!(validate(&["bad name: x".to_string()]).is_err());
+        assert!(validate(&["X-A: {region}".to_string()]).is_err());
+        assert!(validate(&["X-A: {project_id".to_string()]).is_err());
+        assert!(render(&["X-A: {project_id}".to_string()], TemplateVars::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forward_allowlisted_response_headers() {
+        let allowlist = vec!["Request-Id".to_string(), "x-ratelimit-*".to_string()];
+        assert!(validate_forward(&allowlist).is_ok());
+        assert!(validate_forward(&["*".to_string()]).is_ok());
+        assert!(validate_forward(&["bad name".to_string()]).is_err());
+
+        let response = forward(allowlist, async {
+            let mut first = HeaderMap::new();
+            first.insert("request-id", HeaderValue::from_static("req_stale"));
+            record_response(&first);
+
+            let mut upstream = HeaderMap::new();
+            upstream.insert("request-id", HeaderValue::from_static("req_123"));
+            upstream.insert("x-ratelimit-remaining", HeaderValue::from_static("99"));
+            upstream.insert("set-cookie", HeaderValue::from_static("session=1"));
+            record_response(&upstream);
+            Response::new(axum::body::Body::empty())
+        })
+        .await;
+
+        let headers = response.headers();
+        assert_eq!(headers["x-upstream-request-id"], "req_123");
+        assert_eq!(headers["x-upstream-x-ratelimit-remaining"], "99");
+        assert!(!headers.contains_key("x-upstream-set-cookie"));
+        assert!(!headers.contains_key("set-cookie"));
+    }
+}
@@ -0,0 +1,155 @@
+// Startup warm-up of the credentials and connections the first requests need.
+//
+// A cold proxy fetches the Vertex access token (a `gcloud` call for service
+// accounts), harvester tokens and the Anthropic bridge's health on first use,
+// which can add seconds to the first requests. When enabled, the server runs
+// all three in parallel right after it starts, each bounded by
+// `warmup.timeout_secs`, logs the outcome and reports it on `/health/ready`.
+// A failed step does not keep the proxy from serving; the request that needs
+// it retries as it would have without warm-up.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::config::WarmupConfig;
+use crate::openai::harvester::HarvesterClient;
+use crate::services::providers::Provider;
+use crate::state::AppState;
+
+/// Outcome of one warm-up step.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WarmupStep {
+    pub name: String,
+    pub ok: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of a completed warm-up.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WarmupReport {
+    pub completed_at: DateTime<Utc>,
+    pub steps: Vec<WarmupStep>,
+}
+
+/// Whether warm-up is configured and, once it has run, its report.
+#[derive(Debug, Default)]
+pub struct Warmup {
+    enabled: bool,
+    timeout: Duration,
+    report: RwLock<Option<WarmupReport>>,
+}
+
+impl Warmup {
+    #[must_use]
+    pub fn from_config(config: &WarmupConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            timeout: Duration::from_secs(config.timeout_secs),
+            report: RwLock::new(None),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether the proxy is ready to take traffic: warm-up is disabled or
+    /// has finished, whatever its outcome.
+    pub async fn is_ready(&self) -> bool {
+        !self.enabled || self.report.read().await.is_some()
+    }
+
+    pub async fn report(&self) -> Option<WarmupReport> {
+        self.report.read().await.clone()
+    }
+
+    /// Runs every step in parallel, logs and records the outcome.
+    pub async fn run(&self, state: &AppState) -> WarmupReport {
+        let started = Instant::now();
+        let (vertex, harvester, bridge) = tokio::join!(
+            self.step("vertex_token", async {
+                state
+                    .token_manager
+                    .get_token()
+                    .await
+                    .map(drop)
+                    .map_err(|e| format!("{e:#}"))
+            }),
+            self.step("harvester_tokens", async {
+                let harvester = HarvesterClient::new(&state.config)
+                    .map_err(|e| format!("{e:#}"))?
+                    .with_metrics(state.metrics.clone());
+                harvester
+                    .get_tokens(false)
+                    .await
+                    .map(drop)
+                    .map_err(|e| format!("{e:#}"))
+            }),
+            self.step("anthropic_bridge", async {
+                let registry = &state.provider_registry;
+                registry.probe_health_of(Some(Provider::AnthropicCLI)).await;
+                registry
+                    .health_snapshot()
+                    .await
+                    .into_iter()
+                    .find(|(provider, _)| *provider == Provider::AnthropicCLI)
+                    .and_then(|(_, health)| health.error)
+                    .map_or(Ok(()), Err)
+            }),
+        );
+
+        let report = WarmupReport {
+            completed_at: Utc::now(),
+            steps: vec![vertex, harvester, bridge],
+        };
+        let failed = report.steps.iter().filter(|step| !step.ok).count();
+        if failed == 0 {
+            info!("Warm-up finished in {:?}", started.elapsed());
+        } else {
+            warn!(
+                "Warm-up finished in {:?} with {failed} of {} steps failed",
+                started.elapsed(),
+                report.steps.len()
+            );
+        }
+        *self.report.write().await = Some(report.clone());
+        report
+    }
+
+    async fn step(&self, name: &str, work: impl Future<Output = Result<(), String>>) -> WarmupStep {
+        let started = Instant::now();
+        let error = match tokio::time::timeout(self.timeout, work).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e),
+            Err(_) => Some(format!("Timed out after {}s", self.timeout.as_secs())),
+        };
+        let latency_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        match &error {
+            None => info!("Warm-up: {name} ready in {latency_ms}ms"),
+            Some(e) => warn!("Warm-up: {name} failed after {latency_ms}ms: {e}"),
+        }
+        WarmupStep {
+            name: name.to_string(),
+            ok: error.is_none(),
+            latency_ms,
+            error,
+        }
+    }
+}
+
+/// Runs warm-up once in the background.
+#[must_use]
+pub fn spawn(state: AppState) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        state.warmup.run(&state).await;
+    })
+}
@@ -0,0 +1,329 @@
+// Marks completions with the proxy instance, provider and model that served
+// them.
+//
+// When a completion turns out wrong, whoever holds the response can tell
+// which replica produced it, through which provider and model version,
+// without matching timestamps against logs. The mark goes into a response
+// header, a field of the body, or both. For streams the header names the
+// requested model, as it is sent before the provider reports its own; the
+// body field rides on the final chunk and carries the reported one.
+
+use axum::body::{to_bytes, Body, Bytes};
+use axum::http::{header, HeaderName, HeaderValue};
+use axum::response::Response;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::config::WatermarkConfig;
+use crate::services::finish_reason;
+
+// Provider named for responses the proxy produced itself, such as canned
+// fallback replies
+const PROXY_PROVIDER: &str = "proxy";
+
+/// Checks that the configured header name and instance can be sent.
+///
+/// # Errors
+///
+/// Returns a description of the first invalid setting.
+pub fn validate(config: &WatermarkConfig) -> Result<(), String> {
+    if let Some(name) = &config.header {
+        HeaderName::try_from(name.as_str()).map_err(|e| format!("invalid header '{name}': {e}"))?;
+    }
+    if let Some(instance) = &config.instance {
+        HeaderValue::try_from(instance.as_str())
+            .map_err(|e| format!("invalid instance '{instance}': {e}"))?;
+    }
+    Ok(())
+}
+
+/// Adds the configured watermark to responses.
+///
+/// Without a header or body field every call returns the response
+/// untouched, so callers can apply it unconditionally.
+#[derive(Debug, Clone, Default)]
+pub struct Watermark {
+    header: Option<HeaderName>,
+    body_field: Option<String>,
+    instance: String,
+    keys: Vec<String>,
+}
+
+impl Watermark {
+    #[must_use]
+    pub fn from_config(config: &WatermarkConfig) -> Self {
+        Self {
+            header: config
+                .header
+                .as_deref()
+                .and_then(|name| HeaderName::try_from(name).ok()),
+            body_field: config.body_field.clone(),
+            instance: config
+                .instance
+                .clone()
+                .or_else(|| std::env::var("HOSTNAME").ok())
+                .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string()),
+            keys: config.keys.clone(),
+        }
+    }
+
+    /// Whether responses to the API key `key_name` are marked.
+    #[must_use]
+    pub fn applies_to(&self, key_name: &str) -> bool {
+        (self.header.is_some() || self.body_field.is_some())
+            && (self.keys.is_empty() || self.keys.iter().any(|k| k == key_name))
+    }
+
+    fn header_value(&self, provider: &str, model: &str) -> Option<HeaderValue> {
+        let value = format!(
+            "instance={}; provider={provider}; model={model}",
+            self.instance
+        );
+        HeaderValue::try_from(value).ok()
+    }
+
+    fn body_value(&self, provider: &str, model: &str) -> Value {
+        json!({
+            "instance": self.instance,
+            "provider": provider,
+            "model": model,
+        })
+    }
+
+    /// Marks `response` to the API key `key_name`. `provider` is the
+    /// provider the request was routed to, if any, and `requested_model` the
+    /// model the client asked for, used where the response names none.
+    pub async fn apply(
+        &self,
+        key_name: &str,
+        provider: Option<&str>,
+        requested_model: &str,
+        response: Response,
+    ) -> Response {
+        if !self.applies_to(key_name) {
+            return response;
+        }
+        let provider = provider.unwrap_or(PROXY_PROVIDER).to_string();
+        let is_stream = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        let is_json = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        let marks_body = self.body_field.is_some() && response.status().is_success();
+
+        if is_json && marks_body {
+            return self.apply_json(&provider, requested_model, response).await;
+        }
+        let mut response = response;
+        if let Some(name) = &self.header {
+            if let Some(value) = self.header_value(&provider, requested_model) {
+                response.headers_mut().insert(name.clone(), value);
+            }
+        }
+        if is_stream && marks_body {
+            return self.apply_stream(provider, requested_model.to_string(), response);
+        }
+        response
+    }
+
+    /// Marks a JSON completion with the model it reports.
+    async fn apply_json(
+        &self,
+        provider: &str,
+        requested_model: &str,
+        response: Response,
+    ) -> Response {
+        let (mut parts, body) = response.into_parts();
+        let bytes = match to_bytes(body, usize::MAX).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Failed to read response body for watermarking: {e}");
+                return Response::from_parts(parts, Body::empty());
+            }
+        };
+        let mut value: Value = match serde_json::from_slice(&bytes) {
+            Ok(value) => value,
+            Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+        };
+        let model = value["model"]
+            .as_str()
+            .unwrap_or(requested_model)
+            .to_string();
+        if let Some(name) = &self.header {
+            if let Some(header_value) = self.header_value(provider, &model) {
+                parts.headers.insert(name.clone(), header_value);
+            }
+        }
+        let (Some(field), Some(object)) = (&self.body_field, value.as_object_mut()) else {
+            return Response::from_parts(parts, Body::from(bytes));
+        };
+        object.insert(field.clone(), self.body_value(provider, &model));
+        parts.headers.remove(header::CONTENT_LENGTH);
+        Response::from_parts(parts, Body::from(value.to_string()))
+    }
+
+    /// Marks the chunks of a stream that carry a finish reason.
+    fn apply_stream(
+        &self,
+        provider: String,
+        requested_model: String,
+        response: Response,
+    ) -> Response {
+        let Some(field) = self.body_field.clone() else {
+            return response;
+        };
+        let watermark = self.clone();
+        let (parts, body) = response.into_parts();
+        let body = body
+            .into_data_stream()
+            .map(move |frame| -> Result<Bytes, axum::Error> {
+                let bytes = frame?;
+                let Ok(text) = std::str::from_utf8(&bytes) else {
+                    return Ok(bytes);
+                };
+                if !text.contains("\"finish_reason\"") {
+                    return Ok(bytes);
+                }
+                Ok(Bytes::from(finish_reason::rewrite_sse_chunk(
+                    text,
+                    |event| {
+                        if event["choices"][0]["finish_reason"].is_null() {
+                            return false;
+                        }
+                        let model = event["model"]
+                            .as_str()
+                            .unwrap_or(&requested_model)
+                            .to_string();
+                        let mark = watermark.body_value(&provider, &model);
+                        event
+                            .as_object_mut()
+                            .map(|event| event.insert(field.clone(), mark))
+                            .is_some()
+                    },
+                )))
+            });
+        Response::from_parts(parts, Body::from_stream(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use axum::Json;
+
+    fn watermark(keys: &[&str]) -> Watermark {
+        Watermark::from_config(&WatermarkConfig {
+            header: Some("x-served-by".to_string()),
+            body_field: Some("x_served_by".to_string()),
+            instance: Some("eu-1".to_string()),
+            keys: keys.iter().map(|k| (*k).to_string()).collect(),
+        })
+    }
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        serde_json::from_slice(&bytes).expect("body should be JSON")
+    }
+
+    #[tokio::test]
+    async fn test_json_completion_is_marked_with_reported_model() {
+        let response =
+            Json(json!({"model": "gemini-2.5-flash-001", "choices": []})).into_response();
+        let response = watermark(&[])
+            .apply("team-a", Some("vertex"), "gemini-2.5-flash", response)
+            .await;
+        assert_eq!(
+            response.headers()["x-served-by"],
+            "instance=eu-1; provider=vertex; model=gemini-2.5-flash-001"
+        );
+        assert_eq!(
+            body_json(response).await["x_served_by"],
+            json!({"instance": "eu-1", "provider": "vertex", "model": "gemini-2.5-flash-001"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_marks_only_the_final_chunk() {
+        let frames = [
+            "data: {\"model\":\"m-001\",\"choices\":[{\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\n",
+            "data: {\"model\":\"m-001\",\"choices\":[{\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ];
+        let mut response = Response::new(Body::from_stream(futures::stream::iter(
+            frames.map(|f| Ok::<_, std::io::Error>(Bytes::from(f))),
+        )));
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/event-stream"),
+        );
+        let response = watermark(&[])
+            .apply("team-a", Some("anthropic"), "m", response)
+            .await;
+        assert_eq!(
+            response.headers()["x-served-by"],
+            "instance=eu-1; provider=anthropic; model=m"
+        );
+        let bytes = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("body should be readable");
+        let events: Vec<Value> = String::from_utf8_lossy(&bytes)
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str(data).ok())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].get("x_served_by").is_none());
+        assert_eq!(events[1]["x_served_by"]["model"], "m-001");
+    }
+
+    #[tokio::test]
+    async fn test_only_listed_keys_and_successes_get_the_body_field() {
+        let listed = watermark(&["team-a"]);
+        assert!(listed.applies_to("team-a"));
+        assert!(!listed.applies_to("team-b"));
+        assert!(!Watermark::default().applies_to("team-a"));
+
+        let mut failed = Json(json!({"error": {"message": "boom"}})).into_response();
+        *failed.status_mut() = StatusCode::BAD_GATEWAY;
+        let failed = listed.apply("team-a", None, "m", failed).await;
+        assert_eq!(
+            failed.headers()["x-served-by"],
+            "instance=eu-1; provider=proxy; model=m"
+        );
+        assert!(body_json(failed).await.get("x_served_by").is_none());
+
+        let untouched = listed
+            .apply(
+                "team-b",
+                Some("vertex"),
+                "m",
+                Json(json!({})).into_response(),
+            )
+            .await;
+        assert!(untouched.headers().get("x-served-by").is_none());
+    }
+
+    #[test]
+    fn test_validate_rejects_unsendable_settings() {
+        let mut config = WatermarkConfig {
+            header: Some("x-served-by".to_string()),
+            ..Default::default()
+        };
+        assert!(validate(&config).is_ok());
+        config.header = Some("bad header".to_string());
+        assert!(validate(&config).is_err());
+        config.header = None;
+        config.instance = Some("line\nbreak".to_string());
+        assert!(validate(&config).is_err());
+    }
+}
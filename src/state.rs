@@ -1,11 +1,43 @@
 use crate::config::AppConfig;
 use crate::middleware::rate_limit::RateLimiter;
+use crate::models::openai::ChatCompletionResponse;
 use crate::openai::circuit_breaker::CircuitBreaker;
 use crate::openai::metrics::Metrics;
+use crate::openai::token_bindings::TokenBindings;
+use crate::openai::waf_cooldown::WafCooldown;
 use crate::services::auth::TokenManager;
+use crate::services::budgets::BudgetManager;
 use crate::services::cache::Cache;
+use crate::services::experiments::Experiments;
+use crate::services::fallback_responses::FallbackResponses;
+use crate::services::idempotency::IdempotencyStore;
+use crate::services::keys::KeyStore;
+use crate::services::maintenance_mode::MaintenanceMode;
+use crate::services::mirror::Mirror;
+use crate::services::model_catalog::ModelCatalog;
+use crate::services::model_registry::ModelRegistry;
+use crate::services::notifier::Notifier;
+use crate::services::post_processor::PostProcessor;
+use crate::services::prompt_templates::PromptTemplateStore;
+use crate::services::providers::ProviderError;
 use crate::services::providers::ProviderRegistry;
+use crate::services::routing::{LatencyTracker, SessionAffinity};
+use crate::services::routing_rules::RoutingRules;
+use crate::services::scheduler::PriorityScheduler;
+use crate::services::single_flight::SingleFlight;
+use crate::services::slo::Slos;
+use crate::services::sqlite_store::SqliteStore;
+use crate::services::status_page::StatusPage;
+use crate::services::storage::SharedStorage;
+use crate::services::transcripts::Transcripts;
+use crate::services::usage::UsageTracker;
+use crate::services::warmup::Warmup;
+use crate::services::watermark::Watermark;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Non-streaming chat completions currently in flight, shared among identical requests.
+pub type InFlightCompletions = SingleFlight<Result<ChatCompletionResponse, Arc<ProviderError>>>;
 
 /// Application state shared across all request handlers.
 ///
@@ -13,20 +45,69 @@ use std::sync::Arc;
 /// - Configuration (read-only)
 /// - Token manager for Google Cloud authentication
 /// - Provider registry for routing requests to different LLM providers
+/// - Rolling provider latency for latency-aware routing
+/// - Session affinity for sticky alias group routing
 /// - Rate limiter for request throttling
 /// - Circuit breaker for backend resilience
 /// - Metrics collector for observability
+/// - Cooldown for harvester sessions blocked by the ChatGPT WAF
+/// - Harvester tokens bound to the conversation sessions that first used them
 /// - Response cache for performance optimization
+/// - In-flight completions for coalescing identical concurrent requests
+/// - Recent responses replayed for retries with the same `Idempotency-Key`
+/// - Model metadata registry (context window, pricing, capabilities)
+/// - Cached upstream model lists for `/v1/models`
+/// - Per-client API key store
+/// - Named prompt templates
+/// - A/B experiments over models and prompts
+/// - Content-based routing rules
+/// - Webhook post-processing of completed responses
+/// - Sampled request mirroring for offline evaluation
+/// - Conversation transcripts kept for clients that send a session ID
+/// - Priority scheduler bounding concurrent upstream calls
+/// - Usage accounting and per-key spend limits
+/// - Webhook notifier for operational alerts
+/// - Optional SQLite store for usage, keys and audit events
+/// - Shutdown token that per-request cancellation tokens descend from
 ///
 /// All fields are wrapped in `Arc` for efficient sharing across async tasks,
-/// except `token_manager` and `rate_limiter` which are `Clone` themselves.
+/// except `token_manager`, `rate_limiter` and `shutdown` which are `Clone`
+/// themselves.
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<AppConfig>,
     pub token_manager: TokenManager,
     pub provider_registry: Arc<ProviderRegistry>,
+    pub latency: Arc<LatencyTracker>,
+    pub affinity: Arc<SessionAffinity>,
     pub rate_limiter: RateLimiter,
     pub circuit_breaker: Arc<CircuitBreaker>,
     pub metrics: Arc<Metrics>,
+    pub waf_cooldown: Arc<WafCooldown>,
+    pub token_bindings: Arc<TokenBindings>,
     pub cache: Arc<Cache>,
+    pub in_flight: Arc<InFlightCompletions>,
+    pub idempotency: Arc<IdempotencyStore>,
+    pub model_registry: Arc<ModelRegistry>,
+    pub model_catalog: Arc<ModelCatalog>,
+    pub key_store: Arc<KeyStore>,
+    pub prompts: Arc<PromptTemplateStore>,
+    pub experiments: Arc<Experiments>,
+    pub fallback_responses: Arc<FallbackResponses>,
+    pub post_processor: Arc<PostProcessor>,
+    pub mirror: Arc<Mirror>,
+    pub watermark: Arc<Watermark>,
+    pub transcripts: Arc<Transcripts>,
+    pub slos: Arc<Slos>,
+    pub routing_rules: Arc<RoutingRules>,
+    pub scheduler: Arc<PriorityScheduler>,
+    pub usage: Arc<UsageTracker>,
+    pub budgets: Arc<BudgetManager>,
+    pub notifier: Arc<Notifier>,
+    pub maintenance_mode: Arc<MaintenanceMode>,
+    pub status_page: Arc<StatusPage>,
+    pub warmup: Arc<Warmup>,
+    pub store: Option<Arc<SqliteStore>>,
+    pub storage: Option<SharedStorage>,
+    pub shutdown: CancellationToken,
 }
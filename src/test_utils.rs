@@ -0,0 +1,742 @@
+// Mock upstreams for exercising the proxy without provider credentials.
+//
+// Each fixture runs a wiremock server speaking one upstream's protocol: the
+// Vertex (Gemini API key) endpoints, the Anthropic bridge, a local Ollama
+// server, DeepSeek's OpenAI-compatible API, a generic OpenAI-compatible
+// upstream, Bedrock's Converse API and the harvester together with the
+// ChatGPT backend it fronts. `configure` points an
+// `AppConfig` at the mock, so a proxy built from that config runs its real
+// handler, provider and transformer code against canned replies, streams and
+// errors. Enabled with the `test-utils` cargo feature.
+
+use serde_json::json;
+use wiremock::matchers::{body_partial_json, header, header_regex, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::config::AppConfig;
+
+/// API key the proxy sends to [`MockVertex`].
+pub const MOCK_VERTEX_API_KEY: &str = "mock-vertex-key";
+
+/// API key the proxy sends to [`MockDeepSeek`].
+pub const MOCK_DEEPSEEK_API_KEY: &str = "mock-deepseek-key";
+
+/// API key the proxy sends to [`MockOpenAICompatible`].
+pub const MOCK_OPENAI_COMPATIBLE_API_KEY: &str = "mock-openrouter-key";
+
+/// AWS access key ID the proxy signs [`MockBedrock`] requests with.
+pub const MOCK_AWS_ACCESS_KEY_ID: &str = "AKIDMOCKBEDROCK";
+
+/// Access token issued by [`MockHarvester`].
+pub const MOCK_ACCESS_TOKEN: &str = "mock-access-token";
+
+const BACKEND_CONVERSATION_PATH: &str = "/backend-api/conversation";
+
+fn event_stream(events: impl IntoIterator<Item = String>) -> ResponseTemplate {
+    let body: String = events
+        .into_iter()
+        .map(|data| format!("data: {data}\n\n"))
+        .collect();
+    ResponseTemplate::new(200).set_body_raw(body, "text/event-stream")
+}
+
+/// Vertex answering Gemini API key requests.
+pub struct MockVertex {
+    server: MockServer,
+}
+
+impl MockVertex {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// The underlying server, for custom mocks and inspecting requests.
+    #[must_use]
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Sends Vertex traffic to this mock, authenticating with an API key.
+    pub fn configure(&self, config: &mut AppConfig) {
+        config.vertex.api_key = Some(MOCK_VERTEX_API_KEY.to_string());
+        config.vertex.api_key_base_url = Some(self.server.uri());
+    }
+
+    fn endpoint(model: &str, method_name: &str) -> String {
+        format!("/v1beta/models/{model}:{method_name}")
+    }
+
+    /// Answers non-streaming requests for `model` with `text`.
+    pub async fn reply(&self, model: &str, text: &str) {
+        Mock::given(method("POST"))
+            .and(path(Self::endpoint(model, "generateContent")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": text}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }],
+                "usageMetadata": {
+                    "promptTokenCount": 1,
+                    "candidatesTokenCount": 1,
+                    "totalTokenCount": 2
+                }
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Answers token counts for `model` with `total_tokens`.
+    pub async fn count_tokens(&self, model: &str, total_tokens: u32) {
+        Mock::given(method("POST"))
+            .and(path(Self::endpoint(model, "countTokens")))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "totalTokens": total_tokens })),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Streams `chunks` for `model`, one SSE event each; the last one
+    /// finishes the candidate.
+    pub async fn stream(&self, model: &str, chunks: &[&str]) {
+        let last = chunks.len().saturating_sub(1);
+        let events = chunks.iter().enumerate().map(|(i, text)| {
+            let mut candidate = json!({
+                "content": {"role": "model", "parts": [{"text": text}]},
+                "index": 0
+            });
+            if i == last {
+                candidate["finishReason"] = json!("STOP");
+            }
+            json!({"candidates": [candidate]}).to_string()
+        });
+        Mock::given(method("POST"))
+            .and(path(Self::endpoint(model, "streamGenerateContent")))
+            .respond_with(event_stream(events))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Fails streaming and non-streaming requests for `model` with a Google
+    /// API error.
+    pub async fn fail(&self, model: &str, status: u16, message: &str) {
+        let google_status = match status {
+            400 => "INVALID_ARGUMENT",
+            401 => "UNAUTHENTICATED",
+            403 => "PERMISSION_DENIED",
+            404 => "NOT_FOUND",
+            429 => "RESOURCE_EXHAUSTED",
+            503 => "UNAVAILABLE",
+            _ => "INTERNAL",
+        };
+        let error = json!({
+            "error": {"code": status, "message": message, "status": google_status}
+        });
+        for method_name in ["generateContent", "streamGenerateContent"] {
+            Mock::given(method("POST"))
+                .and(path(Self::endpoint(model, method_name)))
+                .respond_with(ResponseTemplate::new(status).set_body_json(error.clone()))
+                .mount(&self.server)
+                .await;
+        }
+    }
+
+    /// Lists `models` on the model-list endpoint, each with a 1M-token
+    /// context window.
+    pub async fn list_models(&self, models: &[&str]) {
+        let models: Vec<_> = models
+            .iter()
+            .map(|id| {
+                json!({
+                    "name": format!("models/{id}"),
+                    "inputTokenLimit": 1_048_576,
+                    "outputTokenLimit": 65_536
+                })
+            })
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/v1beta/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "models": models })))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// The Anthropic bridge, speaking bridge API version 1.
+pub struct MockAnthropicBridge {
+    server: MockServer,
+}
+
+impl MockAnthropicBridge {
+    /// Starts the bridge with a health endpoint reporting streaming chat and
+    /// non-streaming completion support.
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "status": "ok",
+                "version": "mock",
+                "api_version": 1,
+                "capabilities": ["chat", "complete"]
+            })))
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    /// The underlying server, for custom mocks and inspecting requests.
+    #[must_use]
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Sends Anthropic traffic to this mock.
+    pub fn configure(&self, config: &mut AppConfig) {
+        config.anthropic.bridge_url = self.server.uri();
+    }
+
+    /// Answers non-streaming requests with `text`.
+    pub async fn reply(&self, text: &str) {
+        Mock::given(method("POST"))
+            .and(path("/anthropic/complete"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"content": text, "finish_reason": "end_turn"})),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Streams `chunks` as OpenAI chunks, the last one carrying the
+    /// Anthropic stop reason, followed by `[DONE]`.
+    pub async fn stream(&self, model: &str, chunks: &[&str]) {
+        let last = chunks.len().saturating_sub(1);
+        let events = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                json!({
+                    "id": "chatcmpl-mock",
+                    "object": "chat.completion.chunk",
+                    "created": 0,
+                    "model": model,
+                    "choices": [{
+                        "index": 0,
+                        "delta": {"content": text},
+                        "finish_reason": (i == last).then_some("end_turn")
+                    }]
+                })
+                .to_string()
+            })
+            .chain(std::iter::once("[DONE]".to_string()));
+        Mock::given(method("POST"))
+            .and(path("/anthropic/chat"))
+            .respond_with(event_stream(events))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Fails streaming and non-streaming requests with a bridge error.
+    pub async fn fail(&self, status: u16, message: &str) {
+        for endpoint in ["/anthropic/complete", "/anthropic/chat"] {
+            Mock::given(method("POST"))
+                .and(path(endpoint))
+                .respond_with(
+                    ResponseTemplate::new(status).set_body_json(json!({"error": message})),
+                )
+                .mount(&self.server)
+                .await;
+        }
+    }
+}
+
+/// A local Ollama server answering `/api/chat`.
+pub struct MockOllama {
+    server: MockServer,
+}
+
+impl MockOllama {
+    /// Starts the server with a `/api/tags` endpoint for health probes.
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/tags"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"models": []})))
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    /// The underlying server, for custom mocks and inspecting requests.
+    #[must_use]
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Enables the Ollama provider and points it at this mock.
+    pub fn configure(&self, config: &mut AppConfig) {
+        config.ollama.enabled = true;
+        config.ollama.base_url = self.server.uri();
+    }
+
+    /// Answers non-streaming requests with `text`.
+    pub async fn reply(&self, text: &str) {
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({"stream": false})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "message": {"role": "assistant", "content": text},
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 1,
+                "eval_count": 1
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Streams `chunks` as NDJSON lines, followed by a final `done` line.
+    pub async fn stream(&self, chunks: &[&str]) {
+        let body: String = chunks
+            .iter()
+            .map(|text| json!({"message": {"role": "assistant", "content": text}, "done": false}))
+            .chain(std::iter::once(json!({
+                "message": {"role": "assistant", "content": ""},
+                "done": true,
+                "done_reason": "stop"
+            })))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({"stream": true})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/x-ndjson"))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// DeepSeek's OpenAI-compatible API. Mocks only answer requests carrying
+/// [`MOCK_DEEPSEEK_API_KEY`].
+pub struct MockDeepSeek {
+    server: MockServer,
+}
+
+impl MockDeepSeek {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// Sends DeepSeek traffic to this mock.
+    pub fn configure(&self, config: &mut AppConfig) {
+        config.deepseek.api_key = Some(MOCK_DEEPSEEK_API_KEY.to_string());
+        config.deepseek.base_url = self.server.uri();
+    }
+
+    fn chat() -> wiremock::MockBuilder {
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(header(
+                "authorization",
+                format!("Bearer {MOCK_DEEPSEEK_API_KEY}").as_str(),
+            ))
+    }
+
+    /// Answers non-streaming requests for `model` with `text`.
+    pub async fn reply(&self, model: &str, text: &str) {
+        Self::chat()
+            .and(body_partial_json(json!({"stream": false})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion",
+                "created": 0,
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": text},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Streams `chunks` as OpenAI chunks after a keep-alive comment, the last
+    /// one finishing with `stop`, followed by `[DONE]`.
+    pub async fn stream(&self, model: &str, chunks: &[&str]) {
+        let last = chunks.len().saturating_sub(1);
+        let frames: String = std::iter::once(": keep-alive\n\n".to_string())
+            .chain(chunks.iter().enumerate().map(|(i, text)| {
+                let chunk = json!({
+                    "id": "chatcmpl-mock",
+                    "object": "chat.completion.chunk",
+                    "created": 0,
+                    "model": model,
+                    "choices": [{
+                        "index": 0,
+                        "delta": {"content": text},
+                        "finish_reason": (i == last).then_some("stop")
+                    }]
+                });
+                format!("data: {chunk}\n\n")
+            }))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect();
+        Self::chat()
+            .and(body_partial_json(json!({"stream": true})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(frames, "text/event-stream"))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Fails every request with DeepSeek's error envelope.
+    pub async fn fail(&self, status: u16, message: &str) {
+        Self::chat()
+            .respond_with(ResponseTemplate::new(status).set_body_json(json!({
+                "error": {"message": message, "type": "invalid_request_error"}
+            })))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// An OpenAI-compatible upstream such as OpenRouter, serving the models
+/// passed to `configure` under `/api/v1`.
+pub struct MockOpenAICompatible {
+    server: MockServer,
+}
+
+impl MockOpenAICompatible {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// The underlying server, for custom mocks and inspecting requests.
+    #[must_use]
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Sends traffic for `models` to this mock.
+    pub fn configure(&self, config: &mut AppConfig, models: &[&str]) {
+        config.openai_compatible.base_url = Some(format!("{}/api/v1", self.server.uri()));
+        config.openai_compatible.api_key = Some(MOCK_OPENAI_COMPATIBLE_API_KEY.to_string());
+        config.openai_compatible.models = models.iter().map(|m| (*m).to_string()).collect();
+    }
+
+    fn chat(model: &str) -> wiremock::MockBuilder {
+        Mock::given(method("POST"))
+            .and(path("/api/v1/chat/completions"))
+            .and(header(
+                "authorization",
+                format!("Bearer {MOCK_OPENAI_COMPATIBLE_API_KEY}").as_str(),
+            ))
+            .and(body_partial_json(json!({"model": model})))
+    }
+
+    /// Answers non-streaming requests for `model` with `text`.
+    pub async fn reply(&self, model: &str, text: &str) {
+        Self::chat(model)
+            .and(body_partial_json(json!({"stream": false})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "gen-mock",
+                "object": "chat.completion",
+                "created": 0,
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": text},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Streams `chunks` as OpenAI chunks after an OpenRouter processing
+    /// comment, the last one finishing with `stop`, followed by `[DONE]`.
+    pub async fn stream(&self, model: &str, chunks: &[&str]) {
+        let last = chunks.len().saturating_sub(1);
+        let frames: String = std::iter::once(": OPENROUTER PROCESSING\n\n".to_string())
+            .chain(chunks.iter().enumerate().map(|(i, text)| {
+                let chunk = json!({
+                    "id": "gen-mock",
+                    "object": "chat.completion.chunk",
+                    "created": 0,
+                    "model": model,
+                    "choices": [{
+                        "index": 0,
+                        "delta": {"content": text},
+                        "finish_reason": (i == last).then_some("stop")
+                    }]
+                });
+                format!("data: {chunk}\n\n")
+            }))
+            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+            .collect();
+        Self::chat(model)
+            .and(body_partial_json(json!({"stream": true})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(frames, "text/event-stream"))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Fails requests for `model` with an OpenAI error envelope.
+    pub async fn fail(&self, model: &str, status: u16, message: &str) {
+        Self::chat(model)
+            .respond_with(ResponseTemplate::new(status).set_body_json(json!({
+                "error": {"message": message, "code": status}
+            })))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// Bedrock's runtime endpoint, answering Converse and ConverseStream calls.
+/// Mocks only answer requests SigV4-signed with [`MOCK_AWS_ACCESS_KEY_ID`];
+/// the signature itself is not verified.
+pub struct MockBedrock {
+    server: MockServer,
+}
+
+impl MockBedrock {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    /// Enables Bedrock and sends its traffic to this mock, with static
+    /// credentials.
+    pub fn configure(&self, config: &mut AppConfig) {
+        config.bedrock.enabled = true;
+        config.bedrock.region = Some("us-east-1".to_string());
+        config.bedrock.endpoint_url = Some(self.server.uri());
+        config.bedrock.access_key_id = Some(MOCK_AWS_ACCESS_KEY_ID.to_string());
+        config.bedrock.secret_access_key = Some("mock-secret-access-key".to_string());
+    }
+
+    fn action(model: &str, action: &str) -> wiremock::MockBuilder {
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/model/{}/{action}",
+                model.replace(':', "%3A")
+            )))
+            .and(header_regex(
+                "authorization",
+                &format!(
+                    "^AWS4-HMAC-SHA256 Credential={MOCK_AWS_ACCESS_KEY_ID}/\\d{{8}}/us-east-1/bedrock/aws4_request, "
+                ),
+            ))
+    }
+
+    /// One `application/vnd.amazon.eventstream` message carrying `payload`
+    /// as `event_type`; the CRCs are left zero.
+    fn event(event_type: &str, payload: &serde_json::Value) -> Vec<u8> {
+        let mut headers = Vec::new();
+        for (name, value) in [(":message-type", "event"), (":event-type", event_type)] {
+            headers.push(u8::try_from(name.len()).unwrap_or(u8::MAX));
+            headers.extend_from_slice(name.as_bytes());
+            headers.push(7);
+            headers
+                .extend_from_slice(&u16::try_from(value.len()).unwrap_or(u16::MAX).to_be_bytes());
+            headers.extend_from_slice(value.as_bytes());
+        }
+        let payload = payload.to_string().into_bytes();
+        let total = 16 + headers.len() + payload.len();
+        let mut message = Vec::with_capacity(total);
+        message.extend_from_slice(&u32::try_from(total).unwrap_or(u32::MAX).to_be_bytes());
+        message.extend_from_slice(
+            &u32::try_from(headers.len())
+                .unwrap_or(u32::MAX)
+                .to_be_bytes(),
+        );
+        message.extend_from_slice(&[0; 4]);
+        message.extend_from_slice(&headers);
+        message.extend_from_slice(&payload);
+        message.extend_from_slice(&[0; 4]);
+        message
+    }
+
+    /// Answers Converse requests for `model` with `text`.
+    pub async fn reply(&self, model: &str, text: &str) {
+        Self::action(model, "converse")
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "output": {"message": {"role": "assistant", "content": [{"text": text}]}},
+                "stopReason": "end_turn",
+                "usage": {"inputTokens": 1, "outputTokens": 1, "totalTokens": 2}
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Streams `chunks` for `model` as content block deltas, followed by
+    /// `messageStop` and the `metadata` event carrying usage.
+    pub async fn stream(&self, model: &str, chunks: &[&str]) {
+        let mut body = Self::event("messageStart", &json!({"role": "assistant"}));
+        for text in chunks {
+            body.extend(Self::event(
+                "contentBlockDelta",
+                &json!({"contentBlockIndex": 0, "delta": {"text": text}}),
+            ));
+        }
+        body.extend(Self::event(
+            "contentBlockStop",
+            &json!({"contentBlockIndex": 0}),
+        ));
+        body.extend(Self::event(
+            "messageStop",
+            &json!({"stopReason": "end_turn"}),
+        ));
+        body.extend(Self::event(
+            "metadata",
+            &json!({
+                "usage": {"inputTokens": 1, "outputTokens": 1, "totalTokens": 2},
+                "metrics": {"latencyMs": 1}
+            }),
+        ));
+        Self::action(model, "converse-stream")
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(body, "application/vnd.amazon.eventstream"),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Fails requests for `model` with Bedrock's error body, naming the
+    /// exception in `x-amzn-ErrorType`.
+    pub async fn fail(&self, model: &str, status: u16, error_type: &str, message: &str) {
+        for action in ["converse", "converse-stream"] {
+            Self::action(model, action)
+                .respond_with(
+                    ResponseTemplate::new(status)
+                        .insert_header(
+                            "x-amzn-ErrorType",
+                            format!(
+                                "{error_type}:http://internal.amazon.com/coral/com.amazon.bedrock/"
+                            ),
+                        )
+                        .set_body_json(json!({"message": message})),
+                )
+                .mount(&self.server)
+                .await;
+        }
+    }
+}
+
+/// The harvester and the ChatGPT backend conversation endpoint it unlocks,
+/// served from one mock. Backend mocks only answer requests carrying
+/// [`MOCK_ACCESS_TOKEN`].
+pub struct MockHarvester {
+    server: MockServer,
+}
+
+impl MockHarvester {
+    /// Starts the harvester with a live session issuing [`MOCK_ACCESS_TOKEN`].
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let tokens = json!({
+            "access_token": MOCK_ACCESS_TOKEN,
+            "arkose_token": "mock-arkose-token",
+            "expires_at": chrono::Utc::now().timestamp() + 3600
+        });
+        Mock::given(method("GET"))
+            .and(path("/tokens"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(tokens.clone()))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/refresh"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(tokens))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "browser_alive": true,
+                "session_valid": true,
+                "last_token_refresh": chrono::Utc::now().timestamp()
+            })))
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    /// The underlying server, for custom mocks and inspecting requests.
+    #[must_use]
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Sends harvester and ChatGPT backend traffic to this mock.
+    pub fn configure(&self, config: &mut AppConfig) {
+        config.openai.harvester_url = self.server.uri();
+        config.openai.backend_url =
+            Some(format!("{}{BACKEND_CONVERSATION_PATH}", self.server.uri()));
+    }
+
+    /// Streams `chunks` from the backend, one message event each, followed
+    /// by `[DONE]`. Non-streaming completions are collected from the same
+    /// stream.
+    pub async fn stream(&self, chunks: &[&str]) {
+        let events = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                json!({
+                    "message": {
+                        "id": format!("msg_mock_{i}"),
+                        "role": "assistant",
+                        "content": {"content_type": "text", "parts": [text]}
+                    },
+                    "conversation_id": "conv_mock"
+                })
+                .to_string()
+            })
+            .chain(std::iter::once("[DONE]".to_string()));
+        self.mount_backend(event_stream(events)).await;
+    }
+
+    /// Fails backend requests with `status` and a plain-text `body`; 403
+    /// reads as a WAF block.
+    pub async fn fail(&self, status: u16, body: &str) {
+        self.mount_backend(ResponseTemplate::new(status).set_body_string(body))
+            .await;
+    }
+
+    async fn mount_backend(&self, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(path(BACKEND_CONVERSATION_PATH))
+            .and(header(
+                "authorization",
+                format!("Bearer {MOCK_ACCESS_TOKEN}").as_str(),
+            ))
+            .respond_with(response)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Makes the harvester fail to hand out tokens, as when its browser
+    /// session is gone. Takes precedence over the tokens served on start.
+    pub async fn fail_tokens(&self, status: u16) {
+        let error = json!({"error": "session unavailable"});
+        for (verb, endpoint) in [("GET", "/tokens"), ("POST", "/refresh")] {
+            Mock::given(method(verb))
+                .and(path(endpoint))
+                .respond_with(ResponseTemplate::new(status).set_body_json(error.clone()))
+                .with_priority(1)
+                .mount(&self.server)
+                .await;
+        }
+    }
+}
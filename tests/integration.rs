@@ -2,15 +2,20 @@
 // @critical tests must pass before deployment
 
 mod integration {
+    mod admin_test;
     mod auth_test;
     mod chat_test;
     mod e2e_provider_test;
     mod error_test;
     mod health_test;
     mod metrics_test;
+    mod models_test;
     mod multi_provider_test;
+    mod provider_mock_test;
     mod rate_limit_test;
     mod security_test;
+    mod server_test;
     mod smoke_test;
     mod test_utils;
+    mod usage_test;
 }
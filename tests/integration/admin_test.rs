@@ -0,0 +1,396 @@
+// Admin API tests
+
+use super::test_utils::TestServer;
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use serde_json::Value;
+
+/// Reasonable body size limit for tests (1MB)
+const TEST_BODY_LIMIT: usize = 1024 * 1024;
+
+#[tokio::test]
+async fn test_set_and_list_budgets() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/budgets/team-a",
+        Some(r#"{"daily_usd": 5.0, "monthly_usd": 100.0}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/admin/budgets", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read budgets response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["team-a"]["daily_usd"], 5.0);
+    assert_eq!(json["team-a"]["monthly_usd"], 100.0);
+    assert_eq!(json["team-a"]["spent_today_usd"], 0.0);
+}
+
+#[tokio::test]
+async fn test_negative_budget_rejected() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/budgets/team-a",
+        Some(r#"{"daily_usd": -1.0}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_admin_requires_admin_key() {
+    let server = TestServer::with_auth(true, "test-master-key-12345");
+
+    let req = TestServer::make_request("GET", "/admin/budgets", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let req =
+        TestServer::make_request("GET", "/admin/budgets", None, Some("test-master-key-12345"));
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_usage_export_formats() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/admin/usage/export?format=csv", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/csv; charset=utf-8"
+    );
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read export response");
+    let body = String::from_utf8(body_bytes.to_vec()).expect("CSV must be UTF-8");
+    assert!(body.starts_with("period_start,period_end,key,model,"));
+
+    let req = TestServer::make_request(
+        "GET",
+        "/admin/usage/export?format=jsonl&period=2024-01",
+        None,
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let req = TestServer::make_request("GET", "/admin/usage/export?format=xml", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_model_alias_update_applies_to_model_lookup() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/models/aliases/gemini-stable",
+        Some(r#"{"target": "gemini-2.5-flash"}"#),
+        None,
+    );
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/v1/models/gemini-stable", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read model response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["id"], "gemini-2.5-flash");
+
+    let req = TestServer::make_request("GET", "/admin/models/aliases", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read aliases response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["gemini-stable"], "gemini-2.5-flash");
+
+    let req = TestServer::make_request("DELETE", "/admin/models/aliases/gemini-stable", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::NO_CONTENT);
+    let req = TestServer::make_request("DELETE", "/admin/models/aliases/gemini-stable", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_model_alias_to_unknown_model_rejected() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/models/aliases/my-latest",
+        Some(r#"{"target": "no-such-model"}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["error"]["param"], "target");
+}
+
+#[tokio::test]
+async fn test_prompt_template_admin_and_chat_expansion() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/prompts/support_bot",
+        Some(r#"{"messages": [{"role": "system", "content": "You help {{product}} users."}]}"#),
+        None,
+    );
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/admin/prompts", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read prompts response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(
+        json["support_bot"]["messages"][0]["content"],
+        "You help {{product}} users."
+    );
+
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions",
+        Some(r#"{"model": "gemini-2.5-flash", "prompt_template": "support_bot"}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["error"]["param"], "variables");
+
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions",
+        Some(r#"{"model": "gemini-2.5-flash", "prompt_template": "nope", "variables": {}}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["error"]["code"], "prompt_template_not_found");
+
+    let req = TestServer::make_request("DELETE", "/admin/prompts/support_bot", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn test_empty_prompt_template_rejected() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/prompts/empty",
+        Some(r#"{"messages": []}"#),
+        None,
+    );
+    assert_eq!(server.call(req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_rate_limit_stats_endpoint() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/admin/rate-limit?top=5", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read rate limit response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert!(json["capacity"].as_u64().is_some());
+    assert!(json["active_keys"].as_u64().is_some());
+    assert!(json["top_keys"].is_array());
+
+    let req = TestServer::make_request("GET", "/admin/rate-limit?top=many", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_log_settings_need_installed_subscriber() {
+    // The test process installs no log subscriber for the proxy to reconfigure
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/admin/logs", None, None);
+    assert_eq!(
+        server.call(req).await.status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    let req = TestServer::make_request("PUT", "/admin/logs", Some(r#"{"format": "json"}"#), None);
+    assert_eq!(
+        server.call(req).await.status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+}
+
+#[tokio::test]
+async fn test_maintenance_mode_blocks_data_plane_only() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/maintenance",
+        Some(r#"{"message": "Rotating credentials", "retry_after_secs": 120}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/v1/models", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()["retry-after"], "120");
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read maintenance response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["error"]["code"], "maintenance");
+    assert_eq!(json["error"]["message"], "Rotating credentials");
+
+    let req = TestServer::make_request("GET", "/metrics", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/admin/maintenance", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read maintenance status");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["active"], true);
+
+    let req = TestServer::make_request("DELETE", "/admin/maintenance", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/v1/models", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_scheduled_maintenance_waits_for_from() {
+    let server = TestServer::new();
+    let from = chrono::Utc::now() + chrono::Duration::hours(1);
+    let body = serde_json::json!({
+        "from": from,
+        "until": from + chrono::Duration::hours(1)
+    })
+    .to_string();
+    let req = TestServer::make_request("PUT", "/admin/maintenance", Some(&body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read maintenance response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["active"], false);
+    assert!(json["from"].is_string());
+
+    // Not started yet: requests are still served
+    let req = TestServer::make_request("GET", "/v1/models", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/admin/maintenance", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read maintenance status");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["active"], false);
+    assert!(json["started_at"].is_string());
+
+    let body = serde_json::json!({"from": from, "until": from}).to_string();
+    let req = TestServer::make_request("PUT", "/admin/maintenance", Some(&body), None);
+    assert_eq!(server.call(req).await.status(), StatusCode::BAD_REQUEST);
+
+    let req = TestServer::make_request("DELETE", "/admin/maintenance", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_incident_flag_shows_on_public_status() {
+    let server = TestServer::with_auth(true, "admin-key");
+    let status = || async {
+        let req = TestServer::make_request("GET", "/status", None, None);
+        let response = server.call(req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read status response");
+        serde_json::from_slice::<Value>(&body_bytes).expect("Response must be valid JSON")
+    };
+
+    let json = status().await;
+    assert_ne!(json["status"], "incident");
+    assert!(json["uptime_secs"].is_u64());
+    assert!(json["providers"]
+        .as_array()
+        .is_some_and(|providers| providers.iter().any(|p| p["name"] == "openai")));
+
+    let req = TestServer::make_request(
+        "PUT",
+        "/admin/incident",
+        Some(r#"{"message": "Elevated <b>Vertex</b> errors"}"#),
+        Some("admin-key"),
+    );
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+    let json = status().await;
+    assert_eq!(json["status"], "incident");
+    assert_eq!(json["incident"]["message"], "Elevated <b>Vertex</b> errors");
+
+    let mut req = TestServer::make_request("GET", "/status", None, None);
+    req.headers_mut()
+        .insert("accept", "text/html".parse().expect("valid header"));
+    let response = server.call(req).await;
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read status page");
+    let html = String::from_utf8_lossy(&body_bytes);
+    assert!(html.contains("Status: incident"));
+    assert!(html.contains("Elevated &lt;b&gt;Vertex&lt;/b&gt; errors"));
+
+    let req = TestServer::make_request("DELETE", "/admin/incident", None, Some("admin-key"));
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+    assert!(status().await.get("incident").is_none());
+    let req = TestServer::make_request("DELETE", "/admin/incident", None, Some("admin-key"));
+    assert_eq!(server.call(req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_validate_unknown_provider_returns_404() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request(
+        "POST",
+        "/admin/providers/validate?provider=nope",
+        None,
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -192,3 +192,666 @@ async fn test_chat_completions_missing_messages() {
         response.status()
     );
 }
+
+#[tokio::test]
+async fn test_post_processing_webhook_rewrites_responses() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"content": "raw"})),
+        )
+        .mount(&upstream)
+        .await;
+    let chunk = |content: &str, finish: Option<&str>| {
+        serde_json::json!({
+            "id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 1, "model": "claude-3-opus",
+            "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish}]
+        })
+    };
+    Mock::given(method("POST"))
+        .and(path("/anthropic/chat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("ra", None),
+            chunk("w", Some("stop"))
+        )))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/post-process"))
+        .respond_with(|req: &wiremock::Request| {
+            let body: Value = serde_json::from_slice(&req.body).expect("webhook body is JSON");
+            let mut response = body["response"].clone();
+            let content = response["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap_or_default()
+                .to_uppercase();
+            response["choices"][0]["message"]["content"] = Value::from(content);
+            response["reviewed"] = Value::from(true);
+            ResponseTemplate::new(200).set_body_json(response)
+        })
+        .mount(&upstream)
+        .await;
+
+    let bridge_url = upstream.uri();
+    let webhook_url = format!("{}/post-process", upstream.uri());
+    let server = TestServer::with_config(|config| {
+        config.anthropic.bridge_url = bridge_url;
+        config.post_process.webhook_url = Some(webhook_url);
+        config.post_process.streams = true;
+    });
+
+    let body = r#"{"model": "claude-3-opus", "messages": [{"role": "user", "content": "hi"}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["choices"][0]["message"]["content"], "RAW");
+    assert_eq!(json["reviewed"], true);
+
+    let body = r#"{"model": "claude-3-opus", "messages": [{"role": "user", "content": "hi"}], "stream": true}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read stream");
+    let text = String::from_utf8_lossy(&body_bytes);
+    assert!(text.contains(r#""content":"RAW""#), "stream was: {text}");
+    assert!(text.contains(r#""reviewed":true"#));
+    assert!(text.trim_end().ends_with("data: [DONE]"));
+}
+
+#[tokio::test]
+async fn test_post_processing_timeout_follows_failure_policy() {
+    use vertex_bridge::services::post_processor::PostProcessFailurePolicy;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let upstream = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"content": "raw"})),
+        )
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/post-process"))
+        .respond_with(ResponseTemplate::new(204).set_delay(std::time::Duration::from_secs(2)))
+        .mount(&upstream)
+        .await;
+
+    let body = r#"{"model": "claude-3-opus", "messages": [{"role": "user", "content": "hi"}]}"#;
+    for (policy, expected) in [
+        (PostProcessFailurePolicy::PassThrough, StatusCode::OK),
+        (PostProcessFailurePolicy::Reject, StatusCode::BAD_GATEWAY),
+    ] {
+        let bridge_url = upstream.uri();
+        let webhook_url = format!("{}/post-process", upstream.uri());
+        let server = TestServer::with_config(|config| {
+            config.anthropic.bridge_url = bridge_url;
+            config.post_process.webhook_url = Some(webhook_url);
+            config.post_process.timeout_ms = 100;
+            config.post_process.on_failure = policy;
+        });
+        let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+        let response = server.call(req).await;
+        assert_eq!(response.status(), expected);
+        let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read response");
+        let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+        if expected == StatusCode::OK {
+            assert_eq!(json["choices"][0]["message"]["content"], "raw");
+        } else {
+            assert_eq!(json["error"]["code"], "post_processing_failed");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_trace_context_forwarded_to_bridge() {
+    use wiremock::matchers::{header, header_regex, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let bridge = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .and(header("x-request-id", "client-req-1"))
+        .and(header_regex(
+            "traceparent",
+            "^00-4bf92f3577b34da6a3ce929d0e0e4736-[0-9a-f]{16}-01$",
+        ))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"content": "traced"})),
+        )
+        .expect(1)
+        .mount(&bridge)
+        .await;
+
+    let bridge_url = bridge.uri();
+    let server = TestServer::with_config(|config| {
+        config.anthropic.bridge_url = bridge_url;
+    });
+    let body = r#"{"model": "claude-3-opus", "messages": [{"role": "user", "content": "hi"}]}"#;
+    let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    req.headers_mut().insert(
+        "traceparent",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+            .parse()
+            .unwrap(),
+    );
+    req.headers_mut()
+        .insert("x-request-id", "client-req-1".parse().unwrap());
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok()),
+        Some("client-req-1")
+    );
+}
+
+#[tokio::test]
+async fn test_mirror_writes_redacted_pairs_to_file() {
+    use vertex_bridge::services::mirror::MirrorSinkKind;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let bridge = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"content": "Replied to bob@example.com"})),
+        )
+        .mount(&bridge)
+        .await;
+
+    let file = std::env::temp_dir().join(format!("mirror-{}.jsonl", uuid::Uuid::new_v4()));
+    let bridge_url = bridge.uri();
+    let mirror_path = file.to_string_lossy().into_owned();
+    let server = TestServer::with_config(|config| {
+        config.anthropic.bridge_url = bridge_url;
+        config.mirror.sink = Some(MirrorSinkKind::File);
+        config.mirror.path = Some(mirror_path);
+        config.mirror.redact_patterns = vec![r"[\w.]+@[\w.]+".to_string()];
+    });
+
+    let body = r#"{"model": "claude-3-opus", "user": "alice", "messages": [{"role": "user", "content": "Email bob@example.com"}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Records are written by a background task
+    let mut contents = String::new();
+    for _ in 0..50 {
+        contents = std::fs::read_to_string(&file).unwrap_or_default();
+        if !contents.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let record: Value = serde_json::from_str(contents.lines().next().expect("a mirrored record"))
+        .expect("Record must be valid JSON");
+    assert_eq!(record["provider"], "anthropic_cli");
+    assert_eq!(record["request"]["model"], "claude-3-opus");
+    assert_ne!(record["request"]["user"], "alice");
+    assert_eq!(
+        record["request"]["messages"][0]["content"],
+        "Email [REDACTED]"
+    );
+    assert_eq!(
+        record["response"]["choices"][0]["message"]["content"],
+        "Replied to [REDACTED]"
+    );
+    assert!(!contents.contains("example.com"));
+
+    let _ = std::fs::remove_file(file);
+}
+
+#[tokio::test]
+async fn test_vertex_stream_usage_is_reported_and_billed() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    let sse = concat!(
+        "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hel\"}]},\"index\":0}],",
+        "\"usageMetadata\":{\"promptTokenCount\":7,\"candidatesTokenCount\":1,\"totalTokenCount\":8}}\n\n",
+        "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"lo\"}]},",
+        "\"finishReason\":\"STOP\",\"index\":0}],",
+        "\"usageMetadata\":{\"promptTokenCount\":7,\"candidatesTokenCount\":2,\"totalTokenCount\":9}}\n\n"
+    );
+    Mock::given(method("POST"))
+        .and(path(format!(
+            "/v1beta/models/{TEST_GEMINI_MODEL}:streamGenerateContent"
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_string(sse))
+        .mount(&vertex)
+        .await;
+
+    let vertex_url = vertex.uri();
+    let server = TestServer::with_config(|config| {
+        config.vertex.api_key = Some("test-api-key".to_string());
+        config.vertex.api_key_base_url = Some(vertex_url);
+    });
+
+    let mut final_usage = Vec::new();
+    for include_usage in [true, false] {
+        let body = serde_json::json!({
+            "model": TEST_GEMINI_MODEL,
+            "messages": [{"role": "user", "content": "hi"}],
+            "stream": true,
+            "stream_options": {"include_usage": include_usage}
+        })
+        .to_string();
+        let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+        let response = server.call(req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read streaming response body");
+        let chunks: Vec<Value> = String::from_utf8_lossy(&body_bytes)
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).expect("SSE data should be valid JSON"))
+            .collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].get("usage").is_none());
+        final_usage.push(chunks[1].get("usage").cloned());
+    }
+    let reported = final_usage[0].as_ref().expect("usage on the final chunk");
+    assert_eq!(reported["prompt_tokens"], 7);
+    assert_eq!(reported["completion_tokens"], 2);
+    assert_eq!(reported["total_tokens"], 9);
+    assert!(final_usage[1].is_none());
+
+    // Both streams are billed, whether or not usage was shown to the client
+    let mut totals = Value::Null;
+    for _ in 0..50 {
+        let req = TestServer::make_request("GET", "/usage", None, None);
+        let response = server.call(req).await;
+        let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read usage response");
+        let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+        totals = json["data"][0].clone();
+        if totals["requests"] == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(totals["model"], TEST_GEMINI_MODEL);
+    assert_eq!(totals["requests"], 2);
+    assert_eq!(totals["prompt_tokens"], 14);
+    assert_eq!(totals["completion_tokens"], 4);
+}
+
+#[tokio::test]
+async fn test_strict_sse_sends_only_completion_chunks() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    let sse = concat!(
+        "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hel\"}]},\"index\":0}]}\n\n",
+        "data: {not json}\n\n",
+        "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"lo\"}]},",
+        "\"finishReason\":\"STOP\",\"index\":0}]}\n\n"
+    );
+    Mock::given(method("POST"))
+        .and(path(format!(
+            "/v1beta/models/{TEST_GEMINI_MODEL}:streamGenerateContent"
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_string(sse))
+        .mount(&vertex)
+        .await;
+
+    let vertex_url = vertex.uri();
+    let server = TestServer::with_config(|config| {
+        config.vertex.api_key = Some("test-api-key".to_string());
+        config.vertex.api_key_base_url = Some(vertex_url);
+        config.sse.strict = true;
+    });
+    let body = serde_json::json!({
+        "model": TEST_GEMINI_MODEL,
+        "messages": [{"role": "user", "content": "hi"}],
+        "stream": true
+    })
+    .to_string();
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read streaming response body");
+    let body = String::from_utf8_lossy(&body_bytes);
+
+    let data: Vec<&str> = body
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.strip_prefix("data: ")
+                .unwrap_or_else(|| panic!("only data lines expected, got {line:?}"))
+        })
+        .collect();
+    assert_eq!(data.last(), Some(&"[DONE]"));
+    for data in &data[..data.len() - 1] {
+        let chunk: Value = serde_json::from_str(data).expect("SSE data should be valid JSON");
+        assert_eq!(
+            chunk["object"], "chat.completion.chunk",
+            "unexpected frame: {data}"
+        );
+    }
+    assert!(data[0].contains("Hel"));
+}
+
+#[tokio::test]
+async fn test_vertex_error_detail_is_exposed_when_enabled() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    let error = serde_json::json!({
+        "error": {
+            "code": 400,
+            "message": "Request contains an invalid argument.",
+            "status": "INVALID_ARGUMENT",
+            "details": [{"reason": "BAD_FIELD", "metadata": {"api_key": "leaked"}}]
+        }
+    });
+    Mock::given(method("POST"))
+        .and(path(format!(
+            "/v1beta/models/{TEST_GEMINI_MODEL}:generateContent"
+        )))
+        .respond_with(ResponseTemplate::new(400).set_body_json(error))
+        .mount(&vertex)
+        .await;
+
+    for provider_detail in [true, false] {
+        let vertex_url = vertex.uri();
+        let server = TestServer::with_config(|config| {
+            config.vertex.api_key = Some("test-api-key".to_string());
+            config.vertex.api_key_base_url = Some(vertex_url);
+            config.errors.provider_detail = provider_detail;
+        });
+        let body = serde_json::json!({
+            "model": TEST_GEMINI_MODEL,
+            "messages": [{"role": "user", "content": "hi"}]
+        })
+        .to_string();
+        let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+        let response = server.call(req).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read error body");
+        let json: Value = serde_json::from_slice(&body_bytes).expect("error body should be JSON");
+
+        if provider_detail {
+            let detail = &json["error"]["provider_detail"];
+            assert_eq!(detail["status"], "INVALID_ARGUMENT");
+            assert_eq!(detail["details"][0]["reason"], "BAD_FIELD");
+            assert_eq!(detail["details"][0]["metadata"]["api_key"], "[REDACTED]");
+        } else {
+            assert!(json["error"].get("provider_detail").is_none());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_idempotency_key_replays_the_first_response() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(format!(
+            "/v1beta/models/{TEST_GEMINI_MODEL}:generateContent"
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Hello"}]},
+                "finishReason": "STOP",
+                "index": 0
+            }],
+            "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 1, "totalTokenCount": 4}
+        })))
+        .expect(1)
+        .mount(&vertex)
+        .await;
+
+    let vertex_url = vertex.uri();
+    let server = TestServer::with_config(|config| {
+        config.vertex.api_key = Some("test-api-key".to_string());
+        config.vertex.api_key_base_url = Some(vertex_url);
+    });
+    let send = |content: &str| {
+        let body = serde_json::json!({
+            "model": TEST_GEMINI_MODEL,
+            "messages": [{"role": "user", "content": content}]
+        })
+        .to_string();
+        let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+        req.headers_mut().insert(
+            "idempotency-key",
+            "order-42".parse().expect("valid header value"),
+        );
+        server.call(req)
+    };
+
+    let first = send("hi").await;
+    assert_eq!(first.status(), StatusCode::OK);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first_body = to_bytes(first.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response body");
+
+    let retry = send("hi").await;
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    let retry_body = to_bytes(retry.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response body");
+    assert_eq!(retry_body, first_body);
+
+    let reused = send("something else").await;
+    assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let json: Value = serde_json::from_slice(
+        &to_bytes(reused.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read error body"),
+    )
+    .expect("error body should be JSON");
+    assert_eq!(json["error"]["code"], "idempotency_key_reused");
+}
+
+#[tokio::test]
+async fn test_conversation_transcript_is_recorded_and_deletable() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(format!(
+            "/v1beta/models/{TEST_GEMINI_MODEL}:generateContent"
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Hello"}]},
+                "finishReason": "STOP",
+                "index": 0
+            }],
+            "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 1, "totalTokenCount": 4}
+        })))
+        .mount(&vertex)
+        .await;
+
+    let vertex_url = vertex.uri();
+    let server = TestServer::with_config(|config| {
+        config.vertex.api_key = Some("test-api-key".to_string());
+        config.vertex.api_key_base_url = Some(vertex_url);
+        config.transcripts.enabled = true;
+    });
+    let body = serde_json::json!({
+        "model": TEST_GEMINI_MODEL,
+        "messages": [{"role": "user", "content": "hi"}]
+    })
+    .to_string();
+    let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    req.headers_mut().insert(
+        "x-session-id",
+        "chat-7".parse().expect("valid header value"),
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response body");
+
+    let req = TestServer::make_request("GET", "/v1/conversations/chat-7", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json: Value = serde_json::from_slice(
+        &to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read transcript"),
+    )
+    .expect("transcript should be JSON");
+    assert_eq!(json["object"], "conversation");
+    assert_eq!(json["messages"][0]["content"], "hi");
+    assert_eq!(json["messages"][1]["role"], "assistant");
+    assert_eq!(json["messages"][1]["content"], "Hello");
+
+    let req = TestServer::make_request("DELETE", "/v1/conversations/chat-7", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::NO_CONTENT);
+    let req = TestServer::make_request("GET", "/v1/conversations/chat-7", None, None);
+    assert_eq!(server.call(req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_missing_usage_is_estimated() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(format!(
+            "/v1beta/models/{TEST_GEMINI_MODEL}:generateContent"
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Hello there"}]},
+                "finishReason": "STOP",
+                "index": 0
+            }]
+        })))
+        .mount(&vertex)
+        .await;
+
+    let vertex_url = vertex.uri();
+    let server = TestServer::with_config(|config| {
+        config.vertex.api_key = Some("test-api-key".to_string());
+        config.vertex.api_key_base_url = Some(vertex_url);
+    });
+    let body = serde_json::json!({
+        "model": TEST_GEMINI_MODEL,
+        "messages": [{"role": "user", "content": "Say hello to me"}]
+    })
+    .to_string();
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json: Value = serde_json::from_slice(
+        &to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read response body"),
+    )
+    .expect("response should be JSON");
+    assert_eq!(json["usage"]["estimated"], true);
+    assert_eq!(json["usage"]["prompt_tokens"], 4);
+    assert_eq!(json["usage"]["completion_tokens"], 2);
+}
+
+#[tokio::test]
+async fn test_prompts_fan_out_to_one_completion_each() {
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    for (prompt, answer) in [("first", "one"), ("second", "two"), ("third", "three")] {
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/v1beta/models/{TEST_GEMINI_MODEL}:generateContent"
+            )))
+            .and(body_string_contains(prompt))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "candidates": [{
+                    "content": {"role": "model", "parts": [{"text": answer}]},
+                    "finishReason": "STOP",
+                    "index": 0
+                }],
+                "usageMetadata": {"promptTokenCount": 5, "candidatesTokenCount": 1, "totalTokenCount": 6}
+            })))
+            .expect(1)
+            .mount(&vertex)
+            .await;
+    }
+
+    let vertex_url = vertex.uri();
+    let server = TestServer::with_config(|config| {
+        config.vertex.api_key = Some("test-api-key".to_string());
+        config.vertex.api_key_base_url = Some(vertex_url);
+        config.limits.max_concurrent_prompts = 2;
+    });
+    let body = serde_json::json!({
+        "model": TEST_GEMINI_MODEL,
+        "messages": [{"role": "system", "content": "Spell out the ordinal."}],
+        "prompts": ["first", "second", "third"]
+    })
+    .to_string();
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json: Value = serde_json::from_slice(
+        &to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read response body"),
+    )
+    .expect("response should be JSON");
+
+    let choices = json["choices"]
+        .as_array()
+        .expect("choices should be an array");
+    let answers: Vec<_> = choices
+        .iter()
+        .map(|c| c["message"]["content"].as_str().unwrap_or_default())
+        .collect();
+    assert_eq!(answers, ["one", "two", "three"]);
+    for (index, choice) in choices.iter().enumerate() {
+        assert_eq!(choice["index"], index);
+    }
+    assert_eq!(json["usage"]["prompt_tokens"], 15);
+    assert_eq!(json["usage"]["total_tokens"], 18);
+
+    let body = serde_json::json!({
+        "model": TEST_GEMINI_MODEL,
+        "prompts": ["first"],
+        "stream": true
+    })
+    .to_string();
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    assert_eq!(server.call(req).await.status(), StatusCode::BAD_REQUEST);
+}
@@ -62,7 +62,12 @@ async fn test_401_error_format() {
     let response = server.call(req).await;
 
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
-    // Auth middleware returns plain 401, not JSON (by design)
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["type"], "authentication_error");
+    assert_eq!(json["error"]["code"], "invalid_api_key");
 }
 
 #[tokio::test]
@@ -73,6 +78,12 @@ async fn test_404_not_found() {
     let response = server.call(req).await;
 
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["type"], "invalid_request_error");
+    assert!(json["error"].get("param").is_some());
 }
 
 #[tokio::test]
@@ -83,6 +94,11 @@ async fn test_malformed_json_400() {
     let response = server.call(req).await;
 
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["code"], "invalid_request");
 }
 
 #[tokio::test]
@@ -116,3 +132,383 @@ async fn test_empty_messages_array() {
         "Empty messages should return 400 or 503, got {status}"
     );
 }
+
+#[tokio::test]
+async fn test_unknown_model_returns_model_not_found() {
+    let server = TestServer::new();
+
+    let request_body = format!(
+        r#"{{"model": "{TEST_INVALID_MODEL}", "messages": [{{"role": "user", "content": "test"}}]}}"#
+    );
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&request_body), None);
+    let response = server.call(req).await;
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["code"], "model_not_found");
+    assert_eq!(json["error"]["param"], "model");
+}
+
+#[tokio::test]
+async fn test_denied_model_returns_403() {
+    let server = TestServer::with_config(|config| {
+        config.model_policy.deny = vec!["gemini-2.5-pro".to_string()];
+    });
+
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions",
+        Some(r#"{"model": "gemini-2.5-pro", "messages": [{"role": "user", "content": "test"}]}"#),
+        None,
+    );
+    let response = server.call(req).await;
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["code"], "model_not_allowed");
+    assert_eq!(json["error"]["param"], "model");
+}
+
+#[tokio::test]
+async fn test_too_many_messages_rejected_before_routing() {
+    let server = TestServer::new();
+
+    let messages = vec![r#"{"role": "user", "content": "hi"}"#; 1001].join(",");
+    let request_body = format!(r#"{{"model": "{TEST_GEMINI_MODEL}", "messages": [{messages}]}}"#);
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&request_body), None);
+    let response = server.call(req).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["param"], "messages");
+    assert!(json["error"]["message"]
+        .as_str()
+        .is_some_and(|m| m.contains("1001 messages")));
+}
+
+#[tokio::test]
+async fn test_prompt_over_context_window_returns_context_length_exceeded() {
+    let server = TestServer::new();
+
+    // gemini-pro has a 32,760 token context window
+    let prompt = "x ".repeat(100_000);
+    let request_body = format!(
+        r#"{{"model": "gemini-pro", "messages": [{{"role": "user", "content": "{prompt}"}}]}}"#
+    );
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&request_body), None);
+    let response = server.call(req).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["code"], "context_length_exceeded");
+}
+
+#[tokio::test]
+async fn test_context_overflow_retries_on_fallback_model() {
+    let server = TestServer::with_config(|config| {
+        config.models.context_fallbacks = vec!["gemini-pro=claude-3-opus".to_string()];
+    });
+
+    // Too long for gemini-pro's 32,760 tokens, within claude-3-opus's window
+    let prompt = "x ".repeat(100_000);
+    let request_body = format!(
+        r#"{{"model": "gemini-pro", "messages": [{{"role": "user", "content": "{prompt}"}}]}}"#
+    );
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&request_body), None);
+    let response = server.call(req).await;
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    assert_eq!(header("x-context-fallback").as_deref(), Some("gemini-pro"));
+    assert_eq!(
+        header("x-routed-provider").as_deref(),
+        Some("anthropic_cli")
+    );
+}
+
+#[tokio::test]
+async fn test_provider_context_rejection_retries_on_fallback_model() {
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let bridge = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .and(body_partial_json(
+            serde_json::json!({"model": "claude-3-5-haiku"}),
+        ))
+        .respond_with(
+            ResponseTemplate::new(400).set_body_string("prompt is too long: 250000 tokens"),
+        )
+        .expect(1)
+        .mount(&bridge)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .and(body_partial_json(
+            serde_json::json!({"model": "claude-3-opus"}),
+        ))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"content": "fits"})),
+        )
+        .expect(1)
+        .mount(&bridge)
+        .await;
+
+    let bridge_url = bridge.uri();
+    let server = TestServer::with_config(|config| {
+        config.anthropic.bridge_url = bridge_url;
+        config.models.context_fallbacks = vec!["claude-3-5-haiku=claude-3-opus".to_string()];
+    });
+    let body = r#"{"model": "claude-3-5-haiku", "messages": [{"role": "user", "content": "hi"}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get("x-context-fallback")
+            .and_then(|v| v.to_str().ok()),
+        Some("claude-3-5-haiku")
+    );
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["model"], "claude-3-opus");
+    assert_eq!(json["choices"][0]["message"]["content"], "fits");
+}
+
+#[tokio::test]
+async fn test_provider_outage_serves_fallback_response() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let bridge = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("bridge down"))
+        .mount(&bridge)
+        .await;
+
+    let file = std::env::temp_dir().join(format!("fallback-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &file,
+        r#"[{"models": ["claude-*"], "message": "Claude is taking a break."}]"#,
+    )
+    .expect("Failed to write fallback responses");
+    let bridge_url = bridge.uri();
+    let fallback_file = file.to_string_lossy().into_owned();
+    let server = TestServer::with_config(|config| {
+        config.anthropic.bridge_url = bridge_url;
+        config.fallback_responses.file = Some(fallback_file);
+    });
+
+    let body = r#"{"model": "claude-3-5-haiku", "messages": [{"role": "user", "content": "hi"}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get("x-fallback-response")
+            .and_then(|v| v.to_str().ok()),
+        Some("true")
+    );
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["model"], "claude-3-5-haiku");
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Claude is taking a break."
+    );
+    assert_eq!(json["choices"][0]["finish_reason"], "error");
+
+    // Request errors still surface as errors
+    let body = r#"{"model": "claude-3-5-haiku", "messages": []}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let _ = std::fs::remove_file(file);
+}
+
+#[tokio::test]
+async fn test_request_timeout_cancels_provider_call() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let bridge = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"content": "too late"}))
+                .set_delay(std::time::Duration::from_secs(30)),
+        )
+        .mount(&bridge)
+        .await;
+
+    let bridge_url = bridge.uri();
+    let server = TestServer::with_config(|config| {
+        config.anthropic.bridge_url = bridge_url;
+        config.server.request_timeout_secs = 1;
+    });
+    let body = r#"{"model": "claude-3-5-haiku", "messages": [{"role": "user", "content": "hi"}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let started = std::time::Instant::now();
+    let response = server.call(req).await;
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert!(json["error"]["message"]
+        .as_str()
+        .is_some_and(|m| m.contains("cancelled")));
+}
+
+#[tokio::test]
+async fn test_endpoint_body_limit_returns_413() {
+    let server = TestServer::with_config(|config| {
+        config.server.body_limits = vec!["/v1/chat/*=512".to_string()];
+    });
+
+    let prompt = "x".repeat(1024);
+    let request_body = format!(
+        r#"{{"model": "{TEST_GEMINI_MODEL}", "messages": [{{"role": "user", "content": "{prompt}"}}]}}"#
+    );
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&request_body), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert!(
+        json.get("error").is_some(),
+        "413 must use the error envelope"
+    );
+
+    // A declared Content-Length over the budget is rejected before the body is read
+    let mut req =
+        TestServer::make_request("POST", "/v1/chat/completions", Some(&request_body), None);
+    req.headers_mut().insert(
+        axum::http::header::CONTENT_LENGTH,
+        request_body
+            .len()
+            .to_string()
+            .parse()
+            .expect("valid header"),
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).expect("gzip write");
+    encoder.finish().expect("gzip finish")
+}
+
+fn gzipped_chat_request(body: &str) -> axum::http::Request<axum::body::Body> {
+    axum::http::Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("content-type", "application/json")
+        .header("content-encoding", "gzip")
+        .body(axum::body::Body::from(gzip(body.as_bytes())))
+        .expect("valid request")
+}
+
+#[tokio::test]
+async fn test_gzip_request_body_accepted() {
+    let server = TestServer::with_config(|config| {
+        config.model_policy.deny = vec!["gemini-2.5-pro".to_string()];
+    });
+
+    // The policy check only fires if the decompressed body parsed
+    let response = server
+        .call(gzipped_chat_request(
+            r#"{"model": "gemini-2.5-pro", "messages": [{"role": "user", "content": "test"}]}"#,
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read error response body");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+    assert_eq!(json["error"]["code"], "model_not_allowed");
+}
+
+#[tokio::test]
+async fn test_decompressed_body_limit_enforced() {
+    let server = TestServer::with_config(|config| {
+        config.server.body_limits = vec!["/v1/chat/completions=4096".to_string()];
+    });
+
+    // A few KB on the wire, a megabyte once inflated
+    let prompt = "x".repeat(1024 * 1024);
+    let request_body = format!(
+        r#"{{"model": "{TEST_GEMINI_MODEL}", "messages": [{{"role": "user", "content": "{prompt}"}}]}}"#
+    );
+    let response = server.call(gzipped_chat_request(&request_body)).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_unsupported_capability_returns_400() {
+    let server = TestServer::with_config(|config| {
+        config.ollama.enabled = true;
+    });
+
+    let image = serde_json::json!({
+        "model": "gpt-4",
+        "messages": [{"role": "user", "content": [
+            {"type": "text", "text": "What is this?"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+        ]}]
+    });
+    let tools = serde_json::json!({
+        "model": "ollama/llama3",
+        "messages": [{"role": "user", "content": "Hi"}],
+        "tools": [{"type": "function", "function": {"name": "f", "parameters": {}}}]
+    });
+    for (body, param) in [(image, "messages"), (tools, "tools")] {
+        let req = TestServer::make_request(
+            "POST",
+            "/v1/chat/completions",
+            Some(&body.to_string()),
+            None,
+        );
+        let response = server.call(req).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+            .await
+            .expect("Failed to read error response body");
+        let json: Value = serde_json::from_slice(&body_bytes).expect("Error response must be JSON");
+        assert_eq!(json["error"]["code"], "unsupported_capability");
+        assert_eq!(json["error"]["param"], param);
+    }
+}
@@ -105,3 +105,310 @@ async fn test_metrics_increment_after_request() {
         );
     }
 }
+
+#[tokio::test]
+async fn test_routing_decision_recorded_and_exposed() {
+    let server = TestServer::new();
+
+    // The bridge isn't running, so the request fails after routing
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions",
+        Some(r#"{"model": "claude-3-opus", "messages": [{"role": "user", "content": "hi"}]}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(
+        response
+            .headers()
+            .get("x-routed-provider")
+            .and_then(|v| v.to_str().ok()),
+        Some("anthropic_cli")
+    );
+
+    let req = TestServer::make_request("GET", "/metrics", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read metrics");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Metrics response not JSON");
+    assert_eq!(
+        json["routing_decisions"],
+        serde_json::json!([{"provider": "anthropic_cli", "reason": "prefix_match", "count": 1}])
+    );
+
+    let req = TestServer::make_request("GET", "/metrics/prometheus", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read prometheus metrics");
+    assert!(String::from_utf8_lossy(&body_bytes)
+        .contains(r#"routing_decisions_total{provider="anthropic_cli",reason="prefix_match"} 1"#));
+}
+
+#[tokio::test]
+async fn test_identical_concurrent_requests_share_one_upstream_call() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let bridge = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"content": "shared"}))
+                .set_delay(std::time::Duration::from_millis(300)),
+        )
+        .expect(1)
+        .mount(&bridge)
+        .await;
+
+    let bridge_url = bridge.uri();
+    let server = TestServer::with_config(|config| config.anthropic.bridge_url = bridge_url);
+    let body = r#"{"model": "claude-3-opus", "messages": [{"role": "user", "content": "hi"}]}"#;
+    let (first, second) = tokio::join!(
+        server.call(TestServer::make_request(
+            "POST",
+            "/v1/chat/completions",
+            Some(body),
+            None
+        )),
+        server.call(TestServer::make_request(
+            "POST",
+            "/v1/chat/completions",
+            Some(body),
+            None
+        )),
+    );
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(second.status(), StatusCode::OK);
+
+    let req = TestServer::make_request("GET", "/metrics", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read metrics");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Metrics response not JSON");
+    assert_eq!(json["coalesced_requests"], 1);
+    assert_eq!(json["total_requests"], 2);
+}
+
+#[tokio::test]
+async fn test_cheapest_strategy_routes_alias_group_across_providers() {
+    let server = TestServer::with_config(|config| {
+        config.models.aliases = vec!["smart=gemini-1.5-pro|claude-3-5-haiku".to_string()];
+    });
+    let body = r#"{"model": "smart", "messages": [{"role": "user", "content": "hi"}]}"#;
+
+    // The bridge isn't running, so requests fail after routing
+    let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    req.headers_mut()
+        .insert("x-routing-strategy", "cheapest".parse().unwrap());
+    let response = server.call(req).await;
+    assert_eq!(
+        response
+            .headers()
+            .get("x-routed-provider")
+            .and_then(|v| v.to_str().ok()),
+        Some("anthropic_cli")
+    );
+
+    let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    req.headers_mut()
+        .insert("x-routing-strategy", "random".parse().unwrap());
+    assert_eq!(server.call(req).await.status(), StatusCode::BAD_REQUEST);
+
+    let req = TestServer::make_request("GET", "/metrics", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read metrics");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Metrics response not JSON");
+    assert_eq!(
+        json["routing_decisions"],
+        serde_json::json!([{"provider": "anthropic_cli", "reason": "cheapest", "count": 1}])
+    );
+}
+
+#[tokio::test]
+async fn test_sticky_session_keeps_alias_group_on_first_provider() {
+    let server = TestServer::with_config(|config| {
+        config.models.aliases = vec!["smart=gemini-1.5-pro|claude-3-5-haiku".to_string()];
+        config.models.sticky_sessions = true;
+    });
+    let body =
+        r#"{"model": "smart", "messages": [{"role": "user", "content": "hi"}], "user": "conv-1"}"#;
+    let routed = |response: &axum::response::Response| {
+        response
+            .headers()
+            .get("x-routed-provider")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+
+    // First turn takes the primary; a later cheapest override must not move it
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    assert_eq!(routed(&server.call(req).await).as_deref(), Some("vertex"));
+
+    let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    req.headers_mut()
+        .insert("x-routing-strategy", "cheapest".parse().unwrap());
+    assert_eq!(routed(&server.call(req).await).as_deref(), Some("vertex"));
+
+    // A different session is free to follow the strategy
+    let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    req.headers_mut()
+        .insert("x-routing-strategy", "cheapest".parse().unwrap());
+    req.headers_mut()
+        .insert("x-session-id", "conv-2".parse().unwrap());
+    assert_eq!(
+        routed(&server.call(req).await).as_deref(),
+        Some("anthropic_cli")
+    );
+}
+
+#[tokio::test]
+async fn test_experiment_variant_applied_and_reported() {
+    let path = std::env::temp_dir().join(format!("experiments-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &path,
+        r#"[{"name": "pro-vs-haiku", "model": "gemini-1.5-pro", "variants": [
+            {"name": "control", "weight": 0},
+            {"name": "haiku", "weight": 1, "model": "claude-3-5-haiku"}
+        ]}]"#,
+    )
+    .expect("Failed to write experiments file");
+    let server = TestServer::with_config(|config| {
+        config.experiments.file = Some(path.to_string_lossy().into_owned());
+    });
+    std::fs::remove_file(&path).ok();
+
+    // The bridge isn't running, so the variant's request fails after routing
+    let body = r#"{"model": "gemini-1.5-pro", "messages": [{"role": "user", "content": "hi"}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(body), None);
+    let response = server.call(req).await;
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    assert_eq!(
+        header("x-routed-provider").as_deref(),
+        Some("anthropic_cli")
+    );
+    assert_eq!(
+        header("x-experiment").as_deref(),
+        Some("pro-vs-haiku=haiku")
+    );
+
+    let req = TestServer::make_request("GET", "/metrics", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read metrics");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Metrics response not JSON");
+    let variant = &json["experiment_variants"][0];
+    assert_eq!(variant["experiment"], "pro-vs-haiku");
+    assert_eq!(variant["variant"], "haiku");
+    assert_eq!(variant["requests"], 1);
+    assert_eq!(variant["failed_requests"], 1);
+
+    let req = TestServer::make_request("GET", "/metrics/prometheus", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read Prometheus metrics");
+    let text = String::from_utf8(body_bytes.to_vec()).expect("Metrics must be UTF-8");
+    assert!(text.contains(
+        "experiment_requests_total{experiment=\"pro_vs_haiku\",variant=\"haiku\",outcome=\"failure\"} 1"
+    ));
+}
+
+#[tokio::test]
+async fn test_upstream_traffic_is_measured_per_provider() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let vertex = MockServer::start().await;
+    let upstream_body = serde_json::json!({
+        "candidates": [{
+            "content": {"role": "model", "parts": [{"text": "Hello"}]},
+            "finishReason": "STOP",
+            "index": 0
+        }]
+    })
+    .to_string();
+    Mock::given(method("POST"))
+        .and(path("/v1beta/models/gemini-2.5-flash:generateContent"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(upstream_body.clone(), "application/json"),
+        )
+        .mount(&vertex)
+        .await;
+
+    let vertex_url = vertex.uri();
+    let server = TestServer::with_config(|config| {
+        config.vertex.api_key = Some("test-api-key".to_string());
+        config.vertex.api_key_base_url = Some(vertex_url);
+    });
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions",
+        Some(r#"{"model": "gemini-2.5-flash", "messages": [{"role": "user", "content": "hi"}]}"#),
+        None,
+    );
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+
+    let sent = vertex
+        .received_requests()
+        .await
+        .expect("requests should be recorded");
+    let req = TestServer::make_request("GET", "/metrics", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read metrics");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Metrics response not JSON");
+    assert_eq!(
+        json["upstream_traffic"],
+        serde_json::json!([{
+            "provider": "vertex",
+            "requests": 1,
+            "request_bytes": sent[0].body.len(),
+            "response_bytes": upstream_body.len()
+        }])
+    );
+
+    let req = TestServer::make_request("GET", "/metrics/prometheus", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read prometheus metrics");
+    let prometheus = String::from_utf8_lossy(&body_bytes);
+    assert!(prometheus.contains(r#"upstream_requests_total{provider="vertex"} 1"#));
+    assert!(prometheus.contains(&format!(
+        r#"upstream_response_bytes_total{{provider="vertex"}} {}"#,
+        upstream_body.len()
+    )));
+}
+
+#[tokio::test]
+async fn test_slo_compliance_is_exposed() {
+    let server = TestServer::with_config(|config| {
+        config.slo.objectives = vec!["claude-3-opus=99.5@5000".to_string()];
+    });
+
+    // The bridge isn't running, so the request fails on the proxy's side
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions",
+        Some(r#"{"model": "claude-3-opus", "messages": [{"role": "user", "content": "hi"}]}"#),
+        None,
+    );
+    let response = server.call(req).await;
+    assert!(response.status().is_server_error());
+
+    let req = TestServer::make_request("GET", "/metrics/prometheus", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read Prometheus metrics");
+    let text = String::from_utf8(body_bytes.to_vec()).expect("Metrics must be UTF-8");
+    assert!(text.contains("slo_target_ratio{model=\"claude_3_opus\"} 0.995"));
+    assert!(text.contains("slo_window_requests{model=\"claude_3_opus\",outcome=\"bad\"} 1"));
+    assert!(text.contains("slo_compliance_ratio{model=\"claude_3_opus\"} 0.0000"));
+    assert!(text.contains("slo_burn_rate{model=\"claude_3_opus\"} 200.0000"));
+}
@@ -0,0 +1,106 @@
+// Model metadata endpoint tests
+
+use super::test_utils::TestServer;
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use vertex_bridge::test_utils::MockVertex;
+
+/// Reasonable body size limit for tests (1MB)
+const TEST_BODY_LIMIT: usize = 1024 * 1024;
+
+async fn count_tokens(server: &TestServer, model: &str, content: &str) -> Value {
+    let body = json!({"model": model, "messages": [{"role": "user", "content": content}]});
+    let req = TestServer::make_request("POST", "/v1/tokens/count", Some(&body.to_string()), None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read token count response");
+    serde_json::from_slice(&body_bytes).expect("Response must be valid JSON")
+}
+
+#[tokio::test]
+async fn test_list_models_returns_openai_list() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/v1/models", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read models response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+
+    assert_eq!(json["object"], "list");
+    let data = json["data"].as_array().expect("data must be an array");
+    let gemini = data
+        .iter()
+        .find(|m| m["id"] == "gemini-1.5-pro")
+        .expect("gemini-1.5-pro should be listed");
+    assert_eq!(gemini["object"], "model");
+    assert!(gemini["context_window"].as_u64().is_some());
+    assert!(gemini["pricing"]["input_per_million"].as_f64().is_some());
+}
+
+#[tokio::test]
+async fn test_get_model_resolves_versioned_id() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/v1/models/claude-3-5-sonnet-20241022", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read model response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["id"], "claude-3-5-sonnet");
+    assert_eq!(json["owned_by"], "anthropic");
+}
+
+#[tokio::test]
+async fn test_get_unknown_model_returns_404() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/v1/models/no-such-model", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_count_tokens_uses_model_family_tokenizer() {
+    let vertex = MockVertex::start().await;
+    vertex.count_tokens("gemini-2.5-flash", 11).await;
+    let server = TestServer::with_config(|config| vertex.configure(config));
+
+    let json = count_tokens(&server, "gpt-4", "Hello there 1234567").await;
+    assert_eq!(json["object"], "tokens.count");
+    assert_eq!(json["tokenizer"], "cl100k_base");
+    assert_eq!(json["prompt_tokens"], 6);
+
+    // Gemini prompts are counted by Vertex
+    let json = count_tokens(&server, "gemini-2.5-flash", "Hello there 1234567").await;
+    assert_eq!(json["tokenizer"], "vertex_count_tokens");
+    assert_eq!(json["prompt_tokens"], 11);
+    let sent = vertex
+        .server()
+        .received_requests()
+        .await
+        .expect("requests should be recorded");
+    assert_eq!(sent.len(), 1);
+    let upstream: Value = serde_json::from_slice(&sent[0].body).expect("Vertex body is JSON");
+    assert_eq!(
+        upstream["generateContentRequest"]["contents"][0]["parts"][0]["text"],
+        "Hello there 1234567"
+    );
+
+    // ...and estimated, one token per digit, when Vertex does not answer
+    let json = count_tokens(&server, "gemini-1.5-pro", "Hello there 1234567").await;
+    assert_eq!(json["tokenizer"], "gemini_approx");
+    assert_eq!(json["prompt_tokens"], 9);
+
+    let req = TestServer::make_request("POST", "/v1/tokens/count", Some("{}"), None);
+    assert_eq!(server.call(req).await.status(), StatusCode::BAD_REQUEST);
+}
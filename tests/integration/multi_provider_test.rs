@@ -24,9 +24,11 @@ async fn test_provider_routing_gemini_to_vertex() {
     let response = server.call(req).await;
 
     // Should route to Vertex provider (may fail without credentials, but routing should work)
-    // Accept either success (if credentials available) or service unavailable (if no credentials)
+    // Accept success (if credentials available), 400 (placeholder API key rejected upstream),
+    // or service unavailable / bad gateway (if Vertex is unreachable)
     assert!(
         response.status() == StatusCode::OK
+            || response.status() == StatusCode::BAD_REQUEST
             || response.status() == StatusCode::SERVICE_UNAVAILABLE
             || response.status() == StatusCode::BAD_GATEWAY
     );
@@ -151,7 +153,14 @@ async fn test_provider_routing_streaming_vs_non_streaming() {
 #[test]
 fn test_provider_routing_logic() {
     // Test routing logic via registry
-    let registry = ProviderRegistry::with_config(&Some("http://localhost:4001".to_string()), &None);
+    let registry = ProviderRegistry::with_config(
+        &Some("http://localhost:4001".to_string()),
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+    );
 
     // Gemini models should route to Vertex
     assert!(registry.route_by_model("gemini-2.5-flash").is_some());
@@ -189,3 +198,54 @@ async fn test_circuit_breaker_with_provider_routing() {
         );
     }
 }
+
+#[tokio::test]
+async fn test_routing_rules_redirect_by_request_content() {
+    let path = std::env::temp_dir().join(format!("routing-rules-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &path,
+        r#"[
+            {"name": "tools-to-claude", "when": {"has_tools": true}, "model": "claude-3-5-haiku"},
+            {"name": "cyrillic", "when": {"language": ["cyrillic"]}, "model": "claude-3-opus"}
+        ]"#,
+    )
+    .expect("Failed to write routing rules");
+    let server = TestServer::with_config(|config| {
+        config.models.rules_file = Some(path.to_string_lossy().into_owned());
+    });
+    std::fs::remove_file(&path).ok();
+    let routed = |response: &axum::response::Response| {
+        response
+            .headers()
+            .get("x-routed-provider")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+
+    // The bridge isn't running, so rule-routed requests fail after routing
+    let with_tools = r#"{"model": "gemini-2.5-flash", "messages": [{"role": "user", "content": "hi"}],
+        "tools": [{"type": "function", "function": {"name": "lookup"}}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(with_tools), None);
+    assert_eq!(
+        routed(&server.call(req).await).as_deref(),
+        Some("anthropic_cli")
+    );
+
+    let russian =
+        r#"{"model": "gemini-2.5-flash", "messages": [{"role": "user", "content": "Привет"}]}"#;
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(russian), None);
+    assert_eq!(
+        routed(&server.call(req).await).as_deref(),
+        Some("anthropic_cli")
+    );
+
+    let req = TestServer::make_request("GET", "/metrics", None, None);
+    let body_bytes = to_bytes(server.call(req).await.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read metrics");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Metrics response not JSON");
+    assert_eq!(
+        json["routing_decisions"],
+        serde_json::json!([{"provider": "anthropic_cli", "reason": "rule", "count": 2}])
+    );
+}
@@ -0,0 +1,740 @@
+// @critical: Provider pipeline tests against mock upstreams
+// These tests run Request -> Handler -> Provider -> Transform -> Response for
+// every provider without credentials, using the `test-utils` fixtures
+
+use super::test_utils::{create_chat_request, create_simple_message, TestServer};
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use serde_json::Value;
+use vertex_bridge::config::RedactionMode;
+use vertex_bridge::test_utils::{
+    MockAnthropicBridge, MockBedrock, MockDeepSeek, MockHarvester, MockOllama,
+    MockOpenAICompatible, MockVertex, MOCK_ACCESS_TOKEN, MOCK_VERTEX_API_KEY,
+};
+
+/// Reasonable body size limit for tests (1MB)
+const TEST_BODY_LIMIT: usize = 1024 * 1024;
+
+async fn chat(server: &TestServer, model: &str, stream: bool) -> axum::response::Response {
+    let body = create_chat_request(model, &create_simple_message("user", "Hello"), stream);
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    server.call(req).await
+}
+
+async fn json_body(response: axum::response::Response) -> Value {
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response body");
+    serde_json::from_slice(&body_bytes).expect("Response must be valid JSON")
+}
+
+/// Content deltas and the final finish reason of a streamed completion.
+async fn stream_body(response: axum::response::Response) -> (String, Option<String>) {
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read streaming response body");
+    let mut content = String::new();
+    let mut finish_reason = None;
+    for data in String::from_utf8_lossy(&body_bytes)
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim)
+        .filter(|data| *data != "[DONE]")
+    {
+        let chunk: Value = serde_json::from_str(data).expect("SSE data should be valid JSON");
+        let choice = &chunk["choices"][0];
+        if let Some(text) = choice["delta"]["content"].as_str() {
+            content.push_str(text);
+        }
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            finish_reason = Some(reason.to_string());
+        }
+    }
+    (content, finish_reason)
+}
+
+#[tokio::test]
+async fn test_vertex_pipeline_against_mock() {
+    let vertex = MockVertex::start().await;
+    vertex.reply("gemini-2.5-flash", "Hello from Vertex").await;
+    vertex
+        .stream("gemini-2.5-flash", &["Hel", "lo ", "stream"])
+        .await;
+    let server = TestServer::with_config(|config| vertex.configure(config));
+
+    let response = chat(&server, "gemini-2.5-flash", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from Vertex"
+    );
+    assert_eq!(json["choices"][0]["finish_reason"], "stop");
+    assert_eq!(json["usage"]["total_tokens"], 2);
+
+    let response = chat(&server, "gemini-2.5-flash", true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        stream_body(response).await,
+        ("Hello stream".to_string(), Some("stop".to_string()))
+    );
+
+    let sent = vertex
+        .server()
+        .received_requests()
+        .await
+        .expect("requests should be recorded");
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|req| req
+        .url
+        .query()
+        .is_some_and(|q| q.contains(MOCK_VERTEX_API_KEY))));
+    let upstream: Value = serde_json::from_slice(&sent[0].body).expect("Vertex body is JSON");
+    assert_eq!(upstream["contents"][0]["parts"][0]["text"], "Hello");
+}
+
+#[tokio::test]
+async fn test_vertex_errors_against_mock() {
+    let vertex = MockVertex::start().await;
+    vertex
+        .fail(
+            "gemini-2.5-flash",
+            400,
+            "Request contains an invalid argument.",
+        )
+        .await;
+    let server = TestServer::with_config(|config| vertex.configure(config));
+
+    for stream in [false, true] {
+        let response = chat(&server, "gemini-2.5-flash", stream).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let json = json_body(response).await;
+        assert!(json["error"]["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn test_vertex_error_text_is_redacted() {
+    let vertex = MockVertex::start().await;
+    vertex
+        .fail(
+            "gemini-2.5-flash",
+            400,
+            "Unsupported input: my secret prompt",
+        )
+        .await;
+    let server = TestServer::with_config(|config| {
+        vertex.configure(config);
+        config.redaction.mode = RedactionMode::Full;
+        config.errors.provider_detail = true;
+    });
+
+    let json = json_body(chat(&server, "gemini-2.5-flash", false).await).await;
+    let message = json["error"]["message"].as_str().unwrap_or_default();
+    assert!(message.contains("[redacted"), "{message}");
+    assert!(!message.contains("secret"), "{message}");
+    assert_eq!(json["error"]["provider_detail"]["code"], 400);
+    assert_eq!(
+        json["error"]["provider_detail"]["message"],
+        "[redacted 35 chars]"
+    );
+}
+
+#[tokio::test]
+async fn test_anthropic_pipeline_against_mock() {
+    let bridge = MockAnthropicBridge::start().await;
+    bridge.reply("Hello from Claude").await;
+    bridge
+        .stream("claude-3-5-sonnet", &["Hel", "lo ", "stream"])
+        .await;
+    let server = TestServer::with_config(|config| bridge.configure(config));
+
+    let response = chat(&server, "claude-3-5-sonnet", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from Claude"
+    );
+    assert_eq!(json["choices"][0]["finish_reason"], "stop");
+
+    let response = chat(&server, "claude-3-5-sonnet", true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        stream_body(response).await,
+        ("Hello stream".to_string(), Some("stop".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_anthropic_errors_against_mock() {
+    let bridge = MockAnthropicBridge::start().await;
+    bridge.fail(429, "rate limited by Anthropic").await;
+    let server = TestServer::with_config(|config| bridge.configure(config));
+
+    for stream in [false, true] {
+        let response = chat(&server, "claude-3-5-sonnet", stream).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let json = json_body(response).await;
+        assert!(json["error"]["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn test_deepseek_pipeline_against_mock() {
+    let deepseek = MockDeepSeek::start().await;
+    deepseek.reply("deepseek-chat", "Hello from DeepSeek").await;
+    deepseek
+        .stream("deepseek-chat", &["Hel", "lo ", "stream"])
+        .await;
+    let server = TestServer::with_config(|config| deepseek.configure(config));
+
+    let response = chat(&server, "deepseek-chat", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from DeepSeek"
+    );
+    assert_eq!(json["usage"]["total_tokens"], 2);
+
+    let response = chat(&server, "deepseek-chat", true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let (content, finish_reason) = stream_body(response).await;
+    assert_eq!(content, "Hello stream");
+    assert_eq!(finish_reason.as_deref(), Some("stop"));
+}
+
+#[tokio::test]
+async fn test_deepseek_errors_against_mock() {
+    let deepseek = MockDeepSeek::start().await;
+    deepseek.fail(400, "Invalid max_tokens value").await;
+    let server = TestServer::with_config(|config| deepseek.configure(config));
+
+    let response = chat(&server, "deepseek-chat", false).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let json = json_body(response).await;
+    assert!(json["error"]["message"]
+        .as_str()
+        .is_some_and(|m| m.contains("Invalid max_tokens value")));
+}
+
+#[tokio::test]
+async fn test_openai_compatible_pipeline_against_mock() {
+    let upstream = MockOpenAICompatible::start().await;
+    upstream
+        .reply("meta-llama/llama-3.1-70b-instruct", "Hello from OpenRouter")
+        .await;
+    upstream
+        .stream("gpt-4o-mini", &["Hel", "lo ", "stream"])
+        .await;
+    // Listed models take precedence over the built-in `gpt-` routing
+    let server = TestServer::with_config(|config| {
+        upstream.configure(config, &["meta-llama/*", "gpt-4o-mini"]);
+    });
+
+    let response = chat(&server, "meta-llama/llama-3.1-70b-instruct", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from OpenRouter"
+    );
+    assert_eq!(json["usage"]["total_tokens"], 2);
+
+    let response = chat(&server, "gpt-4o-mini", true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let (content, finish_reason) = stream_body(response).await;
+    assert_eq!(content, "Hello stream");
+    assert_eq!(finish_reason.as_deref(), Some("stop"));
+}
+
+#[tokio::test]
+async fn test_openai_compatible_forwards_unknown_fields() {
+    let upstream = MockOpenAICompatible::start().await;
+    upstream
+        .reply("meta-llama/llama-3.1-70b-instruct", "{}")
+        .await;
+    let server = TestServer::with_config(|config| {
+        upstream.configure(config, &["meta-llama/*"]);
+    });
+
+    let body = serde_json::json!({
+        "model": "meta-llama/llama-3.1-70b-instruct",
+        "messages": [{"role": "user", "content": "Hello"}],
+        "response_format": {"type": "json_object"},
+        "seed": 42
+    })
+    .to_string();
+    let req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    assert_eq!(server.call(req).await.status(), StatusCode::OK);
+
+    let sent = upstream
+        .server()
+        .received_requests()
+        .await
+        .expect("requests should be recorded");
+    let chat_request = sent
+        .iter()
+        .find(|req| req.url.path().ends_with("/chat/completions"))
+        .expect("completion should reach the upstream");
+    let upstream_body: Value =
+        serde_json::from_slice(&chat_request.body).expect("upstream body is JSON");
+    assert_eq!(
+        upstream_body["response_format"],
+        serde_json::json!({"type": "json_object"})
+    );
+    assert_eq!(upstream_body["seed"], 42);
+}
+
+#[tokio::test]
+async fn test_openai_compatible_errors_against_mock() {
+    let upstream = MockOpenAICompatible::start().await;
+    upstream
+        .fail("mistralai/mixtral-8x7b", 429, "Rate limit exceeded")
+        .await;
+    let server = TestServer::with_config(|config| {
+        upstream.configure(config, &["mistralai/mixtral-8x7b"]);
+    });
+
+    let response = chat(&server, "mistralai/mixtral-8x7b", false).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let json = json_body(response).await;
+    assert!(json["error"]["message"]
+        .as_str()
+        .is_some_and(|m| m.contains("Rate limit exceeded")));
+}
+
+const BEDROCK_CLAUDE: &str = "anthropic.claude-3-haiku-20240307-v1:0";
+
+#[tokio::test]
+async fn test_bedrock_pipeline_against_mock() {
+    let bedrock = MockBedrock::start().await;
+    bedrock.reply(BEDROCK_CLAUDE, "Hello from Bedrock").await;
+    bedrock
+        .stream("amazon.titan-text-express-v1", &["Hel", "lo ", "stream"])
+        .await;
+    let server = TestServer::with_config(|config| bedrock.configure(config));
+
+    let response = chat(&server, BEDROCK_CLAUDE, false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from Bedrock"
+    );
+    assert_eq!(json["choices"][0]["finish_reason"], "stop");
+    assert_eq!(json["usage"]["total_tokens"], 2);
+
+    let response = chat(&server, "amazon.titan-text-express-v1", true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let (content, finish_reason) = stream_body(response).await;
+    assert_eq!(content, "Hello stream");
+    assert_eq!(finish_reason.as_deref(), Some("stop"));
+}
+
+#[tokio::test]
+async fn test_bedrock_errors_against_mock() {
+    let bedrock = MockBedrock::start().await;
+    bedrock
+        .fail(
+            BEDROCK_CLAUDE,
+            429,
+            "ThrottlingException",
+            "Too many requests, please wait before trying again.",
+        )
+        .await;
+    let server = TestServer::with_config(|config| bedrock.configure(config));
+
+    for stream in [false, true] {
+        let response = chat(&server, BEDROCK_CLAUDE, stream).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let json = json_body(response).await;
+        assert!(json["error"]["message"]
+            .as_str()
+            .is_some_and(|m| m.contains("ThrottlingException")));
+    }
+}
+
+#[tokio::test]
+async fn test_ollama_pipeline_against_mock() {
+    let ollama = MockOllama::start().await;
+    ollama.reply("Hello from Ollama").await;
+    ollama.stream(&["Hel", "lo ", "stream"]).await;
+    let server = TestServer::with_config(|config| ollama.configure(config));
+
+    let response = chat(&server, "ollama/llama3.1", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from Ollama"
+    );
+    assert_eq!(json["model"], "ollama/llama3.1");
+    assert_eq!(json["usage"]["total_tokens"], 2);
+
+    let response = chat(&server, "ollama/llama3.1", true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let (content, finish_reason) = stream_body(response).await;
+    assert_eq!(content, "Hello stream");
+    assert_eq!(finish_reason.as_deref(), Some("stop"));
+
+    let requests = ollama.server().received_requests().await.unwrap();
+    let chat_request = requests
+        .iter()
+        .find(|r| r.url.path() == "/api/chat")
+        .expect("Ollama should have received a chat request");
+    let sent: Value = serde_json::from_slice(&chat_request.body).unwrap();
+    assert_eq!(sent["model"], "llama3.1");
+}
+
+#[tokio::test]
+async fn test_harvester_pipeline_against_mock() {
+    let harvester = MockHarvester::start().await;
+    harvester.stream(&["Hel", "lo ", "stream"]).await;
+    let server = TestServer::with_config(|config| harvester.configure(config));
+
+    let response = chat(&server, "gpt-4o-mini", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(json["choices"][0]["message"]["content"], "Hello stream");
+    assert_eq!(json["choices"][0]["finish_reason"], "stop");
+
+    let response = chat(&server, "gpt-4o-mini", true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        stream_body(response).await,
+        ("Hello stream".to_string(), Some("stop".to_string()))
+    );
+
+    let sent = harvester
+        .server()
+        .received_requests()
+        .await
+        .expect("requests should be recorded");
+    let conversation = sent
+        .iter()
+        .find(|req| req.url.path() == "/backend-api/conversation")
+        .expect("backend should be called");
+    assert_eq!(
+        conversation.headers["authorization"],
+        format!("Bearer {MOCK_ACCESS_TOKEN}").as_str()
+    );
+    let upstream: Value = serde_json::from_slice(&conversation.body).expect("backend body is JSON");
+    assert_eq!(upstream["model"], "gpt-4o-mini");
+    assert_eq!(upstream["messages"][0]["content"]["parts"][0], "Hello");
+}
+
+#[tokio::test]
+async fn test_harvester_stop_and_max_tokens_against_mock() {
+    let harvester = MockHarvester::start().await;
+    harvester.stream(&["one two E", "ND three", " four"]).await;
+    let server = TestServer::with_config(|config| harvester.configure(config));
+    let limited = |params: Value, stream: bool| {
+        let mut body = serde_json::json!({
+            "model": "gpt-4o-mini",
+            "messages": [{"role": "user", "content": "Count"}],
+            "stream": stream,
+        });
+        if let (Some(body), Some(params)) = (body.as_object_mut(), params.as_object()) {
+            body.extend(params.clone());
+        }
+        TestServer::make_request(
+            "POST",
+            "/v1/chat/completions",
+            Some(&body.to_string()),
+            None,
+        )
+    };
+
+    let response = server
+        .call(limited(serde_json::json!({"stop": "END"}), false))
+        .await;
+    let json = json_body(response).await;
+    assert_eq!(json["choices"][0]["message"]["content"], "one two ");
+    assert_eq!(json["choices"][0]["finish_reason"], "stop");
+
+    let response = server
+        .call(limited(serde_json::json!({"stop": ["END"]}), true))
+        .await;
+    assert_eq!(
+        stream_body(response).await,
+        ("one two ".to_string(), Some("stop".to_string()))
+    );
+
+    // One token is estimated at four characters
+    let response = server
+        .call(limited(serde_json::json!({"max_tokens": 1}), true))
+        .await;
+    assert_eq!(
+        stream_body(response).await,
+        ("one ".to_string(), Some("length".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_harvester_tokens_stay_bound_to_session() {
+    let harvester = MockHarvester::start().await;
+    harvester.stream(&["Hello"]).await;
+    let server = TestServer::with_config(|config| harvester.configure(config));
+    let turn = |session: Option<&str>| {
+        let body = create_chat_request("gpt-4o-mini", &create_simple_message("user", "Hi"), true);
+        let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+        if let Some(session) = session {
+            req.headers_mut()
+                .insert("x-session-id", session.parse().expect("valid header"));
+        }
+        req
+    };
+    let token_fetches = || async {
+        harvester
+            .server()
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter(|r| r.url.path() == "/tokens")
+            .count()
+    };
+
+    for _ in 0..3 {
+        let response = server.call(turn(Some("conv-a"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        stream_body(response).await;
+    }
+    // Later turns reuse the tokens the conversation started with
+    assert_eq!(token_fetches().await, 1);
+
+    server.call(turn(Some("conv-b"))).await;
+    server.call(turn(None)).await;
+    assert_eq!(token_fetches().await, 3);
+}
+
+#[tokio::test]
+async fn test_harvester_errors_against_mock() {
+    let harvester = MockHarvester::start().await;
+    harvester.fail(403, "Just a moment...").await;
+    let server = TestServer::with_config(|config| harvester.configure(config));
+    let response = chat(&server, "gpt-4o-mini", false).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let harvester = MockHarvester::start().await;
+    harvester.fail_tokens(503).await;
+    let server = TestServer::with_config(|config| harvester.configure(config));
+    let response = chat(&server, "gpt-4o-mini", true).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let json = json_body(response).await;
+    assert!(json["error"]["message"]
+        .as_str()
+        .is_some_and(|m| m.contains("Harvester unavailable")));
+}
+
+#[tokio::test]
+async fn test_credential_refresh_against_mock() {
+    let harvester = MockHarvester::start().await;
+    let server = TestServer::with_config(|config| harvester.configure(config));
+    let req = TestServer::make_request("POST", "/admin/credentials/refresh", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(json["harvester_refreshed"], true);
+    assert_eq!(json["waf_cooldowns_cleared"], 0);
+    assert_eq!(json["token_bindings_cleared"], 0);
+
+    let sent = harvester
+        .server()
+        .received_requests()
+        .await
+        .expect("requests should be recorded");
+    let refresh = sent
+        .iter()
+        .find(|req| req.url.path() == "/refresh")
+        .expect("harvester should be asked for a new session");
+    let body: Value = serde_json::from_slice(&refresh.body).expect("refresh body is JSON");
+    assert_eq!(body["force_arkose"], true);
+
+    harvester.fail_tokens(503).await;
+    let req = TestServer::make_request("POST", "/admin/credentials/refresh", None, None);
+    let json = json_body(server.call(req).await).await;
+    assert_eq!(json["harvester_refreshed"], false);
+    assert!(json["harvester_error"].is_string());
+}
+
+#[tokio::test]
+async fn test_model_discovery_against_mock() {
+    let vertex = MockVertex::start().await;
+    vertex
+        .list_models(&["gemini-3-pro-preview", "text-embedding-004"])
+        .await;
+    let server = TestServer::with_config(|config| {
+        vertex.configure(config);
+        config.model_discovery.enabled = true;
+    });
+    let list_requests = || async {
+        vertex
+            .server()
+            .received_requests()
+            .await
+            .expect("requests should be recorded")
+            .iter()
+            .filter(|req| req.url.path() == "/v1beta/models")
+            .count()
+    };
+
+    for _ in 0..2 {
+        let req = TestServer::make_request("GET", "/v1/models", None, None);
+        let json = json_body(server.call(req).await).await;
+        let ids: Vec<&str> = json["data"]
+            .as_array()
+            .expect("data should be a list")
+            .iter()
+            .filter_map(|m| m["id"].as_str())
+            .collect();
+        assert!(ids.contains(&"gemini-3-pro-preview"));
+        assert!(ids.contains(&"claude-sonnet-4"));
+        assert!(!ids.contains(&"text-embedding-004"));
+    }
+    assert_eq!(list_requests().await, 1);
+
+    let req = TestServer::make_request("GET", "/v1/models/gemini-3-pro-preview", None, None);
+    let json = json_body(server.call(req).await).await;
+    assert_eq!(json["context_window"], 1_048_576);
+
+    let req = TestServer::make_request("POST", "/admin/models/refresh", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["vertex_models"], 1);
+    assert_eq!(list_requests().await, 2);
+}
+
+#[tokio::test]
+async fn test_debug_breakdown_against_mock() {
+    let vertex = MockVertex::start().await;
+    vertex.reply("gemini-2.5-flash", "Hello from Vertex").await;
+    let server = TestServer::with_config(|config| vertex.configure(config));
+
+    let body = create_chat_request(
+        "gemini-2.5-flash",
+        &create_simple_message("user", "Hello"),
+        false,
+    );
+    let mut req = TestServer::make_request("POST", "/v1/chat/completions", Some(&body), None);
+    req.headers_mut()
+        .insert("x-debug", "true".parse().expect("valid header"));
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from Vertex"
+    );
+    let debug = &json["debug"];
+    assert_eq!(debug["requested_model"], "gemini-2.5-flash");
+    assert_eq!(debug["route"]["provider"], "vertex");
+    assert_eq!(debug["attempts"][0]["status"], 200);
+    assert_eq!(debug["retries"], 0);
+    assert!(debug["cache"].is_string());
+    assert!(debug["timings_ms"]["upstream"].is_u64());
+
+    let json = json_body(chat(&server, "gemini-2.5-flash", false).await).await;
+    assert!(json.get("debug").is_none());
+}
+
+#[tokio::test]
+async fn test_watermark_against_mock() {
+    let vertex = MockVertex::start().await;
+    vertex.reply("gemini-2.5-flash", "Hello from Vertex").await;
+    let server = TestServer::with_config(|config| {
+        vertex.configure(config);
+        config.watermark.header = Some("x-served-by".to_string());
+        config.watermark.body_field = Some("x_served_by".to_string());
+        config.watermark.instance = Some("test-1".to_string());
+    });
+
+    let response = chat(&server, "gemini-2.5-flash", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let header = response.headers()["x-served-by"]
+        .to_str()
+        .expect("watermark should be ASCII")
+        .to_string();
+    assert!(header.starts_with("instance=test-1; provider=vertex; model="));
+    let json = json_body(response).await;
+    assert_eq!(json["x_served_by"]["instance"], "test-1");
+    assert_eq!(json["x_served_by"]["provider"], "vertex");
+    assert_eq!(
+        json["choices"][0]["message"]["content"],
+        "Hello from Vertex"
+    );
+}
+
+#[tokio::test]
+async fn test_upstream_headers_forwarded_against_mock() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, ResponseTemplate};
+
+    let bridge = MockAnthropicBridge::start().await;
+    Mock::given(method("POST"))
+        .and(path("/anthropic/complete"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("request-id", "req_mock_123")
+                .insert_header("x-internal-token", "secret")
+                .set_body_json(serde_json::json!({"content": "Hi", "finish_reason": "end_turn"})),
+        )
+        .mount(bridge.server())
+        .await;
+    let server = TestServer::with_config(|config| {
+        bridge.configure(config);
+        config.upstream_headers.forward = vec!["request-id".to_string()];
+    });
+
+    let response = chat(&server, "claude-3-5-sonnet", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers["x-upstream-request-id"], "req_mock_123");
+    assert!(!headers.contains_key("x-upstream-x-internal-token"));
+}
+
+#[tokio::test]
+async fn test_dry_run_plans_without_calling_provider() {
+    let bridge = MockAnthropicBridge::start().await;
+    bridge.reply("Hello from Claude").await;
+    let server = TestServer::with_config(|config| {
+        bridge.configure(config);
+        config.models.aliases = vec!["smart=claude-3-5-sonnet".to_string()];
+    });
+
+    let body = r#"{"model": "smart", "messages": [{"role": "user", "content": "Hello there"}], "max_tokens": 100}"#;
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions?dry_run=true",
+        Some(body),
+        None,
+    );
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(json["object"], "chat.completion.plan");
+    assert_eq!(json["model"], "claude-3-5-sonnet");
+    assert_eq!(json["provider"], "anthropic_cli");
+    assert_eq!(json["tokenizer"], "claude_approx");
+    assert_eq!(json["prompt_tokens"], 4);
+    assert_eq!(json["max_tokens"], 100);
+    assert!(json["max_cost_usd"].as_f64().is_some_and(|cost| cost > 0.0));
+
+    let body = r#"{"model": "no-such-model", "messages": [{"role": "user", "content": "Hi"}]}"#;
+    let req = TestServer::make_request(
+        "POST",
+        "/v1/chat/completions?dry_run=true",
+        Some(body),
+        None,
+    );
+    assert_eq!(server.call(req).await.status(), StatusCode::NOT_FOUND);
+
+    let sent = bridge
+        .server()
+        .received_requests()
+        .await
+        .expect("requests should be recorded");
+    assert!(sent.is_empty());
+}
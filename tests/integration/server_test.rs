@@ -0,0 +1,257 @@
+// Embedding the proxy in another axum app through `Server::builder`
+
+use super::test_utils::TestServer;
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower::ServiceExt;
+use vertex_bridge::server::{initialize_state, Server};
+use vertex_bridge::test_utils::{MockAnthropicBridge, MockHarvester, MockVertex};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Reasonable body size limit for tests (1MB)
+const TEST_BODY_LIMIT: usize = 1024 * 1024;
+
+async fn get(app: &axum::Router, uri: &str) -> (StatusCode, Vec<u8>) {
+    let req = Request::builder()
+        .uri(uri)
+        .body(Body::empty())
+        .expect("valid request");
+    let response = app.clone().oneshot(req).await.expect("infallible");
+    let status = response.status();
+    let body = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read response body");
+    (status, body.to_vec())
+}
+
+#[tokio::test]
+async fn test_server_mounted_under_prefix() {
+    let config = TestServer::create_test_config(false, "");
+    let server = Server::builder(config)
+        .path_prefix("/llm")
+        .background_tasks(false)
+        .build()
+        .await
+        .expect("server should build");
+    let host = axum::Router::new()
+        .route("/", axum::routing::get(|| async { "host app" }))
+        .merge(server.into_router());
+
+    let (status, body) = get(&host, "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"host app");
+
+    let (status, body) = get(&host, "/llm/v1/models").await;
+    assert_eq!(status, StatusCode::OK);
+    let json: Value = serde_json::from_slice(&body).expect("model list must be JSON");
+    assert_eq!(json["object"], "list");
+
+    let (status, _) = get(&host, "/v1/models").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_server_uses_injected_state() {
+    let config = TestServer::create_test_config(false, "");
+    let state = initialize_state(&config)
+        .await
+        .expect("state should initialize");
+    let metrics = Arc::clone(&state.metrics);
+
+    let server = Server::builder(config)
+        .state(state)
+        .background_tasks(false)
+        .build()
+        .await
+        .expect("server should build");
+    assert!(Arc::ptr_eq(&server.state().metrics, &metrics));
+}
+
+#[tokio::test]
+async fn test_ready_after_parallel_warmup() {
+    let vertex = MockVertex::start().await;
+    let harvester = MockHarvester::start().await;
+    let bridge = MockAnthropicBridge::start().await;
+    let mut config = TestServer::create_test_config(false, "");
+    vertex.configure(&mut config);
+    harvester.configure(&mut config);
+    bridge.configure(&mut config);
+    config.warmup.enabled = true;
+    let app = Server::builder(config)
+        .background_tasks(false)
+        .build()
+        .await
+        .expect("server should build")
+        .into_router();
+
+    let mut ready = None;
+    for _ in 0..50 {
+        let (status, body) = get(&app, "/health/ready").await;
+        if status == StatusCode::OK {
+            ready = Some(body);
+            break;
+        }
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let body = ready.expect("warm-up should finish");
+    let json: Value = serde_json::from_slice(&body).expect("readiness must be JSON");
+    assert_eq!(json["ready"], true);
+    let steps = json["warmup"]["steps"].as_array().expect("warm-up steps");
+    let names: Vec<&str> = steps.iter().filter_map(|s| s["name"].as_str()).collect();
+    assert_eq!(
+        names,
+        ["vertex_token", "harvester_tokens", "anthropic_bridge"]
+    );
+    assert!(steps.iter().all(|s| s["ok"] == true), "{steps:?}");
+}
+
+#[tokio::test]
+async fn test_invalid_path_prefix_rejected() {
+    let config = TestServer::create_test_config(false, "");
+    for prefix in ["llm", "/"] {
+        let result = Server::builder(config.clone())
+            .path_prefix(prefix)
+            .background_tasks(false)
+            .build()
+            .await;
+        assert!(result.is_err(), "accepted prefix {prefix:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_openapi_document_is_public() {
+    let config = TestServer::create_test_config(true, "test-key");
+    let server = Server::builder(config)
+        .background_tasks(false)
+        .build()
+        .await
+        .expect("server should build");
+    let app = server.into_router();
+
+    let (status, body) = get(&app, "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    let doc: Value = serde_json::from_slice(&body).expect("OpenAPI document must be JSON");
+    assert!(doc["openapi"]
+        .as_str()
+        .is_some_and(|v| v.starts_with("3.1")));
+    for path in [
+        "/v1/chat/completions",
+        "/v1/models/{model_id}",
+        "/usage",
+        "/admin/maintenance",
+    ] {
+        assert!(doc["paths"].get(path).is_some(), "missing path {path}");
+    }
+    for schema in [
+        "ChatCompletionRequest",
+        "ChatCompletionResponse",
+        "OpenAIError",
+    ] {
+        assert!(
+            doc["components"]["schemas"].get(schema).is_some(),
+            "missing schema {schema}"
+        );
+    }
+    assert_eq!(
+        doc["paths"]["/health"]["get"]["security"],
+        serde_json::json!([{}])
+    );
+}
+
+#[cfg(feature = "swagger-ui")]
+#[tokio::test]
+async fn test_swagger_ui_served_with_own_csp() {
+    let config = TestServer::create_test_config(true, "test-key");
+    let app = Server::builder(config)
+        .background_tasks(false)
+        .build()
+        .await
+        .expect("server should build")
+        .into_router();
+
+    let req = Request::builder()
+        .uri("/docs/")
+        .body(Body::empty())
+        .expect("valid request");
+    let response = app.oneshot(req).await.expect("infallible");
+    assert_eq!(response.status(), StatusCode::OK);
+    let csp = response
+        .headers()
+        .get("content-security-policy")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    assert!(
+        csp.starts_with("default-src 'self'"),
+        "unexpected CSP {csp}"
+    );
+}
+
+#[tokio::test]
+async fn test_keepalive_padding_is_not_compressed() {
+    const UPSTREAM_DELAY: Duration = Duration::from_secs(3);
+    let vertex = MockVertex::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1beta/models/gemini-2.5-flash:generateContent"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "candidates": [{
+                        "content": {"role": "model", "parts": [{"text": "late"}]},
+                        "finishReason": "STOP",
+                        "index": 0
+                    }]
+                }))
+                .set_delay(UPSTREAM_DELAY),
+        )
+        .mount(vertex.server())
+        .await;
+    let mut config = TestServer::create_test_config(false, "");
+    vertex.configure(&mut config);
+    config.keepalive.after_secs = 1;
+    config.keepalive.interval_secs = 1;
+    let app = Server::builder(config)
+        .background_tasks(false)
+        .build()
+        .await
+        .expect("server should build")
+        .into_router();
+
+    let body = json!({
+        "model": "gemini-2.5-flash",
+        "messages": [{"role": "user", "content": "Hello"}]
+    });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::from(body.to_string()))
+        .expect("valid request");
+    let started = Instant::now();
+    let response = app.oneshot(req).await.expect("infallible");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+
+    // Padding reaches the client while the upstream is still working
+    let mut frames = response.into_body().into_data_stream();
+    let first = frames
+        .next()
+        .await
+        .expect("body should not be empty")
+        .expect("body should be readable");
+    assert!(started.elapsed() < UPSTREAM_DELAY);
+    assert!(first.iter().all(|b| *b == b' '));
+
+    let mut rest = first.to_vec();
+    while let Some(frame) = frames.next().await {
+        rest.extend_from_slice(&frame.expect("body should be readable"));
+    }
+    let json: Value = serde_json::from_slice(&rest).expect("padded body must be JSON");
+    assert_eq!(json["choices"][0]["message"]["content"], "late");
+}
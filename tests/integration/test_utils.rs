@@ -53,13 +53,34 @@ use vertex_bridge::config::{
     AnthropicConfig, AppConfig, AuthConfig, CacheConfig, CircuitBreakerConfig, LogConfig,
     OpenAIConfig, RateLimitConfig, ServerConfig, VertexConfig,
 };
-use vertex_bridge::handlers::{chat, health, metrics};
-use vertex_bridge::middleware::{auth::auth_middleware, rate_limit::RateLimiter};
+use vertex_bridge::handlers::{
+    admin, chat, conversations, health, metrics, models, openapi, status, tokens, usage,
+};
+use vertex_bridge::middleware::{
+    auth::{admin_middleware, auth_middleware},
+    body_limit::{body_limit_middleware, BodyLimits},
+    decompression::decompression_middleware,
+    maintenance_mode::maintenance_mode_middleware,
+    priority::priority_middleware,
+    rate_limit::RateLimiter,
+    trace_context::trace_context_middleware,
+};
 use vertex_bridge::openai::circuit_breaker::CircuitBreaker;
 use vertex_bridge::openai::metrics::Metrics;
 use vertex_bridge::services::auth::TokenManager;
 use vertex_bridge::services::cache::Cache;
+use vertex_bridge::services::experiments::Experiments;
+use vertex_bridge::services::fallback_responses::FallbackResponses;
+use vertex_bridge::services::idempotency::IdempotencyStore;
+use vertex_bridge::services::mirror::Mirror;
+use vertex_bridge::services::model_catalog::ModelCatalog;
+use vertex_bridge::services::model_registry::ModelRegistry;
+use vertex_bridge::services::post_processor::PostProcessor;
 use vertex_bridge::services::providers::ProviderRegistry;
+use vertex_bridge::services::routing_rules::RoutingRules;
+use vertex_bridge::services::slo::Slos;
+use vertex_bridge::services::transcripts::Transcripts;
+use vertex_bridge::services::watermark::Watermark;
 use vertex_bridge::state::AppState;
 
 pub struct TestServer {
@@ -71,7 +92,7 @@ impl TestServer {
         Self::with_auth(false, "")
     }
 
-    fn create_test_config(require_auth: bool, master_key: &str) -> AppConfig {
+    pub fn create_test_config(require_auth: bool, master_key: &str) -> AppConfig {
         // Use real credentials from env if available, otherwise use fake for unit tests
         let api_key = std::env::var("VERTEX_API_KEY").ok();
         let credentials_file = std::env::var("GOOGLE_APPLICATION_CREDENTIALS").ok();
@@ -85,6 +106,10 @@ impl TestServer {
                 host: "127.0.0.1".to_string(),
                 port: 0,                            // Let OS assign port
                 max_request_size: 10 * 1024 * 1024, // 10MB
+                request_timeout_secs: 0,
+                shutdown_grace_secs: 30,
+                body_limits: Vec::new(),
+                reuse_port: false,
             },
             auth: AuthConfig {
                 require_auth,
@@ -97,6 +122,7 @@ impl TestServer {
                 credentials_file,
                 api_key_base_url: None,
                 oauth_base_url: None,
+                generation: Default::default(),
             },
             log: LogConfig {
                 level: "error".to_string(), // Quiet during tests
@@ -104,18 +130,26 @@ impl TestServer {
             },
             openai: OpenAIConfig {
                 harvester_url: "http://localhost:3001".to_string(),
+                backend_url: None,
                 access_token_ttl_secs: 3600,
                 arkose_token_ttl_secs: 120,
+                waf_cooldown: Default::default(),
             },
             anthropic: AnthropicConfig {
                 bridge_url: "http://localhost:4001".to_string(),
+                health_check_interval_secs: 30,
             },
             gemini_cli: config::GeminiCliConfig {
                 enabled: false,
                 cli_path: None,
                 timeout_secs: 30,
                 max_concurrency: 4,
+                ..Default::default()
             },
+            ollama: config::OllamaConfig::default(),
+            deepseek: Default::default(),
+            bedrock: Default::default(),
+            openai_compatible: Default::default(),
             rate_limit: RateLimitConfig {
                 capacity: 1000,
                 refill_per_second: 100,
@@ -124,11 +158,44 @@ impl TestServer {
                 failure_threshold: 100,
                 timeout_secs: 60,
                 success_threshold: 3,
+                persist: false,
+                state_file: None,
             },
             cache: CacheConfig {
                 enabled: false,
                 default_ttl_secs: 3600,
+                coalesce_requests: true,
+                vary_on_key: true,
             },
+            maintenance: Default::default(),
+            maintenance_mode: Default::default(),
+            warmup: Default::default(),
+            models: Default::default(),
+            keys: Default::default(),
+            scheduler: Default::default(),
+            alerts: Default::default(),
+            storage: Default::default(),
+            cluster: Default::default(),
+            limits: Default::default(),
+            upstream_headers: Default::default(),
+            upstream_clients: Default::default(),
+            model_policy: Default::default(),
+            prompts: Default::default(),
+            experiments: Default::default(),
+            fallback_responses: Default::default(),
+            post_process: Default::default(),
+            mirror: Default::default(),
+            errors: Default::default(),
+            redaction: Default::default(),
+            idempotency: Default::default(),
+            watermark: Default::default(),
+            keepalive: Default::default(),
+            sse: Default::default(),
+            admin_signature: Default::default(),
+            model_discovery: Default::default(),
+            transcripts: Default::default(),
+            sampling: Default::default(),
+            slo: Default::default(),
         }
     }
 
@@ -147,9 +214,36 @@ impl TestServer {
                 config.cache.enabled,
                 config.cache.default_ttl_secs,
             )),
+            in_flight: Default::default(),
+            waf_cooldown: Default::default(),
+            token_bindings: Default::default(),
+            idempotency: Arc::new(IdempotencyStore::from_config(&config.idempotency)),
+            affinity: Default::default(),
+            prompts: Default::default(),
+            experiments: Arc::new(
+                Experiments::load(config.experiments.file.as_deref())
+                    .expect("Failed to load experiments"),
+            ),
+            fallback_responses: Arc::new(
+                FallbackResponses::load(config.fallback_responses.file.as_deref())
+                    .expect("Failed to load fallback responses"),
+            ),
+            post_processor: Arc::new(PostProcessor::from_config(&config.post_process)),
+            mirror: Arc::new(Mirror::from_config(&config.mirror).expect("Failed to start mirror")),
+            watermark: Arc::new(Watermark::from_config(&config.watermark)),
+            shutdown: Default::default(),
+            routing_rules: Arc::new(
+                RoutingRules::load(config.models.rules_file.as_deref())
+                    .expect("Failed to load routing rules"),
+            ),
+            latency: Default::default(),
             provider_registry: Arc::new(ProviderRegistry::with_config(
                 &Some(config.anthropic.bridge_url.clone()),
                 &None,
+                &Some(config.ollama.clone()),
+                &Some(config.deepseek.clone()),
+                &Some(config.bedrock.clone()),
+                &Some(config.openai_compatible.clone()),
             )),
             rate_limiter: RateLimiter::new(1000, 100), // High limits for tests
             circuit_breaker: Arc::new(CircuitBreaker::new(
@@ -158,13 +252,103 @@ impl TestServer {
                 config.circuit_breaker.success_threshold,
             )),
             metrics: Arc::new(Metrics::new()),
+            model_registry: Arc::new(
+                ModelRegistry::load(
+                    config.models.overrides_file.as_deref(),
+                    &config.models.aliases,
+                )
+                .and_then(|registry| {
+                    registry.with_context_fallbacks(&config.models.context_fallbacks)
+                })
+                .and_then(|registry| registry.with_deprecations(&config.models.deprecations))
+                .expect("Failed to load model registry"),
+            ),
+            model_catalog: Arc::new(ModelCatalog::from_config(&config.model_discovery)),
+            transcripts: Arc::new(Transcripts::from_config(&config.transcripts, None)),
+            slos: Arc::new(Slos::from_config(&config.slo)),
+            key_store: Default::default(),
+            scheduler: Default::default(),
+            usage: Default::default(),
+            budgets: Default::default(),
+            notifier: Default::default(),
+            maintenance_mode: Default::default(),
+            status_page: Default::default(),
+            warmup: Default::default(),
+            store: None,
+            storage: None,
         }
     }
 
     fn create_router(state: AppState) -> Router {
+        let body_limits = Arc::new(
+            BodyLimits::from_config(&state.config.server).expect("valid test body limits"),
+        );
+
         // Public routes (no authentication required)
-        let public_routes =
-            Router::new().route("/health", axum::routing::get(health::health_check));
+        let public_routes = Router::new()
+            .route("/health", axum::routing::get(health::health_check))
+            .route("/health/ready", axum::routing::get(health::readiness))
+            .route("/status", axum::routing::get(status::status))
+            .route("/openapi.json", axum::routing::get(openapi::openapi_json));
+
+        // Admin routes (require an admin key)
+        let admin_routes = Router::new()
+            .route("/admin/budgets", axum::routing::get(admin::list_budgets))
+            .route("/admin/budgets/:key", axum::routing::put(admin::set_budget))
+            .route(
+                "/admin/models/aliases",
+                axum::routing::get(admin::list_model_aliases),
+            )
+            .route(
+                "/admin/models/aliases/:alias",
+                axum::routing::put(admin::set_model_alias).delete(admin::delete_model_alias),
+            )
+            .route(
+                "/admin/prompts",
+                axum::routing::get(admin::list_prompt_templates),
+            )
+            .route(
+                "/admin/prompts/:name",
+                axum::routing::put(admin::set_prompt_template)
+                    .delete(admin::delete_prompt_template),
+            )
+            .route(
+                "/admin/rate-limit",
+                axum::routing::get(admin::rate_limit_stats),
+            )
+            .route(
+                "/admin/usage/export",
+                axum::routing::get(usage::export_usage),
+            )
+            .route(
+                "/admin/providers/validate",
+                axum::routing::post(admin::validate_providers),
+            )
+            .route(
+                "/admin/maintenance",
+                axum::routing::get(admin::maintenance_status)
+                    .put(admin::start_maintenance)
+                    .delete(admin::end_maintenance),
+            )
+            .route(
+                "/admin/incident",
+                axum::routing::get(admin::incident_status)
+                    .put(admin::raise_incident)
+                    .delete(admin::resolve_incident),
+            )
+            .route(
+                "/admin/credentials/refresh",
+                axum::routing::post(admin::refresh_credentials),
+            )
+            .route(
+                "/admin/models/refresh",
+                axum::routing::post(admin::refresh_models),
+            )
+            .route(
+                "/admin/logs",
+                axum::routing::get(admin::log_settings).put(admin::update_log_settings),
+            )
+            .route_layer(axum::middleware::from_fn(admin_middleware));
 
         // Protected routes (require authentication)
         let protected_routes = Router::new()
@@ -175,8 +359,36 @@ impl TestServer {
             )
             .route(
                 "/v1/chat/completions",
-                axum::routing::post(chat::chat_completions),
+                axum::routing::post(chat::chat_completions)
+                    .route_layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        priority_middleware,
+                    ))
+                    .route_layer(axum::middleware::from_fn_with_state(
+                        body_limits.clone(),
+                        decompression_middleware,
+                    )),
+            )
+            .route("/v1/models", axum::routing::get(models::list_models))
+            .route(
+                "/v1/models/:model_id",
+                axum::routing::get(models::get_model),
+            )
+            .route(
+                "/v1/tokens/count",
+                axum::routing::post(tokens::count_tokens),
+            )
+            .route(
+                "/v1/conversations/:id",
+                axum::routing::get(conversations::get_conversation)
+                    .delete(conversations::delete_conversation),
             )
+            .route("/usage", axum::routing::get(usage::get_usage))
+            .merge(admin_routes)
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                maintenance_mode_middleware,
+            ))
             .layer(axum::middleware::from_fn_with_state(
                 state.clone(),
                 auth_middleware,
@@ -186,6 +398,13 @@ impl TestServer {
         Router::new()
             .merge(public_routes)
             .merge(protected_routes)
+            .fallback(vertex_bridge::openai::errors::not_found_handler)
+            .layer(axum::middleware::from_fn_with_state(
+                body_limits,
+                body_limit_middleware,
+            ))
+            .layer(axum::extract::DefaultBodyLimit::disable())
+            .layer(axum::middleware::from_fn(trace_context_middleware))
             .with_state(state)
     }
 
@@ -199,6 +418,19 @@ impl TestServer {
         Self { app }
     }
 
+    /// Builds a server from the default test config after applying `configure`.
+    #[allow(dead_code)] // Only used by the integration suite, not the performance suite
+    pub fn with_config(configure: impl FnOnce(&mut AppConfig)) -> Self {
+        let mut config = Self::create_test_config(false, "");
+        configure(&mut config);
+
+        let state = Self::create_app_state(&config);
+
+        let app = Self::create_router(state);
+
+        Self { app }
+    }
+
     pub async fn call(&self, req: Request<Body>) -> axum::response::Response {
         self.app.clone().oneshot(req).await.unwrap()
     }
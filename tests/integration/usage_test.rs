@@ -0,0 +1,38 @@
+// Usage query endpoint tests
+
+use super::test_utils::TestServer;
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use serde_json::Value;
+
+/// Reasonable body size limit for tests (1MB)
+const TEST_BODY_LIMIT: usize = 1024 * 1024;
+
+#[tokio::test]
+async fn test_usage_defaults_to_current_month() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/usage", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = to_bytes(response.into_body(), TEST_BODY_LIMIT)
+        .await
+        .expect("Failed to read usage response");
+    let json: Value = serde_json::from_slice(&body_bytes).expect("Response must be valid JSON");
+    assert_eq!(json["object"], "list");
+    assert_eq!(json["persistent"], false);
+    assert!(json["data"].as_array().is_some_and(Vec::is_empty));
+}
+
+#[tokio::test]
+async fn test_usage_rejects_invalid_range() {
+    let server = TestServer::new();
+
+    let req = TestServer::make_request("GET", "/usage?from=not-a-date", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let req = TestServer::make_request("GET", "/usage?from=2024-06-01&to=2024-05-01", None, None);
+    let response = server.call(req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}